@@ -1,34 +1,34 @@
+use crate::float::Float;
 use crate::ray::Ray;
 use nalgebra::Vector3;
-use std::f32;
 
 pub fn surrounding_box(box0: &AABB, box1: &AABB) -> AABB {
     let min = Vector3::new(
-        f32::min(box0.min.x, box1.min.x),
-        f32::min(box0.min.y, box1.min.y),
-        f32::min(box0.min.z, box1.min.z),
+        Float::min(box0.min.x, box1.min.x),
+        Float::min(box0.min.y, box1.min.y),
+        Float::min(box0.min.z, box1.min.z),
     );
     let max = Vector3::new(
-        f32::max(box0.max.x, box1.max.x),
-        f32::max(box0.max.y, box1.max.y),
-        f32::max(box0.max.z, box1.max.z),
+        Float::max(box0.max.x, box1.max.x),
+        Float::max(box0.max.y, box1.max.y),
+        Float::max(box0.max.z, box1.max.z),
     );
     AABB::new(min, max)
 }
 
 #[derive(Clone, Copy)]
 pub struct AABB {
-    pub min: Vector3<f32>,
-    pub max: Vector3<f32>,
+    pub min: Vector3<Float>,
+    pub max: Vector3<Float>,
 }
 
 impl AABB {
-    pub fn new(min: Vector3<f32>, max: Vector3<f32>) -> Self {
+    pub fn new(min: Vector3<Float>, max: Vector3<Float>) -> Self {
         AABB { min, max }
     }
 
     #[allow(dead_code)]
-    pub fn hit(&self, ray: &Ray, mut t_min: f32, mut t_max: f32) -> bool {
+    pub fn hit(&self, ray: &Ray, mut t_min: Float, mut t_max: Float) -> bool {
         for a in 0..3 {
             let inv_d = 1.0 / ray.direction()[a];
             let t0 = (self.min[a] - ray.origin()[a]) * inv_d;
@@ -42,4 +42,26 @@ impl AABB {
         }
         true
     }
+
+    // parametric entry and exit of the ray through the box, clipped to [t_min, t_max]
+    #[allow(dead_code)]
+    pub fn interval(
+        &self,
+        ray: &Ray,
+        mut t_min: Float,
+        mut t_max: Float,
+    ) -> Option<(Float, Float)> {
+        for a in 0..3 {
+            let inv_d = 1.0 / ray.direction()[a];
+            let t0 = (self.min[a] - ray.origin()[a]) * inv_d;
+            let t1 = (self.max[a] - ray.origin()[a]) * inv_d;
+            let (t0, t1) = if inv_d < 0.0 { (t1, t0) } else { (t0, t1) };
+            t_min = t_min.max(t0);
+            t_max = t_max.min(t1);
+            if t_max <= t_min {
+                return None;
+            }
+        }
+        Some((t_min, t_max))
+    }
 }
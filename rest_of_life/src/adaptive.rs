@@ -0,0 +1,84 @@
+use crate::estimator::luminance;
+use crate::float::{self, Float};
+use nalgebra::Vector3;
+
+// Adaptive sampling: every pixel takes at least `min_spp` samples, then keeps
+// sampling until the 95% confidence interval of its mean luminance is within
+// `threshold` of the mean, or the render's spp is reached. Near black pixels are
+// judged against one 8 bit step instead, so they do not sample forever.
+#[derive(Clone, Copy, Debug)]
+pub struct Adaptive {
+    pub threshold: Float,
+    pub min_spp: usize,
+}
+
+impl Default for Adaptive {
+    fn default() -> Self {
+        Adaptive {
+            threshold: 0.05,
+            min_spp: 16,
+        }
+    }
+}
+
+// Running mean and variance of a pixel's sample luminance, by Welford's method.
+#[derive(Clone, Copy, Default)]
+pub struct PixelStats {
+    pub n: usize,
+    pub mean: f64,
+    m2: f64,
+}
+
+impl PixelStats {
+    pub fn push(&mut self, sample: &Vector3<Float>) {
+        let l = float::double(luminance(sample));
+        self.n += 1;
+        let delta = l - self.mean;
+        self.mean += delta / self.n as f64;
+        self.m2 += delta * (l - self.mean);
+    }
+
+    pub fn variance(&self) -> f64 {
+        if self.n > 1 {
+            self.m2 / (self.n - 1) as f64
+        } else {
+            0.0
+        }
+    }
+
+    // half width of the 95% confidence interval of the mean
+    pub fn half_width(&self) -> f64 {
+        1.96 * (self.variance() / self.n.max(1) as f64).sqrt()
+    }
+
+    pub fn converged(&self, adaptive: &Adaptive) -> bool {
+        self.n >= adaptive.min_spp.max(2)
+            && self.half_width() <= float::double(adaptive.threshold) * self.mean.max(1.0 / 255.0)
+    }
+}
+
+fn ramp(t: Float) -> [u8; 3] {
+    // black, blue, red, yellow, white
+    const STOPS: [[Float; 3]; 5] = [
+        [0.0, 0.0, 0.0],
+        [0.0, 0.0, 1.0],
+        [1.0, 0.0, 0.0],
+        [1.0, 1.0, 0.0],
+        [1.0, 1.0, 1.0],
+    ];
+    let x = t.clamp(0.0, 1.0) * (STOPS.len() - 1) as Float;
+    let i = (x as usize).min(STOPS.len() - 2);
+    let f = x - i as Float;
+    [0, 1, 2].map(|c| (255.99 * (STOPS[i][c] * (1.0 - f) + STOPS[i + 1][c] * f)) as u8)
+}
+
+// Plain ppm of the samples each pixel took, from black for none through blue and
+// red to white for max_spp. Rows from the top.
+pub fn heatmap(width: usize, height: usize, counts: &[usize], max_spp: usize) -> String {
+    let mut ppm = format!("P3\n{} {}\n255\n", width, height);
+    for &n in counts {
+        let [r, g, b] = ramp(n as Float / max_spp.max(1) as Float);
+        ppm.push_str(&format!("{} {} {}\n", r, g, b));
+    }
+    ppm
+}
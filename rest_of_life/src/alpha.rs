@@ -0,0 +1,131 @@
+use crate::aabb::AABB;
+use crate::cone::Cone;
+use crate::float::{self, Float};
+use crate::hittable::{HitRecord, Hittable, Surfaces};
+use crate::ray::Ray;
+use crate::texture::Texture;
+use nalgebra::Vector3;
+use std::cell::Cell;
+
+// each rejected layer restarts the search just past it
+const MAX_LAYERS: usize = 64;
+const GOLDEN_RATIO: Float = 0.618_034;
+
+thread_local! {
+    static PIXEL: Cell<(Float, u32)> = const { Cell::new((0.0, 0)) };
+}
+
+fn hash(mut x: u32) -> u32 {
+    x ^= x >> 16;
+    x = x.wrapping_mul(0x7feb352d);
+    x ^= x >> 15;
+    x = x.wrapping_mul(0x846ca68b);
+    x ^ (x >> 16)
+}
+
+fn unit(x: u32) -> Float {
+    (x >> 8) as Float / (1 << 24) as Float
+}
+
+// Sets the pixel and sample the current thread is tracing. Every pixel gets a fixed
+// random offset and successive samples walk a golden ratio sequence from it, so the
+// coverage decisions are stratified per pixel but uncorrelated between neighbours.
+pub fn set_pixel(x: usize, y: usize, sample: usize) {
+    let offset = unit(hash(x as u32 ^ hash(y as u32)));
+    PIXEL.with(|pixel| pixel.set((offset, sample as u32)));
+}
+
+// coverage threshold for a hit at p; hashing the position decorrelates the layers
+// of overlapping cards hit along the same path
+fn coverage_sample(p: &Vector3<Float>) -> Float {
+    let (offset, sample) = PIXEL.with(|pixel| pixel.get());
+    let bits = |c: Float| float::single(c).to_bits();
+    let h = hash(bits(p.x) ^ hash(bits(p.y) ^ hash(bits(p.z))));
+    (offset + unit(h) + sample as Float * GOLDEN_RATIO).fract()
+}
+
+// Stochastic alpha-to-coverage: a hit on the wrapped surface is kept with
+// probability equal to the alpha texture's value there, otherwise the ray passes
+// through. Applies to every ray, so light sampling rays see the same cutouts.
+pub struct StochasticAlpha<H: Hittable, T: Texture> {
+    hittable: H,
+    alpha: T,
+}
+
+impl<H: Hittable, T: Texture> StochasticAlpha<H, T> {
+    #[allow(dead_code)]
+    pub fn new(hittable: H, alpha: T) -> Self {
+        StochasticAlpha { hittable, alpha }
+    }
+}
+
+impl<H: Hittable, T: Texture> Hittable for StochasticAlpha<H, T> {
+    fn hit(&self, ray: &Ray, t_min: Float, t_max: Float) -> Option<HitRecord<'_>> {
+        let mut surfaces = Surfaces::new(ray);
+        for _ in 0..MAX_LAYERS {
+            let hit = surfaces.next(&self.hittable, t_min, t_max)?;
+            let alpha = self.alpha.value(hit.u, hit.v, &hit.texture_p).x;
+            if coverage_sample(&hit.p) < alpha {
+                return Some(hit);
+            }
+        }
+        None
+    }
+
+    fn bounding_box(&self, t0: Float, t1: Float) -> Option<AABB> {
+        self.hittable.bounding_box(t0, t1)
+    }
+
+    fn pdf_value(&self, o: Vector3<Float>, v: Vector3<Float>) -> Float {
+        self.hittable.pdf_value(o, v)
+    }
+
+    fn random(&self, o: Vector3<Float>) -> Vector3<Float> {
+        self.hittable.random(o)
+    }
+
+    fn occlusion(&self, cone: &Cone, t_min: Float, t_max: Float) -> Float {
+        self.hittable.occlusion(cone, t_min, t_max)
+    }
+}
+
+// Lets rays through the wrapped surface wherever its material is not opaque, see
+// Material::opaque, so a masked material cuts holes in it for every kind of ray.
+pub struct Cutout<H: Hittable> {
+    hittable: H,
+}
+
+impl<H: Hittable> Cutout<H> {
+    pub fn new(hittable: H) -> Self {
+        Cutout { hittable }
+    }
+}
+
+impl<H: Hittable> Hittable for Cutout<H> {
+    fn hit(&self, ray: &Ray, t_min: Float, t_max: Float) -> Option<HitRecord<'_>> {
+        let mut surfaces = Surfaces::new(ray);
+        for _ in 0..MAX_LAYERS {
+            let hit = surfaces.next(&self.hittable, t_min, t_max)?;
+            if hit.material.opaque(&hit) {
+                return Some(hit);
+            }
+        }
+        None
+    }
+
+    fn bounding_box(&self, t0: Float, t1: Float) -> Option<AABB> {
+        self.hittable.bounding_box(t0, t1)
+    }
+
+    fn pdf_value(&self, o: Vector3<Float>, v: Vector3<Float>) -> Float {
+        self.hittable.pdf_value(o, v)
+    }
+
+    fn random(&self, o: Vector3<Float>) -> Vector3<Float> {
+        self.hittable.random(o)
+    }
+
+    fn occlusion(&self, cone: &Cone, t_min: Float, t_max: Float) -> Float {
+        self.hittable.occlusion(cone, t_min, t_max)
+    }
+}
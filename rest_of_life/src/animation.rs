@@ -0,0 +1,166 @@
+use crate::camera::Shutter;
+use crate::cli::{Backend, Options};
+use crate::float::Float;
+use crate::metadata::Metadata;
+use crate::render::{self, Image, RenderSettings};
+use crate::{gltf, gpu, scene, scenefile};
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, Stdio};
+
+// Renders a scene's keyframed motion as a sequence of images, frame_0001 onwards
+// in the output format, into a directory, or as one video. Frame n's shutter
+// opens n / fps after the first, which opens when --shutter does, and stays open
+// for the shutter angle's share of a frame: 180 degrees, half a frame, unless
+// given, as film cameras blur motion. The frames' shutters replace a scene file's
+// own.
+#[derive(Clone)]
+pub struct Animation {
+    pub frames: usize,
+    pub fps: Float,
+    pub shutter_angle: Float,
+    pub dir: String,
+    pub video: Option<String>,
+}
+
+// the ffmpeg arguments encoding a video file with the extension, after its input;
+// yuv420p halves the chroma, so odd sizes are padded by a pixel
+fn encoding(path: &str) -> Result<&'static str, String> {
+    let extension = Path::new(path)
+        .extension()
+        .map_or(String::new(), |e| e.to_string_lossy().to_ascii_lowercase());
+    match extension.as_str() {
+        "mp4" | "mkv" | "mov" => {
+            Ok("-c:v libx264 -pix_fmt yuv420p -vf pad=ceil(iw/2)*2:ceil(ih/2)*2")
+        }
+        "webm" => {
+            Ok("-c:v libvpx-vp9 -pix_fmt yuv420p -b:v 0 -crf 30 -vf pad=ceil(iw/2)*2:ceil(ih/2)*2")
+        }
+        "apng" | "png" => Ok("-f apng -plays 0"),
+        "gif" => Ok("-loop 0"),
+        _ => Err(format!(
+            "cannot encode {}: videos must be .mp4, .mkv, .mov, .webm, .apng, .png or .gif",
+            path
+        )),
+    }
+}
+
+// where the frames go: numbered files in a directory, or an ffmpeg process
+// encoding what it reads from its standard input into one video
+enum Output {
+    Files(PathBuf),
+    Video(String, Child),
+}
+
+impl Animation {
+    pub fn new(frames: usize) -> Self {
+        Animation {
+            frames,
+            fps: 24.0,
+            shutter_angle: 180.0,
+            dir: String::from("frames"),
+            video: None,
+        }
+    }
+
+    fn output(&self, settings: &RenderSettings) -> Result<Output, String> {
+        let Some(path) = &self.video else {
+            fs::create_dir_all(&self.dir)
+                .map_err(|e| format!("cannot create {}: {}", self.dir, e))?;
+            return Ok(Output::Files(PathBuf::from(&self.dir)));
+        };
+        let input = format!(
+            "-y -loglevel error -f rawvideo -pix_fmt rgb24 -s {}x{} -framerate {} -i -",
+            settings.width, settings.height, self.fps
+        );
+        let child = Command::new("ffmpeg")
+            .args(input.split_whitespace())
+            .args(encoding(path)?.split_whitespace())
+            .arg(path)
+            .stdin(Stdio::piped())
+            .spawn()
+            .map_err(|e| format!("cannot run ffmpeg, which --video needs: {}", e))?;
+        Ok(Output::Video(path.clone(), child))
+    }
+
+    // the shutter of frame `frame`, counted from 0, of an animation starting at `start`
+    pub fn shutter(&self, start: &Shutter, frame: usize) -> Shutter {
+        let open = start.open + frame as Float / self.fps;
+        Shutter {
+            open,
+            close: open + self.shutter_angle / 360.0 / self.fps,
+            motion_blur: start.motion_blur,
+        }
+    }
+
+    pub fn render(&self, options: &Options, settings: &RenderSettings) -> Result<(), String> {
+        let mut output = self.output(settings)?;
+        let aspect = settings.width as Float / settings.height as Float;
+        // scene descriptions are read once, glTF files and built in scenes built
+        // again for each frame's shutter
+        let mut description = match &options.scene_file {
+            Some(path) if !gltf::is_gltf(path) => Some(scenefile::read(path, options.shutter)?),
+            _ => None,
+        };
+        let mut frame_options = options.clone();
+        let metadata = Metadata::new(options, settings.spp, None);
+        for frame in 0..self.frames {
+            eprintln!("rendering frame {}/{}", frame + 1, self.frames);
+            frame_options.shutter = self.shutter(&options.shutter, frame);
+            let image: Image = match description.as_mut() {
+                Some(description) => {
+                    description.shutter = frame_options.shutter;
+                    if options.backend == Backend::GPU {
+                        gpu::render(description, settings)?
+                    } else {
+                        render::render(&description.scene(aspect, &frame_options), settings)
+                    }
+                }
+                None => {
+                    let scene = match &options.scene_file {
+                        Some(path) => scenefile::load(path, aspect, &frame_options)?,
+                        None => scene::by_name(&options.scene, aspect, &frame_options)
+                            .expect("unknown scene"),
+                    };
+                    render::render(&scene, settings)
+                }
+            };
+            match &mut output {
+                Output::Files(dir) => {
+                    let path = dir.join(format!(
+                        "frame_{:04}.{}",
+                        frame + 1,
+                        options.format.extension()
+                    ));
+                    let metadata = metadata.clone().with("Frame", (frame + 1).to_string());
+                    fs::write(
+                        &path,
+                        image.encode(options.format, &options.tone, settings.spp, &metadata),
+                    )
+                    .map_err(|e| format!("cannot write {}: {}", path.display(), e))?;
+                }
+                Output::Video(path, ffmpeg) => {
+                    let rgb: Vec<u8> = image.rgb8(&options.tone).collect();
+                    ffmpeg
+                        .stdin
+                        .as_mut()
+                        .expect("ffmpeg's input is piped")
+                        .write_all(&rgb)
+                        .map_err(|e| format!("cannot encode {}: {}", path, e))?;
+                }
+            }
+        }
+        if let Output::Video(path, mut ffmpeg) = output {
+            // closing its input ends the video
+            drop(ffmpeg.stdin.take());
+            let status = ffmpeg
+                .wait()
+                .map_err(|e| format!("cannot encode {}: {}", path, e))?;
+            if !status.success() {
+                return Err(format!("ffmpeg could not encode {}", path));
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,146 @@
+use crate::float::{self, Float};
+use crate::hittable::Hittable;
+use crate::material::ScatterRecord;
+use crate::parallel::*;
+use crate::ray;
+use crate::render::{Image, RenderSettings};
+use crate::rng;
+use crate::scene::Scene;
+use nalgebra::Vector3;
+use std::fs;
+
+// pass index of the aov samples, apart from the beauty and guide training passes
+const AOV_PASS: usize = usize::MAX;
+
+// Auxiliary buffers from the first hit of the camera rays, for denoisers and
+// compositing. Albedo, normal and depth are averaged over as many jittered samples
+// per pixel as the beauty pass takes; the object id is the one under the pixel
+// centre. Rows from the top.
+pub struct AOVs {
+    pub width: usize,
+    pub height: usize,
+    pub albedo: Vec<Vector3<Float>>,
+    pub normal: Vec<Vector3<Float>>,
+    pub depth: Vec<Float>,
+    pub object_id: Vec<u32>,
+}
+
+struct Pixel {
+    albedo: Vector3<Float>,
+    normal: Vector3<Float>,
+    depth: Float,
+    object_id: u32,
+}
+
+// What the camera ray sees first: the surface's reflectance, or its emission capped at
+// one for lights, its world space shading normal and its distance. Background gives
+// zeros.
+fn first_hit(scene: &Scene, u: Float, v: Float) -> (Vector3<Float>, Vector3<Float>, Option<Float>) {
+    let ray = match scene.camera.get_ray(u, v) {
+        Some(ray) => ray,
+        None => return (Vector3::zeros(), Vector3::zeros(), None),
+    };
+    match scene.world.hit(&ray, ray::T_MIN, Float::MAX) {
+        Some(hit) => {
+            let albedo = match hit.material.scatter(&ray, &hit) {
+                Some(ScatterRecord::Scatter { attenuation, .. })
+                | Some(ScatterRecord::Specular { attenuation, .. }) => attenuation,
+                None => hit.material.emitted(&ray, &hit).map(|c| c.min(1.0)),
+            };
+            (albedo, hit.normal, Some(hit.t * ray.direction().norm()))
+        }
+        None => (Vector3::zeros(), Vector3::zeros(), None),
+    }
+}
+
+fn pixel(scene: &Scene, settings: &RenderSettings, x: usize, y: usize) -> Pixel {
+    let (nx, ny) = (settings.width as Float, settings.height as Float);
+    rng::seed_pixel(settings.rng, settings.seed, AOV_PASS, x, y);
+    let mut albedo = Vector3::zeros();
+    let mut normal = Vector3::zeros();
+    let (mut depth, mut hits) = (0.0, 0);
+    for _ in 0..settings.spp {
+        let u = (x as Float + rng::uniform()) / nx;
+        let v = (y as Float + rng::uniform()) / ny;
+        let (a, n, d) = first_hit(scene, u, v);
+        albedo += a;
+        normal += n;
+        if let Some(d) = d {
+            depth += d;
+            hits += 1;
+        }
+    }
+    let object_id = scene
+        .camera
+        .get_ray((x as Float + 0.5) / nx, (y as Float + 0.5) / ny)
+        .and_then(|ray| scene.world.hit(&ray, ray::T_MIN, Float::MAX))
+        .map_or(0, |hit| hit.object_id);
+    Pixel {
+        albedo: albedo / settings.spp as Float,
+        normal: normal / settings.spp as Float,
+        depth: if hits > 0 { depth / hits as Float } else { 0.0 },
+        object_id,
+    }
+}
+
+pub fn render(scene: &Scene, settings: &RenderSettings) -> AOVs {
+    let (nx, ny) = (settings.width, settings.height);
+    let pixels = (0..ny)
+        .into_par_iter()
+        .rev()
+        .flat_map(|y| {
+            (0..nx)
+                .map(|x| pixel(scene, settings, x, y))
+                .collect::<Vec<Pixel>>()
+        })
+        .collect::<Vec<Pixel>>();
+    AOVs {
+        width: nx,
+        height: ny,
+        albedo: pixels.iter().map(|p| p.albedo).collect(),
+        normal: pixels.iter().map(|p| p.normal).collect(),
+        depth: pixels.iter().map(|p| p.depth).collect(),
+        object_id: pixels.iter().map(|p| p.object_id).collect(),
+    }
+}
+
+// grayscale pfm, rows from the bottom as the format wants
+pub fn gray_pfm(width: usize, height: usize, values: &[Float]) -> Vec<u8> {
+    let mut bytes = format!("Pf\n{} {}\n-1.0\n", width, height).into_bytes();
+    for row in values.chunks(width).rev() {
+        for v in row {
+            bytes.extend(float::single(*v).to_le_bytes());
+        }
+    }
+    bytes
+}
+
+impl AOVs {
+    fn image(&self, pixels: &[Vector3<Float>]) -> Image {
+        Image {
+            width: self.width,
+            height: self.height,
+            pixels: pixels.to_vec(),
+        }
+    }
+
+    // Writes <prefix>_albedo.pfm, <prefix>_normal.pfm, <prefix>_depth.pfm and the ids
+    // as a 16 bit <prefix>_object_id.pgm.
+    pub fn write(&self, prefix: &str) -> Result<(), String> {
+        let mut ids = format!("P2\n{} {}\n65535\n", self.width, self.height);
+        for id in &self.object_id {
+            ids.push_str(&format!("{}\n", (*id).min(65535)));
+        }
+        let files = [
+            ("albedo.pfm", self.image(&self.albedo).pfm()),
+            ("normal.pfm", self.image(&self.normal).pfm()),
+            ("depth.pfm", gray_pfm(self.width, self.height, &self.depth)),
+            ("object_id.pgm", ids.into_bytes()),
+        ];
+        for (suffix, data) in files {
+            let path = format!("{}_{}", prefix, suffix);
+            fs::write(&path, data).map_err(|e| format!("cannot write {}: {}", path, e))?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,193 @@
+use crate::float::{self, Float};
+use crate::reference;
+use nalgebra::Vector3;
+
+// What a ray that leaves the scene sees: the radiance arriving from infinitely far
+// away along the reverse of `direction`, which need not be unit length. Every
+// integrator asks the scene's background when a ray misses, so the choice is the
+// scene's and not the renderer's. Only BSDF sampling finds it; nothing samples a
+// background as a light.
+pub trait Background: Send + Sync {
+    fn radiance(&self, direction: &Vector3<Float>) -> Vector3<Float>;
+
+    // the colour when it is the same in every direction, as the gpu backend needs
+    fn solid(&self) -> Option<Vector3<Float>> {
+        None
+    }
+}
+
+// one colour all round, black for scenes lit by their own emitters
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct SolidColor(pub Vector3<Float>);
+
+impl Background for SolidColor {
+    fn radiance(&self, _direction: &Vector3<Float>) -> Vector3<Float> {
+        self.0
+    }
+
+    fn solid(&self) -> Option<Vector3<Float>> {
+        Some(self.0)
+    }
+}
+
+// The book's sky: a blend from `horizon` straight below to `zenith` straight above,
+// by height, white to light blue unless given.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Gradient {
+    pub horizon: Vector3<Float>,
+    pub zenith: Vector3<Float>,
+}
+
+impl Default for Gradient {
+    fn default() -> Self {
+        Gradient {
+            horizon: Vector3::new(1.0, 1.0, 1.0),
+            zenith: Vector3::new(0.5, 0.7, 1.0),
+        }
+    }
+}
+
+impl Background for Gradient {
+    fn radiance(&self, direction: &Vector3<Float>) -> Vector3<Float> {
+        let t = 0.5 * (direction.normalize().y + 1.0);
+        self.horizon.lerp(&self.zenith, t)
+    }
+}
+
+// An environment map in the latitude-longitude layout an equirectangular render
+// writes: a camera at the origin looking down -z with y up sees the map's centre,
+// x to the right. The map is turned by `rotation` degrees about y and its linear
+// values scaled by `intensity`.
+pub struct Hdri {
+    width: usize,
+    height: usize,
+    pixels: Vec<Vector3<Float>>,
+    pub rotation: Float,
+    pub intensity: Float,
+}
+
+impl Hdri {
+    // reads a map in any format reference::load takes, EXR, Radiance HDR or PFM
+    // keeping their linear values
+    pub fn open(path: &str) -> Result<Self, String> {
+        let image = reference::load(path)?;
+        Ok(Hdri {
+            width: image.width,
+            height: image.height,
+            pixels: image.pixels,
+            rotation: 0.0,
+            intensity: 1.0,
+        })
+    }
+}
+
+impl Background for Hdri {
+    fn radiance(&self, direction: &Vector3<Float>) -> Vector3<Float> {
+        let d = direction.normalize();
+        let longitude = d.x.atan2(-d.z) - self.rotation.to_radians();
+        let latitude = d.y.clamp(-1.0, 1.0).asin();
+        let s = (longitude / (2.0 * float::consts::PI) + 0.5).rem_euclid(1.0);
+        let t = 0.5 - latitude / float::consts::PI;
+        let x = ((s * self.width as Float) as usize).min(self.width - 1);
+        let y = ((t * self.height as Float) as usize).min(self.height - 1);
+        self.intensity * self.pixels[y * self.width + x]
+    }
+}
+
+// A procedural daylight sky lit by a sun in `sun`'s direction: blue overhead,
+// paler toward the horizon, reddening and dimming as the sun sets, with a glow
+// around the sun and its disc, which is small and bright and so noisy to find by
+// BSDF sampling alone. Below the horizon is a grey ground. Not a physical model,
+// only a plausible backdrop.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Sky {
+    pub sun: Vector3<Float>,
+    pub intensity: Float,
+}
+
+// the angle the sun's disc spans from its centre, in radians
+const SUN_RADIUS: Float = 0.01;
+// radiance of the noon sun's disc, relative to the sky's
+const SUN_RADIANCE: Float = 500.0;
+
+impl Sky {
+    pub fn new(sun: Vector3<Float>) -> Self {
+        Sky {
+            sun: sun.normalize(),
+            intensity: 1.0,
+        }
+    }
+}
+
+impl Background for Sky {
+    fn radiance(&self, direction: &Vector3<Float>) -> Vector3<Float> {
+        let d = direction.normalize();
+        let elevation = self.sun.y;
+        // full daylight once the sun is 10 degrees up, dark 5 below the horizon
+        let daylight = ((elevation + 0.09) / 0.26).clamp(0.0, 1.0);
+        let sunset = (1.0 - elevation.max(0.0) / 0.3).max(0.0).powi(2);
+        let zenith = Vector3::new(0.25, 0.45, 0.9);
+        let horizon = Vector3::new(0.85, 0.9, 1.0).lerp(&Vector3::new(1.0, 0.55, 0.3), sunset);
+        let sun_color = Vector3::new(1.0, 0.95, 0.85).lerp(&Vector3::new(1.0, 0.5, 0.2), sunset);
+        let cos_sun = d.dot(&self.sun);
+        let sky = if d.y >= 0.0 {
+            let sky = horizon.lerp(&zenith, d.y.sqrt());
+            let glow =
+                sun_color * (0.6 * cos_sun.max(0.0).powi(32) + 0.2 * cos_sun.max(0.0).powi(4));
+            let disc = if cos_sun > SUN_RADIUS.cos() {
+                sun_color * SUN_RADIANCE
+            } else {
+                Vector3::zeros()
+            };
+            sky + glow + disc
+        } else {
+            0.3 * horizon
+        };
+        self.intensity * daylight * sky
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn gradient_runs_from_horizon_to_zenith() {
+        let gradient = Gradient::default();
+        let up = gradient.radiance(&Vector3::new(0.0, 2.0, 0.0));
+        let down = gradient.radiance(&Vector3::new(0.0, -1.0, 0.0));
+        assert!((up - gradient.zenith).norm() < 1e-6);
+        assert!((down - gradient.horizon).norm() < 1e-6);
+        assert_eq!(gradient.solid(), None);
+        let solid = SolidColor(Vector3::new(0.1, 0.2, 0.3));
+        assert_eq!(solid.solid(), Some(solid.radiance(&up)));
+    }
+
+    #[test]
+    fn hdri_centre_is_straight_ahead() {
+        let mut pixels = vec![Vector3::zeros(); 8 * 4];
+        pixels[4] = Vector3::new(1.0, 0.0, 0.0);
+        pixels[2 * 8 + 4] = Vector3::new(0.0, 1.0, 0.0);
+        let hdri = Hdri {
+            width: 8,
+            height: 4,
+            pixels,
+            rotation: 0.0,
+            intensity: 2.0,
+        };
+        let ahead = Vector3::new(0.0, -0.01, -1.0);
+        assert_eq!(hdri.radiance(&ahead), Vector3::new(0.0, 2.0, 0.0));
+        assert_eq!(
+            hdri.radiance(&Vector3::new(0.0, 1.0, -0.01)),
+            Vector3::new(2.0, 0.0, 0.0)
+        );
+        let turned = Hdri {
+            rotation: 90.0,
+            ..hdri
+        };
+        assert_eq!(
+            turned.radiance(&Vector3::new(1.0, -0.01, 0.0)),
+            Vector3::new(0.0, 2.0, 0.0)
+        );
+    }
+}
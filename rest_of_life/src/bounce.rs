@@ -0,0 +1,70 @@
+use crate::hittable::HitRecord;
+use crate::ray::Ray;
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum BounceKind {
+    Diffuse,
+    Glossy,
+    Transmission,
+}
+
+impl BounceKind {
+    // a specular scatter is a transmission when it leaves through the surface
+    pub fn specular(ray: &Ray, hit: &HitRecord, scattered: &Ray) -> Self {
+        if ray.direction().dot(&hit.normal) * scattered.direction().dot(&hit.normal) > 0.0 {
+            BounceKind::Transmission
+        } else {
+            BounceKind::Glossy
+        }
+    }
+}
+
+// Most bounces of each kind a path may take, on top of the overall depth limit.
+// Capping transmission keeps long chains inside glass from costing as much as a
+// full diffuse path.
+#[derive(Clone, Copy)]
+pub struct BounceLimits {
+    pub diffuse: u32,
+    pub glossy: u32,
+    pub transmission: u32,
+}
+
+impl Default for BounceLimits {
+    fn default() -> Self {
+        BounceLimits {
+            diffuse: u32::MAX,
+            glossy: u32::MAX,
+            transmission: u32::MAX,
+        }
+    }
+}
+
+// bounces a path has taken so far
+#[derive(Clone, Copy, Default)]
+pub struct Bounces {
+    pub depth: i32,
+    diffuse: u32,
+    glossy: u32,
+    transmission: u32,
+}
+
+impl Bounces {
+    // the counts after one more bounce of kind, None when that exceeds its limit
+    pub fn after(self, kind: BounceKind, limits: &BounceLimits) -> Option<Self> {
+        let mut next = Bounces {
+            depth: self.depth + 1,
+            ..self
+        };
+        let (count, limit) = match kind {
+            BounceKind::Diffuse => (&mut next.diffuse, limits.diffuse),
+            BounceKind::Glossy => (&mut next.glossy, limits.glossy),
+            BounceKind::Transmission => (&mut next.transmission, limits.transmission),
+        };
+        *count += 1;
+        if *count > limit {
+            None
+        } else {
+            Some(next)
+        }
+    }
+}
@@ -0,0 +1,929 @@
+use crate::aabb;
+use crate::aabb::AABB;
+use crate::cone::{self, Cone};
+use crate::float::{Float, Float4};
+use crate::hittable::{HitRecord, Hittable};
+use crate::packet::{self, RayPacket, LANES};
+use crate::parallel::*;
+use crate::ray::Ray;
+use crate::stats::{self, Counter};
+use nalgebra::Vector3;
+use std::cell::Cell;
+use std::str::FromStr;
+use wide::CmpLe;
+
+const SAH_BUCKETS: usize = 12;
+const MORTON_BITS: u32 = 10;
+const MAX_SAH_DEPTH: usize = 32;
+// the entries a traversal keeps in place before spilling to the heap, see Stack
+const TRAVERSAL_STACK: usize = 64;
+
+thread_local! {
+    // nodes the traversals on this thread have visited, for the debug view of them
+    static VISITS: Cell<u32> = const { Cell::new(0) };
+}
+
+// counts one node visited by a traversal
+pub fn count_visit() {
+    VISITS.with(|visits| visits.set(visits.get() + 1));
+}
+
+// the nodes this thread visited since the last call, starting the count over
+pub fn take_visits() -> u32 {
+    VISITS.with(|visits| visits.replace(0))
+}
+
+// How the hierarchy is built: a top-down binned surface area heuristic split gives
+// the best trees, a linear BVH over Morton codes builds much faster for huge scenes.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum BuildStrategy {
+    #[default]
+    SAH,
+    LBVH,
+}
+
+impl FromStr for BuildStrategy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "sah" => Ok(BuildStrategy::SAH),
+            "lbvh" => Ok(BuildStrategy::LBVH),
+            _ => Err(format!("unknown bvh build strategy: {}", s)),
+        }
+    }
+}
+
+// A traversal stack holding its first N entries in place and any beyond them on
+// the heap, so a tree deeper than the stack was sized for costs an allocation
+// instead of overflowing it.
+struct Stack<T, const N: usize> {
+    fixed: [T; N],
+    spilled: Vec<T>,
+    len: usize,
+}
+
+impl<T: Copy + Default, const N: usize> Stack<T, N> {
+    fn new() -> Self {
+        Stack {
+            fixed: [T::default(); N],
+            spilled: Vec::new(),
+            len: 0,
+        }
+    }
+
+    fn push(&mut self, entry: T) {
+        if self.len < N {
+            self.fixed[self.len] = entry;
+        } else {
+            self.spilled.push(entry);
+        }
+        self.len += 1;
+    }
+
+    fn pop(&mut self) -> Option<T> {
+        if self.len == 0 {
+            return None;
+        }
+        self.len -= 1;
+        if self.len < N {
+            Some(self.fixed[self.len])
+        } else {
+            self.spilled.pop()
+        }
+    }
+}
+
+// Nodes are stored depth first, so an interior node's first child directly follows
+// it and only the second child's index is kept; leaves index a contiguous run of
+// primitives instead.
+#[derive(Clone, Copy)]
+struct LinearNode {
+    bbox: AABB,
+    offset: u32,
+    count: u16,
+    axis: u8,
+}
+
+// The primitives are kept by value in one Vec, in the order the leaves index them,
+// so a BVH over objects of one kind, a mesh's triangles or a cloud of spheres, is a
+// typed arena of them laid out beside its nodes rather than one allocation each.
+// Objects of different kinds go in boxed.
+pub struct BVH<P = Box<dyn Hittable>> {
+    nodes: Vec<LinearNode>,
+    primitives: Vec<P>,
+}
+
+struct Primitive<P> {
+    hittable: P,
+    bbox: AABB,
+}
+
+impl<P> Primitive<P> {
+    fn centroid(&self, axis: usize) -> Float {
+        0.5 * (self.bbox.min[axis] + self.bbox.max[axis])
+    }
+}
+
+fn surface_area(bbox: &AABB) -> Float {
+    let d = bbox.max - bbox.min;
+    2.0 * (d.x * d.y + d.y * d.z + d.z * d.x)
+}
+
+fn bounds<P>(primitives: &[Primitive<P>]) -> AABB {
+    primitives
+        .iter()
+        .skip(1)
+        .fold(primitives[0].bbox, |acc, p| {
+            aabb::surrounding_box(&acc, &p.bbox)
+        })
+}
+
+// spreads the low 10 bits of x so there are two zero bits between each of them
+fn expand_bits(x: u32) -> u32 {
+    let x = (x | (x << 16)) & 0x030000ff;
+    let x = (x | (x << 8)) & 0x0300f00f;
+    let x = (x | (x << 4)) & 0x030c30c3;
+    (x | (x << 2)) & 0x09249249
+}
+
+fn morton_code(x: Float, y: Float, z: Float) -> u32 {
+    let scale = (1 << MORTON_BITS) as Float;
+    let quantize = |c: Float| (c * scale).max(0.0).min(scale - 1.0) as u32;
+    (expand_bits(quantize(x)) << 2) | (expand_bits(quantize(y)) << 1) | expand_bits(quantize(z))
+}
+
+impl<P: Hittable> BVH<P> {
+    #[allow(dead_code)]
+    pub fn new(hittable: Vec<P>, time0: Float, time1: Float) -> Self {
+        BVH::with_strategy(hittable, time0, time1, BuildStrategy::default())
+    }
+
+    pub fn with_strategy(
+        hittable: Vec<P>,
+        time0: Float,
+        time1: Float,
+        strategy: BuildStrategy,
+    ) -> Self {
+        if hittable.is_empty() {
+            panic!["no elements in scene"]
+        }
+        let hittable_count = hittable.len();
+        let primitives = hittable
+            .into_iter()
+            .map(|hittable| match hittable.bounding_box(time0, time1) {
+                Some(bbox) => Primitive { hittable, bbox },
+                None => panic!["no bounding box in bvh node"],
+            })
+            .collect();
+        let mut bvh = BVH {
+            nodes: Vec::with_capacity(2 * hittable_count - 1),
+            primitives: Vec::with_capacity(hittable_count),
+        };
+        match strategy {
+            BuildStrategy::SAH => bvh.build_sah(primitives, 0),
+            BuildStrategy::LBVH => bvh.build_lbvh(primitives),
+        };
+        bvh
+    }
+
+    // the primitives in the order the tree holds them
+    pub fn primitives(&self) -> &[P] {
+        &self.primitives
+    }
+
+    fn push_leaf(&mut self, primitive: Primitive<P>) -> AABB {
+        self.nodes.push(LinearNode {
+            bbox: primitive.bbox,
+            offset: self.primitives.len() as u32,
+            count: 1,
+            axis: 0,
+        });
+        self.primitives.push(primitive.hittable);
+        primitive.bbox
+    }
+
+    // reserves an interior node, builds both children after it and fills in its bounds
+    fn push_branch(
+        &mut self,
+        axis: usize,
+        build_left: impl FnOnce(&mut Self) -> AABB,
+        build_right: impl FnOnce(&mut Self) -> AABB,
+    ) -> AABB {
+        let index = self.nodes.len();
+        self.nodes.push(LinearNode {
+            bbox: AABB::new(Vector3::zeros(), Vector3::zeros()),
+            offset: 0,
+            count: 0,
+            axis: axis as u8,
+        });
+        let left = build_left(self);
+        self.nodes[index].offset = self.nodes.len() as u32;
+        let right = build_right(self);
+        let bbox = aabb::surrounding_box(&left, &right);
+        self.nodes[index].bbox = bbox;
+        bbox
+    }
+
+    fn build_sah(&mut self, mut primitives: Vec<Primitive<P>>, depth: usize) -> AABB {
+        if primitives.len() == 1 {
+            return self.push_leaf(primitives.pop().unwrap());
+        }
+        let (mut c_min, mut c_max) = ([Float::MAX; 3], [Float::MIN; 3]);
+        for p in primitives.iter() {
+            for a in 0..3 {
+                c_min[a] = c_min[a].min(p.centroid(a));
+                c_max[a] = c_max[a].max(p.centroid(a));
+            }
+        }
+        let axis = (0..3)
+            .max_by(|&a, &b| (c_max[a] - c_min[a]).total_cmp(&(c_max[b] - c_min[b])))
+            .unwrap();
+        let extent = c_max[axis] - c_min[axis];
+        primitives.sort_unstable_by(|a, b| a.centroid(axis).total_cmp(&b.centroid(axis)));
+
+        // bin the centroids and pick the cheapest split between buckets; deep down the
+        // tree fall back to median splits, which keep its depth within log2 of what
+        // is left should the heuristic peel primitives off one at a time
+        let mut split = primitives.len() / 2;
+        if extent > 0.0 && depth < MAX_SAH_DEPTH {
+            let bucket = |p: &Primitive<P>| {
+                (((p.centroid(axis) - c_min[axis]) / extent * SAH_BUCKETS as Float) as usize)
+                    .min(SAH_BUCKETS - 1)
+            };
+            let mut counts = [0; SAH_BUCKETS];
+            let mut boxes: [Option<AABB>; SAH_BUCKETS] = [None; SAH_BUCKETS];
+            for p in primitives.iter() {
+                let b = bucket(p);
+                counts[b] += 1;
+                boxes[b] = Some(match boxes[b] {
+                    Some(bbox) => aabb::surrounding_box(&bbox, &p.bbox),
+                    None => p.bbox,
+                });
+            }
+            let merge = |acc: Option<AABB>, bbox: &Option<AABB>| match (acc, bbox) {
+                (Some(a), Some(b)) => Some(aabb::surrounding_box(&a, b)),
+                (a, b) => a.or(*b),
+            };
+            let mut best_cost = Float::MAX;
+            for s in 1..SAH_BUCKETS {
+                let left_count: usize = counts[..s].iter().sum();
+                let right_count: usize = counts[s..].iter().sum();
+                if left_count == 0 || right_count == 0 {
+                    continue;
+                }
+                let left = boxes[..s].iter().fold(None, merge).unwrap();
+                let right = boxes[s..].iter().fold(None, merge).unwrap();
+                let cost = left_count as Float * surface_area(&left)
+                    + right_count as Float * surface_area(&right);
+                if cost < best_cost {
+                    best_cost = cost;
+                    split = left_count;
+                }
+            }
+        }
+
+        let right = primitives.split_off(split);
+        self.push_branch(
+            axis,
+            |bvh| bvh.build_sah(primitives, depth + 1),
+            |bvh| bvh.build_sah(right, depth + 1),
+        )
+    }
+
+    fn build_lbvh(&mut self, primitives: Vec<Primitive<P>>) -> AABB {
+        let scene = bounds(&primitives);
+        let extent = scene.max - scene.min;
+        let mut codes = primitives
+            .par_iter()
+            .enumerate()
+            .map(|(i, p)| {
+                let c = 0.5 * (p.bbox.min + p.bbox.max) - scene.min;
+                let normalized = |a: usize| {
+                    if extent[a] > 0.0 {
+                        c[a] / extent[a]
+                    } else {
+                        0.5
+                    }
+                };
+                (morton_code(normalized(0), normalized(1), normalized(2)), i)
+            })
+            .collect::<Vec<(u32, usize)>>();
+        codes.par_sort_unstable_by_key(|&(code, _)| code);
+
+        let mut slots = primitives
+            .into_iter()
+            .map(Some)
+            .collect::<Vec<Option<Primitive<P>>>>();
+        let sorted = codes
+            .iter()
+            .map(|&(_, i)| slots[i].take().unwrap())
+            .collect::<Vec<Primitive<P>>>();
+        let codes = codes
+            .into_iter()
+            .map(|(code, _)| code)
+            .collect::<Vec<u32>>();
+        self.emit_lbvh(sorted, &codes)
+    }
+
+    // splits the Morton ordered range where its highest differing bit flips, so the
+    // node bounds are merged from the leaves upwards as the recursion returns
+    fn emit_lbvh(&mut self, mut primitives: Vec<Primitive<P>>, codes: &[u32]) -> AABB {
+        if primitives.len() == 1 {
+            return self.push_leaf(primitives.pop().unwrap());
+        }
+        let first = codes[0];
+        let last = codes[codes.len() - 1];
+        let (split, axis) = if first == last {
+            (codes.len() / 2, 0)
+        } else {
+            let prefix = (first ^ last).leading_zeros();
+            let split = codes.partition_point(|&code| (first ^ code).leading_zeros() > prefix);
+            // bits are interleaved x, y, z from the most significant end
+            (split, (2 - (31 - prefix as usize) % 3))
+        };
+        let right = primitives.split_off(split);
+        self.push_branch(
+            axis,
+            |bvh| bvh.emit_lbvh(primitives, &codes[..split]),
+            |bvh| bvh.emit_lbvh(right, &codes[split..]),
+        )
+    }
+}
+
+impl<P: Hittable> Hittable for BVH<P> {
+    fn hit(&self, ray: &Ray, t_min: Float, mut t_max: Float) -> Option<HitRecord<'_>> {
+        let direction = ray.direction();
+        let dir_is_neg = [direction.x < 0.0, direction.y < 0.0, direction.z < 0.0];
+        let mut closest = None;
+        let mut stack = Stack::<usize, TRAVERSAL_STACK>::new();
+        let mut current = 0;
+        loop {
+            count_visit();
+            stats::count(Counter::NodeTests);
+            let node = &self.nodes[current];
+            if node.bbox.hit(ray, t_min, t_max) {
+                if node.count > 0 {
+                    let start = node.offset as usize;
+                    for primitive in &self.primitives[start..start + node.count as usize] {
+                        if let Some(hit) = primitive.hit(ray, t_min, t_max) {
+                            t_max = hit.t;
+                            closest = Some(hit);
+                        }
+                    }
+                } else {
+                    // visit the child nearer along the split axis first
+                    let (near, far) = if dir_is_neg[node.axis as usize] {
+                        (node.offset as usize, current + 1)
+                    } else {
+                        (current + 1, node.offset as usize)
+                    };
+                    stack.push(far);
+                    current = near;
+                    continue;
+                }
+            }
+            match stack.pop() {
+                Some(next) => current = next,
+                None => break,
+            }
+        }
+        closest
+    }
+
+    // the same walk, stopping at the first primitive in the way and in no
+    // particular order
+    fn occluded(&self, ray: &Ray, t_min: Float, t_max: Float) -> bool {
+        let mut stack = Stack::<usize, TRAVERSAL_STACK>::new();
+        let mut current = 0;
+        loop {
+            count_visit();
+            stats::count(Counter::NodeTests);
+            let node = &self.nodes[current];
+            if node.bbox.hit(ray, t_min, t_max) {
+                if node.count > 0 {
+                    let start = node.offset as usize;
+                    let leaf = &self.primitives[start..start + node.count as usize];
+                    if leaf.iter().any(|p| p.occluded(ray, t_min, t_max)) {
+                        return true;
+                    }
+                } else {
+                    stack.push(node.offset as usize);
+                    current += 1;
+                    continue;
+                }
+            }
+            match stack.pop() {
+                Some(next) => current = next,
+                None => return false,
+            }
+        }
+    }
+
+    // One walk for the whole packet: a node is entered when any of its rays
+    // crosses the box, children in the order the first ray would take them, and a
+    // leaf's primitives are tested against only those rays.
+    fn hit_packet(&self, packet: &RayPacket) -> [Option<HitRecord<'_>>; LANES] {
+        let mut closest: [Option<HitRecord>; LANES] = Default::default();
+        let mut t_max = packet.t_max;
+        let dir_is_neg = packet.dir_is_neg();
+        let mut stack = Stack::<usize, TRAVERSAL_STACK>::new();
+        let mut current = 0;
+        loop {
+            count_visit();
+            stats::add(Counter::NodeTests, packet.len() as u64);
+            let node = &self.nodes[current];
+            let mask = packet.hit_box(&node.bbox, &t_max);
+            if mask != 0 {
+                if node.count > 0 {
+                    let start = node.offset as usize;
+                    for primitive in &self.primitives[start..start + node.count as usize] {
+                        for lane in packet::lanes_of(mask) {
+                            let ray = packet.ray(lane);
+                            if let Some(hit) = primitive.hit(ray, packet.t_min, t_max[lane]) {
+                                t_max[lane] = hit.t;
+                                closest[lane] = Some(hit);
+                            }
+                        }
+                    }
+                } else {
+                    let (near, far) = if dir_is_neg[node.axis as usize] {
+                        (node.offset as usize, current + 1)
+                    } else {
+                        (current + 1, node.offset as usize)
+                    };
+                    stack.push(far);
+                    current = near;
+                    continue;
+                }
+            }
+            match stack.pop() {
+                Some(next) => current = next,
+                None => break,
+            }
+        }
+        closest
+    }
+
+    // the same, dropping each ray from the walk once something blocks it
+    fn occluded_packet(&self, packet: &RayPacket) -> [bool; LANES] {
+        let mut blocked = [false; LANES];
+        let mut open = packet.lanes();
+        let mut stack = Stack::<usize, TRAVERSAL_STACK>::new();
+        let mut current = 0;
+        while open != 0 {
+            count_visit();
+            stats::add(Counter::NodeTests, open.count_ones() as u64);
+            let node = &self.nodes[current];
+            let mask = packet.hit_box(&node.bbox, &packet.t_max) & open;
+            if mask != 0 {
+                if node.count > 0 {
+                    let start = node.offset as usize;
+                    let leaf = &self.primitives[start..start + node.count as usize];
+                    for lane in packet::lanes_of(mask) {
+                        let ray = packet.ray(lane);
+                        if leaf
+                            .iter()
+                            .any(|p| p.occluded(ray, packet.t_min, packet.t_max[lane]))
+                        {
+                            blocked[lane] = true;
+                            open &= !(1 << lane);
+                        }
+                    }
+                } else {
+                    stack.push(node.offset as usize);
+                    current += 1;
+                    continue;
+                }
+            }
+            match stack.pop() {
+                Some(next) => current = next,
+                None => break,
+            }
+        }
+        blocked
+    }
+
+    fn bounding_box(&self, _t0: Float, _t1: Float) -> Option<AABB> {
+        Some(self.nodes[0].bbox)
+    }
+
+    // visits every node whose box, dilated by the cone's footprint, the axis crosses
+    fn occlusion(&self, cone: &Cone, t_min: Float, t_max: Float) -> Float {
+        let mut occlusion = 0.0;
+        let mut stack = Stack::<usize, TRAVERSAL_STACK>::new();
+        let mut current = 0;
+        loop {
+            let node = &self.nodes[current];
+            if cone.overlaps(&node.bbox, t_min, t_max) {
+                if node.count > 0 {
+                    let start = node.offset as usize;
+                    let leaf = &self.primitives[start..start + node.count as usize];
+                    let blocked = cone::occlusion_of(
+                        leaf.iter().map(|p| p as &dyn Hittable),
+                        cone,
+                        t_min,
+                        t_max,
+                    );
+                    occlusion = cone::combine(occlusion, blocked);
+                    if occlusion >= cone::OPAQUE {
+                        return 1.0;
+                    }
+                } else {
+                    stack.push(node.offset as usize);
+                    current += 1;
+                    continue;
+                }
+            }
+            match stack.pop() {
+                Some(next) => current = next,
+                None => break,
+            }
+        }
+        occlusion
+    }
+}
+
+const EMPTY: u32 = u32::MAX;
+
+// Four children per node with their boxes stored as structure of arrays, each
+// coordinate a SIMD register of the four, so the slab tests for all of them run
+// as packed operations. A child with a non-zero count is a leaf run of primitives,
+// otherwise a node index.
+#[derive(Clone, Copy)]
+struct QNode {
+    min_x: Float4,
+    min_y: Float4,
+    min_z: Float4,
+    max_x: Float4,
+    max_y: Float4,
+    max_z: Float4,
+    child: [u32; 4],
+    count: [u16; 4],
+}
+
+impl QNode {
+    // entry distance of the ray into each child box, infinite where it misses;
+    // min and max pass over the NaN a slab the ray lies in gives, as Float's do
+    fn hit(
+        &self,
+        origin: &[Float4; 3],
+        inv_dir: &[Float4; 3],
+        t_min: Float,
+        t_max: Float,
+    ) -> [Float; 4] {
+        let tx0 = (self.min_x - origin[0]) * inv_dir[0];
+        let tx1 = (self.max_x - origin[0]) * inv_dir[0];
+        let ty0 = (self.min_y - origin[1]) * inv_dir[1];
+        let ty1 = (self.max_y - origin[1]) * inv_dir[1];
+        let tz0 = (self.min_z - origin[2]) * inv_dir[2];
+        let tz1 = (self.max_z - origin[2]) * inv_dir[2];
+        let enter = Float4::splat(t_min)
+            .max(tx0.min(tx1))
+            .max(ty0.min(ty1))
+            .max(tz0.min(tz1));
+        let exit = Float4::splat(t_max)
+            .min(tx0.max(tx1))
+            .min(ty0.max(ty1))
+            .min(tz0.max(tz1));
+        enter
+            .cmp_le(exit)
+            .blend(enter, Float4::splat(Float::INFINITY))
+            .to_array()
+    }
+
+    // the box of child i
+    fn bbox(&self, i: usize) -> AABB {
+        let lane = |v: &Float4| v.to_array()[i];
+        AABB::new(
+            Vector3::new(lane(&self.min_x), lane(&self.min_y), lane(&self.min_z)),
+            Vector3::new(lane(&self.max_x), lane(&self.max_y), lane(&self.max_z)),
+        )
+    }
+}
+
+// the ray's origin and inverse direction, each coordinate in all four lanes
+fn splat(ray: &Ray) -> ([Float4; 3], [Float4; 3]) {
+    let o = ray.origin();
+    let d = ray.direction();
+    (
+        [Float4::splat(o.x), Float4::splat(o.y), Float4::splat(o.z)],
+        [
+            Float4::splat(1.0 / d.x),
+            Float4::splat(1.0 / d.y),
+            Float4::splat(1.0 / d.z),
+        ],
+    )
+}
+
+// A 4-wide BVH made by collapsing a binary one, trading depth for wider nodes.
+pub struct QBVH<P = Box<dyn Hittable>> {
+    nodes: Vec<QNode>,
+    primitives: Vec<P>,
+    bbox: AABB,
+}
+
+impl<P: Hittable> QBVH<P> {
+    pub fn new(hittable: Vec<P>, time0: Float, time1: Float) -> Self {
+        QBVH::with_strategy(hittable, time0, time1, BuildStrategy::default())
+    }
+
+    pub fn with_strategy(
+        hittable: Vec<P>,
+        time0: Float,
+        time1: Float,
+        strategy: BuildStrategy,
+    ) -> Self {
+        QBVH::from_bvh(BVH::with_strategy(hittable, time0, time1, strategy))
+    }
+
+    pub fn from_bvh(bvh: BVH<P>) -> Self {
+        let mut qbvh = QBVH {
+            nodes: Vec::with_capacity(bvh.nodes.len() / 2 + 1),
+            primitives: Vec::new(),
+            bbox: bvh.nodes[0].bbox,
+        };
+        qbvh.collapse(&bvh.nodes, 0);
+        qbvh.primitives = bvh.primitives;
+        qbvh
+    }
+
+    // opens the largest interior children of a binary node until there are four
+    fn collapse(&mut self, nodes: &[LinearNode], root: usize) -> u32 {
+        let mut children = if nodes[root].count > 0 {
+            vec![root]
+        } else {
+            vec![root + 1, nodes[root].offset as usize]
+        };
+        while children.len() < 4 {
+            let widest = children
+                .iter()
+                .enumerate()
+                .filter(|(_, &c)| nodes[c].count == 0)
+                .max_by(|(_, &a), (_, &b)| {
+                    surface_area(&nodes[a].bbox).total_cmp(&surface_area(&nodes[b].bbox))
+                })
+                .map(|(i, _)| i);
+            match widest {
+                Some(i) => {
+                    let c = children.swap_remove(i);
+                    children.push(c + 1);
+                    children.push(nodes[c].offset as usize);
+                }
+                None => break,
+            }
+        }
+
+        // the node goes in before its children, which fill its lanes as they return
+        let index = self.nodes.len();
+        let empty = Float4::splat(Float::INFINITY);
+        self.nodes.push(QNode {
+            min_x: empty,
+            min_y: empty,
+            min_z: empty,
+            max_x: -empty,
+            max_y: -empty,
+            max_z: -empty,
+            child: [EMPTY; 4],
+            count: [0; 4],
+        });
+        let mut min = [[Float::INFINITY; 4]; 3];
+        let mut max = [[Float::NEG_INFINITY; 4]; 3];
+        for (i, &c) in children.iter().enumerate() {
+            let (child, count) = if nodes[c].count > 0 {
+                (nodes[c].offset, nodes[c].count)
+            } else {
+                (self.collapse(nodes, c), 0)
+            };
+            for axis in 0..3 {
+                min[axis][i] = nodes[c].bbox.min[axis];
+                max[axis][i] = nodes[c].bbox.max[axis];
+            }
+            let node = &mut self.nodes[index];
+            node.child[i] = child;
+            node.count[i] = count;
+        }
+        let node = &mut self.nodes[index];
+        [node.min_x, node.min_y, node.min_z] = min.map(Float4::new);
+        [node.max_x, node.max_y, node.max_z] = max.map(Float4::new);
+        index as u32
+    }
+}
+
+impl<P: Hittable> Hittable for QBVH<P> {
+    fn hit(&self, ray: &Ray, t_min: Float, mut t_max: Float) -> Option<HitRecord<'_>> {
+        let (origin, inv_dir) = splat(ray);
+        let mut closest = None;
+        // each node popped pushes at most three more than it took
+        let mut stack = Stack::<(u32, Float), { 3 * TRAVERSAL_STACK }>::new();
+        stack.push((0, 0.0));
+        while let Some((current, entry)) = stack.pop() {
+            if entry > t_max {
+                continue;
+            }
+            count_visit();
+            stats::add(Counter::NodeTests, 4);
+            let node = &self.nodes[current as usize];
+            let near = node.hit(&origin, &inv_dir, t_min, t_max);
+            let mut order = [0, 1, 2, 3];
+            order.sort_unstable_by(|&a, &b| near[a].total_cmp(&near[b]));
+            // intersect leaves nearest first to shrink t_max early, then push the
+            // interior children farthest first so the nearest is popped next
+            for &i in order.iter() {
+                if node.count[i] > 0 && near[i] <= t_max {
+                    let start = node.child[i] as usize;
+                    for primitive in &self.primitives[start..start + node.count[i] as usize] {
+                        if let Some(hit) = primitive.hit(ray, t_min, t_max) {
+                            t_max = hit.t;
+                            closest = Some(hit);
+                        }
+                    }
+                }
+            }
+            for &i in order.iter().rev() {
+                if node.count[i] == 0 && node.child[i] != EMPTY && near[i] <= t_max {
+                    stack.push((node.child[i], near[i]));
+                }
+            }
+        }
+        closest
+    }
+
+    // every child the ray crosses is visited, with no sorting, until a primitive
+    // blocks it
+    fn occluded(&self, ray: &Ray, t_min: Float, t_max: Float) -> bool {
+        let (origin, inv_dir) = splat(ray);
+        let mut stack = Stack::<u32, { 3 * TRAVERSAL_STACK }>::new();
+        stack.push(0);
+        while let Some(current) = stack.pop() {
+            count_visit();
+            stats::add(Counter::NodeTests, 4);
+            let node = &self.nodes[current as usize];
+            let near = node.hit(&origin, &inv_dir, t_min, t_max);
+            for ((&child, &count), &near) in node.child.iter().zip(&node.count).zip(&near) {
+                if child == EMPTY || near > t_max {
+                    continue;
+                }
+                if count > 0 {
+                    let start = child as usize;
+                    let leaf = &self.primitives[start..start + count as usize];
+                    if leaf.iter().any(|p| p.occluded(ray, t_min, t_max)) {
+                        return true;
+                    }
+                } else {
+                    stack.push(child);
+                }
+            }
+        }
+        false
+    }
+
+    fn bounding_box(&self, _t0: Float, _t1: Float) -> Option<AABB> {
+        Some(self.bbox)
+    }
+
+    fn occlusion(&self, cone: &Cone, t_min: Float, t_max: Float) -> Float {
+        let mut occlusion = 0.0;
+        let mut stack = Stack::<u32, { 3 * TRAVERSAL_STACK }>::new();
+        stack.push(0);
+        while let Some(current) = stack.pop() {
+            let node = &self.nodes[current as usize];
+            for i in 0..4 {
+                if node.child[i] == EMPTY || !cone.overlaps(&node.bbox(i), t_min, t_max) {
+                    continue;
+                }
+                if node.count[i] > 0 {
+                    let start = node.child[i] as usize;
+                    let leaf = &self.primitives[start..start + node.count[i] as usize];
+                    let blocked = cone::occlusion_of(
+                        leaf.iter().map(|p| p as &dyn Hittable),
+                        cone,
+                        t_min,
+                        t_max,
+                    );
+                    occlusion = cone::combine(occlusion, blocked);
+                    if occlusion >= cone::OPAQUE {
+                        return 1.0;
+                    }
+                } else {
+                    stack.push(node.child[i]);
+                }
+            }
+        }
+        occlusion
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::Lambertian;
+    use crate::sphere::Sphere;
+    use crate::texture::ConstantTexture;
+
+    #[test]
+    fn qbvh_hits_what_the_binary_bvh_does() {
+        let grey = Lambertian::new(ConstantTexture::new(0.5, 0.5, 0.5));
+        let spheres = || {
+            (0..300)
+                .map(|i| {
+                    let a = i as Float;
+                    let center = Vector3::new(6.0 * a.sin(), 0.05 * a - 7.5, 6.0 * (1.3 * a).cos());
+                    Sphere::new(center, 0.2 + 0.3 * (0.7 * a).sin().abs(), grey.clone())
+                })
+                .collect::<Vec<_>>()
+        };
+        for strategy in [BuildStrategy::SAH, BuildStrategy::LBVH] {
+            let bvh = BVH::with_strategy(spheres(), 0.0, 1.0, strategy);
+            let qbvh = QBVH::with_strategy(spheres(), 0.0, 1.0, strategy);
+            let mut hits = 0;
+            for i in 0..500 {
+                let a = i as Float;
+                let origin = Vector3::new(20.0 * (0.3 * a).sin(), 4.0 * a.cos(), 20.0);
+                let target = Vector3::new(5.0 * (1.7 * a).sin(), 7.0 * (0.9 * a).cos(), 0.0);
+                // axis aligned rays too, whose slabs give NaN where they lie in them
+                let direction = if i % 10 == 0 {
+                    -Vector3::z()
+                } else {
+                    target - origin
+                };
+                let ray = Ray::new(origin, direction, 0.0);
+                let expected = bvh.hit(&ray, 0.001, Float::MAX);
+                let hit = qbvh.hit(&ray, 0.001, Float::MAX);
+                assert_eq!(hit.as_ref().map(|h| h.t), expected.as_ref().map(|h| h.t));
+                assert_eq!(hit.map(|h| h.p), expected.map(|h| h.p));
+                for reach in [0.1, 0.5, 1.0] {
+                    assert_eq!(
+                        qbvh.occluded(&ray, 0.001, reach),
+                        bvh.occluded(&ray, 0.001, reach)
+                    );
+                }
+                hits += qbvh.hit(&ray, 0.001, Float::MAX).is_some() as usize;
+            }
+            // enough of both to mean something
+            assert!(hits > 50 && hits < 450);
+        }
+    }
+
+    #[test]
+    fn deep_trees_hit_what_a_list_does() {
+        // each sphere half again the last's size and distance, so every split peels
+        // one off until the median splits take over
+        let grey = Lambertian::new(ConstantTexture::new(0.5, 0.5, 0.5));
+        let spheres = || {
+            (0..200)
+                .map(|i| {
+                    let scale = (1.1 as Float).powi(i);
+                    Sphere::new(Vector3::new(scale, 0.0, 0.0), 0.04 * scale, grey.clone())
+                })
+                .collect::<Vec<_>>()
+        };
+        let list = spheres();
+        for strategy in [BuildStrategy::SAH, BuildStrategy::LBVH] {
+            let bvh = BVH::with_strategy(spheres(), 0.0, 1.0, strategy);
+            for i in 0..200 {
+                let x = (1.1 as Float).powi(i);
+                let ray = Ray::new(Vector3::new(x, x, 0.0), -Vector3::y(), 0.0);
+                let expected = list
+                    .iter()
+                    .filter_map(|s| s.hit(&ray, 0.001, Float::MAX))
+                    .map(|h| h.t)
+                    .min_by(|a, b| a.total_cmp(b));
+                assert_eq!(bvh.hit(&ray, 0.001, Float::MAX).map(|h| h.t), expected);
+                assert_eq!(bvh.occluded(&ray, 0.001, Float::MAX), expected.is_some());
+            }
+        }
+    }
+
+    #[test]
+    fn nan_centroids_do_not_stop_builds() {
+        // as a degenerate imported triangle can give
+        let grey = Lambertian::new(ConstantTexture::new(0.5, 0.5, 0.5));
+        let spheres = || {
+            (0..20)
+                .map(|i| {
+                    let x = if i == 7 { Float::NAN } else { 3.0 * i as Float };
+                    Sphere::new(Vector3::new(x, 0.0, 0.0), 1.0, grey.clone())
+                })
+                .collect::<Vec<_>>()
+        };
+        for strategy in [BuildStrategy::SAH, BuildStrategy::LBVH] {
+            let bvh = BVH::with_strategy(spheres(), 0.0, 1.0, strategy);
+            let ray = Ray::new(Vector3::new(6.0, 5.0, 0.0), -Vector3::y(), 0.0);
+            assert_eq!(bvh.hit(&ray, 0.001, Float::MAX).map(|h| h.t), Some(4.0));
+        }
+    }
+
+    #[test]
+    fn traversal_stacks_spill_past_their_fixed_entries() {
+        let mut stack = Stack::<u32, 4>::new();
+        for i in 0..10 {
+            stack.push(i);
+        }
+        assert_eq!(stack.spilled.len(), 6);
+        for i in (0..10).rev() {
+            assert_eq!(stack.pop(), Some(i));
+        }
+        assert_eq!(stack.pop(), None);
+    }
+}
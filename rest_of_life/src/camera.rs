@@ -1,75 +1,600 @@
-use crate::ray::Ray;
+use crate::float::{self, Float};
+use crate::motion::Keyframes;
+use crate::ray::{Differentials, Ray};
+use crate::rng;
 use nalgebra::Vector3;
-use rand::Rng;
-use std::f32;
-
-fn random_in_unit_disk() -> Vector3<f32> {
-    let mut rng = rand::thread_rng();
-    let unit = Vector3::new(1.0, 1.0, 0.0);
-    loop {
-        let p = 2.0 * Vector3::new(rng.gen::<f32>(), rng.gen::<f32>(), 0.0) - unit;
-        if p.dot(&p) < 1.0 {
-            return p;
+use std::str::FromStr;
+use std::sync::Arc;
+
+// When the shutter is open. With motion blur on, rays leave at times spread over
+// [open, close]; with it off they all leave at `open`, freezing moving objects
+// where they are then.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Shutter {
+    pub open: Float,
+    pub close: Float,
+    pub motion_blur: bool,
+}
+
+impl Default for Shutter {
+    fn default() -> Self {
+        Shutter {
+            open: 0.0,
+            close: 1.0,
+            motion_blur: true,
+        }
+    }
+}
+
+impl Shutter {
+    // the times rays leave at, as Camera::new takes them
+    pub fn interval(&self) -> (Float, Float) {
+        if self.motion_blur {
+            (self.open, self.close)
+        } else {
+            (self.open, self.open)
         }
     }
 }
 
+// p rotated by angle radians about the unit axis
+fn rotate(p: &Vector3<Float>, axis: &Vector3<Float>, angle: Float) -> Vector3<Float> {
+    let (sin, cos) = angle.sin_cos();
+    p * cos + axis.cross(p) * sin + axis * axis.dot(p) * (1.0 - cos)
+}
+
+// How a camera turns image plane coordinates (s, t) in [0, 1]^2 into rays: the
+// origin and direction of the ray through them, or None where the model sees
+// nothing, as outside a fisheye's image circle.
+pub trait CameraModel: Send + Sync {
+    fn ray(&self, camera: &Camera, s: Float, t: Float) -> Option<(Vector3<Float>, Vector3<Float>)>;
+
+    // How the direction of the ray through (s, t) changes to those through
+    // (s + ds, t) and (s, t + dt) from the same point on the lens, for its ray
+    // differentials, without drawing random numbers. None where the model cannot
+    // say, which leaves the textures it sees unfiltered.
+    fn differentials(
+        &self,
+        _camera: &Camera,
+        _s: Float,
+        _t: Float,
+        _ds: Float,
+        _dt: Float,
+    ) -> Option<(Vector3<Float>, Vector3<Float>)> {
+        None
+    }
+}
+
+// the differentials of a pinhole model, whose rays leave from one point and so can
+// be traced again beside each other
+fn pinhole_differentials(
+    model: &dyn CameraModel,
+    camera: &Camera,
+    s: Float,
+    t: Float,
+    ds: Float,
+    dt: Float,
+) -> Option<(Vector3<Float>, Vector3<Float>)> {
+    let (_, direction) = model.ray(camera, s, t)?;
+    let (_, dx) = model.ray(camera, s + ds, t)?;
+    let (_, dy) = model.ray(camera, s, t + dt)?;
+    Some((dx - direction, dy - direction))
+}
+
+// The book's thin lens camera: rays from a disk on the lens through the focus plane.
+pub struct Perspective;
+
+impl CameraModel for Perspective {
+    fn ray(&self, camera: &Camera, s: Float, t: Float) -> Option<(Vector3<Float>, Vector3<Float>)> {
+        let origin = if camera.lens_radius == 0.0 {
+            camera.origin
+        } else {
+            let rd = camera.lens_radius * camera.aperture.sample(s, t);
+            let offset = camera.u * rd.x + camera.v * rd.y;
+            camera.origin + offset
+        };
+        let target = camera
+            .focus_on(&(camera.lower_left_corner + s * camera.horizontal + t * camera.vertical));
+        let direction = target - origin;
+        Some((origin, direction))
+    }
+
+    // every ray through a point on the focus plane, wherever it leaves the lens
+    fn differentials(
+        &self,
+        camera: &Camera,
+        s: Float,
+        t: Float,
+        ds: Float,
+        dt: Float,
+    ) -> Option<(Vector3<Float>, Vector3<Float>)> {
+        let target = |s: Float, t: Float| {
+            camera
+                .focus_on(&(camera.lower_left_corner + s * camera.horizontal + t * camera.vertical))
+        };
+        let centre = target(s, t);
+        Some((target(s + ds, t) - centre, target(s, t + dt) - centre))
+    }
+}
+
+// how a fisheye maps the angle from the view direction to the distance from the
+// centre of its image circle
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FisheyeMapping {
+    // distance proportional to the angle
+    Equidistant,
+    // equal solid angles cover equal areas
+    Equisolid,
+}
+
+// A pinhole fisheye whose image circle fills the frame's shorter side and spans
+// `fov` degrees across, up to 360.
+pub struct Fisheye {
+    pub mapping: FisheyeMapping,
+    pub fov: Float,
+}
+
+impl CameraModel for Fisheye {
+    fn ray(&self, camera: &Camera, s: Float, t: Float) -> Option<(Vector3<Float>, Vector3<Float>)> {
+        let aspect = camera.horizontal.norm() / camera.vertical.norm();
+        let (x, y) = (2.0 * s - 1.0, 2.0 * t - 1.0);
+        let (x, y) = if aspect >= 1.0 {
+            (x * aspect, y)
+        } else {
+            (x, y / aspect)
+        };
+        let r = (x * x + y * y).sqrt();
+        if r > 1.0 {
+            return None;
+        }
+        let half_fov = 0.5 * self.fov.to_radians();
+        let theta = match self.mapping {
+            FisheyeMapping::Equidistant => r * half_fov,
+            FisheyeMapping::Equisolid => 2.0 * (r * (0.5 * half_fov).sin()).asin(),
+        };
+        let phi = y.atan2(x);
+        let (_, u, v, forward) = camera.frame();
+        let direction = theta.sin() * (phi.cos() * u + phi.sin() * v) + theta.cos() * forward;
+        Some((camera.origin, direction))
+    }
+
+    fn differentials(
+        &self,
+        camera: &Camera,
+        s: Float,
+        t: Float,
+        ds: Float,
+        dt: Float,
+    ) -> Option<(Vector3<Float>, Vector3<Float>)> {
+        pinhole_differentials(self, camera, s, t, ds, dt)
+    }
+}
+
+// A pinhole 360 degree panorama: s runs once around the horizon starting behind the
+// camera, with the view direction in the middle, and t from straight down to
+// straight up, as VR players expect of a 2:1 image.
+pub struct Equirectangular;
+
+impl CameraModel for Equirectangular {
+    fn ray(&self, camera: &Camera, s: Float, t: Float) -> Option<(Vector3<Float>, Vector3<Float>)> {
+        let longitude = (s - 0.5) * 2.0 * float::consts::PI;
+        let latitude = (t - 0.5) * float::consts::PI;
+        let (_, u, v, forward) = camera.frame();
+        let direction =
+            latitude.cos() * (longitude.sin() * u + longitude.cos() * forward) + latitude.sin() * v;
+        Some((camera.origin, direction))
+    }
+
+    fn differentials(
+        &self,
+        camera: &Camera,
+        s: Float,
+        t: Float,
+        ds: Float,
+        dt: Float,
+    ) -> Option<(Vector3<Float>, Vector3<Float>)> {
+        pinhole_differentials(self, camera, s, t, ds, dt)
+    }
+}
+
+// The camera model the command line picks
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum Projection {
+    #[default]
+    Perspective,
+    Fisheye(FisheyeMapping),
+    Equirectangular,
+}
+
+impl FromStr for Projection {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "perspective" => Ok(Projection::Perspective),
+            "fisheye" => Ok(Projection::Fisheye(FisheyeMapping::Equidistant)),
+            "fisheye-equisolid" => Ok(Projection::Fisheye(FisheyeMapping::Equisolid)),
+            "equirect" => Ok(Projection::Equirectangular),
+            _ => Err(format!("unknown camera: {}", s)),
+        }
+    }
+}
+
+impl Projection {
+    // the model, with fisheyes spanning fov degrees
+    pub fn model(&self, fov: Float) -> Arc<dyn CameraModel> {
+        match *self {
+            Projection::Perspective => Arc::new(Perspective),
+            Projection::Fisheye(mapping) => Arc::new(Fisheye { mapping, fov }),
+            Projection::Equirectangular => Arc::new(Equirectangular),
+        }
+    }
+}
+
+// The shape of the lens opening, which out of focus highlights take. With three or
+// more blades it is the regular polygon they leave, turned by `rotation` degrees;
+// otherwise it is round. `cat_eye` clips it with a second circle that slides
+// outward with the pixel, as the lens barrel does off axis, from 0 (off) to 1
+// (the circles' centres a radius apart at the corners).
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Aperture {
+    pub blades: usize,
+    pub rotation: Float,
+    pub cat_eye: Float,
+}
+
+impl Aperture {
+    // a point on the unit opening as seen from image plane coordinates (s, t)
+    pub fn sample(&self, s: Float, t: Float) -> Vector3<Float> {
+        let shift =
+            self.cat_eye * Vector3::new(2.0 * s - 1.0, 2.0 * t - 1.0, 0.0) / float::consts::SQRT_2;
+        loop {
+            let p = self.opening();
+            if (p - shift).norm_squared() <= 1.0 {
+                return p;
+            }
+        }
+    }
+
+    fn opening(&self) -> Vector3<Float> {
+        if self.blades < 3 {
+            return rng::in_unit_disk();
+        }
+        // uniform in one of the triangles the polygon's edges make with its centre
+        let n = self.blades as Float;
+        let edge = (rng::uniform() * n).floor().min(n - 1.0);
+        let a0 = self.rotation.to_radians() + 2.0 * float::consts::PI * edge / n;
+        let a1 = a0 + 2.0 * float::consts::PI / n;
+        let (mut x, mut y) = (rng::uniform(), rng::uniform());
+        if x + y > 1.0 {
+            x = 1.0 - x;
+            y = 1.0 - y;
+        }
+        x * Vector3::new(a0.cos(), a0.sin(), 0.0) + y * Vector3::new(a1.cos(), a1.sin(), 0.0)
+    }
+}
+
+// Tilt-shift movements. `shift` slides the sensor by fractions of the frame's width
+// and height, moving the view without turning the camera, as architectural shots
+// do to keep verticals straight. `tilt` and `swing` turn the plane of focus by
+// degrees about the camera's right and up axes (Scheimpflug), leaning its top and
+// its right side away from the camera, for the miniature look.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Movements {
+    pub shift: (Float, Float),
+    pub tilt: Float,
+    pub swing: Float,
+}
+
+// The optics a camera is built with: its vertical field of view in degrees, the
+// diameter of its lens opening, the distance it focuses at and its movements.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Optics {
+    pub vertical_fov: Float,
+    pub aperture: Float,
+    pub focus_dist: Float,
+    pub movements: Movements,
+}
+
+impl Default for Optics {
+    fn default() -> Self {
+        Optics {
+            vertical_fov: 40.0,
+            aperture: 0.0,
+            focus_dist: 10.0,
+            movements: Movements::default(),
+        }
+    }
+}
+
+// Keyframed offsets of the look-from and look-at points a camera was built with,
+// moving it while the shutter is open for pans and dolly moves.
+#[derive(Clone, Debug)]
+pub struct CameraPath {
+    pub look_at: Vector3<Float>,
+    pub view_up: Vector3<Float>,
+    pub from: Keyframes,
+    pub at: Keyframes,
+}
+
+impl CameraPath {
+    // the look-from point and the unit right, up and backward directions at `time`
+    // of a camera built looking from `look_from`
+    fn frame(
+        &self,
+        look_from: &Vector3<Float>,
+        time: Float,
+    ) -> (
+        Vector3<Float>,
+        Vector3<Float>,
+        Vector3<Float>,
+        Vector3<Float>,
+    ) {
+        let from = look_from + self.from.at(time);
+        let w = (from - self.look_at - self.at.at(time)).normalize();
+        let u = self.view_up.cross(&w).normalize();
+        (from, u, w.cross(&u), w)
+    }
+}
+
+#[derive(Clone)]
 pub struct Camera {
-    origin: Vector3<f32>,
-    lower_left_corner: Vector3<f32>,
-    horizontal: Vector3<f32>,
-    vertical: Vector3<f32>,
-    u: Vector3<f32>,
-    v: Vector3<f32>,
-    time0: f32,
-    time1: f32,
-    lens_radius: f32,
+    origin: Vector3<Float>,
+    lower_left_corner: Vector3<Float>,
+    horizontal: Vector3<Float>,
+    vertical: Vector3<Float>,
+    u: Vector3<Float>,
+    v: Vector3<Float>,
+    time0: Float,
+    time1: Float,
+    lens_radius: Float,
+    aperture: Aperture,
+    // normal of a tilted plane of focus
+    focus_normal: Option<Vector3<Float>>,
+    path: Option<CameraPath>,
+    model: Arc<dyn CameraModel>,
 }
 
 impl Camera {
     pub fn new(
-        look_from: Vector3<f32>,
-        look_at: Vector3<f32>,
-        view_up: Vector3<f32>,
-        vertical_fov: f32,
-        aspect: f32,
-        aperture: f32,
-        focus_dist: f32,
-        time0: f32,
-        time1: f32,
+        look_from: Vector3<Float>,
+        look_at: Vector3<Float>,
+        view_up: Vector3<Float>,
+        aspect: Float,
+        optics: Optics,
+        time0: Float,
+        time1: Float,
     ) -> Self {
-        let theta = vertical_fov * f32::consts::PI / 180.0;
-        let half_height = focus_dist * f32::tan(theta / 2.0);
+        let Optics {
+            vertical_fov,
+            aperture,
+            focus_dist,
+            movements,
+        } = optics;
+        let theta = vertical_fov * float::consts::PI / 180.0;
+        let half_height = focus_dist * Float::tan(theta / 2.0);
         let half_width = aspect * half_height;
         let w = (look_from - look_at).normalize();
         let u = view_up.cross(&w).normalize();
         let v = w.cross(&u);
+        let horizontal = 2.0 * half_width * u;
+        let vertical = 2.0 * half_height * v;
+        let (shift_x, shift_y) = movements.shift;
+        let focus_normal = if movements.tilt != 0.0 || movements.swing != 0.0 {
+            let n = rotate(&-w, &u, -movements.tilt.to_radians());
+            Some(rotate(&n, &v, movements.swing.to_radians()))
+        } else {
+            None
+        };
         Camera {
             origin: look_from,
-            lower_left_corner: look_from - half_width * u - half_height * v - focus_dist * w,
-            horizontal: 2.0 * half_width * u,
-            vertical: 2.0 * half_height * v,
+            lower_left_corner: look_from - half_width * u - half_height * v - focus_dist * w
+                + shift_x * horizontal
+                + shift_y * vertical,
+            horizontal,
+            vertical,
             u,
             v,
             time0,
             time1,
             lens_radius: aperture / 2.0,
+            aperture: Aperture::default(),
+            focus_normal,
+            path: None,
+            model: Arc::new(Perspective),
+        }
+    }
+
+    // the same camera seeing through another model; the perspective frame it was
+    // built with still places it and sizes its frame
+    pub fn with_model(self, model: Arc<dyn CameraModel>) -> Camera {
+        Camera { model, ..self }
+    }
+
+    // the same camera with a differently shaped lens opening
+    pub fn with_aperture(self, aperture: Aperture) -> Camera {
+        Camera { aperture, ..self }
+    }
+
+    // the same camera following a path while the shutter is open
+    pub fn with_path(self, path: CameraPath) -> Camera {
+        Camera {
+            path: Some(path),
+            ..self
+        }
+    }
+
+    // the ray through image plane coordinates (s, t), None where the model sees nothing
+    pub fn get_ray(&self, s: Float, t: Float) -> Option<Ray> {
+        self.ray_at(s, t, None)
+    }
+
+    // the ray through (s, t) carrying, as its differentials, the rays through the
+    // pixels ds to the right and dt up, where the model can say what they are
+    pub fn get_ray_differential(&self, s: Float, t: Float, ds: Float, dt: Float) -> Option<Ray> {
+        self.ray_at(s, t, Some((ds, dt)))
+    }
+
+    fn ray_at(&self, s: Float, t: Float, spacing: Option<(Float, Float)>) -> Option<Ray> {
+        let (origin, direction) = self.model.ray(self, s, t)?;
+        let time = self.time0 + rng::uniform() * (self.time1 - self.time0);
+        let offsets = spacing.and_then(|(ds, dt)| self.model.differentials(self, s, t, ds, dt));
+        let (origin, direction, offsets) = match &self.path {
+            Some(path) => {
+                let (from, turn) = self.moved(path, time);
+                (
+                    from + turn(&(origin - self.origin)),
+                    turn(&direction),
+                    offsets.map(|(dx, dy)| (turn(&dx), turn(&dy))),
+                )
+            }
+            None => (origin, direction, offsets),
+        };
+        let differentials = offsets.map(|(dx, dy)| Differentials {
+            dx_origin: Vector3::zeros(),
+            dx_direction: dx,
+            dy_origin: Vector3::zeros(),
+            dy_direction: dy,
+        });
+        Some(Ray::new(origin, direction, time).with_differentials(differentials))
+    }
+
+    // where the path puts the lens at `time`, and the rotation from the camera's
+    // own frame to the one it has there
+    fn moved(
+        &self,
+        path: &CameraPath,
+        time: Float,
+    ) -> (Vector3<Float>, impl Fn(&Vector3<Float>) -> Vector3<Float>) {
+        let (from, u, v, w) = path.frame(&self.origin, time);
+        let (u0, v0) = (self.u, self.v);
+        let w0 = u0.cross(&v0);
+        (from, move |p: &Vector3<Float>| {
+            u * p.dot(&u0) + v * p.dot(&v0) + w * p.dot(&w0)
+        })
+    }
+
+    // The camera standing still where its path has it at `time`; without a path,
+    // the camera itself.
+    pub fn at_time(&self, time: Float) -> Camera {
+        let path = match &self.path {
+            Some(path) => path,
+            None => return self.clone(),
+        };
+        let (origin, turn) = self.moved(path, time);
+        Camera {
+            origin,
+            lower_left_corner: origin + turn(&(self.lower_left_corner - self.origin)),
+            horizontal: turn(&self.horizontal),
+            vertical: turn(&self.vertical),
+            u: turn(&self.u),
+            v: turn(&self.v),
+            focus_normal: self.focus_normal.map(|n| turn(&n)),
+            path: None,
+            ..self.clone()
         }
     }
 
-    pub fn get_ray(&self, s: f32, t: f32) -> Ray {
-        let origin = if self.lens_radius == 0.0 {
-            self.origin
+    // the position of the lens and the unit right, up and forward directions
+    pub fn frame(
+        &self,
+    ) -> (
+        Vector3<Float>,
+        Vector3<Float>,
+        Vector3<Float>,
+        Vector3<Float>,
+    ) {
+        (self.origin, self.u, self.v, self.u.cross(&self.v) * -1.0)
+    }
+
+    // the lower left corner of the image plane and its horizontal and vertical extents
+    pub fn image_plane(&self) -> (Vector3<Float>, Vector3<Float>, Vector3<Float>) {
+        (self.lower_left_corner, self.horizontal, self.vertical)
+    }
+
+    // the ray from the centre of the lens through image plane coordinates (s, t),
+    // leaving as the shutter opens
+    pub fn centre_ray(&self, s: Float, t: Float) -> Ray {
+        let p = self.lower_left_corner + s * self.horizontal + t * self.vertical;
+        Ray::new(self.origin, p - self.origin, self.time0)
+    }
+
+    // the same camera focused `distance` in front of the lens along its axis
+    pub fn refocused(&self, distance: Float) -> Camera {
+        let (_, _, _, forward) = self.frame();
+        let k = distance / (self.lower_left_corner - self.origin).dot(&forward);
+        Camera {
+            lower_left_corner: self.origin + k * (self.lower_left_corner - self.origin),
+            horizontal: k * self.horizontal,
+            vertical: k * self.vertical,
+            ..self.clone()
+        }
+    }
+
+    // Where the line from the lens centre through a point on the image plane meets
+    // the plane of focus. That is the point itself unless the plane is tilted, and
+    // stays it where the tilted plane is not ahead.
+    pub fn focus_on(&self, p: &Vector3<Float>) -> Vector3<Float> {
+        let n = match self.focus_normal {
+            Some(n) => n,
+            None => return *p,
+        };
+        let d = p - self.origin;
+        let denom = d.dot(&n);
+        if denom <= 0.0 {
+            return *p;
+        }
+        let (_, _, _, forward) = self.frame();
+        let distance = (self.lower_left_corner - self.origin).dot(&forward);
+        self.origin + distance * forward.dot(&n) / denom * d
+    }
+
+    pub fn lens_radius(&self) -> Float {
+        self.lens_radius
+    }
+
+    // the centre of the image plane, the point the camera is focused on
+    pub fn focus_point(&self) -> Vector3<Float> {
+        self.lower_left_corner + 0.5 * self.horizontal + 0.5 * self.vertical
+    }
+
+    // The camera turned by `yaw` about the world's y axis and then by `pitch` about
+    // its own right axis, both in radians around `pivot`, then moved by `offset`.
+    // Turning around the lens looks around; turning around the focus point orbits
+    // it. A pitch that would tip the view past straight up or down is dropped, and
+    // a turned camera no longer follows its path.
+    pub fn turned(
+        &self,
+        pivot: &Vector3<Float>,
+        yaw: Float,
+        pitch: Float,
+        offset: &Vector3<Float>,
+    ) -> Camera {
+        let y_axis = Vector3::new(0.0, 1.0, 0.0);
+        let to_plane = self.focus_point() - self.origin;
+        let right = rotate(&self.u, &y_axis, yaw);
+        let turned_forward = rotate(&rotate(&to_plane, &y_axis, yaw), &right, pitch);
+        let pitch = if turned_forward.normalize().dot(&y_axis).abs() > 0.99 {
+            0.0
         } else {
-            let rd = self.lens_radius * random_in_unit_disk();
-            let offset = self.u * rd.x + self.v * rd.y;
-            self.origin + offset
+            pitch
         };
-        let time = self.time0 + rand::thread_rng().gen::<f32>() * (self.time1 - self.time0);
-        Ray::new(
+        let turn = |p: &Vector3<Float>| rotate(&rotate(p, &y_axis, yaw), &right, pitch);
+        let origin = pivot + turn(&(self.origin - pivot)) + offset;
+        let u = turn(&self.u);
+        let v = turn(&self.v);
+        let horizontal = self.horizontal.norm() * u;
+        let vertical = self.vertical.norm() * v;
+        Camera {
             origin,
-            self.lower_left_corner + s * self.horizontal + t * self.vertical - origin,
-            time,
-        )
+            lower_left_corner: origin + turn(&to_plane) - 0.5 * horizontal - 0.5 * vertical,
+            horizontal,
+            vertical,
+            u,
+            v,
+            focus_normal: self.focus_normal.map(|n| turn(&n)),
+            path: None,
+            ..self.clone()
+        }
     }
 }
@@ -0,0 +1,931 @@
+use crate::adaptive::Adaptive;
+use crate::animation::Animation;
+use crate::bounce::BounceLimits;
+use crate::bvh::BuildStrategy;
+use crate::camera::{Aperture, Projection, Shutter};
+use crate::crop::Crop;
+use crate::dataset::Dataset;
+use crate::estimator::Estimator;
+use crate::float::Float;
+use crate::gltf;
+use crate::guide::GuideSchedule;
+use crate::integrator::IntegratorKind;
+use crate::lens::{self, Lens};
+use crate::lut;
+use crate::reference;
+use crate::render::{OutputFormat, RenderSettings};
+use crate::rng::RngBackend;
+use crate::scene;
+use crate::sensor::SensorNoise;
+use crate::sppm::PhotonMapping;
+use crate::throughput::ThroughputCutoff;
+use crate::tiles::TileRange;
+use crate::tonemap::{ToneMap, ToneMapping, Transfer};
+use std::str::FromStr;
+use std::sync::Arc;
+
+pub const USAGE: &str = "usage: rest_of_life [options] > out.ppm
+       rest_of_life rtmerge <batch.rtfb>... [--format <format>] > merged.rtfb
+       rest_of_life patch <whole.rtfb> <crop.rtfb> --crop <x0,y0,x1,y1> > patched.rtfb
+
+options:
+  --scene <name>                 scene to render (default cornell_box)
+  --scene-file <path>            render the scene a file describes instead, see scenefile.rs,
+                                 a .pbrt file, see pbrt.rs, or a .gltf or .glb file
+                                 (needs the gltf feature)
+  --list-scenes                  print the scene names and exit
+  --backend <cpu|gpu>            renderer (default cpu); gpu is an experimental wavefront
+                                 path tracer on a compute shader for --scene-file scenes,
+                                 sampling only BSDFs and freezing motion at the shutter's
+                                 opening (needs the gpu feature)
+  --shutter <open>:<close>       when the shutter is open (default 0:1)
+  --no-motion-blur               send every ray at the shutter's opening time
+  --frames <n>                   render n frames of the scene's keyframed motion as
+                                 frame_0001 onwards, in --format, the first opening when
+                                 --shutter does
+  --fps <rate>                   frames per unit of scene time (default 24)
+  --shutter-angle <degrees>      share of a frame each frame's shutter is open, in
+                                 (0, 360] (default 180)
+  --frames-dir <dir>             output directory for the frames (default frames)
+  --video <path>                 encode the frames into one video instead, by piping them
+                                 to ffmpeg: .mp4, .mkv or .mov as H.264, .webm as VP9,
+                                 .apng or .png as animated PNG, or .gif
+  --camera <perspective|fisheye|fisheye-equisolid|equirect>
+                                 camera model (default perspective); fisheyes fill the
+                                 frame's shorter side with an image circle and equirect
+                                 renders a 360 degree panorama, best at 2:1
+  --fisheye-fov <degrees>        angle a fisheye's image circle spans (default 180)
+  --aperture-blades <n>          shape the lens opening as a polygon of n >= 3 blades for
+                                 polygonal bokeh (default 0, round)
+  --aperture-rotation <degrees>  turn the blades (default 0)
+  --autofocus <x>:<y>            focus on what the ray through the given fractions of the
+                                 frame from its top left hits, 0.5:0.5 for the centre
+  --lens <file>                  trace camera rays through the spherical elements a lens
+                                 file lists, radius thickness ior aperture in mm per line
+                                 from the scene side as in pbrt's lens files
+  --lens-film <mm>               film diagonal behind the lens (default 35)
+  --lens-scale <units>           scene units per millimetre (default 0.001, metres)
+  --lens-focus <distance>        distance from the film the lens focuses on (default the
+                                 scene camera's focus distance)
+  --cat-eye <strength>           clip the bokeh toward the frame's edges into cat's eyes,
+                                 from 0 (default, off) to 1
+  --medium-g <g>                 asymmetry of the built-in scenes' smoke and fog, from -1
+                                 (back scattering) through 0 (default, isotropic) to 1
+                                 (forward scattering)
+  --width <pixels>               image width (default 500)
+  --height <pixels>              image height (default 500)
+  --spp <samples>                samples per pixel (default 1000)
+  --format <p3|p6|png|raw>       output format (default p3); raw is the linear f32
+                                 framebuffer after an RTFB magic and u32 width, height
+                                 and spp
+  --tonemap <clamp|reinhard|aces>
+                                 tone mapping of the 8 bit formats (default clamp)
+  --exposure <stops>             scale the image by 2^stops before tone mapping (default 0)
+  --white-point <value>          exposed value shown as full white (default per tone map)
+  --gamma <gamma>                encode with a plain power curve of 1/gamma instead of
+                                 the sRGB curve
+  --legacy-gamma                 encode with the book's sqrt, --gamma 2
+  --lut <file>                   grade the encoded colours of the 8 bit formats through the
+                                 3D LUT in a .cube file, to match a camera's or a grade's
+                                 look
+  --aov <prefix>                 also write the first hit albedo, normal and depth as
+                                 <prefix>_albedo.pfm, _normal.pfm, _depth.pfm and the
+                                 object ids as <prefix>_object_id.pgm
+  --rng <pcg|xoshiro|chacha>     random number generator (default xoshiro)
+  --seed <seed>                  seed for scene generation and every pixel's samples
+                                 (default 0)
+  --adaptive <threshold>         sample each pixel until its 95% confidence interval is
+                                 within <threshold> of its mean, taking at most --spp
+  --adaptive-min-spp <samples>   samples every pixel takes first (default 16)
+  --heatmap <path>               also write the samples each pixel took as a ppm heatmap
+  --median-of-means <k>          combine samples as the median of k group means
+  --clamp <radiance>             cap what a sample gathers past its first bounce (biased)
+  --reject-outliers <sigma>      drop samples more than <sigma> standard deviations
+                                 brighter than the rest of their pixel (biased)
+  --bvh <sah|lbvh>               bvh build strategy (default sah)
+  --accel <bvh|qbvh|kdtree>      acceleration structure (default bvh)
+  --kd-max-depth <depth>         kd-tree depth limit (default 8 + 1.3 log2 n)
+  --kd-leaf-size <count>         kd-tree primitives per leaf (default 1)
+  --min-throughput <t>           cut paths whose throughput falls below <t> (biased)
+  --throughput-roulette          continue cut paths probabilistically instead (unbiased)
+  --max-diffuse <bounces>        most diffuse bounces per path (default unlimited)
+  --max-glossy <bounces>         most specular reflections per path (default unlimited)
+  --max-transmission <bounces>   most refractions per path (default unlimited)
+  --mnee                         connect caustics through the glass sphere to the light
+  --light-link <light>:<ids>     let object <light> illuminate only the listed objects
+  --shadow-link <light>:<ids>    let only the listed objects shadow object <light>
+                                 cornell_box ids: 1 green wall, 2 red wall, 3 light,
+                                 4 ceiling, 5 floor, 6 back wall, 7 glass sphere, 8 box
+  --preview                      show the image in a window as passes accumulate (needs
+                                 the preview feature): wasd moves, q and e lower and
+                                 raise, arrows or right drag look around, left drag
+                                 orbits the focus point, the wheel dollies, shift moves
+                                 faster, r resets the camera, + and - change the
+                                 exposure, p saves, escape quits; a --scene-file is
+                                 read again whenever it is saved
+  --cone-preview                 fast approximate preview: one cone per sample with soft
+                                 shadows and glossy blur from footprints, direct light only
+  --spectral                     trace each sample at a random wavelength so dispersive
+                                 glass splits light into its colours
+  --integrator <name>            how samples estimate radiance: path (default), naive
+                                 (bsdf sampling only), ao (ambient occlusion), direct
+                                 (direct light only), clay (every surface matte grey),
+                                 or a false colour view of the first hit: normal, uv,
+                                 depth or visits (acceleration structure nodes visited)
+  --ao-distance <d>              farthest occluder ambient occlusion counts (default
+                                 unbounded)
+  --packets                      trace each pixel's samples 8 at a time as ray packets;
+                                 ao and direct trace their shadow rays as packets too
+  --wavefront                    run the path tracer as a wavefront, many samples'
+                                 paths advancing a bounce at a time in stages
+  --debug-nan                    log each sample whose radiance is NaN or infinite, which
+                                 counts as black, with its pixel and, for the path
+                                 tracer, the bounce and material it went wrong at
+  --debug-pixel <x,y>            trace the first few samples of the pixel in column x and
+                                 row y from the top left, as the render would, and print
+                                 each bounce's hit, emission, pdfs and throughput instead
+                                 of an image
+  --sppm                         render by stochastic progressive photon mapping, one
+                                 iteration per --spp, so caustics resolve quickly
+  --sppm-photons <n>             photons traced per iteration (default 100000)
+  --sppm-radius <r>              initial gather radius (default 0.5% of the scene's
+                                 bounding box diagonal)
+  --sppm-alpha <a>               share of photons each iteration keeps, in (0, 1]
+                                 (default 0.667)
+  --guide                        learn a path guiding distribution before rendering
+  --guide-passes <n>             guide training passes (default 3)
+  --guide-spp <samples>          samples per pixel of the first training pass, doubling
+                                 each pass (default 4)
+  --guide-max-weight <w>         largest guided share of the sampling mixture (default 0.5)
+  --guide-confidence <records>   records after which a cell reaches half its guided
+                                 share (default 64)
+  --sensor-iso <iso>             simulate sensor noise at the given ISO
+  --sensor-read-noise <e->       read noise in electrons (default 3)
+  --sensor-hot-pixels <fraction> fraction of hot pixels (default 0.0001)
+  --sensor-black-level <level>   black level offset (default 0.002)
+  --sensor-seed <seed>           seed for the sensor noise pattern (default 0)
+  --dataset <count>              render <count> labeled random scenes with COCO annotations
+  --dataset-dir <dir>            output directory for the dataset (default dataset)
+  --dataset-seed <seed>          seed for the first dataset scene (default 0)
+  --reference <scene>:<file>     render <scene>, a gallery scene or a scene file such as
+                                 a .pbrt file, at the size of the reference image <file>
+                                 (EXR from another renderer, or a PFM) and report the
+                                 error, FLIP included
+  --reference-dir <dir>          output directory for the renders, error images and
+                                 report (default reference)
+  --tiles <start>:<end>          render only the tiles from start up to but not including
+                                 end, numbered row by row from the top left, and write
+                                 their pixels as a partial buffer for --merge; workers
+                                 given the same options render parts of one image
+  --crop <x0,y0,x1,y1>           render only the window of columns x0 up to x1 and rows
+                                 y0 up to y1 from the top left, each pixel as the whole
+                                 image would have it, and write the window as its own
+                                 image
+  --tile-size <pixels>           side of a tile (default 32)
+  --tile-count                   print how many tiles the image has and exit
+  --merge <part>                 combine the partial buffers of every tile into the image,
+                                 once per part, applying the sensor and writing the heatmap
+  --help                         print this message
+
+rtmerge averages raw framebuffers rendered with different --seed values, weighted by the
+samples each records, into one image, raw unless --format says otherwise, so a long
+render can be split into batches across invocations or machines.
+
+patch pastes the raw framebuffer of a --crop render, say one of a noisy region at more
+--spp, over the same window of a raw framebuffer of the whole image, writing raw unless
+--format says otherwise.
+
+Ctrl-C or SIGTERM stops a path traced render early and writes what it has, pixels not yet
+started black, raw dumps recording the samples taken on average; a second one quits at
+once.";
+
+// Which renderer runs: the CPU integrator, or the experimental wavefront path
+// tracer in gpu.rs for --scene-file scenes.
+#[derive(Clone, Copy, Default, PartialEq)]
+pub enum Backend {
+    #[default]
+    CPU,
+    GPU,
+}
+
+impl FromStr for Backend {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "cpu" => Ok(Backend::CPU),
+            "gpu" => Ok(Backend::GPU),
+            _ => Err(format!("unknown backend: {}", s)),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Default, PartialEq)]
+pub enum Accelerator {
+    #[default]
+    BVH,
+    QBVH,
+    KdTree,
+}
+
+impl FromStr for Accelerator {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "bvh" => Ok(Accelerator::BVH),
+            "qbvh" => Ok(Accelerator::QBVH),
+            "kdtree" => Ok(Accelerator::KdTree),
+            _ => Err(format!("unknown acceleration structure: {}", s)),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct Options {
+    pub scene: String,
+    pub scene_file: Option<String>,
+    pub list_scenes: bool,
+    pub backend: Backend,
+    pub width: usize,
+    pub height: usize,
+    pub spp: usize,
+    pub adaptive: Option<Adaptive>,
+    pub heatmap: Option<String>,
+    pub format: OutputFormat,
+    pub tone: ToneMapping,
+    pub aov: Option<String>,
+    pub rng: RngBackend,
+    pub seed: u64,
+    pub estimator: Estimator,
+    pub bvh: BuildStrategy,
+    pub accel: Accelerator,
+    pub kd_max_depth: Option<usize>,
+    pub kd_leaf_size: usize,
+    pub cutoff: ThroughputCutoff,
+    pub bounces: BounceLimits,
+    pub shutter: Shutter,
+    pub projection: Projection,
+    pub fisheye_fov: Float,
+    pub aperture: Aperture,
+    pub lens: Option<Lens>,
+    pub autofocus: Option<(Float, Float)>,
+    pub medium_g: Float,
+    pub clamp: Option<Float>,
+    pub reject_sigma: Option<Float>,
+    pub mnee: bool,
+    pub light_links: Vec<(u32, Vec<u32>)>,
+    pub shadow_links: Vec<(u32, Vec<u32>)>,
+    pub guide: Option<GuideSchedule>,
+    pub cone: bool,
+    pub spectral: bool,
+    pub integrator: IntegratorKind,
+    pub ao_distance: Option<Float>,
+    pub packets: bool,
+    pub wavefront: bool,
+    pub debug_nan: bool,
+    pub debug_pixel: Option<(usize, usize)>,
+    pub sppm: Option<PhotonMapping>,
+    pub preview: bool,
+    pub sensor: Option<SensorNoise>,
+    pub dataset: Option<Dataset>,
+    pub animation: Option<Animation>,
+    pub references: Vec<(String, String)>,
+    pub reference_dir: String,
+    pub tiles: Option<TileRange>,
+    pub tile_count: bool,
+    pub merge: Vec<String>,
+    pub rtmerge: Option<Vec<String>>,
+    pub crop: Option<Crop>,
+    pub patch: Option<Vec<String>>,
+    // the command line, as images record it, see metadata.rs
+    pub command: Vec<String>,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Options {
+            scene: String::from("cornell_box"),
+            scene_file: None,
+            list_scenes: false,
+            backend: Backend::default(),
+            width: 500,
+            height: 500,
+            spp: 1000,
+            adaptive: None,
+            heatmap: None,
+            format: OutputFormat::default(),
+            tone: ToneMapping::default(),
+            aov: None,
+            rng: RngBackend::default(),
+            seed: 0,
+            estimator: Estimator::default(),
+            bvh: BuildStrategy::default(),
+            accel: Accelerator::default(),
+            kd_max_depth: None,
+            kd_leaf_size: 1,
+            cutoff: ThroughputCutoff::default(),
+            bounces: BounceLimits::default(),
+            shutter: Shutter::default(),
+            projection: Projection::default(),
+            fisheye_fov: 180.0,
+            aperture: Aperture::default(),
+            lens: None,
+            autofocus: None,
+            medium_g: 0.0,
+            clamp: None,
+            reject_sigma: None,
+            mnee: false,
+            light_links: Vec::new(),
+            shadow_links: Vec::new(),
+            guide: None,
+            cone: false,
+            spectral: false,
+            integrator: IntegratorKind::default(),
+            ao_distance: None,
+            packets: false,
+            wavefront: false,
+            debug_nan: false,
+            debug_pixel: None,
+            sppm: None,
+            preview: false,
+            sensor: None,
+            dataset: None,
+            animation: None,
+            references: Vec::new(),
+            reference_dir: String::from("reference"),
+            tiles: None,
+            tile_count: false,
+            merge: Vec::new(),
+            rtmerge: None,
+            crop: None,
+            patch: None,
+            command: Vec::new(),
+        }
+    }
+}
+
+fn value<T: FromStr>(args: &mut impl Iterator<Item = String>, flag: &str) -> Result<T, String> {
+    let arg = args
+        .next()
+        .ok_or_else(|| format!("missing value for {}", flag))?;
+    arg.parse()
+        .map_err(|_| format!("invalid value for {}: {}", flag, arg))
+}
+
+// parses <open>:<close> with open no later than close
+fn interval(args: &mut impl Iterator<Item = String>, flag: &str) -> Result<(Float, Float), String> {
+    let arg: String = value(args, flag)?;
+    let invalid = || format!("invalid value for {}: {}", flag, arg);
+    let (open, close) = arg.split_once(':').ok_or_else(invalid)?;
+    let open: Float = open.parse().map_err(|_| invalid())?;
+    let close: Float = close.parse().map_err(|_| invalid())?;
+    if open.is_nan() || close.is_nan() || open > close {
+        return Err(invalid());
+    }
+    Ok((open, close))
+}
+
+// parses <x>:<y> with both in [0, 1]
+fn frame_point(
+    args: &mut impl Iterator<Item = String>,
+    flag: &str,
+) -> Result<(Float, Float), String> {
+    let arg: String = value(args, flag)?;
+    let invalid = || format!("invalid value for {}: {}", flag, arg);
+    let (x, y) = arg.split_once(':').ok_or_else(invalid)?;
+    let x: Float = x.parse().map_err(|_| invalid())?;
+    let y: Float = y.parse().map_err(|_| invalid())?;
+    if !(0.0..=1.0).contains(&x) || !(0.0..=1.0).contains(&y) {
+        return Err(invalid());
+    }
+    Ok((x, y))
+}
+
+// parses <light>:<id>,<id>,...
+fn link(args: &mut impl Iterator<Item = String>, flag: &str) -> Result<(u32, Vec<u32>), String> {
+    let arg: String = value(args, flag)?;
+    let invalid = || format!("invalid value for {}: {}", flag, arg);
+    let (light, objects) = arg.split_once(':').ok_or_else(invalid)?;
+    let light = light.parse().map_err(|_| invalid())?;
+    let objects = objects
+        .split(',')
+        .map(|id| id.parse().map_err(|_| invalid()))
+        .collect::<Result<Vec<u32>, String>>()?;
+    Ok((light, objects))
+}
+
+impl Options {
+    pub fn parse() -> Result<Self, String> {
+        let command: Vec<String> = std::env::args().collect();
+        let options = Options::from_args(command.iter().skip(1).cloned())?;
+        Ok(Options { command, ..options })
+    }
+
+    pub fn from_args(args: impl Iterator<Item = String>) -> Result<Self, String> {
+        let mut options = Options::default();
+        let mut args = args.peekable();
+        if args.next_if(|arg| arg == "rtmerge").is_some() {
+            options.rtmerge = Some(Vec::new());
+            options.format = OutputFormat::Raw;
+        } else if args.next_if(|arg| arg == "patch").is_some() {
+            options.patch = Some(Vec::new());
+            options.format = OutputFormat::Raw;
+        }
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--scene" => options.scene = value(&mut args, &arg)?,
+                "--scene-file" => options.scene_file = Some(value(&mut args, &arg)?),
+                "--shutter" => {
+                    let (open, close) = interval(&mut args, &arg)?;
+                    options.shutter.open = open;
+                    options.shutter.close = close;
+                }
+                "--no-motion-blur" => options.shutter.motion_blur = false,
+                "--frames" => options.animation_mut().frames = value(&mut args, &arg)?,
+                "--fps" => options.animation_mut().fps = value(&mut args, &arg)?,
+                "--shutter-angle" => {
+                    options.animation_mut().shutter_angle = value(&mut args, &arg)?
+                }
+                "--frames-dir" => options.animation_mut().dir = value(&mut args, &arg)?,
+                "--video" => options.animation_mut().video = Some(value(&mut args, &arg)?),
+                "--camera" => options.projection = value(&mut args, &arg)?,
+                "--fisheye-fov" => options.fisheye_fov = value(&mut args, &arg)?,
+                "--aperture-blades" => options.aperture.blades = value(&mut args, &arg)?,
+                "--aperture-rotation" => options.aperture.rotation = value(&mut args, &arg)?,
+                "--medium-g" => options.medium_g = value(&mut args, &arg)?,
+                "--autofocus" => options.autofocus = Some(frame_point(&mut args, &arg)?),
+                "--lens" => {
+                    let path: String = value(&mut args, &arg)?;
+                    options.lens_mut().elements = lens::read(&path)?;
+                }
+                "--lens-film" => options.lens_mut().film_diagonal = value(&mut args, &arg)?,
+                "--lens-scale" => options.lens_mut().scale = value(&mut args, &arg)?,
+                "--lens-focus" => options.lens_mut().focus = Some(value(&mut args, &arg)?),
+                "--cat-eye" => options.aperture.cat_eye = value(&mut args, &arg)?,
+                "--list-scenes" => options.list_scenes = true,
+                "--backend" => options.backend = value(&mut args, &arg)?,
+                "--width" => options.width = value(&mut args, &arg)?,
+                "--height" => options.height = value(&mut args, &arg)?,
+                "--spp" => options.spp = value(&mut args, &arg)?,
+                "--format" => options.format = value(&mut args, &arg)?,
+                "--tonemap" => options.tone.operator = value::<ToneMap>(&mut args, &arg)?,
+                "--exposure" => options.tone.exposure = value(&mut args, &arg)?,
+                "--white-point" => options.tone.white = Some(value(&mut args, &arg)?),
+                "--gamma" => options.tone.transfer = Transfer::Gamma(value(&mut args, &arg)?),
+                "--legacy-gamma" => options.tone.transfer = Transfer::Gamma(2.0),
+                "--lut" => {
+                    let path: String = value(&mut args, &arg)?;
+                    options.tone.lut = Some(Arc::new(lut::read(&path)?));
+                }
+                "--aov" => options.aov = Some(value(&mut args, &arg)?),
+                "--rng" => options.rng = value(&mut args, &arg)?,
+                "--seed" => options.seed = value(&mut args, &arg)?,
+                "--adaptive" => options.adaptive_mut().threshold = value(&mut args, &arg)?,
+                "--adaptive-min-spp" => options.adaptive_mut().min_spp = value(&mut args, &arg)?,
+                "--heatmap" => options.heatmap = Some(value(&mut args, &arg)?),
+                "--median-of-means" => options.estimator = value(&mut args, &arg)?,
+                "--clamp" => options.clamp = Some(value(&mut args, &arg)?),
+                "--reject-outliers" => options.reject_sigma = Some(value(&mut args, &arg)?),
+                "--bvh" => options.bvh = value(&mut args, &arg)?,
+                "--accel" => options.accel = value(&mut args, &arg)?,
+                "--kd-max-depth" => options.kd_max_depth = Some(value(&mut args, &arg)?),
+                "--kd-leaf-size" => options.kd_leaf_size = value(&mut args, &arg)?,
+                "--min-throughput" => options.cutoff.min_throughput = value(&mut args, &arg)?,
+                "--throughput-roulette" => options.cutoff.probabilistic = true,
+                "--max-diffuse" => options.bounces.diffuse = value(&mut args, &arg)?,
+                "--max-glossy" => options.bounces.glossy = value(&mut args, &arg)?,
+                "--max-transmission" => options.bounces.transmission = value(&mut args, &arg)?,
+                "--mnee" => options.mnee = true,
+                "--light-link" => options.light_links.push(link(&mut args, &arg)?),
+                "--shadow-link" => options.shadow_links.push(link(&mut args, &arg)?),
+                "--cone-preview" => options.cone = true,
+                "--spectral" => options.spectral = true,
+                "--preview" => options.preview = true,
+                "--integrator" => options.integrator = value(&mut args, &arg)?,
+                "--ao-distance" => options.ao_distance = Some(value(&mut args, &arg)?),
+                "--packets" => options.packets = true,
+                "--wavefront" => options.wavefront = true,
+                "--debug-nan" => options.debug_nan = true,
+                "--debug-pixel" => {
+                    let arg: String = value(&mut args, &arg)?;
+                    let invalid = || format!("invalid value for --debug-pixel: {}", arg);
+                    let (x, row) = arg.split_once(',').ok_or_else(invalid)?;
+                    options.debug_pixel = Some((
+                        x.trim().parse().map_err(|_| invalid())?,
+                        row.trim().parse().map_err(|_| invalid())?,
+                    ));
+                }
+                "--sppm" => {
+                    options.sppm_mut();
+                }
+                "--sppm-photons" => options.sppm_mut().photons = value(&mut args, &arg)?,
+                "--sppm-radius" => options.sppm_mut().radius = Some(value(&mut args, &arg)?),
+                "--sppm-alpha" => options.sppm_mut().alpha = value(&mut args, &arg)?,
+                "--guide" => {
+                    options.guide_mut();
+                }
+                "--guide-passes" => options.guide_mut().passes = value(&mut args, &arg)?,
+                "--guide-spp" => options.guide_mut().spp = value(&mut args, &arg)?,
+                "--guide-max-weight" => options.guide_mut().max_weight = value(&mut args, &arg)?,
+                "--guide-confidence" => options.guide_mut().confidence = value(&mut args, &arg)?,
+                "--sensor-iso" => options.sensor_mut().iso = value(&mut args, &arg)?,
+                "--sensor-read-noise" => options.sensor_mut().read_noise = value(&mut args, &arg)?,
+                "--sensor-hot-pixels" => {
+                    options.sensor_mut().hot_pixel_fraction = value(&mut args, &arg)?
+                }
+                "--sensor-black-level" => {
+                    options.sensor_mut().black_level = value(&mut args, &arg)?
+                }
+                "--sensor-seed" => options.sensor_mut().seed = value(&mut args, &arg)?,
+                "--dataset" => options.dataset_mut().count = value(&mut args, &arg)?,
+                "--dataset-dir" => options.dataset_mut().dir = value(&mut args, &arg)?,
+                "--dataset-seed" => options.dataset_mut().seed = value(&mut args, &arg)?,
+                "--reference" => {
+                    let reference: String = value(&mut args, &arg)?;
+                    let (scene, path) = reference
+                        .split_once(':')
+                        .ok_or_else(|| format!("invalid value for {}: {}", arg, reference))?;
+                    if !scene::SCENES.contains(&scene) && !reference::is_scene_file(scene) {
+                        return Err(format!("unknown scene: {}", scene));
+                    }
+                    options
+                        .references
+                        .push((scene.to_string(), path.to_string()));
+                }
+                "--reference-dir" => options.reference_dir = value(&mut args, &arg)?,
+                "--tiles" => {
+                    let arg: String = value(&mut args, &arg)?;
+                    let invalid = || format!("invalid value for --tiles: {}", arg);
+                    let (start, end) = arg.split_once(':').ok_or_else(invalid)?;
+                    options.tiles_mut().start = start.parse().map_err(|_| invalid())?;
+                    options.tiles_mut().end = end.parse().map_err(|_| invalid())?;
+                }
+                "--crop" => options.crop = Some(value(&mut args, &arg)?),
+                "--tile-size" => options.tiles_mut().size = value(&mut args, &arg)?,
+                "--tile-count" => options.tile_count = true,
+                "--merge" => options.merge.push(value(&mut args, &arg)?),
+                "--help" | "-h" => return Err(String::new()),
+                _ if !arg.starts_with('-') && options.rtmerge.is_some() => {
+                    options.rtmerge.as_mut().unwrap().push(arg)
+                }
+                _ if !arg.starts_with('-') && options.patch.is_some() => {
+                    options.patch.as_mut().unwrap().push(arg)
+                }
+                _ => return Err(format!("unknown option: {}", arg)),
+            }
+        }
+        if !scene::SCENES.contains(&options.scene.as_str()) {
+            return Err(format!("unknown scene: {}", options.scene));
+        }
+        if options.backend == Backend::GPU && options.scene_file.is_none() {
+            return Err(String::from(
+                "the gpu backend renders --scene-file scenes only",
+            ));
+        }
+        if options.backend == Backend::GPU
+            && options.scene_file.as_deref().is_some_and(gltf::is_gltf)
+        {
+            return Err(String::from("the gpu backend does not render glTF files"));
+        }
+        if options.backend == Backend::GPU
+            && (options.projection != Projection::Perspective
+                || options.aperture != Aperture::default()
+                || options.lens.is_some())
+        {
+            return Err(String::from(
+                "the gpu backend renders thin lens perspective cameras with round apertures only",
+            ));
+        }
+        if options.spectral && (options.cone || options.backend == Backend::GPU) {
+            return Err(String::from(
+                "--spectral needs the cpu path tracer, not --cone-preview or the gpu backend",
+            ));
+        }
+        if options.integrator != IntegratorKind::Path
+            && (options.cone
+                || options.backend == Backend::GPU
+                || options.sppm.is_some()
+                || options.guide.is_some()
+                || options.mnee
+                || !options.light_links.is_empty()
+                || !options.shadow_links.is_empty())
+        {
+            return Err(String::from(
+                "--integrator other than path cannot combine with --cone-preview, the gpu \
+                 backend, sppm, guiding, mnee or light links",
+            ));
+        }
+        if options.packets
+            && (options.cone || options.backend == Backend::GPU || options.sppm.is_some())
+        {
+            return Err(String::from(
+                "--packets needs the cpu path tracer, not --cone-preview, sppm or the gpu backend",
+            ));
+        }
+        if options.wavefront
+            && (options.integrator != IntegratorKind::Path
+                || options.cone
+                || options.backend == Backend::GPU
+                || options.sppm.is_some()
+                || options.guide.is_some()
+                || options.adaptive.is_some()
+                || options.packets
+                || options.mnee
+                || !options.light_links.is_empty()
+                || !options.shadow_links.is_empty())
+        {
+            return Err(String::from(
+                "--wavefront runs the cpu path tracer, so it cannot combine with other \
+                 integrators, --cone-preview, the gpu backend, sppm, guiding, adaptive \
+                 sampling, packets, mnee or light links",
+            ));
+        }
+        if let Some(distance) = options.ao_distance {
+            if options.integrator != IntegratorKind::AO {
+                return Err(String::from("--ao-distance needs --integrator ao"));
+            }
+            if distance.is_nan() || distance <= 0.0 {
+                return Err(String::from("--ao-distance must be positive"));
+            }
+        }
+        if let Some(sppm) = &options.sppm {
+            if options.cone
+                || options.spectral
+                || options.preview
+                || options.backend == Backend::GPU
+                || options.guide.is_some()
+                || options.adaptive.is_some()
+                || options.mnee
+                || !options.light_links.is_empty()
+                || !options.shadow_links.is_empty()
+            {
+                return Err(String::from(
+                    "--sppm replaces the path tracer, so it cannot combine with previews, \
+                     spectral rendering, the gpu backend, guiding, adaptive sampling, mnee or \
+                     light links",
+                ));
+            }
+            if sppm.photons == 0
+                || sppm.radius.is_some_and(|r| !(r > 0.0 && r.is_finite()))
+                || !(sppm.alpha > 0.0 && sppm.alpha <= 1.0)
+            {
+                return Err(String::from(
+                    "sppm photons and radius must be positive and alpha in (0, 1]",
+                ));
+            }
+        }
+        if let Some(lens) = &options.lens {
+            if lens.elements.is_empty() {
+                return Err(String::from(
+                    "--lens-film, --lens-scale and --lens-focus need --lens",
+                ));
+            }
+            if options.projection != Projection::Perspective {
+                return Err(String::from("a lens replaces the camera model"));
+            }
+            if [Some(lens.film_diagonal), Some(lens.scale), lens.focus]
+                .iter()
+                .flatten()
+                .any(|v| !(*v > 0.0 && v.is_finite()))
+            {
+                return Err(String::from("lens film, scale and focus must be positive"));
+            }
+        }
+        if !(options.medium_g > -1.0 && options.medium_g < 1.0) {
+            return Err(String::from("medium g must be in (-1, 1)"));
+        }
+        if options.aperture.blades > 0 && options.aperture.blades < 3 {
+            return Err(String::from("an aperture needs at least 3 blades"));
+        }
+        if !options.aperture.rotation.is_finite()
+            || !(0.0..=1.0).contains(&options.aperture.cat_eye)
+        {
+            return Err(String::from(
+                "aperture rotation must be finite and cat's eye in [0, 1]",
+            ));
+        }
+        if !(options.fisheye_fov > 0.0 && options.fisheye_fov <= 360.0) {
+            return Err(String::from("fisheye fov must be in (0, 360]"));
+        }
+        if options.width == 0 || options.height == 0 || options.spp == 0 {
+            return Err(String::from("width, height and spp must be positive"));
+        }
+        if !options.tone.exposure.is_finite()
+            || options
+                .tone
+                .white
+                .is_some_and(|w| !(w > 0.0 && w.is_finite()))
+        {
+            return Err(String::from(
+                "exposure must be finite and the white point positive",
+            ));
+        }
+        if let Transfer::Gamma(gamma) = options.tone.transfer {
+            if !(gamma > 0.0 && gamma.is_finite()) {
+                return Err(String::from("gamma must be positive"));
+            }
+        }
+        if options
+            .adaptive
+            .is_some_and(|a| a.threshold.is_nan() || a.threshold <= 0.0)
+        {
+            return Err(String::from("adaptive threshold must be positive"));
+        }
+        if options
+            .sensor
+            .as_ref()
+            .is_some_and(|sensor| !(sensor.iso > 0.0 && sensor.iso.is_finite()))
+        {
+            return Err(String::from("sensor iso must be positive"));
+        }
+        if [options.clamp, options.reject_sigma]
+            .iter()
+            .flatten()
+            .any(|v| v.is_nan() || *v <= 0.0)
+        {
+            return Err(String::from("clamp and outlier sigma must be positive"));
+        }
+        if let Some(animation) = &options.animation {
+            if animation.frames == 0 || !(animation.fps > 0.0 && animation.fps.is_finite()) {
+                return Err(String::from("frames and fps must be positive"));
+            }
+            if !(animation.shutter_angle > 0.0 && animation.shutter_angle <= 360.0) {
+                return Err(String::from("shutter angle must be in (0, 360]"));
+            }
+            if options.preview || options.dataset.is_some() || !options.references.is_empty() {
+                return Err(String::from(
+                    "--frames renders on its own, not with --preview, --dataset or --reference",
+                ));
+            }
+        }
+        if options
+            .rtmerge
+            .as_ref()
+            .is_some_and(|paths| paths.is_empty())
+        {
+            return Err(String::from("rtmerge needs the raw framebuffers to merge"));
+        }
+        if let Some(paths) = &options.patch {
+            if paths.len() != 2 || options.crop.is_none() {
+                return Err(String::from(
+                    "patch needs the whole image, the patch and the --crop it was rendered at",
+                ));
+            }
+        } else if let Some(crop) = &options.crop {
+            if !crop.fits(options.width, options.height) {
+                return Err(format!(
+                    "--crop {} is outside the {}x{} image",
+                    crop, options.width, options.height
+                ));
+            }
+            if options.backend == Backend::GPU
+                || options.preview
+                || options.aov.is_some()
+                || options.animation.is_some()
+                || options.dataset.is_some()
+                || !options.references.is_empty()
+                || options.tiles.is_some()
+                || !options.merge.is_empty()
+            {
+                return Err(String::from(
+                    "--crop renders on the cpu on its own, not with the gpu backend, \
+                     --preview, --aov, --frames, --dataset, --reference, --tiles or --merge",
+                ));
+            }
+        }
+        if let Some((x, row)) = options.debug_pixel {
+            if x >= options.width || row >= options.height {
+                return Err(format!(
+                    "--debug-pixel {},{} is outside the {}x{} image",
+                    x, row, options.width, options.height
+                ));
+            }
+            if options.backend == Backend::GPU
+                || !matches!(
+                    options.integrator,
+                    IntegratorKind::Path | IntegratorKind::Clay
+                )
+                || options.cone
+                || options.sppm.is_some()
+                || options.wavefront
+                || options.guide.is_some()
+                || options.preview
+                || options.animation.is_some()
+                || options.dataset.is_some()
+                || !options.references.is_empty()
+                || options.tiles.is_some()
+                || !options.merge.is_empty()
+                || options.crop.is_some()
+            {
+                return Err(String::from(
+                    "--debug-pixel logs the cpu path tracer on its own, not with the gpu \
+                     backend, other integrators, --cone-preview, --sppm, --wavefront, \
+                     guiding, --preview, --frames, --dataset, --reference, --tiles, --merge \
+                     or --crop",
+                ));
+            }
+        }
+        if let Some(tiles) = &options.tiles {
+            if tiles.size == 0 {
+                return Err(String::from("tile size must be positive"));
+            }
+            let count = tiles.count(options.width, options.height);
+            if !options.tile_count && (tiles.start >= tiles.end || tiles.end > count) {
+                return Err(format!(
+                    "--tiles must name a non-empty range of the image's {} tiles",
+                    count
+                ));
+            }
+            if options.backend == Backend::GPU
+                || options.cone
+                || options.sppm.is_some()
+                || options.preview
+                || options.aov.is_some()
+                || options.heatmap.is_some()
+                || options.animation.is_some()
+                || options.dataset.is_some()
+                || !options.references.is_empty()
+                || !options.merge.is_empty()
+            {
+                return Err(String::from(
+                    "--tiles renders with the cpu path tracer on its own, not with the gpu \
+                     backend, --cone-preview, --sppm, --preview, --aov, --heatmap, --frames, \
+                     --dataset, --reference or --merge",
+                ));
+            }
+        }
+        if let Some(guide) = &options.guide {
+            if guide.spp == 0 {
+                return Err(String::from("guide spp must be positive"));
+            }
+            if !(0.0..1.0).contains(&guide.max_weight)
+                || guide.confidence.is_nan()
+                || guide.confidence < 0.0
+            {
+                return Err(String::from(
+                    "guide max weight must be in [0, 1) and confidence non-negative",
+                ));
+            }
+        }
+        Ok(options)
+    }
+
+    pub fn settings(&self) -> RenderSettings {
+        RenderSettings {
+            width: self.width,
+            height: self.height,
+            spp: self.spp,
+            adaptive: self.adaptive,
+            estimator: self.estimator,
+            cutoff: self.cutoff,
+            bounces: self.bounces,
+            clamp: self.clamp,
+            reject_sigma: self.reject_sigma,
+            guide: self.guide,
+            cone: self.cone,
+            spectral: self.spectral,
+            integrator: self.integrator,
+            ao_distance: self.ao_distance,
+            packets: self.packets,
+            wavefront: self.wavefront,
+            sppm: self.sppm,
+            sensor: self.sensor.clone(),
+            rng: self.rng,
+            seed: self.seed,
+            crop: self.crop,
+        }
+    }
+
+    fn adaptive_mut(&mut self) -> &mut Adaptive {
+        self.adaptive.get_or_insert_with(Adaptive::default)
+    }
+
+    fn lens_mut(&mut self) -> &mut Lens {
+        self.lens.get_or_insert_with(Lens::default)
+    }
+
+    fn sensor_mut(&mut self) -> &mut SensorNoise {
+        self.sensor.get_or_insert_with(|| SensorNoise::new(100.0))
+    }
+
+    fn guide_mut(&mut self) -> &mut GuideSchedule {
+        self.guide.get_or_insert_with(GuideSchedule::default)
+    }
+
+    fn sppm_mut(&mut self) -> &mut PhotonMapping {
+        self.sppm.get_or_insert_with(PhotonMapping::default)
+    }
+
+    fn dataset_mut(&mut self) -> &mut Dataset {
+        self.dataset.get_or_insert_with(|| Dataset::new(1))
+    }
+
+    fn tiles_mut(&mut self) -> &mut TileRange {
+        self.tiles.get_or_insert_with(TileRange::default)
+    }
+
+    fn animation_mut(&mut self) -> &mut Animation {
+        self.animation.get_or_insert_with(|| Animation::new(1))
+    }
+}
@@ -0,0 +1,287 @@
+use crate::aabb::AABB;
+use crate::float::{self, Float};
+use crate::hittable::{HitRecord, Hittable};
+use crate::material::{reflect, ScatterRecord};
+use crate::parallel::*;
+use crate::ray::{self, Ray};
+use crate::render::RenderSettings;
+use crate::rng;
+use crate::scene::Scene;
+use nalgebra::Vector3;
+
+// specular bounces a preview path follows before it is cut
+const MAX_DEPTH: usize = 8;
+// occlusion past which a shadow cone counts as blocked
+pub const OPAQUE: Float = 0.999;
+
+// A ray with a footprint: the cross section around the unit direction has radius
+// `radius` at the origin and widens by `spread` per unit distance.
+#[derive(Clone, Copy)]
+pub struct Cone {
+    pub origin: Vector3<Float>,
+    pub direction: Vector3<Float>,
+    pub radius: Float,
+    pub spread: Float,
+}
+
+// occlusion of two occluders along the same cone, taken as independent
+pub fn combine(a: Float, b: Float) -> Float {
+    1.0 - (1.0 - a) * (1.0 - b)
+}
+
+impl Cone {
+    pub fn new(
+        origin: Vector3<Float>,
+        direction: Vector3<Float>,
+        radius: Float,
+        spread: Float,
+    ) -> Self {
+        Cone {
+            origin,
+            direction: direction.normalize(),
+            radius,
+            spread,
+        }
+    }
+
+    pub fn width(&self, t: Float) -> Float {
+        (self.radius + self.spread * t).max(0.0)
+    }
+
+    pub fn at(&self, t: Float) -> Vector3<Float> {
+        self.origin + t * self.direction
+    }
+
+    pub fn axis(&self) -> Ray {
+        Ray::new(self.origin, self.direction, 0.0)
+    }
+
+    // the same cone in a frame where points map through `point` and directions
+    // through `direction`, both rigid
+    pub fn transform(
+        &self,
+        point: impl Fn(&Vector3<Float>) -> Vector3<Float>,
+        direction: impl Fn(&Vector3<Float>) -> Vector3<Float>,
+    ) -> Cone {
+        Cone {
+            origin: point(&self.origin),
+            direction: direction(&self.direction),
+            ..*self
+        }
+    }
+
+    // Conservative cull: the box grown by the widest footprint along [t_min, t_max]
+    // against the axis.
+    pub fn overlaps(&self, bbox: &AABB, t_min: Float, t_max: Float) -> bool {
+        let w = Vector3::repeat(self.width(t_min).max(self.width(t_max)));
+        AABB::new(bbox.min - w, bbox.max + w).hit(&self.axis(), t_min, t_max)
+    }
+
+    // Share of the footprint at t covered by an occluder whose signed distance from
+    // the axis there is `distance`; half covered when the axis grazes its edge.
+    pub fn coverage(&self, distance: Float, t: Float) -> Float {
+        let w = self.width(t);
+        if w <= 0.0 {
+            return if distance < 0.0 { 1.0 } else { 0.0 };
+        }
+        (0.5 - distance / (2.0 * w)).clamp(0.0, 1.0)
+    }
+
+    // Coverage of a sphere, measured where the axis passes its centre. Spheres centred
+    // outside [t_min, t_max] do not occlude, which keeps a light from shadowing itself.
+    pub fn sphere_coverage(
+        &self,
+        center: &Vector3<Float>,
+        radius: Float,
+        t_min: Float,
+        t_max: Float,
+    ) -> Float {
+        let t = (center - self.origin).dot(&self.direction);
+        if t < t_min || t > t_max {
+            return 0.0;
+        }
+        self.coverage((center - self.at(t)).norm() - radius, t)
+    }
+
+    // Coverage of a box, measured where the axis passes its centre like a sphere.
+    pub fn box_coverage(&self, bbox: &AABB, t_min: Float, t_max: Float) -> Float {
+        let center = 0.5 * (bbox.min + bbox.max);
+        let t = (center - self.origin).dot(&self.direction);
+        if t < t_min || t > t_max {
+            return 0.0;
+        }
+        self.coverage(box_distance(&self.at(t), bbox), t)
+    }
+}
+
+// signed distance from p to the box, negative inside
+fn box_distance(p: &Vector3<Float>, bbox: &AABB) -> Float {
+    let half = 0.5 * (bbox.max - bbox.min);
+    let q = (p - 0.5 * (bbox.min + bbox.max)).abs() - half;
+    q.map(|c| c.max(0.0)).norm() + q.max().min(0.0)
+}
+
+// Direct light from one emitter at the hit of a shading cone. The light is stood
+// in for by the disk of its bounding box's largest face: a shadow cone narrows or widens from the
+// incoming footprint to that disk, so penumbrae soften with the light's size and
+// with the blur of the glossy bounces before.
+fn direct(
+    scene: &Scene,
+    light: &dyn Hittable,
+    cone: &Cone,
+    ray: &Ray,
+    hit: &HitRecord,
+    scattering_pdf: impl Fn(&Ray) -> Float,
+) -> Vector3<Float> {
+    let bbox = match light.bounding_box(0.0, 1.0) {
+        Some(bbox) => bbox,
+        None => return Vector3::zeros(),
+    };
+    let center = 0.5 * (bbox.min + bbox.max);
+    let size = bbox.max - bbox.min;
+    let mut extent = [size.x, size.y, size.z];
+    extent.sort_by(|a, b| b.partial_cmp(a).unwrap());
+    let light_radius = (extent[0] * extent[1] / float::consts::PI).sqrt();
+
+    let to_light = center - hit.p;
+    let distance = to_light.norm();
+    let shadow_ray = hit.spawn(to_light, ray.time());
+    let emitted = match light.hit(&shadow_ray, ray::T_MIN, Float::MAX) {
+        Some(light_hit) => light_hit.material.emitted(&shadow_ray, &light_hit),
+        None => return Vector3::zeros(),
+    };
+    let pdf = light.pdf_value(hit.p, to_light);
+    if pdf <= 0.0 {
+        return Vector3::zeros();
+    }
+    let footprint = cone.width(hit.t);
+    let shadow = Cone::new(
+        shadow_ray.origin(),
+        to_light,
+        footprint,
+        (light_radius - footprint) / distance,
+    );
+    let occlusion = scene
+        .world
+        .occlusion(&shadow, ray::T_MIN, distance * (1.0 - 1e-3));
+    emitted * scattering_pdf(&shadow_ray) * (1.0 - occlusion) / pdf
+}
+
+// Follows the axis of a cone through specular bounces, gathering emission and the
+// shadowed direct light at the first diffuse surface. The background above the
+// surface stands in for all indirect light there, as an unoccluded ambient term.
+fn shade(scene: &Scene, cone: &Cone, time: Float, depth: usize) -> Vector3<Float> {
+    let ray = Ray::new(cone.origin, cone.direction, time);
+    let hit = match scene.world.hit(&ray, ray::T_MIN, Float::MAX) {
+        Some(hit) => hit,
+        None => return scene.background.radiance(&cone.direction),
+    };
+    let hit = hit.material.exit(&ray, hit);
+    let emitted = hit.material.emitted(&ray, &hit);
+    if depth >= MAX_DEPTH {
+        return emitted;
+    }
+    match hit.material.scatter(&ray, &hit) {
+        Some(ScatterRecord::Specular {
+            specular_ray,
+            attenuation,
+        }) => {
+            // a rough reflection keeps to the mirror direction and widens instead
+            let roughness = hit.material.roughness();
+            let direction = if roughness > 0.0 {
+                reflect(&cone.direction, &hit.normal)
+            } else {
+                specular_ray.direction()
+            };
+            let origin = ray::offset_origin(hit.p, hit.normal, direction);
+            let reflected = Cone::new(
+                origin,
+                direction,
+                cone.width(hit.t),
+                cone.spread + roughness,
+            );
+            emitted + attenuation.component_mul(&shade(scene, &reflected, time, depth + 1))
+        }
+        Some(ScatterRecord::Scatter { attenuation, .. }) => {
+            let light = scene
+                .lights
+                .iter()
+                .map(|light| {
+                    direct(scene, light.as_ref(), cone, &ray, &hit, |scattered| {
+                        hit.material.scattering_pdf(&ray, &hit, scattered)
+                    })
+                })
+                .sum::<Vector3<Float>>();
+            // seen from the side of the surface the cone arrived on
+            let up = if hit.normal.dot(&cone.direction) < 0.0 {
+                hit.normal
+            } else {
+                -hit.normal
+            };
+            let ambient = scene.background.radiance(&up);
+            emitted + attenuation.component_mul(&(light + ambient))
+        }
+        None => emitted,
+    }
+}
+
+// Approximate preview: one cone per camera sample with the pixel's footprint, no
+// random paths or light samples. Soft shadows come from the shadow cones' partial
+// occlusion, glossy blur from the footprint fuzzy reflections widen.
+pub fn render_pass(scene: &Scene, settings: &RenderSettings, pass: usize) -> Vec<Vector3<Float>> {
+    let (nx, ny) = (settings.width, settings.height);
+    (0..ny)
+        .into_par_iter()
+        .rev()
+        .flat_map(|y| {
+            (0..nx)
+                .map(|x| {
+                    rng::seed_pixel(settings.rng, settings.seed, pass, x, y);
+                    (0..settings.spp)
+                        .map(|_| {
+                            let u = (x as Float + rng::uniform()) / nx as Float;
+                            let v = (y as Float + rng::uniform()) / ny as Float;
+                            let ray = match scene.camera.get_ray(u, v) {
+                                Some(ray) => ray,
+                                None => return Vector3::zeros(),
+                            };
+                            // the neighbouring pixel's point on the image plane, seen
+                            // from this ray's origin on the lens
+                            let next = match scene.camera.get_ray(u + 1.0 / nx as Float, v) {
+                                Some(next) => next.origin() + next.direction() - ray.origin(),
+                                None => ray.direction(),
+                            };
+                            let pixel_angle = ray
+                                .direction()
+                                .normalize()
+                                .dot(&next.normalize())
+                                .min(1.0)
+                                .acos();
+                            let cone =
+                                Cone::new(ray.origin(), ray.direction(), 0.0, 0.5 * pixel_angle);
+                            shade(scene, &cone, ray.time(), 0)
+                        })
+                        .sum::<Vector3<Float>>()
+                        / settings.spp as Float
+                })
+                .collect::<Vec<Vector3<Float>>>()
+        })
+        .collect::<Vec<Vector3<Float>>>()
+}
+
+// occlusion of a list of hittables, stopping once the cone is blocked
+pub fn occlusion_of<'a>(
+    hittables: impl Iterator<Item = &'a dyn Hittable>,
+    cone: &Cone,
+    t_min: Float,
+    t_max: Float,
+) -> Float {
+    let mut occlusion = 0.0;
+    for hittable in hittables {
+        occlusion = combine(occlusion, hittable.occlusion(cone, t_min, t_max));
+        if occlusion >= OPAQUE {
+            return 1.0;
+        }
+    }
+    occlusion
+}
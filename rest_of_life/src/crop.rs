@@ -0,0 +1,171 @@
+use crate::cli::Options;
+use crate::metadata::Metadata;
+use crate::render::Image;
+use std::fmt;
+use std::fs;
+use std::str::FromStr;
+
+// A window of the image, columns x0 up to but not including x1 and rows y0 up to
+// but not including y1 from the top left. A cropped render traces only the
+// window's pixels, each exactly as the whole image would, and writes the window as
+// an image of its own, so a region can be looked at alone or rendered again with
+// more samples and patched back into the whole.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Crop {
+    pub x0: usize,
+    pub y0: usize,
+    pub x1: usize,
+    pub y1: usize,
+}
+
+impl FromStr for Crop {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("invalid crop: {}, expected x0,y0,x1,y1", s);
+        let values = s
+            .split(',')
+            .map(|v| v.trim().parse::<usize>().ok())
+            .collect::<Option<Vec<usize>>>()
+            .filter(|values| values.len() == 4)
+            .ok_or_else(invalid)?;
+        let crop = Crop {
+            x0: values[0],
+            y0: values[1],
+            x1: values[2],
+            y1: values[3],
+        };
+        if crop.x0 >= crop.x1 || crop.y0 >= crop.y1 {
+            return Err(format!("crop {} is empty", s));
+        }
+        Ok(crop)
+    }
+}
+
+impl fmt::Display for Crop {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{},{},{},{}", self.x0, self.y0, self.x1, self.y1)
+    }
+}
+
+impl Crop {
+    pub fn width(&self) -> usize {
+        self.x1 - self.x0
+    }
+
+    pub fn height(&self) -> usize {
+        self.y1 - self.y0
+    }
+
+    // whether the window lies within an image of the size
+    pub fn fits(&self, width: usize, height: usize) -> bool {
+        self.x1 <= width && self.y1 <= height
+    }
+
+    // the window's pixels, columns and rows from the top left, row by row
+    pub fn pixels(&self) -> Vec<(usize, usize)> {
+        (self.y0..self.y1)
+            .flat_map(|row| (self.x0..self.x1).map(move |x| (x, row)))
+            .collect()
+    }
+
+    // the window's part of values laid out over a whole image `width` across, rows
+    // from the top
+    pub fn cut<T: Copy>(&self, whole: &[T], width: usize) -> Vec<T> {
+        self.pixels()
+            .iter()
+            .map(|(x, row)| whole[row * width + x])
+            .collect()
+    }
+}
+
+// The whole image with the window's pixels taken from the patch, a cropped render
+// of the window.
+pub fn patch(mut whole: Image, patch: &Image, crop: &Crop) -> Result<Image, String> {
+    if !crop.fits(whole.width, whole.height) {
+        return Err(format!(
+            "crop {} is outside the {}x{} image",
+            crop, whole.width, whole.height
+        ));
+    }
+    if (patch.width, patch.height) != (crop.width(), crop.height()) {
+        return Err(format!(
+            "a {}x{} patch does not fill the {}x{} crop {}",
+            patch.width,
+            patch.height,
+            crop.width(),
+            crop.height(),
+            crop
+        ));
+    }
+    for ((x, row), c) in crop.pixels().into_iter().zip(&patch.pixels) {
+        whole.pixels[row * whole.width + x] = *c;
+    }
+    Ok(whole)
+}
+
+// Patches the raw framebuffer of a cropped render, the second path patch names,
+// into that of the whole image, the first, at --crop, and writes the result in
+// --format. A raw result records the whole image's samples per pixel.
+pub fn run(paths: &[String], options: &Options) -> Result<Vec<u8>, String> {
+    let crop = options
+        .crop
+        .ok_or("patch needs the --crop the patch was rendered at")?;
+    let read = |path: &String| {
+        let bytes = fs::read(path).map_err(|e| format!("cannot read {}: {}", path, e))?;
+        Image::from_raw(&bytes).map_err(|e| format!("cannot patch {}: {}", path, e))
+    };
+    let [whole, part] = paths else {
+        return Err(String::from("patch needs the whole image and the patch"));
+    };
+    let (whole, spp) = read(whole)?;
+    let (part, _) = read(part)?;
+    let image = patch(whole, &part, &crop)?;
+    Ok(image.encode(options.format, &options.tone, spp, &Metadata::default()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::render::{self, RenderSettings};
+    use crate::scene;
+    use nalgebra::Vector3;
+
+    #[test]
+    fn patched_crop_matches_a_whole_render() {
+        let crop: Crop = "3,2,7,5".parse().unwrap();
+        assert_eq!(crop.to_string(), "3,2,7,5");
+        assert!("3,2,3,5".parse::<Crop>().is_err() && "3,2,7".parse::<Crop>().is_err());
+        let settings = RenderSettings {
+            width: 10,
+            height: 8,
+            spp: 4,
+            seed: 5,
+            ..RenderSettings::default()
+        };
+        let scene = scene::by_name("cornell_box", 10.0 / 8.0, &Options::default()).unwrap();
+        let whole = render::render(&scene, &settings);
+        let cropped = render::render(
+            &scene,
+            &RenderSettings {
+                crop: Some(crop),
+                ..settings.clone()
+            },
+        );
+        assert_eq!((cropped.width, cropped.height), (4, 3));
+        assert_eq!(cropped.pixels, crop.cut(&whole.pixels, whole.width));
+        // patched into a black image, it is the whole render inside the window only
+        let black = Image {
+            width: 10,
+            height: 8,
+            pixels: vec![Vector3::zeros(); 80],
+        };
+        let patched = patch(black, &cropped, &crop).unwrap();
+        for (i, (p, w)) in patched.pixels.iter().zip(&whole.pixels).enumerate() {
+            let (x, row) = (i % 10, i / 10);
+            let inside = (3..7).contains(&x) && (2..5).contains(&row);
+            assert_eq!(*p, if inside { *w } else { Vector3::zeros() });
+        }
+        assert!(patch(patched, &cropped, &"0,0,2,2".parse().unwrap()).is_err());
+    }
+}
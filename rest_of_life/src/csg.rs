@@ -0,0 +1,254 @@
+use crate::aabb;
+use crate::aabb::AABB;
+use crate::float::Float;
+use crate::hittable::{HitRecord, Hittable, Surfaces};
+use crate::ray::Ray;
+use nalgebra::Vector3;
+use std::str::FromStr;
+
+const MAX_CROSSINGS: usize = 32;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Operation {
+    Union,
+    Intersection,
+    Difference,
+}
+
+impl FromStr for Operation {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "union" => Ok(Operation::Union),
+            "intersection" => Ok(Operation::Intersection),
+            "difference" => Ok(Operation::Difference),
+            _ => Err(format!("unknown csg operation: {}", s)),
+        }
+    }
+}
+
+impl Operation {
+    fn inside(&self, in_left: bool, in_right: bool) -> bool {
+        match self {
+            Operation::Union => in_left || in_right,
+            Operation::Intersection => in_left && in_right,
+            Operation::Difference => in_left && !in_right,
+        }
+    }
+}
+
+struct Crossing<'a> {
+    hit: HitRecord<'a>,
+    entering: bool,
+    from_left: bool,
+}
+
+// Collects every surface crossing of a closed primitive along the whole ray line,
+// classifying each one as entry or exit by its outward normal.
+fn crossings<'a, H: Hittable>(
+    hittable: &'a H,
+    ray: &Ray,
+    from_left: bool,
+    out: &mut Vec<Crossing<'a>>,
+) {
+    let mut surfaces = Surfaces::new(ray);
+    for _ in 0..MAX_CROSSINGS {
+        match surfaces.next(hittable, -Float::MAX, Float::MAX) {
+            Some(hit) => {
+                let entering = ray.direction().dot(&hit.normal) < 0.0;
+                out.push(Crossing {
+                    hit,
+                    entering,
+                    from_left,
+                });
+            }
+            None => break,
+        }
+    }
+}
+
+pub struct CSG<A: Hittable, B: Hittable> {
+    operation: Operation,
+    left: A,
+    right: B,
+}
+
+impl<A: Hittable, B: Hittable> CSG<A, B> {
+    pub fn new(operation: Operation, left: A, right: B) -> Self {
+        CSG {
+            operation,
+            left,
+            right,
+        }
+    }
+
+    pub fn union(left: A, right: B) -> Self {
+        CSG::new(Operation::Union, left, right)
+    }
+
+    pub fn intersection(left: A, right: B) -> Self {
+        CSG::new(Operation::Intersection, left, right)
+    }
+
+    pub fn difference(left: A, right: B) -> Self {
+        CSG::new(Operation::Difference, left, right)
+    }
+}
+
+impl<A: Hittable, B: Hittable> Hittable for CSG<A, B> {
+    fn hit(&self, ray: &Ray, t_min: Float, t_max: Float) -> Option<HitRecord<'_>> {
+        let mut events = Vec::new();
+        crossings(&self.left, ray, true, &mut events);
+        if events.is_empty() && !matches!(self.operation, Operation::Union) {
+            return None;
+        }
+        crossings(&self.right, ray, false, &mut events);
+        events.sort_unstable_by(|a, b| a.hit.t.total_cmp(&b.hit.t));
+
+        let mut in_left = false;
+        let mut in_right = false;
+        for event in events {
+            let was_inside = self.operation.inside(in_left, in_right);
+            if event.from_left {
+                in_left = event.entering
+            } else {
+                in_right = event.entering
+            }
+            let is_inside = self.operation.inside(in_left, in_right);
+            if was_inside != is_inside && event.hit.t > t_min && event.hit.t < t_max {
+                let mut hit = event.hit;
+                if !event.from_left && matches!(self.operation, Operation::Difference) {
+                    hit.normal = -hit.normal;
+                }
+                return Some(hit);
+            }
+            if event.hit.t >= t_max {
+                break;
+            }
+        }
+        None
+    }
+
+    fn bounding_box(&self, t0: Float, t1: Float) -> Option<AABB> {
+        let left = self.left.bounding_box(t0, t1);
+        let right = self.right.bounding_box(t0, t1);
+        match self.operation {
+            Operation::Union => match (left, right) {
+                (Some(l), Some(r)) => Some(aabb::surrounding_box(&l, &r)),
+                _ => None,
+            },
+            Operation::Intersection => match (left, right) {
+                (Some(l), Some(r)) => {
+                    let min = Vector3::new(
+                        Float::max(l.min.x, r.min.x),
+                        Float::max(l.min.y, r.min.y),
+                        Float::max(l.min.z, r.min.z),
+                    );
+                    let max = Vector3::new(
+                        Float::min(l.max.x, r.max.x),
+                        Float::min(l.max.y, r.max.y),
+                        Float::min(l.max.z, r.max.z),
+                    );
+                    Some(AABB::new(min, max))
+                }
+                (Some(b), None) | (None, Some(b)) => Some(b),
+                _ => None,
+            },
+            Operation::Difference => left,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cli::Options;
+    use crate::material::Lambertian;
+    use crate::scenefile;
+    use crate::sphere::Sphere;
+    use crate::texture::ConstantTexture;
+
+    type Grey = Sphere<Lambertian<ConstantTexture>>;
+
+    // unit spheres about the origin and one along x, overlapping over 0 < x < 1
+    fn spheres(operation: Operation) -> CSG<Grey, Grey> {
+        let grey = Lambertian::new(ConstantTexture::new(0.5, 0.5, 0.5));
+        CSG::new(
+            operation,
+            Sphere::new(Vector3::zeros(), 1.0, grey.clone()),
+            Sphere::new(Vector3::x(), 1.0, grey),
+        )
+    }
+
+    // where a ray from `origin` along `direction` first meets the solid, and the
+    // normal there
+    fn first(
+        csg: &impl Hittable,
+        origin: Vector3<Float>,
+        direction: Vector3<Float>,
+    ) -> Option<(Vector3<Float>, Vector3<Float>)> {
+        let ray = Ray::new(origin, direction, 0.0);
+        let hit = csg.hit(&ray, 0.0, Float::MAX)?;
+        Some((hit.p, hit.normal))
+    }
+
+    fn near(a: Vector3<Float>, b: Vector3<Float>) -> bool {
+        (a - b).norm() < 1e-4
+    }
+
+    #[test]
+    fn two_spheres_combine() {
+        let (left, right) = (Vector3::new(-5.0, 0.0, 0.0), Vector3::new(5.0, 0.0, 0.0));
+        let union = spheres(Operation::Union);
+        let (p, n) = first(&union, left, Vector3::x()).unwrap();
+        assert!(near(p, Vector3::new(-1.0, 0.0, 0.0)) && near(n, -Vector3::x()));
+        let (p, n) = first(&union, right, -Vector3::x()).unwrap();
+        assert!(near(p, Vector3::new(2.0, 0.0, 0.0)) && near(n, Vector3::x()));
+
+        // the lens between them, entered through the other sphere's surface
+        let intersection = spheres(Operation::Intersection);
+        let (p, n) = first(&intersection, left, Vector3::x()).unwrap();
+        assert!(near(p, Vector3::zeros()) && near(n, -Vector3::x()));
+        let (p, n) = first(&intersection, right, -Vector3::x()).unwrap();
+        assert!(near(p, Vector3::x()) && near(n, Vector3::x()));
+
+        // the first sphere bitten by the second, whose surface faces the other way
+        // where it cuts
+        let difference = spheres(Operation::Difference);
+        let (p, n) = first(&difference, left, Vector3::x()).unwrap();
+        assert!(near(p, Vector3::new(-1.0, 0.0, 0.0)) && near(n, -Vector3::x()));
+        let (p, n) = first(&difference, right, -Vector3::x()).unwrap();
+        assert!(near(p, Vector3::zeros()) && near(n, Vector3::x()));
+
+        // past the second sphere, only its union and difference are there
+        let below = Vector3::new(-0.5, -5.0, 0.0);
+        let y = -(0.75 as Float).sqrt();
+        for csg in [&union, &difference] {
+            let (p, _) = first(csg, below, Vector3::y()).unwrap();
+            assert!(near(p, Vector3::new(-0.5, y, 0.0)));
+        }
+        assert!(first(&intersection, below, Vector3::y()).is_none());
+    }
+
+    #[test]
+    fn csg_statements_build_the_solid() {
+        let text = "camera from 0 0 10 at 0 0 0\n\
+                    material grey lambertian 0.5 0.5 0.5\n\
+                    csg difference box -1 -1 -1 1 1 1 sphere 1 0 0 1 grey\n";
+        let scene = scenefile::parse(text, 1.0, &Options::default()).unwrap();
+        let ray = Ray::new(Vector3::new(5.0, 0.0, 0.0), -Vector3::x(), 0.0);
+        let hit = scene.world.hit(&ray, 0.0, Float::MAX).unwrap();
+        assert!(near(hit.p, Vector3::zeros()) && near(hit.normal, Vector3::x()));
+        let ray = Ray::new(Vector3::new(5.0, 0.9, 0.9), -Vector3::x(), 0.0);
+        let hit = scene.world.hit(&ray, 0.0, Float::MAX).unwrap();
+        assert!(near(hit.p, Vector3::new(1.0, 0.9, 0.9)));
+        assert!(scenefile::parse(
+            "camera from 0 0 10 at 0 0 0\nmaterial grey lambertian 0.5 0.5 0.5\n\
+             csg union sphere 0 0 0 1 rect xy 0 1 0 1 0 grey\n",
+            1.0,
+            &Options::default()
+        )
+        .is_err());
+    }
+}
@@ -1,77 +1,94 @@
 use crate::aabb::AABB;
+use crate::float::Float;
 use crate::hittable::{FlipNormals, HitRecord, Hittable, HittableList};
 use crate::material::Material;
 use crate::ray::Ray;
-use crate::rect::{AARect, Plane};
+use crate::rect::{self, AARect, FaceUv, Plane};
 use nalgebra::Vector3;
 
+// How a box's faces are laid on a texture. Each face is upright seen from outside:
+// the sides with v up, the top with v running away from the +z side and the bottom
+// with v running toward it, so a net of the faces folds up around the box.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CubeLayout {
+    // every face shows the whole texture, repeated (u, v) times across it
+    Tiled(Float, Float),
+    // the faces unfolded into a cross 4 faces wide and 3 high, as a die's net is
+    // drawn: -x, +z, +x and -z along the middle row, +y above +z and -y below it
+    Cross,
+}
+
 pub struct Cube {
-    p_min: Vector3<f32>,
-    p_max: Vector3<f32>,
+    p_min: Vector3<Float>,
+    p_max: Vector3<Float>,
     sides: HittableList,
 }
 
 impl Cube {
     pub fn new<M: Material + Clone + 'static>(
-        p_min: Vector3<f32>,
-        p_max: Vector3<f32>,
+        p_min: Vector3<Float>,
+        p_max: Vector3<Float>,
+        material: M,
+    ) -> Self {
+        Cube::with_layout(p_min, p_max, material, CubeLayout::Tiled(1.0, 1.0))
+    }
+
+    pub fn with_layout<M: Material + Clone + 'static>(
+        p_min: Vector3<Float>,
+        p_max: Vector3<Float>,
         material: M,
+        layout: CubeLayout,
     ) -> Self {
+        // each face's plane, whether it is the one at the maximum, which faces out
+        // while the others are flipped, how its u and v turn to stand upright and
+        // where in the cross it goes
+        let faces = [
+            (Plane::XY, true, 0, false, (1, 1)),
+            (Plane::XY, false, 0, true, (3, 1)),
+            (Plane::ZX, true, 3, false, (1, 2)),
+            (Plane::ZX, false, 1, true, (1, 0)),
+            (Plane::YZ, true, 1, false, (2, 1)),
+            (Plane::YZ, false, 1, true, (0, 1)),
+        ];
         let mut sides = HittableList::default();
-        sides.push(AARect::new(
-            Plane::XY,
-            p_min.x,
-            p_max.x,
-            p_min.y,
-            p_max.y,
-            p_max.z,
-            material.clone(),
-        ));
-        sides.push(FlipNormals::new(AARect::new(
-            Plane::XY,
-            p_min.x,
-            p_max.x,
-            p_min.y,
-            p_max.y,
-            p_min.z,
-            material.clone(),
-        )));
-        sides.push(AARect::new(
-            Plane::ZX,
-            p_min.z,
-            p_max.z,
-            p_min.x,
-            p_max.x,
-            p_max.y,
-            material.clone(),
-        ));
-        sides.push(FlipNormals::new(AARect::new(
-            Plane::ZX,
-            p_min.z,
-            p_max.z,
-            p_min.x,
-            p_max.x,
-            p_min.y,
-            material.clone(),
-        )));
-        sides.push(AARect::new(
-            Plane::YZ,
-            p_min.y,
-            p_max.y,
-            p_min.z,
-            p_max.z,
-            p_max.x,
-            material.clone(),
-        ));
-        sides.push(FlipNormals::new(AARect::new(
-            Plane::YZ,
-            p_min.y,
-            p_max.y,
-            p_min.z,
-            p_max.z,
-            p_min.x,
-            material,
-        )));
+        for (plane, outward, turns, mirror, (column, row)) in faces {
+            let uv = match layout {
+                CubeLayout::Tiled(u, v) => FaceUv {
+                    turns,
+                    mirror,
+                    tiles: (u, v),
+                    ..FaceUv::default()
+                },
+                CubeLayout::Cross => FaceUv {
+                    turns,
+                    mirror,
+                    offset: (column as Float / 4.0, row as Float / 3.0),
+                    size: (1.0 / 4.0, 1.0 / 3.0),
+                    ..FaceUv::default()
+                },
+            };
+            let (k_axis, a_axis, b_axis) = rect::get_axis(&plane);
+            let k = if outward {
+                p_max[k_axis]
+            } else {
+                p_min[k_axis]
+            };
+            let side = AARect::new(
+                plane,
+                p_min[a_axis],
+                p_max[a_axis],
+                p_min[b_axis],
+                p_max[b_axis],
+                k,
+                material.clone(),
+            )
+            .with_uv(uv);
+            if outward {
+                sides.push(side);
+            } else {
+                sides.push(FlipNormals::new(side));
+            }
+        }
         Cube {
             p_min,
             p_max,
@@ -81,11 +98,90 @@ impl Cube {
 }
 
 impl Hittable for Cube {
-    fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord> {
+    fn hit(&self, ray: &Ray, t_min: Float, t_max: Float) -> Option<HitRecord<'_>> {
         self.sides.hit(ray, t_min, t_max)
     }
 
-    fn bounding_box(&self, _t0: f32, _t1: f32) -> Option<AABB> {
+    fn bounding_box(&self, _t0: Float, _t1: Float) -> Option<AABB> {
         Some(AABB::new(self.p_min, self.p_max))
     }
+
+    // a side picked alike, then a point on it
+    fn pdf_value(&self, o: Vector3<Float>, v: Vector3<Float>) -> Float {
+        self.sides.pdf_value(o, v)
+    }
+
+    fn random(&self, o: Vector3<Float>) -> Vector3<Float> {
+        self.sides.random(o)
+    }
+
+    fn sample_surface(&self) -> Option<(Vector3<Float>, Vector3<Float>, Float)> {
+        self.sides.sample_surface()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::Lambertian;
+    use crate::texture::ConstantTexture;
+
+    // the u and v where a ray from `from` toward the box's centre meets it
+    fn uv_from(cube: &Cube, from: Vector3<Float>) -> (Float, Float) {
+        let hit = cube
+            .hit(&Ray::new(from, -from, 0.0), 0.001, Float::MAX)
+            .unwrap();
+        (hit.u, hit.v)
+    }
+
+    #[test]
+    fn faces_fold_into_a_cross() {
+        let white = Lambertian::new(ConstantTexture::new(1.0, 1.0, 1.0));
+        let (min, max) = (Vector3::new(-1.0, -1.0, -1.0), Vector3::new(1.0, 1.0, 1.0));
+        let cube = Cube::with_layout(min, max, white.clone(), CubeLayout::Cross);
+        let close = |(u, v): (Float, Float), (column, row): (Float, Float)| {
+            (u - column / 4.0).abs() < 1e-3 && (v - row / 3.0).abs() < 1e-3
+        };
+        // each face's centre at the centre of its cell
+        assert!(close(
+            uv_from(&cube, Vector3::new(0.0, 0.0, 5.0)),
+            (1.5, 1.5)
+        ));
+        assert!(close(
+            uv_from(&cube, Vector3::new(5.0, 0.0, 0.0)),
+            (2.5, 1.5)
+        ));
+        assert!(close(
+            uv_from(&cube, Vector3::new(0.0, 0.0, -5.0)),
+            (3.5, 1.5)
+        ));
+        assert!(close(
+            uv_from(&cube, Vector3::new(-5.0, 0.0, 0.0)),
+            (0.5, 1.5)
+        ));
+        assert!(close(
+            uv_from(&cube, Vector3::new(0.0, 5.0, 0.0)),
+            (1.5, 2.5)
+        ));
+        assert!(close(
+            uv_from(&cube, Vector3::new(0.0, -5.0, 0.0)),
+            (1.5, 0.5)
+        ));
+        // the top's edge along the front meets the front's top edge in the net, and
+        // the right side's edge along the front meets the front's right edge
+        let top = uv_from(&cube, Vector3::new(0.0, 5.0, 4.99));
+        assert!(close(top, (1.5, 2.0)), "{:?}", top);
+        let right = uv_from(&cube, Vector3::new(4.99, 0.0, 5.0));
+        assert!(close(right, (2.0, 1.5)), "{:?}", right);
+
+        // tiled, every face repeats the whole texture
+        let tiled = Cube::with_layout(min, max, white, CubeLayout::Tiled(2.0, 1.0));
+        let ray = Ray::new(
+            Vector3::new(0.5, 0.0, 5.0),
+            Vector3::new(0.0, 0.0, -1.0),
+            0.0,
+        );
+        let hit = tiled.hit(&ray, 0.001, Float::MAX).unwrap();
+        assert!((hit.u - 0.5).abs() < 1e-4 && (hit.v - 0.5).abs() < 1e-4);
+    }
 }
@@ -0,0 +1,571 @@
+use crate::aov;
+use crate::camera::{Camera, Optics};
+use crate::cube::Cube;
+use crate::float::{self, Float};
+use crate::hittable::{FlipNormals, Hittable, HittableList, Labeled};
+use crate::material::{Dielectric, DiffuseLight, Lambertian, Material, Metal};
+use crate::metadata::Metadata;
+use crate::ray;
+use crate::rect::{AARect, Plane};
+use crate::render::{self, RenderSettings};
+use crate::rotate::{Axis, Rotate};
+use crate::scene::Scene;
+use crate::sphere::Sphere;
+use crate::texture::ConstantTexture;
+use crate::tonemap::ToneMapping;
+use crate::translate::Translate;
+use nalgebra::Vector3;
+use rand::{Rng, RngCore};
+use std::fs;
+use std::path::Path;
+use std::sync::Arc;
+
+// semantic classes, indexed by class id; 0 is left for pixels that hit nothing
+pub const CLASSES: [&str; 5] = ["background", "floor", "sphere", "cube", "light"];
+const FLOOR: u32 = 1;
+const SPHERE: u32 = 2;
+const CUBE: u32 = 3;
+const LIGHT: u32 = 4;
+
+// Renders a batch of randomized scenes together with ground truth for training:
+// segmentation by class and by instance, depth and surface normals, plus a json
+// manifest describing every scene.
+#[derive(Clone)]
+pub struct Dataset {
+    pub count: usize,
+    pub dir: String,
+    pub seed: u64,
+}
+
+struct Object {
+    id: u32,
+    class_id: u32,
+    material: &'static str,
+    center: Vector3<Float>,
+    size: Float,
+}
+
+struct Sample {
+    scene: Scene,
+    look_from: Vector3<Float>,
+    look_at: Vector3<Float>,
+    light: Object,
+    objects: Vec<Object>,
+}
+
+fn push_labeled<H: Hittable + 'static>(world: &mut HittableList, hittable: H, object: &Object) {
+    world.push(Labeled::new(hittable, object.id, object.class_id));
+}
+
+fn push_object<M: Material + Clone + 'static>(
+    world: &mut HittableList,
+    rng: &mut dyn RngCore,
+    object: &Object,
+    material: M,
+) {
+    if object.class_id == SPHERE {
+        push_labeled(
+            world,
+            Sphere::new(object.center, object.size, material),
+            object,
+        );
+    } else {
+        let half = Vector3::new(object.size, object.size, object.size);
+        let cube = Cube::new(-half, half, material);
+        let rotated = Rotate::new(Axis::Y, cube, rng.gen_range(0.0..90.0));
+        push_labeled(world, Translate::new(rotated, object.center), object);
+    }
+}
+
+fn random_scene(rng: &mut dyn RngCore, aspect: Float) -> Sample {
+    let mut world = HittableList::default();
+    let floor = Object {
+        id: 1,
+        class_id: FLOOR,
+        material: "lambertian",
+        center: Vector3::new(0.0, 0.0, 0.0),
+        size: 10.0,
+    };
+    let (r, g, b) = (
+        rng.gen_range(0.3..0.8),
+        rng.gen_range(0.3..0.8),
+        rng.gen_range(0.3..0.8),
+    );
+    push_labeled(
+        &mut world,
+        AARect::new(
+            Plane::ZX,
+            -10.0,
+            10.0,
+            -10.0,
+            10.0,
+            0.0,
+            Lambertian::new(ConstantTexture::new(r, g, b)),
+        ),
+        &floor,
+    );
+
+    let mut objects = vec![floor];
+    let count = rng.gen_range(3..=8);
+    let mut attempts = 0;
+    while objects.len() <= count && attempts < 100 {
+        attempts += 1;
+        let size = rng.gen_range(0.3..0.8);
+        let center = Vector3::new(rng.gen_range(-3.0..3.0), size, rng.gen_range(-3.0..3.0));
+        // keep bounding circles apart so objects never interpenetrate
+        let overlaps = objects.iter().skip(1).any(|o| {
+            let d = Vector3::new(o.center.x - center.x, 0.0, o.center.z - center.z);
+            d.norm() < (o.size + size) * float::consts::SQRT_2
+        });
+        if overlaps {
+            continue;
+        }
+        let class_id = if rng.gen::<bool>() { SPHERE } else { CUBE };
+        let kind = rng.gen_range(0..3);
+        let mut object = Object {
+            id: objects.len() as u32 + 1,
+            class_id,
+            material: "",
+            center,
+            size,
+        };
+        let albedo = Vector3::new(rng.gen::<Float>(), rng.gen::<Float>(), rng.gen::<Float>());
+        match kind {
+            0 => {
+                object.material = "lambertian";
+                let texture = ConstantTexture::new(albedo.x, albedo.y, albedo.z);
+                push_object(&mut world, rng, &object, Lambertian::new(texture));
+            }
+            1 => {
+                object.material = "metal";
+                let fuzz = rng.gen_range(0.0..0.5);
+                let albedo = 0.5 * (albedo + Vector3::new(1.0, 1.0, 1.0));
+                push_object(&mut world, rng, &object, Metal::new(albedo, fuzz));
+            }
+            _ => {
+                object.material = "dielectric";
+                let ref_idx = rng.gen_range(1.3..1.8);
+                push_object(&mut world, rng, &object, Dielectric::new(ref_idx));
+            }
+        }
+        objects.push(object);
+    }
+
+    let light_size = rng.gen_range(1.0..3.0);
+    let light = Object {
+        id: objects.len() as u32 + 1,
+        class_id: LIGHT,
+        material: "diffuse_light",
+        center: Vector3::new(
+            rng.gen_range(-2.0..2.0),
+            rng.gen_range(4.0..6.0),
+            rng.gen_range(-2.0..2.0),
+        ),
+        size: light_size,
+    };
+    let intensity = rng.gen_range(20.0..60.0) / (light_size * light_size);
+    let light_rect = AARect::new(
+        Plane::ZX,
+        light.center.z - 0.5 * light_size,
+        light.center.z + 0.5 * light_size,
+        light.center.x - 0.5 * light_size,
+        light.center.x + 0.5 * light_size,
+        light.center.y,
+        DiffuseLight::new(ConstantTexture::new(intensity, intensity, intensity)),
+    );
+    let light_shape = Arc::new(FlipNormals::new(light_rect));
+    push_labeled(&mut world, light_shape.clone(), &light);
+
+    // orbit the camera around the middle of the scene
+    let azimuth = rng.gen_range(0.0..2.0 * float::consts::PI);
+    let elevation = rng.gen_range(15.0..45.0 as Float).to_radians();
+    let distance = rng.gen_range(6.0..9.0);
+    let look_at = Vector3::new(0.0, 0.5, 0.0);
+    let look_from = look_at
+        + distance
+            * Vector3::new(
+                elevation.cos() * azimuth.cos(),
+                elevation.sin(),
+                elevation.cos() * azimuth.sin(),
+            );
+    let cam = Camera::new(
+        look_from,
+        look_at,
+        Vector3::new(0.0, 1.0, 0.0),
+        aspect,
+        Optics::default(),
+        0.0,
+        1.0,
+    );
+
+    Sample {
+        scene: Scene {
+            lights: vec![light_shape.clone()],
+            light_shape: Some(light_shape),
+            ..Scene::new(Arc::new(world), cam)
+        },
+        look_from,
+        look_at,
+        light,
+        objects,
+    }
+}
+
+// s as a quoted JSON string, with quotes, backslashes and control characters escaped
+fn json_string(s: &str) -> String {
+    let mut json = String::from("\"");
+    for c in s.chars() {
+        match c {
+            '"' => json.push_str("\\\""),
+            '\\' => json.push_str("\\\\"),
+            '\n' => json.push_str("\\n"),
+            '\r' => json.push_str("\\r"),
+            '\t' => json.push_str("\\t"),
+            c if c.is_control() => json.push_str(&format!("\\u{:04x}", c as u32)),
+            c => json.push(c),
+        }
+    }
+    json.push('"');
+    json
+}
+
+fn vector_json(v: &Vector3<Float>) -> String {
+    format!("[{}, {}, {}]", v.x, v.y, v.z)
+}
+
+fn object_json(object: &Object) -> String {
+    format!(
+        "{{\"id\": {}, \"class\": {}, \"material\": {}, \"center\": {}, \"size\": {}}}",
+        object.id,
+        json_string(CLASSES[object.class_id as usize]),
+        json_string(object.material),
+        vector_json(&object.center),
+        object.size
+    )
+}
+
+// COCO style annotations for every object visible in the instance map `ids`, row
+// by row from the top left: the box [x, y, width, height] in pixels bounds the
+// object's visible pixels, so a partly hidden object's covers only what shows,
+// and the area counts them
+fn annotations(
+    sample: &Sample,
+    ids: &[u32],
+    nx: usize,
+    ny: usize,
+    image_id: usize,
+    next_id: &mut usize,
+) -> Vec<String> {
+    let mut annotations = Vec::new();
+    for object in sample.objects.iter().chain(std::iter::once(&sample.light)) {
+        let mut area = 0;
+        let (mut x0, mut y0, mut x1, mut y1) = (nx, ny, 0, 0);
+        for (i, _) in ids.iter().enumerate().filter(|(_, &id)| id == object.id) {
+            let (x, y) = (i % nx, i / nx);
+            area += 1;
+            x0 = x0.min(x);
+            y0 = y0.min(y);
+            x1 = x1.max(x + 1);
+            y1 = y1.max(y + 1);
+        }
+        if area == 0 {
+            continue;
+        }
+        annotations.push(format!(
+            "    {{\"id\": {}, \"image_id\": {}, \"category_id\": {}, \"object_id\": {}, \"bbox\": [{}, {}, {}, {}], \"area\": {}, \"iscrowd\": 0}}",
+            next_id,
+            image_id,
+            object.class_id,
+            object.id,
+            x0,
+            y0,
+            x1 - x0,
+            y1 - y0,
+            area
+        ));
+        *next_id += 1;
+    }
+    annotations
+}
+
+impl Dataset {
+    pub fn new(count: usize) -> Self {
+        Dataset {
+            count,
+            dir: String::from("dataset"),
+            seed: 0,
+        }
+    }
+
+    // renders the samples into the dataset directory, or says which file could not
+    // be written
+    pub fn generate(&self, settings: &RenderSettings) -> Result<(), String> {
+        let (nx, ny) = (settings.width, settings.height);
+        let dir = Path::new(&self.dir);
+        fs::create_dir_all(dir).map_err(|e| format!("cannot create {}: {}", dir.display(), e))?;
+        let write = |file: &str, data: &[u8]| {
+            let path = dir.join(file);
+            fs::write(&path, data).map_err(|e| format!("cannot write {}: {}", path.display(), e))
+        };
+        let mut entries = Vec::with_capacity(self.count);
+        let mut images = Vec::with_capacity(self.count);
+        let mut coco = Vec::new();
+        let mut next_annotation_id = 1;
+        for index in 0..self.count {
+            eprintln!("rendering sample {}/{}", index + 1, self.count);
+            let mut rng = settings.rng.seeded(self.seed + index as u64);
+            let sample = random_scene(rng.as_mut(), nx as Float / ny as Float);
+            let name = format!("sample_{:04}", index);
+
+            let beauty = render::render(&sample.scene, settings)
+                .ppm(&ToneMapping::default(), &Metadata::default());
+
+            // ground truth from the first hit of the ray through each pixel center
+            let mut class = format!("P2\n{} {}\n255\n", nx, ny);
+            let mut instance = format!("P2\n{} {}\n65535\n", nx, ny);
+            let mut normal = format!("P3\n{} {}\n255\n", nx, ny);
+            let mut depth = Vec::with_capacity(nx * ny);
+            let mut ids = Vec::with_capacity(nx * ny);
+            for y in (0..ny).rev() {
+                for x in 0..nx {
+                    let u = (x as Float + 0.5) / nx as Float;
+                    let v = (y as Float + 0.5) / ny as Float;
+                    let ray = sample
+                        .scene
+                        .camera
+                        .get_ray(u, v)
+                        .expect("dataset cameras are perspective");
+                    match sample.scene.world.hit(&ray, ray::T_MIN, Float::MAX) {
+                        Some(hit) => {
+                            let n = (0.5 * (hit.normal + Vector3::new(1.0, 1.0, 1.0))) * 255.99;
+                            class.push_str(&format!("{}\n", hit.class_id));
+                            instance.push_str(&format!("{}\n", hit.object_id));
+                            normal
+                                .push_str(&format!("{} {} {}\n", n.x as u8, n.y as u8, n.z as u8));
+                            depth.push(hit.t * ray.direction().norm());
+                            ids.push(hit.object_id);
+                        }
+                        None => {
+                            class.push_str("0\n");
+                            instance.push_str("0\n");
+                            normal.push_str("0 0 0\n");
+                            depth.push(0.0);
+                            ids.push(0);
+                        }
+                    }
+                }
+            }
+            let file = |suffix: &str| format!("{}{}", name, suffix);
+            write(&file(".ppm"), beauty.as_bytes())?;
+            write(&file("_class.pgm"), class.as_bytes())?;
+            write(&file("_instance.pgm"), instance.as_bytes())?;
+            write(&file("_depth.pfm"), &aov::gray_pfm(nx, ny, &depth))?;
+            write(&file("_normal.ppm"), normal.as_bytes())?;
+
+            images.push(format!(
+                "    {{\"id\": {}, \"file_name\": {}, \"width\": {}, \"height\": {}}}",
+                index + 1,
+                json_string(&file(".ppm")),
+                nx,
+                ny
+            ));
+            coco.extend(annotations(
+                &sample,
+                &ids,
+                nx,
+                ny,
+                index + 1,
+                &mut next_annotation_id,
+            ));
+
+            let objects = sample
+                .objects
+                .iter()
+                .map(object_json)
+                .collect::<Vec<String>>()
+                .join(",\n        ");
+            entries.push(format!(
+                "    {{\n      \"name\": {},\n      \"image\": {},\n      \"class\": {},\n      \"instance\": {},\n      \"depth\": {},\n      \"normal\": {},\n      \"camera\": {{\"look_from\": {}, \"look_at\": {}, \"vertical_fov\": 40}},\n      \"light\": {},\n      \"objects\": [\n        {}\n      ]\n    }}",
+                json_string(&name),
+                json_string(&file(".ppm")),
+                json_string(&file("_class.pgm")),
+                json_string(&file("_instance.pgm")),
+                json_string(&file("_depth.pfm")),
+                json_string(&file("_normal.ppm")),
+                vector_json(&sample.look_from),
+                vector_json(&sample.look_at),
+                object_json(&sample.light),
+                objects
+            ));
+        }
+
+        let classes = CLASSES
+            .iter()
+            .map(|c| json_string(c))
+            .collect::<Vec<String>>()
+            .join(", ");
+        let manifest = format!(
+            "{{\n  \"width\": {},\n  \"height\": {},\n  \"spp\": {},\n  \"seed\": {},\n  \"classes\": [{}],\n  \"samples\": [\n{}\n  ]\n}}\n",
+            nx,
+            ny,
+            settings.spp,
+            self.seed,
+            classes,
+            entries.join(",\n")
+        );
+        write("manifest.json", manifest.as_bytes())?;
+
+        let categories = CLASSES
+            .iter()
+            .enumerate()
+            .skip(1)
+            .map(|(id, c)| format!("    {{\"id\": {}, \"name\": {}}}", id, json_string(c)))
+            .collect::<Vec<String>>()
+            .join(",\n");
+        let annotations = format!(
+            "{{\n  \"images\": [\n{}\n  ],\n  \"categories\": [\n{}\n  ],\n  \"annotations\": [\n{}\n  ]\n}}\n",
+            images.join(",\n"),
+            categories,
+            coco.join(",\n")
+        );
+        write("annotations.json", annotations.as_bytes())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // the bbox and area of each annotation, by object id
+    fn boxes(annotations: &[String]) -> Vec<(u32, [usize; 4], usize)> {
+        annotations
+            .iter()
+            .map(|a| {
+                let field = |name: &str| {
+                    let start = a.find(&format!("\"{}\": ", name)).unwrap() + name.len() + 4;
+                    let end = start + a[start..].find([',', '}']).unwrap();
+                    a[start..end].trim_start_matches('[').to_string()
+                };
+                let bbox = a[a.find('[').unwrap() + 1..a.find(']').unwrap()]
+                    .split(", ")
+                    .map(|n| n.parse().unwrap())
+                    .collect::<Vec<usize>>();
+                (
+                    field("object_id").parse().unwrap(),
+                    [bbox[0], bbox[1], bbox[2], bbox[3]],
+                    field("area").parse().unwrap(),
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn annotations_box_the_visible_pixels() {
+        let object = |id, class_id, center: Vector3<Float>| Object {
+            id,
+            class_id,
+            material: "lambertian",
+            center,
+            size: 1.0,
+        };
+        let grey = Lambertian::new(ConstantTexture::new(0.5, 0.5, 0.5));
+        // one sphere in the open, and one with a box hiding its right half
+        let open = object(1, SPHERE, Vector3::new(-2.5, 0.0, 0.0));
+        let hidden = object(2, SPHERE, Vector3::new(2.5, 0.0, 0.0));
+        let cube = object(3, CUBE, Vector3::new(3.45, 0.0, 3.0));
+        let mut world = HittableList::default();
+        for sphere in [&open, &hidden] {
+            push_labeled(
+                &mut world,
+                Sphere::new(sphere.center, 1.0, grey.clone()),
+                sphere,
+            );
+        }
+        let half = Vector3::new(1.5, 3.0, 0.5);
+        let wall = Cube::new(cube.center - half, cube.center + half, grey);
+        push_labeled(&mut world, wall, &cube);
+        let look_from = Vector3::new(0.0, 0.0, 10.0);
+        let optics = Optics {
+            vertical_fov: 60.0,
+            ..Optics::default()
+        };
+        let cam = Camera::new(
+            look_from,
+            Vector3::zeros(),
+            Vector3::y(),
+            2.0,
+            optics,
+            0.0,
+            1.0,
+        );
+        let sample = Sample {
+            scene: Scene::new(Arc::new(world), cam),
+            look_from,
+            look_at: Vector3::zeros(),
+            light: object(4, LIGHT, Vector3::new(0.0, 50.0, 0.0)),
+            objects: vec![open, hidden, cube],
+        };
+        let (nx, ny) = (128, 64);
+        let mut ids = Vec::with_capacity(nx * ny);
+        for y in (0..ny).rev() {
+            for x in 0..nx {
+                let u = (x as Float + 0.5) / nx as Float;
+                let v = (y as Float + 0.5) / ny as Float;
+                let ray = sample.scene.camera.get_ray(u, v).unwrap();
+                let hit = sample.scene.world.hit(&ray, ray::T_MIN, Float::MAX);
+                ids.push(hit.map_or(0, |hit| hit.object_id));
+            }
+        }
+        let mut next_id = 1;
+        let boxes = boxes(&annotations(&sample, &ids, nx, ny, 1, &mut next_id));
+        // the light is out of view and gets none
+        assert_eq!(boxes.len(), 3);
+        assert_eq!(next_id, 4);
+        // each box just holds its object's pixels
+        for &(id, [x, y, w, h], area) in &boxes {
+            let inside = (y..y + h)
+                .flat_map(|row| (x..x + w).map(move |column| row * nx + column))
+                .filter(|&i| ids[i] == id)
+                .count();
+            assert_eq!(inside, area);
+            assert_eq!(ids.iter().filter(|&&i| i == id).count(), area);
+        }
+        // a sphere 1 across 10 away, in a 60 degree frame 64 pixels high, is about
+        // 11 pixels across; the hidden one shows half as wide but as high
+        let (_, open, _) = boxes[0];
+        let (_, hidden, _) = boxes[1];
+        let across = 64.0 * (0.1 as Float).asin().tan() / (30.0 as Float).to_radians().tan();
+        assert!((open[2] as Float - across).abs() <= 2.0);
+        assert!((open[3] as Float - across).abs() <= 2.0);
+        assert!(hidden[2] < open[2] * 3 / 4);
+        assert_eq!(hidden[3], open[3]);
+    }
+
+    #[test]
+    fn json_strings_are_escaped() {
+        assert_eq!(json_string("plain"), "\"plain\"");
+        assert_eq!(
+            json_string("a \"b\"\\c\nd\u{1}"),
+            "\"a \\\"b\\\"\\\\c\\nd\\u0001\""
+        );
+    }
+
+    #[test]
+    fn unwritable_directories_are_errors() {
+        // a file where the dataset directory should be
+        let path = std::env::temp_dir().join("rest_of_life_dataset_blocked");
+        fs::write(&path, "").unwrap();
+        let dataset = Dataset {
+            dir: path.join("dataset").to_str().unwrap().to_string(),
+            ..Dataset::new(1)
+        };
+        let settings = RenderSettings {
+            width: 4,
+            height: 3,
+            spp: 1,
+            ..RenderSettings::default()
+        };
+        let message = dataset.generate(&settings).unwrap_err();
+        assert!(message.contains("rest_of_life_dataset_blocked"));
+    }
+}
@@ -0,0 +1,186 @@
+use crate::aabb::AABB;
+use crate::cone::Cone;
+use crate::float::{self, Float};
+use crate::hittable::{HitRecord, Hittable};
+use crate::material::Material;
+use crate::ray::Ray;
+use crate::rng;
+use nalgebra::Vector3;
+
+// Where a decal is projected: an oriented box centred at `center`, width by
+// height across and depth deep, looking along `facing` with `view_up` toward the
+// top of the image. Surfaces inside it turned more than `max_angle` degrees away
+// from facing keep their own material.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Projection {
+    pub center: Vector3<Float>,
+    pub facing: Vector3<Float>,
+    pub view_up: Vector3<Float>,
+    pub width: Float,
+    pub height: Float,
+    pub depth: Float,
+    pub opacity: Float,
+    pub max_angle: Float,
+}
+
+impl Default for Projection {
+    fn default() -> Self {
+        Projection {
+            center: Vector3::zeros(),
+            facing: Vector3::new(0.0, 0.0, 1.0),
+            view_up: Vector3::new(0.0, 1.0, 0.0),
+            width: 1.0,
+            height: 1.0,
+            depth: 1.0,
+            opacity: 1.0,
+            max_angle: 90.0,
+        }
+    }
+}
+
+// Projects a material (typically a Lambertian with an ImageTexture) onto whatever
+// part of the wrapped geometry falls inside an oriented box. The decal's material
+// replaces the underlying one with probability `opacity`, which blends the two
+// stochastically without needing a combined material.
+pub struct Decal<H: Hittable, M: Material> {
+    hittable: H,
+    center: Vector3<Float>,
+    u: Vector3<Float>,
+    v: Vector3<Float>,
+    w: Vector3<Float>,
+    half_width: Float,
+    half_height: Float,
+    half_depth: Float,
+    opacity: Float,
+    cos_cutoff: Float,
+    material: M,
+}
+
+impl<H: Hittable, M: Material> Decal<H, M> {
+    pub fn new(hittable: H, projection: Projection, material: M) -> Self {
+        let w = projection.facing.normalize();
+        let u = projection.view_up.cross(&w).normalize();
+        let v = w.cross(&u);
+        Decal {
+            hittable,
+            center: projection.center,
+            u,
+            v,
+            w,
+            half_width: projection.width / 2.0,
+            half_height: projection.height / 2.0,
+            half_depth: projection.depth / 2.0,
+            opacity: projection.opacity.clamp(0.0, 1.0),
+            cos_cutoff: (projection.max_angle * float::consts::PI / 180.0).cos(),
+            material,
+        }
+    }
+}
+
+impl<H: Hittable, M: Material> Hittable for Decal<H, M> {
+    fn hit(&self, ray: &Ray, t_min: Float, t_max: Float) -> Option<HitRecord<'_>> {
+        self.hittable.hit(ray, t_min, t_max).map(|mut hit| {
+            let local = hit.p - self.center;
+            let a = local.dot(&self.u);
+            let b = local.dot(&self.v);
+            let c = local.dot(&self.w);
+            if a.abs() <= self.half_width
+                && b.abs() <= self.half_height
+                && c.abs() <= self.half_depth
+                && hit.normal.dot(&self.w) >= self.cos_cutoff
+                && rng::uniform() < self.opacity
+            {
+                hit.u = 0.5 + a / (2.0 * self.half_width);
+                hit.v = 0.5 + b / (2.0 * self.half_height);
+                hit.dpdu = 2.0 * self.half_width * self.u;
+                hit.dpdv = 2.0 * self.half_height * self.v;
+                hit.material = &self.material;
+            }
+            hit
+        })
+    }
+
+    fn bounding_box(&self, t0: Float, t1: Float) -> Option<AABB> {
+        self.hittable.bounding_box(t0, t1)
+    }
+
+    fn pdf_value(&self, o: Vector3<Float>, v: Vector3<Float>) -> Float {
+        self.hittable.pdf_value(o, v)
+    }
+
+    fn random(&self, o: Vector3<Float>) -> Vector3<Float> {
+        self.hittable.random(o)
+    }
+
+    fn occlusion(&self, cone: &Cone, t_min: Float, t_max: Float) -> Float {
+        self.hittable.occlusion(cone, t_min, t_max)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cli::Options;
+    use crate::material::{Lambertian, Metal};
+    use crate::ray;
+    use crate::scenefile;
+    use crate::sphere::Sphere;
+    use crate::texture::ConstantTexture;
+
+    #[test]
+    fn decals_cover_what_lies_in_their_box() {
+        let grey = Lambertian::new(ConstantTexture::new(0.5, 0.5, 0.5));
+        let projection = Projection {
+            center: Vector3::new(0.0, 0.0, 1.0),
+            ..Projection::default()
+        };
+        let decal = Decal::new(
+            Sphere::new(Vector3::zeros(), 1.0, grey),
+            projection,
+            Metal::new(Vector3::new(0.9, 0.9, 0.9), 0.0),
+        );
+        let name = |origin: Vector3<Float>, direction: Vector3<Float>| {
+            let ray = Ray::new(origin, direction, 0.0);
+            let hit = decal.hit(&ray, ray::T_MIN, Float::MAX).unwrap();
+            (hit.material.name(), hit.u, hit.v)
+        };
+        // head on, the middle of the image
+        let (material, u, v) = name(Vector3::new(0.0, 0.0, 5.0), -Vector3::z());
+        assert!(material.contains("Metal"));
+        assert!((u - 0.5).abs() < 1e-6 && (v - 0.5).abs() < 1e-6);
+        // right of the box, and on the back, out of its depth
+        let (material, ..) = name(Vector3::new(0.8, 0.0, 5.0), -Vector3::z());
+        assert!(material.contains("Lambertian"));
+        let (material, ..) = name(Vector3::new(0.0, 0.0, -5.0), Vector3::z());
+        assert!(material.contains("Lambertian"));
+    }
+
+    #[test]
+    fn decal_options_project_onto_objects() {
+        let text = "camera from 0 0 10 at 0 0 0\n\
+                    material grey lambertian 0.5 0.5 0.5\n\
+                    material steel metal 0.9 0.9 0.9 0\n\
+                    sphere 0 0 0 1 grey decal steel 0 0 1 0 0 1 2 2 1 up 0 1 0 angle 30\n";
+        let scene = scenefile::parse(text, 1.0, &Options::default()).unwrap();
+        let hit = |origin: Vector3<Float>| {
+            let ray = Ray::new(origin, -Vector3::z(), 0.0);
+            let hit = scene.world.hit(&ray, ray::T_MIN, Float::MAX).unwrap();
+            hit.material.name()
+        };
+        assert!(hit(Vector3::new(0.0, 0.0, 5.0)).contains("Metal"));
+        // within the box, but turned more than 30 degrees from its facing
+        assert!(hit(Vector3::new(0.7, 0.0, 5.0)).contains("Lambertian"));
+        for bad in [
+            "decal rust 0 0 1 0 0 1 1 1 1",
+            "decal steel 0 0 1 0 0 1 0 1 1",
+            "decal steel 0 0 1 0 1 0 1 1 1",
+        ] {
+            let text = format!(
+                "camera from 0 0 10 at 0 0 0\nmaterial grey lambertian 0.5 0.5 0.5\n\
+                 material steel metal 0.9 0.9 0.9 0\nsphere 0 0 0 1 grey {}\n",
+                bad
+            );
+            assert!(scenefile::parse(&text, 1.0, &Options::default()).is_err());
+        }
+    }
+}
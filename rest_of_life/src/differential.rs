@@ -0,0 +1,172 @@
+use crate::float::Float;
+use crate::hittable::HitRecord;
+use crate::ray::{Differentials, Ray};
+use crate::texture::Footprint;
+use nalgebra::Vector3;
+
+// Ray differentials, after pbrt: a camera ray carries the rays through the pixels
+// beside it, and where it meets a surface they meet the plane tangent to it a
+// little way off. How far, in u and v, is the footprint a texture lookup filters
+// over. A specular bounce turns the neighbours as it turns the ray, so mirrors and
+// glass see textures as blurred as the camera would; a diffuse bounce drops them.
+
+// the offsets from the hit point to where the neighbouring rays cross its tangent
+// plane, None where one runs parallel to it
+fn offsets(
+    ray: &Ray,
+    hit: &HitRecord,
+    d: &Differentials,
+) -> Option<(Vector3<Float>, Vector3<Float>)> {
+    let n = hit.normal;
+    let offset = |origin: Vector3<Float>, direction: Vector3<Float>| {
+        let (origin, direction) = (ray.origin() + origin, ray.direction() + direction);
+        let denom = n.dot(&direction);
+        if denom.abs() < 1e-12 {
+            return None;
+        }
+        let t = n.dot(&(hit.p - origin)) / denom;
+        Some(origin + t * direction - hit.p)
+    };
+    Some((
+        offset(d.dx_origin, d.dx_direction)?,
+        offset(d.dy_origin, d.dy_direction)?,
+    ))
+}
+
+// The footprint at a hit of a ray with differentials: the offsets across the
+// pixel solved, by least squares, for the changes in u and v that move the point
+// along dpdu and dpdv as far. None without differentials or on a surface that does
+// not say how its u and v run.
+pub fn footprint(ray: &Ray, hit: &HitRecord) -> Option<Footprint> {
+    let d = ray.differentials()?;
+    let (dpdx, dpdy) = offsets(ray, hit, &d)?;
+    let (a, b, c) = (
+        hit.dpdu.norm_squared(),
+        hit.dpdu.dot(&hit.dpdv),
+        hit.dpdv.norm_squared(),
+    );
+    let det = a * c - b * b;
+    if det <= 1e-6 * a * c {
+        return None;
+    }
+    let solve = |dp: Vector3<Float>| {
+        let (x, y) = (hit.dpdu.dot(&dp), hit.dpdv.dot(&dp));
+        ((c * x - b * y) / det, (a * y - b * x) / det)
+    };
+    let (dudx, dvdx) = solve(dpdx);
+    let (dudy, dvdy) = solve(dpdy);
+    Some(Footprint {
+        dudx,
+        dvdx,
+        dudy,
+        dvdy,
+    })
+}
+
+// The ray a specular bounce scattered at the hit, carrying the incoming ray's
+// differentials turned with it. They leave from where they crossed the tangent
+// plane; their directions keep the part along the surface that the ray's own kept,
+// scaled as refraction scales it, and follow it off the surface. The surface is
+// taken to be flat there, so curved mirrors spread the footprint less than they
+// should. Past the first diffuse bounce the ray's cone goes on with it instead.
+pub fn specular(ray: &Ray, hit: &HitRecord, scattered: Ray) -> Ray {
+    let scattered = scattered.with_cone(ray.continued(hit.t, 0.0));
+    let Some(d) = ray.differentials() else {
+        return scattered;
+    };
+    let Some((dpdx, dpdy)) = offsets(ray, hit, &d) else {
+        return scattered;
+    };
+    let n = hit.normal;
+    let length_in = ray.direction().norm();
+    let length_out = scattered.direction().norm();
+    if length_in == 0.0 || length_out == 0.0 {
+        return scattered;
+    }
+    let wi = ray.direction() / length_in;
+    let wo = scattered.direction() / length_out;
+    let tangential = |w: Vector3<Float>| w - w.dot(&n) * n;
+    let (wi_t, wo_t) = (tangential(wi), tangential(wo));
+    // the ratio of the indices of refraction, or 1 for a reflection
+    let eta = if wi_t.norm() > 1e-6 {
+        wo_t.norm() / wi_t.norm()
+    } else {
+        1.0
+    };
+    let wo_n = wo.dot(&n);
+    let turn = |direction: Vector3<Float>| {
+        // how the unit incoming direction changes, then the outgoing one, which
+        // stays unit length
+        let dwi = (direction - wi * wi.dot(&direction)) / length_in;
+        let dwo_t = eta * tangential(dwi);
+        let dwo_n = if wo_n.abs() > 1e-6 {
+            -wo_t.dot(&dwo_t) / wo_n
+        } else {
+            0.0
+        };
+        length_out * (dwo_t + dwo_n * n)
+    };
+    let differentials = Differentials {
+        dx_origin: dpdx,
+        dx_direction: turn(d.dx_direction),
+        dy_origin: dpdy,
+        dy_direction: turn(d.dy_direction),
+    };
+    scattered.with_differentials(Some(differentials))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::{self, Material};
+    use crate::texture::ConstantTexture;
+
+    fn hit(material: &dyn Material) -> HitRecord<'_> {
+        HitRecord {
+            t: 1.0,
+            u: 0.5,
+            v: 0.5,
+            p: Vector3::zeros(),
+            texture_p: Vector3::zeros(),
+            normal: Vector3::new(0.0, 0.0, 1.0),
+            tangent: Vector3::new(1.0, 0.0, 0.0),
+            dpdu: Vector3::new(2.0, 0.0, 0.0),
+            dpdv: Vector3::new(0.0, 4.0, 0.0),
+            material,
+            object_id: 0,
+            class_id: 0,
+        }
+    }
+
+    // rays from a pinhole one unit above the plane, 0.01 apart where they land
+    fn camera_ray(direction: Vector3<Float>) -> Ray {
+        Ray::new(Vector3::new(0.0, 0.0, 1.0), direction, 0.0).with_differentials(Some(
+            Differentials {
+                dx_origin: Vector3::zeros(),
+                dx_direction: Vector3::new(0.01, 0.0, 0.0),
+                dy_origin: Vector3::zeros(),
+                dy_direction: Vector3::new(0.0, 0.01, 0.0),
+            },
+        ))
+    }
+
+    #[test]
+    fn footprint_follows_the_parameterisation() {
+        let lambertian = material::Lambertian::new(ConstantTexture::new(1.0, 1.0, 1.0));
+        let hit = hit(&lambertian);
+        let ray = camera_ray(Vector3::new(0.0, 0.0, -1.0));
+        let patch = footprint(&ray, &hit).unwrap();
+        assert!((patch.dudx - 0.005).abs() < 1e-6);
+        assert!((patch.dvdy - 0.0025).abs() < 1e-6);
+        assert!(patch.dvdx.abs() < 1e-6 && patch.dudy.abs() < 1e-6);
+        assert!(footprint(&Ray::new(ray.origin(), ray.direction(), 0.0), &hit).is_none());
+
+        // a mirror facing straight back sends the neighbours on from where they
+        // landed, diverging as they came
+        let reflected = Ray::new(hit.p, Vector3::new(0.0, 0.0, 1.0), 0.0);
+        let reflected = specular(&ray, &hit, reflected);
+        let d = reflected.differentials().unwrap();
+        assert!((d.dx_origin - Vector3::new(0.01, 0.0, 0.0)).norm() < 1e-6);
+        assert!((d.dx_direction - Vector3::new(0.01, 0.0, 0.0)).norm() < 1e-6);
+    }
+}
@@ -0,0 +1,112 @@
+use crate::aabb::AABB;
+use crate::cone::Cone;
+use crate::float::{self, Float};
+use crate::hittable::{HitRecord, Hittable};
+use crate::material::Material;
+use crate::ray::{self, Ray};
+use crate::rng;
+use crate::sampling::ONB;
+use nalgebra::Vector3;
+
+// A flat round disk facing along its normal. The hit's u is the angle around the
+// centre as a share of a turn, v the distance out as a share of the radius.
+pub struct Disk<M: Material> {
+    center: Vector3<Float>,
+    radius: Float,
+    uvw: ONB,
+    material: M,
+}
+
+impl<M: Material> Disk<M> {
+    pub fn new(center: Vector3<Float>, normal: Vector3<Float>, radius: Float, material: M) -> Self {
+        Disk {
+            center,
+            radius,
+            uvw: ONB::build_from_w(&normal),
+            material,
+        }
+    }
+}
+
+impl<M: Material> Hittable for Disk<M> {
+    fn hit(&self, ray: &Ray, t_min: Float, t_max: Float) -> Option<HitRecord<'_>> {
+        let normal = self.uvw.w();
+        let t = (self.center - ray.origin()).dot(&normal) / ray.direction().dot(&normal);
+        if !(t_min..=t_max).contains(&t) {
+            return None;
+        }
+        let p = ray.point_at_parameter(t);
+        let local = self.uvw.to_local(&(p - self.center));
+        let r = local.x.hypot(local.y);
+        if r > self.radius {
+            return None;
+        }
+        // put back on the disk's plane, off which the rounding in t leaves p
+        let p = self.center + self.uvw.local(&Vector3::new(local.x, local.y, 0.0));
+        let phi = local.y.atan2(local.x);
+        // u turns once around the centre and v runs out to the rim
+        let radial = self.uvw.local(&Vector3::new(phi.cos(), phi.sin(), 0.0));
+        Some(HitRecord {
+            t,
+            u: (phi + float::consts::PI) / (2.0 * float::consts::PI),
+            v: r / self.radius,
+            p,
+            texture_p: p,
+            normal,
+            tangent: self.uvw.u(),
+            dpdu: 2.0 * float::consts::PI * r * normal.cross(&radial),
+            dpdv: self.radius * radial,
+            material: &self.material,
+            object_id: 0,
+            class_id: 0,
+        })
+    }
+
+    // the disk's extent along each axis shrinks with how closely it faces it
+    fn bounding_box(&self, _t0: Float, _t1: Float) -> Option<AABB> {
+        let normal = self.uvw.w();
+        let extent = Vector3::new(
+            (1.0 - normal.x.powi(2)).max(0.0).sqrt(),
+            (1.0 - normal.y.powi(2)).max(0.0).sqrt(),
+            (1.0 - normal.z.powi(2)).max(0.0).sqrt(),
+        ) * self.radius
+            + Vector3::new(0.0001, 0.0001, 0.0001);
+        Some(AABB::new(self.center - extent, self.center + extent))
+    }
+
+    fn pdf_value(&self, o: Vector3<Float>, v: Vector3<Float>) -> Float {
+        if let Some(hit) = self.hit(&Ray::new(o, v, 0.0), ray::T_MIN, Float::MAX) {
+            let area = float::consts::PI * self.radius.powi(2);
+            let distance_squared = hit.t.powi(2) * v.norm_squared();
+            let cosine = v.dot(&hit.normal).abs() / v.norm();
+            if cosine != 0.0 {
+                distance_squared / (cosine * area)
+            } else {
+                0.0
+            }
+        } else {
+            0.0
+        }
+    }
+
+    fn random(&self, o: Vector3<Float>) -> Vector3<Float> {
+        self.center + self.uvw.local(&(self.radius * rng::in_unit_disk())) - o
+    }
+
+    fn sample_surface(&self) -> Option<(Vector3<Float>, Vector3<Float>, Float)> {
+        let p = self.center + self.uvw.local(&(self.radius * rng::in_unit_disk()));
+        let area = float::consts::PI * self.radius.powi(2);
+        Some((p, self.uvw.w(), area))
+    }
+
+    // coverage where the axis crosses the disk's plane
+    fn occlusion(&self, cone: &Cone, t_min: Float, t_max: Float) -> Float {
+        let normal = self.uvw.w();
+        let t = (self.center - cone.origin).dot(&normal) / cone.direction.dot(&normal);
+        if !(t_min..=t_max).contains(&t) {
+            return 0.0;
+        }
+        let distance = (cone.at(t) - self.center).norm() - self.radius;
+        cone.coverage(distance, t)
+    }
+}
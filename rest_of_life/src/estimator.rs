@@ -0,0 +1,99 @@
+use crate::float::{self, Float};
+use nalgebra::Vector3;
+use std::str::FromStr;
+
+// How the samples of a pixel are combined. Median of means averages k interleaved
+// groups of samples and takes the per channel median of the group means, so a rare
+// extremely bright path spoils one group instead of the whole pixel.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum Estimator {
+    #[default]
+    Mean,
+    MedianOfMeans(usize),
+}
+
+impl FromStr for Estimator {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "mean" => Ok(Estimator::Mean),
+            _ => match s.parse::<usize>() {
+                Ok(k) if k > 0 => Ok(Estimator::MedianOfMeans(k)),
+                _ => Err(format!("invalid estimator: {}", s)),
+            },
+        }
+    }
+}
+
+fn median(values: &mut [Float]) -> Float {
+    values.sort_unstable_by(|a, b| a.partial_cmp(b).unwrap());
+    let n = values.len();
+    if n % 2 == 1 {
+        values[n / 2]
+    } else {
+        0.5 * (values[n / 2 - 1] + values[n / 2])
+    }
+}
+
+pub fn luminance(c: &Vector3<Float>) -> Float {
+    c.dot(&Vector3::new(0.2126, 0.7152, 0.0722))
+}
+
+// Drops the samples whose luminance lies more than `sigma` standard deviations above
+// the mean of the pixel's other samples. Judging each sample against the others keeps
+// a lone firefly from inflating the spread it is measured by; the darkest sample can
+// never be dropped, so some always remain.
+pub fn reject_outliers(samples: Vec<Vector3<Float>>, sigma: Float) -> Vec<Vector3<Float>> {
+    let n = samples.len();
+    if n < 3 {
+        return samples;
+    }
+    let (sum, sum_sq) = samples.iter().map(luminance).fold((0.0, 0.0), |(a, b), l| {
+        (a + float::double(l), b + float::double(l * l))
+    });
+    let others = (n - 1) as f64;
+    samples
+        .into_iter()
+        .filter(|s| {
+            let l = float::double(luminance(s));
+            let mean = (sum - l) / others;
+            let variance = ((sum_sq - l * l) / others - mean * mean).max(0.0);
+            l <= mean + float::double(sigma) * variance.sqrt()
+        })
+        .collect()
+}
+
+impl Estimator {
+    pub fn combine(&self, samples: impl Iterator<Item = Vector3<Float>>) -> Vector3<Float> {
+        match *self {
+            Estimator::Mean => {
+                let (sum, n) = samples.fold((Vector3::zeros(), 0), |(sum, n), s| (sum + s, n + 1));
+                sum / n as Float
+            }
+            Estimator::MedianOfMeans(k) => {
+                let mut sums = vec![Vector3::zeros(); k];
+                let mut counts = vec![0; k];
+                for (i, s) in samples.enumerate() {
+                    sums[i % k] += s;
+                    counts[i % k] += 1;
+                }
+                let means = sums
+                    .iter()
+                    .zip(counts.iter())
+                    .filter(|(_, &n)| n > 0)
+                    .map(|(sum, &n)| sum / n as Float)
+                    .collect::<Vec<Vector3<Float>>>();
+                let mut channel = vec![0.0; means.len()];
+                let mut result = Vector3::zeros();
+                for a in 0..3 {
+                    for (c, mean) in channel.iter_mut().zip(means.iter()) {
+                        *c = mean[a];
+                    }
+                    result[a] = median(&mut channel);
+                }
+                result
+            }
+        }
+    }
+}
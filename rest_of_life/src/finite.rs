@@ -0,0 +1,112 @@
+use crate::float::Float;
+use nalgebra::Vector3;
+use std::cell::Cell;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+
+// A sample a numerical blowup has left NaN or infinite, a pdf rounding to nothing
+// or a normal with no length, poisons the mean of its whole pixel and shows as a
+// black or white speck. Such samples count as black instead, and the render
+// counts how many did, so the specks neither show nor go unnoticed. With debugging
+// on, each is logged with its pixel, and where the path tracer can tell, with the
+// bounce and the material its radiance stopped being finite at.
+static DEBUG: AtomicBool = AtomicBool::new(false);
+static REPLACED: AtomicU64 = AtomicU64::new(0);
+
+thread_local! {
+    // the pixel and sample the current thread is tracing, and whether that sample
+    // has been logged
+    static SAMPLE: Cell<(usize, usize, usize, bool)> = const { Cell::new((0, 0, 0, false)) };
+}
+
+pub fn set_debug(debug: bool) {
+    DEBUG.store(debug, Ordering::Relaxed);
+}
+
+#[inline(always)]
+pub fn debugging() -> bool {
+    DEBUG.load(Ordering::Relaxed)
+}
+
+// Sets the pixel, column and row from the top left, and the sample the current
+// thread is tracing, for the log to name.
+pub fn set_pixel(x: usize, row: usize, sample: usize) {
+    SAMPLE.with(|current| current.set((x, row, sample, false)));
+}
+
+// Marks the current sample as logged already, for a tracer that logged it while
+// another thread was tracing it.
+pub fn set_logged() {
+    SAMPLE.with(|current| {
+        let (x, row, sample, _) = current.get();
+        current.set((x, row, sample, true));
+    });
+}
+
+pub fn is_finite(v: &Vector3<Float>) -> bool {
+    v.iter().all(|c| c.is_finite())
+}
+
+// Logs the current sample's radiance having stopped being finite, at the bounce
+// and material given, if known.
+pub fn report(vertex: Option<(i32, &str)>) {
+    let (x, row, sample, _) = SAMPLE.with(|current| current.get());
+    match vertex {
+        Some((bounce, material)) => eprintln!(
+            "non-finite radiance in pixel {},{} sample {}, at bounce {} on {}",
+            x, row, sample, bounce, material
+        ),
+        None => eprintln!(
+            "non-finite radiance in pixel {},{} sample {}",
+            x, row, sample
+        ),
+    }
+    SAMPLE.with(|current| current.set((x, row, sample, true)));
+}
+
+// the sample's radiance, or black in place of a non-finite one, which is counted
+pub fn replace(radiance: Vector3<Float>) -> Vector3<Float> {
+    if is_finite(&radiance) {
+        return radiance;
+    }
+    REPLACED.fetch_add(1, Ordering::Relaxed);
+    Vector3::zeros()
+}
+
+// as replace, logging the non-finite samples that have not been already
+pub fn guard(radiance: Vector3<Float>) -> Vector3<Float> {
+    if debugging() && !is_finite(&radiance) && !SAMPLE.with(|current| current.get().3) {
+        report(None);
+    }
+    replace(radiance)
+}
+
+// the samples replaced so far, starting the count over
+pub fn take_replaced() -> u64 {
+    REPLACED.swap(0, Ordering::Relaxed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn non_finite_samples_count_as_black() {
+        let seen = Vector3::new(0.5, 2.0, 0.0);
+        assert_eq!(replace(seen), seen);
+        take_replaced();
+        assert_eq!(
+            replace(Vector3::new(0.5, Float::NAN, 1.0)),
+            Vector3::zeros()
+        );
+        assert_eq!(
+            replace(Vector3::new(Float::INFINITY, 0.0, 0.0)),
+            Vector3::zeros()
+        );
+        assert_eq!(
+            replace(Vector3::new(0.0, 0.0, Float::NEG_INFINITY)),
+            Vector3::zeros()
+        );
+        // tests rendering alongside may add samples of their own
+        assert!(take_replaced() >= 3);
+    }
+}
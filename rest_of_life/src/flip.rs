@@ -0,0 +1,241 @@
+use crate::float::{self, Float};
+use crate::render::Image;
+use nalgebra::Vector3;
+
+// Viewing conditions and exponents of the paper: a 0.7 m wide 4k monitor seen from
+// 0.7 m gives 67 pixels per degree.
+const PIXELS_PER_DEGREE: Float = 67.0;
+const QC: Float = 0.7;
+const QF: Float = 0.5;
+const PC: Float = 0.4;
+const PT: Float = 0.95;
+// width in degrees of the edge and point detectors
+const FEATURE_WIDTH: Float = 0.082;
+
+// Mean LDR FLIP error of image against reference (Andersson et al. 2020), in [0, 1]:
+// a colour difference between the images as filtered by the eye's contrast
+// sensitivity, raised where edges and points differ. Values are clamped to [0, 1]
+// first, so run it on tone mapped images to judge highlights.
+pub fn mean_error(image: &Image, reference: &Image) -> Float {
+    assert_eq!(
+        (image.width, image.height),
+        (reference.width, reference.height),
+        "flip needs images of the same size"
+    );
+    let errors = errors(image, reference);
+    errors.iter().sum::<Float>() / errors.len() as Float
+}
+
+// the per pixel error, rows from the top
+pub fn errors(image: &Image, reference: &Image) -> Vec<Float> {
+    let (width, height) = (image.width, image.height);
+    let colour_test = colour(image);
+    let colour_reference = colour(reference);
+    let feature_test = features(image);
+    let feature_reference = features(reference);
+    let cmax = hyab(
+        &hunt(&lab(Vector3::new(0.0, 1.0, 0.0))),
+        &hunt(&lab(Vector3::new(0.0, 0.0, 1.0))),
+    )
+    .powf(QC);
+    (0..width * height)
+        .map(|i| {
+            let delta = hyab(&colour_test[i], &colour_reference[i]).powf(QC);
+            // compresses large colour differences into the top of the range
+            let colour = if delta < PC * cmax {
+                PT / (PC * cmax) * delta
+            } else {
+                PT + (delta - PC * cmax) / (cmax - PC * cmax) * (1.0 - PT)
+            };
+            let (edge_test, point_test) = feature_test[i];
+            let (edge_reference, point_reference) = feature_reference[i];
+            let feature = (edge_test - edge_reference)
+                .abs()
+                .max((point_test - point_reference).abs());
+            let feature = (feature / float::consts::SQRT_2).powf(QF);
+            colour.powf(1.0 - feature)
+        })
+        .collect()
+}
+
+fn linear_rgb_to_xyz(c: &Vector3<Float>) -> Vector3<Float> {
+    Vector3::new(
+        0.4124 * c.x + 0.3576 * c.y + 0.1805 * c.z,
+        0.2126 * c.x + 0.7152 * c.y + 0.0722 * c.z,
+        0.0193 * c.x + 0.1192 * c.y + 0.9505 * c.z,
+    )
+}
+
+fn xyz_to_linear_rgb(c: &Vector3<Float>) -> Vector3<Float> {
+    Vector3::new(
+        3.2406 * c.x - 1.5372 * c.y - 0.4986 * c.z,
+        -0.9689 * c.x + 1.8758 * c.y + 0.0415 * c.z,
+        0.0557 * c.x - 0.2040 * c.y + 1.0570 * c.z,
+    )
+}
+
+// the white the colour spaces are relative to, that of linear rgb (1, 1, 1)
+fn white() -> Vector3<Float> {
+    linear_rgb_to_xyz(&Vector3::new(1.0, 1.0, 1.0))
+}
+
+// opponent space whose channels the contrast sensitivity filters apply to
+fn ycxcz(c: &Vector3<Float>) -> Vector3<Float> {
+    let xyz = linear_rgb_to_xyz(c).component_div(&white());
+    Vector3::new(
+        116.0 * xyz.y - 16.0,
+        500.0 * (xyz.x - xyz.y),
+        200.0 * (xyz.y - xyz.z),
+    )
+}
+
+fn ycxcz_to_linear_rgb(c: &Vector3<Float>) -> Vector3<Float> {
+    let y = (c.x + 16.0) / 116.0;
+    let xyz = Vector3::new(y + c.y / 500.0, y, y - c.z / 200.0).component_mul(&white());
+    xyz_to_linear_rgb(&xyz)
+}
+
+fn lab(c: Vector3<Float>) -> Vector3<Float> {
+    let f = |t: Float| {
+        if t > 0.008856 {
+            t.cbrt()
+        } else {
+            7.787 * t + 16.0 / 116.0
+        }
+    };
+    let xyz = linear_rgb_to_xyz(&c).component_div(&white()).map(f);
+    Vector3::new(
+        116.0 * xyz.y - 16.0,
+        500.0 * (xyz.x - xyz.y),
+        200.0 * (xyz.y - xyz.z),
+    )
+}
+
+// chroma scaled down with lightness, as dark colours are told apart less
+fn hunt(c: &Vector3<Float>) -> Vector3<Float> {
+    Vector3::new(c.x, 0.01 * c.x * c.y, 0.01 * c.x * c.z)
+}
+
+fn hyab(a: &Vector3<Float>, b: &Vector3<Float>) -> Float {
+    (a.x - b.x).abs() + ((a.y - b.y).powi(2) + (a.z - b.z).powi(2)).sqrt()
+}
+
+// Each pixel's Hunt adjusted Lab colour after the contrast sensitivity filters, one
+// sum of Gaussians per opponent channel.
+fn colour(image: &Image) -> Vec<Vector3<Float>> {
+    // a1, b1, a2, b2 of the achromatic, red-green and blue-yellow filters
+    let parameters = [
+        (1.0, 0.0047, 0.0, 1e-5),
+        (1.0, 0.0053, 0.0, 1e-5),
+        (34.1, 0.04, 13.5, 0.025),
+    ];
+    let radius = (3.0 * (0.04 / (2.0 * float::consts::PI.powi(2))).sqrt() * PIXELS_PER_DEGREE)
+        .ceil() as isize;
+    let kernels: Vec<Vec<Float>> = parameters
+        .iter()
+        .map(|&(a1, b1, a2, b2): &(Float, Float, Float, Float)| {
+            let mut kernel = Vec::new();
+            for y in -radius..=radius {
+                for x in -radius..=radius {
+                    let r2 = ((x * x + y * y) as Float) / PIXELS_PER_DEGREE.powi(2);
+                    let pi2 = float::consts::PI.powi(2);
+                    kernel.push(
+                        a1 * (float::consts::PI / b1).sqrt() * (-pi2 * r2 / b1).exp()
+                            + a2 * (float::consts::PI / b2).sqrt() * (-pi2 * r2 / b2).exp(),
+                    );
+                }
+            }
+            let sum: Float = kernel.iter().sum();
+            kernel.iter().map(|k| k / sum).collect()
+        })
+        .collect();
+    let opponent: Vec<Vector3<Float>> = image.pixels.iter().map(|c| ycxcz(&clamped(c))).collect();
+    let filtered = convolve(image, radius, |x, y, dx, dy| {
+        let c = opponent[y * image.width + x];
+        let k = ((dy + radius) * (2 * radius + 1) + dx + radius) as usize;
+        Vector3::new(
+            kernels[0][k] * c.x,
+            kernels[1][k] * c.y,
+            kernels[2][k] * c.z,
+        )
+    });
+    filtered
+        .iter()
+        .map(|c| hunt(&lab(clamped(&ycxcz_to_linear_rgb(c)))))
+        .collect()
+}
+
+// Each pixel's edge and point strength in the normalized luminance, from the first
+// and second derivatives of a Gaussian.
+fn features(image: &Image) -> Vec<(Float, Float)> {
+    let sigma = 0.5 * FEATURE_WIDTH * PIXELS_PER_DEGREE;
+    let radius = (3.0 * sigma).ceil() as isize;
+    let gaussian = |x: isize, y: isize| (-((x * x + y * y) as Float) / (2.0 * sigma * sigma)).exp();
+    let mut edge = Vec::new();
+    let mut point = Vec::new();
+    for y in -radius..=radius {
+        for x in -radius..=radius {
+            edge.push(-(x as Float) * gaussian(x, y));
+            point.push(((x * x) as Float / (sigma * sigma) - 1.0) * gaussian(x, y));
+        }
+    }
+    // positive and negative weights each sum to one, so flat regions give zero
+    let balance = |kernel: Vec<Float>| {
+        let positive: Float = kernel.iter().filter(|k| **k > 0.0).sum();
+        let negative: Float = -kernel.iter().filter(|k| **k < 0.0).sum::<Float>();
+        kernel
+            .into_iter()
+            .map(|k| if k > 0.0 { k / positive } else { k / negative })
+            .collect::<Vec<Float>>()
+    };
+    let (edge, point) = (balance(edge), balance(point));
+    let luminance: Vec<Float> = image
+        .pixels
+        .iter()
+        .map(|c| (ycxcz(&clamped(c)).x + 16.0) / 116.0)
+        .collect();
+    let size = 2 * radius + 1;
+    // the x derivative kernels as given, transposed for y
+    let detect = |kernel: &[Float]| {
+        convolve(image, radius, |x, y, dx, dy| {
+            let l = luminance[y * image.width + x];
+            let kx = ((dy + radius) * size + dx + radius) as usize;
+            let ky = ((dx + radius) * size + dy + radius) as usize;
+            Vector3::new(kernel[kx] * l, kernel[ky] * l, 0.0)
+        })
+    };
+    detect(&edge)
+        .iter()
+        .zip(detect(&point).iter())
+        .map(|(e, p)| (e.x.hypot(e.y), p.x.hypot(p.y)))
+        .collect()
+}
+
+fn clamped(c: &Vector3<Float>) -> Vector3<Float> {
+    c.map(|v| v.clamp(0.0, 1.0))
+}
+
+// Sums weight(x, y, dx, dy) over the window of each pixel, where (x, y) is the pixel
+// at offset (dx, dy) with the borders extended.
+fn convolve(
+    image: &Image,
+    radius: isize,
+    weight: impl Fn(usize, usize, isize, isize) -> Vector3<Float>,
+) -> Vec<Vector3<Float>> {
+    let (width, height) = (image.width as isize, image.height as isize);
+    let mut out = Vec::with_capacity(image.pixels.len());
+    for y in 0..height {
+        for x in 0..width {
+            let mut sum = Vector3::zeros();
+            for dy in -radius..=radius {
+                for dx in -radius..=radius {
+                    let sx = (x + dx).clamp(0, width - 1) as usize;
+                    let sy = (y + dy).clamp(0, height - 1) as usize;
+                    sum += weight(sx, sy, dx, dy);
+                }
+            }
+            out.push(sum);
+        }
+    }
+    out
+}
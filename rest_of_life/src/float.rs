@@ -0,0 +1,29 @@
+// The scalar the renderer computes in, rt-core's Real, so the weekend chapters'
+// helpers take and give it as it is. It is f32, which is quick and keeps meshes
+// and images small; the f64 feature makes it f64 throughout, rt-core's included,
+// to tell the banding and surface acne of single precision from faults of the
+// renderer's own, or to compare with the weekend crates, which are f64. Files keep
+// their own precision whichever is chosen: images, raw frames and meshes are read
+// and written as they always were, and the GPU backend's buffers stay f32.
+pub use rt_core::vec3::Real as Float;
+
+// the constants of the chosen scalar, as std::f32::consts
+pub use rt_core::vec3::consts;
+
+// four of the chosen scalar in one SIMD register
+#[cfg(not(feature = "f64"))]
+pub use wide::f32x4 as Float4;
+#[cfg(feature = "f64")]
+pub use wide::f64x4 as Float4;
+
+// a scalar as the f32 files and the GPU buffers hold, rounded if it is f64
+#[allow(clippy::unnecessary_cast)]
+pub fn single(x: Float) -> f32 {
+    x as f32
+}
+
+// a scalar widened to f64, for sums that would lose precision in f32
+#[allow(clippy::unnecessary_cast)]
+pub fn double(x: Float) -> f64 {
+    x as f64
+}
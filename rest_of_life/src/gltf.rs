@@ -0,0 +1,571 @@
+use crate::cli::Options;
+use crate::float::Float;
+use crate::scene::Scene;
+
+// whether --scene-file names a glTF file rather than a scene description
+pub fn is_gltf(path: &str) -> bool {
+    let path = path.to_ascii_lowercase();
+    path.ends_with(".gltf") || path.ends_with(".glb")
+}
+
+// Imports a glTF 2.0 file, .gltf or .glb, with --features gltf, so assets exported
+// from Blender render as they are. The default scene's node hierarchy is flattened
+// into world space: each primitive becomes a triangle mesh with its normals and
+// first texture coordinates, and the first perspective camera found sees it, at
+// the render's aspect ratio and focused on the middle of the scene. Without one a
+// camera looks down -z at everything. Metallic-roughness materials become a mix of
+// a diffuse base and GGX metal by their metallic factor, with the base colour,
+// metallic and roughness textures mapped on; emissive ones become lights, sampled
+// directly. Scenes without emitters are lit by the gallery's sky. Punctual lights,
+// alpha and the other extensions are ignored.
+#[cfg(feature = "gltf")]
+pub fn load(path: &str, aspect: Float, options: &Options) -> Result<Scene, String> {
+    import::load(path, aspect, options)
+}
+
+#[cfg(not(feature = "gltf"))]
+pub fn load(_path: &str, _aspect: Float, _options: &Options) -> Result<Scene, String> {
+    Err(String::from(
+        "glTF files need rest_of_life built with --features gltf",
+    ))
+}
+
+#[cfg(feature = "gltf")]
+mod import {
+    use crate::aabb::{self, AABB};
+    use crate::background::SolidColor;
+    use crate::camera::{Camera, Optics};
+    use crate::cli::Options;
+    use crate::float::Float;
+    use crate::hittable::{Hittable, HittableList};
+    use crate::material::{Anisotropic, DiffuseLight, Lambertian, Material, Mix};
+    use crate::scene::{self, Scene};
+    use crate::texture::{ConstantTexture, Footprint, ImageTexture, Texture};
+    use crate::triangle::{Triangle, TriangleMesh};
+    use ::gltf::camera::Projection;
+    use ::gltf::image::Format;
+    use ::gltf::mesh::Mode;
+    use nalgebra::{Matrix4, Point3, Vector3};
+    use std::collections::HashMap;
+    use std::sync::Arc;
+
+    const CAMERA_FOV: Float = 40.0;
+
+    // How a texture reads the image it maps: a colour decoded from sRGB, or the
+    // metallic factor from the blue channel or the roughness from the green one of a
+    // metallic-roughness image, both in the red channel as Mix and Anisotropic read
+    // them. Roughness comes out squared, the GGX alpha it scales.
+    #[derive(Clone, Copy)]
+    enum Channel {
+        Colour,
+        Metallic,
+        Roughness,
+    }
+
+    // An image repeated over the uv plane, scaled by a factor
+    #[derive(Clone)]
+    struct MapTexture {
+        image: Arc<ImageTexture>,
+        factor: Vector3<Float>,
+        channel: Channel,
+    }
+
+    impl MapTexture {
+        // the channel's value from the image's colour
+        fn channel(&self, c: Vector3<Float>) -> Vector3<Float> {
+            let value = match self.channel {
+                Channel::Colour => c.map(linear),
+                Channel::Metallic => Vector3::repeat(c.z),
+                Channel::Roughness => Vector3::repeat(c.y * c.y),
+            };
+            value.component_mul(&self.factor)
+        }
+    }
+
+    impl Texture for MapTexture {
+        fn value(&self, u: Float, v: Float, p: &Vector3<Float>) -> Vector3<Float> {
+            self.channel(self.image.value(u.rem_euclid(1.0), v.rem_euclid(1.0), p))
+        }
+
+        fn filtered(
+            &self,
+            u: Float,
+            v: Float,
+            p: &Vector3<Float>,
+            footprint: &Footprint,
+        ) -> Vector3<Float> {
+            let (u, v) = (u.rem_euclid(1.0), v.rem_euclid(1.0));
+            self.channel(self.image.filtered(u, v, p, footprint))
+        }
+    }
+
+    // a vector as glTF stores it, in single precision
+    fn vector(v: [f32; 3]) -> Vector3<Float> {
+        Vector3::from(v).cast()
+    }
+
+    fn linear(c: Float) -> Float {
+        if c <= 0.04045 {
+            c / 12.92
+        } else {
+            ((c + 0.055) / 1.055).powf(2.4)
+        }
+    }
+
+    // a colour factor, or the image it scales if the material has one
+    fn texture(
+        info: Option<::gltf::texture::Info>,
+        images: &[Arc<ImageTexture>],
+        factor: Vector3<Float>,
+        channel: Channel,
+    ) -> Arc<dyn Texture> {
+        match info {
+            Some(info) => Arc::new(MapTexture {
+                image: images[info.texture().source().index()].clone(),
+                factor,
+                channel,
+            }),
+            None => Arc::new(ConstantTexture::new(factor.x, factor.y, factor.z)),
+        }
+    }
+
+    // the image as the 8 bit rgb ImageTexture holds
+    fn image(data: &::gltf::image::Data) -> ImageTexture {
+        let (channels, bytes) = match data.format {
+            Format::R8 => (1, 1),
+            Format::R8G8 => (2, 1),
+            Format::R8G8B8 => (3, 1),
+            Format::R8G8B8A8 => (4, 1),
+            Format::R16 => (1, 2),
+            Format::R16G16 => (2, 2),
+            Format::R16G16B16 => (3, 2),
+            Format::R16G16B16A16 => (4, 2),
+            Format::R32G32B32FLOAT => (3, 4),
+            Format::R32G32B32A32FLOAT => (4, 4),
+        };
+        let component = |pixel: &[u8], i: usize| -> u8 {
+            let at = &pixel[i * bytes..(i + 1) * bytes];
+            match bytes {
+                1 => at[0],
+                2 => (u16::from_ne_bytes([at[0], at[1]]) >> 8) as u8,
+                _ => {
+                    let value = f32::from_ne_bytes([at[0], at[1], at[2], at[3]]);
+                    (value.clamp(0.0, 1.0) * 255.0).round() as u8
+                }
+            }
+        };
+        let rgb = data
+            .pixels
+            .chunks_exact(channels * bytes)
+            .flat_map(|pixel| {
+                // a grey image's value fills every channel
+                let grey = component(pixel, 0);
+                let at = |i: usize| {
+                    if channels == 1 {
+                        grey
+                    } else if i < channels {
+                        component(pixel, i)
+                    } else {
+                        0
+                    }
+                };
+                [at(0), at(1), at(2)]
+            })
+            .collect();
+        ImageTexture::new(rgb, data.width, data.height)
+    }
+
+    // the renderer's material for a glTF one and whether it emits
+    fn material(
+        material: &::gltf::Material,
+        images: &[Arc<ImageTexture>],
+    ) -> (Arc<dyn Material>, bool) {
+        let emissive = vector(material.emissive_factor())
+            * material.emissive_strength().unwrap_or(1.0) as Float;
+        if emissive.max() > 0.0 {
+            let emit = texture(
+                material.emissive_texture(),
+                images,
+                emissive,
+                Channel::Colour,
+            );
+            return (Arc::new(DiffuseLight::new(emit)), true);
+        }
+        let pbr = material.pbr_metallic_roughness();
+        let [r, g, b, _] = pbr.base_color_factor();
+        let base = texture(
+            pbr.base_color_texture(),
+            images,
+            vector([r, g, b]),
+            Channel::Colour,
+        );
+        let diffuse = Lambertian::new(base.clone());
+        let alpha = (pbr.roughness_factor() as Float).powi(2).max(1e-3);
+        let metallic_roughness = pbr.metallic_roughness_texture();
+        let roughness = texture(
+            metallic_roughness.clone(),
+            images,
+            Vector3::repeat(1.0),
+            Channel::Roughness,
+        );
+        let metal = Anisotropic::with_roughness_map(base, alpha, alpha, roughness);
+        let metallic = pbr.metallic_factor() as Float;
+        let material: Arc<dyn Material> = match metallic_roughness {
+            Some(_) => Arc::new(Mix::with_mask(
+                diffuse,
+                metal,
+                texture(
+                    metallic_roughness,
+                    images,
+                    Vector3::repeat(metallic),
+                    Channel::Metallic,
+                ),
+            )),
+            None if metallic <= 0.0 => Arc::new(diffuse),
+            None if metallic >= 1.0 => Arc::new(metal),
+            None => Arc::new(Mix::new(diffuse, metal, metallic)),
+        };
+        (material, false)
+    }
+
+    // a camera node's position, forward and up directions in world space, and its
+    // vertical field of view in degrees
+    type Placement = (Vector3<Float>, Vector3<Float>, Vector3<Float>, Float);
+
+    // what the node hierarchy has been flattened into
+    #[derive(Default)]
+    struct Flattened {
+        meshes: Vec<(Arc<dyn Hittable>, bool)>,
+        camera: Option<Placement>,
+    }
+
+    struct Importer<'a> {
+        buffers: &'a [::gltf::buffer::Data],
+        images: Vec<Arc<ImageTexture>>,
+        // by material index, None for the default material
+        materials: HashMap<Option<usize>, (Arc<dyn Material>, bool)>,
+    }
+
+    impl<'a> Importer<'a> {
+        fn node(&mut self, node: ::gltf::Node, parent: &Matrix4<Float>, out: &mut Flattened) {
+            let transform = parent * Matrix4::from(node.transform().matrix()).cast();
+            if let Some(mesh) = node.mesh() {
+                for primitive in mesh.primitives() {
+                    if let Some(mesh) = self.primitive(&primitive, &transform) {
+                        out.meshes.push(mesh);
+                    }
+                }
+            }
+            if let Some(camera) = node.camera() {
+                if let (None, Projection::Perspective(perspective)) =
+                    (&out.camera, camera.projection())
+                {
+                    let from = transform.transform_point(&Point3::origin()).coords;
+                    let forward = transform.transform_vector(&-Vector3::z()).normalize();
+                    let up = transform.transform_vector(&Vector3::y()).normalize();
+                    out.camera = Some((
+                        from,
+                        forward,
+                        up,
+                        (perspective.yfov() as Float).to_degrees(),
+                    ));
+                }
+            }
+            for child in node.children() {
+                self.node(child, &transform, out);
+            }
+        }
+
+        // a triangle mesh in world space and whether it emits, None for primitives
+        // of points or lines or without triangles
+        fn primitive(
+            &mut self,
+            primitive: &::gltf::Primitive,
+            transform: &Matrix4<Float>,
+        ) -> Option<(Arc<dyn Hittable>, bool)> {
+            let buffers = self.buffers;
+            let reader = primitive.reader(|buffer| Some(&buffers[buffer.index()]));
+            let positions: Vec<Vector3<Float>> = reader
+                .read_positions()?
+                .map(|p| transform.transform_point(&Point3::from(vector(p))).coords)
+                .collect();
+            let indices: Vec<usize> = match reader.read_indices() {
+                Some(indices) => indices.into_u32().map(|i| i as usize).collect(),
+                None => (0..positions.len()).collect(),
+            };
+            let corners: Vec<[usize; 3]> = match primitive.mode() {
+                Mode::Triangles => indices
+                    .chunks_exact(3)
+                    .map(|t| [t[0], t[1], t[2]])
+                    .collect(),
+                // every other triangle of a strip winds the other way
+                Mode::TriangleStrip => (0..indices.len().saturating_sub(2))
+                    .map(|i| match i % 2 {
+                        0 => [indices[i], indices[i + 1], indices[i + 2]],
+                        _ => [indices[i + 1], indices[i], indices[i + 2]],
+                    })
+                    .collect(),
+                Mode::TriangleFan => (1..indices.len().saturating_sub(1))
+                    .map(|i| [indices[0], indices[i], indices[i + 1]])
+                    .collect(),
+                _ => return None,
+            };
+            let corners: Vec<[usize; 3]> = corners
+                .into_iter()
+                .filter(|t| t.iter().all(|&i| i < positions.len()))
+                .collect();
+            if corners.is_empty() {
+                return None;
+            }
+            let gltf_material = primitive.material();
+            let (material, emits) = self
+                .materials
+                .entry(gltf_material.index())
+                .or_insert_with(|| material(&gltf_material, &self.images))
+                .clone();
+            // normals go to world space by the inverse transpose, which keeps them
+            // perpendicular to scaled surfaces
+            let normal_transform = transform.try_inverse().map(|m| m.transpose());
+            let normals: Option<Vec<Vector3<Float>>> = match (&normal_transform, emits) {
+                (Some(m), false) => reader
+                    .read_normals()
+                    .map(|normals| normals.map(|n| m.transform_vector(&vector(n))).collect()),
+                _ => None,
+            };
+            // glTF counts v down from the top of an image, ImageTexture up from the
+            // bottom
+            let uvs: Option<Vec<(Float, Float)>> = reader.read_tex_coords(0).map(|uvs| {
+                uvs.into_f32()
+                    .map(|[u, v]| (u as Float, 1.0 - v as Float))
+                    .collect()
+            });
+            let triangles = corners
+                .into_iter()
+                .map(|[a, b, c]| {
+                    let triangle =
+                        Triangle::new(positions[a], positions[b], positions[c], material.clone());
+                    let triangle = match &normals {
+                        Some(n) if n.len() == positions.len() => {
+                            triangle.with_normals([n[a], n[b], n[c]])
+                        }
+                        _ => triangle,
+                    };
+                    match &uvs {
+                        Some(t) if t.len() == positions.len() => {
+                            triangle.with_uvs([t[a], t[b], t[c]])
+                        }
+                        _ => triangle,
+                    }
+                })
+                .collect();
+            Some((Arc::new(TriangleMesh::from_triangles(triangles)), emits))
+        }
+    }
+
+    // the file's camera aimed at the middle of the scene, or one of its own looking
+    // down -z at all of it
+    fn camera(
+        placement: Option<Placement>,
+        bounds: &AABB,
+        aspect: Float,
+        options: &Options,
+    ) -> Camera {
+        let centre = 0.5 * (bounds.min + bounds.max);
+        let (from, forward, up, fov) = placement.unwrap_or_else(|| {
+            let radius = 0.5 * (bounds.max - bounds.min).norm();
+            let distance = radius / (0.5 * CAMERA_FOV.to_radians()).sin();
+            (
+                centre + Vector3::new(0.0, 0.0, distance),
+                -Vector3::z(),
+                Vector3::y(),
+                CAMERA_FOV,
+            )
+        });
+        let focus = (centre - from).dot(&forward).max(1e-3);
+        let (time0, time1) = options.shutter.interval();
+        let optics = Optics {
+            vertical_fov: fov,
+            focus_dist: focus,
+            ..Optics::default()
+        };
+        Camera::new(
+            from,
+            from + focus * forward,
+            up,
+            aspect,
+            optics,
+            time0,
+            time1,
+        )
+    }
+
+    pub fn load(path: &str, aspect: Float, options: &Options) -> Result<Scene, String> {
+        let (document, buffers, images) =
+            ::gltf::import(path).map_err(|e| format!("cannot read {}: {}", path, e))?;
+        let gltf_scene = document
+            .default_scene()
+            .or_else(|| document.scenes().next())
+            .ok_or_else(|| format!("{}: no scenes", path))?;
+        let mut importer = Importer {
+            buffers: &buffers,
+            images: images.iter().map(|data| Arc::new(image(data))).collect(),
+            materials: HashMap::new(),
+        };
+        let mut flattened = Flattened::default();
+        for node in gltf_scene.nodes() {
+            importer.node(node, &Matrix4::identity(), &mut flattened);
+        }
+        if flattened.meshes.is_empty() {
+            return Err(format!("{}: no triangles", path));
+        }
+        let bounds = flattened
+            .meshes
+            .iter()
+            .filter_map(|(mesh, _)| mesh.bounding_box(0.0, 1.0))
+            .reduce(|a, b| aabb::surrounding_box(&a, &b))
+            .expect("meshes have bounds");
+        let lights: Vec<Arc<dyn Hittable>> = flattened
+            .meshes
+            .iter()
+            .filter(|(_, emits)| *emits)
+            .map(|(mesh, _)| mesh.clone())
+            .collect();
+        let world: Vec<Box<dyn Hittable>> = flattened
+            .meshes
+            .into_iter()
+            .map(|(mesh, _)| Box::new(mesh) as Box<dyn Hittable>)
+            .collect();
+        let (background, light_shape) = if lights.is_empty() {
+            let (r, g, b) = scene::SKY;
+            (SolidColor(Vector3::new(r, g, b)), None)
+        } else {
+            let mut list = HittableList::default();
+            for light in lights.iter() {
+                list.push_shared(light.clone());
+            }
+            (
+                SolidColor::default(),
+                Some(Arc::new(list) as Arc<dyn Hittable>),
+            )
+        };
+        let camera = camera(flattened.camera, &bounds, aspect, options);
+        let scene = Scene {
+            background: Arc::new(background),
+            light_shape,
+            lights,
+            ..Scene::new(
+                scene::accelerate_over(world, options, options.shutter),
+                camera,
+            )
+        };
+        Ok(scene::configure_camera(scene, options))
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use crate::ray::Ray;
+
+        // A triangle under a half metallic material and a glowing one beside it, each
+        // the same three points in an embedded buffer, and optionally a camera.
+        // Written out as a .gltf for load to import.
+        fn fixture(name: &str, camera: bool, emissive: bool) -> String {
+            let glow = if emissive { 1.0 } else { 0.0 };
+            let (camera_node, cameras) = if camera {
+                (
+                    r#", {"camera": 0, "translation": [0.25, 0.25, 3.0]}"#,
+                    r#", "cameras": [{"type": "perspective", "perspective": {"yfov": 0.6, "znear": 0.01}}]"#,
+                )
+            } else {
+                ("", "")
+            };
+            let text = format!(
+                r#"{{
+                    "asset": {{"version": "2.0"}},
+                    "scene": 0,
+                    "scenes": [{{"nodes": [0, 1{nodes}]}}],
+                    "nodes": [
+                        {{"mesh": 0}},
+                        {{"mesh": 1, "translation": [0.0, 2.0, 0.0]}}{camera_node}
+                    ],
+                    "meshes": [
+                        {{"primitives": [{{"attributes": {{"POSITION": 0}}, "material": 0}}]}},
+                        {{"primitives": [{{"attributes": {{"POSITION": 0}}, "material": 1}}]}}
+                    ],
+                    "materials": [
+                        {{"pbrMetallicRoughness": {{"metallicFactor": 0.5, "roughnessFactor": 0.5}}}},
+                        {{"emissiveFactor": [{glow}, {glow}, {glow}]}}
+                    ],
+                    "buffers": [{{
+                        "byteLength": 36,
+                        "uri": "data:application/octet-stream;base64,AAAAAAAAAAAAAAAAAACAPwAAAAAAAAAAAAAAAAAAgD8AAAAA"
+                    }}],
+                    "bufferViews": [{{"buffer": 0, "byteLength": 36}}],
+                    "accessors": [{{
+                        "bufferView": 0, "componentType": 5126, "count": 3, "type": "VEC3",
+                        "min": [0.0, 0.0, 0.0], "max": [1.0, 1.0, 0.0]
+                    }}]{cameras}
+                }}"#,
+                nodes = if camera { ", 2" } else { "" },
+            );
+            let path = std::env::temp_dir().join(format!("rest_of_life_{}.gltf", name));
+            std::fs::write(&path, text).unwrap();
+            path.to_str().unwrap().to_string()
+        }
+
+        // the camera's vertical field of view in degrees
+        fn fov(camera: &Camera) -> Float {
+            let (origin, _, _, _) = camera.frame();
+            let (_, _, vertical) = camera.image_plane();
+            let distance = (camera.focus_point() - origin).norm();
+            (2.0 * (0.5 * vertical.norm() / distance).atan()).to_degrees()
+        }
+
+        #[test]
+        fn cameras_take_the_files_fov_or_40_degrees() {
+            let options = Options::default();
+            let path = fixture("gltf_camera", true, true);
+            let scene = load(&path, 1.0, &options).unwrap();
+            assert!((fov(&scene.camera) - (0.6 as Float).to_degrees()).abs() < 1e-3);
+            let (origin, _, _, forward) = scene.camera.frame();
+            assert!((origin - Vector3::new(0.25, 0.25, 3.0)).norm() < 1e-5);
+            assert!((forward + Vector3::z()).norm() < 1e-5);
+            // without one, a camera looks down -z at everything
+            let path = fixture("gltf_no_camera", false, true);
+            let scene = load(&path, 1.0, &options).unwrap();
+            assert!((fov(&scene.camera) - CAMERA_FOV).abs() < 1e-3);
+            let (origin, _, _, forward) = scene.camera.frame();
+            assert!(origin.z > 1.0 && (forward + Vector3::z()).norm() < 1e-5);
+        }
+
+        #[test]
+        fn metallic_materials_mix_diffuse_and_anisotropic_metal() {
+            let path = fixture("gltf_metal", false, true);
+            let scene = load(&path, 1.0, &Options::default()).unwrap();
+            let ray = Ray::new(Vector3::new(0.25, 0.25, 1.0), -Vector3::z(), 0.0);
+            let hit = scene.world.hit(&ray, 0.001, Float::MAX).unwrap();
+            let name = hit.material.name();
+            assert!(name.contains("Mix<"), "{}", name);
+            assert!(name.contains("Lambertian<"), "{}", name);
+            assert!(name.contains("Anisotropic<"), "{}", name);
+            assert_eq!(hit.material.emitted(&ray, &hit), Vector3::zeros());
+        }
+
+        #[test]
+        fn emissive_meshes_become_lights() {
+            let options = Options::default();
+            let path = fixture("gltf_emissive", false, true);
+            let scene = load(&path, 1.0, &options).unwrap();
+            assert_eq!(scene.lights.len(), 1);
+            assert!(scene.light_shape.is_some());
+            let ray = Ray::new(Vector3::new(0.25, 2.25, 1.0), -Vector3::z(), 0.0);
+            assert!(scene.lights[0].hit(&ray, 0.001, Float::MAX).is_some());
+            let hit = scene.world.hit(&ray, 0.001, Float::MAX).unwrap();
+            assert_eq!(hit.material.emitted(&ray, &hit), Vector3::repeat(1.0));
+            // without emitters the sky lights the scene
+            let path = fixture("gltf_unlit", false, false);
+            let scene = load(&path, 1.0, &options).unwrap();
+            assert!(scene.lights.is_empty() && scene.light_shape.is_none());
+        }
+    }
+}
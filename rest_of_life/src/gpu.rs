@@ -0,0 +1,636 @@
+use crate::render::{Image, RenderSettings};
+use crate::scenefile::Description;
+
+// Experimental GPU backend, --backend gpu. The scene a file describes is flattened
+// into spheres and triangles (rects and boxes are split into two triangles a face),
+// a median split BVH is built over them, and both are uploaded with the materials
+// to storage buffers. The compute kernels in gpu.wgsl then trace every pixel's path
+// in stages: one kernel generates camera rays, and per bounce one intersects the
+// queue of live paths with the BVH and one shades the hits and queues the paths
+// that go on. Paths only sample their BSDF and end by Russian roulette, and
+// keyframed objects and cameras are frozen where they are when the shutter opens,
+// so the CPU integrator stays the reference the GPU image is checked against.
+#[cfg(feature = "gpu")]
+pub fn render(description: &Description, settings: &RenderSettings) -> Result<Image, String> {
+    let movements = description.camera.movements;
+    if movements.tilt != 0.0 || movements.swing != 0.0 {
+        return Err(String::from("the gpu backend cannot tilt the lens"));
+    }
+    if !description.volumes.is_empty() {
+        return Err(String::from("the gpu backend cannot render volumes"));
+    }
+    if description.objects.iter().any(|o| !o.placements.is_empty()) {
+        return Err(String::from("the gpu backend cannot render placed groups"));
+    }
+    if description.sun.is_some() {
+        return Err(String::from("the gpu backend cannot render a sun"));
+    }
+    if description.objects.iter().any(|o| {
+        matches!(
+            o.shape,
+            crate::scenefile::Shape::Csg { .. }
+                | crate::scenefile::Shape::Sdf(..)
+                | crate::scenefile::Shape::Heightfield { .. }
+        )
+    }) {
+        return Err(String::from(
+            "the gpu backend cannot render csg, sdfs or heightfields",
+        ));
+    }
+    if description.objects.iter().any(|o| o.decal.is_some()) {
+        return Err(String::from("the gpu backend cannot render decals"));
+    }
+    if description.background.solid().is_none() {
+        return Err(String::from(
+            "the gpu backend renders one colour backgrounds only",
+        ));
+    }
+    if description.materials.iter().any(|m| {
+        matches!(
+            m,
+            crate::scenefile::MaterialDescription::Subsurface(..)
+                | crate::scenefile::MaterialDescription::ThinFilm { .. }
+                | crate::scenefile::MaterialDescription::Mix { .. }
+                | crate::scenefile::MaterialDescription::TwoSided { .. }
+                | crate::scenefile::MaterialDescription::Cutout { .. }
+                | crate::scenefile::MaterialDescription::VertexColors
+                | crate::scenefile::MaterialDescription::Textured { .. }
+        )
+    }) {
+        return Err(String::from(
+            "the gpu backend cannot render subsurface scattering, thin films, combined materials, cutouts, vertex colours or textures",
+        ));
+    }
+    pollster::block_on(wavefront::render(description, settings))
+}
+
+#[cfg(not(feature = "gpu"))]
+pub fn render(_description: &Description, _settings: &RenderSettings) -> Result<Image, String> {
+    Err(String::from(
+        "the gpu backend needs rest_of_life built with --features gpu",
+    ))
+}
+
+#[cfg(feature = "gpu")]
+mod wavefront {
+    use crate::float::{self, Float};
+    use crate::material::Conductor;
+    use crate::motion;
+    use crate::rect::{self, Plane};
+    use crate::render::{Image, RenderSettings};
+    use crate::scenefile::{Description, MaterialDescription, Shape};
+    use crate::spectrum;
+    use bytemuck::{Pod, Zeroable};
+    use nalgebra::Vector3;
+    use wgpu::util::DeviceExt;
+
+    const WORKGROUP: usize = 64;
+    const MAX_WORKGROUPS: usize = 65535;
+    const MAX_DEPTH: usize = 32;
+    const LEAF_SIZE: usize = 4;
+    // bytes of a path's state in gpu.wgsl
+    const PATH_SIZE: u64 = 64;
+
+    const SPHERE: u32 = 0;
+    const TRIANGLE: u32 = 1;
+
+    // A sphere keeps its centre in p0 and its radius in p1.x; a triangle its
+    // corners, counter-clockwise around its normal.
+    #[repr(C)]
+    #[derive(Clone, Copy, Pod, Zeroable)]
+    struct Primitive {
+        p0: [f32; 3],
+        kind: u32,
+        p1: [f32; 3],
+        material: u32,
+        p2: [f32; 3],
+        flip: u32,
+    }
+
+    // kinds 0 lambertian, 1 metal, 2 dielectric, 3 light
+    #[repr(C)]
+    #[derive(Clone, Copy, Pod, Zeroable)]
+    struct Material {
+        color: [f32; 3],
+        kind: u32,
+        fuzz: f32,
+        ior: f32,
+        pad: [f32; 2],
+    }
+
+    // Laid out depth first like bvh::LinearNode: an interior node (count 0) has its
+    // first child next and keeps the second's index in offset, a leaf keeps the index
+    // of its first primitive.
+    #[repr(C)]
+    #[derive(Clone, Copy, Pod, Zeroable)]
+    struct Node {
+        min: [f32; 3],
+        offset: u32,
+        max: [f32; 3],
+        count: u32,
+    }
+
+    #[repr(C)]
+    #[derive(Clone, Copy, Pod, Zeroable)]
+    struct Params {
+        // w holds the lens radius
+        origin: [f32; 4],
+        lower_left: [f32; 4],
+        horizontal: [f32; 4],
+        vertical: [f32; 4],
+        u: [f32; 4],
+        v: [f32; 4],
+        background: [f32; 4],
+        width: u32,
+        height: u32,
+        sample: u32,
+        seed: u32,
+    }
+
+    fn vec3(v: Vector3<Float>) -> [f32; 3] {
+        v.cast::<f32>().into()
+    }
+
+    fn vec4(v: Vector3<Float>, w: Float) -> [f32; 4] {
+        let [x, y, z] = vec3(v);
+        [x, y, z, float::single(w)]
+    }
+
+    fn material(material: &MaterialDescription) -> Material {
+        let (color, kind, fuzz, ior) = match *material {
+            MaterialDescription::Lambertian(c) => (c, 0, 0.0, 1.0),
+            MaterialDescription::Metal(c, fuzz) => (c, 1, fuzz.min(1.0), 1.0),
+            MaterialDescription::Anisotropic(c, alpha_x, alpha_y) => {
+                (c, 1, (alpha_x * alpha_y).sqrt().min(1.0), 1.0)
+            }
+            // reflecting as head on, the gpu has no Fresnel
+            MaterialDescription::Conductor(eta, k, fuzz) => (
+                Conductor::new(eta, k, fuzz).reflectance(1.0),
+                1,
+                fuzz.min(1.0),
+                1.0,
+            ),
+            MaterialDescription::Dielectric(ior) => (Vector3::new(1.0, 1.0, 1.0), 2, 0.0, ior),
+            // the gpu renders in RGB, at the glass's sodium D index
+            MaterialDescription::Dispersive(dispersion) => (
+                Vector3::new(1.0, 1.0, 1.0),
+                2,
+                0.0,
+                dispersion.ior(spectrum::SODIUM_D),
+            ),
+            MaterialDescription::Light(c) => (c, 3, 0.0, 1.0),
+            MaterialDescription::Subsurface(..)
+            | MaterialDescription::ThinFilm { .. }
+            | MaterialDescription::Mix { .. }
+            | MaterialDescription::TwoSided { .. }
+            | MaterialDescription::Cutout { .. }
+            | MaterialDescription::VertexColors
+            | MaterialDescription::Textured { .. } => {
+                unreachable!(
+                    "render refuses subsurface, thin films, combined materials and cutouts"
+                )
+            }
+        };
+        Material {
+            color: vec3(color),
+            kind,
+            fuzz: float::single(fuzz),
+            ior: float::single(ior),
+            pad: [0.0; 2],
+        }
+    }
+
+    // the two triangles of an axis aligned rect, facing +k as AARect does
+    fn rect(
+        plane: &Plane,
+        [a0, a1, b0, b1, k]: [Float; 5],
+        offset: Vector3<Float>,
+        material: u32,
+        flip: bool,
+    ) -> [Primitive; 2] {
+        let (k_axis, a_axis, b_axis) = rect::get_axis(plane);
+        let corner = |a: Float, b: Float| {
+            let mut p = offset;
+            p[k_axis] += k;
+            p[a_axis] += a;
+            p[b_axis] += b;
+            vec3(p)
+        };
+        let triangle = |p0, p1, p2| Primitive {
+            p0,
+            kind: TRIANGLE,
+            p1,
+            material,
+            p2,
+            flip: flip as u32,
+        };
+        [
+            triangle(corner(a0, b0), corner(a1, b0), corner(a1, b1)),
+            triangle(corner(a0, b0), corner(a1, b1), corner(a0, b1)),
+        ]
+    }
+
+    fn flatten(description: &Description) -> (Vec<Primitive>, Vec<Material>) {
+        let mut primitives = Vec::new();
+        for object in description.objects.iter() {
+            let offset = object
+                .keys
+                .as_ref()
+                .map_or(Vector3::zeros(), |keys| keys.at(description.shutter.open));
+            let material = object.material as u32;
+            let start = primitives.len();
+            match &object.shape {
+                Shape::Sphere { center, radius } => primitives.push(Primitive {
+                    p0: vec3(center + offset),
+                    kind: SPHERE,
+                    p1: [float::single(*radius), 0.0, 0.0],
+                    material,
+                    p2: [0.0; 3],
+                    flip: object.flip as u32,
+                }),
+                Shape::Rect {
+                    plane,
+                    a0,
+                    a1,
+                    b0,
+                    b1,
+                    k,
+                    ..
+                } => primitives.extend(rect(
+                    plane,
+                    [*a0, *a1, *b0, *b1, *k],
+                    offset,
+                    material,
+                    object.flip,
+                )),
+                Shape::Box { p_min, p_max, .. } => {
+                    // as in Cube, the faces at the maximum face out and the others
+                    // are flipped
+                    for plane in [Plane::XY, Plane::ZX, Plane::YZ] {
+                        let (k_axis, a_axis, b_axis) = rect::get_axis(&plane);
+                        let bounds = |k: Float| {
+                            [
+                                p_min[a_axis],
+                                p_max[a_axis],
+                                p_min[b_axis],
+                                p_max[b_axis],
+                                k,
+                            ]
+                        };
+                        primitives.extend(rect(
+                            &plane,
+                            bounds(p_max[k_axis]),
+                            offset,
+                            material,
+                            object.flip,
+                        ));
+                        primitives.extend(rect(
+                            &plane,
+                            bounds(p_min[k_axis]),
+                            offset,
+                            material,
+                            !object.flip,
+                        ));
+                    }
+                }
+                // flat shaded, the gpu backend having no normals of its own for triangles
+                Shape::Mesh(mesh) => {
+                    primitives.extend(mesh.indices.iter().map(|&[a, b, c]| Primitive {
+                        p0: vec3(mesh.vertices[a] + offset),
+                        kind: TRIANGLE,
+                        p1: vec3(mesh.vertices[b] + offset),
+                        material,
+                        p2: vec3(mesh.vertices[c] + offset),
+                        flip: object.flip as u32,
+                    }))
+                }
+                Shape::Csg { .. } | Shape::Sdf(..) | Shape::Heightfield { .. } => {
+                    unreachable!("render refuses csg, sdfs and heightfields")
+                }
+            }
+            // spheres turn about their centres, so only triangles need turning
+            if let Some(keys) = &object.turn_keys {
+                let angles = keys.at(description.shutter.open);
+                let pivot = object.shape.center() + offset;
+                let turned = |p: [f32; 3]| -> [f32; 3] {
+                    vec3(pivot + motion::turn(Vector3::from(p).cast() - pivot, angles))
+                };
+                for primitive in primitives[start..].iter_mut() {
+                    if primitive.kind == TRIANGLE {
+                        primitive.p0 = turned(primitive.p0);
+                        primitive.p1 = turned(primitive.p1);
+                        primitive.p2 = turned(primitive.p2);
+                    }
+                }
+            }
+        }
+        let materials = description.materials.iter().map(material).collect();
+        (primitives, materials)
+    }
+
+    struct Item {
+        primitive: Primitive,
+        min: Vector3<f32>,
+        max: Vector3<f32>,
+    }
+
+    impl Item {
+        fn new(primitive: Primitive) -> Self {
+            let (min, max) = if primitive.kind == SPHERE {
+                let center = Vector3::from(primitive.p0);
+                let r = Vector3::repeat(primitive.p1[0].abs());
+                (center - r, center + r)
+            } else {
+                let corners = [primitive.p0, primitive.p1, primitive.p2].map(Vector3::from);
+                (
+                    corners[0].inf(&corners[1]).inf(&corners[2]),
+                    corners[0].sup(&corners[1]).sup(&corners[2]),
+                )
+            };
+            // padded so rects keep some thickness, as AARect's boxes do
+            let pad = Vector3::repeat(0.0001);
+            Item {
+                primitive,
+                min: min - pad,
+                max: max + pad,
+            }
+        }
+
+        fn centroid(&self, axis: usize) -> f32 {
+            0.5 * (self.min[axis] + self.max[axis])
+        }
+    }
+
+    // builds the nodes over items, ordering them so every leaf's are contiguous
+    fn build(nodes: &mut Vec<Node>, items: &mut [Item], first: usize) {
+        let (min, max) = items.iter().fold(
+            (Vector3::repeat(f32::MAX), Vector3::repeat(f32::MIN)),
+            |(min, max), item| (min.inf(&item.min), max.sup(&item.max)),
+        );
+        let index = nodes.len();
+        nodes.push(Node {
+            min: min.into(),
+            offset: first as u32,
+            max: max.into(),
+            count: items.len() as u32,
+        });
+        if items.len() <= LEAF_SIZE {
+            return;
+        }
+        // split at the median centroid along the axis the centroids spread most on
+        let (low, high) = items.iter().fold(
+            (Vector3::repeat(f32::MAX), Vector3::repeat(f32::MIN)),
+            |(low, high), item| {
+                let c = Vector3::new(item.centroid(0), item.centroid(1), item.centroid(2));
+                (low.inf(&c), high.sup(&c))
+            },
+        );
+        let extent = high - low;
+        let axis = (0..3)
+            .max_by(|&a, &b| extent[a].total_cmp(&extent[b]))
+            .unwrap();
+        let mid = items.len() / 2;
+        items.select_nth_unstable_by(mid, |a, b| a.centroid(axis).total_cmp(&b.centroid(axis)));
+        let (left, right) = items.split_at_mut(mid);
+        build(nodes, left, first);
+        let second = nodes.len();
+        build(nodes, right, first + mid);
+        nodes[index].offset = second as u32;
+        nodes[index].count = 0;
+    }
+
+    // workgroups to cover count threads, spilling into y past the per dimension limit
+    fn workgroups(count: usize) -> (u32, u32) {
+        let groups = count.div_ceil(WORKGROUP);
+        (
+            groups.min(MAX_WORKGROUPS) as u32,
+            groups.div_ceil(MAX_WORKGROUPS) as u32,
+        )
+    }
+
+    pub async fn render(
+        description: &Description,
+        settings: &RenderSettings,
+    ) -> Result<Image, String> {
+        let (width, height) = (settings.width, settings.height);
+        let n = width * height;
+        let (primitives, materials) = flatten(description);
+        let mut items = primitives.into_iter().map(Item::new).collect::<Vec<Item>>();
+        let mut nodes = Vec::with_capacity(2 * items.len());
+        build(&mut nodes, &mut items, 0);
+        let primitives = items
+            .iter()
+            .map(|item| item.primitive)
+            .collect::<Vec<Primitive>>();
+
+        let camera = description
+            .camera(width as Float / height as Float)
+            .at_time(description.shutter.open);
+        let (origin, u, v, _) = camera.frame();
+        let (lower_left, horizontal, vertical) = camera.image_plane();
+        let mut params = Params {
+            origin: vec4(origin, camera.lens_radius()),
+            lower_left: vec4(lower_left, 0.0),
+            horizontal: vec4(horizontal, 0.0),
+            vertical: vec4(vertical, 0.0),
+            u: vec4(u, 0.0),
+            v: vec4(v, 0.0),
+            background: vec4(description.background.solid().unwrap_or_default(), 0.0),
+            width: width as u32,
+            height: height as u32,
+            sample: 0,
+            seed: (settings.seed ^ (settings.seed >> 32)) as u32,
+        };
+
+        let instance = wgpu::Instance::new(wgpu::InstanceDescriptor::default());
+        let adapter = instance
+            .request_adapter(&wgpu::RequestAdapterOptions {
+                power_preference: wgpu::PowerPreference::HighPerformance,
+                force_fallback_adapter: false,
+                compatible_surface: None,
+            })
+            .await
+            .ok_or("no GPU adapter found")?;
+        let (device, queue) = adapter
+            .request_device(
+                &wgpu::DeviceDescriptor {
+                    label: Some("rest_of_life"),
+                    required_features: wgpu::Features::empty(),
+                    required_limits: adapter.limits(),
+                },
+                None,
+            )
+            .await
+            .map_err(|e| format!("cannot open the GPU: {}", e))?;
+
+        let storage = |label, contents: &[u8]| {
+            device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some(label),
+                contents,
+                usage: wgpu::BufferUsages::STORAGE,
+            })
+        };
+        let scratch = |label, size, usage| {
+            device.create_buffer(&wgpu::BufferDescriptor {
+                label: Some(label),
+                size,
+                usage,
+                mapped_at_creation: false,
+            })
+        };
+        let params_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("params"),
+            contents: bytemuck::bytes_of(&params),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+        let node_buffer = storage("nodes", bytemuck::cast_slice(&nodes));
+        let primitive_buffer = storage("primitives", bytemuck::cast_slice(&primitives));
+        let material_buffer = storage("materials", bytemuck::cast_slice(&materials));
+        let path_buffer = scratch("paths", n as u64 * PATH_SIZE, wgpu::BufferUsages::STORAGE);
+        let queue_buffer = scratch("queue", 2 * n as u64 * 4, wgpu::BufferUsages::STORAGE);
+        // the indirect dispatch arguments followed by the queue counts
+        let counter_buffer = scratch(
+            "counters",
+            32,
+            wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::INDIRECT,
+        );
+        let image_size = n as u64 * 16;
+        let accum_buffer = scratch(
+            "accum",
+            image_size,
+            wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC,
+        );
+        let readback_buffer = scratch(
+            "readback",
+            image_size,
+            wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+        );
+
+        let entry = |binding, ty| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::COMPUTE,
+            ty: wgpu::BindingType::Buffer {
+                ty,
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        };
+        let read_only = wgpu::BufferBindingType::Storage { read_only: true };
+        let read_write = wgpu::BufferBindingType::Storage { read_only: false };
+        let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("wavefront"),
+            entries: &[
+                entry(0, wgpu::BufferBindingType::Uniform),
+                entry(1, read_only),
+                entry(2, read_only),
+                entry(3, read_only),
+                entry(4, read_write),
+                entry(5, read_write),
+                entry(6, read_write),
+                entry(7, read_write),
+            ],
+        });
+        let buffers = [
+            &params_buffer,
+            &node_buffer,
+            &primitive_buffer,
+            &material_buffer,
+            &path_buffer,
+            &queue_buffer,
+            &counter_buffer,
+            &accum_buffer,
+        ];
+        let entries = buffers
+            .iter()
+            .enumerate()
+            .map(|(binding, buffer)| wgpu::BindGroupEntry {
+                binding: binding as u32,
+                resource: buffer.as_entire_binding(),
+            })
+            .collect::<Vec<wgpu::BindGroupEntry>>();
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("wavefront"),
+            layout: &layout,
+            entries: &entries,
+        });
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("wavefront"),
+            bind_group_layouts: &[&layout],
+            push_constant_ranges: &[],
+        });
+        let module = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("gpu.wgsl"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("gpu.wgsl").into()),
+        });
+        let pipeline = |entry_point| {
+            device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                label: Some(entry_point),
+                layout: Some(&pipeline_layout),
+                module: &module,
+                entry_point,
+            })
+        };
+        let generate = pipeline("generate");
+        let extend = pipeline("extend");
+        let shade = pipeline("shade");
+        let advance = pipeline("advance");
+        let accumulate = pipeline("accumulate");
+
+        let (groups_x, groups_y) = workgroups(n);
+        for sample in 0..settings.spp {
+            params.sample = sample as u32;
+            queue.write_buffer(&params_buffer, 0, bytemuck::bytes_of(&params));
+            let mut encoder =
+                device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+            {
+                let mut pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+                    label: Some("sample"),
+                    timestamp_writes: None,
+                });
+                pass.set_bind_group(0, &bind_group, &[]);
+                pass.set_pipeline(&generate);
+                pass.dispatch_workgroups(groups_x, groups_y, 1);
+                for _ in 0..MAX_DEPTH {
+                    pass.set_pipeline(&extend);
+                    pass.dispatch_workgroups_indirect(&counter_buffer, 0);
+                    pass.set_pipeline(&shade);
+                    pass.dispatch_workgroups_indirect(&counter_buffer, 0);
+                    pass.set_pipeline(&advance);
+                    pass.dispatch_workgroups(1, 1, 1);
+                }
+                pass.set_pipeline(&accumulate);
+                pass.dispatch_workgroups(groups_x, groups_y, 1);
+            }
+            queue.submit(Some(encoder.finish()));
+        }
+
+        let mut encoder =
+            device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+        encoder.copy_buffer_to_buffer(&accum_buffer, 0, &readback_buffer, 0, image_size);
+        queue.submit(Some(encoder.finish()));
+        let slice = readback_buffer.slice(..);
+        let (sender, receiver) = std::sync::mpsc::channel();
+        slice.map_async(wgpu::MapMode::Read, move |result| {
+            let _ = sender.send(result);
+        });
+        device.poll(wgpu::Maintain::Wait);
+        receiver
+            .recv()
+            .map_err(|e| e.to_string())?
+            .map_err(|e| format!("cannot read the image back: {}", e))?;
+        let data = slice.get_mapped_range();
+        let sums: &[f32] = bytemuck::cast_slice(&data);
+        let pixels = sums
+            .chunks(4)
+            .map(|c| Vector3::new(c[0], c[1], c[2]).cast::<Float>() / settings.spp as Float)
+            .collect();
+        drop(data);
+        readback_buffer.unmap();
+        Ok(Image {
+            width,
+            height,
+            pixels,
+        })
+    }
+}
@@ -0,0 +1,385 @@
+// Wavefront path tracer for the gpu backend, see gpu.rs. Every pixel traces one path
+// per pass. generate starts the paths, then per bounce extend intersects the queued
+// paths with the scene and shade scatters them, pushing the ones that go on into
+// the other half of the queue; advance swaps the halves and sizes the next indirect
+// dispatch. accumulate adds what each path gathered to its pixel.
+
+struct Params {
+    // w holds the lens radius
+    origin: vec4<f32>,
+    lower_left: vec4<f32>,
+    horizontal: vec4<f32>,
+    vertical: vec4<f32>,
+    u: vec4<f32>,
+    v: vec4<f32>,
+    background: vec4<f32>,
+    width: u32,
+    height: u32,
+    sample: u32,
+    seed: u32,
+}
+
+struct Node {
+    min: vec3<f32>,
+    offset: u32,
+    max: vec3<f32>,
+    count: u32,
+}
+
+struct Primitive {
+    p0: vec3<f32>,
+    kind: u32,
+    p1: vec3<f32>,
+    material: u32,
+    p2: vec3<f32>,
+    flip: u32,
+}
+
+struct Material {
+    color: vec3<f32>,
+    kind: u32,
+    fuzz: f32,
+    ior: f32,
+    pad: vec2<f32>,
+}
+
+struct Path {
+    origin: vec3<f32>,
+    rng: u32,
+    direction: vec3<f32>,
+    depth: u32,
+    throughput: vec3<f32>,
+    t: f32,
+    radiance: vec3<f32>,
+    primitive: u32,
+}
+
+struct Counters {
+    dispatch: array<u32, 3>,
+    // paths in the input half of the queue
+    count: u32,
+    // paths pushed to the output half
+    next: atomic<u32>,
+    // which half is input
+    phase: u32,
+}
+
+@group(0) @binding(0) var<uniform> params: Params;
+@group(0) @binding(1) var<storage, read> nodes: array<Node>;
+@group(0) @binding(2) var<storage, read> primitives: array<Primitive>;
+@group(0) @binding(3) var<storage, read> materials: array<Material>;
+@group(0) @binding(4) var<storage, read_write> paths: array<Path>;
+@group(0) @binding(5) var<storage, read_write> queue: array<u32>;
+@group(0) @binding(6) var<storage, read_write> counters: Counters;
+@group(0) @binding(7) var<storage, read_write> accum: array<vec4<f32>>;
+
+const WORKGROUP: u32 = 64u;
+const MAX_WORKGROUPS: u32 = 65535u;
+const MISS: u32 = 0xffffffffu;
+// paths go on from off the surface, see offset_origin, and need no margin
+const T_MIN: f32 = 0.0;
+const T_MAX: f32 = 3.4e38;
+const PI: f32 = 3.14159265;
+const SPHERE: u32 = 0u;
+const LAMBERTIAN: u32 = 0u;
+const METAL: u32 = 1u;
+const DIELECTRIC: u32 = 2u;
+// paths start Russian roulette after this many bounces
+const ROULETTE_DEPTH: u32 = 3u;
+
+fn pcg(v: u32) -> u32 {
+    let state = v * 747796405u + 2891336453u;
+    let word = ((state >> ((state >> 28u) + 4u)) ^ state) * 277803737u;
+    return (word >> 22u) ^ word;
+}
+
+fn random(rng: ptr<function, u32>) -> f32 {
+    *rng = pcg(*rng);
+    return f32(*rng >> 8u) / 16777216.0;
+}
+
+fn unit_vector(rng: ptr<function, u32>) -> vec3<f32> {
+    let z = 1.0 - 2.0 * random(rng);
+    let r = sqrt(max(0.0, 1.0 - z * z));
+    let phi = 2.0 * PI * random(rng);
+    return vec3<f32>(r * cos(phi), r * sin(phi), z);
+}
+
+fn in_unit_sphere(rng: ptr<function, u32>) -> vec3<f32> {
+    return unit_vector(rng) * pow(random(rng), 1.0 / 3.0);
+}
+
+fn in_unit_disk(rng: ptr<function, u32>) -> vec2<f32> {
+    let r = sqrt(random(rng));
+    let phi = 2.0 * PI * random(rng);
+    return vec2<f32>(r * cos(phi), r * sin(phi));
+}
+
+fn pixel_count() -> u32 {
+    return params.width * params.height;
+}
+
+fn thread_index(id: vec3<u32>, groups: vec3<u32>) -> u32 {
+    return id.y * groups.x * WORKGROUP + id.x;
+}
+
+// workgroups for count threads, spilling into y as gpu.rs does
+fn set_dispatch(count: u32) {
+    let groups = (count + WORKGROUP - 1u) / WORKGROUP;
+    counters.dispatch[0] = min(groups, MAX_WORKGROUPS);
+    counters.dispatch[1] = (groups + MAX_WORKGROUPS - 1u) / MAX_WORKGROUPS;
+    counters.dispatch[2] = 1u;
+}
+
+fn hit_node(node: Node, origin: vec3<f32>, inv_direction: vec3<f32>, t_max: f32) -> bool {
+    let t0 = (node.min - origin) * inv_direction;
+    let t1 = (node.max - origin) * inv_direction;
+    let near = max(max(min(t0.x, t1.x), min(t0.y, t1.y)), max(min(t0.z, t1.z), T_MIN));
+    let far = min(min(max(t0.x, t1.x), max(t0.y, t1.y)), min(max(t0.z, t1.z), t_max));
+    return near <= far;
+}
+
+// the distance along the ray to the primitive, or -1 for a miss
+fn hit_primitive(p: Primitive, origin: vec3<f32>, direction: vec3<f32>, t_max: f32) -> f32 {
+    if (p.kind == SPHERE) {
+        let oc = origin - p.p0;
+        let a = dot(direction, direction);
+        let b = dot(oc, direction);
+        let c = dot(oc, oc) - p.p1.x * p.p1.x;
+        let discriminant = b * b - a * c;
+        if (discriminant > 0.0) {
+            let root = sqrt(discriminant);
+            let near = (-b - root) / a;
+            if (near > T_MIN && near < t_max) {
+                return near;
+            }
+            let far = (-b + root) / a;
+            if (far > T_MIN && far < t_max) {
+                return far;
+            }
+        }
+        return -1.0;
+    }
+    // Moller-Trumbore
+    let e1 = p.p1 - p.p0;
+    let e2 = p.p2 - p.p0;
+    let pvec = cross(direction, e2);
+    let det = dot(e1, pvec);
+    if (abs(det) < 1e-12) {
+        return -1.0;
+    }
+    let inv_det = 1.0 / det;
+    let tvec = origin - p.p0;
+    let u = dot(tvec, pvec) * inv_det;
+    if (u < 0.0 || u > 1.0) {
+        return -1.0;
+    }
+    let qvec = cross(tvec, e1);
+    let v = dot(direction, qvec) * inv_det;
+    if (v < 0.0 || u + v > 1.0) {
+        return -1.0;
+    }
+    let t = dot(e2, qvec) * inv_det;
+    if (t > T_MIN && t < t_max) {
+        return t;
+    }
+    return -1.0;
+}
+
+// where a path leaving the surface at `p` along `direction` goes on from, as
+// offset_origin in ray.rs: a fixed number of last places off along the normal to
+// the side it leaves by, or a small fixed distance near the world's origin
+fn offset_origin(p: vec3<f32>, normal: vec3<f32>, direction: vec3<f32>) -> vec3<f32> {
+    let n = select(normal, -normal, dot(normal, direction) < 0.0);
+    let places = vec3<i32>(256.0 * n);
+    let stepped = bitcast<vec3<f32>>(bitcast<vec3<i32>>(p) + select(places, -places, p < vec3<f32>(0.0)));
+    return select(stepped, p + n / 65536.0, abs(p) < vec3<f32>(1.0 / 32.0));
+}
+
+// the direction a dielectric sends a ray on, refracting or reflecting by Schlick's
+// approximation as material.rs does
+fn dielectric(direction: vec3<f32>, normal: vec3<f32>, ior: f32, rng: ptr<function, u32>) -> vec3<f32> {
+    let unit = normalize(direction);
+    var outward = normal;
+    var eta = 1.0 / ior;
+    var cosine = -dot(unit, normal);
+    if (dot(direction, normal) > 0.0) {
+        outward = -normal;
+        eta = ior;
+        cosine = ior * dot(unit, normal);
+    }
+    let dt = dot(unit, outward);
+    let discriminant = 1.0 - eta * eta * (1.0 - dt * dt);
+    if (discriminant > 0.0) {
+        let r = (1.0 - ior) / (1.0 + ior);
+        let r0 = r * r;
+        let m = 1.0 - cosine;
+        let reflect_prob = r0 + (1.0 - r0) * m * m * m * m * m;
+        if (random(rng) >= reflect_prob) {
+            return eta * (unit - outward * dt) - outward * sqrt(discriminant);
+        }
+    }
+    return reflect(direction, normal);
+}
+
+@compute @workgroup_size(64)
+fn generate(@builtin(global_invocation_id) id: vec3<u32>, @builtin(num_workgroups) groups: vec3<u32>) {
+    let i = thread_index(id, groups);
+    let n = pixel_count();
+    if (i == 0u) {
+        counters.count = n;
+        atomicStore(&counters.next, 0u);
+        counters.phase = 0u;
+        set_dispatch(n);
+    }
+    if (i >= n) {
+        return;
+    }
+    var rng = pcg(i ^ pcg(params.sample ^ pcg(params.seed)));
+    // rows from the top
+    let x = i % params.width;
+    let y = params.height - 1u - i / params.width;
+    let s = (f32(x) + random(&rng)) / f32(params.width);
+    let t = (f32(y) + random(&rng)) / f32(params.height);
+    let rd = params.origin.w * in_unit_disk(&rng);
+    let origin = params.origin.xyz + params.u.xyz * rd.x + params.v.xyz * rd.y;
+    let on_plane = params.lower_left.xyz + s * params.horizontal.xyz + t * params.vertical.xyz;
+    paths[i] = Path(origin, rng, on_plane - origin, 0u, vec3<f32>(1.0), 0.0, vec3<f32>(0.0), MISS);
+    queue[i] = i;
+}
+
+@compute @workgroup_size(64)
+fn extend(@builtin(global_invocation_id) id: vec3<u32>, @builtin(num_workgroups) groups: vec3<u32>) {
+    let i = thread_index(id, groups);
+    if (i >= counters.count) {
+        return;
+    }
+    let p = queue[counters.phase * pixel_count() + i];
+    let origin = paths[p].origin;
+    let direction = paths[p].direction;
+    let inv_direction = 1.0 / direction;
+    var closest = T_MAX;
+    var hit = MISS;
+    var stack: array<u32, 64>;
+    var top = 1u;
+    stack[0] = 0u;
+    while (top > 0u) {
+        top -= 1u;
+        let index = stack[top];
+        let node = nodes[index];
+        if (!hit_node(node, origin, inv_direction, closest)) {
+            continue;
+        }
+        if (node.count > 0u) {
+            for (var k = node.offset; k < node.offset + node.count; k++) {
+                let t = hit_primitive(primitives[k], origin, direction, closest);
+                if (t > 0.0) {
+                    closest = t;
+                    hit = k;
+                }
+            }
+        } else {
+            stack[top] = node.offset;
+            stack[top + 1u] = index + 1u;
+            top += 2u;
+        }
+    }
+    paths[p].t = closest;
+    paths[p].primitive = hit;
+}
+
+@compute @workgroup_size(64)
+fn shade(@builtin(global_invocation_id) id: vec3<u32>, @builtin(num_workgroups) groups: vec3<u32>) {
+    let i = thread_index(id, groups);
+    if (i >= counters.count) {
+        return;
+    }
+    let n = pixel_count();
+    let p = queue[counters.phase * n + i];
+    var path = paths[p];
+    if (path.primitive == MISS) {
+        path.radiance += path.throughput * params.background.xyz;
+        paths[p] = path;
+        return;
+    }
+    let primitive = primitives[path.primitive];
+    let material = materials[primitive.material];
+    let point = path.origin + path.t * path.direction;
+    var normal: vec3<f32>;
+    if (primitive.kind == SPHERE) {
+        normal = (point - primitive.p0) / primitive.p1.x;
+    } else {
+        normal = normalize(cross(primitive.p1 - primitive.p0, primitive.p2 - primitive.p0));
+    }
+    if (primitive.flip != 0u) {
+        normal = -normal;
+    }
+    var rng = path.rng;
+    var direction: vec3<f32>;
+    switch material.kind {
+        case LAMBERTIAN: {
+            // cosine weighted, so the albedo is the whole weight
+            direction = normal + unit_vector(&rng);
+            if (dot(direction, direction) < 1e-8) {
+                direction = normal;
+            }
+            path.throughput *= material.color;
+        }
+        case METAL: {
+            direction = reflect(normalize(path.direction), normal) + material.fuzz * in_unit_sphere(&rng);
+            if (dot(direction, normal) <= 0.0) {
+                paths[p] = path;
+                return;
+            }
+            path.throughput *= material.color;
+        }
+        case DIELECTRIC: {
+            direction = dielectric(path.direction, normal, material.ior, &rng);
+        }
+        default: {
+            // lights emit from their front and end the path
+            if (dot(normal, path.direction) < 0.0) {
+                path.radiance += path.throughput * material.color;
+            }
+            paths[p] = path;
+            return;
+        }
+    }
+    path.depth += 1u;
+    if (path.depth >= ROULETTE_DEPTH) {
+        let q = min(max(path.throughput.x, max(path.throughput.y, path.throughput.z)), 0.95);
+        if (random(&rng) >= q) {
+            paths[p] = path;
+            return;
+        }
+        path.throughput /= q;
+    }
+    path.origin = offset_origin(point, normal, direction);
+    path.direction = direction;
+    path.rng = rng;
+    paths[p] = path;
+    let slot = atomicAdd(&counters.next, 1u);
+    queue[(1u - counters.phase) * n + slot] = p;
+}
+
+@compute @workgroup_size(1)
+fn advance() {
+    let count = atomicLoad(&counters.next);
+    atomicStore(&counters.next, 0u);
+    counters.count = count;
+    counters.phase = 1u - counters.phase;
+    set_dispatch(count);
+}
+
+@compute @workgroup_size(64)
+fn accumulate(@builtin(global_invocation_id) id: vec3<u32>, @builtin(num_workgroups) groups: vec3<u32>) {
+    let i = thread_index(id, groups);
+    if (i >= pixel_count()) {
+        return;
+    }
+    let radiance = paths[i].radiance;
+    // a NaN is never equal to itself; such paths are dropped
+    if (all(radiance == radiance)) {
+        accum[i] += vec4<f32>(radiance, 0.0);
+    }
+}
@@ -0,0 +1,310 @@
+use crate::aabb::AABB;
+use crate::float::{self, Float};
+use crate::rng;
+use nalgebra::Vector3;
+use std::sync::Mutex;
+
+// spatial cells per axis over the scene bounds
+const RESOLUTION: usize = 8;
+// equal area bins over the sphere of directions: cos(theta) against phi
+const THETA_BINS: usize = 8;
+const PHI_BINS: usize = 16;
+const BINS: usize = THETA_BINS * PHI_BINS;
+// share of every learned distribution spread uniformly over the sphere, so no
+// direction the bsdf can produce has zero guided probability
+const UNIFORM_FLOOR: Float = 0.1;
+// the bsdf always keeps some share of the mixture
+const MAX_GUIDED_WEIGHT: Float = 0.95;
+
+// How guiding is learned and mixed in. Training runs `passes` passes starting at
+// `spp` samples per pixel and doubling each pass, every pass sampling from what the
+// previous one learned. A cell's guided share of the mixture grows with the number
+// of records it was learned from: max_weight * n / (n + confidence).
+#[derive(Clone, Copy)]
+pub struct GuideSchedule {
+    pub passes: usize,
+    pub spp: usize,
+    pub max_weight: Float,
+    pub confidence: Float,
+}
+
+impl Default for GuideSchedule {
+    fn default() -> Self {
+        GuideSchedule {
+            passes: 3,
+            spp: 4,
+            max_weight: 0.5,
+            confidence: 64.0,
+        }
+    }
+}
+
+impl GuideSchedule {
+    pub fn pass_spp(&self, pass: usize) -> usize {
+        self.spp << pass
+    }
+
+    // guided share of the mixture for a cell learned from `samples` records
+    pub fn weight(&self, samples: usize) -> Float {
+        let weight = self.max_weight * samples as Float / (samples as Float + self.confidence);
+        if weight.is_finite() {
+            weight.clamp(0.0, MAX_GUIDED_WEIGHT)
+        } else {
+            0.0
+        }
+    }
+}
+
+fn bin(direction: &Vector3<Float>) -> Option<usize> {
+    let d = direction.normalize();
+    if !(d.x.is_finite() && d.y.is_finite() && d.z.is_finite()) {
+        return None;
+    }
+    let phi = d.z.atan2(d.x) + float::consts::PI;
+    let t = ((d.y + 1.0) * 0.5 * THETA_BINS as Float) as usize;
+    let p = (phi / (2.0 * float::consts::PI) * PHI_BINS as Float) as usize;
+    Some(t.min(THETA_BINS - 1) * PHI_BINS + p.min(PHI_BINS - 1))
+}
+
+// A piecewise constant distribution over directions learned from one cell's records.
+pub struct Distribution {
+    mass: [Float; BINS],
+    cdf: [Float; BINS],
+    samples: usize,
+}
+
+impl Distribution {
+    // None when the records carry no usable energy, which leaves the cell unguided
+    fn from_histogram(histogram: &[Float; BINS], samples: usize) -> Option<Self> {
+        let total = histogram.iter().sum::<Float>();
+        if !(total > 0.0 && total.is_finite()) {
+            return None;
+        }
+        let mut mass = [0.0; BINS];
+        let mut cdf = [0.0; BINS];
+        let mut sum = 0.0;
+        for i in 0..BINS {
+            mass[i] = (1.0 - UNIFORM_FLOOR) * histogram[i] / total + UNIFORM_FLOOR / BINS as Float;
+            sum += mass[i];
+            cdf[i] = sum;
+        }
+        cdf[BINS - 1] = 1.0;
+        Some(Distribution { mass, cdf, samples })
+    }
+
+    pub fn value(&self, direction: Vector3<Float>) -> Float {
+        match bin(&direction) {
+            Some(b) => self.mass[b] * BINS as Float / (4.0 * float::consts::PI),
+            None => 0.0,
+        }
+    }
+
+    pub fn generate(&self) -> Vector3<Float> {
+        let r = rng::uniform();
+        let b = self.cdf.partition_point(|&c| c < r).min(BINS - 1);
+        let (t, p) = (b / PHI_BINS, b % PHI_BINS);
+        let y = -1.0 + 2.0 * (t as Float + rng::uniform()) / THETA_BINS as Float;
+        let phi = 2.0 * float::consts::PI * (p as Float + rng::uniform()) / PHI_BINS as Float
+            - float::consts::PI;
+        let r = (1.0 - y * y).max(0.0).sqrt();
+        Vector3::new(r * phi.cos(), y, r * phi.sin())
+    }
+}
+
+// Spatio-directional path guiding: a grid over the scene where every cell learns a
+// distribution of incident radiance from the paths traced through it.
+pub struct Guide {
+    bbox: AABB,
+    schedule: GuideSchedule,
+    records: Vec<Mutex<([Float; BINS], usize)>>,
+    distributions: Vec<Option<Distribution>>,
+}
+
+impl Guide {
+    pub fn new(bbox: AABB, schedule: GuideSchedule) -> Self {
+        let cells = RESOLUTION.pow(3);
+        Guide {
+            bbox,
+            schedule,
+            records: (0..cells).map(|_| Mutex::new(([0.0; BINS], 0))).collect(),
+            distributions: (0..cells).map(|_| None).collect(),
+        }
+    }
+
+    fn cell(&self, p: &Vector3<Float>) -> usize {
+        let extent = self.bbox.max - self.bbox.min;
+        let index = |axis: usize| {
+            let f = (p[axis] - self.bbox.min[axis]) / extent[axis];
+            if f.is_finite() {
+                ((f * RESOLUTION as Float).max(0.0) as usize).min(RESOLUTION - 1)
+            } else {
+                0
+            }
+        };
+        (index(0) * RESOLUTION + index(1)) * RESOLUTION + index(2)
+    }
+
+    // records the radiance estimate arriving at p from direction; estimates that are
+    // not finite are dropped instead of poisoning the cell
+    pub fn record(&self, p: &Vector3<Float>, direction: &Vector3<Float>, radiance: Float) {
+        if !(radiance >= 0.0 && radiance.is_finite()) {
+            return;
+        }
+        if let Some(b) = bin(direction) {
+            let mut record = self.records[self.cell(p)].lock().unwrap();
+            record.0[b] += radiance;
+            record.1 += 1;
+        }
+    }
+
+    // learned distribution at p with its share of the mixture, None where unguided
+    pub fn distribution(&self, p: &Vector3<Float>) -> Option<(&Distribution, Float)> {
+        let distribution = self.distributions[self.cell(p)].as_ref()?;
+        let weight = self.schedule.weight(distribution.samples);
+        if weight > 0.0 {
+            Some((distribution, weight))
+        } else {
+            None
+        }
+    }
+
+    // learns from the records of the last pass; cells that recorded nothing usable
+    // keep what they had
+    pub fn refine(&mut self) {
+        for (record, distribution) in self.records.iter_mut().zip(self.distributions.iter_mut()) {
+            let (histogram, samples) =
+                std::mem::replace(record.get_mut().unwrap(), ([0.0; BINS], 0));
+            if let Some(learned) = Distribution::from_histogram(&histogram, samples) {
+                *distribution = Some(learned);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cli::Options;
+    use crate::{Image, RenderSettings};
+
+    fn unit_box() -> AABB {
+        AABB {
+            min: Vector3::zeros(),
+            max: Vector3::new(1.0, 1.0, 1.0),
+        }
+    }
+
+    fn uniform_direction() -> Vector3<Float> {
+        let y = -1.0 + 2.0 * rng::uniform();
+        let phi = 2.0 * float::consts::PI * rng::uniform();
+        let r = (1.0 - y * y).sqrt();
+        Vector3::new(r * phi.cos(), y, r * phi.sin())
+    }
+
+    #[test]
+    fn schedule_weight_ramps_up_to_max_weight() {
+        let schedule = GuideSchedule::default();
+        assert_eq!(schedule.weight(0), 0.0);
+        assert!((schedule.weight(64) - 0.25).abs() < 1e-6);
+        assert!(schedule.weight(1_000_000) <= schedule.max_weight);
+        let broken = GuideSchedule {
+            confidence: Float::NAN,
+            ..schedule
+        };
+        assert_eq!(broken.weight(10), 0.0);
+    }
+
+    #[test]
+    fn distribution_integrates_to_one() {
+        let mut histogram = [0.0; BINS];
+        for (i, h) in histogram.iter_mut().enumerate() {
+            *h = (i % 7) as Float;
+        }
+        let distribution = Distribution::from_histogram(&histogram, 100).unwrap();
+        let n = 200_000;
+        let integral = (0..n)
+            .map(|_| distribution.value(uniform_direction()))
+            .sum::<Float>()
+            * 4.0
+            * float::consts::PI
+            / n as Float;
+        assert!((integral - 1.0).abs() < 0.02, "integral {}", integral);
+    }
+
+    #[test]
+    fn collapsed_distribution_keeps_every_direction() {
+        let mut histogram = [0.0; BINS];
+        histogram[3] = 1.0;
+        let distribution = Distribution::from_histogram(&histogram, 1).unwrap();
+        for _ in 0..10_000 {
+            assert!(distribution.value(uniform_direction()) > 0.0);
+            let direction = distribution.generate();
+            assert!((direction.norm() - 1.0).abs() < 1e-4);
+            let pdf = distribution.value(direction);
+            assert!(pdf > 0.0 && pdf.is_finite());
+        }
+    }
+
+    #[test]
+    fn unusable_records_leave_cells_unguided() {
+        assert!(Distribution::from_histogram(&[0.0; BINS], 10).is_none());
+        assert!(Distribution::from_histogram(&[Float::NAN; BINS], 10).is_none());
+        assert!(Distribution::from_histogram(&[Float::INFINITY; BINS], 10).is_none());
+
+        let mut guide = Guide::new(unit_box(), GuideSchedule::default());
+        let p = Vector3::new(0.5, 0.5, 0.5);
+        let up = Vector3::new(0.0, 1.0, 0.0);
+        guide.record(&p, &up, Float::NAN);
+        guide.record(&p, &up, Float::INFINITY);
+        guide.record(&p, &up, -1.0);
+        guide.record(&p, &Vector3::zeros(), 1.0);
+        guide.refine();
+        assert!(guide.distribution(&p).is_none());
+
+        for _ in 0..100 {
+            guide.record(&p, &up, 1.0);
+        }
+        guide.refine();
+        let (distribution, weight) = guide.distribution(&p).unwrap();
+        assert!(weight > 0.0 && weight < 1.0);
+        assert!(distribution.value(up) > distribution.value(-up));
+
+        // a pass that records nothing keeps what was learned
+        guide.refine();
+        assert!(guide.distribution(&p).is_some());
+    }
+
+    #[test]
+    fn guided_render_matches_unguided() {
+        let options = Options::default();
+        let scene = crate::scene::cornell_box(1.0, &options);
+        let mean = |image: Image| {
+            image.pixels.iter().map(|c| c.x + c.y + c.z).sum::<Float>()
+                / image.pixels.len() as Float
+        };
+        let settings = RenderSettings {
+            width: 16,
+            height: 16,
+            spp: 1024,
+            ..RenderSettings::default()
+        };
+        let reference = mean(crate::render(&scene, &settings));
+        let guided = RenderSettings {
+            guide: Some(GuideSchedule {
+                passes: 2,
+                spp: 8,
+                max_weight: 0.8,
+                confidence: 8.0,
+            }),
+            ..settings
+        };
+        let result = mean(crate::render(&scene, &guided));
+        assert!(result.is_finite());
+        assert!(
+            (result - reference).abs() < 0.1 * reference,
+            "guided {} unguided {}",
+            result,
+            reference
+        );
+    }
+}
@@ -0,0 +1,333 @@
+use crate::aabb::AABB;
+use crate::float::Float;
+use crate::hittable::{HitRecord, Hittable};
+use crate::material::Material;
+use crate::perlin::Perlin;
+use crate::ray::Ray;
+use nalgebra::Vector3;
+
+// A terrain surface over a regular grid of elevation samples in the XZ plane.
+// Each grid cell is a bilinear patch through its four corner heights.
+pub struct Heightfield<M: Material> {
+    nx: usize,
+    nz: usize,
+    heights: Vec<Float>,
+    p_min: Vector3<Float>,
+    dx: Float,
+    dz: Float,
+    bbox: AABB,
+    material: M,
+}
+
+// Where a heightfield made from an image or noise lies: over size_x by size_z
+// from p_min, rising up to max_height above it.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Extent {
+    pub p_min: Vector3<Float>,
+    pub size_x: Float,
+    pub size_z: Float,
+    pub max_height: Float,
+}
+
+// The nx by nz elevations, row by row along x, from the luminance of a
+// grayscale image, scaled to max_height.
+pub fn image_heights(path: &str, max_height: Float) -> Result<(usize, usize, Vec<Float>), String> {
+    let image = image::open(path)
+        .map_err(|e| format!("cannot read {}: {}", path, e))?
+        .to_luma8();
+    let (nx, nz) = image.dimensions();
+    if nx < 2 || nz < 2 {
+        return Err(format!("{} is smaller than 2x2", path));
+    }
+    let heights = image
+        .into_raw()
+        .iter()
+        .map(|&l| max_height * l as Float / 255.0)
+        .collect();
+    Ok((nx as usize, nz as usize, heights))
+}
+
+// nx by nz elevations from turbulent Perlin noise sampled at `scale` grid cells
+// per unit, scaled to max_height
+pub fn noise_heights(nx: usize, nz: usize, scale: Float, max_height: Float) -> Vec<Float> {
+    let noise = Perlin::new();
+    let mut heights = Vec::with_capacity(nx * nz);
+    for z in 0..nz {
+        for x in 0..nx {
+            let p = Vector3::new(x as Float * scale, 0.0, z as Float * scale);
+            heights.push(max_height * noise.turb(&p, 7).min(1.0));
+        }
+    }
+    heights
+}
+
+impl<M: Material> Heightfield<M> {
+    // `heights` holds nx * nz elevations row by row along x, measured up from p_min.y;
+    // the grid spans size_x by size_z starting at p_min
+    pub fn new(
+        nx: usize,
+        nz: usize,
+        heights: Vec<Float>,
+        p_min: Vector3<Float>,
+        size_x: Float,
+        size_z: Float,
+        material: M,
+    ) -> Self {
+        assert!(nx >= 2 && nz >= 2, "heightfield needs at least 2x2 samples");
+        assert_eq!(heights.len(), nx * nz, "heightfield size mismatch");
+        let heights: Vec<Float> = heights.iter().map(|h| p_min.y + h).collect();
+        let (y_min, y_max) = heights
+            .iter()
+            .fold((Float::MAX, Float::MIN), |(lo, hi), &h| {
+                (lo.min(h), hi.max(h))
+            });
+        let bbox = AABB::new(
+            Vector3::new(p_min.x, y_min - 0.0001, p_min.z),
+            Vector3::new(p_min.x + size_x, y_max + 0.0001, p_min.z + size_z),
+        );
+        Heightfield {
+            nx,
+            nz,
+            heights,
+            p_min,
+            dx: size_x / (nx - 1) as Float,
+            dz: size_z / (nz - 1) as Float,
+            bbox,
+            material,
+        }
+    }
+
+    // elevations from the luminance of a grayscale image, see image_heights
+    pub fn from_image(path: &str, extent: Extent, material: M) -> Result<Self, String> {
+        let (nx, nz, heights) = image_heights(path, extent.max_height)?;
+        Ok(Heightfield::new(
+            nx,
+            nz,
+            heights,
+            extent.p_min,
+            extent.size_x,
+            extent.size_z,
+            material,
+        ))
+    }
+
+    // elevations from noise, see noise_heights
+    pub fn from_noise(nx: usize, nz: usize, scale: Float, extent: Extent, material: M) -> Self {
+        Heightfield::new(
+            nx,
+            nz,
+            noise_heights(nx, nz, scale, extent.max_height),
+            extent.p_min,
+            extent.size_x,
+            extent.size_z,
+            material,
+        )
+    }
+
+    fn height(&self, x: usize, z: usize) -> Float {
+        self.heights[z * self.nx + x]
+    }
+
+    // intersects the bilinear patch of cell (cx, cz) within [t0, t1]
+    fn hit_cell(
+        &self,
+        ray: &Ray,
+        cx: usize,
+        cz: usize,
+        t0: Float,
+        t1: Float,
+    ) -> Option<HitRecord<'_>> {
+        let h00 = self.height(cx, cz);
+        let h10 = self.height(cx + 1, cz);
+        let h01 = self.height(cx, cz + 1);
+        let h11 = self.height(cx + 1, cz + 1);
+        let a = h00;
+        let b = h10 - h00;
+        let c = h01 - h00;
+        let d = h00 - h10 - h01 + h11;
+
+        let x0 = self.p_min.x + cx as Float * self.dx;
+        let z0 = self.p_min.z + cz as Float * self.dz;
+        let s0 = (ray.origin().x - x0) / self.dx;
+        let r0 = (ray.origin().z - z0) / self.dz;
+        let ds = ray.direction().x / self.dx;
+        let dr = ray.direction().z / self.dz;
+        let y0 = ray.origin().y;
+        let dy = ray.direction().y;
+
+        // y(t) - h(s(t), r(t)) = qa t^2 + qb t + qc
+        let qa = -d * ds * dr;
+        let qb = dy - b * ds - c * dr - d * (s0 * dr + r0 * ds);
+        let qc = y0 - a - b * s0 - c * r0 - d * s0 * r0;
+
+        let t = if qa.abs() < 1e-8 {
+            if qb == 0.0 {
+                return None;
+            }
+            let t = -qc / qb;
+            if t < t0 || t > t1 {
+                return None;
+            }
+            t
+        } else {
+            let discriminant = qb * qb - 4.0 * qa * qc;
+            if discriminant < 0.0 {
+                return None;
+            }
+            let sqrt_discriminant = discriminant.sqrt();
+            let (ta, tb) = (
+                (-qb - sqrt_discriminant) / (2.0 * qa),
+                (-qb + sqrt_discriminant) / (2.0 * qa),
+            );
+            let (ta, tb) = if ta < tb { (ta, tb) } else { (tb, ta) };
+            if ta >= t0 && ta <= t1 {
+                ta
+            } else if tb >= t0 && tb <= t1 {
+                tb
+            } else {
+                return None;
+            }
+        };
+
+        let s = (s0 + ds * t).clamp(0.0, 1.0);
+        let r = (r0 + dr * t).clamp(0.0, 1.0);
+        let dh_dx = (b + d * r) / self.dx;
+        let dh_dz = (c + d * s) / self.dz;
+        let point = ray.point_at_parameter(t);
+        Some(HitRecord {
+            t,
+            u: (cx as Float + s) / (self.nx - 1) as Float,
+            v: (cz as Float + r) / (self.nz - 1) as Float,
+            p: point,
+            texture_p: point,
+            normal: Vector3::new(-dh_dx, 1.0, -dh_dz).normalize(),
+            tangent: Vector3::new(1.0, dh_dx, 0.0).normalize(),
+            dpdu: (self.nx - 1) as Float * self.dx * Vector3::new(1.0, dh_dx, 0.0),
+            dpdv: (self.nz - 1) as Float * self.dz * Vector3::new(0.0, dh_dz, 1.0),
+            material: &self.material,
+            object_id: 0,
+            class_id: 0,
+        })
+    }
+}
+
+impl<M: Material> Hittable for Heightfield<M> {
+    fn hit(&self, ray: &Ray, t_min: Float, t_max: Float) -> Option<HitRecord<'_>> {
+        let (t_enter, t_exit) = self.bbox.interval(ray, t_min, t_max)?;
+        let cells_x = self.nx - 1;
+        let cells_z = self.nz - 1;
+        let entry = ray.point_at_parameter(t_enter);
+        let gx = (entry.x - self.p_min.x) / self.dx;
+        let gz = (entry.z - self.p_min.z) / self.dz;
+        let mut cx = (gx.max(0.0) as usize).min(cells_x - 1);
+        let mut cz = (gz.max(0.0) as usize).min(cells_z - 1);
+
+        // 2D DDA over the cells crossed by the ray's XZ projection
+        let dir = ray.direction();
+        let (step_x, t_delta_x, mut t_next_x) = if dir.x > 0.0 {
+            let next = self.p_min.x + (cx + 1) as Float * self.dx;
+            (1, self.dx / dir.x, (next - ray.origin().x) / dir.x)
+        } else if dir.x < 0.0 {
+            let next = self.p_min.x + cx as Float * self.dx;
+            (-1, -self.dx / dir.x, (next - ray.origin().x) / dir.x)
+        } else {
+            (0, Float::MAX, Float::MAX)
+        };
+        let (step_z, t_delta_z, mut t_next_z) = if dir.z > 0.0 {
+            let next = self.p_min.z + (cz + 1) as Float * self.dz;
+            (1, self.dz / dir.z, (next - ray.origin().z) / dir.z)
+        } else if dir.z < 0.0 {
+            let next = self.p_min.z + cz as Float * self.dz;
+            (-1, -self.dz / dir.z, (next - ray.origin().z) / dir.z)
+        } else {
+            (0, Float::MAX, Float::MAX)
+        };
+
+        let mut t0 = t_enter;
+        loop {
+            let t1 = t_next_x.min(t_next_z).min(t_exit);
+            if let Some(hit) = self.hit_cell(ray, cx, cz, t0, t1) {
+                return Some(hit);
+            }
+            if t1 >= t_exit {
+                return None;
+            }
+            t0 = t1;
+            if t_next_x < t_next_z {
+                if (step_x < 0 && cx == 0) || (step_x > 0 && cx + 1 == cells_x) {
+                    return None;
+                }
+                cx = (cx as isize + step_x) as usize;
+                t_next_x += t_delta_x;
+            } else {
+                if (step_z < 0 && cz == 0) || (step_z > 0 && cz + 1 == cells_z) {
+                    return None;
+                }
+                cz = (cz as isize + step_z) as usize;
+                t_next_z += t_delta_z;
+            }
+        }
+    }
+
+    fn bounding_box(&self, _t0: Float, _t1: Float) -> Option<AABB> {
+        Some(self.bbox)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cli::Options;
+    use crate::material::Lambertian;
+    use crate::ray;
+    use crate::scenefile;
+    use crate::texture::ConstantTexture;
+
+    #[test]
+    fn a_flat_map_is_hit_at_its_height() {
+        let path = std::env::temp_dir().join("rest_of_life_flat_heightfield.png");
+        image::GrayImage::from_pixel(5, 4, image::Luma([51]))
+            .save(&path)
+            .unwrap();
+        let path = path.to_str().unwrap();
+        let extent = Extent {
+            p_min: Vector3::new(-2.0, 1.0, -3.0),
+            size_x: 4.0,
+            size_z: 6.0,
+            max_height: 5.0,
+        };
+        let grey = Lambertian::new(ConstantTexture::new(0.5, 0.5, 0.5));
+        let terrain = Heightfield::from_image(path, extent, grey.clone()).unwrap();
+        // 51 of 255 is a fifth of the way up
+        for i in 0..20 {
+            let a = i as Float;
+            let target = Vector3::new(1.9 * a.sin(), 2.0, 2.9 * (0.7 * a).cos());
+            let origin = Vector3::new(0.3 * a, 9.0, -0.2 * a);
+            let ray = Ray::new(origin, target - origin, 0.0);
+            let hit = terrain.hit(&ray, ray::T_MIN, Float::MAX).unwrap();
+            assert!((hit.p - target).norm() < 1e-4);
+            assert!((hit.normal - Vector3::y()).norm() < 1e-6);
+        }
+        assert!(Heightfield::from_image("missing.png", extent, grey).is_err());
+    }
+
+    #[test]
+    fn heightfield_statements_lay_terrain() {
+        let text = "camera from 0 5 10 at 0 0 0\n\
+                    material grey lambertian 0.5 0.5 0.5\n\
+                    heightfield noise 8 8 0.3 -4 -1 -4 8 8 0 grey\n";
+        let scene = scenefile::parse(text, 1.0, &Options::default()).unwrap();
+        let ray = Ray::new(Vector3::new(1.0, 5.0, 2.0), -Vector3::y(), 0.0);
+        let hit = scene.world.hit(&ray, ray::T_MIN, Float::MAX).unwrap();
+        assert!((hit.p.y + 1.0).abs() < 1e-4);
+        for bad in [
+            "heightfield noise 1 8 0.3 -4 -1 -4 8 8 1 grey",
+            "heightfield noise 8 8 0.3 -4 -1 -4 0 8 1 grey",
+            "heightfield image missing.png -4 -1 -4 8 8 1 grey",
+            "heightfield cloud -4 -1 -4 8 8 1 grey",
+        ] {
+            let text = format!("material grey lambertian 0.5 0.5 0.5\n{}\n", bad);
+            assert!(scenefile::parse(&text, 1.0, &Options::default()).is_err());
+        }
+    }
+}
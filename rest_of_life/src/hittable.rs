@@ -1,43 +1,237 @@
 use crate::aabb;
 use crate::aabb::AABB;
+use crate::cone::{self, Cone};
+use crate::float::Float;
 use crate::material::Material;
-use crate::ray::Ray;
+use crate::packet::{RayPacket, LANES};
+use crate::ray::{self, Ray};
+use crate::rng;
 use nalgebra::Vector3;
 use rand::seq::SliceRandom;
+use std::sync::Arc;
 
 pub struct HitRecord<'a> {
-    pub t: f32,
-    pub u: f32,
-    pub v: f32,
-    pub p: Vector3<f32>,
-    pub normal: Vector3<f32>,
+    pub t: Float,
+    pub u: Float,
+    pub v: Float,
+    pub p: Vector3<Float>,
+    // where solid textures are looked up: p, unless a transform above the shape
+    // keeps it in the shape's own space so its textures move with it
+    pub texture_p: Vector3<Float>,
+    pub normal: Vector3<Float>,
+    // a unit direction in the surface along its lines of constant v, which
+    // anisotropic materials align their roughness to
+    pub tangent: Vector3<Float>,
+    // how p moves with u and with v, for the footprint of a ray's differentials in
+    // texture space; zero where the surface has no such parameterisation
+    pub dpdu: Vector3<Float>,
+    pub dpdv: Vector3<Float>,
     pub material: &'a dyn Material,
+    pub object_id: u32,
+    pub class_id: u32,
 }
 
-pub trait Hittable: Sync {
-    fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord>;
-    fn bounding_box(&self, t0: f32, t1: f32) -> Option<AABB>;
-    fn pdf_value(&self, _o: Vector3<f32>, _v: Vector3<f32>) -> f32 {
+impl HitRecord<'_> {
+    // a ray leaving the hit along `direction`, from just off the surface on the side
+    // it leaves by
+    pub fn spawn(&self, direction: Vector3<Float>, time: Float) -> Ray {
+        Ray::new(
+            ray::offset_origin(self.p, self.normal, direction),
+            direction,
+            time,
+        )
+    }
+
+    // The ray on along `ray` past the hit, leaving from just beyond the surface as
+    // spawn does, and how far along `ray` it starts, which its t count on from; for
+    // looking past the surfaces a ray finds one by one.
+    pub fn pass(&self, ray: &Ray) -> (Ray, Float) {
+        let direction = ray.direction();
+        let origin = ray::offset_origin(self.p, self.normal, direction);
+        let start = (origin - ray.origin()).dot(&direction) / direction.norm_squared();
+        let passed = Ray::new(origin, direction, ray.time()).with_wavelength(ray.wavelength());
+        (passed, start)
+    }
+}
+
+// Finds the surfaces along a ray one by one, nearest first, each after the first
+// along the ray on from just beyond the last, see HitRecord::pass, and gives them
+// with their t along the ray itself.
+pub struct Surfaces<'r> {
+    ray: &'r Ray,
+    passed: Option<Ray>,
+    start: Float,
+}
+
+impl<'r> Surfaces<'r> {
+    pub fn new(ray: &'r Ray) -> Self {
+        Surfaces {
+            ray,
+            passed: None,
+            start: 0.0,
+        }
+    }
+
+    // the next surface of the hittable up to t_max, the first no nearer than t_min
+    pub fn next<'h, H: Hittable + ?Sized>(
+        &mut self,
+        hittable: &'h H,
+        t_min: Float,
+        t_max: Float,
+    ) -> Option<HitRecord<'h>> {
+        let mut hit = match &self.passed {
+            Some(passed) => hittable.hit(passed, ray::T_MIN, t_max - self.start)?,
+            None => hittable.hit(self.ray, t_min, t_max)?,
+        };
+        hit.t += self.start;
+        let (passed, start) = hit.pass(self.ray);
+        self.passed = Some(passed);
+        self.start = start;
+        Some(hit)
+    }
+}
+
+pub trait Hittable: Send + Sync {
+    fn hit(&self, ray: &Ray, t_min: Float, t_max: Float) -> Option<HitRecord<'_>>;
+    fn bounding_box(&self, t0: Float, t1: Float) -> Option<AABB>;
+    // Whether anything lies along the ray within [t_min, t_max], for shadow and
+    // visibility rays that need no more than that. Shapes and structures that can
+    // answer without working out the hit, or stop at the first one found rather
+    // than the nearest, say so faster than this.
+    fn occluded(&self, ray: &Ray, t_min: Float, t_max: Float) -> bool {
+        self.hit(ray, t_min, t_max).is_some()
+    }
+    // The nearest hit of each ray of a packet, and whether anything blocks each,
+    // lanes without a ray giving None and false. Ray by ray here; acceleration
+    // structures walk their nodes once for the whole packet.
+    fn hit_packet(&self, packet: &RayPacket) -> [Option<HitRecord<'_>>; LANES] {
+        std::array::from_fn(|i| {
+            (i < packet.len())
+                .then(|| self.hit(packet.ray(i), packet.t_min, packet.t_max[i]))
+                .flatten()
+        })
+    }
+    fn occluded_packet(&self, packet: &RayPacket) -> [bool; LANES] {
+        std::array::from_fn(|i| {
+            i < packet.len() && self.occluded(packet.ray(i), packet.t_min, packet.t_max[i])
+        })
+    }
+    fn pdf_value(&self, _o: Vector3<Float>, _v: Vector3<Float>) -> Float {
         0.0
     }
-    fn random(&self, _o: Vector3<f32>) -> Vector3<f32> {
+    fn random(&self, _o: Vector3<Float>) -> Vector3<Float> {
         Vector3::new(1.0, 0.0, 0.0)
     }
+    // Share of the cone's footprint blocked along [t_min, t_max], for the cone
+    // traced preview. Shapes without their own estimate block like their bounding box.
+    fn occlusion(&self, cone: &Cone, t_min: Float, t_max: Float) -> Float {
+        self.bounding_box(0.0, 1.0)
+            .map_or(0.0, |bbox| cone.box_coverage(&bbox, t_min, t_max))
+    }
+    // A point on the surface, its normal there and the inverse of the density per
+    // unit area it was drawn with, which is the area for points spread uniformly.
+    // Photon mapping emits from lights through it; shapes that cannot say give None.
+    fn sample_surface(&self) -> Option<(Vector3<Float>, Vector3<Float>, Float)> {
+        None
+    }
+}
+
+// A shared hittable is the object itself, so the same light can sit in the world
+// and in the list of shapes sampled toward.
+impl<H: Hittable + ?Sized> Hittable for Arc<H> {
+    fn hit(&self, ray: &Ray, t_min: Float, t_max: Float) -> Option<HitRecord<'_>> {
+        self.as_ref().hit(ray, t_min, t_max)
+    }
+
+    fn occluded(&self, ray: &Ray, t_min: Float, t_max: Float) -> bool {
+        self.as_ref().occluded(ray, t_min, t_max)
+    }
+
+    fn hit_packet(&self, packet: &RayPacket) -> [Option<HitRecord<'_>>; LANES] {
+        self.as_ref().hit_packet(packet)
+    }
+
+    fn occluded_packet(&self, packet: &RayPacket) -> [bool; LANES] {
+        self.as_ref().occluded_packet(packet)
+    }
+
+    fn bounding_box(&self, t0: Float, t1: Float) -> Option<AABB> {
+        self.as_ref().bounding_box(t0, t1)
+    }
+
+    fn pdf_value(&self, o: Vector3<Float>, v: Vector3<Float>) -> Float {
+        self.as_ref().pdf_value(o, v)
+    }
+
+    fn random(&self, o: Vector3<Float>) -> Vector3<Float> {
+        self.as_ref().random(o)
+    }
+
+    fn occlusion(&self, cone: &Cone, t_min: Float, t_max: Float) -> Float {
+        self.as_ref().occlusion(cone, t_min, t_max)
+    }
+
+    fn sample_surface(&self) -> Option<(Vector3<Float>, Vector3<Float>, Float)> {
+        self.as_ref().sample_surface()
+    }
+}
+
+// and so is a boxed one, so a BVH can hold objects of different kinds
+impl<H: Hittable + ?Sized> Hittable for Box<H> {
+    fn hit(&self, ray: &Ray, t_min: Float, t_max: Float) -> Option<HitRecord<'_>> {
+        self.as_ref().hit(ray, t_min, t_max)
+    }
+
+    fn occluded(&self, ray: &Ray, t_min: Float, t_max: Float) -> bool {
+        self.as_ref().occluded(ray, t_min, t_max)
+    }
+
+    fn hit_packet(&self, packet: &RayPacket) -> [Option<HitRecord<'_>>; LANES] {
+        self.as_ref().hit_packet(packet)
+    }
+
+    fn occluded_packet(&self, packet: &RayPacket) -> [bool; LANES] {
+        self.as_ref().occluded_packet(packet)
+    }
+
+    fn bounding_box(&self, t0: Float, t1: Float) -> Option<AABB> {
+        self.as_ref().bounding_box(t0, t1)
+    }
+
+    fn pdf_value(&self, o: Vector3<Float>, v: Vector3<Float>) -> Float {
+        self.as_ref().pdf_value(o, v)
+    }
+
+    fn random(&self, o: Vector3<Float>) -> Vector3<Float> {
+        self.as_ref().random(o)
+    }
+
+    fn occlusion(&self, cone: &Cone, t_min: Float, t_max: Float) -> Float {
+        self.as_ref().occlusion(cone, t_min, t_max)
+    }
+
+    fn sample_surface(&self) -> Option<(Vector3<Float>, Vector3<Float>, Float)> {
+        self.as_ref().sample_surface()
+    }
 }
 
 #[derive(Default)]
 pub struct HittableList {
-    list: Vec<Box<dyn Hittable>>,
+    list: Vec<Arc<dyn Hittable>>,
 }
 
 impl HittableList {
     pub fn push(&mut self, hittable: impl Hittable + 'static) {
-        self.list.push(Box::new(hittable))
+        self.list.push(Arc::new(hittable))
+    }
+
+    pub fn push_shared(&mut self, hittable: Arc<dyn Hittable>) {
+        self.list.push(hittable)
     }
 }
 
 impl Hittable for HittableList {
-    fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord> {
+    fn hit(&self, ray: &Ray, t_min: Float, t_max: Float) -> Option<HitRecord<'_>> {
         let mut closest_so_far = t_max;
         let mut hit_anything: Option<HitRecord> = None;
         for h in self.list.iter() {
@@ -49,26 +243,40 @@ impl Hittable for HittableList {
         hit_anything
     }
 
-    fn bounding_box(&self, t0: f32, t1: f32) -> Option<AABB> {
+    fn occluded(&self, ray: &Ray, t_min: Float, t_max: Float) -> bool {
+        self.list.iter().any(|h| h.occluded(ray, t_min, t_max))
+    }
+
+    fn occlusion(&self, cone: &Cone, t_min: Float, t_max: Float) -> Float {
+        cone::occlusion_of(self.list.iter().map(|h| h.as_ref()), cone, t_min, t_max)
+    }
+
+    fn bounding_box(&self, t0: Float, t1: Float) -> Option<AABB> {
         match self.list.first() {
-            Some(first) => {
-                match first.bounding_box(t0, t1) {
-                    Some(bbox) => self.list.iter().skip(1).try_fold(bbox, |acc, hittable| {
-                        hittable.bounding_box(t0, t1).map(|bbox| aabb::surrounding_box(&acc, &bbox))
-                    }),
-                    _ => None,
-                }
-            }
+            Some(first) => match first.bounding_box(t0, t1) {
+                Some(bbox) => self.list.iter().skip(1).try_fold(bbox, |acc, hittable| {
+                    hittable
+                        .bounding_box(t0, t1)
+                        .map(|bbox| aabb::surrounding_box(&acc, &bbox))
+                }),
+                _ => None,
+            },
             _ => None,
         }
     }
 
-    fn pdf_value(&self, o: Vector3<f32>, v: Vector3<f32>) -> f32 {
-        self.list.iter().map(|h| h.pdf_value(o, v)).sum::<f32>() / self.list.len() as f32
+    fn pdf_value(&self, o: Vector3<Float>, v: Vector3<Float>) -> Float {
+        self.list.iter().map(|h| h.pdf_value(o, v)).sum::<Float>() / self.list.len() as Float
     }
 
-    fn random(&self, o: Vector3<f32>) -> Vector3<f32> {
-        self.list.choose(&mut rand::thread_rng()).unwrap().random(o)
+    fn random(&self, o: Vector3<Float>) -> Vector3<Float> {
+        rng::with(|rng| self.list.choose(rng)).unwrap().random(o)
+    }
+
+    // a member picked alike, then a point on it
+    fn sample_surface(&self) -> Option<(Vector3<Float>, Vector3<Float>, Float)> {
+        let (p, normal, area) = rng::with(|rng| self.list.choose(rng))?.sample_surface()?;
+        Some((p, normal, area * self.list.len() as Float))
     }
 }
 
@@ -83,14 +291,86 @@ impl<H: Hittable> FlipNormals<H> {
 }
 
 impl<H: Hittable> Hittable for FlipNormals<H> {
-    fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord> {
+    fn hit(&self, ray: &Ray, t_min: Float, t_max: Float) -> Option<HitRecord<'_>> {
         self.hittable.hit(ray, t_min, t_max).map(|mut hit| {
             hit.normal = -hit.normal;
             hit
         })
     }
 
-    fn bounding_box(&self, t0: f32, t1: f32) -> Option<AABB> {
+    fn occluded(&self, ray: &Ray, t_min: Float, t_max: Float) -> bool {
+        self.hittable.occluded(ray, t_min, t_max)
+    }
+
+    fn bounding_box(&self, t0: Float, t1: Float) -> Option<AABB> {
         self.hittable.bounding_box(t0, t1)
     }
+
+    fn pdf_value(&self, o: Vector3<Float>, v: Vector3<Float>) -> Float {
+        self.hittable.pdf_value(o, v)
+    }
+
+    fn random(&self, o: Vector3<Float>) -> Vector3<Float> {
+        self.hittable.random(o)
+    }
+
+    fn occlusion(&self, cone: &Cone, t_min: Float, t_max: Float) -> Float {
+        self.hittable.occlusion(cone, t_min, t_max)
+    }
+
+    fn sample_surface(&self) -> Option<(Vector3<Float>, Vector3<Float>, Float)> {
+        let (p, normal, area) = self.hittable.sample_surface()?;
+        Some((p, -normal, area))
+    }
+}
+
+// Tags every hit on the wrapped hittable with an instance and a semantic class id.
+pub struct Labeled<H: Hittable> {
+    hittable: H,
+    object_id: u32,
+    class_id: u32,
+}
+
+impl<H: Hittable> Labeled<H> {
+    pub fn new(hittable: H, object_id: u32, class_id: u32) -> Self {
+        Labeled {
+            hittable,
+            object_id,
+            class_id,
+        }
+    }
+}
+
+impl<H: Hittable> Hittable for Labeled<H> {
+    fn hit(&self, ray: &Ray, t_min: Float, t_max: Float) -> Option<HitRecord<'_>> {
+        self.hittable.hit(ray, t_min, t_max).map(|mut hit| {
+            hit.object_id = self.object_id;
+            hit.class_id = self.class_id;
+            hit
+        })
+    }
+
+    fn occluded(&self, ray: &Ray, t_min: Float, t_max: Float) -> bool {
+        self.hittable.occluded(ray, t_min, t_max)
+    }
+
+    fn bounding_box(&self, t0: Float, t1: Float) -> Option<AABB> {
+        self.hittable.bounding_box(t0, t1)
+    }
+
+    fn pdf_value(&self, o: Vector3<Float>, v: Vector3<Float>) -> Float {
+        self.hittable.pdf_value(o, v)
+    }
+
+    fn random(&self, o: Vector3<Float>) -> Vector3<Float> {
+        self.hittable.random(o)
+    }
+
+    fn occlusion(&self, cone: &Cone, t_min: Float, t_max: Float) -> Float {
+        self.hittable.occlusion(cone, t_min, t_max)
+    }
+
+    fn sample_surface(&self) -> Option<(Vector3<Float>, Vector3<Float>, Float)> {
+        self.hittable.sample_surface()
+    }
 }
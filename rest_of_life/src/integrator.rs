@@ -0,0 +1,803 @@
+use crate::aabb::AABB;
+use crate::background::Background;
+use crate::bounce::{BounceKind, BounceLimits, Bounces};
+use crate::bvh;
+use crate::differential;
+use crate::finite;
+use crate::float::{self, Float};
+use crate::guide::Guide;
+use crate::hittable::{HitRecord, Hittable, Surfaces};
+use crate::linking::LightLinks;
+use crate::material::{Lambertian, ScatterRecord};
+use crate::mnee::Mnee;
+use crate::packet::{RayPacket, LANES};
+use crate::pathlog;
+use crate::pdf::PDF;
+use crate::ray::{self, Ray};
+use crate::rng;
+use crate::sampling::{self, ONB};
+use crate::scene::Scene;
+use crate::stats::{self, Counter};
+use crate::texture::ConstantTexture;
+use crate::throughput::ThroughputCutoff;
+use nalgebra::Vector3;
+use std::str::FromStr;
+
+pub const MAX_DEPTH: i32 = 1000;
+
+// How the radiance arriving along a camera ray is estimated. Renders take one of
+// these, so strategies can be swapped and compared on the same scene.
+pub trait Integrator: Sync {
+    fn radiance(&self, ray: &Ray, scene: &Scene) -> Vector3<Float>;
+    // The radiance along each of up to LANES coherent camera rays, the samples of
+    // one pixel. Ray by ray here; integrators whose rays stay in step override it
+    // to trace their primary and shadow rays as packets.
+    fn radiance_packet(&self, rays: &[Ray], scene: &Scene) -> Vec<Vector3<Float>> {
+        rays.iter().map(|ray| self.radiance(ray, scene)).collect()
+    }
+}
+
+// Which integrator renders: the path tracer, the same without light sampling,
+// ambient occlusion, direct light only, the path tracer over clay, or a debug view.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum IntegratorKind {
+    #[default]
+    Path,
+    Naive,
+    AO,
+    Direct,
+    Clay,
+    Debug(DebugView),
+}
+
+impl FromStr for IntegratorKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "path" => Ok(IntegratorKind::Path),
+            "naive" => Ok(IntegratorKind::Naive),
+            "ao" => Ok(IntegratorKind::AO),
+            "direct" => Ok(IntegratorKind::Direct),
+            "clay" => Ok(IntegratorKind::Clay),
+            "normal" => Ok(IntegratorKind::Debug(DebugView::Normal)),
+            "uv" => Ok(IntegratorKind::Debug(DebugView::UV)),
+            "depth" => Ok(IntegratorKind::Debug(DebugView::Depth)),
+            "visits" => Ok(IntegratorKind::Debug(DebugView::Visits)),
+            _ => Err(format!("unknown integrator: {}", s)),
+        }
+    }
+}
+
+// The path tracer: light sampling mixed with the bsdf at every diffuse vertex, with
+// the caustic connections, light links and guide the scene and settings bring.
+// With `training` set, scatter vertices record what they see into the guide
+// instead of only sampling from it.
+#[derive(Clone, Copy, Default)]
+pub struct PathTracer<'a> {
+    pub cutoff: ThroughputCutoff,
+    pub bounces: BounceLimits,
+    pub clamp: Option<Float>,
+    pub mnee: Option<&'a Mnee>,
+    pub links: Option<&'a LightLinks>,
+    pub guide: Option<&'a Guide>,
+    pub training: bool,
+    pub background: Option<&'a dyn Background>,
+}
+
+impl Integrator for PathTracer<'_> {
+    fn radiance(&self, ray: &Ray, scene: &Scene) -> Vector3<Float> {
+        color(
+            ray,
+            scene.world.as_ref(),
+            scene.light_shape.as_deref(),
+            self,
+        )
+    }
+}
+
+impl PathTracer<'_> {
+    // the radiance gathered past a primary hit, scaled down to the clamp so its
+    // hue survives; deeper vertices are left alone as the primary one caps their sum
+    pub fn clamp(&self, bounces: Bounces, radiance: Vector3<Float>) -> Vector3<Float> {
+        match self.clamp {
+            Some(clamp) if bounces.depth == 0 && radiance.max() > clamp => {
+                radiance * (clamp / radiance.max())
+            }
+            _ => radiance,
+        }
+    }
+}
+
+// The ray a diffuse bounce at the hit continues along, drawn from the material's
+// pdf mixed with the lights' and blended with the guide's, and the density it was
+// drawn with. Shared with the wavefront tracer.
+pub fn scatter_direction(
+    ray: &Ray,
+    hit: &HitRecord,
+    pdf: PDF,
+    light_shape: Option<&dyn Hittable>,
+    tracer: &PathTracer,
+) -> (Ray, Float) {
+    let linked_targets = tracer
+        .links
+        .and_then(|links| links.targets_for(hit.object_id));
+    let targets: Option<&dyn Hittable> = match &linked_targets {
+        Some(linked_targets) if linked_targets.is_empty() => None,
+        Some(linked_targets) => Some(linked_targets),
+        None => light_shape,
+    };
+    let hittable_pdf = targets.map(|targets| PDF::hittable(targets, hit.p));
+    let mixture = match &hittable_pdf {
+        Some(hittable_pdf) => PDF::mixture(hittable_pdf, &pdf),
+        None => pdf,
+    };
+    let guided = tracer
+        .guide
+        .and_then(|guide| guide.distribution(&hit.p))
+        .map(|(distribution, share)| (PDF::guided(distribution), share));
+    let blended;
+    let pdf_fun = match &guided {
+        Some((guided_pdf, share)) => {
+            blended = PDF::blend(&mixture, guided_pdf, *share);
+            &blended
+        }
+        None => &mixture,
+    };
+    let scattered = hit
+        .spawn(pdf_fun.generate(), ray.time())
+        .with_wavelength(ray.wavelength())
+        .with_cone(ray.continued(hit.t, float::consts::FRAC_PI_2));
+    let pdf_val = pdf_fun.value(scattered.direction());
+    (scattered, pdf_val)
+}
+
+// The radiance a path gathers as color follows it, vertex by vertex. What the
+// camera ray finds where it lands is kept apart from what arrives past there, which
+// the clamp holds down, and while the guide trains, each diffuse vertex keeps the
+// radiance arriving along the ray it went on along, to record once the path ends.
+// When finite::debugging, the vertex where the radiance first stops being finite
+// is logged.
+pub struct Gathered {
+    throughput: Vector3<Float>,
+    first: Vector3<Float>,
+    past: Vector3<Float>,
+    seen: Vec<Seen>,
+    // the bounce and material of the current vertex, and whether the path has
+    // been logged
+    vertex: (i32, &'static str),
+    reported: bool,
+}
+
+// a diffuse vertex the guide learns from, with the throughput from the ray it went
+// on along and the radiance that ray has brought so far
+struct Seen {
+    p: Vector3<Float>,
+    direction: Vector3<Float>,
+    pdf: Float,
+    throughput: Vector3<Float>,
+    incoming: Vector3<Float>,
+}
+
+impl Default for Gathered {
+    fn default() -> Self {
+        Gathered {
+            throughput: Vector3::new(1.0, 1.0, 1.0),
+            first: Vector3::zeros(),
+            past: Vector3::zeros(),
+            seen: Vec::new(),
+            vertex: (0, "the camera"),
+            reported: false,
+        }
+    }
+}
+
+impl Gathered {
+    // whether the vertex the radiance stopped being finite at has been logged
+    pub fn reported(&self) -> bool {
+        self.reported
+    }
+
+    // the weight of radiance arriving at the current vertex
+    pub fn throughput(&self) -> Vector3<Float> {
+        self.throughput
+    }
+
+    // the path reaches the vertex at the bounce, on the material or whatever else
+    // ends it there
+    pub fn at(&mut self, bounces: Bounces, material: &'static str) {
+        self.vertex = (bounces.depth, material);
+    }
+
+    // logs the current vertex if it left the radiance non-finite
+    fn watch(&mut self) {
+        if finite::debugging()
+            && !self.reported
+            && !(finite::is_finite(&self.throughput)
+                && finite::is_finite(&self.first)
+                && finite::is_finite(&self.past))
+        {
+            finite::report(Some(self.vertex));
+            self.reported = true;
+        }
+    }
+
+    // what the current vertex sends back itself, its emission or the background
+    // where the ray left the scene; the first vertex's is left unclamped
+    pub fn emit(&mut self, bounces: Bounces, radiance: Vector3<Float>) {
+        if bounces.depth == 0 {
+            self.first += radiance;
+            self.watch();
+        } else {
+            self.add(radiance);
+        }
+    }
+
+    // radiance arriving at the current vertex
+    pub fn add(&mut self, radiance: Vector3<Float>) {
+        self.past += self.throughput.component_mul(&radiance);
+        for seen in &mut self.seen {
+            seen.incoming += seen.throughput.component_mul(&radiance);
+        }
+        self.watch();
+    }
+
+    // the path goes on from the current vertex, its throughput scaled by `factor`
+    pub fn go_on(&mut self, factor: Vector3<Float>) {
+        self.throughput.component_mul_assign(&factor);
+        for seen in &mut self.seen {
+            seen.throughput.component_mul_assign(&factor);
+        }
+        self.watch();
+    }
+
+    // keeps the diffuse vertex the path just left at p along `direction`, drawn
+    // with density `pdf`, for the guide to learn from
+    pub fn see(&mut self, p: Vector3<Float>, direction: Vector3<Float>, pdf: Float) {
+        self.seen.push(Seen {
+            p,
+            direction,
+            pdf,
+            throughput: Vector3::new(1.0, 1.0, 1.0),
+            incoming: Vector3::zeros(),
+        });
+    }
+
+    // the path's radiance once it has ended, recording what its diffuse vertices
+    // saw into the guide, the deepest first
+    pub fn finish(&self, tracer: &PathTracer) -> Vector3<Float> {
+        if let Some(guide) = tracer.guide {
+            for seen in self.seen.iter().rev() {
+                let luminance = seen.incoming.dot(&Vector3::new(0.2126, 0.7152, 0.0722));
+                guide.record(&seen.p, &seen.direction, luminance / seen.pdf);
+            }
+        }
+        self.first + tracer.clamp(Bounces::default(), self.past)
+    }
+}
+
+// The path tracer's estimate along the camera ray, following the path a bounce at a
+// time with the throughput it has left. Specular vertices pass what arrives on
+// through their attenuation, counting their own emission only where the path ends
+// there; diffuse ones add their emission and the caustics mnee connects, then go on
+// along a direction drawn by scatter_direction.
+fn color(
+    ray: &Ray,
+    world: &dyn Hittable,
+    light_shape: Option<&dyn Hittable>,
+    tracer: &PathTracer,
+) -> Vector3<Float> {
+    let mnee = tracer.mnee;
+    let mut ray = Ray::new(ray.origin(), ray.direction(), ray.time())
+        .with_wavelength(ray.wavelength())
+        .with_differentials(ray.differentials());
+    let mut bounces = Bounces::default();
+    let mut gathered = Gathered::default();
+    // the refractions through mnee's caster since the last diffuse vertex, and
+    // that vertex, which the light links judge emission by
+    let mut chain = None;
+    let mut receiver = None;
+    loop {
+        if bounces.depth > 0 {
+            stats::count(Counter::SecondaryRays);
+        }
+        let Some(hit) = world.hit(&ray, ray::T_MIN, Float::MAX) else {
+            let background = tracer.background.map_or(Vector3::zeros(), |background| {
+                background.radiance(&ray.direction())
+            });
+            gathered.at(bounces, "the background");
+            gathered.emit(bounces, background);
+            pathlog::miss(bounces, &background);
+            break;
+        };
+        // on from where light entering there leaves, see Material::exit
+        let hit = hit.material.exit(&ray, hit);
+        gathered.at(bounces, hit.material.name());
+        pathlog::hit(bounces, &hit);
+        let linked = match (tracer.links, receiver) {
+            (Some(links), Some(receiver)) => links.illuminates(hit.object_id, receiver),
+            _ => true,
+        };
+        let emitted = if Mnee::covers(chain) || !linked {
+            Vector3::zeros()
+        } else {
+            hit.material.emitted(&ray, &hit)
+        };
+        let weight = if bounces.depth < MAX_DEPTH {
+            tracer.cutoff.continuation(&gathered.throughput())
+        } else {
+            None
+        };
+        pathlog::emitted(&emitted);
+        let scatter = weight.and_then(|weight| Some((weight, hit.material.scatter(&ray, &hit)?)));
+        let Some((weight, scatter)) = scatter else {
+            gathered.emit(bounces, emitted);
+            pathlog::end(match weight {
+                Some(_) => "the material does not scatter",
+                None => "stopped by the throughput cutoff or at the depth limit",
+            });
+            break;
+        };
+        match scatter {
+            ScatterRecord::Specular {
+                specular_ray,
+                attenuation,
+            } => {
+                let specular_ray = differential::specular(&ray, &hit, specular_ray);
+                let kind = BounceKind::specular(&ray, &hit, &specular_ray);
+                let Some(next) = bounces.after(kind, &tracer.bounces) else {
+                    gathered.emit(bounces, emitted);
+                    pathlog::end("at the bounce limit");
+                    break;
+                };
+                chain = mnee.and_then(|mnee| mnee.extend_chain(chain, &ray, &hit, &specular_ray));
+                gathered.go_on(weight * attenuation);
+                pathlog::specular(&(weight * attenuation), &gathered.throughput());
+                bounces = next;
+                ray = specular_ray;
+            }
+            ScatterRecord::Scatter { pdf, attenuation } => {
+                gathered.emit(bounces, emitted);
+                let Some(next) = bounces.after(BounceKind::Diffuse, &tracer.bounces) else {
+                    pathlog::end("at the bounce limit");
+                    break;
+                };
+                let (scattered, pdf_val) = scatter_direction(&ray, &hit, pdf, light_shape, tracer);
+                if let Some(mnee) = mnee {
+                    let caustic = weight * mnee.sample(&ray, &hit, &attenuation, world);
+                    pathlog::added("caustic", &gathered.throughput().component_mul(&caustic));
+                    gathered.add(caustic);
+                }
+                // a direction the mixture cannot produce again carries no usable
+                // estimate
+                if !(pdf_val > 0.0 && pdf_val.is_finite()) {
+                    pathlog::end(&format!("drew a direction of pdf {}", pdf_val));
+                    break;
+                }
+                let scattering_pdf = hit.material.scattering_pdf(&ray, &hit, &scattered);
+                gathered.go_on(weight * attenuation * scattering_pdf / pdf_val);
+                pathlog::diffuse(
+                    &hit,
+                    light_shape,
+                    &scattered.direction(),
+                    pdf_val,
+                    scattering_pdf,
+                    &(weight * attenuation),
+                    &gathered.throughput(),
+                );
+                if tracer.training {
+                    gathered.see(hit.p, scattered.direction(), pdf_val);
+                }
+                // light the links let through blockers the ray stops at
+                if let Some(links) = tracer.links {
+                    let unshadowed = links.unshadowed(&scattered, world, hit.object_id);
+                    pathlog::added(
+                        "linked light",
+                        &gathered.throughput().component_mul(&unshadowed),
+                    );
+                    gathered.add(unshadowed);
+                }
+                chain = mnee.map(|_| 0);
+                receiver = Some(hit.object_id);
+                bounces = next;
+                ray = scattered;
+            }
+        }
+    }
+    gathered.finish(tracer)
+}
+
+// Paths that only follow the bsdf, as the book's first renderer did, so lights are
+// found by chance. Slow to converge, but the reference the sampling strategies of
+// PathTracer must agree with.
+#[derive(Clone, Copy, Default)]
+pub struct NaivePathTracer {
+    pub cutoff: ThroughputCutoff,
+    pub bounces: BounceLimits,
+}
+
+impl Integrator for NaivePathTracer {
+    fn radiance(&self, ray: &Ray, scene: &Scene) -> Vector3<Float> {
+        let mut ray = Ray::new(ray.origin(), ray.direction(), ray.time())
+            .with_wavelength(ray.wavelength())
+            .with_differentials(ray.differentials());
+        let mut bounces = Bounces::default();
+        let mut throughput = Vector3::new(1.0, 1.0, 1.0);
+        let mut radiance = Vector3::zeros();
+        while bounces.depth < MAX_DEPTH {
+            if bounces.depth > 0 {
+                stats::count(Counter::SecondaryRays);
+            }
+            let Some(hit) = scene.world.hit(&ray, ray::T_MIN, Float::MAX) else {
+                return radiance
+                    + throughput.component_mul(&scene.background.radiance(&ray.direction()));
+            };
+            let hit = hit.material.exit(&ray, hit);
+            radiance += throughput.component_mul(&hit.material.emitted(&ray, &hit));
+            let Some(weight) = self.cutoff.continuation(&throughput) else {
+                break;
+            };
+            let (scattered, factor, kind) = match hit.material.scatter(&ray, &hit) {
+                None => break,
+                Some(ScatterRecord::Specular {
+                    specular_ray,
+                    attenuation,
+                }) => {
+                    let kind = BounceKind::specular(&ray, &hit, &specular_ray);
+                    (
+                        differential::specular(&ray, &hit, specular_ray),
+                        attenuation,
+                        kind,
+                    )
+                }
+                Some(ScatterRecord::Scatter { pdf, attenuation }) => {
+                    let scattered = hit
+                        .spawn(pdf.generate(), ray.time())
+                        .with_wavelength(ray.wavelength())
+                        .with_cone(ray.continued(hit.t, float::consts::FRAC_PI_2));
+                    let pdf_val = pdf.value(scattered.direction());
+                    if !(pdf_val > 0.0 && pdf_val.is_finite()) {
+                        break;
+                    }
+                    let scattering_pdf = hit.material.scattering_pdf(&ray, &hit, &scattered);
+                    let factor = attenuation * scattering_pdf / pdf_val;
+                    (scattered, factor, BounceKind::Diffuse)
+                }
+            };
+            let Some(next) = bounces.after(kind, &self.bounces) else {
+                break;
+            };
+            bounces = next;
+            throughput = weight * throughput.component_mul(&factor);
+            ray = scattered;
+        }
+        radiance
+    }
+}
+
+// The share of the hemisphere above the first surface a ray hits that is open for
+// `distance`, by one cosine weighted ray, in grey; rays that miss see the background.
+#[derive(Clone, Copy)]
+pub struct AmbientOcclusion {
+    pub distance: Float,
+}
+
+impl Default for AmbientOcclusion {
+    fn default() -> Self {
+        AmbientOcclusion {
+            distance: Float::MAX,
+        }
+    }
+}
+
+impl AmbientOcclusion {
+    // the probe sent from where the camera ray hit
+    fn probe(&self, ray: &Ray, hit: &HitRecord) -> Ray {
+        // the side of the surface the ray arrived on
+        let normal = if hit.normal.dot(&ray.direction()) < 0.0 {
+            hit.normal
+        } else {
+            -hit.normal
+        };
+        let direction = ONB::build_from_w(&normal)
+            .local(&sampling::cosine_hemisphere(rng::uniform(), rng::uniform()));
+        stats::count(Counter::ShadowRays);
+        hit.spawn(direction, ray.time())
+    }
+}
+
+fn visibility(occluded: bool) -> Vector3<Float> {
+    if occluded {
+        Vector3::zeros()
+    } else {
+        Vector3::new(1.0, 1.0, 1.0)
+    }
+}
+
+impl Integrator for AmbientOcclusion {
+    fn radiance(&self, ray: &Ray, scene: &Scene) -> Vector3<Float> {
+        let Some(hit) = scene.world.hit(ray, ray::T_MIN, Float::MAX) else {
+            return scene.background.radiance(&ray.direction());
+        };
+        let probe = self.probe(ray, &hit);
+        visibility(scene.world.occluded(&probe, ray::T_MIN, self.distance))
+    }
+
+    fn radiance_packet(&self, rays: &[Ray], scene: &Scene) -> Vec<Vector3<Float>> {
+        let hits = scene
+            .world
+            .hit_packet(&RayPacket::new(rays, ray::T_MIN, &[Float::MAX; LANES]));
+        let mut radiance = Vec::with_capacity(rays.len());
+        let (mut probes, mut lanes) = (Vec::new(), Vec::new());
+        for (lane, (ray, hit)) in rays.iter().zip(hits).enumerate() {
+            match hit {
+                Some(hit) => {
+                    probes.push(self.probe(ray, &hit));
+                    lanes.push(lane);
+                    radiance.push(Vector3::zeros());
+                }
+                None => radiance.push(scene.background.radiance(&ray.direction())),
+            }
+        }
+        let occluded = scene.world.occluded_packet(&RayPacket::new(
+            &probes,
+            ray::T_MIN,
+            &[self.distance; LANES],
+        ));
+        for (&lane, occluded) in lanes.iter().zip(occluded) {
+            radiance[lane] = visibility(occluded);
+        }
+        radiance
+    }
+}
+
+// how far toward the emitter it was aimed at a shadow ray looks for blockers, short
+// of the emitter itself
+pub const SHADOW_REACH: Float = 0.999;
+
+// The emitter among the shapes sampled toward that a light sample is aimed at, its
+// emission along the ray and how far along it is, looking past portals and the
+// shapes sampled toward for their caustics, which do not emit. None where the
+// sample is aimed at no emitter, so sees the background if anything. With it a
+// light sample only asks the world whether anything is in the way.
+pub fn aimed_at(light_shape: &dyn Hittable, ray: &Ray) -> Option<(Vector3<Float>, Float)> {
+    let mut surfaces = Surfaces::new(ray);
+    while let Some(hit) = surfaces.next(light_shape, ray::T_MIN, Float::MAX) {
+        let emitted = hit.material.emitted(ray, &hit);
+        if emitted != Vector3::zeros() {
+            return Some((emitted, hit.t));
+        }
+    }
+    None
+}
+
+// Emission seen directly or through mirrors and glass, plus one light sample at
+// the first diffuse surface, without any light bouncing between surfaces. Scenes
+// without light shapes show their emitters only, and the background only through
+// portals.
+#[derive(Clone, Copy, Default)]
+pub struct DirectLightingOnly {
+    pub bounces: BounceLimits,
+}
+
+// A light sample taken at a diffuse surface: the shadow ray toward the light, how
+// far along it blockers count, and the radiance it adds when none is there.
+struct LightSample {
+    ray: Ray,
+    reach: Float,
+    radiance: Vector3<Float>,
+}
+
+impl DirectLightingOnly {
+    // The emission met from the camera ray's first hit, `hit`, on through mirrors
+    // and glass, and the light sample taken at the diffuse surface that ends the
+    // walk, left to the caller to test for blockers so camera rays traced as a
+    // packet can send their shadow rays as one too.
+    fn gather<'s>(
+        &self,
+        ray: &Ray,
+        hit: Option<HitRecord<'s>>,
+        scene: &'s Scene,
+    ) -> (Vector3<Float>, Option<LightSample>) {
+        let mut ray = Ray::new(ray.origin(), ray.direction(), ray.time())
+            .with_wavelength(ray.wavelength())
+            .with_differentials(ray.differentials());
+        let mut hit = hit;
+        let mut bounces = Bounces::default();
+        let mut throughput = Vector3::new(1.0, 1.0, 1.0);
+        let mut radiance = Vector3::zeros();
+        loop {
+            let Some(h) = hit else {
+                let background = scene.background.radiance(&ray.direction());
+                return (radiance + throughput.component_mul(&background), None);
+            };
+            let h = h.material.exit(&ray, h);
+            radiance += throughput.component_mul(&h.material.emitted(&ray, &h));
+            match h.material.scatter(&ray, &h) {
+                None => break,
+                Some(ScatterRecord::Specular {
+                    specular_ray,
+                    attenuation,
+                }) => {
+                    let kind = BounceKind::specular(&ray, &h, &specular_ray);
+                    let Some(next) = bounces.after(kind, &self.bounces) else {
+                        break;
+                    };
+                    bounces = next;
+                    throughput = throughput.component_mul(&attenuation);
+                    ray = differential::specular(&ray, &h, specular_ray);
+                    if bounces.depth >= MAX_DEPTH {
+                        break;
+                    }
+                    stats::count(Counter::SecondaryRays);
+                    hit = scene.world.hit(&ray, ray::T_MIN, Float::MAX);
+                }
+                Some(ScatterRecord::Scatter { attenuation, .. }) => {
+                    let Some(light_shape) = scene.light_shape.as_deref() else {
+                        break;
+                    };
+                    let light_pdf = PDF::hittable(light_shape, h.p);
+                    let to_light = h
+                        .spawn(light_pdf.generate(), ray.time())
+                        .with_wavelength(ray.wavelength());
+                    let pdf_val = light_pdf.value(to_light.direction());
+                    if !(pdf_val > 0.0 && pdf_val.is_finite()) {
+                        break;
+                    }
+                    stats::count(Counter::ShadowRays);
+                    // a sample through a portal that leaves the scene sees the
+                    // background
+                    let (emitted, reach) = match aimed_at(light_shape, &to_light) {
+                        Some((emitted, t)) => (emitted, SHADOW_REACH * t),
+                        None => (scene.background.radiance(&to_light.direction()), Float::MAX),
+                    };
+                    let scattering_pdf = h.material.scattering_pdf(&ray, &h, &to_light);
+                    let light = LightSample {
+                        ray: to_light,
+                        reach,
+                        radiance: throughput
+                            .component_mul(&attenuation)
+                            .component_mul(&emitted)
+                            * scattering_pdf
+                            / pdf_val,
+                    };
+                    return (radiance, Some(light));
+                }
+            }
+        }
+        (radiance, None)
+    }
+}
+
+impl Integrator for DirectLightingOnly {
+    fn radiance(&self, ray: &Ray, scene: &Scene) -> Vector3<Float> {
+        match self.gather(ray, scene.world.hit(ray, ray::T_MIN, Float::MAX), scene) {
+            (radiance, Some(light))
+                if !scene.world.occluded(&light.ray, ray::T_MIN, light.reach) =>
+            {
+                radiance + light.radiance
+            }
+            (radiance, _) => radiance,
+        }
+    }
+
+    fn radiance_packet(&self, rays: &[Ray], scene: &Scene) -> Vec<Vector3<Float>> {
+        let hits = scene
+            .world
+            .hit_packet(&RayPacket::new(rays, ray::T_MIN, &[Float::MAX; LANES]));
+        let mut radiance = Vec::with_capacity(rays.len());
+        let (mut shadow_rays, mut reach, mut lights) = (Vec::new(), Vec::new(), Vec::new());
+        for (lane, (ray, hit)) in rays.iter().zip(hits).enumerate() {
+            let (gathered, light) = self.gather(ray, hit, scene);
+            radiance.push(gathered);
+            if let Some(light) = light {
+                shadow_rays.push(light.ray);
+                reach.push(light.reach);
+                lights.push((lane, light.radiance));
+            }
+        }
+        let occluded =
+            scene
+                .world
+                .occluded_packet(&RayPacket::new(&shadow_rays, ray::T_MIN, &reach));
+        for (&(lane, light), occluded) in lights.iter().zip(occluded) {
+            if !occluded {
+                radiance[lane] += light;
+            }
+        }
+        radiance
+    }
+}
+
+// The path tracer over a world whose surfaces are all matte grey, for checking
+// geometry and lighting before materials. Whatever emits toward the ray keeps its
+// material so the lights still light the scene.
+pub struct ClayRender<'a> {
+    pub tracer: PathTracer<'a>,
+    clay: Lambertian<ConstantTexture>,
+}
+
+impl<'a> ClayRender<'a> {
+    pub fn new(tracer: PathTracer<'a>) -> Self {
+        ClayRender {
+            tracer,
+            clay: Lambertian::new(ConstantTexture::new(0.5, 0.5, 0.5)),
+        }
+    }
+}
+
+impl Integrator for ClayRender<'_> {
+    fn radiance(&self, ray: &Ray, scene: &Scene) -> Vector3<Float> {
+        let world = Clay {
+            world: scene.world.as_ref(),
+            clay: &self.clay,
+        };
+        color(ray, &world, scene.light_shape.as_deref(), &self.tracer)
+    }
+}
+
+struct Clay<'a> {
+    world: &'a dyn Hittable,
+    clay: &'a Lambertian<ConstantTexture>,
+}
+
+impl Hittable for Clay<'_> {
+    fn hit(&self, ray: &Ray, t_min: Float, t_max: Float) -> Option<HitRecord<'_>> {
+        self.world.hit(ray, t_min, t_max).map(|mut hit| {
+            if hit.material.emitted(ray, &hit) == Vector3::zeros() {
+                hit.material = self.clay;
+            }
+            hit
+        })
+    }
+
+    fn bounding_box(&self, t0: Float, t1: Float) -> Option<AABB> {
+        self.world.bounding_box(t0, t1)
+    }
+}
+
+// What a debug view shows of the first hit in false colour: the shading normal
+// mapped from [-1, 1], the texture coordinates in red and green, or on a heat ramp
+// the distance along the ray or the acceleration structure nodes the ray visited.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum DebugView {
+    Normal,
+    UV,
+    Depth,
+    Visits,
+}
+
+// node visits shown as the hot end of the ramp
+const MAX_VISITS: Float = 256.0;
+
+// Shades camera rays by a debug view instead of light. Depth is shown relative to
+// `far`, the scene's extent; rays that miss are black but for the nodes they visited.
+#[derive(Clone, Copy)]
+pub struct DebugIntegrator {
+    pub view: DebugView,
+    pub far: Float,
+}
+
+impl Integrator for DebugIntegrator {
+    fn radiance(&self, ray: &Ray, scene: &Scene) -> Vector3<Float> {
+        bvh::take_visits();
+        let hit = scene.world.hit(ray, ray::T_MIN, Float::MAX);
+        match (self.view, hit) {
+            (DebugView::Visits, _) => heat(bvh::take_visits() as Float / MAX_VISITS),
+            (_, None) => Vector3::zeros(),
+            (DebugView::Normal, Some(hit)) => (hit.normal + Vector3::new(1.0, 1.0, 1.0)) * 0.5,
+            (DebugView::UV, Some(hit)) => Vector3::new(hit.u, hit.v, 0.0),
+            (DebugView::Depth, Some(hit)) => heat(hit.t * ray.direction().norm() / self.far),
+        }
+    }
+}
+
+// blue through cyan, green and yellow to red as x goes from 0 to 1
+fn heat(x: Float) -> Vector3<Float> {
+    let x = 4.0 * x.clamp(0.0, 1.0);
+    Vector3::new(
+        (x - 2.0).clamp(0.0, 1.0),
+        x.min(1.0).min(4.0 - x),
+        (2.0 - x).clamp(0.0, 1.0),
+    )
+}
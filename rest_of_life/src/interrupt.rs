@@ -0,0 +1,27 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+
+// Ctrl-C and SIGTERM during a render. Once the handler is installed the first
+// signal only raises a flag: every pixel stops taking samples at the next one, so
+// the render returns early with the samples it has, and the caller writes that
+// image out instead of losing the work. A second signal exits at once.
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
+// Exit status of a render cut short, as a shell reports a process SIGINT ended.
+pub const EXIT_STATUS: i32 = 130;
+
+pub fn install() -> Result<(), String> {
+    #[cfg(not(target_arch = "wasm32"))]
+    ctrlc::set_handler(|| {
+        if INTERRUPTED.swap(true, Ordering::Relaxed) {
+            std::process::exit(EXIT_STATUS);
+        }
+        eprintln!("interrupted, finishing the samples in flight; again to quit at once");
+    })
+    .map_err(|e| format!("cannot handle interrupts: {}", e))?;
+    Ok(())
+}
+
+#[inline(always)]
+pub fn interrupted() -> bool {
+    INTERRUPTED.load(Ordering::Relaxed)
+}
@@ -0,0 +1,477 @@
+use crate::aabb;
+use crate::aabb::AABB;
+use crate::bvh;
+use crate::cone::{self, Cone};
+use crate::float::Float;
+use crate::hittable::{HitRecord, Hittable};
+use crate::ray::Ray;
+use crate::stats::{self, Counter};
+
+// relative costs of a traversal step and a primitive intersection in the SAH
+const TRAVERSAL_COST: Float = 1.0;
+const INTERSECT_COST: Float = 80.0;
+// extra weight for splits that leave one side empty
+const EMPTY_BONUS: Float = 0.5;
+const TRAVERSAL_STACK: usize = 64;
+
+#[derive(Clone, Copy)]
+enum KdNode {
+    Interior {
+        axis: usize,
+        split: Float,
+        above: u32,
+    },
+    Leaf {
+        start: u32,
+        count: u32,
+    },
+}
+
+#[derive(Clone, Copy)]
+struct Edge {
+    t: Float,
+    start: bool,
+}
+
+// A kd-tree over the primitives' bounding boxes with SAH chosen split planes.
+// Primitives straddling a plane are referenced from both sides; nodes are stored
+// depth first with the below child directly after its parent.
+pub struct KdTree {
+    nodes: Vec<KdNode>,
+    indices: Vec<u32>,
+    primitives: Vec<Box<dyn Hittable>>,
+    bbox: AABB,
+}
+
+// depth limit suggested for a tree over n primitives
+pub fn default_max_depth(n: usize) -> usize {
+    (8.0 + 1.3 * (n as Float).log2()).round() as usize
+}
+
+impl KdTree {
+    #[allow(dead_code)]
+    pub fn new(hittable: Vec<Box<dyn Hittable>>, time0: Float, time1: Float) -> Self {
+        let max_depth = default_max_depth(hittable.len());
+        KdTree::with_params(hittable, time0, time1, max_depth, 1)
+    }
+
+    pub fn with_params(
+        hittable: Vec<Box<dyn Hittable>>,
+        time0: Float,
+        time1: Float,
+        max_depth: usize,
+        leaf_size: usize,
+    ) -> Self {
+        if hittable.is_empty() {
+            panic!["no elements in scene"]
+        }
+        let boxes = hittable
+            .iter()
+            .map(|h| match h.bounding_box(time0, time1) {
+                Some(bbox) => bbox,
+                None => panic!["no bounding box in kd-tree node"],
+            })
+            .collect::<Vec<AABB>>();
+        let bbox = boxes
+            .iter()
+            .skip(1)
+            .fold(boxes[0], |acc, b| aabb::surrounding_box(&acc, b));
+        let mut tree = KdTree {
+            nodes: Vec::new(),
+            indices: Vec::new(),
+            primitives: hittable,
+            bbox,
+        };
+        let all = (0..boxes.len() as u32).collect::<Vec<u32>>();
+        tree.build(
+            &boxes,
+            all,
+            bbox,
+            max_depth.min(TRAVERSAL_STACK - 1),
+            leaf_size.max(1),
+            0,
+        );
+        tree
+    }
+
+    fn build(
+        &mut self,
+        boxes: &[AABB],
+        primitives: Vec<u32>,
+        bounds: AABB,
+        depth: usize,
+        leaf_size: usize,
+        bad_refines: usize,
+    ) {
+        if primitives.len() <= leaf_size || depth == 0 {
+            self.push_leaf(primitives);
+            return;
+        }
+
+        let extent = bounds.max - bounds.min;
+        let area = |d: [Float; 3]| 2.0 * (d[0] * d[1] + d[1] * d[2] + d[2] * d[0]);
+        let total_area = area([extent.x, extent.y, extent.z]);
+        let leaf_cost = INTERSECT_COST * primitives.len() as Float;
+        let mut best: Option<(Float, usize, Float)> = None;
+
+        // sweep the sorted box edges along each axis, counting primitives on either
+        // side of every candidate plane
+        for axis in 0..3 {
+            let mut edges = Vec::with_capacity(2 * primitives.len());
+            for &p in primitives.iter() {
+                let b = &boxes[p as usize];
+                edges.push(Edge {
+                    t: b.min[axis],
+                    start: true,
+                });
+                edges.push(Edge {
+                    t: b.max[axis],
+                    start: false,
+                });
+            }
+            edges.sort_unstable_by(|a, b| a.t.total_cmp(&b.t).then_with(|| b.start.cmp(&a.start)));
+            let (o1, o2) = ((axis + 1) % 3, (axis + 2) % 3);
+            let mut below = 0;
+            let mut above = primitives.len();
+            for edge in edges.iter() {
+                if !edge.start {
+                    above -= 1;
+                }
+                if edge.t > bounds.min[axis] && edge.t < bounds.max[axis] {
+                    let mut d_below = [0.0; 3];
+                    let mut d_above = [0.0; 3];
+                    d_below[o1] = extent[o1];
+                    d_below[o2] = extent[o2];
+                    d_above[o1] = extent[o1];
+                    d_above[o2] = extent[o2];
+                    d_below[axis] = edge.t - bounds.min[axis];
+                    d_above[axis] = bounds.max[axis] - edge.t;
+                    let p_below = area(d_below) / total_area;
+                    let p_above = area(d_above) / total_area;
+                    let bonus = if below == 0 || above == 0 {
+                        EMPTY_BONUS
+                    } else {
+                        0.0
+                    };
+                    let cost = TRAVERSAL_COST
+                        + INTERSECT_COST
+                            * (1.0 - bonus)
+                            * (p_below * below as Float + p_above * above as Float);
+                    if best.is_none_or(|(c, _, _)| cost < c) {
+                        best = Some((cost, axis, edge.t));
+                    }
+                }
+                if edge.start {
+                    below += 1;
+                }
+            }
+        }
+
+        let (cost, axis, split) = match best {
+            Some(best) => best,
+            None => {
+                self.push_leaf(primitives);
+                return;
+            }
+        };
+        // tolerate a few refinements that look worse than a leaf before giving up
+        let bad_refines = if cost > leaf_cost {
+            bad_refines + 1
+        } else {
+            bad_refines
+        };
+        if (cost > 4.0 * leaf_cost && primitives.len() < 16) || bad_refines == 3 {
+            self.push_leaf(primitives);
+            return;
+        }
+
+        // boxes lying flat on the plane go to both sides
+        let below = primitives
+            .iter()
+            .cloned()
+            .filter(|&p| {
+                boxes[p as usize].min[axis] < split || boxes[p as usize].max[axis] <= split
+            })
+            .collect::<Vec<u32>>();
+        let above = primitives
+            .iter()
+            .cloned()
+            .filter(|&p| {
+                boxes[p as usize].max[axis] > split || boxes[p as usize].min[axis] >= split
+            })
+            .collect::<Vec<u32>>();
+        let mut below_bounds = bounds;
+        below_bounds.max[axis] = split;
+        let mut above_bounds = bounds;
+        above_bounds.min[axis] = split;
+
+        let index = self.nodes.len();
+        self.nodes.push(KdNode::Interior {
+            axis,
+            split,
+            above: 0,
+        });
+        self.build(
+            boxes,
+            below,
+            below_bounds,
+            depth - 1,
+            leaf_size,
+            bad_refines,
+        );
+        let above_index = self.nodes.len() as u32;
+        if let KdNode::Interior { above, .. } = &mut self.nodes[index] {
+            *above = above_index;
+        }
+        self.build(
+            boxes,
+            above,
+            above_bounds,
+            depth - 1,
+            leaf_size,
+            bad_refines,
+        );
+    }
+
+    fn push_leaf(&mut self, primitives: Vec<u32>) {
+        self.nodes.push(KdNode::Leaf {
+            start: self.indices.len() as u32,
+            count: primitives.len() as u32,
+        });
+        self.indices.extend(primitives);
+    }
+}
+
+impl Hittable for KdTree {
+    fn hit(&self, ray: &Ray, t_min: Float, mut t_max: Float) -> Option<HitRecord<'_>> {
+        let (mut t_enter, mut t_exit) = self.bbox.interval(ray, t_min, t_max)?;
+        let origin = ray.origin();
+        let direction = ray.direction();
+        let mut closest = None;
+        let mut stack = [(0, 0.0, 0.0); TRAVERSAL_STACK];
+        let mut top = 0;
+        let mut current = 0;
+        loop {
+            if t_max < t_enter {
+                break;
+            }
+            bvh::count_visit();
+            stats::count(Counter::NodeTests);
+            match self.nodes[current] {
+                KdNode::Interior { axis, split, above } => {
+                    let t_plane = (split - origin[axis]) / direction[axis];
+                    let below_first =
+                        origin[axis] < split || (origin[axis] == split && direction[axis] <= 0.0);
+                    let (first, second) = if below_first {
+                        (current + 1, above as usize)
+                    } else {
+                        (above as usize, current + 1)
+                    };
+                    if t_plane.is_nan() || t_plane > t_exit || t_plane <= 0.0 {
+                        current = first;
+                    } else if t_plane < t_enter {
+                        current = second;
+                    } else {
+                        stack[top] = (second, t_plane, t_exit);
+                        top += 1;
+                        current = first;
+                        t_exit = t_plane;
+                    }
+                    continue;
+                }
+                KdNode::Leaf { start, count } => {
+                    let start = start as usize;
+                    for &p in &self.indices[start..start + count as usize] {
+                        if let Some(hit) = self.primitives[p as usize].hit(ray, t_min, t_max) {
+                            t_max = hit.t;
+                            closest = Some(hit);
+                        }
+                    }
+                }
+            }
+            // a hit inside this cell is nearer than anything in the cells still queued
+            if top == 0 || t_max <= t_exit {
+                break;
+            }
+            top -= 1;
+            (current, t_enter, t_exit) = stack[top];
+        }
+        closest
+    }
+
+    // the same walk, stopping at the first primitive in the way; one found in a
+    // later cell than the one being visited blocks the ray all the same
+    fn occluded(&self, ray: &Ray, t_min: Float, t_max: Float) -> bool {
+        let Some((mut t_enter, mut t_exit)) = self.bbox.interval(ray, t_min, t_max) else {
+            return false;
+        };
+        let origin = ray.origin();
+        let direction = ray.direction();
+        let mut stack = [(0, 0.0, 0.0); TRAVERSAL_STACK];
+        let mut top = 0;
+        let mut current = 0;
+        loop {
+            bvh::count_visit();
+            stats::count(Counter::NodeTests);
+            match self.nodes[current] {
+                KdNode::Interior { axis, split, above } => {
+                    let t_plane = (split - origin[axis]) / direction[axis];
+                    let below_first =
+                        origin[axis] < split || (origin[axis] == split && direction[axis] <= 0.0);
+                    let (first, second) = if below_first {
+                        (current + 1, above as usize)
+                    } else {
+                        (above as usize, current + 1)
+                    };
+                    if t_plane.is_nan() || t_plane > t_exit || t_plane <= 0.0 {
+                        current = first;
+                    } else if t_plane < t_enter {
+                        current = second;
+                    } else {
+                        stack[top] = (second, t_plane, t_exit);
+                        top += 1;
+                        current = first;
+                        t_exit = t_plane;
+                    }
+                    continue;
+                }
+                KdNode::Leaf { start, count } => {
+                    let start = start as usize;
+                    if self.indices[start..start + count as usize]
+                        .iter()
+                        .any(|&p| self.primitives[p as usize].occluded(ray, t_min, t_max))
+                    {
+                        return true;
+                    }
+                }
+            }
+            if top == 0 {
+                return false;
+            }
+            top -= 1;
+            (current, t_enter, t_exit) = stack[top];
+        }
+    }
+
+    fn bounding_box(&self, _t0: Float, _t1: Float) -> Option<AABB> {
+        Some(self.bbox)
+    }
+
+    // Gathers the primitives of every cell the cone's segment, widened by its
+    // footprint, reaches; straddling primitives are counted once.
+    fn occlusion(&self, cone: &Cone, t_min: Float, t_max: Float) -> Float {
+        if !cone.overlaps(&self.bbox, t_min, t_max) {
+            return 0.0;
+        }
+        let (from, to) = (cone.at(t_min), cone.at(t_max));
+        let w = cone.width(t_min).max(cone.width(t_max));
+        let mut candidates = Vec::new();
+        let mut stack = [0; TRAVERSAL_STACK];
+        let mut top = 1;
+        while top > 0 {
+            top -= 1;
+            let current = stack[top];
+            match self.nodes[current] {
+                KdNode::Interior { axis, split, above } => {
+                    if from[axis].min(to[axis]) - w <= split {
+                        stack[top] = current + 1;
+                        top += 1;
+                    }
+                    if from[axis].max(to[axis]) + w >= split {
+                        stack[top] = above as usize;
+                        top += 1;
+                    }
+                }
+                KdNode::Leaf { start, count } => {
+                    let start = start as usize;
+                    candidates.extend_from_slice(&self.indices[start..start + count as usize]);
+                }
+            }
+        }
+        candidates.sort_unstable();
+        candidates.dedup();
+        cone::occlusion_of(
+            candidates
+                .iter()
+                .map(|&p| self.primitives[p as usize].as_ref()),
+            cone,
+            t_min,
+            t_max,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bvh::BVH;
+    use crate::material::Lambertian;
+    use crate::packet::{RayPacket, LANES};
+    use crate::sphere::Sphere;
+    use crate::texture::ConstantTexture;
+    use nalgebra::Vector3;
+
+    #[test]
+    fn kd_tree_hits_what_the_bvh_does() {
+        let grey = Lambertian::new(ConstantTexture::new(0.5, 0.5, 0.5));
+        let spheres = (0..300)
+            .map(|i| {
+                let a = i as Float;
+                let center = Vector3::new(6.0 * a.sin(), 0.05 * a - 7.5, 6.0 * (1.3 * a).cos());
+                Sphere::new(center, 0.2 + 0.3 * (0.7 * a).sin().abs(), grey.clone())
+            })
+            .collect::<Vec<_>>();
+        let bvh = BVH::new(spheres.clone(), 0.0, 1.0);
+        let boxed = spheres
+            .into_iter()
+            .map(|s| Box::new(s) as Box<dyn Hittable>)
+            .collect::<Vec<_>>();
+        let kd = KdTree::new(boxed, 0.0, 1.0);
+        let rays = (0..600)
+            .map(|i| {
+                let a = i as Float;
+                // every other ray starts among the spheres, inside the root's bounds
+                let origin = if i % 2 == 0 {
+                    Vector3::new(20.0 * (0.3 * a).sin(), 4.0 * a.cos(), 20.0)
+                } else {
+                    Vector3::new(5.0 * (0.8 * a).cos(), 7.0 * (0.4 * a).sin(), 5.0 * a.sin())
+                };
+                let target = Vector3::new(5.0 * (1.7 * a).sin(), 7.0 * (0.9 * a).cos(), 0.0);
+                // axis aligned rays too, which lie along split planes' normals
+                let direction = if i % 10 == 0 {
+                    -Vector3::z()
+                } else {
+                    target - origin
+                };
+                Ray::new(origin, direction, 0.0)
+            })
+            .collect::<Vec<_>>();
+        let mut hits = 0;
+        for ray in rays.iter() {
+            let expected = bvh.hit(ray, 0.001, Float::MAX);
+            let hit = kd.hit(ray, 0.001, Float::MAX);
+            assert_eq!(hit.as_ref().map(|h| h.t), expected.as_ref().map(|h| h.t));
+            assert_eq!(hit.map(|h| h.p), expected.as_ref().map(|h| h.p));
+            for reach in [0.1, 0.5, 1.0] {
+                assert_eq!(
+                    kd.occluded(ray, 0.001, reach),
+                    bvh.occluded(ray, 0.001, reach)
+                );
+            }
+            hits += expected.is_some() as usize;
+        }
+        // enough of both to mean something
+        assert!(hits > 100 && hits < 500);
+        for chunk in rays.chunks(LANES) {
+            let reach = [0.5; LANES];
+            let packet = RayPacket::new(chunk, 0.001, &[Float::MAX; LANES]);
+            let blocking = RayPacket::new(chunk, 0.001, &reach);
+            let (hits, blocked) = (kd.hit_packet(&packet), kd.occluded_packet(&blocking));
+            for (i, ray) in chunk.iter().enumerate() {
+                let expected = bvh.hit(ray, 0.001, Float::MAX);
+                assert_eq!(hits[i].as_ref().map(|h| h.t), expected.map(|h| h.t));
+                assert_eq!(blocked[i], bvh.occluded(ray, 0.001, reach[i]));
+            }
+        }
+    }
+}
@@ -0,0 +1,297 @@
+use crate::camera::{Camera, CameraModel};
+use crate::float::Float;
+use crate::material::refract;
+use crate::rng;
+use nalgebra::Vector3;
+use std::fs;
+use std::sync::Arc;
+
+// One spherical surface of a lens system, in millimetres: its radius of curvature,
+// positive when its centre lies toward the film and 0 for the aperture stop, the
+// distance to the next surface toward the film, the index of refraction of what
+// lies behind it (0 or 1 for air) and the diameter of its opening.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct LensElement {
+    pub radius: Float,
+    pub thickness: Float,
+    pub ior: Float,
+    pub aperture: Float,
+}
+
+fn medium(ior: Float) -> Float {
+    if ior == 0.0 {
+        1.0
+    } else {
+        ior
+    }
+}
+
+// Surfaces with the z of their vertices, the rear one at 0 and the scene toward +z.
+fn place(elements: &[LensElement]) -> Vec<(LensElement, Float)> {
+    let mut placed = Vec::with_capacity(elements.len());
+    let mut z = 0.0;
+    // the rear surface's thickness is its distance to the film, which focusing sets
+    for (i, element) in elements.iter().enumerate().rev() {
+        if i + 1 < elements.len() {
+            z += element.thickness;
+        }
+        placed.push((*element, z));
+    }
+    placed.reverse();
+    placed
+}
+
+// Follows a ray through the surfaces, toward the scene if it travels to +z and toward
+// the film otherwise. None if an opening blocks it or it reflects internally.
+fn trace(
+    elements: &[(LensElement, Float)],
+    mut origin: Vector3<Float>,
+    mut direction: Vector3<Float>,
+) -> Option<(Vector3<Float>, Vector3<Float>)> {
+    let n = elements.len();
+    let toward_scene = direction.z > 0.0;
+    for k in 0..n {
+        let i = if toward_scene { n - 1 - k } else { k };
+        let (element, vertex) = elements[i];
+        let center = Vector3::new(0.0, 0.0, vertex - element.radius);
+        let t = if element.radius == 0.0 {
+            (vertex - origin.z) / direction.z
+        } else {
+            let oc = origin - center;
+            let a = direction.norm_squared();
+            let b = oc.dot(&direction);
+            let c = oc.norm_squared() - element.radius * element.radius;
+            let discriminant = b * b - a * c;
+            if discriminant < 0.0 {
+                return None;
+            }
+            // the side of the sphere the vertex is on
+            if toward_scene == (element.radius < 0.0) {
+                (-b - discriminant.sqrt()) / a
+            } else {
+                (-b + discriminant.sqrt()) / a
+            }
+        };
+        if t <= 0.0 {
+            return None;
+        }
+        let p = origin + t * direction;
+        let half = 0.5 * element.aperture;
+        if p.x * p.x + p.y * p.y > half * half {
+            return None;
+        }
+        origin = p;
+        if element.radius != 0.0 {
+            let mut normal = (p - center) / element.radius.abs();
+            if normal.dot(&direction) > 0.0 {
+                normal = -normal;
+            }
+            let behind = medium(element.ior);
+            let front = if i > 0 {
+                medium(elements[i - 1].0.ior)
+            } else {
+                1.0
+            };
+            let (from, to) = if toward_scene {
+                (behind, front)
+            } else {
+                (front, behind)
+            };
+            direction = refract(&direction, &normal, from / to)?;
+        }
+    }
+    Some((origin, direction))
+}
+
+// The focal point and principal plane z of a ray entering parallel to the axis at
+// height `height` and leaving as `out`.
+fn cardinal(height: Float, out: (Vector3<Float>, Vector3<Float>)) -> (Float, Float) {
+    let (origin, direction) = out;
+    let focal = origin.z - origin.x / direction.x * direction.z;
+    let principal = origin.z + (height - origin.x) / direction.x * direction.z;
+    (focal, principal)
+}
+
+// The image side focal point and principal plane, then the scene side ones, found
+// by tracing rays close to the axis through the lens both ways.
+fn cardinal_points(elements: &[(LensElement, Float)]) -> Option<(Float, Float, Float, Float)> {
+    let smallest = elements
+        .iter()
+        .map(|(element, _)| element.aperture)
+        .fold(Float::MAX, Float::min);
+    let height = 0.01 * smallest;
+    let front = elements[0].1;
+    let from_scene = trace(
+        elements,
+        Vector3::new(height, 0.0, front + 1.0),
+        Vector3::new(0.0, 0.0, -1.0),
+    )?;
+    let from_film = trace(
+        elements,
+        Vector3::new(height, 0.0, -1.0),
+        Vector3::new(0.0, 0.0, 1.0),
+    )?;
+    let (image_focal, image_principal) = cardinal(height, from_scene);
+    let (scene_focal, scene_principal) = cardinal(height, from_film);
+    Some((image_focal, image_principal, scene_focal, scene_principal))
+}
+
+// Reads a lens description: one surface per line from the scene side to the film as
+// radius, thickness, index of refraction and aperture in millimetres, # starting a
+// comment. pbrt's lens files are in this format.
+pub fn read(path: &str) -> Result<Vec<LensElement>, String> {
+    let text = fs::read_to_string(path).map_err(|e| format!("cannot read {}: {}", path, e))?;
+    parse(&text).map_err(|message| format!("{}: {}", path, message))
+}
+
+// the surfaces a description lists, or why they do not make a lens
+pub fn parse(text: &str) -> Result<Vec<LensElement>, String> {
+    let mut elements = Vec::new();
+    for (number, line) in text.lines().enumerate() {
+        let line = line.split('#').next().unwrap_or("").trim();
+        if line.is_empty() {
+            continue;
+        }
+        let values = line
+            .split_whitespace()
+            .map(|word| word.parse::<Float>().ok().filter(|v| v.is_finite()))
+            .collect::<Option<Vec<Float>>>()
+            .filter(|values| values.len() == 4)
+            .ok_or_else(|| {
+                format!(
+                    "line {}: expected radius thickness ior aperture",
+                    number + 1
+                )
+            })?;
+        let element = LensElement {
+            radius: values[0],
+            thickness: values[1],
+            ior: values[2],
+            aperture: values[3],
+        };
+        if element.thickness < 0.0 || element.ior < 0.0 || element.aperture <= 0.0 {
+            return Err(format!(
+                "line {}: thickness and ior must not be negative and aperture must be positive",
+                number + 1
+            ));
+        }
+        elements.push(element);
+    }
+    if elements.is_empty() {
+        return Err(String::from("no lens elements"));
+    }
+    if cardinal_points(&place(&elements)).is_none() {
+        return Err(String::from("rays along the axis do not pass the lens"));
+    }
+    Ok(elements)
+}
+
+// What the command line sets a realistic camera up with; the focus distance defaults
+// to the scene camera's.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Lens {
+    pub elements: Vec<LensElement>,
+    pub film_diagonal: Float,
+    pub scale: Float,
+    pub focus: Option<Float>,
+}
+
+impl Default for Lens {
+    fn default() -> Self {
+        Lens {
+            elements: Vec::new(),
+            film_diagonal: 35.0,
+            scale: 0.001,
+            focus: None,
+        }
+    }
+}
+
+impl Lens {
+    // the model replacing the camera's
+    pub fn model(&self, camera: &Camera) -> Arc<dyn CameraModel> {
+        let (origin, _, _, forward) = camera.frame();
+        let (lower_left, horizontal, vertical) = camera.image_plane();
+        let focus = self
+            .focus
+            .unwrap_or_else(|| (lower_left - origin).dot(&forward));
+        Arc::new(RealisticCamera::new(
+            &self.elements,
+            self.film_diagonal,
+            self.scale,
+            focus,
+            horizontal.norm() / vertical.norm(),
+        ))
+    }
+}
+
+// A camera model tracing each ray from a point on the film through a system of
+// spherical lens elements, so vignetting, distortion and aberrations come from the
+// lens itself. The film is moved to focus on the camera's focus distance, and the
+// lens and the film's size set the field of view. Rays the lens blocks come back
+// as None and leave their samples black.
+pub struct RealisticCamera {
+    elements: Vec<(LensElement, Float)>,
+    film_distance: Float,
+    film_width: Float,
+    film_height: Float,
+    // scene units per millimetre
+    scale: Float,
+}
+
+impl RealisticCamera {
+    // The lens focused `focus` scene units in front of the film, on a film of
+    // `film_diagonal` millimetres and `aspect` width over height, in a scene measured
+    // in `scale` units per millimetre.
+    pub fn new(
+        elements: &[LensElement],
+        film_diagonal: Float,
+        scale: Float,
+        focus: Float,
+        aspect: Float,
+    ) -> Self {
+        let elements = place(elements);
+        let (image_focal, image_principal, _, scene_principal) =
+            cardinal_points(&elements).expect("rays along the axis do not pass the lens");
+        // Thick lens focusing: with the film at z = -d and the focus plane at
+        // focus - d, the distances from the principal planes to the film and to the
+        // focus plane add up to `sum` and must meet the thin lens equation.
+        let focal_length = image_principal - image_focal;
+        let sum = focus / scale + image_principal - scene_principal;
+        let image = if sum >= 4.0 * focal_length {
+            0.5 * (sum - (sum * sum - 4.0 * focal_length * sum).sqrt())
+        } else {
+            // too close to focus on; focus at infinity
+            focal_length
+        };
+        let film_height = film_diagonal / (1.0 + aspect * aspect).sqrt();
+        RealisticCamera {
+            elements,
+            film_distance: image - image_principal,
+            film_width: aspect * film_height,
+            film_height,
+            scale,
+        }
+    }
+}
+
+impl CameraModel for RealisticCamera {
+    fn ray(&self, camera: &Camera, s: Float, t: Float) -> Option<(Vector3<Float>, Vector3<Float>)> {
+        // the lens turns the image over, so the film is read from the opposite corner
+        let film = Vector3::new(
+            (0.5 - s) * self.film_width,
+            (0.5 - t) * self.film_height,
+            -self.film_distance,
+        );
+        let (rear, _) = self.elements[self.elements.len() - 1];
+        let pupil = 0.5 * rear.aperture * rng::in_unit_disk();
+        let (origin, direction) = trace(&self.elements, film, pupil - film)?;
+        // the camera's origin is the centre of the film
+        let (eye, u, v, forward) = camera.frame();
+        let origin = origin + Vector3::new(0.0, 0.0, self.film_distance);
+        Some((
+            eye + self.scale * (origin.x * u + origin.y * v + origin.z * forward),
+            direction.x * u + direction.y * v + direction.z * forward,
+        ))
+    }
+}
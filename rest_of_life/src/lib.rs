@@ -0,0 +1,81 @@
+pub mod aabb;
+pub mod adaptive;
+pub mod alpha;
+pub mod animation;
+pub mod aov;
+pub mod background;
+pub mod bounce;
+pub mod bvh;
+pub mod camera;
+pub mod cli;
+pub mod cone;
+pub mod crop;
+pub mod csg;
+pub mod cube;
+pub mod dataset;
+pub mod decal;
+pub mod differential;
+pub mod disk;
+pub mod estimator;
+pub mod finite;
+pub mod flip;
+pub mod float;
+pub mod gltf;
+pub mod gpu;
+pub mod guide;
+pub mod heightfield;
+pub mod hittable;
+pub mod integrator;
+pub mod interrupt;
+pub mod kdtree;
+pub mod lens;
+pub mod linking;
+pub mod lut;
+pub mod material;
+pub mod medium;
+pub mod meshfile;
+pub mod metadata;
+pub mod microfacet;
+pub mod mnee;
+pub mod motion;
+pub mod packet;
+pub mod parallel;
+pub mod pathlog;
+pub mod pbrt;
+pub mod pdf;
+pub mod perlin;
+pub mod phase;
+pub mod portal;
+pub mod preview;
+pub mod ray;
+pub mod rect;
+pub mod reference;
+pub mod render;
+pub mod rng;
+pub mod rotate;
+pub mod rtmerge;
+pub mod sampling;
+pub mod scene;
+pub mod scenefile;
+pub mod sdf;
+pub mod sensor;
+pub mod spectrum;
+pub mod sphere;
+pub mod sppm;
+pub mod stats;
+pub mod subsurface;
+pub mod sun;
+pub mod texture;
+pub mod throughput;
+pub mod tiles;
+pub mod tonemap;
+pub mod translate;
+pub mod triangle;
+pub mod vdb;
+pub mod volume;
+pub mod wavefront;
+pub mod worley;
+
+pub use crate::float::Float;
+pub use crate::render::{render, render_counted, Image, OutputFormat, RenderSettings};
+pub use crate::scene::Scene;
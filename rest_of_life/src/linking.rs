@@ -0,0 +1,144 @@
+use crate::aabb::AABB;
+use crate::float::Float;
+use crate::hittable::{HitRecord, Hittable, Surfaces};
+use crate::ray::{self, Ray};
+use crate::rng;
+use crate::stats::{self, Counter};
+use nalgebra::Vector3;
+use rand::seq::SliceRandom;
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+
+// most blockers a shadow linked connection looks through
+const MAX_BLOCKERS: usize = 16;
+
+// Light and shadow linking by object id. A light linked to receivers only lights
+// those objects, and a light with shadow links is only shadowed by the listed
+// blockers. Lights without links behave as usual.
+#[derive(Default)]
+pub struct LightLinks {
+    targets: Vec<(u32, Arc<dyn Hittable>)>,
+    illumination: HashMap<u32, HashSet<u32>>,
+    shadows: HashMap<u32, HashSet<u32>>,
+}
+
+impl LightLinks {
+    pub fn link_light(&mut self, light: u32, receivers: &[u32]) {
+        self.illumination
+            .entry(light)
+            .or_default()
+            .extend(receivers.iter().cloned());
+    }
+
+    pub fn link_shadow(&mut self, light: u32, blockers: &[u32]) {
+        self.shadows
+            .entry(light)
+            .or_default()
+            .extend(blockers.iter().cloned());
+    }
+
+    // a shape the scene samples directly, under the object id its hits carry
+    pub fn add_target(&mut self, id: u32, target: Arc<dyn Hittable>) {
+        self.targets.push((id, target));
+    }
+
+    pub fn illuminates(&self, light: u32, receiver: u32) -> bool {
+        self.illumination
+            .get(&light)
+            .is_none_or(|receivers| receivers.contains(&receiver))
+    }
+
+    fn casts_shadow(&self, light: u32, blocker: u32) -> bool {
+        self.shadows
+            .get(&light)
+            .is_none_or(|blockers| blockers.contains(&blocker))
+    }
+
+    // The sampling targets for a receiver when linking hides some of them, None when
+    // it sees them all.
+    pub fn targets_for(&self, receiver: u32) -> Option<LinkedTargets<'_>> {
+        let targets = self
+            .targets
+            .iter()
+            .filter(|(id, _)| self.illuminates(*id, receiver))
+            .map(|(_, target)| target.as_ref())
+            .collect::<Vec<&dyn Hittable>>();
+        if targets.len() == self.targets.len() {
+            None
+        } else {
+            Some(LinkedTargets { targets })
+        }
+    }
+
+    // Emission that reaches the ray's origin from behind blockers that are unlinked
+    // from shadowing the light. The ray itself stops at the first blocker, so this
+    // is what it misses.
+    pub fn unshadowed(&self, ray: &Ray, world: &dyn Hittable, receiver: u32) -> Vector3<Float> {
+        if self.shadows.is_empty() {
+            return Vector3::zeros();
+        }
+        let mut blockers = Vec::new();
+        let mut surfaces = Surfaces::new(ray);
+        while blockers.len() < MAX_BLOCKERS {
+            stats::count(Counter::ShadowRays);
+            let hit = match surfaces.next(world, ray::T_MIN, Float::MAX) {
+                Some(hit) => hit,
+                None => break,
+            };
+            let emitted = hit.material.emitted(ray, &hit);
+            if emitted != Vector3::zeros() {
+                let light = hit.object_id;
+                if !blockers.is_empty()
+                    && self.illuminates(light, receiver)
+                    && blockers.iter().all(|&b| !self.casts_shadow(light, b))
+                {
+                    return emitted;
+                }
+                break;
+            }
+            blockers.push(hit.object_id);
+        }
+        Vector3::zeros()
+    }
+}
+
+// light sampling restricted to the targets linked to one receiver
+pub struct LinkedTargets<'a> {
+    targets: Vec<&'a dyn Hittable>,
+}
+
+impl LinkedTargets<'_> {
+    pub fn is_empty(&self) -> bool {
+        self.targets.is_empty()
+    }
+}
+
+impl Hittable for LinkedTargets<'_> {
+    fn hit(&self, ray: &Ray, t_min: Float, t_max: Float) -> Option<HitRecord<'_>> {
+        let mut closest_so_far = t_max;
+        let mut hit_anything = None;
+        for h in self.targets.iter() {
+            if let Some(hit) = h.hit(ray, t_min, closest_so_far) {
+                closest_so_far = hit.t;
+                hit_anything = Some(hit);
+            }
+        }
+        hit_anything
+    }
+
+    fn bounding_box(&self, _t0: Float, _t1: Float) -> Option<AABB> {
+        None
+    }
+
+    fn pdf_value(&self, o: Vector3<Float>, v: Vector3<Float>) -> Float {
+        self.targets
+            .iter()
+            .map(|h| h.pdf_value(o, v))
+            .sum::<Float>()
+            / self.targets.len() as Float
+    }
+
+    fn random(&self, o: Vector3<Float>) -> Vector3<Float> {
+        rng::with(|rng| self.targets.choose(rng)).unwrap().random(o)
+    }
+}
@@ -0,0 +1,173 @@
+use crate::float::Float;
+use nalgebra::Vector3;
+use std::fs;
+
+// A 3D colour lookup table, the look of a camera or a grade as colour tools export
+// it: a lattice of size x size x size output colours spread evenly over the cube
+// of inputs from the domain's minimum to its maximum, 0 to 1 unless the file says
+// otherwise. Colours between lattice points are interpolated trilinearly, and
+// inputs outside the domain clamped to it.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Lut {
+    size: usize,
+    domain_min: Vector3<Float>,
+    domain_max: Vector3<Float>,
+    // red changing fastest, then green, then blue
+    table: Vec<Vector3<Float>>,
+}
+
+impl Lut {
+    fn at(&self, r: usize, g: usize, b: usize) -> Vector3<Float> {
+        self.table[r + self.size * (g + self.size * b)]
+    }
+
+    pub fn apply(&self, c: &Vector3<Float>) -> Vector3<Float> {
+        let last = (self.size - 1) as Float;
+        let mut lower = [0; 3];
+        let mut fraction = [0.0; 3];
+        for a in 0..3 {
+            let x = (c[a] - self.domain_min[a]) / (self.domain_max[a] - self.domain_min[a]);
+            let x = if x.is_nan() { 0.0 } else { x.clamp(0.0, 1.0) } * last;
+            lower[a] = (x as usize).min(self.size - 2);
+            fraction[a] = x - lower[a] as Float;
+        }
+        (0..8).fold(Vector3::zeros(), |sum, corner: usize| {
+            let up = |a: usize| (corner >> a) & 1;
+            let weight: Float = (0..3)
+                .map(|a| {
+                    if up(a) == 1 {
+                        fraction[a]
+                    } else {
+                        1.0 - fraction[a]
+                    }
+                })
+                .product();
+            sum + weight * self.at(lower[0] + up(0), lower[1] + up(1), lower[2] + up(2))
+        })
+    }
+}
+
+// Reads a .cube file, the format Resolve and most grading tools write.
+pub fn read(path: &str) -> Result<Lut, String> {
+    let text = fs::read_to_string(path).map_err(|e| format!("cannot read {}: {}", path, e))?;
+    parse(&text).map_err(|message| format!("{}: {}", path, message))
+}
+
+// The table a .cube file describes: LUT_3D_SIZE, then optionally DOMAIN_MIN and
+// DOMAIN_MAX or LUT_3D_INPUT_RANGE, then a line of red, green and blue for each
+// lattice point, # starting a comment. A TITLE and the keywords of other tools
+// are passed over; 1D tables are not read.
+pub fn parse(text: &str) -> Result<Lut, String> {
+    let mut size = None;
+    let mut domain_min = Vector3::zeros();
+    let mut domain_max = Vector3::new(1.0, 1.0, 1.0);
+    let mut table = Vec::new();
+    for (number, line) in text.lines().enumerate() {
+        // a title is quoted, and may hold a #
+        if line.trim_start().starts_with("TITLE") {
+            continue;
+        }
+        let line = line.split('#').next().unwrap_or("").trim();
+        let mut words = line.split_whitespace();
+        let Some(first) = words.next() else {
+            continue;
+        };
+        let error = |message: &str| format!("line {}: {}", number + 1, message);
+        let numbers = |words: &[&str], count: usize| {
+            words
+                .iter()
+                .map(|word| word.parse::<Float>().ok().filter(|v| v.is_finite()))
+                .collect::<Option<Vec<Float>>>()
+                .filter(|values| values.len() == count)
+        };
+        let rest: Vec<&str> = words.collect();
+        match first {
+            "LUT_3D_SIZE" => {
+                let n = rest
+                    .first()
+                    .and_then(|word| word.parse::<usize>().ok())
+                    .filter(|n| rest.len() == 1 && (2..=256).contains(n))
+                    .ok_or_else(|| error("LUT_3D_SIZE must be from 2 to 256"))?;
+                size = Some(n);
+            }
+            "LUT_1D_SIZE" => return Err(error("1D LUTs are not supported")),
+            "DOMAIN_MIN" | "DOMAIN_MAX" => {
+                let values =
+                    numbers(&rest, 3).ok_or_else(|| error("expected a red, green and blue"))?;
+                let bound = Vector3::new(values[0], values[1], values[2]);
+                if first == "DOMAIN_MIN" {
+                    domain_min = bound;
+                } else {
+                    domain_max = bound;
+                }
+            }
+            "LUT_3D_INPUT_RANGE" => {
+                let values = numbers(&rest, 2).ok_or_else(|| error("expected a min and max"))?;
+                domain_min = Vector3::repeat(values[0]);
+                domain_max = Vector3::repeat(values[1]);
+            }
+            keyword if keyword.starts_with(|c: char| c.is_ascii_alphabetic()) => {}
+            _ => {
+                if size.is_none() {
+                    return Err(error("colours before LUT_3D_SIZE"));
+                }
+                let mut words = vec![first];
+                words.extend(rest);
+                let values =
+                    numbers(&words, 3).ok_or_else(|| error("expected a red, green and blue"))?;
+                table.push(Vector3::new(values[0], values[1], values[2]));
+            }
+        }
+    }
+    let size = size.ok_or("no LUT_3D_SIZE")?;
+    if table.len() != size.pow(3) {
+        return Err(format!(
+            "{} colours for a {}x{}x{} table",
+            table.len(),
+            size,
+            size,
+            size
+        ));
+    }
+    if (0..3).any(|a| domain_min[a] >= domain_max[a]) {
+        return Err(String::from(
+            "the domain's minimum must be below its maximum",
+        ));
+    }
+    Ok(Lut {
+        size,
+        domain_min,
+        domain_max,
+        table,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cube_files_map_colours() {
+        // red and blue swapped, over a 3x3x3 lattice
+        let mut text = String::from("TITLE \"swap # test\"\n# a comment\nLUT_3D_SIZE 3\n");
+        for b in 0..3 {
+            for g in 0..3 {
+                for r in 0..3 {
+                    let [r, g, b] = [r, g, b].map(|v| v as Float / 2.0);
+                    text.push_str(&format!("{} {} {}\n", b, g, r));
+                }
+            }
+        }
+        let lut = parse(&text).unwrap();
+        let c = Vector3::new(0.1, 0.6, 0.85);
+        assert!((lut.apply(&c) - Vector3::new(0.85, 0.6, 0.1)).norm() < 1e-5);
+        // outside the domain is its edge
+        assert!(
+            (lut.apply(&Vector3::new(-1.0, 2.0, 0.5)) - Vector3::new(0.5, 1.0, 0.0)).norm() < 1e-5
+        );
+
+        assert!(parse("LUT_3D_SIZE 2\n0 0 0\n").is_err());
+        assert!(parse("LUT_1D_SIZE 2\n0 0 0\n1 1 1\n").is_err());
+        assert!(parse(&text.replace("LUT_3D_SIZE 3", "DOMAIN_MAX 1 0 1\nLUT_3D_SIZE 3")).is_err());
+    }
+}
@@ -1,27 +1,26 @@
+use crate::float::{self, Float};
 use crate::hittable::HitRecord;
+use crate::microfacet::GGX;
 use crate::pdf::PDF;
+use crate::phase::PhaseFunction;
 use crate::ray::Ray;
-use crate::texture::Texture;
+use crate::rng;
+use crate::sampling::{self, ONB};
+use crate::spectrum::{self, Dispersion};
+use crate::texture::{self, ConstantTexture, Texture};
 use nalgebra::Vector3;
-use rand::Rng;
-use std::f32;
+use std::str::FromStr;
+use std::sync::Arc;
 
-fn random_in_unit_sphere() -> Vector3<f32> {
-    let mut rng = rand::thread_rng();
-    let unit = Vector3::new(1.0, 1.0, 1.0);
-    loop {
-        let p = 2.0 * Vector3::new(rng.gen::<f32>(), rng.gen::<f32>(), rng.gen::<f32>()) - unit;
-        if p.magnitude_squared() < 1.0 {
-            return p;
-        }
-    }
-}
-
-fn reflect(v: &Vector3<f32>, n: &Vector3<f32>) -> Vector3<f32> {
+pub fn reflect(v: &Vector3<Float>, n: &Vector3<Float>) -> Vector3<Float> {
     v - 2.0 * v.dot(n) * n
 }
 
-fn refract(v: &Vector3<f32>, n: &Vector3<f32>, ni_over_nt: f32) -> Option<Vector3<f32>> {
+pub fn refract(
+    v: &Vector3<Float>,
+    n: &Vector3<Float>,
+    ni_over_nt: Float,
+) -> Option<Vector3<Float>> {
     let uv = v.normalize();
     let dt = uv.dot(n);
     let discriminant = 1.0 - ni_over_nt.powi(2) * (1.0 - dt.powi(2));
@@ -33,34 +32,134 @@ fn refract(v: &Vector3<f32>, n: &Vector3<f32>, ni_over_nt: f32) -> Option<Vector
     }
 }
 
-fn schlick(cosine: f32, ref_idx: f32) -> f32 {
+pub fn schlick(cosine: Float, ref_idx: Float) -> Float {
     let r0 = ((1.0 - ref_idx) / (1.0 + ref_idx)).powi(2);
     r0 + (1.0 - r0) * (1.0 - cosine).powi(5)
 }
 
+// Fresnel amplitude reflection coefficients, s and p polarised, of light crossing
+// from index n_i into index n_t at the given cosines of its angles to the normal
+fn amplitudes(n_i: Float, cos_i: Float, n_t: Float, cos_t: Float) -> (Float, Float) {
+    (
+        (n_i * cos_i - n_t * cos_t) / (n_i * cos_i + n_t * cos_t),
+        (n_t * cos_i - n_i * cos_t) / (n_t * cos_i + n_i * cos_t),
+    )
+}
+
+// Reflectance of a film `thickness` nanometres thick with index `film_ior` on a
+// substrate of index `substrate_ior`, seen from air at the given cosine, for light
+// of one wavelength in nanometres: the waves reflected off its top and bottom
+// interfere, by Airy's formula, and unpolarised light averages the polarisations.
+pub fn thin_film(
+    cosine: Float,
+    film_ior: Float,
+    substrate_ior: Float,
+    thickness: Float,
+    wavelength: Float,
+) -> Float {
+    let sin2 = 1.0 - cosine * cosine;
+    let cos_film = (1.0 - sin2 / (film_ior * film_ior)).max(0.0).sqrt();
+    let sin2_substrate = sin2 / (substrate_ior * substrate_ior);
+    let (top_s, top_p) = amplitudes(1.0, cosine, film_ior, cos_film);
+    // total internal reflection at the bottom turns all the light back
+    let (bottom_s, bottom_p) = if sin2_substrate >= 1.0 {
+        (1.0, 1.0)
+    } else {
+        amplitudes(
+            film_ior,
+            cos_film,
+            substrate_ior,
+            (1.0 - sin2_substrate).sqrt(),
+        )
+    };
+    let phase = 4.0 * float::consts::PI * film_ior * thickness * cos_film / wavelength;
+    let airy = |r12: Float, r23: Float| {
+        let cross = 2.0 * r12 * r23 * phase.cos();
+        (r12 * r12 + r23 * r23 + cross) / (1.0 + r12 * r12 * r23 * r23 + cross)
+    };
+    0.5 * (airy(top_s, bottom_s) + airy(top_p, bottom_p))
+}
+
 pub enum ScatterRecord<'a> {
     Specular {
         specular_ray: Ray,
-        attenuation: Vector3<f32>,
+        attenuation: Vector3<Float>,
     },
     Scatter {
         pdf: PDF<'a>,
-        attenuation: Vector3<f32>,
+        attenuation: Vector3<Float>,
     },
 }
 
-pub trait Material: Sync {
-    fn scatter(&self, _ray: &Ray, _hit: &HitRecord) -> Option<ScatterRecord> {
+pub trait Material: Send + Sync {
+    fn scatter(&self, _ray: &Ray, _hit: &HitRecord) -> Option<ScatterRecord<'_>> {
         None
     }
 
-    fn scattering_pdf(&self, _ray: &Ray, _hit: &HitRecord, _scattered: &Ray) -> f32 {
+    fn scattering_pdf(&self, _ray: &Ray, _hit: &HitRecord, _scattered: &Ray) -> Float {
         1.0
     }
 
-    fn emitted(&self, _ray: &Ray, _hit: &HitRecord) -> Vector3<f32> {
+    fn emitted(&self, _ray: &Ray, _hit: &HitRecord) -> Vector3<Float> {
         Vector3::zeros()
     }
+
+    // Where light entering the surface at the hit comes back out, for materials it
+    // travels beneath the surface of, see subsurface.rs: the hit there, with the
+    // material it leaves by. Integrators take this step at every hit before
+    // shading it; other materials leave the hit as it is.
+    fn exit<'a>(&'a self, _ray: &Ray, hit: HitRecord<'a>) -> HitRecord<'a> {
+        hit
+    }
+
+    // how far specular rays scatter around the mirror direction, as the spread a
+    // bounce adds to a traced cone
+    fn roughness(&self) -> Float {
+        0.0
+    }
+
+    // whether the surface is there at a hit; shapes wrapped in alpha::Cutout let
+    // rays through where it is not
+    fn opaque(&self, _hit: &HitRecord) -> bool {
+        true
+    }
+
+    // the material's type, as the log of non-finite samples names it, see finite.rs
+    fn name(&self) -> &'static str {
+        std::any::type_name::<Self>()
+    }
+}
+
+// A shared material is the material itself, so scenes built at run time can hand
+// out one material to many shapes.
+impl<M: Material + ?Sized> Material for Arc<M> {
+    fn scatter(&self, ray: &Ray, hit: &HitRecord) -> Option<ScatterRecord<'_>> {
+        self.as_ref().scatter(ray, hit)
+    }
+
+    fn scattering_pdf(&self, ray: &Ray, hit: &HitRecord, scattered: &Ray) -> Float {
+        self.as_ref().scattering_pdf(ray, hit, scattered)
+    }
+
+    fn emitted(&self, ray: &Ray, hit: &HitRecord) -> Vector3<Float> {
+        self.as_ref().emitted(ray, hit)
+    }
+
+    fn exit<'a>(&'a self, ray: &Ray, hit: HitRecord<'a>) -> HitRecord<'a> {
+        self.as_ref().exit(ray, hit)
+    }
+
+    fn roughness(&self) -> Float {
+        self.as_ref().roughness()
+    }
+
+    fn opaque(&self, hit: &HitRecord) -> bool {
+        self.as_ref().opaque(hit)
+    }
+
+    fn name(&self) -> &'static str {
+        self.as_ref().name()
+    }
 }
 
 #[derive(Clone)]
@@ -75,27 +174,26 @@ impl<T: Texture> Lambertian<T> {
 }
 
 impl<T: Texture> Material for Lambertian<T> {
-    fn scatter(&self, _ray: &Ray, hit: &HitRecord) -> Option<ScatterRecord> {
+    fn scatter(&self, ray: &Ray, hit: &HitRecord) -> Option<ScatterRecord<'_>> {
         Some(ScatterRecord::Scatter {
             pdf: PDF::cosine(hit.normal),
-            attenuation: self.albedo.value(hit.u, hit.v, &hit.p),
+            attenuation: texture::lookup(&self.albedo, ray, hit),
         })
     }
 
-    fn scattering_pdf(&self, _ray: &Ray, hit: &HitRecord, scattered: &Ray) -> f32 {
-        let cosine = hit.normal.dot(&scattered.direction().normalize()).max(0.0);
-        cosine / f32::consts::PI
+    fn scattering_pdf(&self, _ray: &Ray, hit: &HitRecord, scattered: &Ray) -> Float {
+        sampling::cosine_hemisphere_pdf(hit.normal.dot(&scattered.direction().normalize()))
     }
 }
 
 #[derive(Clone)]
 pub struct Metal {
-    albedo: Vector3<f32>,
-    fuzz: f32,
+    albedo: Vector3<Float>,
+    fuzz: Float,
 }
 
 impl Metal {
-    pub fn new(albedo: Vector3<f32>, fuzz: f32) -> Self {
+    pub fn new(albedo: Vector3<Float>, fuzz: Float) -> Self {
         Metal {
             albedo,
             fuzz: if fuzz < 1.0 { fuzz } else { 1.0 },
@@ -104,61 +202,386 @@ impl Metal {
 }
 
 impl Material for Metal {
-    fn scatter(&self, ray: &Ray, hit: &HitRecord) -> Option<ScatterRecord> {
+    fn scatter(&self, ray: &Ray, hit: &HitRecord) -> Option<ScatterRecord<'_>> {
         let mut reflected = reflect(&ray.direction().normalize(), &hit.normal);
         if self.fuzz > 0.0 {
-            reflected += self.fuzz * random_in_unit_sphere()
+            reflected += self.fuzz * rng::in_unit_sphere()
         };
         if reflected.dot(&hit.normal) > 0.0 {
             Some(ScatterRecord::Specular {
-                specular_ray: Ray::new(hit.p, reflected, ray.time()),
+                specular_ray: hit
+                    .spawn(reflected, ray.time())
+                    .with_wavelength(ray.wavelength()),
                 attenuation: self.albedo,
             })
         } else {
             None
         }
     }
+
+    fn roughness(&self) -> Float {
+        self.fuzz
+    }
+}
+
+// Fresnel reflectance of a conductor with complex index eta + ik, unpolarised, at
+// the cosine of the angle of incidence
+pub fn fresnel_conductor(cosine: Float, eta: Float, k: Float) -> Float {
+    let cos2 = cosine * cosine;
+    let sin2 = 1.0 - cos2;
+    let t0 = eta * eta - k * k - sin2;
+    let a2_plus_b2 = (t0 * t0 + 4.0 * eta * eta * k * k).sqrt();
+    let a = (0.5 * (a2_plus_b2 + t0)).max(0.0).sqrt();
+    let t1 = a2_plus_b2 + cos2;
+    let t2 = 2.0 * cosine * a;
+    let rs = (t1 - t2) / (t1 + t2);
+    let t3 = cos2 * a2_plus_b2 + sin2 * sin2;
+    let t4 = t2 * sin2;
+    let rp = rs * (t3 - t4) / (t3 + t4);
+    0.5 * (rs + rp)
+}
+
+// Metals with measured complex indices of refraction, at the red, green and blue
+// wavelengths of 650, 550 and 450 nm.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum MetalPreset {
+    #[default]
+    Gold,
+    Silver,
+    Copper,
+    Aluminium,
+}
+
+impl FromStr for MetalPreset {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "gold" => Ok(MetalPreset::Gold),
+            "silver" => Ok(MetalPreset::Silver),
+            "copper" => Ok(MetalPreset::Copper),
+            "aluminium" | "aluminum" => Ok(MetalPreset::Aluminium),
+            _ => Err(format!("unknown metal: {}", s)),
+        }
+    }
+}
+
+impl MetalPreset {
+    // eta and k per channel
+    pub fn ior(&self) -> (Vector3<Float>, Vector3<Float>) {
+        match *self {
+            MetalPreset::Gold => (
+                Vector3::new(0.143, 0.374, 1.442),
+                Vector3::new(3.983, 2.385, 1.603),
+            ),
+            MetalPreset::Silver => (
+                Vector3::new(0.155, 0.116, 0.138),
+                Vector3::new(4.828, 3.122, 2.147),
+            ),
+            MetalPreset::Copper => (
+                Vector3::new(0.200, 0.924, 1.102),
+                Vector3::new(3.912, 2.452, 2.142),
+            ),
+            MetalPreset::Aluminium => (
+                Vector3::new(1.657, 0.880, 0.521),
+                Vector3::new(9.224, 6.270, 4.837),
+            ),
+        }
+    }
+}
+
+// A metal reflecting by the Fresnel equations of its complex index of refraction
+// per channel rather than a flat albedo, so gold and copper turn whiter toward
+// grazing angles as real metals do. Fuzz blurs the reflection as Metal's does.
+#[derive(Clone)]
+pub struct Conductor {
+    eta: Vector3<Float>,
+    k: Vector3<Float>,
+    fuzz: Float,
+}
+
+impl Conductor {
+    pub fn new(eta: Vector3<Float>, k: Vector3<Float>, fuzz: Float) -> Self {
+        Conductor {
+            eta,
+            k,
+            fuzz: fuzz.min(1.0),
+        }
+    }
+
+    pub fn preset(metal: MetalPreset, fuzz: Float) -> Self {
+        let (eta, k) = metal.ior();
+        Conductor::new(eta, k, fuzz)
+    }
+
+    pub fn reflectance(&self, cosine: Float) -> Vector3<Float> {
+        Vector3::new(
+            fresnel_conductor(cosine, self.eta.x, self.k.x),
+            fresnel_conductor(cosine, self.eta.y, self.k.y),
+            fresnel_conductor(cosine, self.eta.z, self.k.z),
+        )
+    }
+}
+
+impl Material for Conductor {
+    fn scatter(&self, ray: &Ray, hit: &HitRecord) -> Option<ScatterRecord<'_>> {
+        let direction = ray.direction().normalize();
+        let mut reflected = reflect(&direction, &hit.normal);
+        if self.fuzz > 0.0 {
+            reflected += self.fuzz * rng::in_unit_sphere()
+        };
+        if reflected.dot(&hit.normal) <= 0.0 {
+            return None;
+        }
+        let cosine = (-direction.dot(&hit.normal)).clamp(0.0, 1.0);
+        Some(ScatterRecord::Specular {
+            specular_ray: hit
+                .spawn(reflected, ray.time())
+                .with_wavelength(ray.wavelength()),
+            attenuation: self.reflectance(cosine),
+        })
+    }
+
+    fn roughness(&self) -> Float {
+        self.fuzz
+    }
+}
+
+// Anisotropic GGX reflection tinted by an albedo, for brushed metal: alpha_x is
+// the roughness along the surface's tangent and alpha_y across it, each scaled by
+// the red channel of a roughness map, so highlights stretch across the direction
+// the metal was brushed in. Directions are sampled from the visible normals and
+// mixed with the lights like a diffuse bounce.
+#[derive(Clone)]
+pub struct Anisotropic<T: Texture, R: Texture = ConstantTexture> {
+    albedo: T,
+    alpha_x: Float,
+    alpha_y: Float,
+    roughness_map: R,
+}
+
+impl<T: Texture> Anisotropic<T> {
+    pub fn new(albedo: T, alpha_x: Float, alpha_y: Float) -> Self {
+        Anisotropic::with_roughness_map(
+            albedo,
+            alpha_x,
+            alpha_y,
+            ConstantTexture::new(1.0, 1.0, 1.0),
+        )
+    }
+}
+
+impl<T: Texture, R: Texture> Anisotropic<T, R> {
+    pub fn with_roughness_map(albedo: T, alpha_x: Float, alpha_y: Float, roughness_map: R) -> Self {
+        Anisotropic {
+            albedo,
+            alpha_x,
+            alpha_y,
+            roughness_map,
+        }
+    }
+
+    // the distribution at the hit and its frame, facing the ray
+    fn lobe(&self, ray: &Ray, hit: &HitRecord) -> (ONB, GGX) {
+        let normal = if ray.direction().dot(&hit.normal) > 0.0 {
+            -hit.normal
+        } else {
+            hit.normal
+        };
+        let scale = self
+            .roughness_map
+            .value(hit.u, hit.v, &hit.texture_p)
+            .x
+            .max(0.0);
+        (
+            ONB::build_from_w_tangent(&normal, &hit.tangent),
+            GGX::new(scale * self.alpha_x, scale * self.alpha_y),
+        )
+    }
+}
+
+impl<T: Texture, R: Texture> Material for Anisotropic<T, R> {
+    fn scatter(&self, ray: &Ray, hit: &HitRecord) -> Option<ScatterRecord<'_>> {
+        let (uvw, ggx) = self.lobe(ray, hit);
+        Some(ScatterRecord::Scatter {
+            pdf: PDF::microfacet(uvw, ggx, -ray.direction()),
+            attenuation: texture::lookup(&self.albedo, ray, hit),
+        })
+    }
+
+    fn scattering_pdf(&self, ray: &Ray, hit: &HitRecord, scattered: &Ray) -> Float {
+        let (uvw, ggx) = self.lobe(ray, hit);
+        ggx.reflectance(
+            &uvw.to_local(&-ray.direction().normalize()),
+            &uvw.to_local(&scattered.direction().normalize()),
+        )
+    }
+
+    fn roughness(&self) -> Float {
+        (self.alpha_x * self.alpha_y).sqrt()
+    }
 }
 
 #[derive(Clone)]
 pub struct Dielectric {
-    ref_idx: f32,
+    ref_idx: Float,
+    dispersion: Option<Dispersion>,
 }
 
 impl Dielectric {
-    pub fn new(ref_idx: f32) -> Self {
-        Dielectric { ref_idx }
+    pub fn new(ref_idx: Float) -> Self {
+        Dielectric {
+            ref_idx,
+            dispersion: None,
+        }
+    }
+
+    // A glass whose index of refraction follows the wavelength a spectral render
+    // traces, splitting white light into its colours. Rays without a wavelength see
+    // its index at the sodium D line.
+    pub fn dispersive(dispersion: Dispersion) -> Self {
+        Dielectric {
+            ref_idx: dispersion.ior(spectrum::SODIUM_D),
+            dispersion: Some(dispersion),
+        }
     }
 }
 
 impl Material for Dielectric {
-    fn scatter(&self, ray: &Ray, hit: &HitRecord) -> Option<ScatterRecord> {
+    fn scatter(&self, ray: &Ray, hit: &HitRecord) -> Option<ScatterRecord<'_>> {
         let attenuation = Vector3::new(1.0, 1.0, 1.0);
+        let ref_idx = match (self.dispersion, ray.wavelength()) {
+            (Some(dispersion), Some(wavelength)) => dispersion.ior(wavelength),
+            _ => self.ref_idx,
+        };
         let (outward_normal, ni_over_nt, cosine) = if ray.direction().dot(&hit.normal) > 0.0 {
-            let cosine =
-                self.ref_idx * ray.direction().dot(&hit.normal) / ray.direction().magnitude();
-            (-hit.normal, self.ref_idx, cosine)
+            let cosine = ref_idx * ray.direction().dot(&hit.normal) / ray.direction().magnitude();
+            (-hit.normal, ref_idx, cosine)
         } else {
             let cosine = -ray.direction().dot(&hit.normal) / ray.direction().magnitude();
-            (hit.normal, 1.0 / self.ref_idx, cosine)
+            (hit.normal, 1.0 / ref_idx, cosine)
         };
         if let Some(refracted) = refract(&ray.direction(), &outward_normal, ni_over_nt) {
-            let reflect_prob = schlick(cosine, self.ref_idx);
-            if rand::thread_rng().gen::<f32>() >= reflect_prob {
+            let reflect_prob = schlick(cosine, ref_idx);
+            if rng::uniform() >= reflect_prob {
                 return Some(ScatterRecord::Specular {
-                    specular_ray: Ray::new(hit.p, refracted, ray.time()),
+                    specular_ray: hit
+                        .spawn(refracted, ray.time())
+                        .with_wavelength(ray.wavelength()),
                     attenuation,
                 });
             }
         }
         let reflected = reflect(&ray.direction(), &hit.normal);
         Some(ScatterRecord::Specular {
-            specular_ray: Ray::new(hit.p, reflected, ray.time()),
+            specular_ray: hit
+                .spawn(reflected, ray.time())
+                .with_wavelength(ray.wavelength()),
             attenuation,
         })
     }
 }
 
+// wavelengths the film's reflectance is averaged over in RGB renders
+const FILM_WAVELENGTHS: usize = 32;
+
+// A thin transparent film coating a base material, as a soap bubble's wall or oil
+// on water: it reflects a share of the light that varies with wavelength, angle
+// and the film's thickness into iridescent colours, and the rest reaches the base.
+// The thickness texture's red channel gives it in nanometres, so a noise texture
+// makes swirls. Spectral renders reflect by the path's own wavelength; RGB ones
+// by the film's colour averaged over the spectrum.
+#[derive(Clone)]
+pub struct ThinFilm<M: Material, T: Texture> {
+    base: M,
+    ior: Float,
+    substrate_ior: Float,
+    thickness: T,
+}
+
+impl<M: Material, T: Texture> ThinFilm<M, T> {
+    pub fn new(base: M, ior: Float, substrate_ior: Float, thickness: T) -> Self {
+        ThinFilm {
+            base,
+            ior,
+            substrate_ior,
+            thickness,
+        }
+    }
+
+    fn reflectance(
+        &self,
+        cosine: Float,
+        thickness: Float,
+        wavelength: Option<Float>,
+    ) -> Vector3<Float> {
+        let at =
+            |wavelength| thin_film(cosine, self.ior, self.substrate_ior, thickness, wavelength);
+        match wavelength {
+            Some(wavelength) => Vector3::repeat(at(wavelength)),
+            None => {
+                let sum: Vector3<Float> = (0..FILM_WAVELENGTHS)
+                    .map(|i| {
+                        let u = (i as Float + 0.5) / FILM_WAVELENGTHS as Float;
+                        let wavelength = spectrum::sample_wavelength(u);
+                        at(wavelength) * spectrum::rgb_weight(wavelength)
+                    })
+                    .sum();
+                (sum / FILM_WAVELENGTHS as Float).map(|c| c.clamp(0.0, 1.0))
+            }
+        }
+    }
+}
+
+impl<M: Material, T: Texture> Material for ThinFilm<M, T> {
+    fn scatter(&self, ray: &Ray, hit: &HitRecord) -> Option<ScatterRecord<'_>> {
+        let direction = ray.direction().normalize();
+        let cosine = direction.dot(&hit.normal).abs();
+        let thickness = self
+            .thickness
+            .value(hit.u, hit.v, &hit.texture_p)
+            .x
+            .max(0.0);
+        let reflectance = self.reflectance(cosine, thickness, ray.wavelength());
+        // reflect off the film as often as it reflects on average
+        let p = ((reflectance.x + reflectance.y + reflectance.z) / 3.0).clamp(0.01, 0.99);
+        if rng::uniform() < p {
+            return Some(ScatterRecord::Specular {
+                specular_ray: hit
+                    .spawn(reflect(&direction, &hit.normal), ray.time())
+                    .with_wavelength(ray.wavelength()),
+                attenuation: reflectance / p,
+            });
+        }
+        let transmitted = (Vector3::repeat(1.0) - reflectance) / (1.0 - p);
+        Some(match self.base.scatter(ray, hit)? {
+            ScatterRecord::Specular {
+                specular_ray,
+                attenuation,
+            } => ScatterRecord::Specular {
+                specular_ray,
+                attenuation: attenuation.component_mul(&transmitted),
+            },
+            ScatterRecord::Scatter { pdf, attenuation } => ScatterRecord::Scatter {
+                pdf,
+                attenuation: attenuation.component_mul(&transmitted),
+            },
+        })
+    }
+
+    fn scattering_pdf(&self, ray: &Ray, hit: &HitRecord, scattered: &Ray) -> Float {
+        self.base.scattering_pdf(ray, hit, scattered)
+    }
+
+    fn emitted(&self, ray: &Ray, hit: &HitRecord) -> Vector3<Float> {
+        self.base.emitted(ray, hit)
+    }
+
+    fn roughness(&self) -> Float {
+        self.base.roughness()
+    }
+}
+
 #[derive(Clone)]
 pub struct DiffuseLight<T: Texture> {
     emit: T,
@@ -171,11 +594,371 @@ impl<T: Texture> DiffuseLight<T> {
 }
 
 impl<T: Texture> Material for DiffuseLight<T> {
-    fn emitted(&self, ray: &Ray, hit: &HitRecord) -> Vector3<f32> {
+    fn emitted(&self, ray: &Ray, hit: &HitRecord) -> Vector3<Float> {
         if hit.normal.dot(&ray.direction()) < 0.0 {
-            self.emit.value(hit.u, hit.v, &hit.p)
+            texture::lookup(&self.emit, ray, hit)
         } else {
             Vector3::zeros()
         }
     }
 }
+
+// scattering inside a participating medium: directions follow its phase function,
+// tinted by the albedo
+#[derive(Clone)]
+pub struct Volumetric<T: Texture, P: PhaseFunction> {
+    albedo: T,
+    phase: P,
+}
+
+impl<T: Texture, P: PhaseFunction> Volumetric<T, P> {
+    pub fn new(albedo: T, phase: P) -> Self {
+        Volumetric { albedo, phase }
+    }
+}
+
+impl<T: Texture, P: PhaseFunction> Material for Volumetric<T, P> {
+    fn scatter(&self, ray: &Ray, hit: &HitRecord) -> Option<ScatterRecord<'_>> {
+        Some(ScatterRecord::Scatter {
+            pdf: PDF::phase(&self.phase, ray.direction()),
+            attenuation: self.albedo.value(hit.u, hit.v, &hit.texture_p),
+        })
+    }
+
+    fn scattering_pdf(&self, ray: &Ray, _hit: &HitRecord, scattered: &Ray) -> Float {
+        self.phase.value(&ray.direction(), &scattered.direction())
+    }
+}
+
+// A number in [0, 1) fixed by the ray and where it hits, so the calls a bounce makes
+// to a material agree on choices drawn from it
+fn hit_sample(ray: &Ray, hit: &HitRecord) -> Float {
+    let mut x: u32 = 0x9e37_79b9;
+    for c in hit.p.iter().chain(ray.direction().iter()) {
+        x ^= float::single(*c).to_bits();
+        x ^= x >> 16;
+        x = x.wrapping_mul(0x7feb_352d);
+        x ^= x >> 15;
+        x = x.wrapping_mul(0x846c_a68b);
+        x ^= x >> 16;
+    }
+    (x >> 8) as Float / (1 << 24) as Float
+}
+
+// Blends two materials: each bounce scatters off b with the probability the mask's
+// red channel gives and off a otherwise, which averages to their mix, and emission
+// is mixed directly. The choice is drawn from the ray and the hit rather than the
+// generator, so scattering_pdf follows the material scatter picked.
+#[derive(Clone)]
+pub struct Mix<A: Material, B: Material, T: Texture = ConstantTexture> {
+    a: A,
+    b: B,
+    mask: T,
+}
+
+impl<A: Material, B: Material> Mix<A, B> {
+    pub fn new(a: A, b: B, factor: Float) -> Self {
+        Mix::with_mask(a, b, ConstantTexture::new(factor, factor, factor))
+    }
+}
+
+impl<A: Material, B: Material, T: Texture> Mix<A, B, T> {
+    pub fn with_mask(a: A, b: B, mask: T) -> Self {
+        Mix { a, b, mask }
+    }
+
+    fn factor(&self, hit: &HitRecord) -> Float {
+        self.mask
+            .value(hit.u, hit.v, &hit.texture_p)
+            .x
+            .clamp(0.0, 1.0)
+    }
+
+    fn pick(&self, ray: &Ray, hit: &HitRecord) -> &dyn Material {
+        if hit_sample(ray, hit) < self.factor(hit) {
+            &self.b
+        } else {
+            &self.a
+        }
+    }
+}
+
+impl<A: Material, B: Material, T: Texture> Material for Mix<A, B, T> {
+    fn scatter(&self, ray: &Ray, hit: &HitRecord) -> Option<ScatterRecord<'_>> {
+        self.pick(ray, hit).scatter(ray, hit)
+    }
+
+    fn scattering_pdf(&self, ray: &Ray, hit: &HitRecord, scattered: &Ray) -> Float {
+        self.pick(ray, hit).scattering_pdf(ray, hit, scattered)
+    }
+
+    fn emitted(&self, ray: &Ray, hit: &HitRecord) -> Vector3<Float> {
+        let factor = self.factor(hit);
+        (1.0 - factor) * self.a.emitted(ray, hit) + factor * self.b.emitted(ray, hit)
+    }
+
+    fn roughness(&self) -> Float {
+        self.a.roughness().max(self.b.roughness())
+    }
+}
+
+// Different materials on the two faces of a surface: rays arriving against the
+// normal see the front one, the others the back one, which sees the hit with its
+// normal turned toward them as if it were a front face.
+#[derive(Clone)]
+pub struct TwoSided<F: Material, B: Material> {
+    front: F,
+    back: B,
+}
+
+impl<F: Material, B: Material> TwoSided<F, B> {
+    pub fn new(front: F, back: B) -> Self {
+        TwoSided { front, back }
+    }
+}
+
+// the back face's view of a hit
+fn turned<'a>(hit: &HitRecord<'a>) -> HitRecord<'a> {
+    HitRecord {
+        normal: -hit.normal,
+        ..*hit
+    }
+}
+
+impl<F: Material, B: Material> Material for TwoSided<F, B> {
+    fn scatter(&self, ray: &Ray, hit: &HitRecord) -> Option<ScatterRecord<'_>> {
+        if ray.direction().dot(&hit.normal) < 0.0 {
+            self.front.scatter(ray, hit)
+        } else {
+            self.back.scatter(ray, &turned(hit))
+        }
+    }
+
+    fn scattering_pdf(&self, ray: &Ray, hit: &HitRecord, scattered: &Ray) -> Float {
+        if ray.direction().dot(&hit.normal) < 0.0 {
+            self.front.scattering_pdf(ray, hit, scattered)
+        } else {
+            self.back.scattering_pdf(ray, &turned(hit), scattered)
+        }
+    }
+
+    fn emitted(&self, ray: &Ray, hit: &HitRecord) -> Vector3<Float> {
+        if ray.direction().dot(&hit.normal) < 0.0 {
+            self.front.emitted(ray, hit)
+        } else {
+            self.back.emitted(ray, &turned(hit))
+        }
+    }
+
+    fn roughness(&self) -> Float {
+        self.front.roughness().max(self.back.roughness())
+    }
+}
+
+// Cuts a material away where its mask's red channel falls below the threshold, as
+// the transparent parts of a leaf or fence texture on a flat card. Elsewhere it is
+// the material itself.
+#[derive(Clone)]
+pub struct Masked<M: Material, T: Texture> {
+    material: M,
+    mask: T,
+    threshold: Float,
+}
+
+impl<M: Material, T: Texture> Masked<M, T> {
+    pub fn new(material: M, mask: T, threshold: Float) -> Self {
+        Masked {
+            material,
+            mask,
+            threshold,
+        }
+    }
+}
+
+impl<M: Material, T: Texture> Material for Masked<M, T> {
+    fn scatter(&self, ray: &Ray, hit: &HitRecord) -> Option<ScatterRecord<'_>> {
+        self.material.scatter(ray, hit)
+    }
+
+    fn scattering_pdf(&self, ray: &Ray, hit: &HitRecord, scattered: &Ray) -> Float {
+        self.material.scattering_pdf(ray, hit, scattered)
+    }
+
+    fn emitted(&self, ray: &Ray, hit: &HitRecord) -> Vector3<Float> {
+        self.material.emitted(ray, hit)
+    }
+
+    fn roughness(&self) -> Float {
+        self.material.roughness()
+    }
+
+    fn opaque(&self, hit: &HitRecord) -> bool {
+        self.mask.value(hit.u, hit.v, &hit.texture_p).x >= self.threshold
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::phase::{HenyeyGreenstein, IsotropicPhase};
+
+    const N: usize = 100_000;
+    // incidence angles as cosines to the normal, head on to grazing
+    const COSINES: [Float; 4] = [1.0, 0.7, 0.3, 0.05];
+
+    fn white() -> ConstantTexture {
+        ConstantTexture::new(1.0, 1.0, 1.0)
+    }
+
+    // a hit at the origin on a surface facing +z, tangent along x
+    fn hit(material: &dyn Material) -> HitRecord<'_> {
+        HitRecord {
+            t: 1.0,
+            u: 0.5,
+            v: 0.5,
+            p: Vector3::zeros(),
+            texture_p: Vector3::zeros(),
+            normal: Vector3::new(0.0, 0.0, 1.0),
+            tangent: Vector3::new(1.0, 0.0, 0.0),
+            dpdu: Vector3::new(1.0, 0.0, 0.0),
+            dpdv: Vector3::new(0.0, 1.0, 0.0),
+            material,
+            object_id: 0,
+            class_id: 0,
+        }
+    }
+
+    // Share of the light arriving at cos_theta to the normal that the material sends
+    // on: the mean of attenuation times scattering_pdf over the pdf a direction was
+    // drawn with, or the attenuation of specular rays. Below zero the light comes
+    // from behind the surface.
+    fn albedo(material: &dyn Material, cos_theta: Float) -> Vector3<Float> {
+        rng::seed(Default::default(), 7);
+        let sin_theta = (1.0 - cos_theta * cos_theta).sqrt();
+        let ray = Ray::new(
+            Vector3::new(-sin_theta, 0.0, cos_theta),
+            Vector3::new(sin_theta, 0.0, -cos_theta),
+            0.0,
+        );
+        let hit = hit(material);
+        let total: Vector3<Float> = (0..N)
+            .map(|_| match material.scatter(&ray, &hit) {
+                Some(ScatterRecord::Specular { attenuation, .. }) => attenuation,
+                Some(ScatterRecord::Scatter { pdf, attenuation }) => {
+                    let scattered = Ray::new(hit.p, pdf.generate(), 0.0);
+                    let pdf = pdf.value(scattered.direction());
+                    if pdf > 0.0 {
+                        attenuation * material.scattering_pdf(&ray, &hit, &scattered) / pdf
+                    } else {
+                        Vector3::zeros()
+                    }
+                }
+                None => Vector3::zeros(),
+            })
+            .sum();
+        total / N as Float
+    }
+
+    fn assert_albedo(material: &dyn Material, expected: Float, tolerance: Float) {
+        for cos_theta in COSINES {
+            let albedo = albedo(material, cos_theta);
+            for a in albedo.iter() {
+                assert!(
+                    (a - expected).abs() < tolerance,
+                    "albedo {} at cos {}, expected {}",
+                    a,
+                    cos_theta,
+                    expected
+                );
+            }
+        }
+    }
+
+    fn assert_conserves(material: &dyn Material) {
+        for cos_theta in COSINES {
+            let albedo = albedo(material, cos_theta);
+            assert!(
+                albedo.iter().all(|a| *a <= 1.01),
+                "albedo {:?} above one at cos {}",
+                albedo,
+                cos_theta
+            );
+        }
+    }
+
+    // White materials that neither absorb nor lose light send all of it on.
+    #[test]
+    fn white_furnace() {
+        assert_albedo(&Lambertian::new(white()), 1.0, 0.01);
+        assert_albedo(&Metal::new(Vector3::new(1.0, 1.0, 1.0), 0.0), 1.0, 1e-6);
+        assert_albedo(&Dielectric::new(1.5), 1.0, 1e-6);
+        assert_albedo(&Volumetric::new(white(), IsotropicPhase), 1.0, 0.01);
+        assert_albedo(
+            &Volumetric::new(white(), HenyeyGreenstein::new(0.6)),
+            1.0,
+            0.01,
+        );
+        assert_albedo(
+            &ThinFilm::new(
+                Lambertian::new(white()),
+                1.33,
+                1.0,
+                ConstantTexture::new(400.0, 0.0, 0.0),
+            ),
+            1.0,
+            0.02,
+        );
+        assert_albedo(
+            &Mix::new(Lambertian::new(white()), Dielectric::new(1.5), 0.5),
+            1.0,
+            0.01,
+        );
+        assert_albedo(
+            &TwoSided::new(Lambertian::new(white()), Lambertian::new(white())),
+            1.0,
+            0.01,
+        );
+        assert_albedo(
+            &Masked::new(Lambertian::new(white()), white(), 0.5),
+            1.0,
+            0.01,
+        );
+    }
+
+    #[test]
+    fn lambertian_albedo_is_its_colour() {
+        let albedo = albedo(&Lambertian::new(ConstantTexture::new(0.2, 0.5, 0.8)), 0.6);
+        assert!((albedo - Vector3::new(0.2, 0.5, 0.8)).norm() < 0.01);
+    }
+
+    // Rough and coloured materials lose light to absorption or to directions under
+    // the surface, but never make it.
+    #[test]
+    fn materials_conserve_energy() {
+        assert_conserves(&Metal::new(Vector3::new(1.0, 1.0, 1.0), 0.5));
+        for preset in ["gold", "silver", "copper", "aluminium"] {
+            let (eta, k) = preset.parse::<MetalPreset>().unwrap().ior();
+            assert_conserves(&Conductor::new(eta, k, 0.0));
+            assert_conserves(&Conductor::new(eta, k, 0.3));
+        }
+        for (alpha_x, alpha_y) in [(0.05, 0.05), (0.3, 0.3), (0.1, 0.6), (0.8, 0.8)] {
+            assert_conserves(&Anisotropic::new(white(), alpha_x, alpha_y));
+        }
+        assert_conserves(&ThinFilm::new(
+            Metal::new(Vector3::new(1.0, 1.0, 1.0), 0.2),
+            1.33,
+            1.5,
+            ConstantTexture::new(300.0, 0.0, 0.0),
+        ));
+    }
+
+    // A smooth microfacet surface is nearly a mirror, so little of the light goes
+    // missing from masking and shadowing.
+    #[test]
+    fn smooth_anisotropic_reflects_nearly_all() {
+        let material = Anisotropic::new(white(), 0.05, 0.05);
+        for cos_theta in [1.0, 0.7, 0.3] {
+            assert!(albedo(&material, cos_theta).x > 0.95);
+        }
+    }
+}
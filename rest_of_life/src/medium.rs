@@ -0,0 +1,535 @@
+use crate::aabb::AABB;
+use crate::cone::Cone;
+use crate::float::Float;
+use crate::hittable::{HitRecord, Hittable, Surfaces};
+use crate::material::{Material, Volumetric};
+use crate::perlin::Perlin;
+use crate::phase::{IsotropicPhase, PhaseFunction};
+use crate::ray::Ray;
+use crate::rng;
+use crate::texture::Texture;
+use crate::volume::{SparseGrid, BRICK};
+use nalgebra::Vector3;
+use std::sync::Arc;
+
+// the most mip levels of a sparse volume's grid, the finest and up to three
+// coarser, whose voxels span up to eight of its own
+const MAX_LEVELS: usize = 4;
+
+// the part of [t_min, t_max] the ray spends inside a closed boundary
+fn span<H: Hittable>(
+    boundary: &H,
+    ray: &Ray,
+    t_min: Float,
+    t_max: Float,
+) -> Option<(Float, Float)> {
+    let mut surfaces = Surfaces::new(ray);
+    let hit1 = surfaces.next(boundary, -Float::MAX, Float::MAX)?;
+    let hit2 = surfaces.next(boundary, -Float::MAX, Float::MAX)?;
+    let (t0, t1) = (hit1.t.max(t_min), hit2.t.min(t_max));
+    if t0 < t1 {
+        Some((t0, t1))
+    } else {
+        None
+    }
+}
+
+// A homogeneous participating medium filling a closed boundary, scattering
+// isotropically unless given another phase function.
+pub struct ConstantMedium<H: Hittable, T: Texture, P: PhaseFunction = IsotropicPhase> {
+    boundary: H,
+    density: Float,
+    phase_function: Volumetric<T, P>,
+}
+
+impl<H: Hittable, T: Texture> ConstantMedium<H, T> {
+    pub fn new(boundary: H, density: Float, texture: T) -> Self {
+        ConstantMedium::with_phase(boundary, density, texture, IsotropicPhase)
+    }
+}
+
+impl<H: Hittable, T: Texture, P: PhaseFunction> ConstantMedium<H, T, P> {
+    pub fn with_phase(boundary: H, density: Float, texture: T, phase: P) -> Self {
+        ConstantMedium {
+            boundary,
+            density,
+            phase_function: Volumetric::new(texture, phase),
+        }
+    }
+}
+
+impl<H: Hittable, T: Texture, P: PhaseFunction> Hittable for ConstantMedium<H, T, P> {
+    fn hit(&self, ray: &Ray, t_min: Float, t_max: Float) -> Option<HitRecord<'_>> {
+        let (t0, t1) = span(&self.boundary, ray, t_min, t_max)?;
+        let distance_inside_boundary = (t1 - t0) * ray.direction().norm();
+        let hit_distance = -(1.0 / self.density) * rng::uniform().ln();
+        if hit_distance < distance_inside_boundary {
+            let t = t0 + hit_distance / ray.direction().norm();
+            return Some(scattering(ray, t, &self.phase_function));
+        }
+        None
+    }
+
+    fn bounding_box(&self, t0: Float, t1: Float) -> Option<AABB> {
+        self.boundary.bounding_box(t0, t1)
+    }
+
+    // the boundary's coverage thinned by the transmittance along the axis's chord
+    // through its bounding box
+    fn occlusion(&self, cone: &Cone, t_min: Float, t_max: Float) -> Float {
+        let chord = self
+            .boundary
+            .bounding_box(0.0, 1.0)
+            .and_then(|bbox| bbox.interval(&cone.axis(), t_min, t_max))
+            .map_or(0.0, |(t0, t1)| t1 - t0);
+        self.boundary.occlusion(cone, t_min, t_max) * (1.0 - (-self.density * chord).exp())
+    }
+}
+
+// a scattering event inside a medium at ray parameter t
+fn scattering<'a>(ray: &Ray, t: Float, material: &'a dyn Material) -> HitRecord<'a> {
+    let p = ray.point_at_parameter(t);
+    HitRecord {
+        t,
+        u: 0.0,
+        v: 0.0,
+        p,
+        texture_p: p,
+        normal: Vector3::new(1.0, 0.0, 0.0), // arbitrary
+        tangent: Vector3::new(0.0, 1.0, 0.0),
+        dpdu: Vector3::zeros(),
+        dpdv: Vector3::zeros(),
+        material,
+        object_id: 0,
+        class_id: 0,
+    }
+}
+
+// A density varying through space, bounded for tracking through it.
+pub trait DensityField: Send + Sync {
+    fn density(&self, p: &Vector3<Float>) -> Float;
+    // the density averaged over a footprint `footprint` across around p, for rays
+    // whose cone has widened; fields without coarser levels give p's own
+    fn filtered_density(&self, p: &Vector3<Float>, _footprint: Float) -> Float {
+        self.density(p)
+    }
+    // no point is denser
+    fn max_density(&self) -> Float;
+}
+
+// Perlin turbulence up to `density`, for wisps of smoke.
+pub struct NoiseDensity {
+    noise: Perlin,
+    scale: Float,
+    density: Float,
+}
+
+impl NoiseDensity {
+    pub fn new(scale: Float, density: Float) -> Self {
+        NoiseDensity {
+            noise: Perlin::new(),
+            scale,
+            density,
+        }
+    }
+}
+
+impl DensityField for NoiseDensity {
+    fn density(&self, p: &Vector3<Float>) -> Float {
+        self.density * self.noise.turb(&(self.scale * p), 7).min(1.0)
+    }
+
+    fn max_density(&self) -> Float {
+        self.density
+    }
+}
+
+// Ground fog: `density` at the floor height, thinning by a factor e every `height`
+// above it and broken up by turbulence of the given scale.
+pub struct GroundFog {
+    noise: Perlin,
+    scale: Float,
+    density: Float,
+    floor: Float,
+    height: Float,
+}
+
+impl GroundFog {
+    pub fn new(scale: Float, density: Float, floor: Float, height: Float) -> Self {
+        GroundFog {
+            noise: Perlin::new(),
+            scale,
+            density,
+            floor,
+            height,
+        }
+    }
+}
+
+impl DensityField for GroundFog {
+    fn density(&self, p: &Vector3<Float>) -> Float {
+        let falloff = (-(p.y - self.floor).max(0.0) / self.height).exp();
+        let breakup = 0.5 + 0.5 * self.noise.turb(&(self.scale * p), 5).min(1.0);
+        self.density * falloff * breakup
+    }
+
+    fn max_density(&self) -> Float {
+        self.density
+    }
+}
+
+// A participating medium inside a closed boundary whose density a field sets point
+// by point. Scattering distances are sampled by delta tracking against the field's
+// largest density, and transmittance for cone occlusion is estimated by ratio
+// tracking.
+pub struct HeterogeneousMedium<H: Hittable, D: DensityField, T: Texture, P = IsotropicPhase>
+where
+    P: PhaseFunction,
+{
+    boundary: H,
+    field: D,
+    majorant: Float,
+    phase_function: Volumetric<T, P>,
+}
+
+impl<H: Hittable, D: DensityField, T: Texture> HeterogeneousMedium<H, D, T> {
+    pub fn new(boundary: H, field: D, texture: T) -> Self {
+        HeterogeneousMedium::with_phase(boundary, field, texture, IsotropicPhase)
+    }
+}
+
+impl<H: Hittable, D: DensityField, T: Texture, P: PhaseFunction> HeterogeneousMedium<H, D, T, P> {
+    pub fn with_phase(boundary: H, field: D, texture: T, phase: P) -> Self {
+        HeterogeneousMedium {
+            boundary,
+            majorant: field.max_density(),
+            field,
+            phase_function: Volumetric::new(texture, phase),
+        }
+    }
+
+    // the next tentative collision after t, by the field's largest density
+    fn step(&self, ray: &Ray, t: Float) -> Float {
+        t - (1.0 - rng::uniform()).ln() / (self.majorant * ray.direction().norm())
+    }
+
+    // Ratio tracking estimate of the transmittance along the ray over [t_min, t_max].
+    // Unbiased like delta tracking, but rarely zero.
+    pub fn transmittance(&self, ray: &Ray, t_min: Float, t_max: Float) -> Float {
+        let majorant = self.majorant;
+        let (t0, t1) = match span(&self.boundary, ray, t_min, t_max) {
+            Some(span) if majorant > 0.0 => span,
+            _ => return 1.0,
+        };
+        let mut transmittance = 1.0;
+        let mut t = self.step(ray, t0);
+        while t < t1 {
+            transmittance *= 1.0 - self.density(ray, t) / majorant;
+            t = self.step(ray, t);
+        }
+        transmittance
+    }
+
+    // the field's density at t along the ray, over the ray's footprint there once it
+    // carries a cone
+    fn density(&self, ray: &Ray, t: Float) -> Float {
+        let p = ray.point_at_parameter(t);
+        match ray.cone() {
+            Some(cone) => {
+                let footprint = cone.footprint(t * ray.direction().norm());
+                self.field.filtered_density(&p, footprint)
+            }
+            None => self.field.density(&p),
+        }
+    }
+}
+
+impl<H: Hittable, D: DensityField, T: Texture, P: PhaseFunction> Hittable
+    for HeterogeneousMedium<H, D, T, P>
+{
+    fn hit(&self, ray: &Ray, t_min: Float, t_max: Float) -> Option<HitRecord<'_>> {
+        let majorant = self.majorant;
+        if majorant <= 0.0 {
+            return None;
+        }
+        let (t0, t1) = span(&self.boundary, ray, t_min, t_max)?;
+        // delta tracking: tentative collisions are real with probability density
+        // over majorant, otherwise null and passed through
+        let mut t = self.step(ray, t0);
+        while t < t1 {
+            if rng::uniform() * majorant < self.density(ray, t) {
+                return Some(scattering(ray, t, &self.phase_function));
+            }
+            t = self.step(ray, t);
+        }
+        None
+    }
+
+    fn bounding_box(&self, t0: Float, t1: Float) -> Option<AABB> {
+        self.boundary.bounding_box(t0, t1)
+    }
+
+    fn occlusion(&self, cone: &Cone, t_min: Float, t_max: Float) -> Float {
+        self.boundary.occlusion(cone, t_min, t_max)
+            * (1.0 - self.transmittance(&cone.axis(), t_min, t_max))
+    }
+}
+
+// A sparse voxel grid filled with a participating medium, placed with its index
+// space origin at `offset` and `scale` world units to a voxel. Rays step from brick
+// cell to brick cell, skipping cells nothing reaches, and track collisions within a
+// cell against that cell's own majorant, so thin wisps around dense cores cost
+// little. Grid values are multiplied by `density`. Rays that carry a cone track
+// through the coarsest level of the grid's mip chain whose voxels are no wider
+// than their footprint where they enter it, each level a grid of its own with its
+// own bricks and majorants, so indirect rays skip the fine detail they would only
+// average away.
+pub struct SparseVolume<T: Texture, P: PhaseFunction = IsotropicPhase> {
+    levels: Vec<Arc<SparseGrid>>,
+    offset: Vector3<Float>,
+    scale: Float,
+    density: Float,
+    phase_function: Volumetric<T, P>,
+}
+
+impl<T: Texture> SparseVolume<T> {
+    pub fn new(
+        grid: Arc<SparseGrid>,
+        offset: Vector3<Float>,
+        scale: Float,
+        density: Float,
+        texture: T,
+    ) -> Self {
+        SparseVolume::with_phase(grid, offset, scale, density, texture, IsotropicPhase)
+    }
+}
+
+impl<T: Texture, P: PhaseFunction> SparseVolume<T, P> {
+    pub fn with_phase(
+        grid: Arc<SparseGrid>,
+        offset: Vector3<Float>,
+        scale: Float,
+        density: Float,
+        texture: T,
+        phase: P,
+    ) -> Self {
+        assert!(scale > 0.0, "voxels must have a positive size");
+        let mut levels = vec![grid];
+        while levels.len() < MAX_LEVELS && levels.last().unwrap().num_bricks() > 1 {
+            let next = levels.last().unwrap().downsample();
+            levels.push(Arc::new(next));
+        }
+        SparseVolume {
+            levels,
+            offset,
+            scale,
+            density,
+            phase_function: Volumetric::new(texture, phase),
+        }
+    }
+
+    // where the level's index space origin sits and how wide its voxels are
+    fn placement(&self, level: usize) -> (Vector3<Float>, Float) {
+        let size = (1 << level) as Float;
+        let offset = self.offset + Vector3::repeat(0.5 * (size - 1.0) * self.scale);
+        (offset, size * self.scale)
+    }
+
+    // the level the ray tracks through over [t_min, t_max]: the finest for rays
+    // without a cone, else the coarsest whose voxels fit its footprint at entry
+    fn level(&self, ray: &Ray, t_min: Float, t_max: Float) -> usize {
+        let Some(cone) = ray.cone() else {
+            return 0;
+        };
+        let Some((t_enter, _)) = self
+            .bounding_box(0.0, 1.0)
+            .and_then(|bbox| bbox.interval(ray, t_min, t_max))
+        else {
+            return 0;
+        };
+        let footprint = cone.footprint(t_enter * ray.direction().norm());
+        let level = (footprint / self.scale).log2().floor();
+        level.clamp(0.0, (self.levels.len() - 1) as Float) as usize
+    }
+
+    // Steps the ray through the brick cells over [t_min, t_max], drawing tentative
+    // collisions in each by its majorant. `collide` gets each one's density over
+    // majorant and says whether it is real; the first real one's t is returned.
+    fn track(
+        &self,
+        ray: &Ray,
+        t_min: Float,
+        t_max: Float,
+        mut collide: impl FnMut(Float) -> bool,
+    ) -> Option<Float> {
+        let level = self.level(ray, t_min, t_max);
+        let grid = &self.levels[level];
+        let (offset, scale) = self.placement(level);
+        let bounds = grid.bounds()?;
+        if self.density <= 0.0 {
+            return None;
+        }
+        // the same parameter t along the ray in index space
+        let local = Ray::new(
+            (ray.origin() - offset) / scale,
+            ray.direction() / scale,
+            ray.time(),
+        );
+        let (t_enter, t_exit) = bounds.interval(&local, t_min, t_max)?;
+        let speed = self.density * ray.direction().norm();
+        let origin = local.origin();
+        let direction = local.direction();
+        let size = BRICK as Float;
+        let entry = local.point_at_parameter(t_enter);
+        let mut cell = [0; 3];
+        let mut step = [0; 3];
+        let mut t_next = [Float::MAX; 3];
+        let mut t_delta = [Float::MAX; 3];
+        for a in 0..3 {
+            let c = ((entry[a] - bounds.min[a]) / size).floor() as i32;
+            let last = ((bounds.max[a] - bounds.min[a]) / size) as i32 - 1;
+            cell[a] = (bounds.min[a] / size) as i32 + c.clamp(0, last);
+            if direction[a] > 0.0 {
+                step[a] = 1;
+                t_delta[a] = size / direction[a];
+                t_next[a] = ((cell[a] + 1) as Float * size - origin[a]) / direction[a];
+            } else if direction[a] < 0.0 {
+                step[a] = -1;
+                t_delta[a] = -size / direction[a];
+                t_next[a] = (cell[a] as Float * size - origin[a]) / direction[a];
+            }
+        }
+        let mut t0 = t_enter;
+        while t0 < t_exit {
+            let a = (0..3)
+                .min_by(|&i, &j| t_next[i].total_cmp(&t_next[j]))
+                .unwrap();
+            let t1 = t_next[a].min(t_exit);
+            let majorant = grid.majorant(cell);
+            if majorant > 0.0 {
+                let rate = majorant * speed;
+                let mut t = t0 - (1.0 - rng::uniform()).ln() / rate;
+                while t < t1 {
+                    let density = grid.sample(&local.point_at_parameter(t));
+                    if collide(density / majorant) {
+                        return Some(t);
+                    }
+                    t -= (1.0 - rng::uniform()).ln() / rate;
+                }
+            }
+            t0 = t1;
+            cell[a] += step[a];
+            t_next[a] += t_delta[a];
+        }
+        None
+    }
+
+    // Ratio tracking estimate of the transmittance along the ray over [t_min, t_max].
+    pub fn transmittance(&self, ray: &Ray, t_min: Float, t_max: Float) -> Float {
+        let mut transmittance = 1.0;
+        self.track(ray, t_min, t_max, |ratio| {
+            transmittance *= 1.0 - ratio;
+            false
+        });
+        transmittance
+    }
+}
+
+impl<T: Texture, P: PhaseFunction> Hittable for SparseVolume<T, P> {
+    // delta tracking, cell by cell
+    fn hit(&self, ray: &Ray, t_min: Float, t_max: Float) -> Option<HitRecord<'_>> {
+        let t = self.track(ray, t_min, t_max, |ratio| rng::uniform() < ratio)?;
+        Some(scattering(ray, t, &self.phase_function))
+    }
+
+    // the finest level's, grown by how much further the coarsest level's voxels
+    // reach
+    fn bounding_box(&self, _t0: Float, _t1: Float) -> Option<AABB> {
+        let bounds = self.levels[0].bounds()?;
+        let reach = match self.levels.len() {
+            1 => 0.0,
+            n => 1.5 * (1 << (n - 1)) as Float,
+        };
+        let reach = Vector3::repeat(reach);
+        Some(AABB::new(
+            self.offset + self.scale * (bounds.min - reach),
+            self.offset + self.scale * (bounds.max + reach),
+        ))
+    }
+
+    fn occlusion(&self, cone: &Cone, t_min: Float, t_max: Float) -> Float {
+        match self.bounding_box(0.0, 1.0) {
+            Some(bbox) => {
+                cone.box_coverage(&bbox, t_min, t_max)
+                    * (1.0 - self.transmittance(&cone.axis(), t_min, t_max))
+            }
+            None => 0.0,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::Lambertian;
+    use crate::ray::RayCone;
+    use crate::sphere::Sphere;
+    use crate::texture::ConstantTexture;
+    use crate::volume::{DensityGrid, MipDensityGrid};
+
+    // voxels of 1 and 0 alternating like a chessboard, which average to a half
+    fn checker(x: i32, y: i32, z: i32) -> Float {
+        ((x + y + z) % 2) as Float
+    }
+
+    #[test]
+    fn rays_with_wider_cones_look_up_coarser_levels() {
+        let data = (0..512)
+            .map(|i| checker(i % 8, i / 8 % 8, i / 64))
+            .collect();
+        let bounds = AABB::new(Vector3::zeros(), Vector3::repeat(8.0));
+        let mip = MipDensityGrid::new(DensityGrid::new(8, 8, 8, data, bounds));
+        assert_eq!(mip.num_levels(), 4);
+        assert_eq!(
+            (mip.level(0.5), mip.level(4.0), mip.level(100.0)),
+            (0.0, 2.0, 3.0)
+        );
+        let white = Lambertian::new(ConstantTexture::new(1.0, 1.0, 1.0));
+        let sphere = Sphere::new(Vector3::repeat(4.0), 6.0, white);
+        let medium = HeterogeneousMedium::new(sphere, mip, ConstantTexture::new(1.0, 1.0, 1.0));
+        // through the centre of voxel (0, 4, 5), a unit along the ray
+        let ray = Ray::new(Vector3::new(-0.5, 4.5, 5.5), Vector3::x(), 0.0);
+        assert_eq!(medium.density(&ray, 1.0), 1.0);
+        let ray = ray.with_cone(Some(RayCone::primary(8.0)));
+        assert_eq!(medium.density(&ray, 1.0), 0.5);
+
+        let grid = SparseGrid::new((0..32 * 32 * 32).map(|i| {
+            let (x, y, z) = (i % 32, i / 32 % 32, i / 1024);
+            ([x, y, z], checker(x, y, z))
+        }));
+        let coarse = grid.downsample();
+        assert_eq!(
+            (coarse.voxel([0, 0, 0]), coarse.voxel([15, 15, 15])),
+            (0.5, 0.5)
+        );
+        let volume = SparseVolume::new(
+            Arc::new(grid),
+            Vector3::zeros(),
+            1.0,
+            1.0,
+            ConstantTexture::new(1.0, 1.0, 1.0),
+        );
+        assert_eq!(volume.levels.len(), 3);
+        // entering six units along, where the footprint is six times the spread
+        let ray = || Ray::new(Vector3::new(-20.0, 16.3, 16.3), Vector3::x(), 0.0);
+        let cone = |spread| ray().with_cone(Some(RayCone::primary(spread)));
+        assert_eq!(volume.level(&ray(), 0.0, Float::MAX), 0);
+        assert_eq!(volume.level(&cone(0.1), 0.0, Float::MAX), 0);
+        assert_eq!(volume.level(&cone(0.5), 0.0, Float::MAX), 1);
+        assert_eq!(volume.level(&cone(1.0), 0.0, Float::MAX), 2);
+        assert_eq!(volume.level(&cone(10.0), 0.0, Float::MAX), 2);
+        // the coarser levels are the checkerboard's mean throughout
+        assert_eq!(volume.levels[0].max_density(), 1.0);
+        assert_eq!(volume.levels[2].max_density(), 0.5);
+    }
+}
@@ -0,0 +1,531 @@
+use crate::float::Float;
+use crate::material::{Lambertian, Material};
+use crate::texture::VertexColors;
+use crate::triangle::{Triangle, TriangleMesh};
+use nalgebra::Vector3;
+use std::fs;
+
+// Triangles over indexed vertices as a file gives them, with the normals and
+// colours of the vertices if it has them.
+#[derive(Clone)]
+pub struct MeshData {
+    pub vertices: Vec<Vector3<Float>>,
+    pub normals: Option<Vec<Vector3<Float>>>,
+    pub colors: Option<Vec<Vector3<Float>>>,
+    pub indices: Vec<[usize; 3]>,
+}
+
+impl MeshData {
+    pub fn bounds(&self) -> (Vector3<Float>, Vector3<Float>) {
+        self.vertices.iter().fold(
+            (Vector3::repeat(Float::MAX), Vector3::repeat(Float::MIN)),
+            |(min, max), v| (min.inf(v), max.sup(v)),
+        )
+    }
+
+    // the mesh moved to centre on center and scaled so its longest side is size long
+    pub fn placed(mut self, center: Vector3<Float>, size: Float) -> Self {
+        let (min, max) = self.bounds();
+        let scale = size / (max - min).max();
+        let middle = 0.5 * (min + max);
+        for v in self.vertices.iter_mut() {
+            *v = center + scale * (*v - middle);
+        }
+        self
+    }
+
+    fn triangles<M: Material>(
+        &self,
+        smooth: bool,
+        material: impl Fn([usize; 3]) -> M,
+    ) -> Vec<Triangle<M>> {
+        self.indices
+            .iter()
+            .map(|&[a, b, c]| {
+                let triangle = Triangle::new(
+                    self.vertices[a],
+                    self.vertices[b],
+                    self.vertices[c],
+                    material([a, b, c]),
+                );
+                match &self.normals {
+                    Some(n) if smooth => triangle.with_normals([n[a], n[b], n[c]]),
+                    _ => triangle,
+                }
+            })
+            .collect()
+    }
+
+    // smooth shaded by the vertices' normals if it has them and smooth is set, which
+    // it should not be for emitters, see Triangle::with_normals
+    pub fn build<M: Material + Clone + 'static>(
+        &self,
+        material: M,
+        smooth: bool,
+    ) -> TriangleMesh<M> {
+        TriangleMesh::from_triangles(self.triangles(smooth, |_| material.clone()))
+    }
+
+    // a diffuse mesh coloured by its vertices, None if they have no colours
+    pub fn build_colored(&self) -> Option<TriangleMesh<Lambertian<VertexColors>>> {
+        let colors = self.colors.as_ref()?;
+        Some(TriangleMesh::from_triangles(
+            self.triangles(true, |[a, b, c]| {
+                Lambertian::new(VertexColors::new([colors[a], colors[b], colors[c]]))
+            }),
+        ))
+    }
+}
+
+// Reads a PLY or STL file, told apart by its extension. PLY files may be ASCII or
+// binary of either byte order; their vertices' x, y and z, nx, ny and nz normals
+// and red, green and blue colours are read, as bytes over 255 or as numbers in
+// [0, 1], and faces of more than three corners are split into fans. Other
+// elements and properties are skipped. STL files may be ASCII or binary; their
+// facets' own normals are dropped for the winding of their corners.
+pub fn read(path: &str) -> Result<MeshData, String> {
+    let bytes = fs::read(path).map_err(|e| format!("cannot read {}: {}", path, e))?;
+    let lower = path.to_ascii_lowercase();
+    let mesh = if lower.ends_with(".ply") {
+        ply(&bytes)
+    } else if lower.ends_with(".stl") {
+        stl(&bytes)
+    } else {
+        Err(String::from("meshes must be .ply or .stl files"))
+    };
+    let mesh = mesh.map_err(|message| format!("{}: {}", path, message))?;
+    if mesh.indices.is_empty() {
+        return Err(format!("{}: no triangles", path));
+    }
+    Ok(mesh)
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Encoding {
+    Ascii,
+    LittleEndian,
+    BigEndian,
+}
+
+#[derive(Clone, Copy)]
+enum Scalar {
+    I8,
+    U8,
+    I16,
+    U16,
+    I32,
+    U32,
+    F32,
+    F64,
+}
+
+impl Scalar {
+    fn parse(name: &str) -> Result<Scalar, String> {
+        match name {
+            "char" | "int8" => Ok(Scalar::I8),
+            "uchar" | "uint8" => Ok(Scalar::U8),
+            "short" | "int16" => Ok(Scalar::I16),
+            "ushort" | "uint16" => Ok(Scalar::U16),
+            "int" | "int32" => Ok(Scalar::I32),
+            "uint" | "uint32" => Ok(Scalar::U32),
+            "float" | "float32" => Ok(Scalar::F32),
+            "double" | "float64" => Ok(Scalar::F64),
+            _ => Err(format!("unknown property type {}", name)),
+        }
+    }
+
+    fn size(&self) -> usize {
+        match self {
+            Scalar::I8 | Scalar::U8 => 1,
+            Scalar::I16 | Scalar::U16 => 2,
+            Scalar::I32 | Scalar::U32 | Scalar::F32 => 4,
+            Scalar::F64 => 8,
+        }
+    }
+}
+
+enum Property {
+    Scalar(String, Scalar),
+    // the type of the count, then of the items
+    List(String, Scalar, Scalar),
+}
+
+struct Element {
+    name: String,
+    count: usize,
+    properties: Vec<Property>,
+}
+
+// the values of a PLY file's body in order, whatever its encoding
+struct Values<'a> {
+    encoding: Encoding,
+    bytes: &'a [u8],
+    at: usize,
+    words: std::str::SplitAsciiWhitespace<'a>,
+}
+
+impl<'a> Values<'a> {
+    fn next(&mut self, scalar: Scalar) -> Result<f64, String> {
+        if self.encoding == Encoding::Ascii {
+            let word = self.words.next().ok_or("unexpected end of file")?;
+            return word
+                .parse()
+                .map_err(|_| format!("expected a number, found {}", word));
+        }
+        let size = scalar.size();
+        let raw = self
+            .bytes
+            .get(self.at..self.at + size)
+            .ok_or("unexpected end of file")?;
+        self.at += size;
+        let mut buffer = [0u8; 8];
+        buffer[..size].copy_from_slice(raw);
+        if self.encoding == Encoding::BigEndian {
+            buffer[..size].reverse();
+        }
+        let b = buffer;
+        Ok(match scalar {
+            Scalar::I8 => b[0] as i8 as f64,
+            Scalar::U8 => b[0] as f64,
+            Scalar::I16 => i16::from_le_bytes([b[0], b[1]]) as f64,
+            Scalar::U16 => u16::from_le_bytes([b[0], b[1]]) as f64,
+            Scalar::I32 => i32::from_le_bytes([b[0], b[1], b[2], b[3]]) as f64,
+            Scalar::U32 => u32::from_le_bytes([b[0], b[1], b[2], b[3]]) as f64,
+            Scalar::F32 => f32::from_le_bytes([b[0], b[1], b[2], b[3]]) as f64,
+            Scalar::F64 => f64::from_le_bytes(b),
+        })
+    }
+}
+
+fn ply(bytes: &[u8]) -> Result<MeshData, String> {
+    let end = bytes
+        .windows(11)
+        .position(|w| w == b"end_header\n" || w == b"end_header\r")
+        .ok_or("no end_header")?;
+    let header = std::str::from_utf8(&bytes[..end]).map_err(|_| "the header is not text")?;
+    let mut body = end + "end_header".len();
+    if bytes.get(body) == Some(&b'\r') {
+        body += 1;
+    }
+    body += 1;
+    let mut lines = header.lines().map(str::trim);
+    if lines.next() != Some("ply") {
+        return Err(String::from("not a PLY file"));
+    }
+    let mut encoding = None;
+    let mut elements: Vec<Element> = Vec::new();
+    for line in lines {
+        let words: Vec<&str> = line.split_whitespace().collect();
+        match words.as_slice() {
+            ["format", format, _] => {
+                encoding = Some(match *format {
+                    "ascii" => Encoding::Ascii,
+                    "binary_little_endian" => Encoding::LittleEndian,
+                    "binary_big_endian" => Encoding::BigEndian,
+                    _ => return Err(format!("unknown format {}", format)),
+                })
+            }
+            ["element", name, count] => elements.push(Element {
+                name: name.to_string(),
+                count: count
+                    .parse()
+                    .map_err(|_| format!("bad element count {}", count))?,
+                properties: Vec::new(),
+            }),
+            ["property", "list", count, item, name] => elements
+                .last_mut()
+                .ok_or("a property before any element")?
+                .properties
+                .push(Property::List(
+                    name.to_string(),
+                    Scalar::parse(count)?,
+                    Scalar::parse(item)?,
+                )),
+            ["property", scalar, name] => elements
+                .last_mut()
+                .ok_or("a property before any element")?
+                .properties
+                .push(Property::Scalar(name.to_string(), Scalar::parse(scalar)?)),
+            ["comment", ..] | ["obj_info", ..] | [] => {}
+            _ => return Err(format!("unexpected header line {}", line)),
+        }
+    }
+    let encoding = encoding.ok_or("no format line")?;
+    let text = match encoding {
+        Encoding::Ascii => {
+            std::str::from_utf8(&bytes[body..]).map_err(|_| "the body is not text")?
+        }
+        _ => "",
+    };
+    let mut values = Values {
+        encoding,
+        bytes: &bytes[body..],
+        at: 0,
+        words: text.split_ascii_whitespace(),
+    };
+    let mut mesh = MeshData {
+        vertices: Vec::new(),
+        normals: None,
+        colors: None,
+        indices: Vec::new(),
+    };
+    for element in elements.iter() {
+        let has = |name: &str| {
+            element
+                .properties
+                .iter()
+                .any(|p| matches!(p, Property::Scalar(n, _) if n == name))
+        };
+        let vertex = element.name == "vertex";
+        if vertex {
+            if !(has("x") && has("y") && has("z")) {
+                return Err(String::from("vertices without x, y and z"));
+            }
+            // counts are the file's word, so these grow as vertices are read
+            if has("nx") && has("ny") && has("nz") {
+                mesh.normals = Some(Vec::new());
+            }
+            if has("red") && has("green") && has("blue") {
+                mesh.colors = Some(Vec::new());
+            }
+        }
+        for _ in 0..element.count {
+            let mut position = Vector3::zeros();
+            let mut normal = Vector3::zeros();
+            let mut color = Vector3::zeros();
+            for property in element.properties.iter() {
+                match property {
+                    Property::Scalar(name, scalar) => {
+                        let value = values.next(*scalar)?;
+                        // colours stored as integers count up to their type's maximum
+                        let channel = match scalar {
+                            Scalar::U8 => value / 255.0,
+                            Scalar::U16 => value / 65535.0,
+                            _ => value,
+                        } as Float;
+                        match (vertex, name.as_str()) {
+                            (true, "x") => position.x = value as Float,
+                            (true, "y") => position.y = value as Float,
+                            (true, "z") => position.z = value as Float,
+                            (true, "nx") => normal.x = value as Float,
+                            (true, "ny") => normal.y = value as Float,
+                            (true, "nz") => normal.z = value as Float,
+                            (true, "red") => color.x = channel,
+                            (true, "green") => color.y = channel,
+                            (true, "blue") => color.z = channel,
+                            _ => {}
+                        }
+                    }
+                    Property::List(name, count, item) => {
+                        let count = values.next(*count)? as usize;
+                        let mut corners = Vec::new();
+                        for _ in 0..count {
+                            // negative indices are as missing as ones past the end
+                            let corner = values.next(*item)?;
+                            corners.push(if corner < 0.0 {
+                                usize::MAX
+                            } else {
+                                corner as usize
+                            });
+                        }
+                        let face = element.name == "face"
+                            && (name == "vertex_indices" || name == "vertex_index");
+                        if face {
+                            for i in 1..corners.len().saturating_sub(1) {
+                                mesh.indices.push([corners[0], corners[i], corners[i + 1]]);
+                            }
+                        }
+                    }
+                }
+            }
+            if vertex {
+                mesh.vertices.push(position);
+                if let Some(normals) = mesh.normals.as_mut() {
+                    normals.push(normal);
+                }
+                if let Some(colors) = mesh.colors.as_mut() {
+                    colors.push(color);
+                }
+            }
+        }
+    }
+    let count = mesh.vertices.len();
+    if mesh.indices.iter().flatten().any(|&i| i >= count) {
+        return Err(String::from("a face refers to a missing vertex"));
+    }
+    Ok(mesh)
+}
+
+// a binary file's size is fixed by its triangle count, which ASCII files that
+// happen to be as long cannot give
+fn stl(bytes: &[u8]) -> Result<MeshData, String> {
+    let binary = bytes.len() >= 84 && {
+        let count = u32::from_le_bytes([bytes[80], bytes[81], bytes[82], bytes[83]]) as usize;
+        bytes.len() == 84 + 50 * count
+    };
+    let mut vertices = Vec::new();
+    if binary {
+        for facet in bytes[84..].chunks_exact(50) {
+            // a normal, three corners, then two bytes of attributes
+            for corner in facet[12..48].chunks_exact(12) {
+                let f = |i: usize| {
+                    f32::from_le_bytes([
+                        corner[4 * i],
+                        corner[4 * i + 1],
+                        corner[4 * i + 2],
+                        corner[4 * i + 3],
+                    ]) as Float
+                };
+                vertices.push(Vector3::new(f(0), f(1), f(2)));
+            }
+        }
+    } else {
+        let text = std::str::from_utf8(bytes).map_err(|_| "neither binary nor ASCII STL")?;
+        let mut words = text.split_ascii_whitespace();
+        while let Some(word) = words.next() {
+            if word == "vertex" {
+                let mut number = || -> Result<Float, String> {
+                    let word = words.next().ok_or("unexpected end of file")?;
+                    word.parse()
+                        .map_err(|_| format!("expected a number, found {}", word))
+                };
+                vertices.push(Vector3::new(number()?, number()?, number()?));
+            }
+        }
+        if vertices.len() % 3 != 0 {
+            return Err(String::from("a facet without three vertices"));
+        }
+    }
+    let indices = (0..vertices.len() / 3)
+        .map(|i| [3 * i, 3 * i + 1, 3 * i + 2])
+        .collect();
+    Ok(MeshData {
+        vertices,
+        normals: None,
+        colors: None,
+        indices,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HEADER: &str = "element vertex 5\n\
+                          property float x\n\
+                          property float y\n\
+                          property float z\n\
+                          property float nx\n\
+                          property float ny\n\
+                          property float nz\n\
+                          property uchar red\n\
+                          property uchar green\n\
+                          property uchar blue\n\
+                          element face 1\n\
+                          property list uchar int vertex_indices\n\
+                          end_header\n";
+
+    // a pentagon over five vertices, each with a normal and a colour
+    fn corners() -> Vec<([f32; 6], [u8; 3])> {
+        (0..5)
+            .map(|i| {
+                let i = i as f32;
+                ([i, 2.0 * i, 3.0 * i, 0.0, 0.0, 1.0], [255, 51 * i as u8, 0])
+            })
+            .collect()
+    }
+
+    fn ascii_ply(face: &str) -> Vec<u8> {
+        let mut text = format!("ply\nformat ascii 1.0\ncomment made by hand\n{}", HEADER);
+        for (v, c) in corners() {
+            let v = v.map(|x| x.to_string()).join(" ");
+            text.push_str(&format!("{} {} {} {}\n", v, c[0], c[1], c[2]));
+        }
+        text.push_str(face);
+        text.into_bytes()
+    }
+
+    fn binary_ply(big_endian: bool, face: &[i32]) -> Vec<u8> {
+        let format = if big_endian {
+            "binary_big_endian"
+        } else {
+            "binary_little_endian"
+        };
+        let mut bytes = format!("ply\nformat {} 1.0\n{}", format, HEADER).into_bytes();
+        let word = |b: [u8; 4]| {
+            if big_endian {
+                [b[3], b[2], b[1], b[0]]
+            } else {
+                b
+            }
+        };
+        for (v, c) in corners() {
+            for x in v {
+                bytes.extend(word(x.to_le_bytes()));
+            }
+            bytes.extend(c);
+        }
+        bytes.push(face.len() as u8);
+        for i in face {
+            bytes.extend(word(i.to_le_bytes()));
+        }
+        bytes
+    }
+
+    fn check_pentagon(mesh: &MeshData) {
+        assert_eq!(mesh.vertices.len(), 5);
+        assert_eq!(mesh.vertices[2], Vector3::new(2.0, 4.0, 6.0));
+        assert_eq!(mesh.normals.as_ref().unwrap()[4], Vector3::z());
+        let colors = mesh.colors.as_ref().unwrap();
+        assert!((colors[1] - Vector3::new(1.0, 0.2, 0.0)).norm() < 1e-6);
+        // split into a fan about the first corner
+        assert_eq!(mesh.indices, [[0, 1, 2], [0, 2, 3], [0, 3, 4]]);
+    }
+
+    #[test]
+    fn ply_encodings_read_alike() {
+        check_pentagon(&ply(&ascii_ply("5 0 1 2 3 4\n")).unwrap());
+        check_pentagon(&ply(&binary_ply(false, &[0, 1, 2, 3, 4])).unwrap());
+        check_pentagon(&ply(&binary_ply(true, &[0, 1, 2, 3, 4])).unwrap());
+    }
+
+    #[test]
+    fn ply_faces_must_refer_to_vertices() {
+        assert!(ply(&ascii_ply("3 0 1 5\n")).is_err());
+        assert!(ply(&ascii_ply("3 0 -1 2\n")).is_err());
+        assert!(ply(&binary_ply(false, &[0, 1, 5])).is_err());
+        assert!(ply(&binary_ply(true, &[0, -1, 2])).is_err());
+        // a count far past what the file holds runs out of values, not memory
+        assert!(ply(&ascii_ply("4000000000 0 1 2\n")).is_err());
+        let huge = "ply\nformat ascii 1.0\nelement vertex 18446744073709551615\n\
+                    property float x\nproperty float y\nproperty float z\nend_header\n0 0 0\n";
+        assert!(ply(huge.as_bytes()).is_err());
+    }
+
+    #[test]
+    fn stl_encodings_read_alike() {
+        let text = "solid tri\n\
+                    facet normal 0 0 1\n outer loop\n\
+                    vertex 0 0 0\n vertex 1 0 0\n vertex 0 1 0\n\
+                    endloop\nendfacet\nendsolid tri\n";
+        let mut binary = vec![0u8; 80];
+        binary.extend(1u32.to_le_bytes());
+        for x in [
+            0.0f32, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0, 0.0,
+        ] {
+            binary.extend(x.to_le_bytes());
+        }
+        binary.extend([0, 0]);
+        for bytes in [text.as_bytes(), &binary] {
+            let mesh = stl(bytes).unwrap();
+            assert_eq!(
+                mesh.vertices,
+                [Vector3::zeros(), Vector3::x(), Vector3::y()]
+            );
+            assert_eq!(mesh.indices, [[0, 1, 2]]);
+            assert!(mesh.normals.is_none());
+        }
+        let broken = "solid tri\nfacet normal 0 0 1\nouter loop\nvertex 0 0 0\nvertex 1 0 0\n";
+        assert!(stl(broken.as_bytes()).is_err());
+    }
+}
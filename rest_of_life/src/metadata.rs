@@ -0,0 +1,170 @@
+use crate::camera::Camera;
+use crate::cli::Options;
+use crate::float::Float;
+use nalgebra::Vector3;
+
+// What an image records about how it was rendered, so it can be rendered again from
+// its own header: the command line, and for reading at a glance the renderer's
+// version, the scene, size, samples, seed and camera. PPMs carry it as comment
+// lines after the magic number and PNGs as tEXt chunks, which viewers list and
+// nothing draws into the picture; raw dumps keep their fixed header.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Metadata {
+    pub entries: Vec<(String, String)>,
+}
+
+impl Metadata {
+    // the settings behind an image of `spp` samples per pixel rendered through the
+    // camera, when it is known
+    pub fn new(options: &Options, spp: usize, camera: Option<&Camera>) -> Self {
+        let precision = if cfg!(feature = "f64") { "f64" } else { "f32" };
+        let scene = match &options.scene_file {
+            Some(path) => path.clone(),
+            None => options.scene.clone(),
+        };
+        let metadata = Metadata::default()
+            .with(
+                "Software",
+                format!("rest_of_life {} {}", env!("CARGO_PKG_VERSION"), precision),
+            )
+            .with(
+                "Command",
+                options
+                    .command
+                    .iter()
+                    .map(|arg| quote(arg))
+                    .collect::<Vec<String>>()
+                    .join(" "),
+            )
+            .with("Scene", scene)
+            .with("Size", format!("{}x{}", options.width, options.height))
+            .with("Samples", spp.to_string())
+            .with("Seed", options.seed.to_string());
+        let metadata = match options.crop {
+            Some(crop) => metadata.with("Crop", crop.to_string()),
+            None => metadata,
+        };
+        match camera {
+            Some(camera) => metadata.with("Camera", describe(camera)),
+            None => metadata,
+        }
+    }
+
+    pub fn with(mut self, key: &str, value: String) -> Self {
+        self.entries.push((key.to_string(), value));
+        self
+    }
+
+    // comment lines, each ended by a newline, to follow a PPM's magic number
+    pub fn ppm_comments(&self) -> String {
+        self.entries
+            .iter()
+            .map(|(key, value)| format!("# {}: {}\n", key, value.replace('\n', " ")))
+            .collect()
+    }
+
+    // tEXt chunks, to follow a PNG's header chunk; the text is Latin-1, so other
+    // characters become ?
+    pub fn png_chunks(&self) -> Vec<u8> {
+        let latin1 = |s: &str| -> Vec<u8> {
+            s.chars()
+                .map(|c| {
+                    if (c as u32) < 256 && c != '\0' {
+                        c as u8
+                    } else {
+                        b'?'
+                    }
+                })
+                .collect()
+        };
+        let mut bytes = Vec::new();
+        for (key, value) in &self.entries {
+            let mut chunk = b"tEXt".to_vec();
+            chunk.extend(latin1(key));
+            chunk.push(0);
+            chunk.extend(latin1(value));
+            bytes.extend(((chunk.len() - 4) as u32).to_be_bytes());
+            bytes.extend(&chunk);
+            bytes.extend(crc32(&chunk).to_be_bytes());
+        }
+        bytes
+    }
+}
+
+// the argument as a shell reads it back as one word
+fn quote(arg: &str) -> String {
+    let plain = |c: char| c.is_ascii_alphanumeric() || "-_./:=,+@%".contains(c);
+    if !arg.is_empty() && arg.chars().all(plain) {
+        arg.to_string()
+    } else {
+        format!("'{}'", arg.replace('\'', "'\\''"))
+    }
+}
+
+// where the camera looks from and the point it is focused on, which it looks at,
+// its vertical field of view in degrees and its aperture, as a scene file's camera
+// statement gives them
+fn describe(camera: &Camera) -> String {
+    let (from, _, _, _) = camera.frame();
+    let at = camera.focus_point();
+    let (_, _, vertical) = camera.image_plane();
+    let fov = 2.0 * (0.5 * vertical.norm() / (at - from).norm()).atan();
+    let point = |p: Vector3<Float>| format!("{} {} {}", p.x, p.y, p.z);
+    format!(
+        "from {} at {} fov {} aperture {}",
+        point(from),
+        point(at),
+        fov.to_degrees(),
+        2.0 * camera.lens_radius()
+    )
+}
+
+// the CRC PNG chunks end with, over their type and data
+fn crc32(bytes: &[u8]) -> u32 {
+    !bytes.iter().fold(!0u32, |crc, &byte| {
+        (0..8).fold(crc ^ byte as u32, |crc, _| {
+            if crc & 1 == 1 {
+                (crc >> 1) ^ 0xedb8_8320
+            } else {
+                crc >> 1
+            }
+        })
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn metadata_reads_back() {
+        // the check value of the CRC PNG uses
+        assert_eq!(crc32(b"123456789"), 0xcbf4_3926);
+        let options = Options {
+            command: [
+                "rest_of_life",
+                "--spp",
+                "16",
+                "--scene-file",
+                "my scene.txt",
+            ]
+            .map(String::from)
+            .to_vec(),
+            ..Options::default()
+        };
+        let metadata = Metadata::new(&options, 16, None);
+        let comments = metadata.ppm_comments();
+        assert!(comments.contains("# Command: rest_of_life --spp 16 --scene-file 'my scene.txt'\n"));
+        assert!(comments.lines().all(|line| line.starts_with("# ")));
+        // the chunks, one after another, each as long as it says
+        let chunks = metadata.png_chunks();
+        let mut at = 0;
+        for (key, value) in &metadata.entries {
+            let length = u32::from_be_bytes(chunks[at..at + 4].try_into().unwrap()) as usize;
+            assert_eq!(&chunks[at + 4..at + 8], b"tEXt");
+            assert_eq!(length, key.len() + 1 + value.len());
+            at += 12 + length;
+        }
+        assert_eq!(at, chunks.len());
+    }
+}
@@ -0,0 +1,91 @@
+use crate::float::{self, Float};
+use nalgebra::Vector3;
+
+// The anisotropic GGX (Trowbridge-Reitz) distribution of microfacet normals, in a
+// local frame with z along the surface normal and x along its tangent. alpha_x and
+// alpha_y are the roughnesses along the tangent and across it: brushed metal is
+// rough across its grooves and smooth along them. Directions are unit vectors
+// pointing away from the surface.
+#[derive(Clone, Copy, Debug)]
+pub struct GGX {
+    alpha_x: Float,
+    alpha_y: Float,
+}
+
+impl GGX {
+    pub fn new(alpha_x: Float, alpha_y: Float) -> Self {
+        // a perfect mirror's distribution is a delta the sampling cannot evaluate
+        GGX {
+            alpha_x: alpha_x.max(1e-3),
+            alpha_y: alpha_y.max(1e-3),
+        }
+    }
+
+    // density of microfacet normals h per projected area
+    pub fn d(&self, h: &Vector3<Float>) -> Float {
+        if h.z <= 0.0 {
+            return 0.0;
+        }
+        let e = (h.x / self.alpha_x).powi(2) + (h.y / self.alpha_y).powi(2) + h.z * h.z;
+        1.0 / (float::consts::PI * self.alpha_x * self.alpha_y * e * e)
+    }
+
+    // Smith's auxiliary function for the facets w sees shadowed
+    fn lambda(&self, w: &Vector3<Float>) -> Float {
+        let a2 = ((w.x * self.alpha_x).powi(2) + (w.y * self.alpha_y).powi(2)) / (w.z * w.z);
+        0.5 * (-1.0 + (1.0 + a2).sqrt())
+    }
+
+    pub fn g1(&self, w: &Vector3<Float>) -> Float {
+        1.0 / (1.0 + self.lambda(w))
+    }
+
+    // height correlated masking and shadowing of the two directions
+    pub fn g2(&self, wo: &Vector3<Float>, wi: &Vector3<Float>) -> Float {
+        1.0 / (1.0 + self.lambda(wo) + self.lambda(wi))
+    }
+
+    // A microfacet normal visible from wo, drawn in proportion to its projected
+    // area (Heitz 2018).
+    pub fn sample_visible(&self, wo: &Vector3<Float>, u1: Float, u2: Float) -> Vector3<Float> {
+        let vh = Vector3::new(self.alpha_x * wo.x, self.alpha_y * wo.y, wo.z).normalize();
+        let length_squared = vh.x * vh.x + vh.y * vh.y;
+        let t1 = if length_squared > 0.0 {
+            Vector3::new(-vh.y, vh.x, 0.0) / length_squared.sqrt()
+        } else {
+            Vector3::new(1.0, 0.0, 0.0)
+        };
+        let t2 = vh.cross(&t1);
+        let r = u1.sqrt();
+        let phi = 2.0 * float::consts::PI * u2;
+        let p1 = r * phi.cos();
+        let s = 0.5 * (1.0 + vh.z);
+        let p2 = (1.0 - s) * (1.0 - p1 * p1).max(0.0).sqrt() + s * r * phi.sin();
+        let nh = p1 * t1 + p2 * t2 + (1.0 - p1 * p1 - p2 * p2).max(0.0).sqrt() * vh;
+        Vector3::new(self.alpha_x * nh.x, self.alpha_y * nh.y, nh.z.max(1e-6)).normalize()
+    }
+
+    // wo mirrored about a visible normal
+    pub fn sample(&self, wo: &Vector3<Float>, u1: Float, u2: Float) -> Vector3<Float> {
+        let h = self.sample_visible(wo, u1, u2);
+        2.0 * wo.dot(&h) * h - wo
+    }
+
+    // density over solid angle of sample's wi
+    pub fn pdf(&self, wo: &Vector3<Float>, wi: &Vector3<Float>) -> Float {
+        if wo.z <= 0.0 || wi.z <= 0.0 {
+            return 0.0;
+        }
+        let h = (wo + wi).normalize();
+        self.g1(wo) * self.d(&h) / (4.0 * wo.z)
+    }
+
+    // the BRDF times the cosine of wi, for a Fresnel reflectance of one
+    pub fn reflectance(&self, wo: &Vector3<Float>, wi: &Vector3<Float>) -> Float {
+        if wo.z <= 0.0 || wi.z <= 0.0 {
+            return 0.0;
+        }
+        let h = (wo + wi).normalize();
+        self.d(&h) * self.g2(wo, wi) / (4.0 * wo.z)
+    }
+}
@@ -0,0 +1,215 @@
+use crate::float::Float;
+use crate::hittable::{HitRecord, Hittable};
+use crate::material::{refract, schlick};
+use crate::ray::{self, Ray};
+use crate::sampling::ONB;
+use crate::stats::{self, Counter};
+use nalgebra::Vector3;
+use std::sync::Arc;
+
+const MAX_ITERATIONS: usize = 20;
+// finite difference step for the walk's jacobian, in tangent plane units
+const STEP: Float = 0.001;
+// how close to the light sample the solved path has to land, in scene units
+const TOLERANCE: Float = 0.01;
+
+// Simplified specular manifold next event estimation through a single glass sphere.
+// From a diffuse point a light position is sampled and a Newton walk over the
+// direction leaving the point looks for the path that refracts into the sphere, out
+// again and lands on that position. Caustics behind the sphere are then connected
+// directly instead of waiting for a diffuse bounce to find the light by chance.
+pub struct Mnee {
+    center: Vector3<Float>,
+    radius: Float,
+    ref_idx: Float,
+    light: Arc<dyn Hittable>,
+}
+
+impl Mnee {
+    pub fn new(
+        center: Vector3<Float>,
+        radius: Float,
+        ref_idx: Float,
+        light: Arc<dyn Hittable>,
+    ) -> Self {
+        Mnee {
+            center,
+            radius,
+            ref_idx,
+            light,
+        }
+    }
+
+    fn on_caster(&self, p: &Vector3<Float>) -> bool {
+        ((p - self.center).norm() - self.radius).abs() < 0.001 * self.radius
+    }
+
+    // Counts the refractions through the caster a path has taken since its last
+    // diffuse vertex; None once it leaves the chain the walk accounts for.
+    pub fn extend_chain(
+        &self,
+        chain: Option<u8>,
+        ray: &Ray,
+        hit: &HitRecord,
+        scattered: &Ray,
+    ) -> Option<u8> {
+        let refracted =
+            ray.direction().dot(&hit.normal) * scattered.direction().dot(&hit.normal) > 0.0;
+        match chain {
+            Some(n) if n < 2 && refracted && self.on_caster(&hit.p) => Some(n + 1),
+            _ => None,
+        }
+    }
+
+    // emission reached along a chain the walk already connected would be counted twice
+    pub fn covers(chain: Option<u8>) -> bool {
+        chain == Some(2)
+    }
+
+    // follows a ray into the sphere and out again, returning the exit point, the
+    // outgoing direction and the fresnel transmittance of both interfaces
+    fn trace(
+        &self,
+        origin: Vector3<Float>,
+        direction: Vector3<Float>,
+    ) -> Option<(Vector3<Float>, Vector3<Float>, Float)> {
+        let d = direction.normalize();
+        let oc = origin - self.center;
+        let b = oc.dot(&d);
+        let discriminant = b.powi(2) - (oc.dot(&oc) - self.radius.powi(2));
+        let t = -b - discriminant.sqrt();
+        if discriminant <= 0.0 || t <= 0.001 {
+            return None;
+        }
+        let entry = origin + t * d;
+        let normal = (entry - self.center) / self.radius;
+        let inside = refract(&d, &normal, 1.0 / self.ref_idx)?.normalize();
+        let mut transmittance = 1.0 - schlick(-d.dot(&normal), self.ref_idx);
+
+        // a ray starting on the sphere leaves it again at -2 (oc . d)
+        let exit = entry - 2.0 * (entry - self.center).dot(&inside) * inside;
+        let normal = (exit - self.center) / self.radius;
+        let outside = refract(&inside, &-normal, self.ref_idx)?.normalize();
+        transmittance *= 1.0 - schlick(self.ref_idx * inside.dot(&normal), self.ref_idx);
+        Some((exit, outside, transmittance))
+    }
+
+    // offset between where the chain leaving x along (a, b) crosses the light plane
+    // and the light sample, in the plane's tangent frame
+    fn constraint(
+        &self,
+        x: Vector3<Float>,
+        frame: &ONB,
+        a: Float,
+        b: Float,
+        target: Vector3<Float>,
+        plane: &ONB,
+    ) -> Option<(Float, Float)> {
+        let direction = frame.local(&Vector3::new(a, b, 1.0));
+        let (exit, outside, _) = self.trace(x, direction)?;
+        let denominator = outside.dot(&plane.w());
+        if denominator.abs() < 1e-6 {
+            return None;
+        }
+        let t = (target - exit).dot(&plane.w()) / denominator;
+        if t <= 0.0 {
+            return None;
+        }
+        let offset = exit + t * outside - target;
+        Some((offset.dot(&plane.u()), offset.dot(&plane.v())))
+    }
+
+    // Radiance arriving at hit through the sphere, weighted by the bsdf and cosine and
+    // divided by the light sampling pdf. Zero when the walk does not converge or the
+    // path is blocked.
+    pub fn sample(
+        &self,
+        ray: &Ray,
+        hit: &HitRecord,
+        attenuation: &Vector3<Float>,
+        world: &dyn Hittable,
+    ) -> Vector3<Float> {
+        self.connect(ray, hit, attenuation, world)
+            .unwrap_or_else(Vector3::zeros)
+    }
+
+    fn connect(
+        &self,
+        ray: &Ray,
+        hit: &HitRecord,
+        attenuation: &Vector3<Float>,
+        world: &dyn Hittable,
+    ) -> Option<Vector3<Float>> {
+        let x = hit.p;
+        let to_light = self.light.random(x);
+        let target = x + to_light;
+        let light_hit = self
+            .light
+            .hit(&hit.spawn(to_light, ray.time()), ray::T_MIN, Float::MAX)?;
+        let cosine = light_hit.normal.dot(&to_light).abs() / to_light.norm();
+        let pdf_area = self.light.pdf_value(x, to_light) * cosine / to_light.norm_squared();
+        if pdf_area <= 0.0 {
+            return None;
+        }
+
+        // walk from the direction through the sphere's centre, which passes straight
+        // through undeviated
+        let frame = ONB::build_from_w(&(self.center - x));
+        let plane = ONB::build_from_w(&light_hit.normal);
+        let (mut a, mut b) = (0.0, 0.0);
+        let mut jacobian_det = None;
+        for _ in 0..MAX_ITERATIONS {
+            let e = self.constraint(x, &frame, a, b, target, &plane)?;
+            let ea = self.constraint(x, &frame, a + STEP, b, target, &plane)?;
+            let eb = self.constraint(x, &frame, a, b + STEP, target, &plane)?;
+            let (j00, j01) = ((ea.0 - e.0) / STEP, (eb.0 - e.0) / STEP);
+            let (j10, j11) = ((ea.1 - e.1) / STEP, (eb.1 - e.1) / STEP);
+            let det = j00 * j11 - j01 * j10;
+            if det.abs() < 1e-12 {
+                return None;
+            }
+            if e.0.hypot(e.1) < TOLERANCE {
+                jacobian_det = Some(det.abs());
+                break;
+            }
+            a -= (j11 * e.0 - j01 * e.1) / det;
+            b -= (j00 * e.1 - j10 * e.0) / det;
+        }
+        let jacobian_det = jacobian_det?;
+
+        let direction = frame.local(&Vector3::new(a, b, 1.0)).normalize();
+        let (exit, outside, transmittance) = self.trace(x, direction)?;
+        stats::count(Counter::ShadowRays);
+        let first = world.hit(&hit.spawn(direction, ray.time()), ray::T_MIN, Float::MAX)?;
+        if !self.on_caster(&first.p) {
+            return None;
+        }
+        let exit_normal = (exit - self.center) / self.radius;
+        let exit_ray = Ray::new(
+            ray::offset_origin(exit, exit_normal, outside),
+            outside,
+            ray.time(),
+        );
+        stats::count(Counter::ShadowRays);
+        let last = world.hit(&exit_ray, ray::T_MIN, Float::MAX)?;
+        if (last.p - target).norm() > 10.0 * TOLERANCE {
+            return None;
+        }
+        let emitted = last.material.emitted(&exit_ray, &last);
+
+        // solid angle at x per unit light area: the walk's jacobian maps tangent plane
+        // offsets to light area, and a unit of tangent plane spans
+        // (1 + a^2 + b^2)^(-3/2) steradians
+        let solid_angle_per_area = 1.0 / ((1.0 + a * a + b * b).powf(1.5) * jacobian_det);
+        let scattering_pdf =
+            hit.material
+                .scattering_pdf(ray, hit, &Ray::new(x, direction, ray.time()));
+        Some(
+            emitted.component_mul(attenuation)
+                * scattering_pdf
+                * transmittance
+                * solid_angle_per_area
+                / pdf_area,
+        )
+    }
+}
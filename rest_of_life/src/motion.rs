@@ -0,0 +1,175 @@
+use crate::aabb::{self, AABB};
+use crate::float::Float;
+use crate::hittable::{HitRecord, Hittable};
+use crate::ray::Ray;
+use nalgebra::Vector3;
+
+// Offsets keyed at times, sorted by time. Between keys the offset moves linearly;
+// before the first and after the last it holds still.
+#[derive(Clone, Debug)]
+pub struct Keyframes {
+    keys: Vec<(Float, Vector3<Float>)>,
+}
+
+impl Keyframes {
+    pub fn new(mut keys: Vec<(Float, Vector3<Float>)>) -> Self {
+        assert!(!keys.is_empty(), "keyframes need at least one key");
+        keys.sort_by(|a, b| a.0.total_cmp(&b.0));
+        Keyframes { keys }
+    }
+
+    pub fn at(&self, time: Float) -> Vector3<Float> {
+        let i = self.keys.partition_point(|(t, _)| *t <= time);
+        if i == 0 {
+            return self.keys[0].1;
+        }
+        if i == self.keys.len() {
+            return self.keys[i - 1].1;
+        }
+        let (t0, a) = self.keys[i - 1];
+        let (t1, b) = self.keys[i];
+        a + (time - t0) / (t1 - t0) * (b - a)
+    }
+
+    // the offsets at t0, t1 and every key between, which bound the path over [t0, t1]
+    fn extremes(&self, t0: Float, t1: Float) -> impl Iterator<Item = Vector3<Float>> + '_ {
+        [self.at(t0), self.at(t1)].into_iter().chain(
+            self.keys
+                .iter()
+                .filter(move |(t, _)| *t > t0 && *t < t1)
+                .map(|(_, offset)| *offset),
+        )
+    }
+}
+
+// A hittable carried along keyframed offsets, placed by the time of each ray.
+pub struct Moving<H: Hittable> {
+    hittable: H,
+    keys: Keyframes,
+    object_space: bool,
+}
+
+impl<H: Hittable> Moving<H> {
+    pub fn new(hittable: H, keys: Keyframes) -> Self {
+        Moving {
+            hittable,
+            keys,
+            object_space: false,
+        }
+    }
+
+    // the same hittable with its solid textures in its own space, so they move
+    // with it rather than it moving through them
+    pub fn in_object_space(self) -> Self {
+        Moving {
+            object_space: true,
+            ..self
+        }
+    }
+}
+
+impl<H: Hittable> Hittable for Moving<H> {
+    fn hit(&self, ray: &Ray, t_min: Float, t_max: Float) -> Option<HitRecord<'_>> {
+        let offset = self.keys.at(ray.time());
+        let moved_ray = Ray::new(ray.origin() - offset, ray.direction(), ray.time());
+        self.hittable.hit(&moved_ray, t_min, t_max).map(|mut hit| {
+            hit.p += offset;
+            if !self.object_space {
+                hit.texture_p += offset;
+            }
+            hit
+        })
+    }
+
+    fn bounding_box(&self, t0: Float, t1: Float) -> Option<AABB> {
+        let bbox = self.hittable.bounding_box(t0, t1)?;
+        self.keys
+            .extremes(t0, t1)
+            .map(|offset| AABB::new(bbox.min + offset, bbox.max + offset))
+            .reduce(|a, b| aabb::surrounding_box(&a, &b))
+    }
+}
+
+// v turned by degrees about the x, y or z axis, 0, 1 or 2
+fn about(v: Vector3<Float>, axis: usize, degrees: Float) -> Vector3<Float> {
+    let (sin, cos) = degrees.to_radians().sin_cos();
+    let (a, b) = ((axis + 1) % 3, (axis + 2) % 3);
+    let mut turned = v;
+    turned[a] = cos * v[a] - sin * v[b];
+    turned[b] = sin * v[a] + cos * v[b];
+    turned
+}
+
+// v turned by angles in degrees about x, then y, then z
+pub fn turn(v: Vector3<Float>, angles: Vector3<Float>) -> Vector3<Float> {
+    about(about(about(v, 0, angles.x), 1, angles.y), 2, angles.z)
+}
+
+fn unturn(v: Vector3<Float>, angles: Vector3<Float>) -> Vector3<Float> {
+    about(about(about(v, 2, -angles.z), 1, -angles.y), 0, -angles.x)
+}
+
+// A hittable turned about a pivot by keyframed angles, in degrees about x, then y,
+// then z, placed by the time of each ray.
+pub struct Turning<H: Hittable> {
+    hittable: H,
+    pivot: Vector3<Float>,
+    keys: Keyframes,
+    object_space: bool,
+}
+
+impl<H: Hittable> Turning<H> {
+    pub fn new(hittable: H, pivot: Vector3<Float>, keys: Keyframes) -> Self {
+        Turning {
+            hittable,
+            pivot,
+            keys,
+            object_space: false,
+        }
+    }
+
+    // the same hittable with its solid textures in its own space, so they move
+    // with it rather than it moving through them
+    pub fn in_object_space(self) -> Self {
+        Turning {
+            object_space: true,
+            ..self
+        }
+    }
+}
+
+impl<H: Hittable> Hittable for Turning<H> {
+    fn hit(&self, ray: &Ray, t_min: Float, t_max: Float) -> Option<HitRecord<'_>> {
+        let angles = self.keys.at(ray.time());
+        let turned_ray = Ray::new(
+            self.pivot + unturn(ray.origin() - self.pivot, angles),
+            unturn(ray.direction(), angles),
+            ray.time(),
+        );
+        self.hittable.hit(&turned_ray, t_min, t_max).map(|mut hit| {
+            hit.p = self.pivot + turn(hit.p - self.pivot, angles);
+            if !self.object_space {
+                hit.texture_p = self.pivot + turn(hit.texture_p - self.pivot, angles);
+            }
+            hit.normal = turn(hit.normal, angles);
+            hit.tangent = turn(hit.tangent, angles);
+            hit.dpdu = turn(hit.dpdu, angles);
+            hit.dpdv = turn(hit.dpdv, angles);
+            hit
+        })
+    }
+
+    // whichever way it turns, it stays within the sphere about the pivot that
+    // holds its box
+    fn bounding_box(&self, t0: Float, t1: Float) -> Option<AABB> {
+        let bbox = self.hittable.bounding_box(t0, t1)?;
+        let reach = (bbox.min - self.pivot)
+            .abs()
+            .sup(&(bbox.max - self.pivot).abs())
+            .norm();
+        Some(AABB::new(
+            self.pivot - Vector3::repeat(reach),
+            self.pivot + Vector3::repeat(reach),
+        ))
+    }
+}
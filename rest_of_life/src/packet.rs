@@ -0,0 +1,154 @@
+use crate::aabb::AABB;
+use crate::float::Float;
+use crate::ray::Ray;
+
+// the most rays a packet bundles
+pub const LANES: usize = 8;
+
+// A bundle of up to LANES coherent rays, the samples of one pixel or the shadow
+// rays they send toward the same lights, traced through an acceleration structure
+// together: a node is fetched once for the whole bundle and its box tested against
+// every ray in one pass over lanes laid out side by side, which the compiler turns
+// into SIMD. Each ray still finds its own nearest hit, within its own t_max; lanes
+// past the rays hold no ray and never hit anything.
+pub struct RayPacket<'a> {
+    rays: &'a [Ray],
+    origin: [[Float; LANES]; 3],
+    inv_dir: [[Float; LANES]; 3],
+    pub t_min: Float,
+    pub t_max: [Float; LANES],
+}
+
+impl<'a> RayPacket<'a> {
+    // the rays, each looking for hits within [t_min, its t_max]
+    pub fn new(rays: &'a [Ray], t_min: Float, t_max: &[Float]) -> Self {
+        assert!(rays.len() <= LANES && t_max.len() >= rays.len());
+        let mut packet = RayPacket {
+            rays,
+            origin: [[0.0; LANES]; 3],
+            inv_dir: [[0.0; LANES]; 3],
+            t_min,
+            t_max: [Float::NEG_INFINITY; LANES],
+        };
+        for (i, ray) in rays.iter().enumerate() {
+            for a in 0..3 {
+                packet.origin[a][i] = ray.origin()[a];
+                packet.inv_dir[a][i] = 1.0 / ray.direction()[a];
+            }
+            packet.t_max[i] = t_max[i];
+        }
+        packet
+    }
+
+    pub fn len(&self) -> usize {
+        self.rays.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rays.is_empty()
+    }
+
+    // every lane holding a ray, one bit each
+    pub fn lanes(&self) -> u32 {
+        (1 << self.rays.len()) - 1
+    }
+
+    pub fn ray(&self, lane: usize) -> &Ray {
+        &self.rays[lane]
+    }
+
+    // which way the first ray points along each axis, standing for the whole
+    // bundle when a traversal orders children
+    pub fn dir_is_neg(&self) -> [bool; 3] {
+        [
+            self.inv_dir[0][0] < 0.0,
+            self.inv_dir[1][0] < 0.0,
+            self.inv_dir[2][0] < 0.0,
+        ]
+    }
+
+    // the lanes whose rays cross the box before their `t_max`, one bit each, by the
+    // same slab test as AABB::hit
+    pub fn hit_box(&self, bbox: &AABB, t_max: &[Float; LANES]) -> u32 {
+        let mut near = [self.t_min; LANES];
+        let mut far = *t_max;
+        for a in 0..3 {
+            for i in 0..LANES {
+                let t0 = (bbox.min[a] - self.origin[a][i]) * self.inv_dir[a][i];
+                let t1 = (bbox.max[a] - self.origin[a][i]) * self.inv_dir[a][i];
+                near[i] = near[i].max(t0.min(t1));
+                far[i] = far[i].min(t0.max(t1));
+            }
+        }
+        (0..LANES).fold(0, |mask, i| mask | (((far[i] > near[i]) as u32) << i))
+    }
+}
+
+// the lanes set in a mask, lowest first
+pub fn lanes_of(mask: u32) -> impl Iterator<Item = usize> {
+    (0..LANES).filter(move |i| mask & (1 << i) != 0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bvh::BVH;
+    use crate::hittable::Hittable;
+    use crate::material::Lambertian;
+    use crate::sphere::Sphere;
+    use crate::texture::ConstantTexture;
+    use nalgebra::Vector3;
+
+    #[test]
+    fn box_test_matches_each_ray() {
+        let bbox = AABB::new(Vector3::new(-1.0, -1.0, -1.0), Vector3::new(1.0, 1.0, 1.0));
+        let rays: Vec<Ray> = (0..5)
+            .map(|i| {
+                let target = Vector3::new(0.6 * i as Float - 1.2, 0.3, 0.0);
+                let origin = Vector3::new(0.0, 0.0, 5.0);
+                Ray::new(origin, target - origin, 0.0)
+            })
+            .collect();
+        let packet = RayPacket::new(&rays, 0.001, &[Float::MAX; LANES]);
+        let mask = packet.hit_box(&bbox, &packet.t_max);
+        for (i, ray) in rays.iter().enumerate() {
+            assert_eq!(mask & (1 << i) != 0, bbox.hit(ray, 0.001, Float::MAX));
+        }
+        // the empty lanes miss, and a ray stopped short of the box misses it
+        assert_eq!(mask & !packet.lanes(), 0);
+        let mut short = packet.t_max;
+        short[2] = 0.5;
+        assert_eq!(packet.hit_box(&bbox, &short) & (1 << 2), 0);
+    }
+
+    #[test]
+    fn bvh_packets_agree_with_single_rays() {
+        let grey = Lambertian::new(ConstantTexture::new(0.5, 0.5, 0.5));
+        let spheres = (0..40)
+            .map(|i| {
+                let center = Vector3::new((i % 8) as Float - 3.5, (i / 8) as Float - 2.0, -5.0);
+                Sphere::new(center, 0.3 + 0.02 * i as Float, grey.clone())
+            })
+            .collect();
+        let bvh = BVH::new(spheres, 0.0, 1.0);
+        let rays: Vec<Ray> = (0..LANES)
+            .map(|i| {
+                // the last looks away from the spheres
+                let z = if i + 1 < LANES { -5.0 } else { 5.0 };
+                let direction = Vector3::new(0.7 * i as Float - 2.5, 0.4 - 0.1 * i as Float, z);
+                Ray::new(Vector3::zeros(), direction, 0.0)
+            })
+            .collect();
+        let reach: Vec<Float> = (0..LANES).map(|i| 0.5 + 0.1 * i as Float).collect();
+        let hits = bvh.hit_packet(&RayPacket::new(&rays, 0.001, &[Float::MAX; LANES]));
+        let blocked = bvh.occluded_packet(&RayPacket::new(&rays, 0.001, &reach));
+        for (i, ray) in rays.iter().enumerate() {
+            let hit = bvh.hit(ray, 0.001, Float::MAX);
+            assert_eq!(hits[i].as_ref().map(|h| h.t), hit.map(|h| h.t));
+            assert_eq!(blocked[i], bvh.occluded(ray, 0.001, reach[i]));
+        }
+        // some rays hit and some miss, some are blocked and some stop short
+        assert!(hits.iter().any(|h| h.is_some()) && hits.iter().any(|h| h.is_none()));
+        assert!(blocked.iter().any(|&b| b) && blocked.iter().any(|&b| !b));
+    }
+}
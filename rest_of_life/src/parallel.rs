@@ -0,0 +1,44 @@
+// The data parallelism the renderer uses. With the parallel feature, on by default,
+// these are rayon's; without it, as on wasm32, the same calls run serially so the
+// core builds for targets without threads.
+#[cfg(feature = "parallel")]
+pub use rayon::prelude::*;
+
+#[cfg(not(feature = "parallel"))]
+pub use serial::*;
+
+#[cfg(not(feature = "parallel"))]
+mod serial {
+    pub trait IntoParallelIterator: IntoIterator + Sized {
+        fn into_par_iter(self) -> Self::IntoIter {
+            self.into_iter()
+        }
+    }
+
+    impl<I: IntoIterator> IntoParallelIterator for I {}
+
+    pub trait ParallelSlice<T> {
+        fn par_iter(&self) -> std::slice::Iter<'_, T>;
+    }
+
+    impl<T> ParallelSlice<T> for [T] {
+        fn par_iter(&self) -> std::slice::Iter<'_, T> {
+            self.iter()
+        }
+    }
+
+    pub trait ParallelSliceMut<T> {
+        fn par_iter_mut(&mut self) -> std::slice::IterMut<'_, T>;
+        fn par_sort_unstable_by_key<K: Ord>(&mut self, f: impl FnMut(&T) -> K);
+    }
+
+    impl<T> ParallelSliceMut<T> for [T] {
+        fn par_iter_mut(&mut self) -> std::slice::IterMut<'_, T> {
+            self.iter_mut()
+        }
+
+        fn par_sort_unstable_by_key<K: Ord>(&mut self, f: impl FnMut(&T) -> K) {
+            self.sort_unstable_by_key(f)
+        }
+    }
+}
@@ -0,0 +1,199 @@
+use crate::bounce::Bounces;
+use crate::float::Float;
+use crate::hittable::{HitRecord, Hittable};
+use crate::ray::Ray;
+use nalgebra::Vector3;
+use std::cell::{Cell, RefCell};
+
+// The paths --debug-pixel traces through its pixel, the pixel's first samples as a
+// render draws them.
+pub const PATHS: usize = 4;
+
+// A bounce by bounce account of the paths the current thread traces while logging
+// is on, for following where a pixel's energy comes from: what each vertex hit, its
+// emission, the densities its next direction was drawn with and the throughput the
+// path carries on with. Only the path tracer logs, see integrator.rs.
+thread_local! {
+    static LOGGING: Cell<bool> = const { Cell::new(false) };
+    static LINES: RefCell<Vec<String>> = const { RefCell::new(Vec::new()) };
+}
+
+// starts logging the current thread's paths, over any lines not taken
+pub fn start() {
+    LINES.with(|lines| lines.borrow_mut().clear());
+    LOGGING.with(|logging| logging.set(true));
+}
+
+#[inline(always)]
+pub fn logging() -> bool {
+    LOGGING.with(|logging| logging.get())
+}
+
+// stops logging, returning the lines logged since start
+pub fn take() -> Vec<String> {
+    LOGGING.with(|logging| logging.set(false));
+    LINES.with(|lines| lines.take())
+}
+
+fn log(line: String) {
+    LINES.with(|lines| lines.borrow_mut().push(line));
+}
+
+fn vector(v: &Vector3<Float>) -> String {
+    format!("({}, {}, {})", v.x, v.y, v.z)
+}
+
+pub fn camera(ray: &Ray) {
+    if logging() {
+        log(format!(
+            "camera ray from {} along {}",
+            vector(&ray.origin()),
+            vector(&ray.direction())
+        ));
+    }
+}
+
+pub fn hit(bounces: Bounces, hit: &HitRecord) {
+    if logging() {
+        log(format!(
+            "bounce {}: object {} ({}) at t {}, p {}, normal {}",
+            bounces.depth,
+            hit.object_id,
+            hit.material.name(),
+            hit.t,
+            vector(&hit.p),
+            vector(&hit.normal)
+        ));
+    }
+}
+
+pub fn miss(bounces: Bounces, background: &Vector3<Float>) {
+    if logging() {
+        log(format!(
+            "bounce {}: left the scene, background {}",
+            bounces.depth,
+            vector(background)
+        ));
+    }
+}
+
+pub fn emitted(emitted: &Vector3<Float>) {
+    if logging() && *emitted != Vector3::zeros() {
+        log(format!("  emits {}", vector(emitted)));
+    }
+}
+
+// radiance reaching the vertex some other way than along the path, caustics mnee
+// connects or light the links let through
+pub fn added(what: &str, radiance: &Vector3<Float>) {
+    if logging() && *radiance != Vector3::zeros() {
+        log(format!("  {} {}", what, vector(radiance)));
+    }
+}
+
+pub fn specular(attenuation: &Vector3<Float>, throughput: &Vector3<Float>) {
+    if logging() {
+        log(format!(
+            "  specular, attenuation {}, throughput now {}",
+            vector(attenuation),
+            vector(throughput)
+        ));
+    }
+}
+
+// a diffuse bounce along `direction`, drawn with density `pdf` from the mixture,
+// beside what the lights alone and the material alone would give it
+pub fn diffuse(
+    hit: &HitRecord,
+    light_shape: Option<&dyn Hittable>,
+    direction: &Vector3<Float>,
+    pdf: Float,
+    scattering_pdf: Float,
+    attenuation: &Vector3<Float>,
+    throughput: &Vector3<Float>,
+) {
+    if logging() {
+        let light_pdf = light_shape.map_or(String::from("none"), |light_shape| {
+            light_shape.pdf_value(hit.p, *direction).to_string()
+        });
+        log(format!(
+            "  diffuse along {}, pdf {} (light {}, material {}), attenuation {}, throughput now {}",
+            vector(direction),
+            pdf,
+            light_pdf,
+            scattering_pdf,
+            vector(attenuation),
+            vector(throughput)
+        ));
+    }
+}
+
+pub fn end(reason: &str) {
+    if logging() {
+        log(format!("  ends: {}", reason));
+    }
+}
+
+// the log of the paths traced through pixel x, row from the top left, each with
+// the radiance it brought, and their mean
+pub fn report(x: usize, row: usize, paths: &[(Vector3<Float>, Vec<String>)]) -> String {
+    let mut report = String::new();
+    for (i, (radiance, lines)) in paths.iter().enumerate() {
+        report.push_str(&format!("pixel {},{} path {}\n", x, row, i));
+        for line in lines {
+            report.push_str(&format!("  {}\n", line));
+        }
+        report.push_str(&format!("  radiance {}\n\n", vector(radiance)));
+    }
+    let mean = paths
+        .iter()
+        .map(|(radiance, _)| radiance)
+        .sum::<Vector3<Float>>()
+        / paths.len().max(1) as Float;
+    report.push_str(&format!(
+        "pixel {},{} mean of {} paths {}\n",
+        x,
+        row,
+        paths.len(),
+        vector(&mean)
+    ));
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::cli::Options;
+    use crate::render::{self, RenderSettings};
+    use crate::scene;
+
+    #[test]
+    fn pixel_paths_are_logged_as_rendered() {
+        let settings = RenderSettings {
+            width: 20,
+            height: 20,
+            spp: 3,
+            seed: 7,
+            ..RenderSettings::default()
+        };
+        let scene = scene::by_name("cornell_box", 1.0, &Options::default()).unwrap();
+        let paths = render::trace_pixel(&scene, &settings, 10, 12, 3);
+        assert_eq!(paths.len(), 3);
+        for (_, lines) in &paths {
+            assert!(lines[0].starts_with("camera ray from"));
+            assert!(lines[1].starts_with("bounce 0: object"));
+            let last = lines.last().unwrap();
+            assert!(last.starts_with("  ends:") || last.contains("left the scene"));
+        }
+        assert!(!super::logging());
+        // the paths are the pixel's samples, so their mean is its estimate
+        let pixel = render::render(
+            &scene,
+            &RenderSettings {
+                crop: "10,12,11,13".parse().ok(),
+                ..settings
+            },
+        );
+        let mean = paths.iter().map(|(c, _)| c).sum::<nalgebra::Vector3<_>>() / 3.0;
+        assert!((pixel.pixels[0] - mean).norm() < 1e-4 * (1.0 + mean.norm()));
+    }
+}
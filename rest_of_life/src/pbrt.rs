@@ -0,0 +1,1214 @@
+use crate::background::SolidColor;
+use crate::camera::{Movements, Shutter};
+use crate::float::Float;
+use crate::material::MetalPreset;
+use crate::meshfile::{self, MeshData};
+use crate::scenefile::{CameraDescription, Description, MaterialDescription, Object, Shape};
+use nalgebra::{Matrix4, Point3, Vector3};
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use std::sync::Arc;
+
+// deep enough for any real scene, shallow enough to stop files including each other
+const MAX_INCLUDE_DEPTH: usize = 32;
+
+// whether --scene-file names a pbrt scene rather than a scene description
+pub fn is_pbrt(path: &str) -> bool {
+    path.to_ascii_lowercase().ends_with(".pbrt")
+}
+
+// Reads the subset of the pbrt v3 scene format that maps onto a scene description,
+// so the published pbrt test scenes can validate and benchmark this renderer. The
+// transform directives, attribute and transform blocks, named coordinate systems,
+// object instancing and Include are followed. Perspective cameras keep their fov,
+// over the shorter side of the Film's resolution, lens radius and focal distance;
+// render at that resolution for the same framing, since --width and --height
+// still set the image's. Spheres, trianglemesh and plymesh shapes are read, see
+// meshfile::read; partial spheres are whole. Matte, plastic, uber and substrate
+// materials become their diffuse Kd, metal a conductor with its roughness as fuzz,
+// mirror a fuzzless metal, glass a dielectric and mix a mix of named materials,
+// coloured by rgb values or constant textures. Diffuse area lights become lights
+// and an infinite light without a map the background. Samplers, integrators,
+// filters, accelerators and media are pbrt's own choices and are ignored; other
+// shapes, materials, textures and lights are errors. pbrt is left-handed, so the
+// world is mirrored in x unless the camera's transform mirrors it already, as the
+// Scale -1 1 1 many exporters begin with does.
+pub fn read(path: &str, shutter: Shutter) -> Result<Description, String> {
+    let directory = Path::new(path).parent().unwrap_or(Path::new(""));
+    let mut files = Vec::new();
+    let mut lexemes = Vec::new();
+    lex(Path::new(path), directory, &mut files, &mut lexemes, 0)?;
+    let mut parser = Parser::new(directory);
+    let mut cursor = Cursor {
+        lexemes: &lexemes,
+        at: 0,
+    };
+    while let Some(lexeme) = lexemes.get(cursor.at) {
+        let located =
+            |message: String| format!("{}: line {}: {}", files[lexeme.file], lexeme.line, message);
+        cursor.at += 1;
+        match &lexeme.token {
+            Token::Word(directive) => parser.directive(directive, &mut cursor).map_err(located)?,
+            token => return Err(located(format!("expected a directive, found {:?}", token))),
+        }
+    }
+    parser
+        .description(shutter)
+        .map_err(|message| format!("{}: {}", path, message))
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    Word(String),
+    Text(String),
+    Number(Float),
+    Open,
+    Close,
+}
+
+// a token and where it was read, files being indices into the ones read
+struct Lexeme {
+    token: Token,
+    file: usize,
+    line: usize,
+}
+
+// the tokens of a file, with those of the files it includes in their place, found
+// relative to the scene's directory as pbrt finds them
+fn lex(
+    path: &Path,
+    directory: &Path,
+    files: &mut Vec<String>,
+    out: &mut Vec<Lexeme>,
+    depth: usize,
+) -> Result<(), String> {
+    let name = path.display().to_string();
+    let text = fs::read_to_string(path).map_err(|e| format!("cannot read {}: {}", name, e))?;
+    let file = files.len();
+    files.push(name.clone());
+    for (i, line) in text.lines().enumerate() {
+        let located = |message: &str| format!("{}: line {}: {}", name, i + 1, message);
+        let mut chars = line.char_indices().peekable();
+        while let Some((start, c)) = chars.next() {
+            let token = match c {
+                '#' => break,
+                '[' => Token::Open,
+                ']' => Token::Close,
+                '"' => {
+                    let end = line[start + 1..]
+                        .find('"')
+                        .ok_or_else(|| located("unterminated string"))?;
+                    while chars.next_if(|&(j, _)| j <= start + 1 + end).is_some() {}
+                    Token::Text(line[start + 1..start + 1 + end].to_string())
+                }
+                c if c.is_whitespace() => continue,
+                _ => {
+                    let mut end = line.len();
+                    while let Some(&(j, c)) = chars.peek() {
+                        if c.is_whitespace() || "[]\"#".contains(c) {
+                            end = j;
+                            break;
+                        }
+                        chars.next();
+                    }
+                    let word = &line[start..end];
+                    match word.parse() {
+                        Ok(number) => Token::Number(number),
+                        Err(_) => Token::Word(word.to_string()),
+                    }
+                }
+            };
+            out.push(Lexeme {
+                token,
+                file,
+                line: i + 1,
+            });
+            if let [.., Lexeme {
+                token: Token::Word(include),
+                ..
+            }, Lexeme {
+                token: Token::Text(included),
+                ..
+            }] = out.as_slice()
+            {
+                if include == "Include" {
+                    if depth == MAX_INCLUDE_DEPTH {
+                        return Err(located("Includes nest too deeply"));
+                    }
+                    let included = directory.join(included);
+                    out.truncate(out.len() - 2);
+                    lex(&included, directory, files, out, depth + 1)?;
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+#[derive(Debug)]
+enum Value {
+    Number(Float),
+    Text(String),
+}
+
+// a parameter's declared type and its values
+struct Param {
+    kind: String,
+    values: Vec<Value>,
+}
+
+// The parameters after a directive, by name. Colours and numbers may name
+// constant textures instead of giving a value.
+struct Params<'a> {
+    params: HashMap<String, Param>,
+    textures: &'a HashMap<String, Vector3<Float>>,
+}
+
+impl Params<'_> {
+    fn numbers(&self, name: &str) -> Result<Option<Vec<Float>>, String> {
+        let Some(param) = self.params.get(name) else {
+            return Ok(None);
+        };
+        param
+            .values
+            .iter()
+            .map(|value| match value {
+                Value::Number(number) => Ok(*number),
+                Value::Text(text) => Err(format!("{} must be numbers, not {}", name, text)),
+            })
+            .collect::<Result<Vec<Float>, String>>()
+            .map(Some)
+    }
+
+    fn number(&self, name: &str, default: Float) -> Result<Float, String> {
+        if self.params.get(name).is_some_and(|p| p.kind == "texture") {
+            return Ok(self.color(name, Vector3::zeros())?.x);
+        }
+        Ok(self.optional_number(name)?.unwrap_or(default))
+    }
+
+    // the number given for name, if any
+    fn optional_number(&self, name: &str) -> Result<Option<Float>, String> {
+        match self.numbers(name)?.as_deref() {
+            None => Ok(None),
+            Some([number]) => Ok(Some(*number)),
+            Some(_) => Err(format!("{} must be one number", name)),
+        }
+    }
+
+    fn texts(&self, name: &str) -> Vec<&str> {
+        self.params.get(name).map_or(Vec::new(), |param| {
+            param
+                .values
+                .iter()
+                .filter_map(|value| match value {
+                    Value::Text(text) => Some(text.as_str()),
+                    Value::Number(_) => None,
+                })
+                .collect()
+        })
+    }
+
+    fn text(&self, name: &str) -> Option<&str> {
+        self.texts(name).first().copied()
+    }
+
+    fn bool(&self, name: &str, default: bool) -> Result<bool, String> {
+        match self.text(name) {
+            None => Ok(default),
+            Some("true") => Ok(true),
+            Some("false") => Ok(false),
+            Some(text) => Err(format!("{} must be true or false, not {}", name, text)),
+        }
+    }
+
+    fn color(&self, name: &str, default: Vector3<Float>) -> Result<Vector3<Float>, String> {
+        let Some(param) = self.params.get(name) else {
+            return Ok(default);
+        };
+        match param.kind.as_str() {
+            "rgb" | "color" => match self.numbers(name)?.as_deref() {
+                Some(&[r, g, b]) => Ok(Vector3::new(r, g, b)),
+                _ => Err(format!("{} must be three numbers", name)),
+            },
+            "float" => Ok(Vector3::repeat(self.number(name, 0.0)?)),
+            "texture" => {
+                let texture = self.text(name).unwrap_or("");
+                self.textures
+                    .get(texture)
+                    .copied()
+                    .ok_or_else(|| format!("{} is not a constant texture", texture))
+            }
+            kind => Err(format!("{} {} is not supported, only rgb", kind, name)),
+        }
+    }
+}
+
+struct Cursor<'a> {
+    lexemes: &'a [Lexeme],
+    at: usize,
+}
+
+impl Cursor<'_> {
+    fn peek(&self) -> Option<&Token> {
+        self.lexemes.get(self.at).map(|lexeme| &lexeme.token)
+    }
+
+    fn next(&mut self) -> Result<&Token, String> {
+        let lexeme = self.lexemes.get(self.at).ok_or("unexpected end of file")?;
+        self.at += 1;
+        Ok(&lexeme.token)
+    }
+
+    fn text(&mut self) -> Result<String, String> {
+        match self.next()? {
+            Token::Text(text) => Ok(text.clone()),
+            token => Err(format!("expected a string, found {:?}", token)),
+        }
+    }
+
+    // n numbers, bracketed or not
+    fn numbers(&mut self, n: usize) -> Result<Vec<Float>, String> {
+        let bracketed = self.peek() == Some(&Token::Open);
+        if bracketed {
+            self.next()?;
+        }
+        let mut numbers = Vec::with_capacity(n);
+        for _ in 0..n {
+            match self.next()? {
+                Token::Number(number) => numbers.push(*number),
+                token => return Err(format!("expected a number, found {:?}", token)),
+            }
+        }
+        if bracketed && self.next()? != &Token::Close {
+            return Err(format!("expected {} numbers in brackets", n));
+        }
+        Ok(numbers)
+    }
+
+    fn params<'t>(
+        &mut self,
+        textures: &'t HashMap<String, Vector3<Float>>,
+    ) -> Result<Params<'t>, String> {
+        let mut params = HashMap::new();
+        while let Some(Token::Text(declaration)) = self.peek() {
+            let declaration = declaration.clone();
+            self.next()?;
+            let (kind, name) = match declaration.split_whitespace().collect::<Vec<_>>()[..] {
+                [kind, name] => (kind.to_string(), name.to_string()),
+                _ => return Err(format!("bad parameter declaration {}", declaration)),
+            };
+            let bracketed = self.peek() == Some(&Token::Open);
+            if bracketed {
+                self.next()?;
+            }
+            let mut values = Vec::new();
+            loop {
+                match self.next()? {
+                    Token::Close if bracketed => break,
+                    Token::Number(number) => values.push(Value::Number(*number)),
+                    Token::Text(text) | Token::Word(text) => values.push(Value::Text(text.clone())),
+                    token => return Err(format!("unexpected {:?} in {}", token, name)),
+                }
+                if !bracketed {
+                    break;
+                }
+            }
+            params.insert(name, Param { kind, values });
+        }
+        Ok(Params { params, textures })
+    }
+}
+
+// the directive's material's Kd, with pbrt's default for the material
+fn diffuse(params: &Params, default: Float) -> Result<MaterialDescription, String> {
+    Ok(MaterialDescription::Lambertian(
+        params.color("Kd", Vector3::repeat(default))?,
+    ))
+}
+
+// pbrt's measured spectra of metals, by the presets they match
+fn metal_spectrum(name: &str) -> Option<MetalPreset> {
+    match name.split('-').nth(1)? {
+        "Au" => Some(MetalPreset::Gold),
+        "Ag" => Some(MetalPreset::Silver),
+        "Cu" => Some(MetalPreset::Copper),
+        "Al" => Some(MetalPreset::Aluminium),
+        _ => None,
+    }
+}
+
+// pbrt v3's map from its perceptual roughness to microfacet alpha
+fn roughness_to_alpha(roughness: Float) -> Float {
+    let x = roughness.max(1e-3).ln();
+    1.62142 + 0.819955 * x + 0.1734 * x * x + 0.0171201 * x * x * x + 0.000640711 * x * x * x * x
+}
+
+fn metal(params: &Params) -> Result<MaterialDescription, String> {
+    let (copper_eta, copper_k) = MetalPreset::Copper.ior();
+    // one of pbrt's named spectra, if that is what the parameter gives
+    let preset = |name: &str| match params.params.get(name) {
+        Some(param) if param.kind == "spectrum" => {
+            let spectrum = params.text(name).unwrap_or("");
+            metal_spectrum(spectrum)
+                .map(Some)
+                .ok_or_else(|| format!("unknown metal spectrum {}", spectrum))
+        }
+        _ => Ok(None),
+    };
+    let eta = match preset("eta")? {
+        Some(metal) => metal.ior().0,
+        None => params.color("eta", copper_eta)?,
+    };
+    let k = match preset("k")? {
+        Some(metal) => metal.ior().1,
+        None => params.color("k", copper_k)?,
+    };
+    let roughness = params.number("roughness", 0.01)?;
+    let roughness =
+        0.5 * (params.number("uroughness", roughness)? + params.number("vroughness", roughness)?);
+    let alpha = if params.bool("remaproughness", true)? {
+        roughness_to_alpha(roughness)
+    } else {
+        roughness
+    };
+    if eta.min() <= 0.0 || k.min() < 0.0 {
+        return Err(String::from("eta must be positive and k not negative"));
+    }
+    Ok(MaterialDescription::Conductor(
+        eta,
+        k,
+        alpha.clamp(0.0, 1.0),
+    ))
+}
+
+// a matrix from its rows, as they are written down
+fn rows(m: [[Float; 4]; 4]) -> Matrix4<Float> {
+    Matrix4::from(m).transpose()
+}
+
+fn translate(d: Vector3<Float>) -> Matrix4<Float> {
+    rows([
+        [1.0, 0.0, 0.0, d.x],
+        [0.0, 1.0, 0.0, d.y],
+        [0.0, 0.0, 1.0, d.z],
+        [0.0, 0.0, 0.0, 1.0],
+    ])
+}
+
+fn scale(s: Vector3<Float>) -> Matrix4<Float> {
+    rows([
+        [s.x, 0.0, 0.0, 0.0],
+        [0.0, s.y, 0.0, 0.0],
+        [0.0, 0.0, s.z, 0.0],
+        [0.0, 0.0, 0.0, 1.0],
+    ])
+}
+
+fn rotate(degrees: Float, axis: Vector3<Float>) -> Matrix4<Float> {
+    let a = axis.normalize();
+    let (sin, cos) = degrees.to_radians().sin_cos();
+    let c = 1.0 - cos;
+    rows([
+        [
+            a.x * a.x * c + cos,
+            a.x * a.y * c - a.z * sin,
+            a.x * a.z * c + a.y * sin,
+            0.0,
+        ],
+        [
+            a.y * a.x * c + a.z * sin,
+            a.y * a.y * c + cos,
+            a.y * a.z * c - a.x * sin,
+            0.0,
+        ],
+        [
+            a.z * a.x * c - a.y * sin,
+            a.z * a.y * c + a.x * sin,
+            a.z * a.z * c + cos,
+            0.0,
+        ],
+        [0.0, 0.0, 0.0, 1.0],
+    ])
+}
+
+// pbrt's LookAt, from world to camera space
+fn look_at(
+    from: Vector3<Float>,
+    at: Vector3<Float>,
+    up: Vector3<Float>,
+) -> Result<Matrix4<Float>, String> {
+    let dir = (at - from).normalize();
+    let right = up.normalize().cross(&dir);
+    if right.norm() == 0.0 {
+        return Err(String::from("LookAt's up is along its view"));
+    }
+    let right = right.normalize();
+    let up = dir.cross(&right);
+    Matrix4::from([
+        [right.x, right.y, right.z, 0.0],
+        [up.x, up.y, up.z, 0.0],
+        [dir.x, dir.y, dir.z, 0.0],
+        [from.x, from.y, from.z, 1.0],
+    ])
+    .try_inverse()
+    .ok_or_else(|| String::from("singular LookAt"))
+}
+
+// the determinant of a transform's linear part, negative if it mirrors
+fn determinant(m: &Matrix4<Float>) -> Float {
+    let axis = |v: Vector3<Float>| m.transform_vector(&v);
+    axis(Vector3::x()).dot(&axis(Vector3::y()).cross(&axis(Vector3::z())))
+}
+
+fn point(m: &Matrix4<Float>, p: Vector3<Float>) -> Vector3<Float> {
+    m.transform_point(&Point3::from(p)).coords
+}
+
+// a shape's geometry in some space
+#[derive(Clone)]
+enum Geometry {
+    Sphere {
+        center: Vector3<Float>,
+        radius: Float,
+    },
+    Mesh(MeshData),
+}
+
+impl Geometry {
+    fn transformed(self, m: &Matrix4<Float>) -> Result<Geometry, String> {
+        match self {
+            Geometry::Sphere { center, radius } => {
+                let scales = [Vector3::x(), Vector3::y(), Vector3::z()]
+                    .map(|axis| m.transform_vector(&axis).norm());
+                let (min, max) = (
+                    scales[0].min(scales[1]).min(scales[2]),
+                    scales[0].max(scales[1]).max(scales[2]),
+                );
+                if max - min > 1e-3 * max {
+                    return Err(String::from("spheres cannot be scaled unevenly"));
+                }
+                Ok(Geometry::Sphere {
+                    center: point(m, center),
+                    radius: radius * max,
+                })
+            }
+            Geometry::Mesh(mut mesh) => {
+                for v in mesh.vertices.iter_mut() {
+                    *v = point(m, *v);
+                }
+                if let Some(normals) = mesh.normals.as_mut() {
+                    let inverse = m
+                        .try_inverse()
+                        .ok_or_else(|| String::from("singular transform"))?
+                        .transpose();
+                    for n in normals.iter_mut() {
+                        *n = inverse.transform_vector(n);
+                    }
+                }
+                // mirroring turns the winding around, and with it the side faced
+                if determinant(m) < 0.0 {
+                    for [_, b, c] in mesh.indices.iter_mut() {
+                        std::mem::swap(b, c);
+                    }
+                }
+                Ok(Geometry::Mesh(mesh))
+            }
+        }
+    }
+
+    fn shape(self) -> Shape {
+        match self {
+            Geometry::Sphere { center, radius } => Shape::Sphere { center, radius },
+            Geometry::Mesh(mesh) => Shape::Mesh(Arc::new(mesh)),
+        }
+    }
+}
+
+// The attributes AttributeBegin saves: the transform from object to world space,
+// the material, None for pbrt's "none" that hides shapes, and the light a shape
+// becomes, if any.
+#[derive(Clone)]
+struct State {
+    transform: Matrix4<Float>,
+    material: Option<usize>,
+    light: Option<usize>,
+    reverse: bool,
+}
+
+// a shape of an object to instance, in the object's space
+#[derive(Clone)]
+struct Part {
+    geometry: Geometry,
+    material: usize,
+    flip: bool,
+}
+
+// the camera as pbrt gives it, framed once the Film is known
+struct PBRTCamera {
+    to_world: Matrix4<Float>,
+    fov: Float,
+    frame_aspect: Option<Float>,
+    lens_radius: Float,
+    focal_distance: Float,
+}
+
+// what the directives so far describe
+struct Parser<'a> {
+    directory: &'a Path,
+    state: State,
+    attributes: Vec<State>,
+    transforms: Vec<Matrix4<Float>>,
+    coordinate_systems: HashMap<String, Matrix4<Float>>,
+    textures: HashMap<String, Vector3<Float>>,
+    named_materials: HashMap<String, usize>,
+    materials: Vec<MaterialDescription>,
+    camera: PBRTCamera,
+    resolution: (Float, Float),
+    // from pbrt's world to this crate's, mirrored for its handedness
+    world: Matrix4<Float>,
+    background: Vector3<Float>,
+    objects: Vec<Object>,
+    instances: HashMap<String, Vec<Part>>,
+    defining: Option<(String, Vec<Part>)>,
+}
+
+impl<'a> Parser<'a> {
+    fn new(directory: &'a Path) -> Self {
+        Parser {
+            directory,
+            state: State {
+                transform: Matrix4::identity(),
+                material: Some(0),
+                light: None,
+                reverse: false,
+            },
+            attributes: Vec::new(),
+            transforms: Vec::new(),
+            coordinate_systems: HashMap::new(),
+            textures: HashMap::new(),
+            named_materials: HashMap::new(),
+            // pbrt's default material
+            materials: vec![MaterialDescription::Lambertian(Vector3::repeat(0.5))],
+            camera: PBRTCamera {
+                to_world: Matrix4::identity(),
+                fov: 90.0,
+                frame_aspect: None,
+                lens_radius: 0.0,
+                focal_distance: 1e6,
+            },
+            resolution: (1280.0, 720.0),
+            world: scale(Vector3::new(-1.0, 1.0, 1.0)),
+            background: Vector3::zeros(),
+            objects: Vec::new(),
+            instances: HashMap::new(),
+            defining: None,
+        }
+    }
+
+    fn transform(&mut self, m: Matrix4<Float>) {
+        self.state.transform *= m;
+    }
+
+    fn material(&self, kind: &str, params: &Params) -> Result<Option<MaterialDescription>, String> {
+        let material = match kind {
+            "" | "none" => return Ok(None),
+            "matte" | "substrate" => diffuse(params, 0.5)?,
+            "plastic" | "uber" => diffuse(params, 0.25)?,
+            "metal" => metal(params)?,
+            "mirror" => MaterialDescription::Metal(params.color("Kr", Vector3::repeat(0.9))?, 0.0),
+            "glass" => {
+                let index = params.number("eta", params.number("index", 1.5)?)?;
+                if index < 1.0 {
+                    return Err(String::from("a glass's index must be at least 1"));
+                }
+                MaterialDescription::Dielectric(index)
+            }
+            "mix" => {
+                // pbrt v3 names them apart, v4 in a list
+                let [a, b] = match params.texts("materials")[..] {
+                    [a, b] => [a, b],
+                    _ => [
+                        params.text("namedmaterial1").unwrap_or(""),
+                        params.text("namedmaterial2").unwrap_or(""),
+                    ],
+                };
+                let index = |name: &str| {
+                    self.named_materials
+                        .get(name)
+                        .copied()
+                        .ok_or_else(|| format!("unknown material {}", name))
+                };
+                let factor = params.number("amount", 0.5)?;
+                if !(0.0..=1.0).contains(&factor) {
+                    return Err(String::from("a mix's amount must be in [0, 1]"));
+                }
+                MaterialDescription::Mix {
+                    a: index(a)?,
+                    b: index(b)?,
+                    factor,
+                }
+            }
+            _ => return Err(format!("unsupported material {}", kind)),
+        };
+        Ok(Some(material))
+    }
+
+    // the index of a material from now on
+    fn declare(&mut self, material: Option<MaterialDescription>) -> Option<usize> {
+        self.materials.push(material?);
+        Some(self.materials.len() - 1)
+    }
+
+    fn geometry(&self, kind: &str, params: &Params) -> Result<Geometry, String> {
+        match kind {
+            "sphere" => Ok(Geometry::Sphere {
+                center: Vector3::zeros(),
+                radius: params.number("radius", 1.0)?,
+            }),
+            "trianglemesh" => {
+                let points = |name: &str| -> Result<Option<Vec<Vector3<Float>>>, String> {
+                    let Some(numbers) = params.numbers(name)? else {
+                        return Ok(None);
+                    };
+                    if numbers.len() % 3 != 0 {
+                        return Err(format!("{} must be triples", name));
+                    }
+                    Ok(Some(
+                        numbers
+                            .chunks_exact(3)
+                            .map(|p| Vector3::new(p[0], p[1], p[2]))
+                            .collect(),
+                    ))
+                };
+                let vertices = points("P")?.ok_or("a trianglemesh without P")?;
+                let indices = match params.numbers("indices")? {
+                    Some(indices) => indices,
+                    None if vertices.len() == 3 => vec![0.0, 1.0, 2.0],
+                    None => return Err(String::from("a trianglemesh without indices")),
+                };
+                if indices.len() % 3 != 0
+                    || indices
+                        .iter()
+                        .any(|&i| i < 0.0 || i as usize >= vertices.len())
+                {
+                    return Err(String::from(
+                        "a trianglemesh's indices must be triples of its points",
+                    ));
+                }
+                let normals = points("N")?;
+                if normals.as_ref().is_some_and(|n| n.len() != vertices.len()) {
+                    return Err(String::from("a trianglemesh needs a normal for each point"));
+                }
+                Ok(Geometry::Mesh(MeshData {
+                    vertices,
+                    normals,
+                    colors: None,
+                    indices: indices
+                        .chunks_exact(3)
+                        .map(|t| [t[0] as usize, t[1] as usize, t[2] as usize])
+                        .collect(),
+                }))
+            }
+            "plymesh" => {
+                let file = params
+                    .text("filename")
+                    .ok_or("a plymesh without a filename")?;
+                let path = self.directory.join(file);
+                Ok(Geometry::Mesh(meshfile::read(&path.display().to_string())?))
+            }
+            _ => Err(format!("unsupported shape {}", kind)),
+        }
+    }
+
+    fn push(&mut self, part: Part, to_world: &Matrix4<Float>) -> Result<(), String> {
+        let m = self.world * *to_world;
+        self.objects.push(Object {
+            shape: part.geometry.transformed(&m)?.shape(),
+            material: part.material,
+            flip: part.flip,
+            keys: None,
+            turn_keys: None,
+            object_space: false,
+            decal: None,
+            placements: Vec::new(),
+        });
+        Ok(())
+    }
+
+    fn directive(&mut self, directive: &str, cursor: &mut Cursor) -> Result<(), String> {
+        let vector = |numbers: &[Float]| Vector3::new(numbers[0], numbers[1], numbers[2]);
+        match directive {
+            "Identity" => self.state.transform = Matrix4::identity(),
+            "Translate" => self.transform(translate(vector(&cursor.numbers(3)?))),
+            "Scale" => self.transform(scale(vector(&cursor.numbers(3)?))),
+            "Rotate" => {
+                let numbers = cursor.numbers(4)?;
+                self.transform(rotate(numbers[0], vector(&numbers[1..])));
+            }
+            "LookAt" => {
+                let n = cursor.numbers(9)?;
+                self.transform(look_at(vector(&n), vector(&n[3..]), vector(&n[6..]))?);
+            }
+            "Transform" | "ConcatTransform" => {
+                let n = cursor.numbers(16)?;
+                // written column by column
+                let m = Matrix4::from([
+                    [n[0], n[1], n[2], n[3]],
+                    [n[4], n[5], n[6], n[7]],
+                    [n[8], n[9], n[10], n[11]],
+                    [n[12], n[13], n[14], n[15]],
+                ]);
+                if directive == "Transform" {
+                    self.state.transform = m;
+                } else {
+                    self.transform(m);
+                }
+            }
+            "CoordinateSystem" => {
+                let name = cursor.text()?;
+                self.coordinate_systems.insert(name, self.state.transform);
+            }
+            "CoordSysTransform" => {
+                let name = cursor.text()?;
+                self.state.transform = *self
+                    .coordinate_systems
+                    .get(&name)
+                    .ok_or_else(|| format!("unknown coordinate system {}", name))?;
+            }
+            "Camera" => {
+                let kind = cursor.text()?;
+                let params = cursor.params(&self.textures)?;
+                if kind != "perspective" {
+                    return Err(format!("unsupported camera {}", kind));
+                }
+                let to_world = self
+                    .state
+                    .transform
+                    .try_inverse()
+                    .ok_or("singular camera transform")?;
+                self.coordinate_systems
+                    .insert(String::from("camera"), to_world);
+                if determinant(&to_world) < 0.0 {
+                    self.world = Matrix4::identity();
+                }
+                self.camera = PBRTCamera {
+                    to_world,
+                    fov: params.number("fov", 90.0)?,
+                    frame_aspect: params.optional_number("frameaspectratio")?,
+                    lens_radius: params.number("lensradius", 0.0)?,
+                    focal_distance: params.number("focaldistance", 1e6)?,
+                };
+            }
+            "Film" => {
+                cursor.text()?;
+                let params = cursor.params(&self.textures)?;
+                self.resolution = (
+                    params.number("xresolution", 1280.0)?,
+                    params.number("yresolution", 720.0)?,
+                );
+            }
+            "Sampler" | "Integrator" | "PixelFilter" | "Accelerator" | "SurfaceIntegrator"
+            | "VolumeIntegrator" | "Renderer" | "MakeNamedMedium" => {
+                cursor.text()?;
+                cursor.params(&self.textures)?;
+            }
+            "MediumInterface" => {
+                while let Some(Token::Text(_)) = cursor.peek() {
+                    cursor.next()?;
+                }
+            }
+            "TransformTimes" => {
+                cursor.numbers(2)?;
+            }
+            "ActiveTransform" => {
+                cursor.next()?;
+            }
+            "WorldBegin" => {
+                self.state.transform = Matrix4::identity();
+                self.coordinate_systems
+                    .insert(String::from("world"), Matrix4::identity());
+            }
+            "WorldEnd" => {}
+            "AttributeBegin" => self.attributes.push(self.state.clone()),
+            "AttributeEnd" => {
+                self.state = self
+                    .attributes
+                    .pop()
+                    .ok_or("AttributeEnd without AttributeBegin")?
+            }
+            "TransformBegin" => self.transforms.push(self.state.transform),
+            "TransformEnd" => {
+                self.state.transform = self
+                    .transforms
+                    .pop()
+                    .ok_or("TransformEnd without TransformBegin")?
+            }
+            "ReverseOrientation" => self.state.reverse = !self.state.reverse,
+            "Texture" => {
+                let name = cursor.text()?;
+                cursor.text()?;
+                let class = cursor.text()?;
+                let params = cursor.params(&self.textures)?;
+                if class != "constant" {
+                    return Err(format!("unsupported texture {}, only constant ones", class));
+                }
+                let value = params.color("value", Vector3::repeat(1.0))?;
+                self.textures.insert(name, value);
+            }
+            "Material" => {
+                let kind = cursor.text()?;
+                let params = cursor.params(&self.textures)?;
+                let material = self.material(&kind, &params)?;
+                self.state.material = self.declare(material);
+            }
+            "MakeNamedMaterial" => {
+                let name = cursor.text()?;
+                let params = cursor.params(&self.textures)?;
+                let kind = params.text("type").unwrap_or("").to_string();
+                let material = self.material(&kind, &params)?;
+                if let Some(index) = self.declare(material) {
+                    self.named_materials.insert(name, index);
+                }
+            }
+            "NamedMaterial" => {
+                let name = cursor.text()?;
+                self.state.material = match self.named_materials.get(&name) {
+                    Some(&index) => Some(index),
+                    None if name == "none" || name.is_empty() => None,
+                    None => return Err(format!("unknown material {}", name)),
+                };
+            }
+            "AreaLightSource" => {
+                let kind = cursor.text()?;
+                let params = cursor.params(&self.textures)?;
+                if kind != "diffuse" {
+                    return Err(format!("unsupported area light {}", kind));
+                }
+                let l = params.color("L", Vector3::repeat(1.0))?;
+                let s = params.color("scale", Vector3::repeat(1.0))?;
+                self.materials
+                    .push(MaterialDescription::Light(l.component_mul(&s)));
+                self.state.light = Some(self.materials.len() - 1);
+            }
+            "LightSource" => {
+                let kind = cursor.text()?;
+                let params = cursor.params(&self.textures)?;
+                if kind != "infinite" || params.text("mapname").is_some() {
+                    return Err(String::from(
+                        "only area lights and infinite lights without maps are supported",
+                    ));
+                }
+                let l = params.color("L", Vector3::repeat(1.0))?;
+                let s = params.color("scale", Vector3::repeat(1.0))?;
+                self.background += l.component_mul(&s);
+            }
+            "Shape" => {
+                let kind = cursor.text()?;
+                let params = cursor.params(&self.textures)?;
+                let geometry = self.geometry(&kind, &params)?;
+                let Some(material) = self.state.light.or(self.state.material) else {
+                    return Ok(());
+                };
+                let part = Part {
+                    geometry,
+                    material,
+                    flip: self.state.reverse,
+                };
+                match self.defining.as_mut() {
+                    Some((_, parts)) => parts.push(Part {
+                        geometry: part.geometry.transformed(&self.state.transform)?,
+                        ..part
+                    }),
+                    None => {
+                        let to_world = self.state.transform;
+                        self.push(part, &to_world)?;
+                    }
+                }
+            }
+            "ObjectBegin" => {
+                let name = cursor.text()?;
+                if self.defining.is_some() {
+                    return Err(String::from("ObjectBegin inside another object"));
+                }
+                self.attributes.push(self.state.clone());
+                self.defining = Some((name, Vec::new()));
+            }
+            "ObjectEnd" => {
+                let (name, parts) = self
+                    .defining
+                    .take()
+                    .ok_or("ObjectEnd without ObjectBegin")?;
+                self.instances.insert(name, parts);
+                self.state = self
+                    .attributes
+                    .pop()
+                    .ok_or("ObjectEnd without ObjectBegin")?;
+            }
+            "ObjectInstance" => {
+                let name = cursor.text()?;
+                let parts = self
+                    .instances
+                    .get(&name)
+                    .ok_or_else(|| format!("unknown object {}", name))?
+                    .clone();
+                let to_world = self.state.transform;
+                for part in parts {
+                    self.push(part, &to_world)?;
+                }
+            }
+            _ => return Err(format!("unsupported directive {}", directive)),
+        }
+        Ok(())
+    }
+
+    fn description(self, shutter: Shutter) -> Result<Description, String> {
+        if self.objects.is_empty() {
+            return Err(String::from("no shapes"));
+        }
+        let c = &self.camera;
+        let aspect = c
+            .frame_aspect
+            .unwrap_or(self.resolution.0 / self.resolution.1);
+        // pbrt's fov spans the shorter side, this crate's the height
+        let fov = if aspect >= 1.0 {
+            c.fov
+        } else {
+            2.0 * ((0.5 * c.fov).to_radians().tan() / aspect)
+                .atan()
+                .to_degrees()
+        };
+        let to_world = self.world * c.to_world;
+        let from = point(&to_world, Vector3::zeros());
+        Ok(Description {
+            camera: CameraDescription {
+                from,
+                at: from + to_world.transform_vector(&Vector3::z()),
+                up: to_world.transform_vector(&Vector3::y()),
+                fov,
+                aperture: 2.0 * c.lens_radius,
+                // rays are as long as the distance to focus, and pbrt's default would
+                // put every hit inside t_min
+                focus: if c.lens_radius > 0.0 {
+                    c.focal_distance
+                } else {
+                    1.0
+                },
+                movements: Movements::default(),
+                from_keys: None,
+                at_keys: None,
+                lens_keys: None,
+            },
+            shutter,
+            background: Arc::new(SolidColor(self.background)),
+            materials: self.materials,
+            objects: self.objects,
+            volumes: Vec::new(),
+            masks: Vec::new(),
+            textures: Vec::new(),
+            portals: Vec::new(),
+            sun: None,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    // a scene file in the temporary directory, named apart for each test
+    fn scene_file(name: &str, text: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("rest_of_life_pbrt_{}.pbrt", name));
+        fs::write(&path, text).unwrap();
+        path
+    }
+
+    fn read_text(name: &str, text: &str) -> Result<Description, String> {
+        let path = scene_file(name, text);
+        read(path.to_str().unwrap(), Shutter::default())
+    }
+
+    fn vertices(object: &Object) -> Vec<Vector3<Float>> {
+        match &object.shape {
+            Shape::Mesh(mesh) => mesh.vertices.clone(),
+            _ => panic!("not a mesh"),
+        }
+    }
+
+    fn sphere(object: &Object) -> (Vector3<Float>, Float) {
+        match object.shape {
+            Shape::Sphere { center, radius } => (center, radius),
+            _ => panic!("not a sphere"),
+        }
+    }
+
+    #[test]
+    fn lexing_skips_comments_and_splits_brackets_and_strings() {
+        let path = scene_file(
+            "lexing",
+            "# a comment \"with a quote\n\
+             Shape \"sphere\" \"float radius\" [2.5]# trailing\n\
+             \"rgb Kd\"[0.1 -2e-1 .3] Word\n",
+        );
+        let mut files = Vec::new();
+        let mut lexemes = Vec::new();
+        lex(&path, Path::new(""), &mut files, &mut lexemes, 0).unwrap();
+        let word = |w: &str| Token::Word(w.to_string());
+        let text = |t: &str| Token::Text(t.to_string());
+        let tokens = lexemes.iter().map(|l| l.token.clone()).collect::<Vec<_>>();
+        assert_eq!(
+            tokens,
+            [
+                word("Shape"),
+                text("sphere"),
+                text("float radius"),
+                Token::Open,
+                Token::Number(2.5),
+                Token::Close,
+                text("rgb Kd"),
+                Token::Open,
+                Token::Number(0.1),
+                Token::Number(-0.2),
+                Token::Number(0.3),
+                Token::Close,
+                word("Word"),
+            ]
+        );
+        let lines = lexemes.iter().map(|l| l.line).collect::<Vec<_>>();
+        assert_eq!(lines, [2, 2, 2, 2, 2, 2, 3, 3, 3, 3, 3, 3, 3]);
+        let path = scene_file("unterminated", "Shape \"sphere\n");
+        let message = lex(&path, Path::new(""), &mut files, &mut lexemes, 0).unwrap_err();
+        assert!(message.ends_with("line 1: unterminated string"));
+    }
+
+    #[test]
+    fn transforms_compose_in_order() {
+        let mesh = "Shape \"trianglemesh\" \"point P\" [1 0 0  0 0 1  0 0 2]\n";
+        let text = format!(
+            "LookAt 0 0 -5  0 0 0  0 1 0\n\
+             Camera \"perspective\"\n\
+             WorldBegin\n\
+             Translate 1 0 0\n\
+             Rotate 90 0 0 1\n\
+             {mesh}\
+             ConcatTransform [2 0 0 0  0 2 0 0  0 0 2 0  0 0 0 1]\n\
+             {mesh}",
+        );
+        let description = read_text("transforms", &text).unwrap();
+        // pbrt's world is mirrored in x
+        let first = vertices(&description.objects[0]);
+        assert!((first[0] - Vector3::new(-1.0, 1.0, 0.0)).norm() < 1e-5);
+        assert!((first[2] - Vector3::new(-1.0, 0.0, 2.0)).norm() < 1e-5);
+        let scaled = vertices(&description.objects[1]);
+        assert!((scaled[0] - Vector3::new(-1.0, 2.0, 0.0)).norm() < 1e-5);
+        assert!((scaled[2] - Vector3::new(-1.0, 0.0, 4.0)).norm() < 1e-5);
+        let camera = &description.camera;
+        assert!((camera.from - Vector3::new(0.0, 0.0, -5.0)).norm() < 1e-5);
+        assert!((camera.at - camera.from - Vector3::z()).norm() < 1e-5);
+        assert!((camera.up - Vector3::y()).norm() < 1e-5);
+        // a camera transform that mirrors already leaves the world as it is
+        let text = format!(
+            "Scale -1 1 1\nLookAt 0 0 -5  0 0 0  0 1 0\nCamera \"perspective\"\n\
+             WorldBegin\nTranslate 1 0 0\n{mesh}"
+        );
+        let description = read_text("mirrored", &text).unwrap();
+        let first = vertices(&description.objects[0]);
+        assert!((first[0] - Vector3::new(2.0, 0.0, 0.0)).norm() < 1e-5);
+    }
+
+    #[test]
+    fn attribute_blocks_scope_transforms_and_materials() {
+        let text = "Camera \"perspective\"\n\
+                    WorldBegin\n\
+                    AttributeBegin\n\
+                      Translate 0 3 0\n\
+                      Material \"matte\" \"rgb Kd\" [1 0 0]\n\
+                      Shape \"sphere\" \"float radius\" 2\n\
+                    AttributeEnd\n\
+                    Shape \"sphere\"\n";
+        let description = read_text("attributes", text).unwrap();
+        let (center, radius) = sphere(&description.objects[0]);
+        assert!((center - Vector3::new(0.0, 3.0, 0.0)).norm() < 1e-5);
+        assert_eq!(radius, 2.0);
+        assert!(matches!(
+            description.materials[description.objects[0].material],
+            MaterialDescription::Lambertian(kd) if kd == Vector3::new(1.0, 0.0, 0.0)
+        ));
+        let (center, radius) = sphere(&description.objects[1]);
+        assert_eq!((center, radius), (Vector3::zeros(), 1.0));
+        assert_eq!(description.objects[1].material, 0);
+        let unbalanced = "WorldBegin\nAttributeEnd\nShape \"sphere\"\n";
+        let message = read_text("unbalanced", unbalanced).err().unwrap();
+        assert!(message.ends_with("line 2: AttributeEnd without AttributeBegin"));
+    }
+
+    #[test]
+    fn trianglemesh_indices_must_name_its_points() {
+        let mesh = |name: &str, params: &str| {
+            read_text(
+                name,
+                &format!(
+                    "WorldBegin\nShape \"trianglemesh\" \"point P\" [0 0 0  1 0 0  0 1 0  1 1 0] {}\n",
+                    params
+                ),
+            )
+        };
+        let description = mesh("quad", "\"integer indices\" [0 1 2  2 1 3]").unwrap();
+        match &description.objects[0].shape {
+            Shape::Mesh(mesh) => assert_eq!(mesh.indices.len(), 2),
+            _ => panic!("not a mesh"),
+        }
+        for (name, params) in [
+            ("out_of_range", "\"integer indices\" [0 1 4]"),
+            ("negative", "\"integer indices\" [0 -1 2]"),
+            ("not_triples", "\"integer indices\" [0 1 2 3]"),
+            ("no_indices", ""),
+            (
+                "few_normals",
+                "\"integer indices\" [0 1 2] \"normal N\" [0 0 1]",
+            ),
+        ] {
+            assert!(mesh(name, params).is_err(), "{}", name);
+        }
+        // a lone triangle needs none
+        let triangle = "WorldBegin\nShape \"trianglemesh\" \"point P\" [0 0 0  1 0 0  0 1 0]\n";
+        assert!(read_text("triangle", triangle).is_ok());
+    }
+
+    #[test]
+    fn unsupported_directives_are_errors() {
+        for (name, text, error) in [
+            (
+                "cylinder",
+                "WorldBegin\nShape \"cylinder\"\n",
+                "unsupported shape cylinder",
+            ),
+            (
+                "orthographic",
+                "Camera \"orthographic\"\nWorldBegin\nShape \"sphere\"\n",
+                "unsupported camera orthographic",
+            ),
+            (
+                "empty_aspect",
+                "Camera \"perspective\" \"float frameaspectratio\" []\n",
+                "frameaspectratio must be one number",
+            ),
+            (
+                "spot",
+                "WorldBegin\nLightSource \"spot\"\n",
+                "only area lights and infinite lights without maps are supported",
+            ),
+            (
+                "directive",
+                "Nonsense 1 2\n",
+                "unsupported directive Nonsense",
+            ),
+            ("empty", "WorldBegin\n", "no shapes"),
+        ] {
+            let message = read_text(name, text).err().unwrap();
+            assert!(message.ends_with(error), "{}: {}", name, message);
+        }
+    }
+}
@@ -1,42 +1,57 @@
+use crate::float::Float;
+use crate::guide::Distribution;
 use crate::hittable::Hittable;
-use crate::onb::ONB;
+use crate::microfacet::GGX;
+use crate::phase::PhaseFunction;
+use crate::rng;
+use crate::sampling::{self, ONB};
 use nalgebra::Vector3;
-use rand::Rng;
-use std::f32;
-
-fn random_cosine_direction() -> Vector3<f32> {
-    let mut rng = rand::thread_rng();
-    let r1 = rng.gen::<f32>();
-    let r2 = rng.gen::<f32>();
-    let z = (1.0 - r2).sqrt();
-    let phi = 2.0 * f32::consts::PI * r1;
-    let x = phi.cos() * 2.0 * r2.sqrt();
-    let y = phi.sin() * 2.0 * r2.sqrt();
-    Vector3::new(x, y, z)
-}
 
 pub enum PDF<'a> {
     Cosine {
         uvw: ONB,
     },
+    Uniform,
     Hittable {
-        origin: Vector3<f32>,
-        hittable: &'a Box<dyn Hittable>,
+        origin: Vector3<Float>,
+        hittable: &'a dyn Hittable,
     },
     Mixture {
         p: &'a PDF<'a>,
         q: &'a PDF<'a>,
     },
+    Guided {
+        distribution: &'a Distribution,
+    },
+    Phase {
+        phase: &'a dyn PhaseFunction,
+        incoming: Vector3<Float>,
+    },
+    Blend {
+        p: &'a PDF<'a>,
+        q: &'a PDF<'a>,
+        weight: Float,
+    },
+    Microfacet {
+        uvw: ONB,
+        ggx: GGX,
+        outgoing: Vector3<Float>,
+    },
 }
 
 impl<'a> PDF<'a> {
-    pub fn cosine(w: Vector3<f32>) -> Self {
+    pub fn cosine(w: Vector3<Float>) -> Self {
         PDF::Cosine {
             uvw: ONB::build_from_w(&w),
         }
     }
 
-    pub fn hittable(hittable: &'a Box<dyn Hittable>, origin: Vector3<f32>) -> Self {
+    // every direction on the sphere alike
+    pub fn uniform() -> Self {
+        PDF::Uniform
+    }
+
+    pub fn hittable(hittable: &'a dyn Hittable, origin: Vector3<Float>) -> Self {
         PDF::Hittable { origin, hittable }
     }
 
@@ -44,33 +59,213 @@ impl<'a> PDF<'a> {
         PDF::Mixture { p, q }
     }
 
-    pub fn value(&self, direction: Vector3<f32>) -> f32 {
+    pub fn guided(distribution: &'a Distribution) -> Self {
+        PDF::Guided { distribution }
+    }
+
+    // a medium's phase function scattering a ray travelling along incoming
+    pub fn phase(phase: &'a dyn PhaseFunction, incoming: Vector3<Float>) -> Self {
+        PDF::Phase { phase, incoming }
+    }
+
+    // samples q with probability weight, p otherwise
+    pub fn blend(p: &'a PDF, q: &'a PDF, weight: Float) -> Self {
+        PDF::Blend { p, q, weight }
+    }
+
+    // GGX reflection of light leaving along `outgoing` in world space, in the frame
+    // with the surface normal as w and its tangent as u
+    pub fn microfacet(uvw: ONB, ggx: GGX, outgoing: Vector3<Float>) -> Self {
+        PDF::Microfacet {
+            outgoing: uvw.to_local(&outgoing.normalize()),
+            uvw,
+            ggx,
+        }
+    }
+
+    pub fn value(&self, direction: Vector3<Float>) -> Float {
         match self {
             PDF::Cosine { uvw } => {
                 let cosine = direction.normalize().dot(&uvw.w());
                 if cosine > 0.0 {
-                    cosine / f32::consts::PI
+                    sampling::cosine_hemisphere_pdf(cosine)
                 } else {
-                    1.0
+                    0.0
                 }
             }
+            PDF::Uniform => sampling::uniform_sphere_pdf(),
             PDF::Hittable { origin, hittable } => hittable.pdf_value(*origin, direction),
             PDF::Mixture { p, q } => 0.5 * p.value(direction) + 0.5 * q.value(direction),
+            PDF::Guided { distribution } => distribution.value(direction),
+            PDF::Phase { phase, incoming } => phase.value(incoming, &direction),
+            PDF::Blend { p, q, weight } => {
+                (1.0 - weight) * p.value(direction) + weight * q.value(direction)
+            }
+            PDF::Microfacet { uvw, ggx, outgoing } => {
+                ggx.pdf(outgoing, &uvw.to_local(&direction.normalize()))
+            }
         }
     }
 
-    pub fn generate(&self) -> Vector3<f32> {
+    pub fn generate(&self) -> Vector3<Float> {
         match self {
-            PDF::Cosine { uvw } => uvw.local(&random_cosine_direction()),
+            PDF::Cosine { uvw } => {
+                uvw.local(&sampling::cosine_hemisphere(rng::uniform(), rng::uniform()))
+            }
+            PDF::Uniform => sampling::uniform_sphere(rng::uniform(), rng::uniform()),
             PDF::Hittable { origin, hittable } => hittable.random(*origin),
             PDF::Mixture { p, q } => {
-                let mut rng = rand::thread_rng();
-                if rng.gen::<bool>() {
+                if rng::uniform() < 0.5 {
                     p.generate()
                 } else {
                     q.generate()
                 }
             }
+            PDF::Guided { distribution } => distribution.generate(),
+            PDF::Phase { phase, incoming } => phase.sample(incoming),
+            PDF::Blend { p, q, weight } => {
+                if rng::uniform() < *weight {
+                    q.generate()
+                } else {
+                    p.generate()
+                }
+            }
+            PDF::Microfacet { uvw, ggx, outgoing } => {
+                uvw.local(&ggx.sample(outgoing, rng::uniform(), rng::uniform()))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::float;
+    use crate::material::DiffuseLight;
+    use crate::phase::HenyeyGreenstein;
+    use crate::rect::{AARect, Plane};
+    use crate::sphere::Sphere;
+    use crate::texture::ConstantTexture;
+
+    const N: usize = 100_000;
+    // the sphere of directions cut into cells of equal solid angle, alike in cos
+    // theta and phi, each integrated over a finer grid
+    const Z_BINS: usize = 10;
+    const PHI_BINS: usize = 20;
+    const SUBDIVISIONS: usize = 32;
+    // cells expecting fewer samples are pooled, as the statistic needs a few in each
+    const MIN_EXPECTED: f64 = 5.0;
+    // standard normal quantile of the significance, 1e-4
+    const Z_SIGNIFICANCE: f64 = 3.719;
+
+    fn light() -> DiffuseLight<ConstantTexture> {
+        DiffuseLight::new(ConstantTexture::new(1.0, 1.0, 1.0))
+    }
+
+    fn cell(d: &Vector3<Float>) -> usize {
+        let d = d.normalize();
+        let z = (((d.z + 1.0) * 0.5 * Z_BINS as Float) as usize).min(Z_BINS - 1);
+        let phi = d.y.atan2(d.x).rem_euclid(2.0 * float::consts::PI);
+        let phi =
+            ((phi / (2.0 * float::consts::PI) * PHI_BINS as Float) as usize).min(PHI_BINS - 1);
+        z * PHI_BINS + phi
+    }
+
+    // N times the integral of the pdf over each cell, by the midpoint rule
+    fn expected(pdf: &PDF) -> Vec<f64> {
+        let n = SUBDIVISIONS;
+        let dz = 2.0 / (Z_BINS * n) as Float;
+        let dphi = 2.0 * float::consts::PI / (PHI_BINS * n) as Float;
+        let mut counts = vec![0.0; Z_BINS * PHI_BINS];
+        for i in 0..Z_BINS * n {
+            let z = -1.0 + (i as Float + 0.5) * dz;
+            let r = (1.0 - z * z).sqrt();
+            for j in 0..PHI_BINS * n {
+                let phi = (j as Float + 0.5) * dphi;
+                let value = pdf.value(Vector3::new(r * phi.cos(), r * phi.sin(), z));
+                counts[(i / n) * PHI_BINS + j / n] += float::double(value * dz * dphi);
+            }
+        }
+        counts.iter().map(|c| c * N as f64).collect()
+    }
+
+    // Pearson's chi-squared test of the directions pdf generates against the counts
+    // its value predicts, so sampling and density that disagree fail it.
+    fn chi_squared(pdf: &PDF) {
+        rng::seed(Default::default(), 7);
+        let mut observed = vec![0.0; Z_BINS * PHI_BINS];
+        for _ in 0..N {
+            observed[cell(&pdf.generate())] += 1.0;
+        }
+        let expected = expected(pdf);
+        let total: f64 = expected.iter().sum();
+        assert!(
+            (total - N as f64).abs() < 0.01 * N as f64,
+            "pdf integrates to {}",
+            total / N as f64
+        );
+        let mut cells: Vec<(f64, f64)> = expected.into_iter().zip(observed).collect();
+        cells.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap());
+        let mut statistic = 0.0;
+        let mut dof = 0;
+        let (mut pooled_expected, mut pooled_observed) = (0.0, 0.0);
+        for (expected, observed) in cells {
+            if expected == 0.0 {
+                assert_eq!(observed, 0.0, "samples where the pdf is zero");
+            } else if expected < MIN_EXPECTED {
+                pooled_expected += expected;
+                pooled_observed += observed;
+            } else {
+                statistic += (observed - expected).powi(2) / expected;
+                dof += 1;
+            }
+        }
+        if pooled_expected > 0.0 {
+            statistic += (pooled_observed - pooled_expected).powi(2) / pooled_expected;
+            dof += 1;
+        }
+        let dof = (dof - 1) as f64;
+        // the critical value by the Wilson-Hilferty approximation
+        let a = 2.0 / (9.0 * dof);
+        let critical = dof * (1.0 - a + Z_SIGNIFICANCE * a.sqrt()).powi(3);
+        assert!(
+            statistic < critical,
+            "chi-squared {} over {} degrees of freedom, critical {}",
+            statistic,
+            dof,
+            critical
+        );
+    }
+
+    #[test]
+    fn cosine_matches_its_value() {
+        chi_squared(&PDF::cosine(Vector3::new(0.0, 0.0, 1.0)));
+        chi_squared(&PDF::cosine(Vector3::new(0.3, -0.8, 0.2)));
+        chi_squared(&PDF::uniform());
+    }
+
+    #[test]
+    fn hittable_matches_its_value() {
+        let sphere = Sphere::new(Vector3::new(1.0, 2.0, 0.5), 1.2, light());
+        chi_squared(&PDF::hittable(&sphere, Vector3::zeros()));
+        let rect = AARect::new(Plane::ZX, -0.5, 1.5, -1.0, 0.5, 1.0, light());
+        chi_squared(&PDF::hittable(&rect, Vector3::zeros()));
+    }
+
+    #[test]
+    fn mixtures_match_their_value() {
+        let sphere = Sphere::new(Vector3::new(-1.0, 0.5, 2.0), 0.8, light());
+        let cosine = PDF::cosine(Vector3::new(0.0, 0.0, 1.0));
+        let to_sphere = PDF::hittable(&sphere, Vector3::zeros());
+        chi_squared(&PDF::mixture(&cosine, &to_sphere));
+        chi_squared(&PDF::blend(&cosine, &to_sphere, 0.2));
+    }
+
+    #[test]
+    fn phase_matches_its_value() {
+        for g in [-0.5, 0.0, 0.7] {
+            let phase = HenyeyGreenstein::new(g);
+            chi_squared(&PDF::phase(&phase, Vector3::new(0.6, 0.0, -0.8)));
         }
     }
 }
@@ -0,0 +1,172 @@
+use crate::float::Float;
+use crate::rng;
+use nalgebra::Vector3;
+use rand::Rng;
+
+fn perlin_generate() -> Vec<Vector3<Float>> {
+    let mut p = Vec::with_capacity(256);
+    for _ in 0..256 {
+        p.push(
+            Vector3::new(
+                -1.0 + 2.0 * rng::uniform(),
+                -1.0 + 2.0 * rng::uniform(),
+                -1.0 + 2.0 * rng::uniform(),
+            )
+            .normalize(),
+        );
+    }
+    p
+}
+
+fn permute(p: &mut [usize], n: usize) {
+    for i in (0..n).rev() {
+        let target = rng::with(|rng| rng.gen_range(0..=i));
+        p.swap(i, target);
+    }
+}
+
+fn perlin_generate_perm() -> Vec<usize> {
+    let mut p = Vec::with_capacity(256);
+    for i in 0..256 {
+        p.push(i);
+    }
+    permute(&mut p, 256);
+    p
+}
+
+fn perlin_interp(c: &[[[Vector3<Float>; 2]; 2]; 2], u: Float, v: Float, w: Float) -> Float {
+    let uu = u * u * (3.0 - 2.0 * u);
+    let vv = v * v * (3.0 - 2.0 * v);
+    let ww = w * w * (3.0 - 2.0 * w);
+    let mut accum = 0.0;
+    for (i, plane) in c.iter().enumerate() {
+        for (j, row) in plane.iter().enumerate() {
+            for (k, corner) in row.iter().enumerate() {
+                let weight = Vector3::new(u - i as Float, v - j as Float, w - k as Float);
+                accum += (i as Float * uu + (1 - i) as Float * (1.0 - uu))
+                    * (j as Float * vv + (1 - j) as Float * (1.0 - vv))
+                    * (k as Float * ww + (1 - k) as Float * (1.0 - ww))
+                    * corner.dot(&weight);
+            }
+        }
+    }
+    accum
+}
+
+// How a fractal sums a noise: `octaves` layers, each `lacunarity` times the
+// frequency of the one before and `gain` times its amplitude.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Octaves {
+    pub octaves: usize,
+    pub lacunarity: Float,
+    pub gain: Float,
+}
+
+impl Default for Octaves {
+    fn default() -> Self {
+        Octaves {
+            octaves: 6,
+            lacunarity: 2.0,
+            gain: 0.5,
+        }
+    }
+}
+
+impl Octaves {
+    // the sum of `layer` at each octave's frequency weighted by its amplitude, over
+    // the amplitudes' sum, so it keeps the range of a single layer
+    pub fn sum(
+        &self,
+        p: &Vector3<Float>,
+        mut layer: impl FnMut(&Vector3<Float>) -> Float,
+    ) -> Float {
+        let (mut total, mut weights) = (0.0, 0.0);
+        let (mut frequency, mut amplitude) = (1.0, 1.0);
+        for _ in 0..self.octaves.max(1) {
+            total += amplitude * layer(&(p * frequency));
+            weights += amplitude;
+            frequency *= self.lacunarity;
+            amplitude *= self.gain;
+        }
+        if weights > 0.0 {
+            total / weights
+        } else {
+            0.0
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct Perlin {
+    ran_vec: Vec<Vector3<Float>>,
+    perm_x: Vec<usize>,
+    perm_y: Vec<usize>,
+    perm_z: Vec<usize>,
+}
+
+impl Default for Perlin {
+    fn default() -> Self {
+        Perlin::new()
+    }
+}
+
+impl Perlin {
+    pub fn new() -> Self {
+        Perlin {
+            ran_vec: perlin_generate(),
+            perm_x: perlin_generate_perm(),
+            perm_y: perlin_generate_perm(),
+            perm_z: perlin_generate_perm(),
+        }
+    }
+
+    // smooth noise, zero at every integer point and within about -1 to 1
+    pub fn noise(&self, p: &Vector3<Float>) -> Float {
+        let u = p.x - Float::floor(p.x);
+        let v = p.y - Float::floor(p.y);
+        let w = p.z - Float::floor(p.z);
+        let i = Float::floor(p.x) as i32;
+        let j = Float::floor(p.y) as i32;
+        let k = Float::floor(p.z) as i32;
+        let mut c = [[[Vector3::new(0.0, 0.0, 0.0); 2]; 2]; 2];
+        for (di, plane) in c.iter_mut().enumerate() {
+            for (dj, row) in plane.iter_mut().enumerate() {
+                for (dk, corner) in row.iter_mut().enumerate() {
+                    *corner = self.ran_vec[self.perm_x[((i + di as i32) & 255) as usize]
+                        ^ self.perm_y[((j + dj as i32) & 255) as usize]
+                        ^ self.perm_z[((k + dk as i32) & 255) as usize]]
+                }
+            }
+        }
+        perlin_interp(&c, u, v, w)
+    }
+
+    pub fn turb(&self, p: &Vector3<Float>, depth: usize) -> Float {
+        let mut accum = 0.0;
+        let mut temp_p = *p;
+        let mut weight = 1.0;
+        for _ in 0..depth {
+            accum += weight * self.noise(&temp_p);
+            weight *= 0.5;
+            temp_p *= 2.0;
+        }
+        Float::abs(accum)
+    }
+
+    // fractal Brownian motion: octaves of noise summed, soft hills and clouds
+    pub fn fbm(&self, p: &Vector3<Float>, octaves: &Octaves) -> Float {
+        octaves.sum(p, |p| self.noise(p))
+    }
+
+    // Musgrave's ridged multifractal, from 0 to 1: each octave folds the noise
+    // about zero into sharp crests, and is weighted by how high the octave below it
+    // reached, so valleys stay smooth while ridges gather detail, as mountains do
+    pub fn ridged(&self, p: &Vector3<Float>, octaves: &Octaves) -> Float {
+        let mut weight = 1.0;
+        octaves.sum(p, |p| {
+            let signal = (1.0 - self.noise(p).abs()).powi(2) * weight;
+            weight = (2.0 * signal).clamp(0.0, 1.0);
+            signal
+        })
+    }
+}
@@ -0,0 +1,72 @@
+use crate::float::{self, Float};
+use crate::rng;
+use crate::sampling::{self, ONB};
+use nalgebra::Vector3;
+
+// How a participating medium redistributes the light it scatters: the density over
+// solid angle of a ray travelling along `incoming` leaving along `scattered`, and a
+// direction drawn from that density. Phase functions integrate to one, so the
+// density is also the pdf of their sampling.
+pub trait PhaseFunction: Send + Sync {
+    fn value(&self, incoming: &Vector3<Float>, scattered: &Vector3<Float>) -> Float;
+    fn sample(&self, incoming: &Vector3<Float>) -> Vector3<Float>;
+}
+
+// scatters alike in every direction
+#[derive(Clone, Copy, Debug, Default)]
+pub struct IsotropicPhase;
+
+impl PhaseFunction for IsotropicPhase {
+    fn value(&self, _incoming: &Vector3<Float>, _scattered: &Vector3<Float>) -> Float {
+        sampling::uniform_sphere_pdf()
+    }
+
+    fn sample(&self, _incoming: &Vector3<Float>) -> Vector3<Float> {
+        sampling::uniform_sphere(rng::uniform(), rng::uniform())
+    }
+}
+
+// Henyey-Greenstein phase function at the cosine of the scattering angle
+pub fn henyey_greenstein(cos_theta: Float, g: Float) -> Float {
+    let denom = 1.0 + g * g - 2.0 * g * cos_theta;
+    (1.0 - g * g) / (4.0 * float::consts::PI * denom * denom.sqrt())
+}
+
+// Henyey-Greenstein scattering with asymmetry g in (-1, 1), the mean cosine of the
+// scattering angle: positive g scatters forward as fog and haze do, negative g
+// back, and 0 is isotropic.
+#[derive(Clone, Copy, Debug)]
+pub struct HenyeyGreenstein {
+    g: Float,
+}
+
+impl HenyeyGreenstein {
+    pub fn new(g: Float) -> Self {
+        assert!(g > -1.0 && g < 1.0, "asymmetry must be in (-1, 1)");
+        HenyeyGreenstein { g }
+    }
+}
+
+impl PhaseFunction for HenyeyGreenstein {
+    fn value(&self, incoming: &Vector3<Float>, scattered: &Vector3<Float>) -> Float {
+        let cos_theta = incoming.normalize().dot(&scattered.normalize());
+        henyey_greenstein(cos_theta, self.g)
+    }
+
+    fn sample(&self, incoming: &Vector3<Float>) -> Vector3<Float> {
+        let g = self.g;
+        // the inversion below loses precision as g vanishes
+        if g.abs() < 1e-3 {
+            return IsotropicPhase.sample(incoming);
+        }
+        let square = (1.0 - g * g) / (1.0 - g + 2.0 * g * rng::uniform());
+        let cos_theta = ((1.0 + g * g - square * square) / (2.0 * g)).clamp(-1.0, 1.0);
+        let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+        let phi = 2.0 * float::consts::PI * rng::uniform();
+        ONB::build_from_w(incoming).local(&Vector3::new(
+            sin_theta * phi.cos(),
+            sin_theta * phi.sin(),
+            cos_theta,
+        ))
+    }
+}
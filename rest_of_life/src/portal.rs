@@ -0,0 +1,25 @@
+use crate::float::Float;
+use crate::material::Material;
+use crate::rect::{AARect, Plane};
+
+// Light portals: rectangles over the openings an environment lights an interior
+// through, its windows and doors. A room lit only by its background is found by
+// bsdf sampling alone, one ray in many leaving through a window, so it converges
+// slowly. A portal is not part of the world, rays pass straight through where it
+// is, but it goes among the scene's light shapes: diffuse bounces send part of
+// their rays toward it as they do toward a lamp, mixed with the bsdf's by the
+// usual pdf, and those that leave the scene through it see the background. Rays
+// that hit something on the way, a window frame or a tree outside, count what
+// they hit, so a portal changes only the noise and never the result, and one
+// that misses part of an opening only leaves that part to the bsdf.
+pub type Portal = AARect<Opening>;
+
+// the material of a portal, never asked for anything as rays do not hit one
+#[derive(Clone, Copy)]
+pub struct Opening;
+
+impl Material for Opening {}
+
+pub fn portal(plane: Plane, a0: Float, a1: Float, b0: Float, b1: Float, k: Float) -> Portal {
+    AARect::new(plane, a0, a1, b0, b1, k, Opening)
+}
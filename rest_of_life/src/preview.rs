@@ -0,0 +1,214 @@
+use crate::cli::Options;
+use crate::render::RenderSettings;
+use crate::scene::Scene;
+
+// Live preview: a window shows the image as one sample per pixel passes accumulate,
+// up to the render's spp, so a shot can be framed before the final render.
+//
+// W, A, S and D move the camera, Q and E lower and raise it, the arrows or a right
+// drag look around, a left drag orbits the point in focus, the wheel dollies and
+// shift moves faster. R puts the camera back. Any move starts the passes over.
+// + and - change the exposure by half a stop, P saves what is shown as
+// preview_<passes>.ppm and Escape closes the window. The window needs the preview
+// feature.
+//
+// A scene file is watched while the window is open: saving it reads it again,
+// rebuilds the world and its BVH and starts the passes over. A file that does not
+// parse leaves the last good scene up and says why. The camera stays where it was
+// flown to, or takes the file's if it has not been moved. Files the scene includes
+// are not watched.
+#[cfg(feature = "preview")]
+pub fn run(scene: &mut Scene, settings: &RenderSettings, options: &Options) -> Result<(), String> {
+    use crate::float::Float;
+    use crate::metadata::Metadata;
+    use crate::render::{self, Image, OutputFormat};
+    use crate::scenefile;
+    use minifb::{Key, KeyRepeat, MouseButton, MouseMode, Window, WindowOptions};
+    use nalgebra::Vector3;
+    use notify::{EventKind, RecursiveMode, Watcher};
+    use std::path::Path;
+    use std::sync::mpsc;
+
+    // radians per pixel dragged and per frame an arrow is held
+    const DRAG_TURN: Float = 0.005;
+    const KEY_TURN: Float = 0.03;
+
+    let (width, height) = (settings.width, settings.height);
+    let mut window = Window::new("rest_of_life", width, height, WindowOptions::default())
+        .map_err(|e| format!("cannot open the preview window: {}", e))?;
+    window.set_target_fps(60);
+
+    // the scene file's directory is watched rather than the file, as editors that
+    // save by writing a new file and renaming it over the old one would otherwise
+    // leave the watch on a file that is gone
+    let (sender, changes) = mpsc::channel();
+    let mut watched = None;
+    if let Some(path) = &options.scene_file {
+        let file = Path::new(path)
+            .canonicalize()
+            .map_err(|e| format!("cannot watch {}: {}", path, e))?;
+        let mut watcher = notify::recommended_watcher(sender)
+            .map_err(|e| format!("cannot watch {}: {}", path, e))?;
+        let directory = file.parent().unwrap_or(Path::new("/"));
+        watcher
+            .watch(directory, RecursiveMode::NonRecursive)
+            .map_err(|e| format!("cannot watch {}: {}", path, e))?;
+        watched = Some((watcher, file));
+    }
+    let aspect = width as Float / height as Float;
+
+    let mut home = scene.camera.clone();
+    let mut flown = false;
+    let mut tone = options.tone.clone();
+    let mut sums = vec![Vector3::zeros(); width * height];
+    let mut passes = 0;
+    let mut buffer = vec![0u32; width * height];
+    let mut last_mouse: Option<(Float, Float)> = None;
+    while window.is_open() && !window.is_key_down(Key::Escape) {
+        // camera controls, moving a step that scales with the distance to focus
+        let (origin, right, _, forward) = scene.camera.frame();
+        let focus = scene.camera.focus_point();
+        let fast = window.is_key_down(Key::LeftShift) || window.is_key_down(Key::RightShift);
+        let step = (focus - origin).norm() * if fast { 0.05 } else { 0.01 };
+        let up = Vector3::new(0.0, 1.0, 0.0);
+        let mut offset = Vector3::zeros();
+        let (mut yaw, mut pitch) = (0.0, 0.0);
+        for key in window.get_keys() {
+            match key {
+                Key::W => offset += step * forward,
+                Key::S => offset -= step * forward,
+                Key::D => offset += step * right,
+                Key::A => offset -= step * right,
+                Key::E => offset += step * up,
+                Key::Q => offset -= step * up,
+                Key::Left => yaw += KEY_TURN,
+                Key::Right => yaw -= KEY_TURN,
+                Key::Up => pitch += KEY_TURN,
+                Key::Down => pitch -= KEY_TURN,
+                _ => {}
+            }
+        }
+        if let Some((_, wheel)) = window.get_scroll_wheel() {
+            offset += wheel.signum() * 5.0 * step * forward;
+        }
+        let mouse = window.get_mouse_pos(MouseMode::Pass);
+        let left = window.get_mouse_down(MouseButton::Left);
+        let right_drag = window.get_mouse_down(MouseButton::Right);
+        let (mut orbit_yaw, mut orbit_pitch) = (0.0, 0.0);
+        if let (Some((x0, y0)), Some((x, y))) = (last_mouse, mouse) {
+            let (dx, dy) = ((x - x0) * DRAG_TURN, (y - y0) * DRAG_TURN);
+            if left {
+                orbit_yaw -= dx;
+                orbit_pitch -= dy;
+            } else if right_drag {
+                yaw += dx;
+                pitch += dy;
+            }
+        }
+        last_mouse = if left || right_drag { mouse } else { None };
+
+        let mut moved = false;
+        if orbit_yaw != 0.0 || orbit_pitch != 0.0 {
+            scene.camera = scene
+                .camera
+                .turned(&focus, orbit_yaw, orbit_pitch, &Vector3::zeros());
+            moved = true;
+        }
+        if yaw != 0.0 || pitch != 0.0 || offset != Vector3::zeros() {
+            scene.camera = scene.camera.turned(&origin, yaw, pitch, &offset);
+            moved = true;
+        }
+        flown |= moved;
+        if window.is_key_pressed(Key::R, KeyRepeat::No) {
+            scene.camera = home.clone();
+            flown = false;
+            moved = true;
+        }
+
+        // a save often comes as several events; all those waiting are taken so that
+        // it reloads once
+        let saved = changes.try_iter().fold(false, |saved, event| {
+            saved
+                | match (event, &watched) {
+                    (Ok(event), Some((_, file))) => {
+                        matches!(event.kind, EventKind::Create(_) | EventKind::Modify(_))
+                            && event.paths.iter().any(|path| path == file)
+                    }
+                    _ => false,
+                }
+        });
+        if let (true, Some(path)) = (saved, &options.scene_file) {
+            match scenefile::load(path, aspect, options) {
+                Ok(mut reloaded) => {
+                    home = reloaded.camera.clone();
+                    if flown {
+                        reloaded.camera = scene.camera.clone();
+                    }
+                    *scene = reloaded;
+                    moved = true;
+                    eprintln!("reloaded {}", path);
+                }
+                Err(message) => eprintln!("{}", message),
+            }
+        }
+        if moved {
+            sums.iter_mut().for_each(|sum| *sum = Vector3::zeros());
+            passes = 0;
+        }
+
+        if passes < settings.spp {
+            let pass = render::sample_pass(scene, settings, passes);
+            for (sum, c) in sums.iter_mut().zip(pass) {
+                *sum += c;
+            }
+            passes += 1;
+        }
+        let image = Image {
+            width,
+            height,
+            pixels: sums.iter().map(|s| s / passes.max(1) as Float).collect(),
+        };
+        for key in window.get_keys_pressed(KeyRepeat::Yes) {
+            match key {
+                Key::Equal | Key::NumPadPlus => tone.exposure += 0.5,
+                Key::Minus | Key::NumPadMinus => tone.exposure -= 0.5,
+                Key::P => {
+                    let path = format!("preview_{}.ppm", passes);
+                    // the camera where it was flown to
+                    let metadata = Metadata::new(options, passes, Some(&scene.camera));
+                    std::fs::write(
+                        &path,
+                        image.encode(OutputFormat::P6, &tone, passes, &metadata),
+                    )
+                    .map_err(|e| format!("cannot write {}: {}", path, e))?;
+                    eprintln!("saved {}", path);
+                }
+                _ => {}
+            }
+        }
+        for (pixel, c) in buffer.iter_mut().zip(image.pixels.iter()) {
+            let c = tone.display(c);
+            let [r, g, b] = [c.x, c.y, c.z].map(|v| (255.99 * v) as u32);
+            *pixel = (r << 16) | (g << 8) | b;
+        }
+        window.set_title(&format!(
+            "rest_of_life - {}/{} spp, exposure {:+.1}",
+            passes, settings.spp, tone.exposure
+        ));
+        window
+            .update_with_buffer(&buffer, width, height)
+            .map_err(|e| format!("cannot update the preview window: {}", e))?;
+    }
+    Ok(())
+}
+
+#[cfg(not(feature = "preview"))]
+pub fn run(
+    _scene: &mut Scene,
+    _settings: &RenderSettings,
+    _options: &Options,
+) -> Result<(), String> {
+    Err(String::from(
+        "the preview window needs rest_of_life built with --features preview",
+    ))
+}
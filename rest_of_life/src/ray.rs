@@ -1,26 +1,210 @@
+use crate::float::Float;
 use nalgebra::Vector3;
 
+// The nearest a hit may be along a ray. Rays leaving a surface start from
+// offset_origin, off the surface by more than the rounding in where it was hit,
+// so they need no margin of their own to miss it; this only keeps out hits behind
+// the origin, and so a fixed distance no longer hides the surfaces of a tiny scene
+// nor lets a huge one's catch the rays leaving them.
+pub const T_MIN: Float = 0.0;
+
+// Where a ray leaving the surface at `p` along `direction` starts: moved along the
+// surface's normal to the side the ray leaves by, far enough that the error in p
+// cannot put it back behind the surface. Each coordinate is moved a fixed number
+// of its last places, so the move grows with the coordinate as the error does;
+// near the world's origin, where those places shrink toward nothing, it moves a
+// small fixed distance instead. After Wächter and Binder, "A Fast and Robust
+// Method for Avoiding Self-Intersection", Ray Tracing Gems, 2019, whose constants
+// are for f32: the fixed distance is 128 of the scalar's epsilons, 1/65536 in f32,
+// so it shrinks with the error under the f64 feature, as the last places do.
+pub fn offset_origin(
+    p: Vector3<Float>,
+    normal: Vector3<Float>,
+    direction: Vector3<Float>,
+) -> Vector3<Float> {
+    const ORIGIN: Float = 1.0 / 32.0;
+    const FLOAT_SCALE: Float = 128.0 * Float::EPSILON;
+    const INT_SCALE: Float = 256.0;
+    let n = if normal.dot(&direction) < 0.0 {
+        -normal
+    } else {
+        normal
+    };
+    p.zip_map(&n, |p, n| {
+        if p.abs() < ORIGIN {
+            p + FLOAT_SCALE * n
+        } else {
+            // the bits of a float count its last places, in the direction of its sign
+            let places = (INT_SCALE * n) as i64;
+            let places = if p < 0.0 { -places } else { places };
+            Float::from_bits((p.to_bits() as i64 + places) as _)
+        }
+    })
+}
+
+// How the rays through the neighbouring pixels, one to the right and one up, leave
+// from and point away from this one's: the offsets of their origins and
+// directions, the directions on the scale of this ray's own. Texture lookups
+// filter over the patch they span where they meet the surface.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Differentials {
+    pub dx_origin: Vector3<Float>,
+    pub dx_direction: Vector3<Float>,
+    pub dy_origin: Vector3<Float>,
+    pub dy_direction: Vector3<Float>,
+}
+
 pub struct Ray {
-    a: Vector3<f32>,
-    b: Vector3<f32>,
-    time: f32,
+    a: Vector3<Float>,
+    b: Vector3<Float>,
+    time: Float,
+    // nanometres, for the path a spectral render traces at one wavelength
+    wavelength: Option<Float>,
+    // camera rays and their specular bounces carry them, other rays do not; boxed
+    // to keep the ray small on the stack of a deep recursive path
+    differentials: Option<Box<Differentials>>,
+    // rays after the first diffuse bounce carry one, see continued
+    cone: Option<RayCone>,
 }
 
 impl Ray {
-    pub fn new(a: Vector3<f32>, b: Vector3<f32>, time: f32) -> Self {
-        Ray { a, b, time }
+    pub fn new(a: Vector3<Float>, b: Vector3<Float>, time: Float) -> Self {
+        Ray {
+            a,
+            b,
+            time,
+            wavelength: None,
+            differentials: None,
+            cone: None,
+        }
+    }
+
+    // the ray carrying the given wavelength
+    pub fn with_wavelength(self, wavelength: Option<Float>) -> Self {
+        Ray { wavelength, ..self }
     }
 
-    pub fn origin(&self) -> Vector3<f32> {
+    // the ray carrying the given differentials
+    pub fn with_differentials(self, differentials: Option<Differentials>) -> Self {
+        Ray {
+            differentials: differentials.map(Box::new),
+            ..self
+        }
+    }
+
+    // the ray carrying the given cone
+    pub fn with_cone(self, cone: Option<RayCone>) -> Self {
+        Ray { cone, ..self }
+    }
+
+    pub fn origin(&self) -> Vector3<Float> {
         self.a
     }
-    pub fn direction(&self) -> Vector3<f32> {
+    pub fn direction(&self) -> Vector3<Float> {
         self.b
     }
-    pub fn time(&self) -> f32 {
+    pub fn time(&self) -> Float {
         self.time
     }
-    pub fn point_at_parameter(&self, t: f32) -> Vector3<f32> {
+    pub fn wavelength(&self) -> Option<Float> {
+        self.wavelength
+    }
+    pub fn differentials(&self) -> Option<Differentials> {
+        self.differentials.as_deref().copied()
+    }
+    pub fn cone(&self) -> Option<RayCone> {
+        self.cone
+    }
+    pub fn point_at_parameter(&self, t: Float) -> Vector3<Float> {
         self.a + t * self.b
     }
+
+    // The cone a ray leaving this one's hit at t goes on in, widened by the spread of
+    // the lobe it leaves by, PI/2 for a diffuse bounce and 0 for a specular one. The
+    // first diffuse bounce starts one, spreading from the pixel's angle the
+    // differentials give; specular bounces before it keep to the differentials.
+    pub fn continued(&self, t: Float, lobe_spread: Float) -> Option<RayCone> {
+        let distance = t * self.b.norm();
+        match self.cone {
+            Some(cone) => Some(cone.scatter(distance, lobe_spread)),
+            None if lobe_spread > 0.0 => {
+                let pixel_spread = self.differentials().map_or(0.0, |d| {
+                    d.dx_direction.norm().max(d.dy_direction.norm()) / self.b.norm()
+                });
+                Some(RayCone::primary(pixel_spread).scatter(distance, lobe_spread))
+            }
+            None => None,
+        }
+    }
+}
+
+// Tracks the width of the cone of directions a ray represents, so lookups along
+// secondary rays can use coarser mip levels.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RayCone {
+    width: Float,
+    spread: Float,
+}
+
+impl RayCone {
+    // pixel_spread is the angle subtended by a single pixel
+    pub fn primary(pixel_spread: Float) -> Self {
+        RayCone {
+            width: 0.0,
+            spread: pixel_spread,
+        }
+    }
+
+    // the width across the cone a distance along it
+    pub fn footprint(&self, distance: Float) -> Float {
+        self.width + self.spread * distance
+    }
+
+    // continues the cone from a scattering event at the distance, widening it by the
+    // angular spread of the scattering lobe (PI/2 for a diffuse bounce)
+    pub fn scatter(&self, distance: Float, lobe_spread: Float) -> Self {
+        RayCone {
+            width: self.footprint(distance),
+            spread: self.spread + lobe_spread,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hittable::{Hittable, Surfaces};
+    use crate::material::Lambertian;
+    use crate::sphere::Sphere;
+    use crate::texture::ConstantTexture;
+
+    #[test]
+    fn rays_leaving_a_distant_sphere_miss_it() {
+        let grey = Lambertian::new(ConstantTexture::new(0.5, 0.5, 0.5));
+        let center = Vector3::new(12000.0, -3000.0, 7000.0);
+        let sphere = Sphere::new(center, 250.0, grey);
+        for i in 0..200 {
+            let target = center + 240.0 * Vector3::new((i as Float).sin(), 0.2, (i as Float).cos());
+            let ray = Ray::new(Vector3::zeros(), target, 0.0);
+            let hit = sphere.hit(&ray, T_MIN, Float::MAX).unwrap();
+            // back the way it came, and on along the surface, neither finds it again
+            let back = hit.normal;
+            let along = hit.normal.cross(&Vector3::y()).normalize() + 1e-4 * hit.normal;
+            for direction in [back, along] {
+                let leaving = hit.spawn(direction, 0.0);
+                assert!((leaving.origin() - center).norm() > 250.0);
+                assert!(sphere.hit(&leaving, T_MIN, Float::MAX).is_none());
+            }
+            // looking past it finds the far side once, near its t along the ray; the
+            // ray it passes on by starts a few last places of 12000 off this one
+            let mut surfaces = Surfaces::new(&ray);
+            let near = surfaces.next(&sphere, T_MIN, Float::MAX).unwrap();
+            let far = surfaces.next(&sphere, T_MIN, Float::MAX).unwrap();
+            assert_eq!(near.t, hit.t);
+            assert!(far.t > near.t);
+            assert!(((far.p - center).norm() - 250.0).abs() < 0.1);
+            assert!((ray.point_at_parameter(far.t) - far.p).norm() < 1.0);
+            assert!(surfaces.next(&sphere, T_MIN, Float::MAX).is_none());
+        }
+    }
 }
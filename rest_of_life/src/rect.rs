@@ -1,10 +1,11 @@
 use crate::aabb::AABB;
+use crate::cone::Cone;
+use crate::float::Float;
 use crate::hittable::{HitRecord, Hittable};
 use crate::material::Material;
-use crate::ray::Ray;
-use nalgebra::Vector3;
-use rand::Rng;
-use std::f32;
+use crate::ray::{self, Ray};
+use crate::rng;
+use nalgebra::{Matrix2, Vector3};
 
 #[derive(Clone)]
 pub enum Plane {
@@ -13,18 +14,89 @@ pub enum Plane {
     XY,
 }
 
+// Where a rectangle's face lands on a texture. Across the face u runs 0 to 1 along
+// its a axis and v along its b axis; they are turned `turns` quarter turns
+// anticlockwise about the face's centre, then mirrored in u if `mirror`, repeated
+// `tiles` times each way and fitted into the part of the texture from `offset`
+// that is `size` across, as an atlas packs several faces into one image.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct FaceUv {
+    pub turns: u8,
+    pub mirror: bool,
+    pub tiles: (Float, Float),
+    pub offset: (Float, Float),
+    pub size: (Float, Float),
+}
+
+impl Default for FaceUv {
+    fn default() -> Self {
+        FaceUv {
+            turns: 0,
+            mirror: false,
+            tiles: (1.0, 1.0),
+            offset: (0.0, 0.0),
+            size: (1.0, 1.0),
+        }
+    }
+}
+
+impl FaceUv {
+    // the texture's u and v at (u, v) across the face, and the point's derivatives
+    // along them from dpdu and dpdv, its derivatives along the face's own
+    fn map(
+        &self,
+        u: Float,
+        v: Float,
+        dpdu: Vector3<Float>,
+        dpdv: Vector3<Float>,
+    ) -> (Float, Float, Vector3<Float>, Vector3<Float>) {
+        let (mut u, mut v) = (u, v);
+        // how the texture's u and v change with the face's
+        let mut jacobian = Matrix2::identity();
+        for _ in 0..self.turns % 4 {
+            (u, v) = (1.0 - v, u);
+            jacobian = Matrix2::new(0.0, -1.0, 1.0, 0.0) * jacobian;
+        }
+        if self.mirror {
+            u = 1.0 - u;
+            jacobian = Matrix2::new(-1.0, 0.0, 0.0, 1.0) * jacobian;
+        }
+        let (mut u, mut v) = (u * self.tiles.0, v * self.tiles.1);
+        if self.tiles != (1.0, 1.0) {
+            u = u.rem_euclid(1.0);
+            v = v.rem_euclid(1.0);
+        }
+        let scale = Matrix2::new(
+            self.tiles.0 * self.size.0,
+            0.0,
+            0.0,
+            self.tiles.1 * self.size.1,
+        );
+        let inverse = (scale * jacobian)
+            .try_inverse()
+            .unwrap_or_else(Matrix2::zeros);
+        (
+            self.offset.0 + self.size.0 * u,
+            self.offset.1 + self.size.1 * v,
+            inverse[(0, 0)] * dpdu + inverse[(1, 0)] * dpdv,
+            inverse[(0, 1)] * dpdu + inverse[(1, 1)] * dpdv,
+        )
+    }
+}
+
 #[derive(Clone)]
 pub struct AARect<M: Material> {
     plane: Plane,
-    a0: f32,
-    a1: f32,
-    b0: f32,
-    b1: f32,
-    k: f32,
+    a0: Float,
+    a1: Float,
+    b0: Float,
+    b1: Float,
+    k: Float,
     material: M,
+    uv: FaceUv,
 }
 
-fn get_axis(plane: &Plane) -> (usize, usize, usize) {
+pub fn get_axis(plane: &Plane) -> (usize, usize, usize) {
     match plane {
         Plane::YZ => (0, 1, 2),
         Plane::ZX => (1, 2, 0),
@@ -33,7 +105,15 @@ fn get_axis(plane: &Plane) -> (usize, usize, usize) {
 }
 
 impl<M: Material> AARect<M> {
-    pub fn new(plane: Plane, a0: f32, a1: f32, b0: f32, b1: f32, k: f32, material: M) -> Self {
+    pub fn new(
+        plane: Plane,
+        a0: Float,
+        a1: Float,
+        b0: Float,
+        b1: Float,
+        k: Float,
+        material: M,
+    ) -> Self {
         AARect {
             plane,
             a0,
@@ -42,12 +122,18 @@ impl<M: Material> AARect<M> {
             b1,
             k,
             material,
+            uv: FaceUv::default(),
         }
     }
+
+    // the rectangle with its face laid on textures as `uv` says
+    pub fn with_uv(self, uv: FaceUv) -> Self {
+        AARect { uv, ..self }
+    }
 }
 
 impl<M: Material> Hittable for AARect<M> {
-    fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord> {
+    fn hit(&self, ray: &Ray, t_min: Float, t_max: Float) -> Option<HitRecord<'_>> {
         let (k_axis, a_axis, b_axis) = get_axis(&self.plane);
         let t = (self.k - ray.origin()[k_axis]) / ray.direction()[k_axis];
         if t < t_min || t > t_max {
@@ -58,31 +144,57 @@ impl<M: Material> Hittable for AARect<M> {
             if a < self.a0 || a > self.a1 || b < self.b0 || b > self.b1 {
                 None
             } else {
-                let u = (a - self.a0) / (self.a1 - self.a0);
-                let v = (b - self.b0) / (self.b1 - self.b0);
-                let p = ray.point_at_parameter(t);
+                let mut dpdu = Vector3::zeros();
+                dpdu[a_axis] = self.a1 - self.a0;
+                let mut dpdv = Vector3::zeros();
+                dpdv[b_axis] = self.b1 - self.b0;
+                let (u, v, dpdu, dpdv) = self.uv.map(
+                    (a - self.a0) / (self.a1 - self.a0),
+                    (b - self.b0) / (self.b1 - self.b0),
+                    dpdu,
+                    dpdv,
+                );
+                // on the plane exactly, whatever the rounding in t
+                let mut p = Vector3::zeros();
+                p[k_axis] = self.k;
+                p[a_axis] = a;
+                p[b_axis] = b;
                 let mut normal = Vector3::zeros();
                 normal[k_axis] = 1.0;
+                let tangent = dpdu.try_normalize(0.0).unwrap_or(Vector3::zeros());
                 Some(HitRecord {
                     t,
                     u,
                     v,
                     p,
+                    texture_p: p,
                     normal,
+                    tangent,
+                    dpdu,
+                    dpdv,
                     material: &self.material,
+                    object_id: 0,
+                    class_id: 0,
                 })
             }
         }
     }
 
-    fn bounding_box(&self, _t0: f32, _t1: f32) -> Option<AABB> {
-        let min = Vector3::new(self.a0, self.b0, self.k - 0.0001);
-        let max = Vector3::new(self.a1, self.b1, self.k + 0.0001);
+    fn bounding_box(&self, _t0: Float, _t1: Float) -> Option<AABB> {
+        let (k_axis, a_axis, b_axis) = get_axis(&self.plane);
+        let mut min = Vector3::zeros();
+        let mut max = Vector3::zeros();
+        min[k_axis] = self.k - 0.0001;
+        max[k_axis] = self.k + 0.0001;
+        min[a_axis] = self.a0;
+        max[a_axis] = self.a1;
+        min[b_axis] = self.b0;
+        max[b_axis] = self.b1;
         Some(AABB { min, max })
     }
 
-    fn pdf_value(&self, o: Vector3<f32>, v: Vector3<f32>) -> f32 {
-        if let Some(hit) = self.hit(&Ray::new(o, v, 0.0), 0.001, f32::MAX) {
+    fn pdf_value(&self, o: Vector3<Float>, v: Vector3<Float>) -> Float {
+        if let Some(hit) = self.hit(&Ray::new(o, v, 0.0), ray::T_MIN, Float::MAX) {
             let area = (self.a1 - self.a0) * (self.b1 - self.b0);
             let distance_squared = hit.t.powi(2) * v.norm_squared();
             let cosine = v.dot(&hit.normal).abs() / v.norm();
@@ -96,13 +208,37 @@ impl<M: Material> Hittable for AARect<M> {
         }
     }
 
-    fn random(&self, o: Vector3<f32>) -> Vector3<f32> {
-        let mut rng = rand::thread_rng();
+    fn random(&self, o: Vector3<Float>) -> Vector3<Float> {
         let (k_axis, a_axis, b_axis) = get_axis(&self.plane);
         let mut random_point = Vector3::zeros();
-        random_point[a_axis] = rng.gen_range(self.a0..self.a1);
-        random_point[b_axis] = rng.gen_range(self.b0..self.b1);
+        random_point[a_axis] = self.a0 + (self.a1 - self.a0) * rng::uniform();
+        random_point[b_axis] = self.b0 + (self.b1 - self.b0) * rng::uniform();
         random_point[k_axis] = self.k;
         random_point - o
     }
+
+    fn sample_surface(&self) -> Option<(Vector3<Float>, Vector3<Float>, Float)> {
+        let (k_axis, a_axis, b_axis) = get_axis(&self.plane);
+        let mut p = Vector3::zeros();
+        p[a_axis] = self.a0 + (self.a1 - self.a0) * rng::uniform();
+        p[b_axis] = self.b0 + (self.b1 - self.b0) * rng::uniform();
+        p[k_axis] = self.k;
+        let mut normal = Vector3::zeros();
+        normal[k_axis] = 1.0;
+        Some((p, normal, (self.a1 - self.a0) * (self.b1 - self.b0)))
+    }
+
+    // coverage where the axis crosses the rectangle's plane
+    fn occlusion(&self, cone: &Cone, t_min: Float, t_max: Float) -> Float {
+        let (k_axis, a_axis, b_axis) = get_axis(&self.plane);
+        let t = (self.k - cone.origin[k_axis]) / cone.direction[k_axis];
+        if !(t_min..=t_max).contains(&t) {
+            return 0.0;
+        }
+        let p = cone.at(t);
+        let da = (self.a0 - p[a_axis]).max(p[a_axis] - self.a1);
+        let db = (self.b0 - p[b_axis]).max(p[b_axis] - self.b1);
+        let distance = da.max(0.0).hypot(db.max(0.0)) + da.max(db).min(0.0);
+        cone.coverage(distance, t)
+    }
 }
@@ -0,0 +1,230 @@
+use crate::cli::Options;
+use crate::flip;
+use crate::float::Float;
+use crate::render::{self, Image, RenderSettings};
+use crate::scene::{self, Scene};
+use crate::scenefile;
+use nalgebra::Vector3;
+use std::fs;
+use std::path::Path;
+
+// keeps the relative error finite where the reference is black
+const RELATIVE_EPSILON: Float = 0.01;
+
+// Per channel error statistics of a render against a reference image.
+pub struct Metrics {
+    pub mse: Float,
+    pub rmse: Float,
+    pub rel_mse: Float,
+    pub mean_abs: Float,
+    pub max_abs: Float,
+    // mean of the render over mean of the reference, away from 1 when either is biased
+    pub mean_ratio: Float,
+    // mean perceptual difference of the two clamped to [0, 1], see flip::mean_error
+    pub flip: Float,
+}
+
+impl Metrics {
+    fn row(&self, name: &str) -> String {
+        format!(
+            "{:<20} {:>12.6} {:>12.6} {:>12.6} {:>12.6} {:>12.6} {:>10.4} {:>8.4}",
+            name,
+            self.mse,
+            self.rmse,
+            self.rel_mse,
+            self.mean_abs,
+            self.max_abs,
+            self.mean_ratio,
+            self.flip
+        )
+    }
+}
+
+// Loads a reference in any format the image crate reads, EXR giving linear values,
+// or a pfm as the reports write them.
+pub fn load(path: &str) -> Result<Image, String> {
+    if path.ends_with(".pfm") {
+        let bytes = fs::read(path).map_err(|e| format!("cannot read reference {}: {}", path, e))?;
+        return Image::from_pfm(&bytes)
+            .map_err(|e| format!("cannot read reference {}: {}", path, e));
+    }
+    let image = image::open(path)
+        .map_err(|e| format!("cannot read reference {}: {}", path, e))?
+        .to_rgb32f();
+    let (width, height) = image.dimensions();
+    if width == 0 || height == 0 {
+        return Err(format!("reference {} is empty", path));
+    }
+    let pixels = image
+        .into_raw()
+        .chunks(3)
+        .map(|c| Vector3::new(c[0], c[1], c[2]).cast())
+        .collect();
+    Ok(Image {
+        width: width as usize,
+        height: height as usize,
+        pixels,
+    })
+}
+
+// The error metrics of image against reference and the absolute error image.
+pub fn compare(image: &Image, reference: &Image) -> Result<(Metrics, Image), String> {
+    if (image.width, image.height) != (reference.width, reference.height) {
+        return Err(format!(
+            "render is {}x{} but the reference is {}x{}",
+            image.width, image.height, reference.width, reference.height
+        ));
+    }
+    let n = 3.0 * image.pixels.len() as Float;
+    let error = image
+        .pixels
+        .iter()
+        .zip(reference.pixels.iter())
+        .map(|(p, r)| (p - r).abs())
+        .collect::<Vec<Vector3<Float>>>();
+    let (mut squared, mut relative, mut absolute, mut max_abs) = (0.0, 0.0, 0.0, 0.0 as Float);
+    for (e, r) in error.iter().zip(reference.pixels.iter()) {
+        for (e, r) in e.iter().zip(r.iter()) {
+            squared += e * e;
+            relative += e * e / (r * r + RELATIVE_EPSILON);
+            absolute += e;
+            max_abs = max_abs.max(*e);
+        }
+    }
+    let sum = |image: &Image| image.pixels.iter().map(|p| p.x + p.y + p.z).sum::<Float>();
+    let mse = squared / n;
+    let metrics = Metrics {
+        mse,
+        rmse: mse.sqrt(),
+        rel_mse: relative / n,
+        mean_abs: absolute / n,
+        max_abs,
+        mean_ratio: sum(image) / sum(reference),
+        flip: flip::mean_error(image, reference),
+    };
+    let error = Image {
+        width: image.width,
+        height: image.height,
+        pixels: error,
+    };
+    Ok((metrics, error))
+}
+
+// The scene a reference names, a gallery scene or a scene file such as an imported
+// pbrt scene, read as --scene-file reads it, and the name its outputs are given.
+fn reference_scene(
+    name: &str,
+    aspect: Float,
+    options: &Options,
+) -> Result<(Scene, String), String> {
+    if let Some(scene) = scene::by_name(name, aspect, options) {
+        return Ok((scene, name.to_string()));
+    }
+    if !is_scene_file(name) {
+        return Err(format!("unknown scene: {}", name));
+    }
+    let stem = Path::new(name)
+        .file_stem()
+        .map_or(String::from("scene"), |s| s.to_string_lossy().into_owned());
+    Ok((scenefile::load(name, aspect, options)?, stem))
+}
+
+// whether a --reference scene is a path to a scene file rather than a gallery name
+pub fn is_scene_file(name: &str) -> bool {
+    Path::new(name).extension().is_some()
+}
+
+// Renders each scene at the resolution of its reference and writes the render, the
+// absolute error and a metrics table for all of them into dir.
+pub fn report(
+    references: &[(String, String)],
+    options: &Options,
+    settings: &RenderSettings,
+    dir: &str,
+) -> Result<String, String> {
+    let dir = Path::new(dir);
+    fs::create_dir_all(dir).map_err(|e| format!("cannot create {}: {}", dir.display(), e))?;
+    let write = |name: String, image: &Image| {
+        let path = dir.join(name);
+        fs::write(&path, image.pfm()).map_err(|e| format!("cannot write {}: {}", path.display(), e))
+    };
+    let mut report = format!(
+        "{:<20} {:>12} {:>12} {:>12} {:>12} {:>12} {:>10} {:>8}\n",
+        "scene", "mse", "rmse", "relmse", "mean abs", "max abs", "mean ratio", "flip"
+    );
+    for (name, path) in references {
+        eprintln!("comparing {} against {}", name, path);
+        let reference = load(path)?;
+        let settings = RenderSettings {
+            width: reference.width,
+            height: reference.height,
+            ..settings.clone()
+        };
+        let aspect = reference.width as Float / reference.height as Float;
+        let (scene, name) = reference_scene(name, aspect, options)?;
+        let image = render::render(&scene, &settings);
+        let (metrics, error) = compare(&image, &reference)?;
+        write(format!("{}_render.pfm", name), &image)?;
+        write(format!("{}_error.pfm", name), &error)?;
+        report.push_str(&metrics.row(&name));
+        report.push('\n');
+    }
+    let path = dir.join("report.txt");
+    fs::write(&path, &report).map_err(|e| format!("cannot write {}: {}", path.display(), e))?;
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn references_compare_imported_pbrt_scenes() {
+        let dir = std::env::temp_dir().join("rest_of_life_pbrt_reference");
+        fs::create_dir_all(&dir).unwrap();
+        let scene_path = dir.join("ball.pbrt");
+        fs::write(
+            &scene_path,
+            "LookAt 0 0 5  0 0 0  0 1 0\n\
+             Camera \"perspective\" \"float fov\" [40]\n\
+             Film \"image\" \"integer xresolution\" [16] \"integer yresolution\" [12]\n\
+             WorldBegin\n\
+             LightSource \"infinite\" \"rgb L\" [0.5 0.5 0.5]\n\
+             Material \"matte\" \"rgb Kd\" [0.8 0.2 0.2]\n\
+             Shape \"sphere\" \"float radius\" [1]\n\
+             WorldEnd\n",
+        )
+        .unwrap();
+        let scene_path = scene_path.to_str().unwrap().to_string();
+        let settings = RenderSettings {
+            width: 16,
+            height: 12,
+            spp: 4,
+            ..RenderSettings::default()
+        };
+        // the same render as the reference, read through the command line
+        let options = Options::default();
+        let scene = scenefile::load(&scene_path, 16.0 / 12.0, &options).unwrap();
+        let reference_path = dir.join("ball_reference.pfm");
+        fs::write(&reference_path, render::render(&scene, &settings).pfm()).unwrap();
+        let argument = format!("{}:{}", scene_path, reference_path.to_str().unwrap());
+        let args = ["--reference", &argument].map(String::from);
+        let options = Options::from_args(args.into_iter()).unwrap();
+        let out = dir.join("report");
+        let report = report(
+            &options.references,
+            &options,
+            &settings,
+            out.to_str().unwrap(),
+        )
+        .unwrap();
+        let row = report.lines().nth(1).unwrap();
+        assert!(row.starts_with("ball "));
+        let rendered = load(out.join("ball_render.pfm").to_str().unwrap()).unwrap();
+        let reference = load(reference_path.to_str().unwrap()).unwrap();
+        assert_eq!(compare(&rendered, &reference).unwrap().0.rmse, 0.0);
+        // a name that is neither a gallery scene nor a file is still refused
+        let args = ["--reference", "nowhere:ref.pfm"].map(String::from);
+        assert!(Options::from_args(args.into_iter()).is_err());
+    }
+}
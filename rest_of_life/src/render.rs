@@ -0,0 +1,706 @@
+use crate::adaptive::{Adaptive, PixelStats};
+use crate::alpha;
+use crate::bounce::BounceLimits;
+use crate::cone;
+use crate::crop::Crop;
+use crate::estimator::{self, Estimator};
+use crate::finite;
+use crate::float::{self, Float};
+use crate::guide::{Guide, GuideSchedule};
+use crate::hittable::Hittable;
+use crate::integrator::{
+    AmbientOcclusion, ClayRender, DebugIntegrator, DirectLightingOnly, Integrator, IntegratorKind,
+    NaivePathTracer, PathTracer,
+};
+use crate::interrupt;
+use crate::metadata::Metadata;
+use crate::packet::LANES;
+use crate::parallel::*;
+use crate::pathlog;
+use crate::ray::Ray;
+use crate::rng::{self, RngBackend};
+use crate::scene::Scene;
+use crate::sensor::SensorNoise;
+use crate::spectrum;
+use crate::sppm::{self, PhotonMapping};
+use crate::stats::{self, Counter};
+use crate::throughput::ThroughputCutoff;
+use crate::tonemap::ToneMapping;
+use crate::wavefront;
+use image::codecs::png::PngEncoder;
+use image::{ColorType, ImageEncoder};
+use nalgebra::Vector3;
+use std::str::FromStr;
+
+// How to render a scene: image size and samples, how samples combine, how paths
+// are cut and fireflies suppressed, whether to learn a guide first or only trace a
+// cone preview, the sensor noise applied after and the random number generator with
+// the seed every pixel's samples derive from. With `adaptive` set, spp is the most
+// samples a pixel may take rather than the number every pixel takes.
+//
+// `clamp` caps the radiance a sample gathers past its first bounce, keeping what the
+// camera sees directly exact; `reject_sigma` drops samples that stand out from the
+// rest of their pixel before the estimator combines them. Both are biased.
+//
+// With `spectral` set every sample traces its path at one wavelength, which
+// dispersive glass refracts by, and is weighted into RGB by the colour matching
+// functions, see spectrum::rgb_weight. With `sppm` set the image is rendered by
+// progressive photon mapping instead, spp iterations of it, see sppm::PhotonMapping.
+// Otherwise `integrator` picks how each sample's radiance is estimated; ambient
+// occlusion counts only occluders within `ao_distance` when it is set. With
+// `packets` set a pixel's samples are traced LANES at a time, their camera rays
+// generated together and handed to Integrator::radiance_packet; the samples draw
+// their random numbers in another order, so the noise differs from a render
+// without it but not the image it converges to. With `wavefront` set the path
+// tracer runs as a wavefront instead, see wavefront.rs.
+#[derive(Clone)]
+pub struct RenderSettings {
+    pub width: usize,
+    pub height: usize,
+    pub spp: usize,
+    pub adaptive: Option<Adaptive>,
+    pub estimator: Estimator,
+    pub cutoff: ThroughputCutoff,
+    pub bounces: BounceLimits,
+    pub clamp: Option<Float>,
+    pub reject_sigma: Option<Float>,
+    pub guide: Option<GuideSchedule>,
+    pub cone: bool,
+    pub spectral: bool,
+    pub integrator: IntegratorKind,
+    pub ao_distance: Option<Float>,
+    pub packets: bool,
+    pub wavefront: bool,
+    pub sppm: Option<PhotonMapping>,
+    pub sensor: Option<SensorNoise>,
+    pub rng: RngBackend,
+    pub seed: u64,
+    // the window of the image to render, if not the whole
+    pub crop: Option<Crop>,
+}
+
+impl Default for RenderSettings {
+    fn default() -> Self {
+        RenderSettings {
+            width: 500,
+            height: 500,
+            spp: 1000,
+            adaptive: None,
+            estimator: Estimator::default(),
+            cutoff: ThroughputCutoff::default(),
+            bounces: BounceLimits::default(),
+            clamp: None,
+            reject_sigma: None,
+            guide: None,
+            cone: false,
+            spectral: false,
+            integrator: IntegratorKind::default(),
+            ao_distance: None,
+            packets: false,
+            wavefront: false,
+            sppm: None,
+            sensor: None,
+            rng: RngBackend::default(),
+            seed: 0,
+            crop: None,
+        }
+    }
+}
+
+// How an image is written out. P3, P6 and PNG hold tone mapped 8 bit sRGB values and
+// the settings they were rendered with, see metadata.rs; raw dumps the linear f32
+// framebuffer after a header of the magic "RTFB" and the width, height and samples per
+// pixel as little-endian u32, three little-endian f32 per pixel with rows from the top. rtmerge averages raw dumps weighted by their samples, see rtmerge.rs.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum OutputFormat {
+    #[default]
+    P3,
+    P6,
+    Png,
+    Raw,
+}
+
+impl FromStr for OutputFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "p3" => Ok(OutputFormat::P3),
+            "p6" => Ok(OutputFormat::P6),
+            "png" => Ok(OutputFormat::Png),
+            "raw" => Ok(OutputFormat::Raw),
+            _ => Err(format!("unknown output format: {}", s)),
+        }
+    }
+}
+
+impl OutputFormat {
+    // the extension of files in the format
+    pub fn extension(&self) -> &'static str {
+        match self {
+            OutputFormat::P3 | OutputFormat::P6 => "ppm",
+            OutputFormat::Png => "png",
+            OutputFormat::Raw => "rtfb",
+        }
+    }
+}
+
+// A linear HDR image, rows from the top.
+pub struct Image {
+    pub width: usize,
+    pub height: usize,
+    pub pixels: Vec<Vector3<Float>>,
+}
+
+impl Image {
+    // tone mapped 8 bit sRGB values, three a pixel
+    pub fn rgb8<'a>(&'a self, tone: &'a ToneMapping) -> impl Iterator<Item = u8> + 'a {
+        self.pixels.iter().flat_map(move |col| {
+            let c = tone.display(col);
+            [c.x, c.y, c.z].map(|c| (255.99 * c) as u8)
+        })
+    }
+
+    // plain ppm, the metadata in comments
+    pub fn ppm(&self, tone: &ToneMapping, metadata: &Metadata) -> String {
+        let mut ppm = format!(
+            "P3\n{}{} {}\n255\n",
+            metadata.ppm_comments(),
+            self.width,
+            self.height
+        );
+        let rgb = self.rgb8(tone).collect::<Vec<u8>>();
+        for rgb in rgb.chunks(3) {
+            ppm.push_str(&format!("{} {} {}\n", rgb[0], rgb[1], rgb[2]));
+        }
+        ppm
+    }
+
+    // color pfm with the linear values, rows from the bottom as the format wants
+    pub fn pfm(&self) -> Vec<u8> {
+        let mut bytes = format!("PF\n{} {}\n-1.0\n", self.width, self.height).into_bytes();
+        for row in self.pixels.chunks(self.width).rev() {
+            for c in row.iter().flat_map(|col| col.iter()) {
+                bytes.extend(float::single(*c).to_le_bytes());
+            }
+        }
+        bytes
+    }
+
+    // reads what pfm writes, grey scale pfms too, and either byte order
+    pub fn from_pfm(bytes: &[u8]) -> Result<Image, String> {
+        let mut fields = Vec::new();
+        let mut start = 0;
+        // the type, width, height and scale, each ended by one whitespace character
+        while fields.len() < 4 {
+            let rest = bytes.get(start..).ok_or("truncated pfm header")?;
+            let skip = rest
+                .iter()
+                .position(|b| !b.is_ascii_whitespace())
+                .ok_or("truncated pfm header")?;
+            let len = rest[skip..]
+                .iter()
+                .position(|b| b.is_ascii_whitespace())
+                .ok_or("truncated pfm header")?;
+            fields.push(String::from_utf8_lossy(&rest[skip..skip + len]).into_owned());
+            start += skip + len + 1;
+        }
+        let channels = match fields[0].as_str() {
+            "PF" => 3,
+            "Pf" => 1,
+            _ => return Err(String::from("not a pfm")),
+        };
+        let parse = |field: &str| {
+            field
+                .parse::<usize>()
+                .map_err(|e| format!("bad pfm size: {}", e))
+        };
+        let (width, height) = (parse(&fields[1])?, parse(&fields[2])?);
+        if width == 0 || height == 0 {
+            return Err(String::from("empty pfm"));
+        }
+        let scale: Float = fields[3]
+            .parse()
+            .map_err(|e| format!("bad pfm scale: {}", e))?;
+        let data = &bytes[start.min(bytes.len())..];
+        if data.len() < 4 * channels * width * height {
+            return Err(String::from("truncated pfm data"));
+        }
+        let values: Vec<Float> = data
+            .chunks_exact(4)
+            .take(channels * width * height)
+            .map(|b| {
+                let b = [b[0], b[1], b[2], b[3]];
+                (if scale < 0.0 {
+                    f32::from_le_bytes(b)
+                } else {
+                    f32::from_be_bytes(b)
+                }) as Float
+            })
+            .collect();
+        let mut pixels = Vec::with_capacity(width * height);
+        for row in values.chunks(channels * width).rev() {
+            pixels.extend(row.chunks(channels).map(|c| match c {
+                [r, g, b] => Vector3::new(*r, *g, *b),
+                _ => Vector3::new(c[0], c[0], c[0]),
+            }));
+        }
+        Ok(Image {
+            width,
+            height,
+            pixels,
+        })
+    }
+
+    // reads what raw writes, and the samples per pixel it records
+    pub fn from_raw(bytes: &[u8]) -> Result<(Image, usize), String> {
+        if bytes.len() < 16 || &bytes[..4] != b"RTFB" {
+            return Err(String::from("not a raw framebuffer"));
+        }
+        let field = |at: usize| {
+            u32::from_le_bytes([bytes[at], bytes[at + 1], bytes[at + 2], bytes[at + 3]]) as usize
+        };
+        let (width, height, spp) = (field(4), field(8), field(12));
+        if bytes.len() != 16 + 12 * width * height {
+            return Err(String::from("truncated raw framebuffer"));
+        }
+        let pixels = bytes[16..]
+            .chunks_exact(12)
+            .map(|p| {
+                let c = |at: usize| {
+                    f32::from_le_bytes([p[at], p[at + 1], p[at + 2], p[at + 3]]) as Float
+                };
+                Vector3::new(c(0), c(4), c(8))
+            })
+            .collect();
+        let image = Image {
+            width,
+            height,
+            pixels,
+        };
+        Ok((image, spp))
+    }
+
+    // the tone mapping and metadata apply to the 8 bit formats only, and the samples
+    // per pixel behind the image, which raw dumps record, to raw only
+    pub fn encode(
+        &self,
+        format: OutputFormat,
+        tone: &ToneMapping,
+        spp: usize,
+        metadata: &Metadata,
+    ) -> Vec<u8> {
+        match format {
+            OutputFormat::P3 => self.ppm(tone, metadata).into_bytes(),
+            OutputFormat::P6 => {
+                let mut bytes = format!(
+                    "P6\n{}{} {}\n255\n",
+                    metadata.ppm_comments(),
+                    self.width,
+                    self.height
+                )
+                .into_bytes();
+                bytes.extend(self.rgb8(tone));
+                bytes
+            }
+            OutputFormat::Png => {
+                let mut bytes = Vec::new();
+                PngEncoder::new(&mut bytes)
+                    .write_image(
+                        &self.rgb8(tone).collect::<Vec<u8>>(),
+                        self.width as u32,
+                        self.height as u32,
+                        ColorType::Rgb8,
+                    )
+                    .expect("cannot encode png");
+                // after the signature and the header chunk
+                bytes.splice(33..33, metadata.png_chunks());
+                bytes
+            }
+            OutputFormat::Raw => {
+                let mut bytes = b"RTFB".to_vec();
+                bytes.extend((self.width as u32).to_le_bytes());
+                bytes.extend((self.height as u32).to_le_bytes());
+                bytes.extend((spp as u32).to_le_bytes());
+                for col in self.pixels.iter() {
+                    for c in col.iter() {
+                        bytes.extend(float::single(*c).to_le_bytes());
+                    }
+                }
+                bytes
+            }
+        }
+    }
+}
+
+// The camera ray of a sample of the pixel in column x and row y, rows counted from
+// the bottom, with the wavelength it traces at; None outside a fisheye's image
+// circle.
+pub fn camera_ray(scene: &Scene, settings: &RenderSettings, x: usize, y: usize) -> Option<Ray> {
+    let (nx, ny) = (settings.width, settings.height);
+    let u = (x as Float + rng::uniform()) / nx as Float;
+    let v = (y as Float + rng::uniform()) / ny as Float;
+    let ray = scene
+        .camera
+        .get_ray_differential(u, v, 1.0 / nx as Float, 1.0 / ny as Float)?;
+    stats::count(Counter::PrimaryRays);
+    let wavelength = settings
+        .spectral
+        .then(|| spectrum::sample_wavelength(rng::uniform()));
+    Some(ray.with_wavelength(wavelength))
+}
+
+// the radiance a sample traced at the wavelength, if any, brings to the pixel in RGB
+pub fn weigh(radiance: Vector3<Float>, wavelength: Option<Float>) -> Vector3<Float> {
+    match wavelength {
+        Some(wavelength) => radiance.component_mul(&spectrum::rgb_weight(wavelength)),
+        None => radiance,
+    }
+}
+
+// combines a pixel's samples, dropping outliers first when asked to
+pub fn estimate(
+    settings: &RenderSettings,
+    estimator: Estimator,
+    samples: impl Iterator<Item = Vector3<Float>>,
+) -> Vector3<Float> {
+    match settings.reject_sigma {
+        Some(sigma) => {
+            estimator.combine(estimator::reject_outliers(samples.collect(), sigma).into_iter())
+        }
+        None => estimator.combine(samples),
+    }
+}
+
+// every pixel of the image, rows from the top, as render_pass takes them
+fn all_pixels(settings: &RenderSettings) -> Vec<(usize, usize)> {
+    (0..settings.height)
+        .flat_map(|row| (0..settings.width).map(move |x| (x, row)))
+        .collect()
+}
+
+// The estimate of each of the given pixels, columns and rows from the top left,
+// and the samples it took: ns each, or under adaptive sampling as many as it
+// needs up to ns.
+#[allow(clippy::too_many_arguments)]
+fn render_pass(
+    scene: &Scene,
+    settings: &RenderSettings,
+    pixels: &[(usize, usize)],
+    pass: usize,
+    ns: usize,
+    integrator: &dyn Integrator,
+    estimator: Estimator,
+    adaptive: Option<Adaptive>,
+) -> Vec<(Vector3<Float>, usize)> {
+    let ny = settings.height;
+    pixels
+        .par_iter()
+        .map(|&(x, row)| {
+            // the camera's v, and the pixel's seed, count rows from the bottom
+            let y = ny - 1 - row;
+            rng::seed_pixel(settings.rng, settings.seed, pass, x, y);
+            let camera_ray = || camera_ray(scene, settings, x, y);
+            // samples s to s + n, one by one or as a packet, any not finite counted
+            // black, see finite.rs; outside a fisheye's image circle stays black
+            let samples = |s: usize, n: usize| -> Vec<Vector3<Float>> {
+                if !settings.packets {
+                    return (s..s + n)
+                        .map(|s| {
+                            alpha::set_pixel(x, y, s);
+                            finite::set_pixel(x, row, s);
+                            camera_ray().map_or(Vector3::zeros(), |ray| {
+                                let radiance = integrator.radiance(&ray, scene);
+                                finite::guard(weigh(radiance, ray.wavelength()))
+                            })
+                        })
+                        .collect();
+                }
+                // the packet's cutouts are decided as its first sample's, and its
+                // non-finite samples logged as that one
+                alpha::set_pixel(x, y, s);
+                finite::set_pixel(x, row, s);
+                let (mut traced, mut lanes) = (Vec::new(), Vec::new());
+                for lane in 0..n {
+                    if let Some(ray) = camera_ray() {
+                        traced.push(ray);
+                        lanes.push(lane);
+                    }
+                }
+                let mut taken = vec![Vector3::zeros(); n];
+                let radiance = integrator.radiance_packet(&traced, scene);
+                for ((radiance, ray), lane) in radiance.into_iter().zip(&traced).zip(lanes) {
+                    taken[lane] = finite::guard(weigh(radiance, ray.wavelength()));
+                }
+                taken
+            };
+            let step = if settings.packets { LANES } else { 1 };
+            // an interrupt stops the pixel at its next sample or packet, one it has
+            // not started takes none and stays black
+            let (c, n) = match adaptive {
+                Some(adaptive) => {
+                    let mut stats = PixelStats::default();
+                    let mut taken = Vec::new();
+                    while taken.len() < ns
+                        && !stats.converged(&adaptive)
+                        && !interrupt::interrupted()
+                    {
+                        for c in samples(taken.len(), step.min(ns - taken.len())) {
+                            stats.push(&c);
+                            taken.push(c);
+                        }
+                    }
+                    let n = taken.len();
+                    (estimate(settings, estimator, taken.into_iter()), n)
+                }
+                None => {
+                    let mut n = 0;
+                    let taken = (0..ns)
+                        .step_by(step)
+                        .take_while(|_| !interrupt::interrupted())
+                        .flat_map(|s| {
+                            let count = step.min(ns - s);
+                            n += count;
+                            samples(s, count)
+                        });
+                    (estimate(settings, estimator, taken), n)
+                }
+            };
+            (if n > 0 { c } else { Vector3::zeros() }, n)
+        })
+        .collect::<Vec<(Vector3<Float>, usize)>>()
+}
+
+// Learns a guide over the scene from a few low sample passes, each sampling from
+// what the previous ones learned.
+fn train_guide(
+    scene: &Scene,
+    settings: &RenderSettings,
+    tracer: &PathTracer,
+    schedule: GuideSchedule,
+) -> Guide {
+    let bbox = scene
+        .world
+        .bounding_box(0.0, 1.0)
+        .expect("no bounding box for the guide");
+    let mut guide = Guide::new(bbox, schedule);
+    for pass in 0..schedule.passes {
+        let training = PathTracer {
+            guide: Some(&guide),
+            training: true,
+            ..*tracer
+        };
+        render_pass(
+            scene,
+            settings,
+            &all_pixels(settings),
+            pass,
+            schedule.pass_spp(pass),
+            &training,
+            Estimator::default(),
+            None,
+        );
+        guide.refine();
+    }
+    guide
+}
+
+fn path_tracer<'a>(scene: &'a Scene, settings: &RenderSettings) -> PathTracer<'a> {
+    PathTracer {
+        cutoff: settings.cutoff,
+        bounces: settings.bounces,
+        clamp: settings.clamp,
+        mnee: scene.mnee.as_ref(),
+        links: scene.links.as_ref(),
+        background: Some(scene.background.as_ref()),
+        ..PathTracer::default()
+    }
+}
+
+// the integrator settings ask for, short of the path tracer's guide
+fn integrator<'a>(scene: &'a Scene, settings: &RenderSettings) -> Box<dyn Integrator + 'a> {
+    match settings.integrator {
+        IntegratorKind::Path => Box::new(path_tracer(scene, settings)),
+        IntegratorKind::Naive => Box::new(NaivePathTracer {
+            cutoff: settings.cutoff,
+            bounces: settings.bounces,
+        }),
+        IntegratorKind::AO => Box::new(AmbientOcclusion {
+            distance: settings.ao_distance.unwrap_or(Float::MAX),
+        }),
+        IntegratorKind::Direct => Box::new(DirectLightingOnly {
+            bounces: settings.bounces,
+        }),
+        // the glass mnee connects through is clay too
+        IntegratorKind::Clay => Box::new(ClayRender::new(PathTracer {
+            mnee: None,
+            ..path_tracer(scene, settings)
+        })),
+        IntegratorKind::Debug(view) => Box::new(DebugIntegrator {
+            view,
+            far: scene
+                .world
+                .bounding_box(0.0, 1.0)
+                .map_or(1.0, |bbox| (bbox.max - bbox.min).norm()),
+        }),
+    }
+}
+
+fn path_trace(
+    scene: &Scene,
+    settings: &RenderSettings,
+    pixels: &[(usize, usize)],
+) -> Vec<(Vector3<Float>, usize)> {
+    let tracer = path_tracer(scene, settings);
+    if settings.wavefront {
+        return wavefront::render(scene, settings, &tracer, pixels, 0);
+    }
+    let guide = settings
+        .guide
+        .map(|schedule| train_guide(scene, settings, &tracer, schedule));
+    let integrator: Box<dyn Integrator> = match settings.integrator {
+        IntegratorKind::Path => Box::new(PathTracer {
+            guide: guide.as_ref(),
+            ..tracer
+        }),
+        _ => integrator(scene, settings),
+    };
+    // the final pass follows the training passes so it draws fresh samples
+    let pass = settings.guide.map_or(0, |schedule| schedule.passes);
+    render_pass(
+        scene,
+        settings,
+        pixels,
+        pass,
+        settings.spp,
+        integrator.as_ref(),
+        settings.estimator,
+        settings.adaptive,
+    )
+}
+
+// The estimates and sample counts of only the given pixels, columns and rows from
+// the top left, exactly as a whole render draws them since every pixel seeds its
+// own samples; a guide is still trained over the whole image. The sensor is not
+// applied, and cone previews and photon mapping, which render the image as a
+// whole, are left out.
+pub fn render_pixels(
+    scene: &Scene,
+    settings: &RenderSettings,
+    pixels: &[(usize, usize)],
+) -> Vec<(Vector3<Float>, usize)> {
+    path_trace(scene, settings, pixels)
+}
+
+// The first `paths` samples of pixel x, row from the top left, as a render with
+// the settings draws them, traced on the current thread with the path tracer's log
+// on, each with the radiance it brought and its log, see pathlog.rs. A guide is not
+// trained and samples are not traced as packets.
+pub fn trace_pixel(
+    scene: &Scene,
+    settings: &RenderSettings,
+    x: usize,
+    row: usize,
+    paths: usize,
+) -> Vec<(Vector3<Float>, Vec<String>)> {
+    let integrator = integrator(scene, settings);
+    let y = settings.height - 1 - row;
+    rng::seed_pixel(settings.rng, settings.seed, 0, x, y);
+    (0..paths)
+        .map(|s| {
+            alpha::set_pixel(x, y, s);
+            finite::set_pixel(x, row, s);
+            pathlog::start();
+            let radiance = camera_ray(scene, settings, x, y).map_or(Vector3::zeros(), |ray| {
+                pathlog::camera(&ray);
+                let radiance = integrator.radiance(&ray, scene);
+                finite::guard(weigh(radiance, ray.wavelength()))
+            });
+            (radiance, pathlog::take())
+        })
+        .collect()
+}
+
+// One sample per pixel, drawn as pass `pass`, for previews that accumulate passes
+// themselves. Guiding and adaptive sampling need more than one pass's samples and
+// are left out; the sensor is not applied.
+pub fn sample_pass(scene: &Scene, settings: &RenderSettings, pass: usize) -> Vec<Vector3<Float>> {
+    if settings.cone {
+        return cone::render_pass(
+            scene,
+            &RenderSettings {
+                spp: 1,
+                ..settings.clone()
+            },
+            pass,
+        );
+    }
+    if settings.wavefront {
+        let settings = RenderSettings {
+            spp: 1,
+            ..settings.clone()
+        };
+        let tracer = path_tracer(scene, &settings);
+        return wavefront::render(scene, &settings, &tracer, &all_pixels(&settings), pass)
+            .into_iter()
+            .map(|(c, _)| c)
+            .collect();
+    }
+    let integrator = integrator(scene, settings);
+    render_pass(
+        scene,
+        settings,
+        &all_pixels(settings),
+        pass,
+        1,
+        integrator.as_ref(),
+        Estimator::Mean,
+        None,
+    )
+    .into_iter()
+    .map(|(c, _)| c)
+    .collect()
+}
+
+pub fn render(scene: &Scene, settings: &RenderSettings) -> Image {
+    render_counted(scene, settings).0
+}
+
+// the image and the samples each of its pixels took, rows from the top
+pub fn render_counted(scene: &Scene, settings: &RenderSettings) -> (Image, Vec<usize>) {
+    // cone previews and photon mapping render the whole image and are cut down to
+    // the crop after
+    let cut = |pixels: Vec<Vector3<Float>>| match &settings.crop {
+        Some(crop) => crop.cut(&pixels, settings.width),
+        None => pixels,
+    };
+    let (mut pixels, counts) = if settings.cone {
+        let pixels = cut(cone::render_pass(scene, settings, 0));
+        let counts = vec![settings.spp; pixels.len()];
+        (pixels, counts)
+    } else if let Some(mapping) = &settings.sppm {
+        let pixels = cut(sppm::render(scene, settings, mapping));
+        let counts = vec![settings.spp; pixels.len()];
+        (pixels, counts)
+    } else {
+        let pixels = match &settings.crop {
+            Some(crop) => crop.pixels(),
+            None => all_pixels(settings),
+        };
+        path_trace(scene, settings, &pixels).into_iter().unzip()
+    };
+    // the noise of a cropped render's sensor is drawn for the window alone
+    if let Some(sensor) = &settings.sensor {
+        sensor.apply(&mut pixels);
+    }
+    let (width, height) = match &settings.crop {
+        Some(crop) => (crop.width(), crop.height()),
+        None => (settings.width, settings.height),
+    };
+    let image = Image {
+        width,
+        height,
+        pixels,
+    };
+    (image, counts)
+}
@@ -0,0 +1,390 @@
+use crate::float::Float;
+use nalgebra::Vector3;
+use rand::{Rng, RngCore};
+use rt_core::vec3::{self, Vec3};
+use std::cell::RefCell;
+use std::str::FromStr;
+
+// The generator every sampling decision draws from. Xoshiro and PCG are fast, ChaCha
+// is a cryptographic stream cipher whose output is the same on every platform, for
+// renders split across machines that have to agree sample for sample.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum RngBackend {
+    PCG,
+    #[default]
+    Xoshiro,
+    ChaCha,
+}
+
+impl FromStr for RngBackend {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "pcg" => Ok(RngBackend::PCG),
+            "xoshiro" => Ok(RngBackend::Xoshiro),
+            "chacha" => Ok(RngBackend::ChaCha),
+            _ => Err(format!("unknown random number generator: {}", s)),
+        }
+    }
+}
+
+impl RngBackend {
+    pub fn seeded(self, seed: u64) -> Box<dyn RngCore + Send> {
+        match self {
+            RngBackend::PCG => Box::new(PCG32::new(seed)),
+            RngBackend::Xoshiro => Box::new(Xoshiro256PlusPlus::new(seed)),
+            RngBackend::ChaCha => Box::new(ChaCha20::new(seed)),
+        }
+    }
+}
+
+fn splitmix64(state: &mut u64) -> u64 {
+    *state = state.wrapping_add(0x9e3779b97f4a7c15);
+    let mut z = *state;
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+    z ^ (z >> 31)
+}
+
+fn fill_bytes_via_u64(rng: &mut impl RngCore, dest: &mut [u8]) {
+    for chunk in dest.chunks_mut(8) {
+        let bytes = rng.next_u64().to_le_bytes();
+        chunk.copy_from_slice(&bytes[..chunk.len()]);
+    }
+}
+
+// PCG-XSH-RR with 64 bits of state and 32 bit output
+pub struct PCG32 {
+    state: u64,
+    increment: u64,
+}
+
+impl PCG32 {
+    const MULTIPLIER: u64 = 6364136223846793005;
+
+    pub fn new(seed: u64) -> Self {
+        let mut mix = seed;
+        let increment = splitmix64(&mut mix);
+        PCG32::seeded(splitmix64(&mut mix), increment)
+    }
+
+    // pcg32_srandom_r of the reference implementation, the odd increment picking
+    // the stream
+    fn seeded(state: u64, increment: u64) -> Self {
+        let mut rng = PCG32 {
+            state: 0,
+            increment: increment | 1,
+        };
+        rng.next_u32();
+        rng.state = rng.state.wrapping_add(state);
+        rng.next_u32();
+        rng
+    }
+}
+
+impl RngCore for PCG32 {
+    fn next_u32(&mut self) -> u32 {
+        let old = self.state;
+        self.state = old
+            .wrapping_mul(PCG32::MULTIPLIER)
+            .wrapping_add(self.increment);
+        let xorshifted = (((old >> 18) ^ old) >> 27) as u32;
+        xorshifted.rotate_right((old >> 59) as u32)
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        (self.next_u32() as u64) << 32 | self.next_u32() as u64
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        fill_bytes_via_u64(self, dest)
+    }
+
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), rand::Error> {
+        self.fill_bytes(dest);
+        Ok(())
+    }
+}
+
+pub struct Xoshiro256PlusPlus {
+    s: [u64; 4],
+}
+
+impl Xoshiro256PlusPlus {
+    // splitmix64 never yields four zero words, the one state xoshiro cannot leave
+    pub fn new(seed: u64) -> Self {
+        let mut mix = seed;
+        Xoshiro256PlusPlus {
+            s: [(); 4].map(|_| splitmix64(&mut mix)),
+        }
+    }
+}
+
+impl RngCore for Xoshiro256PlusPlus {
+    fn next_u32(&mut self) -> u32 {
+        (self.next_u64() >> 32) as u32
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        let s = &mut self.s;
+        let result = s[0].wrapping_add(s[3]).rotate_left(23).wrapping_add(s[0]);
+        let t = s[1] << 17;
+        s[2] ^= s[0];
+        s[3] ^= s[1];
+        s[1] ^= s[2];
+        s[0] ^= s[3];
+        s[2] ^= t;
+        s[3] = s[3].rotate_left(45);
+        result
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        fill_bytes_via_u64(self, dest)
+    }
+
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), rand::Error> {
+        self.fill_bytes(dest);
+        Ok(())
+    }
+}
+
+// The ChaCha20 block function in counter mode with a zero nonce, keyed by the seed
+// expanded through splitmix64.
+pub struct ChaCha20 {
+    key: [u32; 8],
+    counter: u64,
+    block: [u32; 16],
+    index: usize,
+}
+
+impl ChaCha20 {
+    const CONSTANTS: [u32; 4] = [0x61707865, 0x3320646e, 0x79622d32, 0x6b206574];
+
+    pub fn new(seed: u64) -> Self {
+        let mut mix = seed;
+        let mut key = [0; 8];
+        for pair in key.chunks_mut(2) {
+            let word = splitmix64(&mut mix);
+            pair[0] = word as u32;
+            pair[1] = (word >> 32) as u32;
+        }
+        ChaCha20 {
+            key,
+            counter: 0,
+            block: [0; 16],
+            index: 16,
+        }
+    }
+
+    fn quarter_round(x: &mut [u32; 16], a: usize, b: usize, c: usize, d: usize) {
+        x[a] = x[a].wrapping_add(x[b]);
+        x[d] = (x[d] ^ x[a]).rotate_left(16);
+        x[c] = x[c].wrapping_add(x[d]);
+        x[b] = (x[b] ^ x[c]).rotate_left(12);
+        x[a] = x[a].wrapping_add(x[b]);
+        x[d] = (x[d] ^ x[a]).rotate_left(8);
+        x[c] = x[c].wrapping_add(x[d]);
+        x[b] = (x[b] ^ x[c]).rotate_left(7);
+    }
+
+    // the block function on a state of constants, key, counter and nonce
+    fn block(input: [u32; 16]) -> [u32; 16] {
+        let mut x = input;
+        for _ in 0..10 {
+            ChaCha20::quarter_round(&mut x, 0, 4, 8, 12);
+            ChaCha20::quarter_round(&mut x, 1, 5, 9, 13);
+            ChaCha20::quarter_round(&mut x, 2, 6, 10, 14);
+            ChaCha20::quarter_round(&mut x, 3, 7, 11, 15);
+            ChaCha20::quarter_round(&mut x, 0, 5, 10, 15);
+            ChaCha20::quarter_round(&mut x, 1, 6, 11, 12);
+            ChaCha20::quarter_round(&mut x, 2, 7, 8, 13);
+            ChaCha20::quarter_round(&mut x, 3, 4, 9, 14);
+        }
+        for (word, input) in x.iter_mut().zip(input) {
+            *word = word.wrapping_add(input);
+        }
+        x
+    }
+
+    fn refill(&mut self) {
+        let mut input = [0; 16];
+        input[..4].copy_from_slice(&ChaCha20::CONSTANTS);
+        input[4..12].copy_from_slice(&self.key);
+        input[12] = self.counter as u32;
+        input[13] = (self.counter >> 32) as u32;
+        self.block = ChaCha20::block(input);
+        self.counter = self.counter.wrapping_add(1);
+        self.index = 0;
+    }
+}
+
+impl RngCore for ChaCha20 {
+    fn next_u32(&mut self) -> u32 {
+        if self.index == self.block.len() {
+            self.refill();
+        }
+        self.index += 1;
+        self.block[self.index - 1]
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        self.next_u32() as u64 | (self.next_u32() as u64) << 32
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        fill_bytes_via_u64(self, dest)
+    }
+
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), rand::Error> {
+        self.fill_bytes(dest);
+        Ok(())
+    }
+}
+
+thread_local! {
+    static RNG: RefCell<Box<dyn RngCore + Send>> =
+        RefCell::new(RngBackend::default().seeded(rand::random()));
+}
+
+// Replaces the current thread's generator.
+pub fn seed(backend: RngBackend, seed: u64) {
+    RNG.with(|rng| *rng.borrow_mut() = backend.seeded(seed));
+}
+
+// the render seed folded with the numbers that pick out one stream of it
+fn key(seed: u64, parts: &[usize]) -> u64 {
+    let mut mix = seed;
+    let mut key = splitmix64(&mut mix);
+    for &n in parts {
+        mix = key ^ n as u64;
+        key = splitmix64(&mut mix);
+    }
+    key
+}
+
+// Seeds the current thread from the render seed, the pass and the pixel, so a pixel
+// draws the same samples whichever thread or machine traces it.
+pub fn seed_pixel(backend: RngBackend, seed: u64, pass: usize, x: usize, y: usize) {
+    self::seed(backend, key(seed, &[pass, x, y]));
+}
+
+// A generator of its own for one sample of a pixel, for renderers that trace a
+// sample's path a step at a time among many others and install it with `using`
+// for each step.
+pub fn sample_generator(
+    backend: RngBackend,
+    seed: u64,
+    pass: usize,
+    x: usize,
+    y: usize,
+    sample: usize,
+) -> Box<dyn RngCore + Send> {
+    backend.seeded(key(seed, &[pass, x, y, sample]))
+}
+
+// Runs f with `generator` as the current thread's, so everything f draws comes
+// from it and advances it, putting the thread's own back after.
+pub fn using<T>(generator: &mut Box<dyn RngCore + Send>, f: impl FnOnce() -> T) -> T {
+    RNG.with(|rng| std::mem::swap(&mut *rng.borrow_mut(), generator));
+    let result = f();
+    RNG.with(|rng| std::mem::swap(&mut *rng.borrow_mut(), generator));
+    result
+}
+
+// Runs f with the current thread's generator. f must not draw through this module
+// itself, the generator is borrowed for the call.
+pub fn with<T>(f: impl FnOnce(&mut dyn RngCore) -> T) -> T {
+    RNG.with(|rng| f(rng.borrow_mut().as_mut()))
+}
+
+// uniform in [0, 1)
+pub fn uniform() -> Float {
+    with(|rng| rng.gen::<Float>())
+}
+
+// the weekend chapters' rejection samplers, drawing from the current generator
+pub fn in_unit_disk() -> Vector3<Float> {
+    with(|rng| vec3::random_in_unit_disk_with(rng).into())
+}
+
+pub fn in_unit_sphere() -> Vector3<Float> {
+    with(|rng| Vec3::random_in_unit_sphere_with(rng).into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pcg32_matches_the_reference_implementation() {
+        // pcg32-demo's first outputs, seeded with pcg32_srandom_r(42, 54)
+        let mut rng = PCG32::seeded(42, 54 << 1);
+        let outputs = [(); 6].map(|_| rng.next_u32());
+        assert_eq!(
+            outputs,
+            [0xa15c02b7, 0x7b47f409, 0xba1d3330, 0x83d2f293, 0xbfa4784b, 0xcbed606e]
+        );
+    }
+
+    #[test]
+    fn xoshiro_matches_the_reference_implementation() {
+        // xoshiro256plusplus.c from the state 1, 2, 3, 4
+        let mut rng = Xoshiro256PlusPlus { s: [1, 2, 3, 4] };
+        let outputs = [(); 10].map(|_| rng.next_u64());
+        assert_eq!(
+            outputs,
+            [
+                41943041,
+                58720359,
+                3588806011781223,
+                3591011842654386,
+                9228616714210784205,
+                9973669472204895162,
+                14011001112246962877,
+                12406186145184390807,
+                15849039046786891736,
+                10450023813501588000,
+            ]
+        );
+    }
+
+    #[test]
+    fn chacha_matches_rfc_8439() {
+        // the block function test vector of section 2.3.2
+        let input = [
+            0x61707865, 0x3320646e, 0x79622d32, 0x6b206574, 0x03020100, 0x07060504, 0x0b0a0908,
+            0x0f0e0d0c, 0x13121110, 0x17161514, 0x1b1a1918, 0x1f1e1d1c, 0x00000001, 0x09000000,
+            0x4a000000, 0x00000000,
+        ];
+        let output = [
+            0xe4e7f110, 0x15593bd1, 0x1fdd0f50, 0xc47120a3, 0xc7f4d1c7, 0x0368c033, 0x9aaa2204,
+            0x4e6cd4c3, 0x466482d2, 0x09aa9f07, 0x05d7c214, 0xa2028bd9, 0xd19c12b5, 0xb94e16de,
+            0xe883d0cb, 0x4e3c50a2,
+        ];
+        assert_eq!(ChaCha20::block(input), output);
+        // the keystream of appendix A.1's first two vectors, a zero key and nonce at
+        // counters 0 and 1, as the generator gives it
+        let mut rng = ChaCha20 {
+            key: [0; 8],
+            counter: 0,
+            block: [0; 16],
+            index: 16,
+        };
+        let mut bytes = [0u8; 128];
+        for word in bytes.chunks_mut(4) {
+            word.copy_from_slice(&rng.next_u32().to_le_bytes());
+        }
+        let hex = bytes
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect::<String>();
+        assert_eq!(
+            hex,
+            "76b8e0ada0f13d90405d6ae55386bd28bdd219b8a08ded1aa836efcc8b770dc7\
+             da41597c5157488d7724e03fb8d84a376a43b8f41518a11cc387b669b2ee6586\
+             9f07e7be5551387a98ba977c732d080dcb0f29a048e3656912c6533e32ee7aed\
+             29b721769ce64e43d57133b074d839d531ed1f28510afb45ace10a1f4b794d6f"
+        );
+    }
+}
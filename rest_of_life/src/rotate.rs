@@ -1,8 +1,9 @@
 use crate::aabb::AABB;
+use crate::cone::Cone;
+use crate::float::{self, Float};
 use crate::hittable::{HitRecord, Hittable};
 use crate::ray::Ray;
 use nalgebra::Vector3;
-use std::f32;
 
 #[allow(dead_code)]
 pub enum Axis {
@@ -21,27 +22,28 @@ fn get_axis(axis: &Axis) -> (usize, usize, usize) {
 
 pub struct Rotate<H: Hittable> {
     axis: Axis,
-    sin_theta: f32,
-    cos_theta: f32,
+    sin_theta: Float,
+    cos_theta: Float,
     hittable: H,
     bbox: Option<AABB>,
+    object_space: bool,
 }
 
 impl<H: Hittable> Rotate<H> {
-    pub fn new(axis: Axis, hittable: H, angle: f32) -> Self {
+    pub fn new(axis: Axis, hittable: H, angle: Float) -> Self {
         let (r_axis, a_axis, b_axis) = get_axis(&axis);
-        let radians = (f32::consts::PI / 180.0) * angle;
-        let sin_theta = f32::sin(radians);
-        let cos_theta = f32::cos(radians);
+        let radians = (float::consts::PI / 180.0) * angle;
+        let sin_theta = Float::sin(radians);
+        let cos_theta = Float::cos(radians);
         let bbox = hittable.bounding_box(0.0, 1.0).map(|mut b| {
-            let mut min = Vector3::new(f32::MAX, f32::MAX, f32::MAX);
-            let mut max = Vector3::new(-f32::MAX, -f32::MAX, -f32::MAX);
+            let mut min = Vector3::new(Float::MAX, Float::MAX, Float::MAX);
+            let mut max = Vector3::new(-Float::MAX, -Float::MAX, -Float::MAX);
             for i in 0..2 {
                 for j in 0..2 {
                     for k in 0..2 {
-                        let r = k as f32 * b.max[r_axis] + (1 - k) as f32 * b.min[r_axis];
-                        let a = i as f32 * b.max[a_axis] + (1 - i) as f32 * b.min[a_axis];
-                        let b = j as f32 * b.max[b_axis] + (1 - j) as f32 * b.min[b_axis];
+                        let r = k as Float * b.max[r_axis] + (1 - k) as Float * b.min[r_axis];
+                        let a = i as Float * b.max[a_axis] + (1 - i) as Float * b.min[a_axis];
+                        let b = j as Float * b.max[b_axis] + (1 - j) as Float * b.min[b_axis];
                         let new_a = cos_theta * a - sin_theta * b;
                         let new_b = sin_theta * a + cos_theta * b;
 
@@ -77,40 +79,87 @@ impl<H: Hittable> Rotate<H> {
             cos_theta,
             hittable,
             bbox,
+            object_space: false,
         }
     }
+
+    // the same hittable with its solid textures in its own space, so they move
+    // with it rather than it moving through them
+    pub fn in_object_space(self) -> Self {
+        Rotate {
+            object_space: true,
+            ..self
+        }
+    }
+
+    fn to_object(&self, v: &Vector3<Float>) -> Vector3<Float> {
+        let (_, a_axis, b_axis) = get_axis(&self.axis);
+        let mut rotated = *v;
+        rotated[a_axis] = self.cos_theta * v[a_axis] + self.sin_theta * v[b_axis];
+        rotated[b_axis] = -self.sin_theta * v[a_axis] + self.cos_theta * v[b_axis];
+        rotated
+    }
+
+    fn to_world(&self, v: &Vector3<Float>) -> Vector3<Float> {
+        let (_, a_axis, b_axis) = get_axis(&self.axis);
+        let mut rotated = *v;
+        rotated[a_axis] = self.cos_theta * v[a_axis] - self.sin_theta * v[b_axis];
+        rotated[b_axis] = self.sin_theta * v[a_axis] + self.cos_theta * v[b_axis];
+        rotated
+    }
 }
 
 impl<H: Hittable> Hittable for Rotate<H> {
-    fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord> {
-        let (_, a_axis, b_axis) = get_axis(&self.axis);
-        let mut origin = ray.origin();
-        let mut direction = ray.direction();
-        origin[a_axis] =
-            self.cos_theta * ray.origin()[a_axis] + self.sin_theta * ray.origin()[b_axis];
-        origin[b_axis] =
-            -self.sin_theta * ray.origin()[a_axis] + self.cos_theta * ray.origin()[b_axis];
-        direction[a_axis] =
-            self.cos_theta * ray.direction()[a_axis] + self.sin_theta * ray.direction()[b_axis];
-        direction[b_axis] =
-            -self.sin_theta * ray.direction()[a_axis] + self.cos_theta * ray.direction()[b_axis];
-        let rotated_ray = Ray::new(origin, direction, ray.time());
-        self.hittable.hit(&rotated_ray, t_min, t_max).map(|mut hit| {
-            let mut p = hit.p;
-            let mut normal = hit.normal;
-            p[a_axis] = self.cos_theta * hit.p[a_axis] - self.sin_theta * hit.p[b_axis];
-            p[b_axis] = self.sin_theta * hit.p[a_axis] + self.cos_theta * hit.p[b_axis];
-            normal[a_axis] =
-                self.cos_theta * hit.normal[a_axis] - self.sin_theta * hit.normal[b_axis];
-            normal[b_axis] =
-                self.sin_theta * hit.normal[a_axis] + self.cos_theta * hit.normal[b_axis];
-            hit.p = p;
-            hit.normal = normal;
-            hit
-        })
+    fn hit(&self, ray: &Ray, t_min: Float, t_max: Float) -> Option<HitRecord<'_>> {
+        let rotated_ray = Ray::new(
+            self.to_object(&ray.origin()),
+            self.to_object(&ray.direction()),
+            ray.time(),
+        );
+        self.hittable
+            .hit(&rotated_ray, t_min, t_max)
+            .map(|mut hit| {
+                hit.p = self.to_world(&hit.p);
+                if !self.object_space {
+                    hit.texture_p = self.to_world(&hit.texture_p);
+                }
+                hit.normal = self.to_world(&hit.normal);
+                hit.tangent = self.to_world(&hit.tangent);
+                hit.dpdu = self.to_world(&hit.dpdu);
+                hit.dpdv = self.to_world(&hit.dpdv);
+                hit
+            })
+    }
+
+    fn occluded(&self, ray: &Ray, t_min: Float, t_max: Float) -> bool {
+        let rotated_ray = Ray::new(
+            self.to_object(&ray.origin()),
+            self.to_object(&ray.direction()),
+            ray.time(),
+        );
+        self.hittable.occluded(&rotated_ray, t_min, t_max)
     }
 
-    fn bounding_box(&self, _t0: f32, _t1: f32) -> Option<AABB> {
+    fn bounding_box(&self, _t0: Float, _t1: Float) -> Option<AABB> {
         self.bbox
     }
+
+    fn pdf_value(&self, o: Vector3<Float>, v: Vector3<Float>) -> Float {
+        self.hittable
+            .pdf_value(self.to_object(&o), self.to_object(&v))
+    }
+
+    fn random(&self, o: Vector3<Float>) -> Vector3<Float> {
+        self.to_world(&self.hittable.random(self.to_object(&o)))
+    }
+
+    fn occlusion(&self, cone: &Cone, t_min: Float, t_max: Float) -> Float {
+        let rotated = cone.transform(|p| self.to_object(p), |d| self.to_object(d));
+        self.hittable.occlusion(&rotated, t_min, t_max)
+    }
+
+    fn sample_surface(&self) -> Option<(Vector3<Float>, Vector3<Float>, Float)> {
+        let (p, normal, area) = self.hittable.sample_surface()?;
+        Some((self.to_world(&p), self.to_world(&normal), area))
+    }
 }
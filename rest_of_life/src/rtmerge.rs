@@ -0,0 +1,89 @@
+use crate::cli::Options;
+use crate::float::Float;
+use crate::metadata::Metadata;
+use crate::render::Image;
+use nalgebra::Vector3;
+use std::fs;
+
+// Averages raw framebuffers rendered with different seeds into one, each weighted
+// by the samples per pixel its header records, so a long render can be split into
+// batches across invocations or machines and combined after. The result records
+// the batches' samples added up, so it merges again like any batch. Batches
+// rendered with the same seed draw the same samples and add nothing.
+pub fn merge(batches: &[(Image, usize)]) -> Result<(Image, usize), String> {
+    let (first, _) = batches.first().ok_or("no raw framebuffers to merge")?;
+    let (width, height) = (first.width, first.height);
+    if let Some((image, _)) = batches
+        .iter()
+        .find(|(image, _)| (image.width, image.height) != (width, height))
+    {
+        return Err(format!(
+            "cannot merge a {}x{} framebuffer with a {}x{} one",
+            image.width, image.height, width, height
+        ));
+    }
+    let spp: usize = batches.iter().map(|(_, spp)| spp).sum();
+    if spp == 0 {
+        return Err(String::from("the raw framebuffers record no samples"));
+    }
+    let pixels = (0..width * height)
+        .map(|i| {
+            batches
+                .iter()
+                .map(|(image, n)| image.pixels[i] * *n as Float)
+                .sum::<Vector3<Float>>()
+                / spp as Float
+        })
+        .collect();
+    let image = Image {
+        width,
+        height,
+        pixels,
+    };
+    Ok((image, spp))
+}
+
+// merges the raw framebuffers rtmerge names into an image in --format, raw unless
+// given
+pub fn run(paths: &[String], options: &Options) -> Result<Vec<u8>, String> {
+    let batches = paths
+        .iter()
+        .map(|path| {
+            let bytes = fs::read(path).map_err(|e| format!("cannot read {}: {}", path, e))?;
+            Image::from_raw(&bytes).map_err(|e| format!("cannot merge {}: {}", path, e))
+        })
+        .collect::<Result<Vec<(Image, usize)>, String>>()?;
+    let (image, spp) = merge(&batches)?;
+    Ok(image.encode(options.format, &options.tone, spp, &Metadata::default()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::render::OutputFormat;
+    use crate::tonemap::ToneMapping;
+
+    fn flat(width: usize, height: usize, value: Float) -> Image {
+        Image {
+            width,
+            height,
+            pixels: vec![Vector3::new(value, value, value); width * height],
+        }
+    }
+
+    #[test]
+    fn batches_are_weighted_by_their_samples() {
+        let tone = ToneMapping::default();
+        let raw = flat(3, 2, 1.0).encode(OutputFormat::Raw, &tone, 10, &Metadata::default());
+        let (read, spp) = Image::from_raw(&raw).unwrap();
+        assert_eq!((read.width, read.height, spp), (3, 2, 10));
+        let (merged, spp) = merge(&[(read, 10), (flat(3, 2, 4.0), 30)]).unwrap();
+        assert_eq!(spp, 40);
+        assert!(merged
+            .pixels
+            .iter()
+            .all(|c| (c - Vector3::new(3.25, 3.25, 3.25)).norm() < 1e-6));
+        assert!(merge(&[(flat(3, 2, 1.0), 1), (flat(2, 3, 1.0), 1)]).is_err());
+        assert!(Image::from_raw(&raw[..raw.len() - 1]).is_err());
+    }
+}
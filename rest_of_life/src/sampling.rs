@@ -0,0 +1,240 @@
+// Sampling building blocks shared by the materials, lights and guiding. The warps
+// take their uniform numbers as arguments instead of drawing them, so they are pure
+// functions of [0, 1)^2 that any generator, or a port of the renderer, can feed.
+// Directions come out in a local frame with z up; an ONB carries them to world space.
+use crate::float::{self, Float};
+use nalgebra::Vector3;
+
+// An orthonormal basis around w, built as the book does. Local z maps to w.
+pub struct ONB {
+    axis: [Vector3<Float>; 3],
+}
+
+impl ONB {
+    pub fn build_from_w(n: &Vector3<Float>) -> Self {
+        let w = n.normalize();
+        let a = if w.x.abs() > 0.9 {
+            Vector3::new(0.0, 1.0, 0.0)
+        } else {
+            Vector3::new(1.0, 0.0, 0.0)
+        };
+        let v = w.cross(&a).normalize();
+        let u = w.cross(&v);
+        ONB { axis: [u, v, w] }
+    }
+
+    // A basis around w whose u follows the part of `tangent` orthogonal to w, or
+    // the book's if the tangent is parallel to w.
+    pub fn build_from_w_tangent(n: &Vector3<Float>, tangent: &Vector3<Float>) -> Self {
+        let w = n.normalize();
+        let u = tangent - tangent.dot(&w) * w;
+        if u.norm_squared() < 1e-12 {
+            return ONB::build_from_w(&w);
+        }
+        let u = u.normalize();
+        ONB {
+            axis: [u, w.cross(&u), w],
+        }
+    }
+
+    pub fn u(&self) -> Vector3<Float> {
+        self.axis[0]
+    }
+    pub fn v(&self) -> Vector3<Float> {
+        self.axis[1]
+    }
+    pub fn w(&self) -> Vector3<Float> {
+        self.axis[2]
+    }
+
+    // local coordinates to world space
+    pub fn local(&self, a: &Vector3<Float>) -> Vector3<Float> {
+        a.x * self.u() + a.y * self.v() + a.z * self.w()
+    }
+
+    // world space to local coordinates, the inverse of local
+    pub fn to_local(&self, a: &Vector3<Float>) -> Vector3<Float> {
+        Vector3::new(a.dot(&self.u()), a.dot(&self.v()), a.dot(&self.w()))
+    }
+}
+
+// Cosine weighted direction on the upper hemisphere: u1 picks the azimuth, u2 the
+// height, so pdf = cos(theta) / pi.
+pub fn cosine_hemisphere(u1: Float, u2: Float) -> Vector3<Float> {
+    let z = (1.0 - u2).sqrt();
+    let phi = 2.0 * float::consts::PI * u1;
+    let x = phi.cos() * u2.sqrt();
+    let y = phi.sin() * u2.sqrt();
+    Vector3::new(x, y, z)
+}
+
+pub fn cosine_hemisphere_pdf(cos_theta: Float) -> Float {
+    cos_theta.max(0.0) / float::consts::PI
+}
+
+// Uniform direction on the whole sphere: u1 picks the height, u2 the azimuth.
+pub fn uniform_sphere(u1: Float, u2: Float) -> Vector3<Float> {
+    let z = -1.0 + 2.0 * u1;
+    let phi = 2.0 * float::consts::PI * u2;
+    let r = (1.0 - z * z).max(0.0).sqrt();
+    Vector3::new(r * phi.cos(), r * phi.sin(), z)
+}
+
+pub fn uniform_sphere_pdf() -> Float {
+    1.0 / (4.0 * float::consts::PI)
+}
+
+// Uniform direction in the cone around z whose half angle has cosine cos_theta_max:
+// u1 picks the azimuth, u2 the height. A sphere of radius r at distance d fills the
+// cone with cos_theta_max = sqrt(1 - r^2 / d^2).
+pub fn uniform_cone(u1: Float, u2: Float, cos_theta_max: Float) -> Vector3<Float> {
+    let z = 1.0 + u2 * (cos_theta_max - 1.0);
+    let phi = 2.0 * float::consts::PI * u1;
+    let r = (1.0 - z * z).max(0.0).sqrt();
+    Vector3::new(r * phi.cos(), r * phi.sin(), z)
+}
+
+pub fn uniform_cone_pdf(cos_theta_max: Float) -> Float {
+    1.0 / (2.0 * float::consts::PI * (1.0 - cos_theta_max))
+}
+
+// Multiple importance sampling weight of a sample drawn n_f times from f, when g
+// could also have drawn it n_g times.
+pub fn balance_heuristic(n_f: usize, pdf_f: Float, n_g: usize, pdf_g: Float) -> Float {
+    let f = n_f as Float * pdf_f;
+    let g = n_g as Float * pdf_g;
+    if f + g > 0.0 {
+        f / (f + g)
+    } else {
+        0.0
+    }
+}
+
+// Veach's power heuristic with exponent two, which leans harder on the better strategy.
+pub fn power_heuristic(n_f: usize, pdf_f: Float, n_g: usize, pdf_g: Float) -> Float {
+    let f = n_f as Float * pdf_f;
+    let g = n_g as Float * pdf_g;
+    if f * f + g * g > 0.0 {
+        f * f / (f * f + g * g)
+    } else {
+        0.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rng;
+
+    const N: usize = 100_000;
+
+    fn samples(warp: impl Fn(Float, Float) -> Vector3<Float>) -> Vec<Vector3<Float>> {
+        rng::seed(Default::default(), 7);
+        (0..N)
+            .map(|_| warp(rng::uniform(), rng::uniform()))
+            .collect()
+    }
+
+    #[test]
+    fn onb_is_orthonormal_around_w() {
+        let normals = samples(uniform_sphere).into_iter().take(1000).chain([
+            Vector3::new(1.0, 0.0, 0.0),
+            Vector3::new(0.0, -1.0, 0.0),
+            Vector3::new(0.0, 0.0, 3.0),
+        ]);
+        for n in normals {
+            let uvw = ONB::build_from_w(&n);
+            for (a, b) in [(uvw.u(), uvw.v()), (uvw.v(), uvw.w()), (uvw.w(), uvw.u())] {
+                assert!(a.dot(&b).abs() < 1e-5);
+                assert!((a.norm() - 1.0).abs() < 1e-5);
+            }
+            assert!((uvw.w() - n.normalize()).norm() < 1e-5);
+            let d = Vector3::new(0.3, -0.5, 0.8);
+            assert!((uvw.to_local(&uvw.local(&d)) - d).norm() < 1e-5);
+        }
+    }
+
+    // Every warp gives unit directions inside its support, and E[1 / pdf] recovers
+    // the support's solid angle, so each pdf integrates to one over what it samples.
+    fn check_warp(
+        warp: impl Fn(Float, Float) -> Vector3<Float>,
+        pdf: impl Fn(&Vector3<Float>) -> Float,
+        min_z: Float,
+        solid_angle: Float,
+    ) {
+        let directions = samples(warp);
+        for d in &directions {
+            assert!((d.norm() - 1.0).abs() < 1e-4, "not unit: {:?}", d);
+            assert!(d.z >= min_z - 1e-5, "outside the support: {:?}", d);
+        }
+        let estimate = directions.iter().map(|d| 1.0 / pdf(d)).sum::<Float>() / N as Float;
+        assert!(
+            (estimate - solid_angle).abs() < 0.02 * solid_angle,
+            "solid angle {} against {}",
+            estimate,
+            solid_angle
+        );
+    }
+
+    #[test]
+    fn cosine_hemisphere_matches_its_pdf() {
+        check_warp(
+            cosine_hemisphere,
+            |d| cosine_hemisphere_pdf(d.z),
+            0.0,
+            2.0 * float::consts::PI,
+        );
+        // the mean cosine of a cosine weighted hemisphere is 2/3
+        let mean = samples(cosine_hemisphere)
+            .iter()
+            .map(|d| d.z)
+            .sum::<Float>()
+            / N as Float;
+        assert!((mean - 2.0 / 3.0).abs() < 0.01, "mean cosine {}", mean);
+    }
+
+    #[test]
+    fn uniform_sphere_matches_its_pdf() {
+        check_warp(
+            uniform_sphere,
+            |_| uniform_sphere_pdf(),
+            -1.0,
+            4.0 * float::consts::PI,
+        );
+        let mean = samples(uniform_sphere).iter().sum::<Vector3<Float>>() / N as Float;
+        assert!(mean.norm() < 0.02, "mean direction {:?}", mean);
+    }
+
+    #[test]
+    fn uniform_cone_matches_its_pdf() {
+        for cos_theta_max in [0.0, 0.5, 0.9, 0.999] {
+            check_warp(
+                |u1, u2| uniform_cone(u1, u2, cos_theta_max),
+                |_| uniform_cone_pdf(cos_theta_max),
+                cos_theta_max,
+                2.0 * float::consts::PI * (1.0 - cos_theta_max),
+            );
+            let samples = samples(|u1, u2| uniform_cone(u1, u2, cos_theta_max));
+            let mean = samples.iter().map(|d| d.z).sum::<Float>() / N as Float;
+            assert!((mean - 0.5 * (1.0 + cos_theta_max)).abs() < 0.01);
+        }
+    }
+
+    #[test]
+    fn heuristic_weights_sum_to_one() {
+        for (pdf_f, pdf_g) in [(0.1, 3.0), (1.0, 1.0), (5.0, 0.0), (0.25, 0.5)] {
+            for heuristic in [balance_heuristic, power_heuristic] {
+                let w = heuristic(1, pdf_f, 2, pdf_g) + heuristic(2, pdf_g, 1, pdf_f);
+                assert!((w - 1.0).abs() < 1e-6);
+            }
+            // the power heuristic moves weight toward the more likely strategy
+            if pdf_f > 2.0 * pdf_g {
+                assert!(
+                    power_heuristic(1, pdf_f, 2, pdf_g) >= balance_heuristic(1, pdf_f, 2, pdf_g)
+                );
+            }
+        }
+        assert_eq!(balance_heuristic(1, 0.0, 1, 0.0), 0.0);
+        assert_eq!(power_heuristic(1, 0.0, 1, 0.0), 0.0);
+    }
+}
@@ -0,0 +1,943 @@
+use crate::background::{Background, SolidColor};
+use crate::bvh::{BVH, QBVH};
+use crate::camera::{Camera, Optics, Shutter};
+use crate::cli::{Accelerator, Options};
+use crate::cube::Cube;
+use crate::float::Float;
+use crate::hittable::{FlipNormals, Hittable, HittableList, Labeled};
+use crate::kdtree::{self, KdTree};
+use crate::linking::LightLinks;
+use crate::material::{Dielectric, DiffuseLight, Lambertian, Metal};
+use crate::medium::{ConstantMedium, GroundFog, HeterogeneousMedium, NoiseDensity};
+use crate::mnee::Mnee;
+use crate::phase::HenyeyGreenstein;
+use crate::ray;
+use crate::rect::{AARect, Plane};
+use crate::rng;
+use crate::rotate::{Axis, Rotate};
+use crate::sphere::{MovingSphere, Sphere};
+use crate::subsurface::Subsurface;
+use crate::texture::{CheckerTexture, ConstantTexture, ImageTexture, NoiseTexture, Wrap};
+use crate::translate::Translate;
+use nalgebra::Vector3;
+use rand::Rng;
+use std::sync::Arc;
+
+// names accepted by --scene, in the order the books introduce them
+pub const SCENES: &[&str] = &[
+    "random_spheres",
+    "two_spheres",
+    "two_perlin_spheres",
+    "earth",
+    "simple_light",
+    "cornell_box",
+    "cornell_smoke",
+    "cornell_fog",
+    "cornell_subsurface",
+    "final_scene",
+];
+
+// background of the scenes without lights of their own
+pub const SKY: (Float, Float, Float) = (0.7, 0.8, 1.0);
+// built into the binary, so it renders the earth wherever it is installed
+const EARTH_MAP: &[u8] = include_bytes!("../../nextweek/earthmap.png");
+
+// Everything a render needs to know about the world: what rays hit, the shapes
+// sampled directly toward if any, emitters and portals, the emitters on their own,
+// the camera, what rays that miss see, and the optional caustic and linking setups.
+pub struct Scene {
+    pub world: Arc<dyn Hittable>,
+    pub light_shape: Option<Arc<dyn Hittable>>,
+    pub lights: Vec<Arc<dyn Hittable>>,
+    pub camera: Camera,
+    pub background: Arc<dyn Background>,
+    pub mnee: Option<Mnee>,
+    pub links: Option<LightLinks>,
+}
+
+impl Scene {
+    pub fn new(world: Arc<dyn Hittable>, camera: Camera) -> Self {
+        Scene {
+            world,
+            light_shape: None,
+            lights: Vec::new(),
+            camera,
+            background: Arc::new(SolidColor::default()),
+            mnee: None,
+            links: None,
+        }
+    }
+}
+
+// the named gallery scene, None for an unknown name
+pub fn by_name(name: &str, aspect: Float, options: &Options) -> Option<Scene> {
+    // textures drawing random tables while the scene is built see the same numbers
+    // every run
+    rng::seed(options.rng, options.seed);
+    let scene = match name {
+        "random_spheres" => random_spheres(aspect, options),
+        "two_spheres" => two_spheres(aspect, options),
+        "two_perlin_spheres" => two_perlin_spheres(aspect, options),
+        "earth" => earth(aspect, options),
+        "simple_light" => simple_light(aspect, options),
+        "cornell_box" => cornell_box(aspect, options),
+        "cornell_smoke" => cornell_smoke(aspect, options),
+        "cornell_fog" => cornell_fog(aspect, options),
+        "cornell_subsurface" => cornell_subsurface(aspect, options),
+        "final_scene" => final_scene(aspect, options),
+        _ => return None,
+    };
+    Some(configure_camera(scene, options))
+}
+
+// The scene with its camera focused, shaped and modelled as the options ask. Autofocus
+// measures along the ray through its point before a lens model is focused on the
+// result, and leaves the focus as it is when the ray hits nothing.
+pub fn configure_camera(scene: Scene, options: &Options) -> Scene {
+    let mut camera = scene.camera.clone();
+    if let Some((x, y)) = options.autofocus {
+        // the point is given from the top left, the camera counts from the bottom
+        let ray = camera.centre_ray(x, 1.0 - y);
+        match scene.world.hit(&ray, ray::T_MIN, Float::MAX) {
+            Some(hit) => {
+                let (origin, _, _, forward) = camera.frame();
+                camera = camera.refocused((hit.p - origin).dot(&forward));
+            }
+            None => eprintln!("the autofocus ray hits nothing; keeping the focus distance"),
+        }
+    }
+    let model = match &options.lens {
+        Some(lens) => lens.model(&camera),
+        None => options.projection.model(options.fisheye_fov),
+    };
+    Scene {
+        camera: camera.with_model(model).with_aperture(options.aperture),
+        ..scene
+    }
+}
+
+fn camera(
+    look_from: Vector3<Float>,
+    look_at: Vector3<Float>,
+    vertical_fov: Float,
+    aspect: Float,
+    aperture: Float,
+    shutter: Shutter,
+) -> Camera {
+    let (time0, time1) = shutter.interval();
+    let optics = Optics {
+        vertical_fov,
+        aperture,
+        ..Optics::default()
+    };
+    Camera::new(
+        look_from,
+        look_at,
+        Vector3::new(0.0, 1.0, 0.0),
+        aspect,
+        optics,
+        time0,
+        time1,
+    )
+}
+
+// builds the acceleration structure the options ask for
+fn accelerate(world: Vec<Box<dyn Hittable>>, options: &Options) -> Arc<dyn Hittable> {
+    accelerate_over(world, options, options.shutter)
+}
+
+// the same, bounding what moves over the given shutter's interval
+pub fn accelerate_over(
+    world: Vec<Box<dyn Hittable>>,
+    options: &Options,
+    shutter: Shutter,
+) -> Arc<dyn Hittable> {
+    let (time0, time1) = shutter.interval();
+    match options.accel {
+        Accelerator::BVH => Arc::new(BVH::with_strategy(world, time0, time1, options.bvh)),
+        Accelerator::QBVH => Arc::new(QBVH::with_strategy(world, time0, time1, options.bvh)),
+        Accelerator::KdTree => {
+            let max_depth = options
+                .kd_max_depth
+                .unwrap_or_else(|| kdtree::default_max_depth(world.len()));
+            Arc::new(KdTree::with_params(
+                world,
+                time0,
+                time1,
+                max_depth,
+                options.kd_leaf_size,
+            ))
+        }
+    }
+}
+
+fn sky(world: Vec<Box<dyn Hittable>>, cam: Camera, options: &Options) -> Scene {
+    Scene {
+        background: Arc::new(SolidColor(Vector3::new(SKY.0, SKY.1, SKY.2))),
+        ..Scene::new(accelerate(world, options), cam)
+    }
+}
+
+fn checker() -> CheckerTexture<ConstantTexture, ConstantTexture> {
+    CheckerTexture::new(
+        ConstantTexture::new(0.2, 0.3, 0.1),
+        ConstantTexture::new(0.9, 0.9, 0.9),
+    )
+}
+
+fn earth_texture() -> ImageTexture {
+    let image = image::load_from_memory(EARTH_MAP)
+        .expect("the built in earth map is a png")
+        .to_rgb8();
+    let (nx, ny) = image.dimensions();
+    ImageTexture::new(image.into_raw(), nx, ny).with_wrap(Wrap::Globe)
+}
+
+pub fn random_spheres(aspect: Float, options: &Options) -> Scene {
+    let mut rng = options.rng.seeded(options.seed);
+    let origin = Vector3::new(4.0, 0.2, 0.0);
+    let mut world: Vec<Box<dyn Hittable>> = Vec::new();
+    world.push(Box::new(Sphere::new(
+        Vector3::new(0.0, -1000.0, 0.0),
+        1000.0,
+        Lambertian::new(checker()),
+    )));
+    for a in -10..10 {
+        for b in -10..10 {
+            let choose_material = rng.gen::<Float>();
+            let center = Vector3::new(
+                a as Float + 0.9 * rng.gen::<Float>(),
+                0.2,
+                b as Float + 0.9 * rng.gen::<Float>(),
+            );
+            if (center - origin).magnitude() > 0.9 {
+                if choose_material < 0.8 {
+                    // diffuse
+                    world.push(Box::new(MovingSphere::new(
+                        center,
+                        center + Vector3::new(0.0, 0.5 * rng.gen::<Float>(), 0.0),
+                        0.0,
+                        1.0,
+                        0.2,
+                        Lambertian::new(ConstantTexture::new(
+                            rng.gen::<Float>() * rng.gen::<Float>(),
+                            rng.gen::<Float>() * rng.gen::<Float>(),
+                            rng.gen::<Float>() * rng.gen::<Float>(),
+                        )),
+                    )));
+                } else if choose_material < 0.95 {
+                    // metal
+                    world.push(Box::new(Sphere::new(
+                        center,
+                        0.2,
+                        Metal::new(
+                            Vector3::new(
+                                0.5 * (1.0 + rng.gen::<Float>()),
+                                0.5 * (1.0 + rng.gen::<Float>()),
+                                0.5 * (1.0 + rng.gen::<Float>()),
+                            ),
+                            0.5 * rng.gen::<Float>(),
+                        ),
+                    )));
+                } else {
+                    // glass
+                    world.push(Box::new(Sphere::new(center, 0.2, Dielectric::new(1.5))));
+                }
+            }
+        }
+    }
+    world.push(Box::new(Sphere::new(
+        Vector3::new(0.0, 1.0, 0.0),
+        1.0,
+        Dielectric::new(1.5),
+    )));
+    world.push(Box::new(Sphere::new(
+        Vector3::new(-4.0, 1.0, 0.0),
+        1.0,
+        Lambertian::new(ConstantTexture::new(0.4, 0.2, 0.1)),
+    )));
+    world.push(Box::new(Sphere::new(
+        Vector3::new(4.0, 1.0, 0.0),
+        1.0,
+        Metal::new(Vector3::new(0.7, 0.6, 0.5), 0.0),
+    )));
+    let look_from = Vector3::new(13.0, 2.0, 3.0);
+    let cam = camera(
+        look_from,
+        Vector3::zeros(),
+        20.0,
+        aspect,
+        0.1,
+        options.shutter,
+    );
+    sky(world, cam, options)
+}
+
+pub fn two_spheres(aspect: Float, options: &Options) -> Scene {
+    let world: Vec<Box<dyn Hittable>> = vec![
+        Box::new(Sphere::new(
+            Vector3::new(0.0, -10.0, 0.0),
+            10.0,
+            Lambertian::new(checker()),
+        )),
+        Box::new(Sphere::new(
+            Vector3::new(0.0, 10.0, 0.0),
+            10.0,
+            Lambertian::new(checker()),
+        )),
+    ];
+    let look_from = Vector3::new(13.0, 2.0, 3.0);
+    let cam = camera(
+        look_from,
+        Vector3::zeros(),
+        20.0,
+        aspect,
+        0.0,
+        options.shutter,
+    );
+    sky(world, cam, options)
+}
+
+pub fn two_perlin_spheres(aspect: Float, options: &Options) -> Scene {
+    let noise = NoiseTexture::new(4.0);
+    let world: Vec<Box<dyn Hittable>> = vec![
+        Box::new(Sphere::new(
+            Vector3::new(0.0, -1000.0, 0.0),
+            1000.0,
+            Lambertian::new(noise.clone()),
+        )),
+        Box::new(Sphere::new(
+            Vector3::new(0.0, 2.0, 0.0),
+            2.0,
+            Lambertian::new(noise),
+        )),
+    ];
+    let look_from = Vector3::new(13.0, 2.0, 3.0);
+    let cam = camera(
+        look_from,
+        Vector3::zeros(),
+        20.0,
+        aspect,
+        0.0,
+        options.shutter,
+    );
+    sky(world, cam, options)
+}
+
+pub fn earth(aspect: Float, options: &Options) -> Scene {
+    let world: Vec<Box<dyn Hittable>> = vec![Box::new(Sphere::new(
+        Vector3::zeros(),
+        2.0,
+        Lambertian::new(earth_texture()),
+    ))];
+    let look_from = Vector3::new(13.0, 2.0, 3.0);
+    let cam = camera(
+        look_from,
+        Vector3::zeros(),
+        20.0,
+        aspect,
+        0.0,
+        options.shutter,
+    );
+    sky(world, cam, options)
+}
+
+pub fn simple_light(aspect: Float, options: &Options) -> Scene {
+    let noise = NoiseTexture::new(4.0);
+    let light = DiffuseLight::new(ConstantTexture::new(4.0, 4.0, 4.0));
+    let light_sphere: Arc<dyn Hittable> =
+        Arc::new(Sphere::new(Vector3::new(0.0, 7.0, 0.0), 2.0, light.clone()));
+    let light_rect: Arc<dyn Hittable> =
+        Arc::new(AARect::new(Plane::XY, 3.0, 5.0, 1.0, 3.0, -2.0, light));
+    let world: Vec<Box<dyn Hittable>> = vec![
+        Box::new(Sphere::new(
+            Vector3::new(0.0, -1000.0, 0.0),
+            1000.0,
+            Lambertian::new(noise.clone()),
+        )),
+        Box::new(Sphere::new(
+            Vector3::new(0.0, 2.0, 0.0),
+            2.0,
+            Lambertian::new(noise),
+        )),
+        Box::new(light_sphere.clone()),
+        Box::new(light_rect.clone()),
+    ];
+    let lights = vec![light_sphere, light_rect];
+    let mut light_shapes = HittableList::default();
+    for light in &lights {
+        light_shapes.push_shared(light.clone());
+    }
+    let look_from = Vector3::new(26.0, 3.0, 6.0);
+    let cam = camera(
+        look_from,
+        Vector3::new(0.0, 2.0, 0.0),
+        20.0,
+        aspect,
+        0.0,
+        options.shutter,
+    );
+    Scene {
+        light_shape: Some(Arc::new(light_shapes)),
+        lights,
+        ..Scene::new(accelerate(world, options), cam)
+    }
+}
+
+// object ids in the cornell box, as used by --light-link and --shadow-link
+pub const GREEN_WALL: u32 = 1;
+pub const RED_WALL: u32 = 2;
+pub const LIGHT: u32 = 3;
+pub const CEILING: u32 = 4;
+pub const FLOOR: u32 = 5;
+pub const BACK_WALL: u32 = 6;
+pub const GLASS_SPHERE: u32 = 7;
+pub const ALUMINUM_BOX: u32 = 8;
+
+pub fn cornell_box(aspect: Float, options: &Options) -> Scene {
+    let red = Lambertian::new(ConstantTexture::new(0.65, 0.05, 0.05));
+    let white = Lambertian::new(ConstantTexture::new(0.73, 0.73, 0.73));
+    let green = Lambertian::new(ConstantTexture::new(0.12, 0.45, 0.15));
+    let light = DiffuseLight::new(ConstantTexture::new(15.0, 15.0, 15.0));
+    let glass = Dielectric::new(1.5);
+    let aluminum = Metal::new(Vector3::new(0.8, 0.85, 0.88), 0.0);
+    let light_shape: Arc<dyn Hittable> = Arc::new(Labeled::new(
+        FlipNormals::new(AARect::new(
+            Plane::ZX,
+            227.0,
+            332.0,
+            213.0,
+            343.0,
+            554.0,
+            light,
+        )),
+        LIGHT,
+        0,
+    ));
+    let glass_center = Vector3::new(190.0, 90.0, 190.0);
+    let glass_sphere: Arc<dyn Hittable> = Arc::new(Labeled::new(
+        Sphere::new(glass_center, 90.0, glass),
+        GLASS_SPHERE,
+        0,
+    ));
+    let mnee = if options.mnee {
+        Some(Mnee::new(glass_center, 90.0, 1.5, light_shape.clone()))
+    } else {
+        None
+    };
+    let world: Vec<Box<dyn Hittable>> = vec![
+        Box::new(Labeled::new(
+            FlipNormals::new(AARect::new(Plane::YZ, 0.0, 555.0, 0.0, 555.0, 555.0, green)),
+            GREEN_WALL,
+            0,
+        )),
+        Box::new(Labeled::new(
+            AARect::new(Plane::YZ, 0.0, 555.0, 0.0, 555.0, 0.0, red),
+            RED_WALL,
+            0,
+        )),
+        Box::new(light_shape.clone()),
+        Box::new(Labeled::new(
+            FlipNormals::new(AARect::new(
+                Plane::ZX,
+                0.0,
+                555.0,
+                0.0,
+                555.0,
+                555.0,
+                white.clone(),
+            )),
+            CEILING,
+            0,
+        )),
+        Box::new(Labeled::new(
+            AARect::new(Plane::ZX, 0.0, 555.0, 0.0, 555.0, 0.0, white.clone()),
+            FLOOR,
+            0,
+        )),
+        Box::new(Labeled::new(
+            FlipNormals::new(AARect::new(
+                Plane::XY,
+                0.0,
+                555.0,
+                0.0,
+                555.0,
+                555.0,
+                white.clone(),
+            )),
+            BACK_WALL,
+            0,
+        )),
+        Box::new(glass_sphere.clone()),
+        Box::new(Labeled::new(
+            Translate::new(
+                Rotate::new(
+                    Axis::Y,
+                    Cube::new(
+                        Vector3::new(0.0, 0.0, 0.0),
+                        Vector3::new(165.0, 330.0, 165.0),
+                        aluminum,
+                    ),
+                    15.0,
+                ),
+                Vector3::new(265.0, 0.0, 295.0),
+            ),
+            ALUMINUM_BOX,
+            0,
+        )),
+    ];
+
+    let links = if options.light_links.is_empty() && options.shadow_links.is_empty() {
+        None
+    } else {
+        let mut links = LightLinks::default();
+        for (light, receivers) in options.light_links.iter() {
+            links.link_light(*light, receivers);
+        }
+        for (light, blockers) in options.shadow_links.iter() {
+            links.link_shadow(*light, blockers);
+        }
+        links.add_target(LIGHT, light_shape.clone());
+        links.add_target(GLASS_SPHERE, glass_sphere.clone());
+        Some(links)
+    };
+
+    let mut light_shapes = HittableList::default();
+    light_shapes.push_shared(light_shape.clone());
+    light_shapes.push_shared(glass_sphere);
+
+    let look_from = Vector3::new(278.0, 278.0, -800.0);
+    let look_at = Vector3::new(278.0, 278.0, 0.0);
+    let cam = camera(look_from, look_at, 40.0, aspect, 0.0, options.shutter);
+    Scene {
+        light_shape: Some(Arc::new(light_shapes)),
+        lights: vec![light_shape],
+        mnee,
+        links,
+        ..Scene::new(accelerate(world, options), cam)
+    }
+}
+
+pub fn cornell_smoke(aspect: Float, options: &Options) -> Scene {
+    let red = Lambertian::new(ConstantTexture::new(0.65, 0.05, 0.05));
+    let white = Lambertian::new(ConstantTexture::new(0.73, 0.73, 0.73));
+    let green = Lambertian::new(ConstantTexture::new(0.12, 0.45, 0.15));
+    let light = DiffuseLight::new(ConstantTexture::new(7.0, 7.0, 7.0));
+    let light_shape: Arc<dyn Hittable> = Arc::new(FlipNormals::new(AARect::new(
+        Plane::ZX,
+        127.0,
+        432.0,
+        113.0,
+        443.0,
+        554.0,
+        light,
+    )));
+    let box1 = Translate::new(
+        Rotate::new(
+            Axis::Y,
+            Cube::new(
+                Vector3::new(0.0, 0.0, 0.0),
+                Vector3::new(165.0, 165.0, 165.0),
+                white.clone(),
+            ),
+            -18.0,
+        ),
+        Vector3::new(130.0, 0.0, 65.0),
+    );
+    let box2 = Translate::new(
+        Rotate::new(
+            Axis::Y,
+            Cube::new(
+                Vector3::new(0.0, 0.0, 0.0),
+                Vector3::new(165.0, 330.0, 165.0),
+                white.clone(),
+            ),
+            15.0,
+        ),
+        Vector3::new(265.0, 0.0, 295.0),
+    );
+    let world: Vec<Box<dyn Hittable>> = vec![
+        Box::new(FlipNormals::new(AARect::new(
+            Plane::YZ,
+            0.0,
+            555.0,
+            0.0,
+            555.0,
+            555.0,
+            green,
+        ))),
+        Box::new(AARect::new(Plane::YZ, 0.0, 555.0, 0.0, 555.0, 0.0, red)),
+        Box::new(light_shape.clone()),
+        Box::new(FlipNormals::new(AARect::new(
+            Plane::ZX,
+            0.0,
+            555.0,
+            0.0,
+            555.0,
+            555.0,
+            white.clone(),
+        ))),
+        Box::new(AARect::new(
+            Plane::ZX,
+            0.0,
+            555.0,
+            0.0,
+            555.0,
+            0.0,
+            white.clone(),
+        )),
+        Box::new(FlipNormals::new(AARect::new(
+            Plane::XY,
+            0.0,
+            555.0,
+            0.0,
+            555.0,
+            555.0,
+            white,
+        ))),
+        Box::new(ConstantMedium::with_phase(
+            box1,
+            0.01,
+            ConstantTexture::new(1.0, 1.0, 1.0),
+            HenyeyGreenstein::new(options.medium_g),
+        )),
+        Box::new(ConstantMedium::with_phase(
+            box2,
+            0.01,
+            ConstantTexture::new(0.0, 0.0, 0.0),
+            HenyeyGreenstein::new(options.medium_g),
+        )),
+    ];
+    let look_from = Vector3::new(278.0, 278.0, -800.0);
+    let look_at = Vector3::new(278.0, 278.0, 0.0);
+    let cam = camera(look_from, look_at, 40.0, aspect, 0.0, options.shutter);
+    Scene {
+        lights: vec![light_shape.clone()],
+        light_shape: Some(light_shape),
+        ..Scene::new(accelerate(world, options), cam)
+    }
+}
+
+// The Cornell box with ground fog thinning upward from the floor and a wisp of
+// smoke over the boxes, both with densities varying through space.
+pub fn cornell_fog(aspect: Float, options: &Options) -> Scene {
+    let red = Lambertian::new(ConstantTexture::new(0.65, 0.05, 0.05));
+    let white = Lambertian::new(ConstantTexture::new(0.73, 0.73, 0.73));
+    let green = Lambertian::new(ConstantTexture::new(0.12, 0.45, 0.15));
+    let light = DiffuseLight::new(ConstantTexture::new(15.0, 15.0, 15.0));
+    let light_shape: Arc<dyn Hittable> = Arc::new(FlipNormals::new(AARect::new(
+        Plane::ZX,
+        213.0,
+        343.0,
+        227.0,
+        332.0,
+        554.0,
+        light,
+    )));
+    let box1 = Translate::new(
+        Rotate::new(
+            Axis::Y,
+            Cube::new(
+                Vector3::new(0.0, 0.0, 0.0),
+                Vector3::new(165.0, 165.0, 165.0),
+                white.clone(),
+            ),
+            -18.0,
+        ),
+        Vector3::new(130.0, 0.0, 65.0),
+    );
+    let box2 = Translate::new(
+        Rotate::new(
+            Axis::Y,
+            Cube::new(
+                Vector3::new(0.0, 0.0, 0.0),
+                Vector3::new(165.0, 330.0, 165.0),
+                white.clone(),
+            ),
+            15.0,
+        ),
+        Vector3::new(265.0, 0.0, 295.0),
+    );
+    let fog = HeterogeneousMedium::with_phase(
+        Cube::new(
+            Vector3::new(1.0, 1.0, 1.0),
+            Vector3::new(554.0, 554.0, 554.0),
+            white.clone(),
+        ),
+        GroundFog::new(0.01, 0.01, 0.0, 60.0),
+        ConstantTexture::new(1.0, 1.0, 1.0),
+        HenyeyGreenstein::new(options.medium_g),
+    );
+    let smoke = HeterogeneousMedium::with_phase(
+        Sphere::new(Vector3::new(278.0, 420.0, 278.0), 110.0, white.clone()),
+        NoiseDensity::new(0.02, 0.03),
+        ConstantTexture::new(0.9, 0.9, 0.9),
+        HenyeyGreenstein::new(options.medium_g),
+    );
+    let world: Vec<Box<dyn Hittable>> = vec![
+        Box::new(FlipNormals::new(AARect::new(
+            Plane::YZ,
+            0.0,
+            555.0,
+            0.0,
+            555.0,
+            555.0,
+            green,
+        ))),
+        Box::new(AARect::new(Plane::YZ, 0.0, 555.0, 0.0, 555.0, 0.0, red)),
+        Box::new(light_shape.clone()),
+        Box::new(FlipNormals::new(AARect::new(
+            Plane::ZX,
+            0.0,
+            555.0,
+            0.0,
+            555.0,
+            555.0,
+            white.clone(),
+        ))),
+        Box::new(AARect::new(
+            Plane::ZX,
+            0.0,
+            555.0,
+            0.0,
+            555.0,
+            0.0,
+            white.clone(),
+        )),
+        Box::new(FlipNormals::new(AARect::new(
+            Plane::XY,
+            0.0,
+            555.0,
+            0.0,
+            555.0,
+            555.0,
+            white,
+        ))),
+        Box::new(box1),
+        Box::new(box2),
+        Box::new(fog),
+        Box::new(smoke),
+    ];
+    let look_from = Vector3::new(278.0, 278.0, -800.0);
+    let look_at = Vector3::new(278.0, 278.0, 0.0);
+    let cam = camera(look_from, look_at, 40.0, aspect, 0.0, options.shutter);
+    Scene {
+        lights: vec![light_shape.clone()],
+        light_shape: Some(light_shape),
+        ..Scene::new(accelerate(world, options), cam)
+    }
+}
+
+// the Cornell box with a wax sphere on a marble block, lit through them
+pub fn cornell_subsurface(aspect: Float, options: &Options) -> Scene {
+    let red = Lambertian::new(ConstantTexture::new(0.65, 0.05, 0.05));
+    let white = Lambertian::new(ConstantTexture::new(0.73, 0.73, 0.73));
+    let green = Lambertian::new(ConstantTexture::new(0.12, 0.45, 0.15));
+    let light = DiffuseLight::new(ConstantTexture::new(15.0, 15.0, 15.0));
+    let light_shape: Arc<dyn Hittable> = Arc::new(FlipNormals::new(AARect::new(
+        Plane::ZX,
+        213.0,
+        343.0,
+        227.0,
+        332.0,
+        554.0,
+        light,
+    )));
+    let marble = Subsurface::new(
+        Translate::new(
+            Rotate::new(
+                Axis::Y,
+                Cube::new(
+                    Vector3::new(0.0, 0.0, 0.0),
+                    Vector3::new(165.0, 165.0, 165.0),
+                    white.clone(),
+                ),
+                -18.0,
+            ),
+            Vector3::new(130.0, 0.0, 65.0),
+        ),
+        Vector3::new(20.0, 20.0, 25.0),
+        Vector3::new(0.99, 0.99, 0.99),
+        0.0,
+    );
+    let wax = Subsurface::new(
+        Sphere::new(Vector3::new(212.0, 265.0, 147.0), 100.0, white.clone()),
+        Vector3::new(40.0, 15.0, 6.0),
+        Vector3::new(0.995, 0.97, 0.9),
+        options.medium_g,
+    );
+    let box2 = Translate::new(
+        Rotate::new(
+            Axis::Y,
+            Cube::new(
+                Vector3::new(0.0, 0.0, 0.0),
+                Vector3::new(165.0, 330.0, 165.0),
+                white.clone(),
+            ),
+            15.0,
+        ),
+        Vector3::new(265.0, 0.0, 295.0),
+    );
+    let world: Vec<Box<dyn Hittable>> = vec![
+        Box::new(FlipNormals::new(AARect::new(
+            Plane::YZ,
+            0.0,
+            555.0,
+            0.0,
+            555.0,
+            555.0,
+            green,
+        ))),
+        Box::new(AARect::new(Plane::YZ, 0.0, 555.0, 0.0, 555.0, 0.0, red)),
+        Box::new(light_shape.clone()),
+        Box::new(FlipNormals::new(AARect::new(
+            Plane::ZX,
+            0.0,
+            555.0,
+            0.0,
+            555.0,
+            555.0,
+            white.clone(),
+        ))),
+        Box::new(AARect::new(
+            Plane::ZX,
+            0.0,
+            555.0,
+            0.0,
+            555.0,
+            0.0,
+            white.clone(),
+        )),
+        Box::new(FlipNormals::new(AARect::new(
+            Plane::XY,
+            0.0,
+            555.0,
+            0.0,
+            555.0,
+            555.0,
+            white,
+        ))),
+        Box::new(marble),
+        Box::new(wax),
+        Box::new(box2),
+    ];
+    let look_from = Vector3::new(278.0, 278.0, -800.0);
+    let look_at = Vector3::new(278.0, 278.0, 0.0);
+    let cam = camera(look_from, look_at, 40.0, aspect, 0.0, options.shutter);
+    Scene {
+        lights: vec![light_shape.clone()],
+        light_shape: Some(light_shape),
+        ..Scene::new(accelerate(world, options), cam)
+    }
+}
+
+// the closing image of The Next Week
+pub fn final_scene(aspect: Float, options: &Options) -> Scene {
+    let mut rng = options.rng.seeded(options.seed);
+    let white = Lambertian::new(ConstantTexture::new(0.73, 0.73, 0.73));
+    let ground = Lambertian::new(ConstantTexture::new(0.48, 0.83, 0.53));
+    let mut world: Vec<Box<dyn Hittable>> = Vec::new();
+    // the ground's boxes and the cloud of spheres are each of one kind, so their
+    // BVHs hold them by value
+    let mut box_list1 = Vec::new();
+    for i in 0..20 {
+        for j in 0..20 {
+            let w = 100.0;
+            let x0 = -1000.0 + i as Float * w;
+            let z0 = -1000.0 + j as Float * w;
+            let y0 = 0.0;
+            let x1 = x0 + w;
+            let y1 = 100.0 * (rng.gen::<Float>() + 0.01);
+            let z1 = z0 + w;
+            box_list1.push(Cube::new(
+                Vector3::new(x0, y0, z0),
+                Vector3::new(x1, y1, z1),
+                ground.clone(),
+            ));
+        }
+    }
+    world.push(Box::new(BVH::new(box_list1, 0.0, 1.0)));
+    let light = DiffuseLight::new(ConstantTexture::new(7.0, 7.0, 7.0));
+    let light_shape: Arc<dyn Hittable> = Arc::new(FlipNormals::new(AARect::new(
+        Plane::ZX,
+        147.0,
+        412.0,
+        123.0,
+        423.0,
+        554.0,
+        light,
+    )));
+    world.push(Box::new(light_shape.clone()));
+    let center = Vector3::new(400.0, 400.0, 200.0);
+    world.push(Box::new(MovingSphere::new(
+        center,
+        center + Vector3::new(30.0, 0.0, 0.0),
+        0.0,
+        1.0,
+        50.0,
+        Lambertian::new(ConstantTexture::new(0.7, 0.3, 0.1)),
+    )));
+    world.push(Box::new(Sphere::new(
+        Vector3::new(260.0, 150.0, 45.0),
+        50.0,
+        Dielectric::new(1.5),
+    )));
+    world.push(Box::new(Sphere::new(
+        Vector3::new(0.0, 150.0, 145.0),
+        50.0,
+        Metal::new(Vector3::new(0.8, 0.8, 0.9), 10.0),
+    )));
+    let boundary = Sphere::new(
+        Vector3::new(360.0, 150.0, 145.0),
+        70.0,
+        Dielectric::new(1.5),
+    );
+    world.push(Box::new(boundary.clone()));
+    world.push(Box::new(ConstantMedium::with_phase(
+        boundary,
+        0.2,
+        ConstantTexture::new(0.2, 0.4, 0.9),
+        HenyeyGreenstein::new(options.medium_g),
+    )));
+    let boundary = Sphere::new(Vector3::zeros(), 5000.0, Dielectric::new(1.5));
+    world.push(Box::new(ConstantMedium::with_phase(
+        boundary,
+        0.0001,
+        ConstantTexture::new(1.0, 1.0, 1.0),
+        HenyeyGreenstein::new(options.medium_g),
+    )));
+    world.push(Box::new(Sphere::new(
+        Vector3::new(400.0, 200.0, 400.0),
+        100.0,
+        Lambertian::new(earth_texture()),
+    )));
+    world.push(Box::new(Sphere::new(
+        Vector3::new(220.0, 280.0, 300.0),
+        80.0,
+        Lambertian::new(NoiseTexture::new(0.1)),
+    )));
+    let mut box_list2 = Vec::new();
+    for _ in 0..1000 {
+        box_list2.push(Sphere::new(
+            Vector3::new(
+                165.0 * rng.gen::<Float>(),
+                165.0 * rng.gen::<Float>(),
+                165.0 * rng.gen::<Float>(),
+            ),
+            10.0,
+            white.clone(),
+        ));
+    }
+    world.push(Box::new(Translate::new(
+        Rotate::new(Axis::Y, BVH::new(box_list2, 0.0, 1.0), 15.0),
+        Vector3::new(-100.0, 270.0, 395.0),
+    )));
+    let look_from = Vector3::new(478.0, 278.0, -600.0);
+    let look_at = Vector3::new(278.0, 278.0, 0.0);
+    let cam = camera(look_from, look_at, 40.0, aspect, 0.0, options.shutter);
+    Scene {
+        lights: vec![light_shape.clone()],
+        light_shape: Some(light_shape),
+        ..Scene::new(accelerate(world, options), cam)
+    }
+}
@@ -0,0 +1,1758 @@
+use crate::alpha::Cutout;
+use crate::background::{Background, Gradient, Hdri, Sky, SolidColor};
+use crate::camera::{Camera, CameraPath, Movements, Optics, Shutter};
+use crate::cli::Options;
+use crate::csg::{Operation, CSG};
+use crate::cube::{Cube, CubeLayout};
+use crate::decal::{Decal, Projection};
+use crate::float::Float;
+use crate::gltf;
+use crate::heightfield::{self, Heightfield};
+use crate::hittable::{FlipNormals, Hittable, HittableList};
+use crate::material::{
+    Anisotropic, Conductor, Dielectric, DiffuseLight, Lambertian, Masked, Material, Metal,
+    MetalPreset, Mix, ThinFilm, TwoSided,
+};
+use crate::medium::SparseVolume;
+use crate::meshfile::{self, MeshData};
+use crate::motion::{Keyframes, Moving, Turning};
+use crate::pbrt;
+use crate::perlin::Octaves;
+use crate::phase::HenyeyGreenstein;
+use crate::portal::{self, Portal};
+use crate::rect::{self, AARect, FaceUv, Plane};
+use crate::rotate::{Axis, Rotate};
+use crate::scene::{self, Scene};
+use crate::sdf::{Primitive, Sdf};
+use crate::spectrum::Dispersion;
+use crate::sphere::Sphere;
+use crate::subsurface::Subsurface;
+use crate::sun::{self, Sun, SunAndSky};
+use crate::texture::{
+    ConstantTexture, Fractal, FractalTexture, ImageTexture, Texture, TransformedTexture,
+    UvTransform, VoxelTexture, WoodTexture, Wrap,
+};
+use crate::translate::Translate;
+use crate::vdb;
+use crate::volume::SparseGrid;
+use nalgebra::Vector3;
+use std::collections::HashMap;
+use std::fs;
+use std::iter::Peekable;
+use std::str::SplitWhitespace;
+use std::sync::Arc;
+
+// A plain text scene description, one statement per line, # starting a comment:
+//
+//   camera from <x y z> at <x y z> [up <x y z>] [fov <degrees>] [aperture <a>]
+//          [focus <distance>] [shift <x> <y>] [tilt <degrees>] [swing <degrees>]
+//          [from_keys <t x y z>...] [at_keys <t x y z>...]
+//          [lens_keys <t fov aperture focus>...]
+//   shutter <open> <close>
+//   motion_blur <on|off>
+//   background <r g b> | gradient [<horizon r g b> <zenith r g b>]
+//              | hdri <path> [rotation <degrees>] [intensity <s>]
+//              | sky <sun x y z> [intensity <s>]
+//   sun <x y z> <irradiance r g b> [diameter <degrees>]
+//   include <path>
+//   texture <name> <image|wood|voxels|fbm|ridged|worley> ..., as the materials
+//   material <name> lambertian <r g b> | lambertian <texture>
+//                   | like <material> [albedo <r g b>] [texture <texture>]
+//                     [fuzz <f>] [roughness <along> <across>] [ior <n>]
+//                     [thickness <nm>] [factor <f>] [emit <r g b>]
+//                   | metal <r g b> <fuzz> | dielectric <ior>
+//                   | conductor <gold|silver|copper|aluminium> <fuzz>
+//                   | conductor <eta r g b> <k r g b> <fuzz>
+//                   | anisotropic <r g b> <alpha along> <alpha across>
+//                   | dielectric cauchy <a> <b> | dielectric sellmeier <b1 b2 b3> <c1 c2 c3>
+//                   | dielectric bk7 | dielectric sf11
+//                   | thin_film <ior> <thickness nm> <base material> [substrate <ior>]
+//                   | mix <material> <material> <factor> | two_sided <front> <back>
+//                   | cutout <material> <mask image> [threshold <t>]
+//                   | light <r g b> | subsurface <mean free path r g b> <albedo r g b>
+//                   | vertex_colors
+//                   | image <path> [wrap <clamp|repeat|globe>] [offset <u v>]
+//                     [scale <u v>] [rotate <degrees>]
+//                   | wood <light r g b> <dark r g b> [rings <n>] [turbulence <t>]
+//                   | voxels <path> <x y z> <size> [low <r g b>] [high <r g b>]
+//                   | <fbm|ridged|worley> <low r g b> <high r g b> [frequency <f>]
+//                     [octaves <n>] [lacunarity <l>] [gain <g>] [edges]
+//   sphere <x y z> <radius> <material> [flip] [object_space] [motion]
+//   rect <xy|yz|zx> <a0> <a1> <b0> <b1> <k> <material> [tiles <u v>] [flip]
+//        [object_space] [motion]
+//   box <x y z> <x y z> <material> [tiles <u v> | cross] [flip] [object_space]
+//       [motion]
+//   mesh <path> <x y z> <size> <material> [flip] [object_space] [motion]
+//   csg <union|intersection|difference> <solid> <solid> <material> [flip]
+//       [object_space] [motion]
+//   sdf sphere <x y z> <radius> | torus <x y z> <major> <minor>
+//       | rounded_box <x y z> <x y z> <radius>, then <material> [flip]
+//       [object_space] [motion]
+//   heightfield image <path> | noise <nx> <nz> <scale>, then <x y z> <size x>
+//       <size z> <height> <material> [flip] [object_space] [motion]
+//   volume <path> <x y z> <size> [density <d>] [albedo <r g b>] [g <g>]
+//   portal <xy|yz|zx> <a0> <a1> <b0> <b1> <k>
+//   group <name>
+//   end
+//   place <group> [at <x y z>] [turn <x y z>]
+//
+// where any object may also end with
+//
+//   decal <material> <x y z> <facing x y z> <width> <height> <depth> [up <x y z>]
+//         [opacity <o>] [angle <degrees>]
+//
+// and motion is keys <t x y z>... or turn_keys <t x y z>... or both, and a solid
+// is sphere <x y z> <radius> or box <x y z> <x y z>. An sdf is sphere traced, see
+// sdf.rs: a torus rings the y axis through its centre, major from there to the
+// middle of its tube, minor the tube's radius, and a rounded box spans its corners
+// with its edges rounded off by radius. A heightfield is terrain over size x by
+// size z from x y z, rising up to height above it, see heightfield.rs: by the
+// luminance of an image's pixels, or by turbulent noise over nx by nz samples
+// scale apart in the noise. A decal projects its material, often
+// an image, onto the object where it lies within a box width by height across
+// and depth deep, centred at x y z and looking along facing, with up, y unless
+// given, toward the image's top, see decal.rs. Surfaces turned further than
+// angle, 90 unless given, from facing keep their own material, and opacity, 1
+// unless given, is the share of hits that take the decal's. It moves with the
+// object. Keys move an
+// object by the offset keyed at each time, linearly in between, so the file
+// describes what moves as well as when the shutter is open. Turn keys turn it about
+// its centre by the angles keyed, in degrees about x, then y, then z, before keys
+// move it. The camera's from_keys and at_keys move its look-from and look-at points
+// the same way, and its lens_keys set its fov, aperture and focus distance at the
+// shutter's opening, so they change between the frames of an animation but not
+// within one. Shift, tilt and
+// swing are the camera's tilt-shift movements, see camera::Movements. Its shutter and
+// motion blur settings replace the command line's. Emitters that do not move are
+// sampled directly as lights. A volume loads the first grid of an OpenVDB file, see
+// vdb::read, centred at x y z and scaled so its longest side is size long; its
+// voxel values are multiplied by density, and g sets its Henyey-Greenstein
+// asymmetry in place of --medium-g. Cauchy and Sellmeier dielectrics disperse
+// light in --spectral renders, see spectrum::Dispersion for their units. A thin
+// film coats a material declared before it, over a substrate of index 1 unless
+// given, as the air inside a soap bubble. Mixes and two sided materials combine
+// materials declared before them too; a mix takes factor of its second. A cutout
+// is the material declared before it with holes where the red channel of its mask
+// image, mapped over the shape's uv coordinates, is below threshold, 0.5 unless
+// given; rays pass through the holes. A mesh loads a PLY or STL file, see
+// meshfile::read, centred and scaled as a volume is, smooth shaded if the file
+// gives normals. Its vertices' colours are diffuse albedo under the vertex_colors
+// material, which only meshes with colours take and other materials cannot combine.
+// The background is what rays that leave the scene see, black unless given: one
+// colour, the book's white to blue gradient by height or another, an environment
+// map in latitude-longitude layout, or a procedural sky lit by a sun in the given
+// direction, see background.rs.
+// Wood and voxels are diffuse materials whose albedo is a solid texture, looked up
+// by where in space a point is rather than by uv: wood's growth rings circle the y
+// axis, 4 to a unit and wavering by half a ring unless given, see
+// texture::WoodTexture, and voxels blend from low, black unless given, to high,
+// white unless given, by the first grid of an OpenVDB file over its largest value,
+// placed as a volume is. Fbm, ridged and worley blend from low to high by
+// fractal noise, see texture::Fractal, of the point times frequency, 1 unless
+// given: 6 octaves unless given, each lacunarity, 2, times the frequency and gain,
+// 0.5, times the weight of the one before. Worley's edges shade the cracks between
+// its cells instead of the cells. Solid textures stay where they are in space as
+// an object moves through them, unless it is marked object_space, when they move
+// and turn with it.
+// Image is diffuse with its albedo read from an image file by u and v, and beyond
+// its edges as wrap says, clamped unless given; globe repeats it around a sphere
+// and clamps it at the poles. Offset, scale and rotate move it over the surface,
+// see texture::UvTransform, so a map can be turned about a globe. A rect's u
+// and v run 0 to 1 along its a and b axes, and a box's across each face, upright
+// seen from outside; tiles repeats the image u by v times across each, and cross
+// lays a box's faces out as a die's net, see cube::CubeLayout.
+// Materials and textures are named so any number of objects can share one, and a
+// file of them can serve as a palette other files include: include reads a file's
+// statements, from the working directory, as if they stood in its place. A
+// texture statement names a texture for lambertian to take as albedo, and like
+// copies a material declared before with the parameters given changed, each only
+// for the kinds that have it, so brushed_steel can be redone rougher without
+// repeating it.
+// Group and end gather the objects between them into a named group instead of
+// the scene, and place puts a copy of every object in a group into the scene, or
+// into the group being defined, so groups nest: turned about the group's origin
+// by the angles after turn, in degrees about x, then y, then z, and then moved to
+// at. A group is defined once, before it is placed, and holds no volumes; its
+// objects' motion and object_space carry over to every copy.
+// The sun is a distant light toward x y z lighting a surface facing it with the
+// irradiance given, its disc 0.53 degrees across unless given, see sun.rs; a wider
+// disc softens its shadows. It shines over whatever background there is, which
+// should not be a sky with a sun of its own.
+// A portal marks a window or other opening the background lights the scene
+// through, a rectangle as rect takes it, see portal.rs; it is not an object, and
+// only makes interiors lit from outside less noisy.
+
+struct Statement<'a> {
+    words: Peekable<SplitWhitespace<'a>>,
+}
+
+impl<'a> Statement<'a> {
+    fn word(&mut self) -> Result<&'a str, String> {
+        self.words
+            .next()
+            .ok_or_else(|| String::from("unexpected end of statement"))
+    }
+
+    fn number(&mut self) -> Result<Float, String> {
+        let word = self.word()?;
+        word.parse()
+            .map_err(|_| format!("expected a number, found {}", word))
+    }
+
+    fn vector(&mut self) -> Result<Vector3<Float>, String> {
+        Ok(Vector3::new(self.number()?, self.number()?, self.number()?))
+    }
+
+    fn next_is_number(&mut self) -> bool {
+        self.words
+            .peek()
+            .is_some_and(|w| w.parse::<Float>().is_ok())
+    }
+
+    fn end(&mut self) -> Result<(), String> {
+        match self.words.next() {
+            Some(word) => Err(format!("unexpected {}", word)),
+            None => Ok(()),
+        }
+    }
+}
+
+// what a background statement names, its options included
+fn background(statement: &mut Statement) -> Result<Arc<dyn Background>, String> {
+    if statement.next_is_number() {
+        return Ok(Arc::new(SolidColor(statement.vector()?)));
+    }
+    match statement.word()? {
+        "gradient" if statement.next_is_number() => Ok(Arc::new(Gradient {
+            horizon: statement.vector()?,
+            zenith: statement.vector()?,
+        })),
+        "gradient" => Ok(Arc::new(Gradient::default())),
+        "hdri" => {
+            let mut hdri = Hdri::open(statement.word()?)?;
+            while let Some(option) = statement.words.next() {
+                match option {
+                    "rotation" => hdri.rotation = statement.number()?,
+                    "intensity" => hdri.intensity = statement.number()?,
+                    _ => return Err(format!("unexpected {}", option)),
+                }
+            }
+            Ok(Arc::new(hdri))
+        }
+        "sky" => {
+            let sun = statement.vector()?;
+            if sun.norm() == 0.0 {
+                return Err(String::from("the sun needs a direction"));
+            }
+            let mut sky = Sky::new(sun);
+            if statement.words.next_if_eq(&"intensity").is_some() {
+                sky.intensity = statement.number()?;
+            }
+            Ok(Arc::new(sky))
+        }
+        word => Err(format!("unknown background {}", word)),
+    }
+}
+
+// The camera a file places
+pub struct CameraDescription {
+    pub from: Vector3<Float>,
+    pub at: Vector3<Float>,
+    pub up: Vector3<Float>,
+    pub fov: Float,
+    pub aperture: Float,
+    pub focus: Float,
+    pub movements: Movements,
+    pub from_keys: Option<Keyframes>,
+    pub at_keys: Option<Keyframes>,
+    // fov, aperture and focus distance
+    pub lens_keys: Option<Keyframes>,
+}
+
+fn camera(statement: &mut Statement) -> Result<CameraDescription, String> {
+    let mut camera = CameraDescription {
+        from: Vector3::zeros(),
+        at: Vector3::new(0.0, 0.0, -1.0),
+        up: Vector3::new(0.0, 1.0, 0.0),
+        fov: 40.0,
+        aperture: 0.0,
+        focus: 10.0,
+        movements: Movements::default(),
+        from_keys: None,
+        at_keys: None,
+        lens_keys: None,
+    };
+    while let Some(word) = statement.words.next() {
+        match word {
+            "from" => camera.from = statement.vector()?,
+            "at" => camera.at = statement.vector()?,
+            "up" => camera.up = statement.vector()?,
+            "fov" => camera.fov = statement.number()?,
+            "aperture" => camera.aperture = statement.number()?,
+            "focus" => camera.focus = statement.number()?,
+            "shift" => camera.movements.shift = (statement.number()?, statement.number()?),
+            "tilt" => camera.movements.tilt = statement.number()?,
+            "swing" => camera.movements.swing = statement.number()?,
+            "from_keys" => camera.from_keys = Some(keyframes(statement)?),
+            "at_keys" => camera.at_keys = Some(keyframes(statement)?),
+            "lens_keys" => camera.lens_keys = Some(keyframes(statement)?),
+            _ => return Err(format!("unknown camera setting {}", word)),
+        }
+    }
+    Ok(camera)
+}
+
+#[derive(Clone, Copy, Debug)]
+pub enum MaterialDescription {
+    Lambertian(Vector3<Float>),
+    Metal(Vector3<Float>, Float),
+    // eta, k and fuzz
+    Conductor(Vector3<Float>, Vector3<Float>, Float),
+    // albedo and the roughness along and across the tangent
+    Anisotropic(Vector3<Float>, Float, Float),
+    Dielectric(Float),
+    Dispersive(Dispersion),
+    Light(Vector3<Float>),
+    // mean free path and single scattering albedo per channel
+    Subsurface(Vector3<Float>, Vector3<Float>),
+    // the materials declared before it at indices a and b, b's share of the mix
+    Mix {
+        a: usize,
+        b: usize,
+        factor: Float,
+    },
+    // the materials declared before it for either face
+    TwoSided {
+        front: usize,
+        back: usize,
+    },
+    // the material declared before it at index `base`, cut away where the image
+    // at index `mask` of the description's masks is below threshold
+    Cutout {
+        base: usize,
+        mask: usize,
+        threshold: Float,
+    },
+    // a film over the material declared before it at index `base`
+    ThinFilm {
+        ior: Float,
+        thickness: Float,
+        base: usize,
+        substrate_ior: Float,
+    },
+    // the colours of a mesh's vertices
+    VertexColors,
+    // diffuse, its albedo the texture at index `texture` of the description's
+    // textures
+    Textured {
+        texture: usize,
+    },
+}
+
+impl MaterialDescription {
+    pub fn emits(&self) -> bool {
+        matches!(self, MaterialDescription::Light(_))
+    }
+
+    // the material, over the ones declared before it
+    fn build(
+        &self,
+        built: &[Arc<dyn Material>],
+        masks: &[Arc<ImageTexture>],
+        textures: &[Arc<dyn Texture>],
+    ) -> Arc<dyn Material> {
+        match *self {
+            MaterialDescription::Lambertian(c) => {
+                Arc::new(Lambertian::new(ConstantTexture::new(c.x, c.y, c.z)))
+            }
+            MaterialDescription::Metal(c, fuzz) => Arc::new(Metal::new(c, fuzz)),
+            MaterialDescription::Conductor(eta, k, fuzz) => Arc::new(Conductor::new(eta, k, fuzz)),
+            MaterialDescription::Anisotropic(c, alpha_x, alpha_y) => Arc::new(Anisotropic::new(
+                ConstantTexture::new(c.x, c.y, c.z),
+                alpha_x,
+                alpha_y,
+            )),
+            MaterialDescription::Dielectric(ior) => Arc::new(Dielectric::new(ior)),
+            MaterialDescription::Dispersive(dispersion) => {
+                Arc::new(Dielectric::dispersive(dispersion))
+            }
+            MaterialDescription::Light(c) => {
+                Arc::new(DiffuseLight::new(ConstantTexture::new(c.x, c.y, c.z)))
+            }
+            // stands in on the boundary, which the walk replaces, see scene
+            MaterialDescription::Subsurface(_, c) => {
+                Arc::new(Lambertian::new(ConstantTexture::new(c.x, c.y, c.z)))
+            }
+            // stands in for the colours scene gives each of the mesh's triangles
+            MaterialDescription::VertexColors => {
+                Arc::new(Lambertian::new(ConstantTexture::new(0.5, 0.5, 0.5)))
+            }
+            MaterialDescription::Textured { texture } => {
+                Arc::new(Lambertian::new(textures[texture].clone()))
+            }
+            MaterialDescription::Mix { a, b, factor } => {
+                Arc::new(Mix::new(built[a].clone(), built[b].clone(), factor))
+            }
+            MaterialDescription::TwoSided { front, back } => {
+                Arc::new(TwoSided::new(built[front].clone(), built[back].clone()))
+            }
+            MaterialDescription::Cutout {
+                base,
+                mask,
+                threshold,
+            } => Arc::new(Masked::new(
+                built[base].clone(),
+                masks[mask].clone(),
+                threshold,
+            )),
+            MaterialDescription::ThinFilm {
+                ior,
+                thickness,
+                base,
+                substrate_ior,
+            } => Arc::new(ThinFilm::new(
+                built[base].clone(),
+                ior,
+                substrate_ior,
+                ConstantTexture::new(thickness, thickness, thickness),
+            )),
+        }
+    }
+}
+
+// the texture of a kind, from the rest of its statement
+fn texture(statement: &mut Statement, kind: &str) -> Result<Arc<dyn Texture>, String> {
+    let texture: Arc<dyn Texture> = match kind {
+        "image" => {
+            let image = ImageTexture::open(statement.word()?)?;
+            let (mut wrap, mut transform) = (Wrap::Clamp, UvTransform::default());
+            while let Some(word) = statement.words.next() {
+                match word {
+                    "wrap" => {
+                        wrap = match statement.word()? {
+                            "clamp" => Wrap::Clamp,
+                            "repeat" => Wrap::Repeat,
+                            "globe" => Wrap::Globe,
+                            other => return Err(format!("unknown wrap {}", other)),
+                        }
+                    }
+                    "offset" => transform.offset = (statement.number()?, statement.number()?),
+                    "scale" => transform.scale = (statement.number()?, statement.number()?),
+                    "rotate" => transform.rotation = statement.number()?,
+                    _ => return Err(format!("unexpected {}", word)),
+                }
+            }
+            if transform.scale.0 == 0.0 || transform.scale.1 == 0.0 {
+                return Err(String::from("an image's scale must not be zero"));
+            }
+            let image = image.with_wrap(wrap);
+            if transform == UvTransform::default() {
+                Arc::new(image)
+            } else {
+                Arc::new(TransformedTexture::new(image, transform))
+            }
+        }
+        "wood" => {
+            let light = statement.vector()?;
+            let dark = statement.vector()?;
+            let (mut rings, mut turbulence) = (4.0, 0.5);
+            while let Some(word) = statement.words.next() {
+                match word {
+                    "rings" => rings = statement.number()?,
+                    "turbulence" => turbulence = statement.number()?,
+                    _ => return Err(format!("unexpected {}", word)),
+                }
+            }
+            if rings <= 0.0 || turbulence < 0.0 {
+                return Err(String::from(
+                    "wood needs rings and its turbulence must not be negative",
+                ));
+            }
+            Arc::new(WoodTexture::new(light, dark, rings, turbulence))
+        }
+        "fbm" | "ridged" | "worley" => {
+            let low = statement.vector()?;
+            let high = statement.vector()?;
+            let mut octaves = Octaves::default();
+            let (mut frequency, mut edges) = (1.0, false);
+            while let Some(word) = statement.words.next() {
+                match word {
+                    "frequency" => frequency = statement.number()?,
+                    "octaves" => octaves.octaves = statement.number()? as usize,
+                    "lacunarity" => octaves.lacunarity = statement.number()?,
+                    "gain" => octaves.gain = statement.number()?,
+                    "edges" if kind == "worley" => edges = true,
+                    _ => return Err(format!("unexpected {}", word)),
+                }
+            }
+            if frequency <= 0.0 || octaves.octaves == 0 || octaves.lacunarity <= 0.0 {
+                return Err(format!(
+                    "{} needs a positive frequency and lacunarity and an octave",
+                    kind
+                ));
+            }
+            let fractal = match kind {
+                "fbm" => Fractal::fbm(),
+                "ridged" => Fractal::ridged(),
+                _ if edges => Fractal::cracks(),
+                _ => Fractal::cells(),
+            };
+            Arc::new(FractalTexture::new(fractal, octaves, frequency, low, high))
+        }
+        "voxels" => {
+            let path = statement.word()?;
+            let center = statement.vector()?;
+            let size = statement.number()?;
+            if size <= 0.0 {
+                return Err(String::from("voxels' size must be positive"));
+            }
+            let (mut low, mut high) = (Vector3::zeros(), Vector3::new(1.0, 1.0, 1.0));
+            while let Some(word) = statement.words.next() {
+                match word {
+                    "low" => low = statement.vector()?,
+                    "high" => high = statement.vector()?,
+                    _ => return Err(format!("unexpected {}", word)),
+                }
+            }
+            let grid = vdb::read(path)?;
+            let bounds = grid
+                .bounds()
+                .ok_or_else(|| format!("{} has no voxels", path))?;
+            // placed as a volume is
+            let scale = size / (bounds.max - bounds.min).max();
+            let origin = center - 0.5 * scale * (bounds.min + bounds.max);
+            Arc::new(VoxelTexture::new(Arc::new(grid), origin, scale, low, high))
+        }
+        _ => return Err(format!("unknown texture kind {}", kind)),
+    };
+    statement.end()?;
+    Ok(texture)
+}
+
+// the index of a material declared before, by name, to combine with another
+fn declared(
+    statement: &mut Statement,
+    names: &HashMap<String, usize>,
+    materials: &[MaterialDescription],
+) -> Result<usize, String> {
+    let name = statement.word()?;
+    let index = names
+        .get(name)
+        .copied()
+        .ok_or_else(|| format!("unknown material {}", name))?;
+    if let MaterialDescription::VertexColors = materials[index] {
+        return Err(String::from("vertex colours cannot be combined"));
+    }
+    Ok(index)
+}
+
+// the index of a texture declared before, by name
+fn named_texture(
+    statement: &mut Statement,
+    texture_names: &HashMap<String, usize>,
+) -> Result<usize, String> {
+    let name = statement.word()?;
+    texture_names
+        .get(name)
+        .copied()
+        .ok_or_else(|| format!("unknown texture {}", name))
+}
+
+// a material declared before with the parameters the rest of the statement names
+// changed
+fn like(
+    statement: &mut Statement,
+    mut material: MaterialDescription,
+    texture_names: &HashMap<String, usize>,
+) -> Result<MaterialDescription, String> {
+    while let Some(word) = statement.words.next() {
+        material = match (word, material) {
+            (
+                "albedo",
+                MaterialDescription::Lambertian(_) | MaterialDescription::Textured { .. },
+            ) => MaterialDescription::Lambertian(statement.vector()?),
+            ("albedo", MaterialDescription::Metal(_, fuzz)) => {
+                MaterialDescription::Metal(statement.vector()?, fuzz)
+            }
+            ("albedo", MaterialDescription::Anisotropic(_, alpha_x, alpha_y)) => {
+                MaterialDescription::Anisotropic(statement.vector()?, alpha_x, alpha_y)
+            }
+            (
+                "texture",
+                MaterialDescription::Lambertian(_) | MaterialDescription::Textured { .. },
+            ) => MaterialDescription::Textured {
+                texture: named_texture(statement, texture_names)?,
+            },
+            ("fuzz", MaterialDescription::Metal(albedo, _)) => {
+                MaterialDescription::Metal(albedo, statement.number()?)
+            }
+            ("fuzz", MaterialDescription::Conductor(eta, k, _)) => {
+                MaterialDescription::Conductor(eta, k, statement.number()?)
+            }
+            ("roughness", MaterialDescription::Anisotropic(albedo, _, _)) => {
+                let (alpha_x, alpha_y) = (statement.number()?, statement.number()?);
+                if alpha_x < 0.0 || alpha_y < 0.0 {
+                    return Err(String::from("roughness must not be negative"));
+                }
+                MaterialDescription::Anisotropic(albedo, alpha_x, alpha_y)
+            }
+            ("ior", MaterialDescription::Dielectric(_)) => {
+                MaterialDescription::Dielectric(statement.number()?)
+            }
+            (
+                "thickness",
+                MaterialDescription::ThinFilm {
+                    ior,
+                    base,
+                    substrate_ior,
+                    ..
+                },
+            ) => {
+                let thickness = statement.number()?;
+                if thickness < 0.0 {
+                    return Err(String::from("a film's thickness must not be negative"));
+                }
+                MaterialDescription::ThinFilm {
+                    ior,
+                    thickness,
+                    base,
+                    substrate_ior,
+                }
+            }
+            ("factor", MaterialDescription::Mix { a, b, .. }) => {
+                let factor = statement.number()?;
+                if !(0.0..=1.0).contains(&factor) {
+                    return Err(String::from("a mix's factor must be in [0, 1]"));
+                }
+                MaterialDescription::Mix { a, b, factor }
+            }
+            ("emit", MaterialDescription::Light(_)) => {
+                MaterialDescription::Light(statement.vector()?)
+            }
+            _ => return Err(format!("cannot change {} of that material", word)),
+        };
+    }
+    Ok(material)
+}
+
+fn material(
+    statement: &mut Statement,
+    names: &HashMap<String, usize>,
+    materials: &[MaterialDescription],
+    masks: &mut Vec<Arc<ImageTexture>>,
+    textures: &mut Vec<Arc<dyn Texture>>,
+    texture_names: &HashMap<String, usize>,
+) -> Result<MaterialDescription, String> {
+    let kind = statement.word()?;
+    let material = match kind {
+        "lambertian" if statement.next_is_number() => {
+            MaterialDescription::Lambertian(statement.vector()?)
+        }
+        "lambertian" => MaterialDescription::Textured {
+            texture: named_texture(statement, texture_names)?,
+        },
+        "like" => {
+            let name = statement.word()?;
+            let base = names
+                .get(name)
+                .copied()
+                .ok_or_else(|| format!("unknown material {}", name))?;
+            like(statement, materials[base], texture_names)?
+        }
+        "metal" => MaterialDescription::Metal(statement.vector()?, statement.number()?),
+        "anisotropic" => {
+            let albedo = statement.vector()?;
+            let alpha_x = statement.number()?;
+            let alpha_y = statement.number()?;
+            if alpha_x < 0.0 || alpha_y < 0.0 {
+                return Err(String::from("roughness must not be negative"));
+            }
+            MaterialDescription::Anisotropic(albedo, alpha_x, alpha_y)
+        }
+        "conductor" => {
+            let (eta, k) = if statement.next_is_number() {
+                (statement.vector()?, statement.vector()?)
+            } else {
+                statement.word()?.parse::<MetalPreset>()?.ior()
+            };
+            let fuzz = statement.number()?;
+            if eta.min() <= 0.0 || k.min() < 0.0 {
+                return Err(String::from("eta must be positive and k not negative"));
+            }
+            MaterialDescription::Conductor(eta, k, fuzz)
+        }
+        "dielectric" => match statement.words.peek() {
+            Some(&"cauchy") => {
+                statement.word()?;
+                MaterialDescription::Dispersive(Dispersion::Cauchy {
+                    a: statement.number()?,
+                    b: statement.number()?,
+                })
+            }
+            Some(&"sellmeier") => {
+                statement.word()?;
+                let b = statement.vector()?;
+                let c = statement.vector()?;
+                MaterialDescription::Dispersive(Dispersion::Sellmeier {
+                    b: [b.x, b.y, b.z],
+                    c: [c.x, c.y, c.z],
+                })
+            }
+            Some(&"bk7") => {
+                statement.word()?;
+                MaterialDescription::Dispersive(Dispersion::BK7)
+            }
+            Some(&"sf11") => {
+                statement.word()?;
+                MaterialDescription::Dispersive(Dispersion::SF11)
+            }
+            _ => MaterialDescription::Dielectric(statement.number()?),
+        },
+        "light" => MaterialDescription::Light(statement.vector()?),
+        "vertex_colors" => MaterialDescription::VertexColors,
+        "mix" => {
+            let a = declared(statement, names, materials)?;
+            let b = declared(statement, names, materials)?;
+            let factor = statement.number()?;
+            if !(0.0..=1.0).contains(&factor) {
+                return Err(String::from("a mix's factor must be in [0, 1]"));
+            }
+            MaterialDescription::Mix { a, b, factor }
+        }
+        "cutout" => {
+            let base = declared(statement, names, materials)?;
+            masks.push(Arc::new(ImageTexture::open(statement.word()?)?));
+            let threshold = match statement.words.peek() {
+                Some(&"threshold") => {
+                    statement.word()?;
+                    statement.number()?
+                }
+                _ => 0.5,
+            };
+            if !(0.0..=1.0).contains(&threshold) {
+                return Err(String::from("a cutout's threshold must be in [0, 1]"));
+            }
+            MaterialDescription::Cutout {
+                base,
+                mask: masks.len() - 1,
+                threshold,
+            }
+        }
+        "two_sided" => MaterialDescription::TwoSided {
+            front: declared(statement, names, materials)?,
+            back: declared(statement, names, materials)?,
+        },
+        "thin_film" => {
+            let ior = statement.number()?;
+            let thickness = statement.number()?;
+            let base = declared(statement, names, materials)?;
+            let substrate_ior = match statement.words.peek() {
+                Some(&"substrate") => {
+                    statement.word()?;
+                    statement.number()?
+                }
+                _ => 1.0,
+            };
+            if ior < 1.0 || substrate_ior < 1.0 || thickness < 0.0 {
+                return Err(String::from(
+                    "a film's indices must be at least 1 and its thickness not negative",
+                ));
+            }
+            MaterialDescription::ThinFilm {
+                ior,
+                thickness,
+                base,
+                substrate_ior,
+            }
+        }
+        "image" | "wood" | "fbm" | "ridged" | "worley" | "voxels" => {
+            textures.push(texture(statement, kind)?);
+            MaterialDescription::Textured {
+                texture: textures.len() - 1,
+            }
+        }
+        "subsurface" => {
+            let mean_free_path = statement.vector()?;
+            let albedo = statement.vector()?;
+            if mean_free_path.min() <= 0.0 {
+                return Err(String::from("mean free paths must be positive"));
+            }
+            if albedo.min() < 0.0 || albedo.max() > 1.0 {
+                return Err(String::from("subsurface albedo must be in [0, 1]"));
+            }
+            MaterialDescription::Subsurface(mean_free_path, albedo)
+        }
+        _ => return Err(format!("unknown material kind {}", kind)),
+    };
+    statement.end()?;
+    Ok(material)
+}
+
+#[derive(Clone)]
+pub enum Shape {
+    Sphere {
+        center: Vector3<Float>,
+        radius: Float,
+    },
+    Rect {
+        plane: Plane,
+        a0: Float,
+        a1: Float,
+        b0: Float,
+        b1: Float,
+        k: Float,
+        uv: FaceUv,
+    },
+    Box {
+        p_min: Vector3<Float>,
+        p_max: Vector3<Float>,
+        layout: CubeLayout,
+    },
+    // already placed in the world
+    Mesh(Arc<MeshData>),
+    // two spheres or boxes combined, see csg.rs
+    Csg {
+        operation: Operation,
+        left: Box<Shape>,
+        right: Box<Shape>,
+    },
+    Sdf(Primitive),
+    // nx by nz elevations row by row along x, up from p_min, over size_x by size_z
+    Heightfield {
+        nx: usize,
+        nz: usize,
+        heights: Arc<Vec<Float>>,
+        p_min: Vector3<Float>,
+        size_x: Float,
+        size_z: Float,
+    },
+}
+
+impl Shape {
+    // the middle of its bounds, which turn keys turn it about
+    pub fn center(&self) -> Vector3<Float> {
+        match self {
+            Shape::Sphere { center, .. } => *center,
+            Shape::Rect {
+                plane,
+                a0,
+                a1,
+                b0,
+                b1,
+                k,
+                ..
+            } => {
+                let (k_axis, a_axis, b_axis) = rect::get_axis(plane);
+                let mut center = Vector3::zeros();
+                center[k_axis] = *k;
+                center[a_axis] = 0.5 * (a0 + a1);
+                center[b_axis] = 0.5 * (b0 + b1);
+                center
+            }
+            Shape::Box { p_min, p_max, .. } => 0.5 * (p_min + p_max),
+            Shape::Mesh(mesh) => {
+                let (min, max) = mesh.bounds();
+                0.5 * (min + max)
+            }
+            // the first solid's, which bounds a difference, or between the two's
+            Shape::Csg {
+                operation: Operation::Difference,
+                left,
+                ..
+            } => left.center(),
+            Shape::Csg { left, right, .. } => 0.5 * (left.center() + right.center()),
+            Shape::Sdf(primitive) => primitive.center(),
+            Shape::Heightfield {
+                heights,
+                p_min,
+                size_x,
+                size_z,
+                ..
+            } => {
+                let (low, high) = heights
+                    .iter()
+                    .fold((Float::MAX, Float::MIN), |(lo, hi), &h| {
+                        (lo.min(h), hi.max(h))
+                    });
+                p_min + Vector3::new(0.5 * size_x, 0.5 * (low + high), 0.5 * size_z)
+            }
+        }
+    }
+}
+
+// a solid a csg statement combines, sphere <x y z> <radius> or box <x y z> <x y z>
+fn solid(statement: &mut Statement) -> Result<Shape, String> {
+    match statement.word()? {
+        "sphere" => Ok(Shape::Sphere {
+            center: statement.vector()?,
+            radius: statement.number()?,
+        }),
+        "box" => Ok(Shape::Box {
+            p_min: statement.vector()?,
+            p_max: statement.vector()?,
+            layout: CubeLayout::Tiled(1.0, 1.0),
+        }),
+        word => Err(format!("csg combines spheres and boxes, not {}", word)),
+    }
+}
+
+// the distance function after an sdf statement's keyword
+fn primitive(statement: &mut Statement) -> Result<Primitive, String> {
+    let primitive = match statement.word()? {
+        "sphere" => Primitive::Sphere {
+            center: statement.vector()?,
+            radius: statement.number()?,
+        },
+        "torus" => Primitive::Torus {
+            center: statement.vector()?,
+            major: statement.number()?,
+            minor: statement.number()?,
+        },
+        "rounded_box" => {
+            let (p_min, p_max) = (statement.vector()?, statement.vector()?);
+            Primitive::RoundedBox {
+                center: 0.5 * (p_min + p_max),
+                half: 0.5 * (p_max - p_min),
+                radius: statement.number()?,
+            }
+        }
+        word => return Err(format!("unknown sdf {}", word)),
+    };
+    let invalid = match primitive {
+        Primitive::Sphere { radius, .. } if radius <= 0.0 => "a sphere's radius must be positive",
+        Primitive::Torus { major, minor, .. } if !(minor > 0.0 && major > minor) => {
+            "a torus's tube must be thinner than its ring"
+        }
+        Primitive::RoundedBox { half, radius, .. } if !(radius >= 0.0 && half.min() >= radius) => {
+            "a rounded box's radius must fit between its corners"
+        }
+        _ => return Ok(primitive),
+    };
+    Err(String::from(invalid))
+}
+
+// the terrain after a heightfield statement's keyword
+fn heightfield(statement: &mut Statement) -> Result<Shape, String> {
+    let source = statement.word()?;
+    let (path, samples) = match source {
+        "image" => (Some(statement.word()?), None),
+        "noise" => {
+            let nx = statement.number()? as usize;
+            let nz = statement.number()? as usize;
+            (None, Some((nx, nz, statement.number()?)))
+        }
+        _ => return Err(format!("unknown heightfield {}", source)),
+    };
+    let extent = heightfield::Extent {
+        p_min: statement.vector()?,
+        size_x: statement.number()?,
+        size_z: statement.number()?,
+        max_height: statement.number()?,
+    };
+    if extent.size_x <= 0.0 || extent.size_z <= 0.0 || extent.max_height < 0.0 {
+        return Err(String::from(
+            "a heightfield's size must be positive and its height not negative",
+        ));
+    }
+    let (nx, nz, heights) = match (path, samples) {
+        (Some(path), _) => heightfield::image_heights(path, extent.max_height)?,
+        (_, Some((nx, nz, scale))) => {
+            if nx < 2 || nz < 2 {
+                return Err(String::from("a heightfield needs at least 2x2 samples"));
+            }
+            (
+                nx,
+                nz,
+                heightfield::noise_heights(nx, nz, scale, extent.max_height),
+            )
+        }
+        _ => unreachable!("a heightfield is read from an image or noise"),
+    };
+    Ok(Shape::Heightfield {
+        nx,
+        nz,
+        heights: Arc::new(heights),
+        p_min: extent.p_min,
+        size_x: extent.size_x,
+        size_z: extent.size_z,
+    })
+}
+
+// a sphere or box of the material
+fn build_solid(shape: &Shape, material: Arc<dyn Material>) -> Arc<dyn Hittable> {
+    match *shape {
+        Shape::Sphere { center, radius } => Arc::new(Sphere::new(center, radius, material)),
+        Shape::Box {
+            p_min,
+            p_max,
+            layout,
+        } => Arc::new(Cube::with_layout(p_min, p_max, material, layout)),
+        _ => unreachable!("csg only combines spheres and boxes"),
+    }
+}
+
+// A shape with the index of its material and its trailing options
+#[derive(Clone)]
+pub struct Object {
+    pub shape: Shape,
+    pub material: usize,
+    pub flip: bool,
+    // whether its solid textures move with it
+    pub object_space: bool,
+    pub keys: Option<Keyframes>,
+    pub turn_keys: Option<Keyframes>,
+    // a projection of the material at the index over it
+    pub decal: Option<(Projection, usize)>,
+    // where the groups it was defined in were placed, innermost first
+    pub placements: Vec<Placement>,
+}
+
+// Where a group is placed: turned about its own origin by `angles`, in degrees
+// about x, then y, then z, and then moved by `offset`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Placement {
+    pub offset: Vector3<Float>,
+    pub angles: Vector3<Float>,
+}
+
+// <t x y z>... after a keys word
+fn keyframes(statement: &mut Statement) -> Result<Keyframes, String> {
+    let mut frames = Vec::new();
+    while statement.next_is_number() {
+        frames.push((statement.number()?, statement.vector()?));
+    }
+    if frames.is_empty() {
+        return Err(String::from("keys need at least one <t x y z>"));
+    }
+    Ok(Keyframes::new(frames))
+}
+
+// what follows a decal word, with the index of its material among the names
+fn decal(
+    statement: &mut Statement,
+    names: &HashMap<String, usize>,
+) -> Result<(Projection, usize), String> {
+    let name = statement.word()?;
+    let material = *names
+        .get(name)
+        .ok_or_else(|| format!("unknown material {}", name))?;
+    let mut projection = Projection {
+        center: statement.vector()?,
+        facing: statement.vector()?,
+        width: statement.number()?,
+        height: statement.number()?,
+        depth: statement.number()?,
+        ..Projection::default()
+    };
+    while let Some(&word) = statement.words.peek() {
+        match word {
+            "up" => {
+                statement.words.next();
+                projection.view_up = statement.vector()?;
+            }
+            "opacity" => {
+                statement.words.next();
+                projection.opacity = statement.number()?;
+            }
+            "angle" => {
+                statement.words.next();
+                projection.max_angle = statement.number()?;
+            }
+            _ => break,
+        }
+    }
+    if projection.width <= 0.0 || projection.height <= 0.0 || projection.depth <= 0.0 {
+        return Err(String::from("a decal's size must be positive"));
+    }
+    if projection.facing.cross(&projection.view_up) == Vector3::zeros() {
+        return Err(String::from("a decal's up must not be along its facing"));
+    }
+    Ok((projection, material))
+}
+
+// the object a shape of the material makes, with its trailing tiles or cross,
+// flip, object_space, keys, turn keys and decal
+fn object(
+    statement: &mut Statement,
+    shape: Shape,
+    material: usize,
+    names: &HashMap<String, usize>,
+) -> Result<Object, String> {
+    let mut object = Object {
+        shape,
+        material,
+        flip: false,
+        object_space: false,
+        keys: None,
+        turn_keys: None,
+        decal: None,
+        placements: Vec::new(),
+    };
+    while let Some(word) = statement.words.next() {
+        match word {
+            "tiles" => {
+                let tiles = (statement.number()?, statement.number()?);
+                if tiles.0 <= 0.0 || tiles.1 <= 0.0 {
+                    return Err(String::from("tiles must be positive"));
+                }
+                match &mut object.shape {
+                    Shape::Rect { uv, .. } => uv.tiles = tiles,
+                    Shape::Box { layout, .. } => *layout = CubeLayout::Tiled(tiles.0, tiles.1),
+                    _ => return Err(String::from("only rects and boxes are tiled")),
+                }
+            }
+            "cross" => match &mut object.shape {
+                Shape::Box { layout, .. } => *layout = CubeLayout::Cross,
+                _ => return Err(String::from("only boxes unfold into a cross")),
+            },
+            "flip" => object.flip = true,
+            "object_space" => object.object_space = true,
+            "keys" => object.keys = Some(keyframes(statement)?),
+            "turn_keys" => object.turn_keys = Some(keyframes(statement)?),
+            "decal" => object.decal = Some(decal(statement, names)?),
+            _ => return Err(format!("unexpected {}", word)),
+        }
+    }
+    Ok(object)
+}
+
+// A sparse voxel grid loaded from a file and where it goes
+#[derive(Clone)]
+pub struct VolumeDescription {
+    pub grid: Arc<SparseGrid>,
+    pub center: Vector3<Float>,
+    pub size: Float,
+    pub density: Float,
+    pub albedo: Vector3<Float>,
+    pub g: Option<Float>,
+}
+
+impl VolumeDescription {
+    pub fn build(&self, options: &Options) -> SparseVolume<ConstantTexture, HenyeyGreenstein> {
+        let bounds = self.grid.bounds().expect("volumes are not empty");
+        let extent = bounds.max - bounds.min;
+        let scale = self.size / extent.max();
+        let a = self.albedo;
+        SparseVolume::with_phase(
+            self.grid.clone(),
+            self.center - 0.5 * scale * (bounds.min + bounds.max),
+            scale,
+            self.density,
+            ConstantTexture::new(a.x, a.y, a.z),
+            HenyeyGreenstein::new(self.g.unwrap_or(options.medium_g)),
+        )
+    }
+}
+
+fn volume(statement: &mut Statement) -> Result<VolumeDescription, String> {
+    let path = statement.word()?;
+    let center = statement.vector()?;
+    let size = statement.number()?;
+    if size <= 0.0 {
+        return Err(String::from("a volume's size must be positive"));
+    }
+    let mut volume = VolumeDescription {
+        grid: Arc::new(vdb::read(path)?),
+        center,
+        size,
+        density: 1.0,
+        albedo: Vector3::new(1.0, 1.0, 1.0),
+        g: None,
+    };
+    while let Some(word) = statement.words.next() {
+        match word {
+            "density" => volume.density = statement.number()?,
+            "albedo" => volume.albedo = statement.vector()?,
+            "g" => volume.g = Some(statement.number()?),
+            _ => return Err(format!("unexpected {}", word)),
+        }
+    }
+    if volume.density < 0.0 {
+        return Err(String::from("a volume's density must not be negative"));
+    }
+    if volume.g.is_some_and(|g| g <= -1.0 || g >= 1.0) {
+        return Err(String::from("g must be in (-1, 1)"));
+    }
+    Ok(volume)
+}
+
+fn mesh(statement: &mut Statement) -> Result<MeshData, String> {
+    let path = statement.word()?;
+    let center = statement.vector()?;
+    let size = statement.number()?;
+    if size <= 0.0 {
+        return Err(String::from("a mesh's size must be positive"));
+    }
+    Ok(meshfile::read(path)?.placed(center, size))
+}
+
+fn plane(word: &str) -> Result<Plane, String> {
+    match word {
+        "xy" => Ok(Plane::XY),
+        "yz" => Ok(Plane::YZ),
+        "zx" => Ok(Plane::ZX),
+        _ => Err(format!("unknown plane {}", word)),
+    }
+}
+
+// What a file describes, before any of it is built. The CPU scene and the GPU
+// backend's buffers are both made from it.
+pub struct Description {
+    pub camera: CameraDescription,
+    pub shutter: Shutter,
+    pub background: Arc<dyn Background>,
+    pub materials: Vec<MaterialDescription>,
+    pub objects: Vec<Object>,
+    pub volumes: Vec<VolumeDescription>,
+    pub masks: Vec<Arc<ImageTexture>>,
+    pub textures: Vec<Arc<dyn Texture>>,
+    pub portals: Vec<Portal>,
+    pub sun: Option<Sun>,
+}
+
+impl Description {
+    pub fn camera(&self, aspect: Float) -> Camera {
+        let c = &self.camera;
+        let (time0, time1) = self.shutter.interval();
+        let lens = c
+            .lens_keys
+            .as_ref()
+            .map_or(Vector3::new(c.fov, c.aperture, c.focus), |keys| {
+                keys.at(self.shutter.open)
+            });
+        let optics = Optics {
+            vertical_fov: lens.x,
+            aperture: lens.y,
+            focus_dist: lens.z,
+            movements: c.movements,
+        };
+        let camera = Camera::new(c.from, c.at, c.up, aspect, optics, time0, time1);
+        if c.from_keys.is_none() && c.at_keys.is_none() {
+            return camera;
+        }
+        let still = || Keyframes::new(vec![(0.0, Vector3::zeros())]);
+        camera.with_path(CameraPath {
+            look_at: c.at,
+            view_up: c.up,
+            from: c.from_keys.clone().unwrap_or_else(still),
+            at: c.at_keys.clone().unwrap_or_else(still),
+        })
+    }
+
+    pub fn scene(&self, aspect: Float, options: &Options) -> Scene {
+        let mut materials: Vec<Arc<dyn Material>> = Vec::with_capacity(self.materials.len());
+        for material in self.materials.iter() {
+            let built = material.build(&materials, &self.masks, &self.textures);
+            materials.push(built);
+        }
+        let mut world: Vec<Box<dyn Hittable>> = Vec::new();
+        let mut lights: Vec<Arc<dyn Hittable>> = Vec::new();
+        for object in self.objects.iter() {
+            let material = materials[object.material].clone();
+            let shape: Arc<dyn Hittable> = match object.shape.clone() {
+                Shape::Sphere { center, radius } => Arc::new(Sphere::new(center, radius, material)),
+                Shape::Rect {
+                    plane,
+                    a0,
+                    a1,
+                    b0,
+                    b1,
+                    k,
+                    uv,
+                } => Arc::new(AARect::new(plane, a0, a1, b0, b1, k, material).with_uv(uv)),
+                Shape::Box {
+                    p_min,
+                    p_max,
+                    layout,
+                } => Arc::new(Cube::with_layout(p_min, p_max, material, layout)),
+                Shape::Mesh(mesh) => match self.materials[object.material] {
+                    MaterialDescription::VertexColors => Arc::new(
+                        mesh.build_colored()
+                            .expect("vertex colours are only given to meshes with them"),
+                    ),
+                    ref description => Arc::new(mesh.build(material, !description.emits())),
+                },
+                Shape::Csg {
+                    operation,
+                    left,
+                    right,
+                } => Arc::new(CSG::new(
+                    operation,
+                    build_solid(&left, material.clone()),
+                    build_solid(&right, material),
+                )),
+                Shape::Sdf(primitive) => Arc::new(Sdf::new(
+                    move |p| primitive.distance(p),
+                    primitive.bounds(),
+                    material,
+                )),
+                Shape::Heightfield {
+                    nx,
+                    nz,
+                    heights,
+                    p_min,
+                    size_x,
+                    size_z,
+                } => Arc::new(Heightfield::new(
+                    nx,
+                    nz,
+                    heights.to_vec(),
+                    p_min,
+                    size_x,
+                    size_z,
+                    material,
+                )),
+            };
+            let shape: Arc<dyn Hittable> = if object.flip {
+                Arc::new(FlipNormals::new(shape))
+            } else {
+                shape
+            };
+            let shape: Arc<dyn Hittable> = match self.materials[object.material] {
+                MaterialDescription::Subsurface(mean_free_path, albedo) => Arc::new(
+                    Subsurface::new(shape, mean_free_path, albedo, options.medium_g),
+                ),
+                MaterialDescription::Cutout { .. } => Arc::new(Cutout::new(shape)),
+                _ => shape,
+            };
+            let shape: Arc<dyn Hittable> = match object.decal {
+                Some((projection, decal)) => {
+                    Arc::new(Decal::new(shape, projection, materials[decal].clone()))
+                }
+                None => shape,
+            };
+            let shape: Arc<dyn Hittable> = match &object.turn_keys {
+                Some(keys) if object.object_space => Arc::new(
+                    Turning::new(shape, object.shape.center(), keys.clone()).in_object_space(),
+                ),
+                Some(keys) => Arc::new(Turning::new(shape, object.shape.center(), keys.clone())),
+                None => shape,
+            };
+            let shape: Arc<dyn Hittable> = match &object.keys {
+                Some(keys) if object.object_space => {
+                    Arc::new(Moving::new(shape, keys.clone()).in_object_space())
+                }
+                Some(keys) => Arc::new(Moving::new(shape, keys.clone())),
+                None => shape,
+            };
+            let shape = object.placements.iter().fold(shape, |shape, placement| {
+                place(shape, placement, object.object_space)
+            });
+            if object.keys.is_none()
+                && object.turn_keys.is_none()
+                && self.materials[object.material].emits()
+            {
+                lights.push(shape.clone());
+            }
+            world.push(Box::new(shape));
+        }
+        for volume in self.volumes.iter() {
+            world.push(Box::new(volume.build(options)));
+        }
+        // portals and the sun are sampled toward as the emitters are, but are not
+        // emitters
+        let light_shape = if lights.is_empty() && self.portals.is_empty() && self.sun.is_none() {
+            None
+        } else {
+            let mut list = HittableList::default();
+            for light in lights.iter() {
+                list.push_shared(light.clone());
+            }
+            for portal in self.portals.iter() {
+                list.push(portal.clone());
+            }
+            if let Some(sun) = self.sun {
+                list.push(sun);
+            }
+            Some(Arc::new(list) as Arc<dyn Hittable>)
+        };
+        let background: Arc<dyn Background> = match self.sun {
+            Some(sun) => Arc::new(SunAndSky {
+                sun,
+                sky: self.background.clone(),
+            }),
+            None => self.background.clone(),
+        };
+        let scene = Scene {
+            background,
+            light_shape,
+            lights,
+            ..Scene::new(
+                scene::accelerate_over(world, options, self.shutter),
+                self.camera(aspect),
+            )
+        };
+        scene::configure_camera(scene, options)
+    }
+}
+
+// what the statements so far describe
+struct Parser {
+    shutter: Shutter,
+    camera: Option<CameraDescription>,
+    background: Arc<dyn Background>,
+    names: HashMap<String, usize>,
+    materials: Vec<MaterialDescription>,
+    objects: Vec<Object>,
+    volumes: Vec<VolumeDescription>,
+    masks: Vec<Arc<ImageTexture>>,
+    textures: Vec<Arc<dyn Texture>>,
+    texture_names: HashMap<String, usize>,
+    portals: Vec<Portal>,
+    sun: Option<Sun>,
+    groups: HashMap<String, Vec<Object>>,
+    // the group being defined, and its objects so far
+    group: Option<(String, Vec<Object>)>,
+}
+
+// how deep included files may include others, which stops a file including itself
+const MAX_INCLUDE_DEPTH: usize = 16;
+
+impl Parser {
+    // the statements of a file's text, `depth` includes down
+    fn read(&mut self, text: &str, depth: usize) -> Result<(), String> {
+        for (i, line) in text.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or("");
+            let mut statement = Statement {
+                words: line.split_whitespace().peekable(),
+            };
+            let result = match statement.words.next() {
+                Some("include") => self.include(&mut statement, depth),
+                Some(keyword) => self.statement(keyword, &mut statement),
+                None => Ok(()),
+            };
+            result.map_err(|message| format!("line {}: {}", i + 1, message))?;
+        }
+        Ok(())
+    }
+
+    // the statements of another file, as if they stood in place of the include
+    fn include(&mut self, statement: &mut Statement, depth: usize) -> Result<(), String> {
+        let path = statement.word()?;
+        statement.end()?;
+        if depth >= MAX_INCLUDE_DEPTH {
+            return Err(String::from("includes nest too deeply"));
+        }
+        let text = fs::read_to_string(path).map_err(|e| format!("cannot read {}: {}", path, e))?;
+        self.read(&text, depth + 1)
+            .map_err(|message| format!("{}: {}", path, message))
+    }
+
+    // where objects go: into the group being defined, or the scene
+    fn objects(&mut self) -> &mut Vec<Object> {
+        match &mut self.group {
+            Some((_, objects)) => objects,
+            None => &mut self.objects,
+        }
+    }
+
+    // a group placed where the rest of the statement says
+    fn place(&mut self, statement: &mut Statement) -> Result<(), String> {
+        let name = statement.word()?;
+        let mut placement = Placement {
+            offset: Vector3::zeros(),
+            angles: Vector3::zeros(),
+        };
+        while let Some(word) = statement.words.next() {
+            match word {
+                "at" => placement.offset = statement.vector()?,
+                "turn" => placement.angles = statement.vector()?,
+                _ => return Err(format!("unexpected {}", word)),
+            }
+        }
+        let group = self
+            .groups
+            .get(name)
+            .ok_or_else(|| format!("unknown group {}", name))?;
+        let placed: Vec<Object> = group
+            .iter()
+            .map(|object| {
+                let mut object = object.clone();
+                object.placements.push(placement);
+                object
+            })
+            .collect();
+        self.objects().extend(placed);
+        Ok(())
+    }
+
+    fn material(&self, statement: &mut Statement) -> Result<usize, String> {
+        let name = statement.word()?;
+        self.names
+            .get(name)
+            .copied()
+            .ok_or_else(|| format!("unknown material {}", name))
+    }
+
+    fn statement(&mut self, keyword: &str, statement: &mut Statement) -> Result<(), String> {
+        let (shape, material) = match keyword {
+            "camera" => {
+                self.camera = Some(camera(statement)?);
+                return Ok(());
+            }
+            "shutter" => {
+                self.shutter.open = statement.number()?;
+                self.shutter.close = statement.number()?;
+                if self.shutter.close < self.shutter.open {
+                    return Err(String::from("the shutter closes before it opens"));
+                }
+                return statement.end();
+            }
+            "motion_blur" => {
+                self.shutter.motion_blur = match statement.word()? {
+                    "on" => true,
+                    "off" => false,
+                    word => return Err(format!("expected on or off, found {}", word)),
+                };
+                return statement.end();
+            }
+            "background" => {
+                self.background = background(statement)?;
+                return statement.end();
+            }
+            "material" => {
+                let name = statement.word()?;
+                let material = material(
+                    statement,
+                    &self.names,
+                    &self.materials,
+                    &mut self.masks,
+                    &mut self.textures,
+                    &self.texture_names,
+                )?;
+                self.names.insert(name.to_string(), self.materials.len());
+                self.materials.push(material);
+                return Ok(());
+            }
+            "texture" => {
+                let name = statement.word()?;
+                let kind = statement.word()?;
+                let texture = texture(statement, kind)?;
+                self.texture_names
+                    .insert(name.to_string(), self.textures.len());
+                self.textures.push(texture);
+                return Ok(());
+            }
+            "volume" => {
+                if self.group.is_some() {
+                    return Err(String::from("volumes cannot go in groups"));
+                }
+                self.volumes.push(volume(statement)?);
+                return Ok(());
+            }
+            "sun" => {
+                let direction = statement.vector()?;
+                let irradiance = statement.vector()?;
+                let mut diameter = sun::SUN_DIAMETER;
+                while let Some(option) = statement.words.next() {
+                    match option {
+                        "diameter" => diameter = statement.number()?,
+                        _ => return Err(format!("unexpected {}", option)),
+                    }
+                }
+                if direction == Vector3::zeros() {
+                    return Err(String::from("the sun needs a direction"));
+                }
+                if !(diameter > 0.0 && diameter < 180.0) {
+                    return Err(String::from("the sun's diameter must be between 0 and 180"));
+                }
+                self.sun = Some(Sun::new(direction, irradiance, diameter));
+                return Ok(());
+            }
+            "portal" => {
+                if self.group.is_some() {
+                    return Err(String::from("portals cannot go in groups"));
+                }
+                let plane = plane(statement.word()?)?;
+                let mut bounds = [0.0; 5];
+                for bound in bounds.iter_mut() {
+                    *bound = statement.number()?;
+                }
+                let [a0, a1, b0, b1, k] = bounds;
+                self.portals.push(portal::portal(plane, a0, a1, b0, b1, k));
+                return statement.end();
+            }
+            "group" => {
+                let name = statement.word()?;
+                if let Some((open, _)) = &self.group {
+                    return Err(format!("group {} is not ended before group {}", open, name));
+                }
+                if self.groups.contains_key(name) {
+                    return Err(format!("group {} is already defined", name));
+                }
+                self.group = Some((name.to_string(), Vec::new()));
+                return statement.end();
+            }
+            "end" => {
+                let (name, objects) = self.group.take().ok_or("end with no group to end")?;
+                if objects.is_empty() {
+                    return Err(format!("group {} is empty", name));
+                }
+                self.groups.insert(name, objects);
+                return statement.end();
+            }
+            "place" => return self.place(statement),
+            "sphere" => {
+                let center = statement.vector()?;
+                let radius = statement.number()?;
+                (Shape::Sphere { center, radius }, self.material(statement)?)
+            }
+            "rect" => {
+                let plane = plane(statement.word()?)?;
+                let mut bounds = [0.0; 5];
+                for bound in bounds.iter_mut() {
+                    *bound = statement.number()?;
+                }
+                let [a0, a1, b0, b1, k] = bounds;
+                (
+                    Shape::Rect {
+                        plane,
+                        a0,
+                        a1,
+                        b0,
+                        b1,
+                        k,
+                        uv: FaceUv::default(),
+                    },
+                    self.material(statement)?,
+                )
+            }
+            "box" => {
+                let p_min = statement.vector()?;
+                let p_max = statement.vector()?;
+                (
+                    Shape::Box {
+                        p_min,
+                        p_max,
+                        layout: CubeLayout::Tiled(1.0, 1.0),
+                    },
+                    self.material(statement)?,
+                )
+            }
+            "mesh" => {
+                let mesh = mesh(statement)?;
+                (Shape::Mesh(Arc::new(mesh)), self.material(statement)?)
+            }
+            "sdf" => (Shape::Sdf(primitive(statement)?), self.material(statement)?),
+            "heightfield" => (heightfield(statement)?, self.material(statement)?),
+            "csg" => {
+                let operation = statement.word()?.parse()?;
+                let left = Box::new(solid(statement)?);
+                let right = Box::new(solid(statement)?);
+                (
+                    Shape::Csg {
+                        operation,
+                        left,
+                        right,
+                    },
+                    self.material(statement)?,
+                )
+            }
+            _ => return Err(format!("unknown statement {}", keyword)),
+        };
+        if let MaterialDescription::VertexColors = self.materials[material] {
+            if !matches!(&shape, Shape::Mesh(mesh) if mesh.colors.is_some()) {
+                return Err(String::from("vertex colours need a mesh with them"));
+            }
+        }
+        let object = object(statement, shape, material, &self.names)?;
+        if let Some((_, decal)) = object.decal {
+            if let MaterialDescription::VertexColors | MaterialDescription::Subsurface(..) =
+                self.materials[decal]
+            {
+                return Err(String::from("decals cannot be vertex_colors or subsurface"));
+            }
+        }
+        self.objects().push(object);
+        Ok(())
+    }
+}
+
+// a shape where a group around it is placed
+fn place(shape: Arc<dyn Hittable>, placement: &Placement, object_space: bool) -> Arc<dyn Hittable> {
+    let mut shape = shape;
+    for (axis, angle) in [Axis::X, Axis::Y, Axis::Z]
+        .into_iter()
+        .zip(placement.angles.iter())
+    {
+        if *angle != 0.0 {
+            let rotated = Rotate::new(axis, shape, *angle);
+            shape = if object_space {
+                Arc::new(rotated.in_object_space())
+            } else {
+                Arc::new(rotated)
+            };
+        }
+    }
+    if placement.offset == Vector3::zeros() {
+        return shape;
+    }
+    let translated = Translate::new(shape, placement.offset);
+    if object_space {
+        Arc::new(translated.in_object_space())
+    } else {
+        Arc::new(translated)
+    }
+}
+
+// what a file describes, read by pbrt::read for pbrt scenes
+pub fn read(path: &str, shutter: Shutter) -> Result<Description, String> {
+    if pbrt::is_pbrt(path) {
+        return pbrt::read(path, shutter);
+    }
+    let text = fs::read_to_string(path).map_err(|e| format!("cannot read {}: {}", path, e))?;
+    describe(&text, shutter).map_err(|message| format!("{}: {}", path, message))
+}
+
+// what a file says, starting from the given shutter, or why it is invalid
+pub fn describe(text: &str, shutter: Shutter) -> Result<Description, String> {
+    let mut parser = Parser {
+        shutter,
+        camera: None,
+        background: Arc::new(SolidColor::default()),
+        names: HashMap::new(),
+        materials: Vec::new(),
+        objects: Vec::new(),
+        volumes: Vec::new(),
+        masks: Vec::new(),
+        textures: Vec::new(),
+        texture_names: HashMap::new(),
+        portals: Vec::new(),
+        sun: None,
+        groups: HashMap::new(),
+        group: None,
+    };
+    parser.read(text, 0)?;
+    if let Some((name, _)) = parser.group {
+        return Err(format!("group {} is not ended", name));
+    }
+    let camera = parser.camera.ok_or("no camera statement")?;
+    if parser.objects.is_empty() && parser.volumes.is_empty() {
+        return Err(String::from("no objects"));
+    }
+    Ok(Description {
+        camera,
+        shutter: parser.shutter,
+        background: parser.background,
+        materials: parser.materials,
+        objects: parser.objects,
+        volumes: parser.volumes,
+        masks: parser.masks,
+        textures: parser.textures,
+        portals: parser.portals,
+        sun: parser.sun,
+    })
+}
+
+// the scene a file describes, imported by gltf::load for glTF files
+pub fn load(path: &str, aspect: Float, options: &Options) -> Result<Scene, String> {
+    if gltf::is_gltf(path) {
+        return gltf::load(path, aspect, options);
+    }
+    Ok(read(path, options.shutter)?.scene(aspect, options))
+}
+
+// the scene a description holds, or why it is invalid
+pub fn parse(text: &str, aspect: Float, options: &Options) -> Result<Scene, String> {
+    Ok(describe(text, options.shutter)?.scene(aspect, options))
+}
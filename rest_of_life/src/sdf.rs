@@ -0,0 +1,249 @@
+use crate::aabb::AABB;
+use crate::float::Float;
+use crate::hittable::{HitRecord, Hittable};
+use crate::material::Material;
+use crate::ray::Ray;
+use crate::sampling::ONB;
+use nalgebra::Vector3;
+
+const MAX_STEPS: usize = 256;
+const HIT_EPSILON: Float = 0.0001;
+const NORMAL_EPSILON: Float = 0.0001;
+
+// A surface defined implicitly by a signed distance function, intersected by
+// sphere tracing inside the given bounds.
+pub struct Sdf<F, M>
+where
+    F: Fn(Vector3<Float>) -> Float + Send + Sync,
+    M: Material,
+{
+    distance: F,
+    bbox: AABB,
+    material: M,
+}
+
+impl<F, M> Sdf<F, M>
+where
+    F: Fn(Vector3<Float>) -> Float + Send + Sync,
+    M: Material,
+{
+    pub fn new(distance: F, bbox: AABB, material: M) -> Self {
+        Sdf {
+            distance,
+            bbox,
+            material,
+        }
+    }
+
+    fn normal(&self, p: Vector3<Float>) -> Vector3<Float> {
+        let dx = Vector3::new(NORMAL_EPSILON, 0.0, 0.0);
+        let dy = Vector3::new(0.0, NORMAL_EPSILON, 0.0);
+        let dz = Vector3::new(0.0, 0.0, NORMAL_EPSILON);
+        Vector3::new(
+            (self.distance)(p + dx) - (self.distance)(p - dx),
+            (self.distance)(p + dy) - (self.distance)(p - dy),
+            (self.distance)(p + dz) - (self.distance)(p - dz),
+        )
+        .normalize()
+    }
+}
+
+impl<F, M> Hittable for Sdf<F, M>
+where
+    F: Fn(Vector3<Float>) -> Float + Send + Sync,
+    M: Material,
+{
+    fn hit(&self, ray: &Ray, t_min: Float, t_max: Float) -> Option<HitRecord<'_>> {
+        let (t0, t1) = self.bbox.interval(ray, t_min, t_max)?;
+        let speed = ray.direction().norm();
+        // A ray leaving the surface starts within reach of it, and marches on the side
+        // it heads into, on -distance for those heading inside (e.g. refracted
+        // rays), stepping clear of the surface before looking for hits. Others
+        // march on the side the bounds' entry point is on, and may meet the surface
+        // right there.
+        let origin = ray.origin();
+        let mut leaving = (self.distance)(origin).abs() < HIT_EPSILON;
+        let inside = if leaving {
+            self.normal(origin).dot(&ray.direction()) < 0.0
+        } else {
+            (self.distance)(ray.point_at_parameter(t0)) < 0.0
+        };
+        let sign = if inside { -1.0 } else { 1.0 };
+        let mut t = t0;
+        for _ in 0..MAX_STEPS {
+            let p = ray.point_at_parameter(t);
+            let d = sign * (self.distance)(p);
+            if d < HIT_EPSILON {
+                if leaving {
+                    t += HIT_EPSILON / speed;
+                    continue;
+                }
+                let normal = self.normal(p);
+                return Some(HitRecord {
+                    t,
+                    u: 0.0,
+                    v: 0.0,
+                    p,
+                    texture_p: p,
+                    normal,
+                    tangent: ONB::build_from_w(&normal).u(),
+                    dpdu: Vector3::zeros(),
+                    dpdv: Vector3::zeros(),
+                    material: &self.material,
+                    object_id: 0,
+                    class_id: 0,
+                });
+            }
+            leaving = false;
+            t += d / speed;
+            if t > t1 {
+                break;
+            }
+        }
+        None
+    }
+
+    fn bounding_box(&self, _t0: Float, _t1: Float) -> Option<AABB> {
+        Some(self.bbox)
+    }
+}
+
+// The distance functions a scene file places, see scenefile.rs.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Primitive {
+    Sphere {
+        center: Vector3<Float>,
+        radius: Float,
+    },
+    // a ring about the y axis, `major` from its centre to the middle of a tube
+    // `minor` in radius
+    Torus {
+        center: Vector3<Float>,
+        major: Float,
+        minor: Float,
+    },
+    // a box `half` its size each way from the centre, its edges rounded off by
+    // `radius`
+    RoundedBox {
+        center: Vector3<Float>,
+        half: Vector3<Float>,
+        radius: Float,
+    },
+}
+
+impl Primitive {
+    pub fn distance(&self, p: Vector3<Float>) -> Float {
+        match *self {
+            Primitive::Sphere { center, radius } => (p - center).norm() - radius,
+            Primitive::Torus {
+                center,
+                major,
+                minor,
+            } => {
+                let p = p - center;
+                let across = Vector3::new(p.x, 0.0, p.z).norm() - major;
+                (across * across + p.y * p.y).sqrt() - minor
+            }
+            Primitive::RoundedBox {
+                center,
+                half,
+                radius,
+            } => {
+                let q = (p - center).abs() - half + Vector3::repeat(radius);
+                q.map(|c| c.max(0.0)).norm() + q.max().min(0.0) - radius
+            }
+        }
+    }
+
+    pub fn center(&self) -> Vector3<Float> {
+        match *self {
+            Primitive::Sphere { center, .. }
+            | Primitive::Torus { center, .. }
+            | Primitive::RoundedBox { center, .. } => center,
+        }
+    }
+
+    pub fn bounds(&self) -> AABB {
+        let half = match *self {
+            Primitive::Sphere { radius, .. } => Vector3::repeat(radius),
+            Primitive::Torus { major, minor, .. } => {
+                Vector3::new(major + minor, minor, major + minor)
+            }
+            Primitive::RoundedBox { half, .. } => half,
+        };
+        AABB::new(self.center() - half, self.center() + half)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cli::Options;
+    use crate::material::Lambertian;
+    use crate::ray;
+    use crate::scenefile;
+    use crate::sphere::Sphere;
+    use crate::texture::ConstantTexture;
+
+    #[test]
+    fn sphere_sdf_hits_where_the_sphere_does() {
+        let grey = Lambertian::new(ConstantTexture::new(0.5, 0.5, 0.5));
+        let center = Vector3::new(1.0, 2.0, -3.0);
+        let primitive = Primitive::Sphere {
+            center,
+            radius: 1.5,
+        };
+        let sdf = Sdf::new(
+            move |p| primitive.distance(p),
+            primitive.bounds(),
+            grey.clone(),
+        );
+        let sphere = Sphere::new(center, 1.5, grey);
+        for i in 0..50 {
+            let a = i as Float;
+            let origin = center + 6.0 * Vector3::new(a.sin(), (0.7 * a).cos(), a.cos());
+            let target = center + 0.8 * Vector3::new((1.3 * a).cos(), a.sin(), 0.0);
+            let ray = Ray::new(origin, target - origin, 0.0);
+            let expected = sphere.hit(&ray, ray::T_MIN, Float::MAX).unwrap();
+            let hit = sdf.hit(&ray, ray::T_MIN, Float::MAX).unwrap();
+            assert!((hit.t - expected.t).abs() * ray.direction().norm() < 1e-3);
+            assert!((hit.normal - expected.normal).norm() < 1e-2);
+            // leaving it outward misses it, and inward finds its far side
+            let out = hit.spawn(hit.normal, 0.0);
+            assert!(sdf.hit(&out, ray::T_MIN, Float::MAX).is_none());
+            let direction = ray.direction().normalize();
+            let b = (origin - center).dot(&direction);
+            let c = (origin - center).norm_squared() - 1.5 * 1.5;
+            let far = origin + (-b + (b * b - c).sqrt()) * direction;
+            let inward = hit.spawn(direction, 0.0);
+            let inside = sdf.hit(&inward, ray::T_MIN, Float::MAX).unwrap();
+            assert!((inside.p - far).norm() < 1e-3);
+        }
+        // a ray meeting the surface just where it enters the bounds hits it there
+        let ray = Ray::new(center + Vector3::new(5.0, 0.0, 0.0), -Vector3::x(), 0.0);
+        let hit = sdf.hit(&ray, ray::T_MIN, Float::MAX).unwrap();
+        assert!((hit.t - 3.5).abs() < 1e-3);
+    }
+
+    #[test]
+    fn sdf_statements_place_distance_functions() {
+        let text = "camera from 0 0 10 at 0 0 0\n\
+                    material grey lambertian 0.5 0.5 0.5\n\
+                    sdf torus 0 0 0 2 0.5 grey\n\
+                    sdf rounded_box 4 -1 -1 6 1 1 0.25 grey\n";
+        let scene = scenefile::parse(text, 1.0, &Options::default()).unwrap();
+        // down through the torus's tube, and through its hole
+        let ray = Ray::new(Vector3::new(2.0, 5.0, 0.0), -Vector3::y(), 0.0);
+        let hit = scene.world.hit(&ray, ray::T_MIN, Float::MAX).unwrap();
+        assert!((hit.p.y - 0.5).abs() < 1e-3);
+        let ray = Ray::new(Vector3::new(0.0, 5.0, 0.0), -Vector3::y(), 0.0);
+        assert!(scene.world.hit(&ray, ray::T_MIN, Float::MAX).is_none());
+        // onto the rounded box's face
+        let ray = Ray::new(Vector3::new(5.0, 0.0, 5.0), -Vector3::z(), 0.0);
+        let hit = scene.world.hit(&ray, ray::T_MIN, Float::MAX).unwrap();
+        assert!((hit.p.z - 1.0).abs() < 1e-3);
+        let thin = "camera from 0 0 10 at 0 0 0\nmaterial grey lambertian 0.5 0.5 0.5\n\
+                    sdf torus 0 0 0 1 2 grey\n";
+        assert!(scenefile::parse(thin, 1.0, &Options::default()).is_err());
+    }
+}
@@ -0,0 +1,104 @@
+use crate::float::{self, Float};
+use nalgebra::Vector3;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+// electrons collected for a linear value of 1.0 at ISO 100
+const BASE_ELECTRONS: Float = 10000.0;
+
+fn gaussian(rng: &mut StdRng) -> Float {
+    let u1 = rng.gen::<Float>().max(Float::MIN_POSITIVE);
+    let u2 = rng.gen::<Float>();
+    (-2.0 * u1.ln()).sqrt() * (2.0 * float::consts::PI * u2).cos()
+}
+
+// Post-process simulation of a digital camera sensor applied to the linear HDR
+// image: photon shot noise and read noise at a given ISO, stuck-on hot pixels and
+// a small black-level offset.
+#[derive(Clone)]
+pub struct SensorNoise {
+    pub iso: Float,
+    pub read_noise: Float,
+    pub hot_pixel_fraction: Float,
+    pub black_level: Float,
+    pub seed: u64,
+}
+
+impl SensorNoise {
+    pub fn new(iso: Float) -> Self {
+        SensorNoise {
+            iso,
+            read_noise: 3.0,
+            hot_pixel_fraction: 0.0001,
+            black_level: 0.002,
+            seed: 0,
+        }
+    }
+
+    pub fn apply(&self, image: &mut [Vector3<Float>]) {
+        let mut rng = StdRng::seed_from_u64(self.seed);
+        let electrons_per_unit = BASE_ELECTRONS * 100.0 / self.iso;
+        for pixel in image.iter_mut() {
+            for c in pixel.iter_mut() {
+                let electrons = (*c * electrons_per_unit).max(0.0);
+                let shot = electrons.sqrt() * gaussian(&mut rng);
+                let read = self.read_noise * gaussian(&mut rng);
+                *c = (electrons + shot + read) / electrons_per_unit + self.black_level;
+            }
+            if rng.gen::<Float>() < self.hot_pixel_fraction {
+                let channel = rng.gen_range(0..3);
+                pixel[channel] += 0.5 + rng.gen::<Float>();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cli::Options;
+
+    // the mean and standard deviation of every channel of the image
+    fn moments(image: &[Vector3<Float>]) -> (Float, Float) {
+        let values = || image.iter().flat_map(|p| p.iter().copied());
+        let n = (3 * image.len()) as Float;
+        let mean = values().sum::<Float>() / n;
+        let variance = values().map(|v| (v - mean) * (v - mean)).sum::<Float>() / n;
+        (mean, variance.sqrt())
+    }
+
+    #[test]
+    fn higher_iso_keeps_exposure_and_adds_noise() {
+        let noisy = |iso| {
+            let sensor = SensorNoise {
+                hot_pixel_fraction: 0.0,
+                black_level: 0.0,
+                ..SensorNoise::new(iso)
+            };
+            let mut image = vec![Vector3::repeat(0.5); 20000];
+            sensor.apply(&mut image);
+            moments(&image)
+        };
+        let (mean_100, sigma_100) = noisy(100.0);
+        let (mean_1600, sigma_1600) = noisy(1600.0);
+        // the image is as bright at either, but each electron counts for 16 times
+        // as much at 1600, so its shot noise is 4 times as large
+        assert!((mean_100 - 0.5).abs() < 1e-3);
+        assert!((mean_1600 - 0.5).abs() < 1e-3);
+        assert!((sigma_1600 / sigma_100 - 4.0).abs() < 0.2);
+        let expected = (0.5 / (BASE_ELECTRONS * 100.0 / 1600.0)).sqrt();
+        assert!((sigma_1600 / expected - 1.0).abs() < 0.05);
+    }
+
+    #[test]
+    fn iso_must_be_positive() {
+        let parse = |iso: &str| {
+            Options::from_args(["--sensor-iso", iso].iter().map(|s| s.to_string()))
+                .map(|options| options.sensor.unwrap().iso)
+        };
+        assert_eq!(parse("800"), Ok(800.0));
+        for bad in ["0", "-100", "inf", "NaN"] {
+            assert!(parse(bad).is_err());
+        }
+    }
+}
@@ -0,0 +1,96 @@
+use crate::float::Float;
+use nalgebra::Vector3;
+use std::sync::OnceLock;
+
+// the wavelengths spectral rendering samples, in nanometres
+pub const SHORTEST: Float = 380.0;
+pub const LONGEST: Float = 780.0;
+// sodium D line, where a glass's single index of refraction is given
+pub const SODIUM_D: Float = 589.3;
+
+// How a dielectric's index of refraction varies with wavelength in nanometres.
+// Cauchy's equation n = a + b / l^2 takes b in square micrometres; Sellmeier's
+// n^2 = 1 + sum b_i l^2 / (l^2 - c_i) takes c_i in square micrometres as glass
+// catalogues list them.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Dispersion {
+    Cauchy { a: Float, b: Float },
+    Sellmeier { b: [Float; 3], c: [Float; 3] },
+}
+
+impl Dispersion {
+    // Schott N-BK7, the common crown glass
+    pub const BK7: Dispersion = Dispersion::Sellmeier {
+        b: [1.039_612, 0.231_792_34, 1.010_469_5],
+        c: [0.006_000_699, 0.020_017_914, 103.560_65],
+    };
+
+    // Schott N-SF11, a dense flint that splits white light widely
+    pub const SF11: Dispersion = Dispersion::Sellmeier {
+        b: [1.737_596_9, 0.313_747_35, 1.898_781],
+        c: [0.013_188_707, 0.062_306_814, 155.236_3],
+    };
+
+    pub fn ior(&self, wavelength: Float) -> Float {
+        let micrometres = wavelength / 1000.0;
+        let l2 = micrometres * micrometres;
+        match *self {
+            Dispersion::Cauchy { a, b } => a + b / l2,
+            Dispersion::Sellmeier { b, c } => {
+                let sum: Float = (0..3).map(|i| b[i] * l2 / (l2 - c[i])).sum();
+                (1.0 + sum).sqrt()
+            }
+        }
+    }
+}
+
+// a lobe of the colour matching fits, wider on one side of its peak than the other
+fn lobe(wavelength: Float, peak: Float, below: Float, above: Float) -> Float {
+    let width = if wavelength < peak { below } else { above };
+    let x = (wavelength - peak) / width;
+    (-0.5 * x * x).exp()
+}
+
+// The CIE 1931 2 degree colour matching functions, by the multi-lobe fit of Wyman,
+// Sloan and Shirley (2013).
+pub fn xyz(wavelength: Float) -> Vector3<Float> {
+    let l = wavelength;
+    Vector3::new(
+        1.056 * lobe(l, 599.8, 37.9, 31.0) + 0.362 * lobe(l, 442.0, 16.0, 26.7)
+            - 0.065 * lobe(l, 501.1, 20.4, 26.2),
+        0.821 * lobe(l, 568.8, 46.9, 40.5) + 0.286 * lobe(l, 530.9, 16.3, 31.1),
+        1.217 * lobe(l, 437.0, 11.8, 36.0) + 0.681 * lobe(l, 459.0, 26.0, 13.8),
+    )
+}
+
+// linear sRGB of an equal energy light at one wavelength, negative out of gamut
+fn rgb(wavelength: Float) -> Vector3<Float> {
+    let c = xyz(wavelength);
+    Vector3::new(
+        3.2406 * c.x - 1.5372 * c.y - 0.4986 * c.z,
+        -0.9689 * c.x + 1.8758 * c.y + 0.0415 * c.z,
+        0.0557 * c.x - 0.2040 * c.y + 1.0570 * c.z,
+    )
+}
+
+// a wavelength drawn uniformly from the sampled range
+pub fn sample_wavelength(u: Float) -> Float {
+    SHORTEST + u * (LONGEST - SHORTEST)
+}
+
+// What a path traced at one sampled wavelength adds to each channel, relative to
+// its RGB radiance. Scaled so the weights average one over the sampled range: a
+// path that does not depend on its wavelength comes out as in RGB on average, and
+// one that does, through a dispersive glass, spreads its light into the colours
+// of the wavelengths that took it.
+pub fn rgb_weight(wavelength: Float) -> Vector3<Float> {
+    static MEAN: OnceLock<Vector3<Float>> = OnceLock::new();
+    let mean = MEAN.get_or_init(|| {
+        let steps = 4000;
+        let sum: Vector3<Float> = (0..steps)
+            .map(|i| rgb(sample_wavelength((i as Float + 0.5) / steps as Float)))
+            .sum();
+        sum / steps as Float
+    });
+    rgb(wavelength).component_div(mean)
+}
@@ -1,40 +1,98 @@
+use crate::aabb;
 use crate::aabb::AABB;
+use crate::cone::Cone;
+use crate::float::{self, Float};
 use crate::hittable::{HitRecord, Hittable};
 use crate::material::Material;
-use crate::onb::ONB;
-use crate::ray::Ray;
+use crate::ray::{self, Ray};
+use crate::rng;
+use crate::sampling::{self, ONB};
 use nalgebra::Vector3;
-use rand::Rng;
-use std::f32;
 
-fn get_sphere_uv(p: &Vector3<f32>) -> (f32, f32) {
+// Latitude and longitude of a unit normal p: u runs once around the y axis from the
+// seam on the -x side, through 0.5 at +x, and v from the south pole to the north.
+// The poles are whole rows of a map and the seam joins its sides, so an image
+// wrapped about a sphere wants Wrap::Globe. Rounding can carry p a little past unit
+// length, which the poles clamp.
+fn get_sphere_uv(p: &Vector3<Float>) -> (Float, Float) {
     let phi = p.z.atan2(p.x);
-    let theta = p.y.asin();
-    let u = 1.0 - (phi + f32::consts::PI) / (2.0 * f32::consts::PI);
-    let v = (theta + f32::consts::FRAC_PI_2) / f32::consts::PI;
+    let theta = p.y.clamp(-1.0, 1.0).asin();
+    let u = 1.0 - (phi + float::consts::PI) / (2.0 * float::consts::PI);
+    let v = (theta + float::consts::FRAC_PI_2) / float::consts::PI;
     (u, v)
 }
 
-fn random_to_sphere(radius: f32, distance_squared: f32) -> Vector3<f32> {
-    let mut rng = rand::thread_rng();
-    let r1 = rng.gen::<f32>();
-    let r2 = rng.gen::<f32>();
-    let z = 1.0 + r2 * ((1.0 - radius.powi(2) / distance_squared).sqrt() - 1.0);
-    let phi = 2.0 * f32::consts::PI * r1;
-    let x = phi.cos() * (1.0 - z.powi(2)).sqrt();
-    let y = phi.sin() * (1.0 - z.powi(2)).sqrt();
-    Vector3::new(x, y, z)
+// along the circle of latitude through p, around the y axis
+fn get_sphere_tangent(p: &Vector3<Float>) -> Vector3<Float> {
+    let around = Vector3::new(p.z, 0.0, -p.x);
+    if around.norm_squared() > 1e-12 {
+        around.normalize()
+    } else {
+        Vector3::new(1.0, 0.0, 0.0)
+    }
+}
+
+// how a point on a sphere of the given radius moves with get_sphere_uv's u and v
+// at unit normal n; v's derivative vanishes at the poles
+fn get_sphere_derivatives(n: &Vector3<Float>, radius: Float) -> (Vector3<Float>, Vector3<Float>) {
+    let dpdu = 2.0 * float::consts::PI * radius * Vector3::new(n.z, 0.0, -n.x);
+    let ring = n.x.hypot(n.z);
+    let dpdv = if ring > 1e-6 {
+        float::consts::PI * radius * Vector3::new(-n.y * n.x / ring, ring, -n.y * n.z / ring)
+    } else {
+        Vector3::zeros()
+    };
+    (dpdu, dpdv)
+}
+
+// how near the surface, relative to the squared radius, the origin of a ray is
+// taken to lie on it
+const ON_SURFACE: Float = 32.0 * Float::EPSILON;
+
+// Where a ray meets a sphere, the nearer root first, or None if it misses. A ray
+// leaving the sphere starts on it, where the rounding in its origin's offset from
+// the centre, which grows with the radius, can outweigh the origin's offset from
+// the surface; so an origin within that rounding is taken to be on the surface,
+// its root there being the origin itself, and only the root across the sphere is
+// left, which lies ahead if the ray heads in.
+fn roots(ray: &Ray, center: &Vector3<Float>, radius: Float) -> Option<[Float; 2]> {
+    let oc = ray.origin() - center;
+    let a = ray.direction().dot(&ray.direction());
+    let b = oc.dot(&ray.direction());
+    let c = oc.dot(&oc) - radius.powi(2);
+    if c.abs() < ON_SURFACE * radius.powi(2) {
+        return Some([Float::NEG_INFINITY, -2.0 * b / a]);
+    }
+    let discriminant = b.powi(2) - a * c;
+    if discriminant <= 0.0 {
+        return None;
+    }
+    let sqrt_discriminant = discriminant.sqrt();
+    Some([(-b - sqrt_discriminant) / a, (-b + sqrt_discriminant) / a])
+}
+
+// A point found along a ray put back on the sphere, and the normal there. Where the
+// ray meets the sphere far from its origin the root is imprecise, leaving the point
+// off the surface by more than the offset a ray leaving it starts from; along the
+// normal from the centre it is as near the surface as rounding allows.
+fn surface_point(
+    p: Vector3<Float>,
+    center: &Vector3<Float>,
+    radius: Float,
+) -> (Vector3<Float>, Vector3<Float>) {
+    let normal = ((p - center) / radius).normalize();
+    (center + radius * normal, normal)
 }
 
 #[derive(Clone)]
 pub struct Sphere<M: Material> {
-    center: Vector3<f32>,
-    radius: f32,
+    center: Vector3<Float>,
+    radius: Float,
     material: M,
 }
 
 impl<M: Material> Sphere<M> {
-    pub fn new(center: Vector3<f32>, radius: f32, material: M) -> Self {
+    pub fn new(center: Vector3<Float>, radius: Float, material: M) -> Self {
         Sphere {
             center,
             radius,
@@ -44,68 +102,149 @@ impl<M: Material> Sphere<M> {
 }
 
 impl<M: Material> Hittable for Sphere<M> {
-    fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord> {
-        let oc = ray.origin() - self.center;
-        let a = ray.direction().dot(&ray.direction());
-        let b = oc.dot(&ray.direction());
-        let c = oc.dot(&oc) - self.radius.powi(2);
-        let discriminant = b.powi(2) - a * c;
-        if discriminant > 0.0 {
-            let sqrt_discriminant = discriminant.sqrt();
-            let t = (-b - sqrt_discriminant) / a;
+    fn hit(&self, ray: &Ray, t_min: Float, t_max: Float) -> Option<HitRecord<'_>> {
+        for t in roots(ray, &self.center, self.radius)? {
             if t < t_max && t > t_min {
-                let p = ray.point_at_parameter(t);
-                let normal = (p - self.center) / self.radius;
-                let (u, v) = get_sphere_uv(&normal);
-                return Some(HitRecord {
-                    t,
-                    u,
-                    v,
-                    p,
-                    normal,
-                    material: &self.material,
-                });
-            }
-            let t = (-b + sqrt_discriminant) / a;
-            if t < t_max && t > t_min {
-                let p = ray.point_at_parameter(t);
-                let normal = (p - self.center) / self.radius;
+                let (p, normal) =
+                    surface_point(ray.point_at_parameter(t), &self.center, self.radius);
                 let (u, v) = get_sphere_uv(&normal);
+                let (dpdu, dpdv) = get_sphere_derivatives(&normal, self.radius);
                 return Some(HitRecord {
                     t,
                     u,
                     v,
                     p,
+                    texture_p: p,
                     normal,
+                    tangent: get_sphere_tangent(&normal),
+                    dpdu,
+                    dpdv,
                     material: &self.material,
+                    object_id: 0,
+                    class_id: 0,
                 });
             }
         }
         None
     }
 
-    fn bounding_box(&self, _t0: f32, _t1: f32) -> Option<AABB> {
+    // either root within the range, without the point, normal or uv
+    fn occluded(&self, ray: &Ray, t_min: Float, t_max: Float) -> bool {
+        roots(ray, &self.center, self.radius)
+            .is_some_and(|roots| roots.iter().any(|&t| t < t_max && t > t_min))
+    }
+
+    fn bounding_box(&self, _t0: Float, _t1: Float) -> Option<AABB> {
         let radius = Vector3::new(self.radius, self.radius, self.radius);
         let min = self.center - radius;
         let max = self.center + radius;
         Some(AABB { min, max })
     }
 
-    fn pdf_value(&self, o: Vector3<f32>, v: Vector3<f32>) -> f32 {
-        if let Some(_hit) = self.hit(&Ray::new(o, v, 0.0), 0.001, f32::MAX) {
+    fn pdf_value(&self, o: Vector3<Float>, v: Vector3<Float>) -> Float {
+        if let Some(_hit) = self.hit(&Ray::new(o, v, 0.0), ray::T_MIN, Float::MAX) {
             let cos_theta_max =
                 (1.0 - self.radius.powi(2) / (self.center - o).norm_squared()).sqrt();
-            let solid_angle = 2.0 * f32::consts::PI * (1.0 - cos_theta_max);
-            1.0 / solid_angle
+            sampling::uniform_cone_pdf(cos_theta_max)
         } else {
             0.0
         }
     }
 
-    fn random(&self, o: Vector3<f32>) -> Vector3<f32> {
+    fn random(&self, o: Vector3<Float>) -> Vector3<Float> {
         let direction = self.center - o;
-        let distance_squared = direction.norm_squared();
+        let cos_theta_max = (1.0 - self.radius.powi(2) / direction.norm_squared()).sqrt();
         let uvw = ONB::build_from_w(&direction);
-        uvw.local(&random_to_sphere(self.radius, distance_squared))
+        uvw.local(&sampling::uniform_cone(
+            rng::uniform(),
+            rng::uniform(),
+            cos_theta_max,
+        ))
+    }
+
+    fn sample_surface(&self) -> Option<(Vector3<Float>, Vector3<Float>, Float)> {
+        let normal = sampling::uniform_sphere(rng::uniform(), rng::uniform());
+        let area = 4.0 * float::consts::PI * self.radius.powi(2);
+        Some((self.center + self.radius * normal, normal, area))
+    }
+
+    fn occlusion(&self, cone: &Cone, t_min: Float, t_max: Float) -> Float {
+        cone.sphere_coverage(&self.center, self.radius, t_min, t_max)
+    }
+}
+
+#[derive(Clone)]
+pub struct MovingSphere<M: Material> {
+    center0: Vector3<Float>,
+    center1: Vector3<Float>,
+    time0: Float,
+    time1: Float,
+    radius: Float,
+    material: M,
+}
+
+impl<M: Material> MovingSphere<M> {
+    pub fn new(
+        center0: Vector3<Float>,
+        center1: Vector3<Float>,
+        time0: Float,
+        time1: Float,
+        radius: Float,
+        material: M,
+    ) -> Self {
+        MovingSphere {
+            center0,
+            center1,
+            time0,
+            time1,
+            radius,
+            material,
+        }
+    }
+
+    pub fn center(&self, time: Float) -> Vector3<Float> {
+        self.center0
+            + ((time - self.time0) / (self.time1 - self.time0)) * (self.center1 - self.center0)
+    }
+}
+
+impl<M: Material> Hittable for MovingSphere<M> {
+    fn hit(&self, ray: &Ray, t_min: Float, t_max: Float) -> Option<HitRecord<'_>> {
+        let center = self.center(ray.time());
+        for t in roots(ray, &center, self.radius)? {
+            if t < t_max && t > t_min {
+                let (p, normal) = surface_point(ray.point_at_parameter(t), &center, self.radius);
+                let (u, v) = get_sphere_uv(&normal);
+                let (dpdu, dpdv) = get_sphere_derivatives(&normal, self.radius);
+                return Some(HitRecord {
+                    t,
+                    u,
+                    v,
+                    p,
+                    texture_p: p,
+                    normal,
+                    tangent: get_sphere_tangent(&normal),
+                    dpdu,
+                    dpdv,
+                    material: &self.material,
+                    object_id: 0,
+                    class_id: 0,
+                });
+            }
+        }
+        None
+    }
+
+    fn bounding_box(&self, t0: Float, t1: Float) -> Option<AABB> {
+        let radius = Vector3::new(self.radius, self.radius, self.radius);
+        let aabb0 = AABB {
+            min: self.center(t0) - radius,
+            max: self.center(t0) + radius,
+        };
+        let aabb1 = AABB {
+            min: self.center(t1) - radius,
+            max: self.center(t1) + radius,
+        };
+        Some(aabb::surrounding_box(&aabb0, &aabb1))
     }
 }
@@ -0,0 +1,355 @@
+use crate::float::{self, Float};
+use crate::hittable::{HitRecord, Hittable};
+use crate::integrator;
+use crate::material::ScatterRecord;
+use crate::parallel::*;
+use crate::pdf::PDF;
+use crate::ray::{self, Ray};
+use crate::render::RenderSettings;
+use crate::rng;
+use crate::sampling::{self, ONB};
+use crate::scene::Scene;
+use crate::stats::{self, Counter};
+use nalgebra::Vector3;
+use std::collections::HashMap;
+
+const MAX_DEPTH: usize = 64;
+// photons are traced in chunks, each seeded on its own
+const CHUNK: usize = 1024;
+// the initial radius as a share of the scene's bounding box diagonal
+const RADIUS_SHARE: Float = 0.005;
+
+// Stochastic progressive photon mapping. Every iteration traces `photons` photons
+// from the lights, then one camera path per pixel to the first surface that is not
+// a mirror or glass, where the photons within the pixel's radius are gathered. Each
+// pixel's radius then shrinks so the share alpha of its photons is kept, which
+// makes the estimate converge as iterations add up; spp sets the iterations.
+//
+// Light reaching the camera directly or through mirrors and glass is added as it
+// is found, and direct light at the gather points is sampled toward the scene's
+// light shapes, so photons only carry light that has bounced at least once. Lights
+// whose shapes cannot be sampled by area emit no photons, and the background lights
+// the gather points directly only.
+#[derive(Clone, Copy)]
+pub struct PhotonMapping {
+    pub photons: usize,
+    pub radius: Option<Float>,
+    pub alpha: Float,
+}
+
+impl Default for PhotonMapping {
+    fn default() -> Self {
+        PhotonMapping {
+            photons: 100_000,
+            radius: None,
+            alpha: 2.0 / 3.0,
+        }
+    }
+}
+
+struct Photon {
+    p: Vector3<Float>,
+    // the direction it travelled in
+    direction: Vector3<Float>,
+    power: Vector3<Float>,
+}
+
+// Photons bucketed by cubic cells at least as wide as any radius gathered with, so
+// the photons near a point all sit in the 27 cells around it.
+struct PhotonGrid {
+    cell: Float,
+    cells: HashMap<[i32; 3], Vec<Photon>>,
+}
+
+impl PhotonGrid {
+    fn new(photons: Vec<Photon>, cell: Float) -> Self {
+        let mut grid = PhotonGrid {
+            cell,
+            cells: HashMap::new(),
+        };
+        for photon in photons {
+            grid.cells
+                .entry(grid.key(&photon.p))
+                .or_default()
+                .push(photon);
+        }
+        grid
+    }
+
+    fn key(&self, p: &Vector3<Float>) -> [i32; 3] {
+        [0, 1, 2].map(|axis| (p[axis] / self.cell).floor() as i32)
+    }
+
+    fn near<'a>(
+        &'a self,
+        p: &'a Vector3<Float>,
+        radius: Float,
+    ) -> impl Iterator<Item = &'a Photon> {
+        let [x, y, z] = self.key(p);
+        (-1..=1)
+            .flat_map(move |dx| (-1..=1).flat_map(move |dy| (-1..=1).map(move |dz| [dx, dy, dz])))
+            .filter_map(move |[dx, dy, dz]| self.cells.get(&[x + dx, y + dy, z + dz]))
+            .flatten()
+            .filter(move |photon| (photon.p - p).norm_squared() <= radius * radius)
+    }
+}
+
+// What a pixel has gathered so far: its radius, the photon count its estimate
+// stands for, the flux gathered within the radius and the light found directly.
+#[derive(Clone, Copy)]
+struct Pixel {
+    radius: Float,
+    count: Float,
+    flux: Vector3<Float>,
+    direct: Vector3<Float>,
+}
+
+// a point on a light, the way a photon leaves it and the power it carries
+fn emit(lights: &[&dyn Hittable]) -> Option<(Ray, Vector3<Float>)> {
+    let light = lights[(rng::uniform() * lights.len() as Float) as usize % lights.len()];
+    let (p, normal, area) = light.sample_surface()?;
+    // the light's own emission seen from just off its surface
+    let probe = Ray::new(ray::offset_origin(p, normal, normal), -normal, 0.0);
+    let hit = light.hit(&probe, ray::T_MIN, Float::MAX)?;
+    let emitted = hit.material.emitted(&probe, &hit);
+    let direction = ONB::build_from_w(&normal)
+        .local(&sampling::cosine_hemisphere(rng::uniform(), rng::uniform()));
+    // emitted cos / (pdf of the light, the point and the direction)
+    let power = emitted * float::consts::PI * area * lights.len() as Float;
+    let origin = ray::offset_origin(p, normal, direction);
+    Some((Ray::new(origin, direction, 0.0), power))
+}
+
+// The photons one light path leaves on the surfaces it scatters off diffusely,
+// leaving out the first when direct light is sampled at the gather points.
+fn trace_photon(
+    world: &dyn Hittable,
+    lights: &[&dyn Hittable],
+    sampled_direct: bool,
+) -> Vec<Photon> {
+    let mut photons = Vec::new();
+    let Some((mut ray, mut power)) = emit(lights) else {
+        return photons;
+    };
+    for depth in 0..MAX_DEPTH {
+        let Some(hit) = world.hit(&ray, ray::T_MIN, Float::MAX) else {
+            break;
+        };
+        let hit = hit.material.exit(&ray, hit);
+        let (next, factor) = match hit.material.scatter(&ray, &hit) {
+            None => break,
+            Some(ScatterRecord::Specular {
+                specular_ray,
+                attenuation,
+            }) => (specular_ray, attenuation),
+            Some(ScatterRecord::Scatter { pdf, attenuation }) => {
+                if depth > 0 || !sampled_direct {
+                    photons.push(Photon {
+                        p: hit.p,
+                        direction: ray.direction().normalize(),
+                        power,
+                    });
+                }
+                let scattered = hit.spawn(pdf.generate(), ray.time());
+                let pdf_val = pdf.value(scattered.direction());
+                if !(pdf_val > 0.0 && pdf_val.is_finite()) {
+                    break;
+                }
+                let scattering_pdf = hit.material.scattering_pdf(&ray, &hit, &scattered);
+                (scattered, attenuation * scattering_pdf / pdf_val)
+            }
+        };
+        // russian roulette keeps the power of the photons that go on about the same
+        let next_power = power.component_mul(&factor);
+        let survival = (next_power.max() / power.max()).min(1.0);
+        if survival.is_nan() || rng::uniform() >= survival {
+            break;
+        }
+        power = next_power / survival;
+        ray = next;
+    }
+    photons
+}
+
+fn trace_photons(
+    scene: &Scene,
+    settings: &RenderSettings,
+    iteration: usize,
+    count: usize,
+) -> Vec<Photon> {
+    let lights: Vec<&dyn Hittable> = scene.lights.iter().map(|light| light.as_ref()).collect();
+    if lights.is_empty() {
+        return Vec::new();
+    }
+    let sampled_direct = scene.light_shape.is_some();
+    (0..count.div_ceil(CHUNK))
+        .into_par_iter()
+        .flat_map(|chunk| {
+            // rows past the image's keep the photons' streams apart from the pixels'
+            rng::seed_pixel(settings.rng, settings.seed, iteration, chunk, usize::MAX);
+            let n = CHUNK.min(count - chunk * CHUNK);
+            (0..n)
+                .flat_map(|_| trace_photon(scene.world.as_ref(), &lights, sampled_direct))
+                .collect::<Vec<Photon>>()
+        })
+        .collect()
+}
+
+// Where a camera path gathers photons: the first diffuse surface it hits, the ray
+// that hit it and what the camera sees of it per unit radiance and attenuation.
+struct GatherPoint<'a> {
+    ray: Ray,
+    hit: HitRecord<'a>,
+    weight: Vector3<Float>,
+}
+
+impl GatherPoint<'_> {
+    // the flux seen from the photons within radius, and their number
+    fn gather(&self, grid: &PhotonGrid, radius: Float) -> (Vector3<Float>, usize) {
+        let (ray, hit) = (&self.ray, &self.hit);
+        let mut flux = Vector3::zeros();
+        let mut count = 0;
+        for photon in grid.near(&hit.p, radius) {
+            let incoming = Ray::new(hit.p, -photon.direction, ray.time());
+            let cosine = hit.normal.dot(&photon.direction).abs();
+            if cosine < 1e-4 {
+                continue;
+            }
+            // the bsdf, without the cosine scattering_pdf carries
+            let scattering_pdf = hit.material.scattering_pdf(ray, hit, &incoming);
+            flux += self.weight.component_mul(&photon.power) * scattering_pdf / cosine;
+            count += 1;
+        }
+        (flux, count)
+    }
+}
+
+// the light a camera ray finds on its way to its gather point, and the point
+fn trace_camera(scene: &Scene, mut ray: Ray) -> (Vector3<Float>, Option<GatherPoint<'_>>) {
+    let world = scene.world.as_ref();
+    let mut throughput = Vector3::new(1.0, 1.0, 1.0);
+    let mut direct = Vector3::zeros();
+    for _ in 0..MAX_DEPTH {
+        let Some(hit) = world.hit(&ray, ray::T_MIN, Float::MAX) else {
+            let background = scene.background.radiance(&ray.direction());
+            return (direct + throughput.component_mul(&background), None);
+        };
+        let hit = hit.material.exit(&ray, hit);
+        direct += throughput.component_mul(&hit.material.emitted(&ray, &hit));
+        match hit.material.scatter(&ray, &hit) {
+            None => return (direct, None),
+            Some(ScatterRecord::Specular {
+                specular_ray,
+                attenuation,
+            }) => {
+                throughput = throughput.component_mul(&attenuation);
+                ray = specular_ray;
+            }
+            Some(ScatterRecord::Scatter { pdf, attenuation }) => {
+                let weight = throughput.component_mul(&attenuation);
+                // one light sample and one bsdf sample for the background
+                if let Some(light_shape) = scene.light_shape.as_deref() {
+                    let light_pdf = PDF::hittable(light_shape, hit.p);
+                    let to_light = hit.spawn(light_pdf.generate(), ray.time());
+                    let pdf_val = light_pdf.value(to_light.direction());
+                    if pdf_val > 0.0 && pdf_val.is_finite() {
+                        stats::count(Counter::ShadowRays);
+                        if let Some((emitted, t)) = integrator::aimed_at(light_shape, &to_light) {
+                            if !world.occluded(&to_light, ray::T_MIN, integrator::SHADOW_REACH * t)
+                            {
+                                let scattering_pdf =
+                                    hit.material.scattering_pdf(&ray, &hit, &to_light);
+                                direct += weight.component_mul(&emitted) * scattering_pdf / pdf_val;
+                            }
+                        }
+                    }
+                }
+                let scattered = hit.spawn(pdf.generate(), ray.time());
+                let pdf_val = pdf.value(scattered.direction());
+                stats::count(Counter::ShadowRays);
+                if pdf_val > 0.0
+                    && pdf_val.is_finite()
+                    && !world.occluded(&scattered, ray::T_MIN, Float::MAX)
+                {
+                    let scattering_pdf = hit.material.scattering_pdf(&ray, &hit, &scattered);
+                    let background = scene.background.radiance(&scattered.direction());
+                    direct += weight.component_mul(&background) * scattering_pdf / pdf_val;
+                }
+                let point = GatherPoint { ray, hit, weight };
+                return (direct, Some(point));
+            }
+        }
+    }
+    (direct, None)
+}
+
+pub fn render(
+    scene: &Scene,
+    settings: &RenderSettings,
+    mapping: &PhotonMapping,
+) -> Vec<Vector3<Float>> {
+    let (nx, ny) = (settings.width, settings.height);
+    let radius = mapping.radius.unwrap_or_else(|| {
+        let bbox = scene
+            .world
+            .bounding_box(0.0, 1.0)
+            .expect("no bounding box for photon mapping");
+        RADIUS_SHARE * (bbox.max - bbox.min).norm()
+    });
+    let mut pixels = vec![
+        Pixel {
+            radius,
+            count: 0.0,
+            flux: Vector3::zeros(),
+            direct: Vector3::zeros(),
+        };
+        nx * ny
+    ];
+    for iteration in 0..settings.spp {
+        let photons = trace_photons(scene, settings, iteration, mapping.photons);
+        let widest = pixels
+            .iter()
+            .map(|pixel| pixel.radius)
+            .fold(0.0, Float::max);
+        let grid = PhotonGrid::new(photons, widest);
+        pixels = pixels
+            .par_iter()
+            .enumerate()
+            .map(|(i, pixel)| {
+                // rows from the top, as the image stores them
+                let (x, y) = (i % nx, ny - 1 - i / nx);
+                rng::seed_pixel(settings.rng, settings.seed, iteration, x, y);
+                let u = (x as Float + rng::uniform()) / nx as Float;
+                let v = (y as Float + rng::uniform()) / ny as Float;
+                let Some(ray) = scene.camera.get_ray(u, v) else {
+                    return *pixel;
+                };
+                stats::count(Counter::PrimaryRays);
+                let (direct, point) = trace_camera(scene, ray);
+                let mut pixel = Pixel {
+                    direct: pixel.direct + direct,
+                    ..*pixel
+                };
+                if let Some(point) = point {
+                    let (flux, found) = point.gather(&grid, pixel.radius);
+                    if found > 0 {
+                        let count = pixel.count + mapping.alpha * found as Float;
+                        let shrink = count / (pixel.count + found as Float);
+                        pixel.flux = (pixel.flux + flux) * shrink;
+                        pixel.radius *= shrink.sqrt();
+                        pixel.count = count;
+                    }
+                }
+                pixel
+            })
+            .collect();
+    }
+    let emitted = (settings.spp * mapping.photons) as Float;
+    pixels
+        .iter()
+        .map(|pixel| {
+            pixel.direct / settings.spp as Float
+                + pixel.flux / (emitted * float::consts::PI * pixel.radius.powi(2))
+        })
+        .collect()
+}
@@ -0,0 +1,84 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+// What the render counts with the stats feature on: camera rays, rays continuing
+// paths, rays testing a light's visibility, acceleration structure nodes visited,
+// with a wide node's boxes counted each, and ray triangle intersection tests.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Counter {
+    PrimaryRays,
+    SecondaryRays,
+    ShadowRays,
+    NodeTests,
+    TriangleTests,
+}
+
+static COUNTS: [AtomicU64; 5] = [
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+];
+
+// Adds n to the counter. Without the stats feature this does nothing, so traversal
+// code can count unconditionally.
+#[inline(always)]
+pub fn add(counter: Counter, n: u64) {
+    if cfg!(feature = "stats") {
+        COUNTS[counter as usize].fetch_add(n, Ordering::Relaxed);
+    }
+}
+
+#[inline(always)]
+pub fn count(counter: Counter) {
+    add(counter, 1);
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Stats {
+    pub primary_rays: u64,
+    pub secondary_rays: u64,
+    pub shadow_rays: u64,
+    pub node_tests: u64,
+    pub triangle_tests: u64,
+}
+
+impl Stats {
+    // the counts so far, starting them over
+    pub fn take() -> Self {
+        let take = |counter: Counter| COUNTS[counter as usize].swap(0, Ordering::Relaxed);
+        Stats {
+            primary_rays: take(Counter::PrimaryRays),
+            secondary_rays: take(Counter::SecondaryRays),
+            shadow_rays: take(Counter::ShadowRays),
+            node_tests: take(Counter::NodeTests),
+            triangle_tests: take(Counter::TriangleTests),
+        }
+    }
+
+    pub fn rays(&self) -> u64 {
+        self.primary_rays + self.secondary_rays + self.shadow_rays
+    }
+
+    // the counts and rays per second over a render that took `elapsed`
+    pub fn report(&self, elapsed: Duration) -> String {
+        let seconds = elapsed.as_secs_f64();
+        format!(
+            "primary rays    {:>14}\n\
+             secondary rays  {:>14}\n\
+             shadow rays     {:>14}\n\
+             node tests      {:>14}\n\
+             triangle tests  {:>14}\n\
+             render time     {:>13.3}s\n\
+             rays/second     {:>14.0}\n",
+            self.primary_rays,
+            self.secondary_rays,
+            self.shadow_rays,
+            self.node_tests,
+            self.triangle_tests,
+            seconds,
+            self.rays() as f64 / seconds.max(f64::MIN_POSITIVE),
+        )
+    }
+}
@@ -0,0 +1,208 @@
+use crate::aabb::AABB;
+use crate::cone::Cone;
+use crate::float::Float;
+use crate::hittable::{HitRecord, Hittable};
+use crate::material::{Material, ScatterRecord};
+use crate::pdf::PDF;
+use crate::phase::{HenyeyGreenstein, PhaseFunction};
+use crate::ray::{self, Ray};
+use crate::rng;
+use crate::sampling::{self, ONB};
+use nalgebra::Vector3;
+
+// steps after which a walk is taken as absorbed
+const MAX_STEPS: usize = 256;
+
+// Where a walk comes out, scattering diffusely like Lambertian so the light there
+// is sampled directly. A walk follows one colour channel, so only that channel
+// comes out, three times as bright.
+struct Exit {
+    channel: usize,
+}
+
+impl Material for Exit {
+    fn scatter(&self, _ray: &Ray, hit: &HitRecord) -> Option<ScatterRecord<'_>> {
+        let mut attenuation = Vector3::zeros();
+        attenuation[self.channel] = 3.0;
+        Some(ScatterRecord::Scatter {
+            pdf: PDF::cosine(hit.normal),
+            attenuation,
+        })
+    }
+
+    fn scattering_pdf(&self, _ray: &Ray, hit: &HitRecord, scattered: &Ray) -> Float {
+        sampling::cosine_hemisphere_pdf(hit.normal.dot(&scattered.direction().normalize()))
+    }
+}
+
+// where a walk ends inside
+struct Absorbed;
+
+impl Material for Absorbed {}
+
+// Subsurface scattering by a volumetric random walk inside a closed boundary, for
+// wax, skin, marble and milk. Rays hit the boundary as they would any surface;
+// light entering there diffusely scatters through the interior, each channel with
+// its own mean free path and single scattering albedo, until it reaches the
+// boundary again. The integrator's exit step, see Material::exit, takes the path
+// to that exit point with a diffuse material, so it samples the lights from where
+// the light leaves rather than where it entered. Each walk follows one channel
+// picked at random and survives each scattering with that channel's albedo. The
+// boundary's own material is not used.
+pub struct Subsurface<H: Hittable> {
+    boundary: H,
+    // extinction per unit length, one over the mean free path
+    extinction: Vector3<Float>,
+    albedo: Vector3<Float>,
+    phase: HenyeyGreenstein,
+    exits: [Exit; 3],
+}
+
+impl<H: Hittable> Subsurface<H> {
+    pub fn new(
+        boundary: H,
+        mean_free_path: Vector3<Float>,
+        albedo: Vector3<Float>,
+        g: Float,
+    ) -> Self {
+        assert!(
+            mean_free_path.min() > 0.0,
+            "mean free paths must be positive"
+        );
+        assert!(
+            albedo.min() >= 0.0 && albedo.max() <= 1.0,
+            "albedo must be in [0, 1]"
+        );
+        Subsurface {
+            boundary,
+            extinction: mean_free_path.map(|d| 1.0 / d),
+            albedo,
+            phase: HenyeyGreenstein::new(g),
+            exits: [
+                Exit { channel: 0 },
+                Exit { channel: 1 },
+                Exit { channel: 2 },
+            ],
+        }
+    }
+
+    // the point and outward normal where a walk from p into the interior leaves it,
+    // or None if it is absorbed on the way
+    fn walk(
+        &self,
+        p: Vector3<Float>,
+        normal: Vector3<Float>,
+        time: Float,
+        channel: usize,
+    ) -> Option<HitRecord<'_>> {
+        let extinction = self.extinction[channel];
+        let albedo = self.albedo[channel];
+        let mut direction = ONB::build_from_w(&-normal)
+            .local(&sampling::cosine_hemisphere(rng::uniform(), rng::uniform()));
+        // off the boundary it enters by, then from points inside
+        let mut p = ray::offset_origin(p, normal, direction);
+        for _ in 0..MAX_STEPS {
+            let distance = -(1.0 - rng::uniform()).ln() / extinction;
+            let ray = Ray::new(p, direction, time);
+            if let Some(mut exit) = self.boundary.hit(&ray, ray::T_MIN, distance) {
+                if exit.normal.dot(&direction) < 0.0 {
+                    exit.normal = -exit.normal;
+                }
+                return Some(exit);
+            }
+            if rng::uniform() >= albedo {
+                return None;
+            }
+            p = ray.point_at_parameter(distance);
+            direction = self.phase.sample(&direction);
+        }
+        None
+    }
+}
+
+impl<H: Hittable> Hittable for Subsurface<H> {
+    // the boundary's hit, with the walk for its material
+    fn hit(&self, ray: &Ray, t_min: Float, t_max: Float) -> Option<HitRecord<'_>> {
+        let entry = self.boundary.hit(ray, t_min, t_max)?;
+        Some(HitRecord {
+            material: self,
+            ..entry
+        })
+    }
+
+    fn bounding_box(&self, t0: Float, t1: Float) -> Option<AABB> {
+        self.boundary.bounding_box(t0, t1)
+    }
+
+    fn occlusion(&self, cone: &Cone, t_min: Float, t_max: Float) -> Float {
+        self.boundary.occlusion(cone, t_min, t_max)
+    }
+}
+
+// Walks light entering at the hit out through the interior. The path goes on from
+// the exit, at the entry's distance; a walk absorbed inside ends it there.
+impl<H: Hittable> Material for Subsurface<H> {
+    // what looks at the surface without following light through it, as the albedo
+    // AOV does, sees it diffuse with the walk's albedo
+    fn scatter(&self, _ray: &Ray, hit: &HitRecord) -> Option<ScatterRecord<'_>> {
+        Some(ScatterRecord::Scatter {
+            pdf: PDF::cosine(hit.normal),
+            attenuation: self.albedo,
+        })
+    }
+
+    fn scattering_pdf(&self, _ray: &Ray, hit: &HitRecord, scattered: &Ray) -> Float {
+        sampling::cosine_hemisphere_pdf(hit.normal.dot(&scattered.direction().normalize()))
+    }
+
+    fn exit<'a>(&'a self, ray: &Ray, hit: HitRecord<'a>) -> HitRecord<'a> {
+        let channel = ((3.0 * rng::uniform()) as usize).min(2);
+        match self.walk(hit.p, hit.normal, ray.time(), channel) {
+            Some(exit) => HitRecord {
+                t: hit.t,
+                material: &self.exits[channel],
+                ..exit
+            },
+            None => HitRecord {
+                material: &Absorbed,
+                ..hit
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::Lambertian;
+    use crate::sphere::Sphere;
+    use crate::texture::ConstantTexture;
+
+    #[test]
+    fn rays_hit_the_boundary_and_walk_out_of_it() {
+        let grey = Lambertian::new(ConstantTexture::new(0.5, 0.5, 0.5));
+        let boundary = || Sphere::new(Vector3::zeros(), 1.0, grey.clone());
+        let marble = Subsurface::new(boundary(), Vector3::repeat(0.2), Vector3::repeat(0.99), 0.0);
+        let ray = Ray::new(Vector3::new(0.0, 0.0, -3.0), Vector3::z(), 0.0);
+        let entry = marble.hit(&ray, ray::T_MIN, Float::MAX).unwrap();
+        let sphere = boundary();
+        let plain = sphere.hit(&ray, ray::T_MIN, Float::MAX).unwrap();
+        assert_eq!(
+            (entry.t, entry.p, entry.normal),
+            (plain.t, plain.p, plain.normal)
+        );
+        let mut exits = 0;
+        for _ in 0..100 {
+            let entry = marble.hit(&ray, ray::T_MIN, Float::MAX).unwrap();
+            let exit = entry.material.exit(&ray, entry);
+            assert_eq!(exit.t, plain.t);
+            if exit.material.name() != Absorbed.name() {
+                // on the boundary, facing out
+                assert!((exit.p.norm() - 1.0).abs() < 1e-3);
+                assert!(exit.normal.dot(&exit.p) > 0.0);
+                exits += 1;
+            }
+        }
+        assert!(exits > 50);
+    }
+}
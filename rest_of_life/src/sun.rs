@@ -0,0 +1,145 @@
+use crate::aabb::AABB;
+use crate::background::Background;
+use crate::float::{self, Float};
+use crate::hittable::{HitRecord, Hittable};
+use crate::ray::Ray;
+use crate::rng;
+use crate::sampling::ONB;
+use nalgebra::Vector3;
+use std::sync::Arc;
+
+// the sun's angular diameter seen from the earth, in degrees
+pub const SUN_DIAMETER: Float = 0.53;
+
+// A distant light, the sun: a disc of uniform radiance so far away that every
+// point sees it in the same direction and at the same size. Its size is what
+// softens the shadows it casts, the penumbra widening with the distance from the
+// edge that casts it. It is given by the irradiance it delivers to a surface
+// facing it, so widening the disc softens the shadows without brightening them.
+//
+// The sun is not in the world. Its light arrives as the background's does, on rays
+// that leave the scene inside its disc, see SunAndSky, and it goes among the
+// scene's light shapes so that diffuse bounces aim part of their rays at the
+// disc, drawn uniformly over the cone it fills.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Sun {
+    direction: Vector3<Float>,
+    radiance: Vector3<Float>,
+    // the squared sine of the disc's half angle and one less its cosine, as the
+    // cosine of so small an angle rounds to nearly 1
+    sin2_max: Float,
+    one_minus_cos: Float,
+}
+
+impl Sun {
+    // the sun toward `direction` delivering `irradiance` to a surface facing it,
+    // its disc `diameter` degrees across
+    pub fn new(direction: Vector3<Float>, irradiance: Vector3<Float>, diameter: Float) -> Self {
+        let half = 0.5 * diameter.to_radians();
+        let one_minus_cos = 2.0 * (0.5 * half).sin().powi(2);
+        Sun {
+            direction: direction.normalize(),
+            radiance: irradiance / (float::consts::PI * half.sin().powi(2)),
+            sin2_max: half.sin().powi(2),
+            one_minus_cos,
+        }
+    }
+
+    pub fn direction(&self) -> Vector3<Float> {
+        self.direction
+    }
+
+    // whether `direction` points into the disc, judged by the sine of its angle
+    // from the centre, which stays precise where the cosine would not
+    fn covers(&self, direction: &Vector3<Float>) -> bool {
+        direction.dot(&self.direction) > 0.0
+            && direction.cross(&self.direction).norm_squared()
+                <= self.sin2_max * direction.norm_squared()
+    }
+
+    // the radiance arriving against `direction`, the disc's inside it and none
+    // outside
+    pub fn radiance(&self, direction: &Vector3<Float>) -> Vector3<Float> {
+        if self.covers(direction) {
+            self.radiance
+        } else {
+            Vector3::zeros()
+        }
+    }
+}
+
+impl Hittable for Sun {
+    // rays reach the sun by leaving the scene, never by hitting it
+    fn hit(&self, _ray: &Ray, _t_min: Float, _t_max: Float) -> Option<HitRecord<'_>> {
+        None
+    }
+
+    fn bounding_box(&self, _t0: Float, _t1: Float) -> Option<AABB> {
+        None
+    }
+
+    fn pdf_value(&self, _o: Vector3<Float>, v: Vector3<Float>) -> Float {
+        if self.covers(&v) {
+            1.0 / (2.0 * float::consts::PI * self.one_minus_cos)
+        } else {
+            0.0
+        }
+    }
+
+    // uniform in the cone, as sampling::uniform_cone but with the height's
+    // distance from 1 worked out directly so the tiny disc keeps its shape
+    fn random(&self, _o: Vector3<Float>) -> Vector3<Float> {
+        let drop = rng::uniform() * self.one_minus_cos;
+        let phi = 2.0 * float::consts::PI * rng::uniform();
+        let r = (drop * (2.0 - drop)).sqrt();
+        ONB::build_from_w(&self.direction).local(&Vector3::new(
+            r * phi.cos(),
+            r * phi.sin(),
+            1.0 - drop,
+        ))
+    }
+}
+
+// a background with the sun's disc laid over it
+pub struct SunAndSky {
+    pub sun: Sun,
+    pub sky: Arc<dyn Background>,
+}
+
+impl Background for SunAndSky {
+    fn radiance(&self, direction: &Vector3<Float>) -> Vector3<Float> {
+        self.sky.radiance(direction) + self.sun.radiance(direction)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn samples_fill_the_disc() {
+        let sun = Sun::new(
+            Vector3::new(1.0, 2.0, -0.5),
+            Vector3::new(3.0, 3.0, 3.0),
+            SUN_DIAMETER,
+        );
+        let half = 0.5 * SUN_DIAMETER.to_radians();
+        let pdf = sun.pdf_value(Vector3::zeros(), sun.direction());
+        let solid_angle = 2.0 * std::f64::consts::PI * (1.0 - float::double(half).cos());
+        assert!((float::double(pdf) * solid_angle - 1.0).abs() < 1e-3);
+        let (mut widest, mut irradiance) = (0.0 as Float, 0.0);
+        for _ in 0..1000 {
+            let d = sun.random(Vector3::zeros());
+            assert!((d.norm() - 1.0).abs() < 1e-5);
+            let pdf = sun.pdf_value(Vector3::zeros(), 5.0 * d);
+            assert!(pdf > 0.0);
+            widest = widest.max(d.dot(&sun.direction()).min(1.0).acos());
+            irradiance += sun.radiance(&d).x * d.dot(&sun.direction()) / pdf / 1000.0;
+        }
+        // they reach out to the rim and no further, and deliver what was asked to
+        // a surface facing the sun
+        assert!(widest <= half * 1.01 && widest > half * 0.9);
+        assert!((irradiance - 3.0).abs() < 0.01);
+        assert_eq!(sun.pdf_value(Vector3::zeros(), -sun.direction()), 0.0);
+    }
+}
@@ -1,16 +1,73 @@
+use crate::differential;
+use crate::float::{self, Float};
+use crate::hittable::HitRecord;
+use crate::perlin::{Octaves, Perlin};
+use crate::ray::Ray;
+use crate::volume::SparseGrid;
+use crate::worley::Worley;
 use nalgebra::Vector3;
+use std::sync::Arc;
 
-pub trait Texture: Sync {
-    fn value(&self, u: f32, v: f32, p: &Vector3<f32>) -> Vector3<f32>;
+// How far u and v change across a pixel, to the right and up the image: the patch
+// of texture space a lookup averages over.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Footprint {
+    pub dudx: Float,
+    pub dvdx: Float,
+    pub dudy: Float,
+    pub dvdy: Float,
+}
+
+pub trait Texture: Send + Sync {
+    fn value(&self, u: Float, v: Float, p: &Vector3<Float>) -> Vector3<Float>;
+
+    // the texture averaged over a footprint around (u, v); textures with no detail
+    // to alias give their value there
+    fn filtered(
+        &self,
+        u: Float,
+        v: Float,
+        p: &Vector3<Float>,
+        _footprint: &Footprint,
+    ) -> Vector3<Float> {
+        self.value(u, v, p)
+    }
+}
+
+// A texture's value at a hit, filtered over the footprint of the ray's
+// differentials where it has them.
+pub fn lookup<T: Texture + ?Sized>(texture: &T, ray: &Ray, hit: &HitRecord) -> Vector3<Float> {
+    match differential::footprint(ray, hit) {
+        Some(footprint) => texture.filtered(hit.u, hit.v, &hit.texture_p, &footprint),
+        None => texture.value(hit.u, hit.v, &hit.texture_p),
+    }
+}
+
+// A shared texture is the texture itself, so one loaded image can serve many
+// materials.
+impl<T: Texture + ?Sized> Texture for Arc<T> {
+    fn value(&self, u: Float, v: Float, p: &Vector3<Float>) -> Vector3<Float> {
+        self.as_ref().value(u, v, p)
+    }
+
+    fn filtered(
+        &self,
+        u: Float,
+        v: Float,
+        p: &Vector3<Float>,
+        footprint: &Footprint,
+    ) -> Vector3<Float> {
+        self.as_ref().filtered(u, v, p, footprint)
+    }
 }
 
 #[derive(Clone)]
 pub struct ConstantTexture {
-    color: Vector3<f32>,
+    color: Vector3<Float>,
 }
 
 impl ConstantTexture {
-    pub fn new(r: f32, g: f32, b: f32) -> Self {
+    pub fn new(r: Float, g: Float, b: Float) -> Self {
         ConstantTexture {
             color: Vector3::new(r, g, b),
         }
@@ -18,7 +75,728 @@ impl ConstantTexture {
 }
 
 impl Texture for ConstantTexture {
-    fn value(&self, _u: f32, _v: f32, _p: &Vector3<f32>) -> Vector3<f32> {
+    fn value(&self, _u: Float, _v: Float, _p: &Vector3<Float>) -> Vector3<Float> {
         self.color
     }
 }
+
+#[derive(Clone)]
+pub struct CheckerTexture<T: Texture, U: Texture> {
+    odd: T,
+    even: U,
+}
+
+impl<T: Texture, U: Texture> CheckerTexture<T, U> {
+    pub fn new(odd: T, even: U) -> Self {
+        CheckerTexture { odd, even }
+    }
+}
+
+impl<T: Texture, U: Texture> Texture for CheckerTexture<T, U> {
+    fn value(&self, u: Float, v: Float, p: &Vector3<Float>) -> Vector3<Float> {
+        let sines = Float::sin(10.0 * p.x) * Float::sin(10.0 * p.y) * Float::sin(10.0 * p.z);
+        if sines < 0.0 {
+            self.odd.value(u, v, p)
+        } else {
+            self.even.value(u, v, p)
+        }
+    }
+
+    fn filtered(
+        &self,
+        u: Float,
+        v: Float,
+        p: &Vector3<Float>,
+        footprint: &Footprint,
+    ) -> Vector3<Float> {
+        let sines = Float::sin(10.0 * p.x) * Float::sin(10.0 * p.y) * Float::sin(10.0 * p.z);
+        if sines < 0.0 {
+            self.odd.filtered(u, v, p, footprint)
+        } else {
+            self.even.filtered(u, v, p, footprint)
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct NoiseTexture {
+    noise: Perlin,
+    scale: Float,
+}
+
+impl NoiseTexture {
+    pub fn new(scale: Float) -> Self {
+        NoiseTexture {
+            noise: Perlin::new(),
+            scale,
+        }
+    }
+}
+
+impl Texture for NoiseTexture {
+    fn value(&self, _u: Float, _v: Float, p: &Vector3<Float>) -> Vector3<Float> {
+        Vector3::new(1.0, 1.0, 1.0)
+            * 0.5
+            * (1.0 + Float::sin(self.scale * p.x + 5.0 * self.noise.turb(p, 7)))
+    }
+}
+
+// Wood grain, a solid texture: growth rings around the y axis, `rings` to a unit
+// of radius, shading from `light` early wood to `dark` late wood, their edges
+// wavering by `turbulence` rings of Perlin noise. Cut from anywhere in space, so a
+// plank shows the grain across its end and along its faces alike.
+#[derive(Clone)]
+pub struct WoodTexture {
+    light: Vector3<Float>,
+    dark: Vector3<Float>,
+    rings: Float,
+    turbulence: Float,
+    noise: Perlin,
+}
+
+impl WoodTexture {
+    pub fn new(
+        light: Vector3<Float>,
+        dark: Vector3<Float>,
+        rings: Float,
+        turbulence: Float,
+    ) -> Self {
+        WoodTexture {
+            light,
+            dark,
+            rings,
+            turbulence,
+            noise: Perlin::new(),
+        }
+    }
+}
+
+impl Texture for WoodTexture {
+    fn value(&self, _u: Float, _v: Float, p: &Vector3<Float>) -> Vector3<Float> {
+        let radius = p.x.hypot(p.z) * self.rings;
+        let ring = radius + self.turbulence * self.noise.turb(&(p * self.rings), 7);
+        // late wood is the narrower, darker band
+        let late = (0.5 - 0.5 * (2.0 * float::consts::PI * ring).cos()).powi(3);
+        self.light.lerp(&self.dark, late)
+    }
+}
+
+// the noise a FractalTexture sums: fBm for clouds, water and soft stone, ridged
+// multifractal for mountain ranges, and Worley's cells, by f1 or by the cracks of
+// f2 - f1 between them, for pebbles, scales and flagstones
+#[derive(Clone)]
+pub enum Fractal {
+    Fbm(Perlin),
+    Ridged(Perlin),
+    Cells(Worley),
+    Cracks(Worley),
+}
+
+impl Fractal {
+    pub fn fbm() -> Self {
+        Fractal::Fbm(Perlin::new())
+    }
+
+    pub fn ridged() -> Self {
+        Fractal::Ridged(Perlin::new())
+    }
+
+    pub fn cells() -> Self {
+        Fractal::Cells(Worley::new())
+    }
+
+    pub fn cracks() -> Self {
+        Fractal::Cracks(Worley::new())
+    }
+
+    // the fractal at p, from about 0 to 1
+    fn at(&self, p: &Vector3<Float>, octaves: &Octaves) -> Float {
+        match self {
+            Fractal::Fbm(noise) => 0.5 + 0.5 * noise.fbm(p, octaves),
+            Fractal::Ridged(noise) => noise.ridged(p, octaves),
+            Fractal::Cells(noise) => noise.fractal(p, octaves, false),
+            Fractal::Cracks(noise) => noise.fractal(p, octaves, true),
+        }
+    }
+}
+
+// A solid texture blending from `low` to `high` by a fractal of the point scaled
+// by `frequency`, so its first octave's features are about 1 / frequency across.
+#[derive(Clone)]
+pub struct FractalTexture {
+    fractal: Fractal,
+    octaves: Octaves,
+    frequency: Float,
+    low: Vector3<Float>,
+    high: Vector3<Float>,
+}
+
+impl FractalTexture {
+    pub fn new(
+        fractal: Fractal,
+        octaves: Octaves,
+        frequency: Float,
+        low: Vector3<Float>,
+        high: Vector3<Float>,
+    ) -> Self {
+        FractalTexture {
+            fractal,
+            octaves,
+            frequency,
+            low,
+            high,
+        }
+    }
+}
+
+impl Texture for FractalTexture {
+    fn value(&self, _u: Float, _v: Float, p: &Vector3<Float>) -> Vector3<Float> {
+        let t = self.fractal.at(&(p * self.frequency), &self.octaves);
+        self.low.lerp(&self.high, t.clamp(0.0, 1.0))
+    }
+}
+
+// A solid texture from a voxel grid, as vdb::read loads one, placed as a volume
+// is: index space scaled by `scale` and moved to start at `origin`. Each point
+// takes the grid's trilinear sample there over its largest as its share of `high`
+// over `low`, and points outside the grid are `low`.
+#[derive(Clone)]
+pub struct VoxelTexture {
+    grid: Arc<SparseGrid>,
+    origin: Vector3<Float>,
+    scale: Float,
+    max: Float,
+    low: Vector3<Float>,
+    high: Vector3<Float>,
+}
+
+impl VoxelTexture {
+    pub fn new(
+        grid: Arc<SparseGrid>,
+        origin: Vector3<Float>,
+        scale: Float,
+        low: Vector3<Float>,
+        high: Vector3<Float>,
+    ) -> Self {
+        VoxelTexture {
+            max: grid.max_density(),
+            grid,
+            origin,
+            scale,
+            low,
+            high,
+        }
+    }
+}
+
+impl Texture for VoxelTexture {
+    fn value(&self, _u: Float, _v: Float, p: &Vector3<Float>) -> Vector3<Float> {
+        if self.max <= 0.0 {
+            return self.low;
+        }
+        let share = self.grid.sample(&((p - self.origin) / self.scale)) / self.max;
+        self.low.lerp(&self.high, share.clamp(0.0, 1.0))
+    }
+}
+
+// What an image shows beyond its edges
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Wrap {
+    // its edge texels, stretched
+    Clamp,
+    // itself again, each way, for tiling
+    Repeat,
+    // itself again across its sides, but its top and bottom rows stretched, as a
+    // latitude-longitude map closes around a sphere's seam and gathers at its poles
+    Globe,
+}
+
+impl Wrap {
+    // the column and row of a texel, for an image width by height
+    fn address(self, x: i64, y: i64, width: usize, height: usize) -> (usize, usize) {
+        let (width, height) = (width as i64, height as i64);
+        let x = match self {
+            Wrap::Clamp => x.clamp(0, width - 1),
+            Wrap::Repeat | Wrap::Globe => x.rem_euclid(width),
+        };
+        let y = match self {
+            Wrap::Clamp | Wrap::Globe => y.clamp(0, height - 1),
+            Wrap::Repeat => y.rem_euclid(height),
+        };
+        (x as usize, y as usize)
+    }
+}
+
+// one level of an image's mip pyramid, rows from the top
+#[derive(Clone)]
+struct MipLevel {
+    width: usize,
+    height: usize,
+    texels: Vec<Vector3<Float>>,
+}
+
+impl MipLevel {
+    fn texel(&self, x: usize, y: usize) -> Vector3<Float> {
+        self.texels[y.min(self.height - 1) * self.width + x.min(self.width - 1)]
+    }
+
+    fn wrapped(&self, x: i64, y: i64, wrap: Wrap) -> Vector3<Float> {
+        let (x, y) = wrap.address(x, y, self.width, self.height);
+        self.texels[y * self.width + x]
+    }
+
+    // the level at half the size, each texel the mean of the up to four below it
+    fn halved(&self) -> MipLevel {
+        let (width, height) = (self.width.div_ceil(2), self.height.div_ceil(2));
+        let texels = (0..height)
+            .flat_map(|y| (0..width).map(move |x| (x, y)))
+            .map(|(x, y)| {
+                let (x0, y0) = (2 * x, 2 * y);
+                let (x1, y1) = ((x0 + 1).min(self.width - 1), (y0 + 1).min(self.height - 1));
+                0.25 * (self.texel(x0, y0)
+                    + self.texel(x1, y0)
+                    + self.texel(x0, y1)
+                    + self.texel(x1, y1))
+            })
+            .collect();
+        MipLevel {
+            width,
+            height,
+            texels,
+        }
+    }
+
+    // interpolated between the four texel centres around (u, v)
+    fn bilinear(&self, u: Float, v: Float, wrap: Wrap) -> Vector3<Float> {
+        let x = u * self.width as Float - 0.5;
+        let y = (1.0 - v) * self.height as Float - 0.5;
+        let (x0, y0) = (x.floor() as i64, y.floor() as i64);
+        let (fx, fy) = (x - x.floor(), y - y.floor());
+        let texel = |x, y| self.wrapped(x, y, wrap);
+        let top = texel(x0, y0).lerp(&texel(x0 + 1, y0), fx);
+        let bottom = texel(x0, y0 + 1).lerp(&texel(x0 + 1, y0 + 1), fx);
+        top.lerp(&bottom, fy)
+    }
+
+    // Heckbert's elliptically weighted average, as pbrt takes it: the texels inside
+    // the ellipse with axes a and b, in uv units, around (u, v), weighted by a
+    // gaussian falling off toward its rim. The ellipse is widened to at least a
+    // texel across so it never falls between texel centres.
+    fn ewa(
+        &self,
+        u: Float,
+        v: Float,
+        a: (Float, Float),
+        b: (Float, Float),
+        wrap: Wrap,
+    ) -> Vector3<Float> {
+        const ALPHA: Float = 2.0;
+        let (w, h) = (self.width as Float, self.height as Float);
+        let (s, t) = (u * w - 0.5, (1.0 - v) * h - 0.5);
+        // texel rows run down the image, against v
+        let (ds0, dt0) = (a.0 * w, -a.1 * h);
+        let (ds1, dt1) = (b.0 * w, -b.1 * h);
+        let mut ea = dt0 * dt0 + dt1 * dt1 + 1.0;
+        let mut eb = -2.0 * (ds0 * dt0 + ds1 * dt1);
+        let mut ec = ds0 * ds0 + ds1 * ds1 + 1.0;
+        let inv_f = 1.0 / (ea * ec - 0.25 * eb * eb);
+        ea *= inv_f;
+        eb *= inv_f;
+        ec *= inv_f;
+        let det = 4.0 * ea * ec - eb * eb;
+        let (s_extent, t_extent) = (2.0 * (det * ec).sqrt() / det, 2.0 * (det * ea).sqrt() / det);
+        let (s0, s1) = ((s - s_extent).ceil() as i64, (s + s_extent).floor() as i64);
+        let (t0, t1) = ((t - t_extent).ceil() as i64, (t + t_extent).floor() as i64);
+        let mut sum = Vector3::zeros();
+        let mut weights = 0.0;
+        for y in t0..=t1 {
+            let dt = y as Float - t;
+            for x in s0..=s1 {
+                let ds = x as Float - s;
+                let r2 = ea * ds * ds + eb * ds * dt + ec * dt * dt;
+                if r2 < 1.0 {
+                    let weight = (-ALPHA * r2).exp() - (-ALPHA).exp();
+                    sum += weight * self.wrapped(x, y, wrap);
+                    weights += weight;
+                }
+            }
+        }
+        if weights > 0.0 {
+            sum / weights
+        } else {
+            self.bilinear(u, v, wrap)
+        }
+    }
+}
+
+// how many times longer than it is wide a footprint is filtered as: longer ones
+// are widened, blurring them a little rather than summing ever more texels
+const MAX_ANISOTROPY: Float = 8.0;
+
+// An RGB image over the unit square, v up, and beyond it as `wrap` says, clamped
+// unless given. Unfiltered lookups take the nearest texel. Filtered ones average the texels the footprint's ellipse covers, on the
+// two levels of its mip pyramid whose texels are nearest its width across, so a
+// footprint drawn out along a surface seen at a grazing angle is averaged along
+// its length without blurring across it.
+#[derive(Clone)]
+pub struct ImageTexture {
+    data: Vec<u8>,
+    nx: u32,
+    ny: u32,
+    levels: Vec<MipLevel>,
+    wrap: Wrap,
+}
+
+#[allow(dead_code)]
+impl ImageTexture {
+    pub fn new(data: Vec<u8>, nx: u32, ny: u32) -> Self {
+        let base = MipLevel {
+            width: nx as usize,
+            height: ny as usize,
+            texels: data
+                .chunks_exact(3)
+                .map(|c| Vector3::new(c[0], c[1], c[2]).map(|c| c as Float / 255.0))
+                .collect(),
+        };
+        let mut levels = vec![base];
+        while let Some(last) = levels.last().filter(|l| l.width > 1 || l.height > 1) {
+            let next = last.halved();
+            levels.push(next);
+        }
+        ImageTexture {
+            data,
+            nx,
+            ny,
+            levels,
+            wrap: Wrap::Clamp,
+        }
+    }
+
+    pub fn with_wrap(self, wrap: Wrap) -> Self {
+        ImageTexture { wrap, ..self }
+    }
+
+    pub fn open(path: &str) -> Result<Self, String> {
+        let image = image::open(path)
+            .map_err(|e| format!("cannot read {}: {}", path, e))?
+            .to_rgb8();
+        let (nx, ny) = image.dimensions();
+        Ok(ImageTexture::new(image.into_raw(), nx, ny))
+    }
+}
+
+impl Texture for ImageTexture {
+    fn value(&self, u: Float, v: Float, _p: &Vector3<Float>) -> Vector3<Float> {
+        let nx = self.nx as usize;
+        let ny = self.ny as usize;
+        let (i, j) = self.wrap.address(
+            (u * nx as Float).floor() as i64,
+            ((1.0 - v) * ny as Float).floor() as i64,
+            nx,
+            ny,
+        );
+        let idx = 3 * i + 3 * nx * j;
+        let r = self.data[idx] as Float / 255.0;
+        let g = self.data[idx + 1] as Float / 255.0;
+        let b = self.data[idx + 2] as Float / 255.0;
+        Vector3::new(r, g, b)
+    }
+
+    fn filtered(
+        &self,
+        u: Float,
+        v: Float,
+        _p: &Vector3<Float>,
+        footprint: &Footprint,
+    ) -> Vector3<Float> {
+        // the footprint's axes, the longer first, and their lengths in texels of
+        // the full image
+        let (nx, ny) = (self.nx as Float, self.ny as Float);
+        let texels = |(du, dv): (Float, Float)| (du * nx).hypot(dv * ny);
+        let (mut major, mut minor) = (
+            (footprint.dudx, footprint.dvdx),
+            (footprint.dudy, footprint.dvdy),
+        );
+        if texels(major) < texels(minor) {
+            std::mem::swap(&mut major, &mut minor);
+        }
+        let (major_length, mut minor_length) = (texels(major), texels(minor));
+        // magnified, the texels are interpolated
+        if major_length <= 1.0 {
+            return self.levels[0].bilinear(u, v, self.wrap);
+        }
+        if minor_length * MAX_ANISOTROPY < major_length {
+            // a footprint with no width is widened across its length
+            let axis = if minor_length > 0.0 {
+                minor
+            } else {
+                (-major.1 * ny / nx, major.0 * nx / ny)
+            };
+            minor_length = major_length / MAX_ANISOTROPY;
+            let scale = minor_length / texels(axis);
+            minor = (axis.0 * scale, axis.1 * scale);
+        }
+        let top = (self.levels.len() - 1) as Float;
+        let level = minor_length.max(1.0).log2().min(top);
+        let below = level.floor();
+        let fine = self.levels[below as usize].ewa(u, v, major, minor, self.wrap);
+        if level == below {
+            return fine;
+        }
+        let coarse = self.levels[below as usize + 1].ewa(u, v, major, minor, self.wrap);
+        fine.lerp(&coarse, level - below)
+    }
+}
+
+// A triangle's colours at its corners, blended by the barycentric weights of b and
+// c that a triangle without texture coordinates reports as u and v.
+#[derive(Clone)]
+pub struct VertexColors {
+    colors: [Vector3<Float>; 3],
+}
+
+impl VertexColors {
+    pub fn new(colors: [Vector3<Float>; 3]) -> Self {
+        VertexColors { colors }
+    }
+}
+
+impl Texture for VertexColors {
+    fn value(&self, u: Float, v: Float, _p: &Vector3<Float>) -> Vector3<Float> {
+        (1.0 - u - v) * self.colors[0] + u * self.colors[1] + v * self.colors[2]
+    }
+}
+
+// Where a texture sits on its surface, moved without editing it: turned by
+// `rotation` degrees anticlockwise and grown `scale` times about the middle of the
+// unit square, then moved `offset` along u and v.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct UvTransform {
+    pub offset: (Float, Float),
+    pub scale: (Float, Float),
+    pub rotation: Float,
+}
+
+impl Default for UvTransform {
+    fn default() -> Self {
+        UvTransform {
+            offset: (0.0, 0.0),
+            scale: (1.0, 1.0),
+            rotation: 0.0,
+        }
+    }
+}
+
+impl UvTransform {
+    // the texture's own coordinates of a change (du, dv) across the surface
+    fn linear(&self, (du, dv): (Float, Float)) -> (Float, Float) {
+        let (sin, cos) = (-self.rotation.to_radians()).sin_cos();
+        (
+            (cos * du - sin * dv) / self.scale.0,
+            (sin * du + cos * dv) / self.scale.1,
+        )
+    }
+
+    // the texture's own coordinates at (u, v) on the surface
+    fn apply(&self, u: Float, v: Float) -> (Float, Float) {
+        let (u, v) = self.linear((u - self.offset.0 - 0.5, v - self.offset.1 - 0.5));
+        (u + 0.5, v + 0.5)
+    }
+}
+
+// A texture looked up through a UvTransform, its footprints transformed with it.
+// Whatever falls beyond the texture's unit square is as it wraps there, so an
+// image meant to move around a sphere wants Wrap::Globe.
+#[derive(Clone)]
+pub struct TransformedTexture<T: Texture> {
+    texture: T,
+    transform: UvTransform,
+}
+
+impl<T: Texture> TransformedTexture<T> {
+    pub fn new(texture: T, transform: UvTransform) -> Self {
+        TransformedTexture { texture, transform }
+    }
+}
+
+impl<T: Texture> Texture for TransformedTexture<T> {
+    fn value(&self, u: Float, v: Float, p: &Vector3<Float>) -> Vector3<Float> {
+        let (u, v) = self.transform.apply(u, v);
+        self.texture.value(u, v, p)
+    }
+
+    fn filtered(
+        &self,
+        u: Float,
+        v: Float,
+        p: &Vector3<Float>,
+        footprint: &Footprint,
+    ) -> Vector3<Float> {
+        let (u, v) = self.transform.apply(u, v);
+        let (dudx, dvdx) = self.transform.linear((footprint.dudx, footprint.dvdx));
+        let (dudy, dvdy) = self.transform.linear((footprint.dudy, footprint.dvdy));
+        let footprint = Footprint {
+            dudx,
+            dvdx,
+            dudy,
+            dvdy,
+        };
+        self.texture.filtered(u, v, p, &footprint)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // a black and white checkerboard of single texels
+    fn checkerboard(n: u32) -> ImageTexture {
+        let data = (0..n * n)
+            .flat_map(|i| {
+                let c = if (i % n + i / n).is_multiple_of(2) {
+                    255
+                } else {
+                    0
+                };
+                [c, c, c]
+            })
+            .collect();
+        ImageTexture::new(data, n, n)
+    }
+
+    #[test]
+    fn wide_footprints_average_the_texels() {
+        let texture = checkerboard(16);
+        assert_eq!(texture.levels.len(), 5);
+        let p = Vector3::zeros();
+        let texel = 1.0 / 16.0;
+        let sharp = Footprint {
+            dudx: texel,
+            dvdx: 0.0,
+            dudy: 0.0,
+            dvdy: texel,
+        };
+        let centre = 0.5 * texel;
+        assert_eq!(
+            texture.filtered(centre, 1.0 - centre, &p, &sharp),
+            texture.value(centre, 1.0 - centre, &p)
+        );
+        let blurred = Footprint {
+            dudx: 8.0 * texel,
+            dvdx: 0.0,
+            dudy: 0.0,
+            dvdy: 8.0 * texel,
+        };
+        for (u, v) in [(0.3, 0.3), (0.71, 0.45), (0.5, 0.9)] {
+            let c = texture.filtered(u, v, &p, &blurred);
+            assert!((c - Vector3::repeat(0.5)).norm() < 1e-5, "{:?}", c);
+        }
+    }
+
+    #[test]
+    fn voxels_blend_between_their_colours() {
+        let grid = SparseGrid::new([([0, 0, 0], 2.0), ([1, 0, 0], 1.0)]);
+        let (low, high) = (Vector3::zeros(), Vector3::new(1.0, 0.5, 0.0));
+        let texture =
+            VoxelTexture::new(Arc::new(grid), Vector3::new(10.0, 0.0, 0.0), 0.5, low, high);
+        let at = |x: Float| texture.value(0.0, 0.0, &Vector3::new(x, 0.0, 0.0));
+        assert_eq!(at(10.0), high);
+        assert_eq!(at(10.5), 0.5 * high);
+        assert_eq!(at(10.25), 0.75 * high);
+        assert_eq!(at(12.0), low);
+    }
+
+    #[test]
+    fn transforms_move_a_wrapped_image() {
+        // a 4 by 2 image, its texels numbered along the top row then the bottom
+        let data = (0..8u8).flat_map(|i| [i, 0, 0]).collect();
+        let image = ImageTexture::new(data, 4, 2).with_wrap(Wrap::Globe);
+        let p = Vector3::zeros();
+        let texel = |c: Vector3<Float>| (c.x * 255.0).round() as u8;
+        // around the seam, but not over the poles
+        assert_eq!(texel(image.value(1.1, 0.9, &p)), 0);
+        assert_eq!(texel(image.value(-0.1, 0.9, &p)), 3);
+        assert_eq!(texel(image.value(0.1, -0.5, &p)), 4);
+        let moved = TransformedTexture::new(
+            image.clone(),
+            UvTransform {
+                offset: (0.25, 0.0),
+                ..UvTransform::default()
+            },
+        );
+        assert_eq!(texel(moved.value(0.3, 0.9, &p)), 0);
+        assert_eq!(texel(moved.value(0.1, 0.9, &p)), 3);
+        // a quarter turn anticlockwise lays the image's left side along the
+        // bottom, and turns footprints back the other way
+        let turned = TransformedTexture::new(
+            image,
+            UvTransform {
+                rotation: 90.0,
+                ..UvTransform::default()
+            },
+        );
+        assert_eq!(texel(turned.value(0.4, 0.1, &p)), 0);
+        assert_eq!(texel(turned.value(0.6, 0.1, &p)), 4);
+        let (du, dv) = turned.transform.linear((0.01, 0.0));
+        assert!(du.abs() < 1e-6 && (dv + 0.01).abs() < 1e-6);
+    }
+
+    #[test]
+    fn fractals_vary_between_their_colours() {
+        let (low, high) = (Vector3::zeros(), Vector3::new(1.0, 1.0, 1.0));
+        for fractal in [
+            Fractal::fbm(),
+            Fractal::ridged(),
+            Fractal::cells(),
+            Fractal::cracks(),
+        ] {
+            let texture = FractalTexture::new(fractal, Octaves::default(), 3.0, low, high);
+            let values: Vec<Float> = (0..200)
+                .map(|i| {
+                    let p = Vector3::new(0.013 * i as Float, 0.007 * i as Float, 0.5);
+                    texture.value(0.0, 0.0, &p).x
+                })
+                .collect();
+            let least = values.iter().cloned().fold(Float::INFINITY, Float::min);
+            let most = values.iter().cloned().fold(Float::NEG_INFINITY, Float::max);
+            assert!(least >= 0.0 && most <= 1.0);
+            assert!(most - least > 0.1);
+        }
+    }
+
+    #[test]
+    fn long_footprints_keep_detail_across_them() {
+        // rows of texels alternately black and white, so only v varies
+        let n: u32 = 32;
+        let data = (0..n * n)
+            .flat_map(|i| {
+                let c = if (i / n).is_multiple_of(2) { 255 } else { 0 };
+                [c, c, c]
+            })
+            .collect();
+        let texture = ImageTexture::new(data, n, n);
+        let p = Vector3::zeros();
+        let texel = 1.0 / n as Float;
+        // eight texels along the rows, a quarter of one across them
+        let grazing = Footprint {
+            dudx: 8.0 * texel,
+            dvdx: 0.0,
+            dudy: 0.0,
+            dvdy: 0.25 * texel,
+        };
+        let row = |y: u32| 1.0 - (y as Float + 0.5) * texel;
+        let white = texture.filtered(0.5, row(10), &p, &grazing);
+        let black = texture.filtered(0.5, row(11), &p, &grazing);
+        // an isotropic filter as wide would give grey
+        assert!(white.x > 0.6 && black.x < 0.4, "{:?} {:?}", white, black);
+        // the same footprint turned across the rows averages them
+        let turned = Footprint {
+            dudx: 0.0,
+            dvdx: 8.0 * texel,
+            dudy: 0.25 * texel,
+            dvdy: 0.0,
+        };
+        let grey = texture.filtered(0.5, row(10), &p, &turned);
+        assert!((grey.x - 0.5).abs() < 0.1, "{:?}", grey);
+    }
+}
@@ -0,0 +1,32 @@
+use crate::float::Float;
+use crate::rng;
+use nalgebra::Vector3;
+
+// Preview speed knob: paths whose throughput drops below min_throughput are cut,
+// which darkens the image slightly. With `probabilistic` such paths instead survive
+// with probability throughput / min_throughput and are reweighted, which keeps the
+// estimate unbiased at the cost of some noise.
+#[derive(Clone, Copy, Default)]
+pub struct ThroughputCutoff {
+    pub min_throughput: Float,
+    pub probabilistic: bool,
+}
+
+impl ThroughputCutoff {
+    // weight for continuing a path with the given throughput, None to terminate it
+    pub fn continuation(&self, throughput: &Vector3<Float>) -> Option<Float> {
+        let t = throughput.max();
+        if t >= self.min_throughput {
+            return Some(1.0);
+        }
+        if !self.probabilistic || t <= 0.0 {
+            return None;
+        }
+        let p = t / self.min_throughput;
+        if rng::uniform() < p {
+            Some(1.0 / p)
+        } else {
+            None
+        }
+    }
+}
@@ -0,0 +1,240 @@
+use crate::adaptive;
+use crate::cli::Options;
+use crate::float::{self, Float};
+use crate::metadata::Metadata;
+use crate::render::{self, Image, RenderSettings};
+use crate::scene::Scene;
+use nalgebra::Vector3;
+use std::fs;
+
+// Splits a render across processes, on this machine or others, render farm style.
+// The image is cut into square tiles numbered row by row from the top left; a
+// worker given the scene, options and seed of the others renders the tiles from
+// `start` up to but not including `end` and writes their pixels as a partial
+// buffer, and merging the partial buffers of every tile gives the image. Every
+// pixel seeds its own samples, so it is the image one process would render.
+//
+// A partial buffer is the magic "RTPB" and the image's width and height as
+// little-endian u32, then for each pixel its column and row from the top left as
+// u32, its estimate as three f32 and the samples it took as u32, all
+// little-endian.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TileRange {
+    pub size: usize,
+    pub start: usize,
+    pub end: usize,
+}
+
+const MAGIC: &[u8; 4] = b"RTPB";
+
+impl Default for TileRange {
+    fn default() -> Self {
+        TileRange {
+            size: 32,
+            start: 0,
+            end: 0,
+        }
+    }
+}
+
+impl TileRange {
+    // tiles across and down an image
+    fn grid(&self, width: usize, height: usize) -> (usize, usize) {
+        (width.div_ceil(self.size), height.div_ceil(self.size))
+    }
+
+    pub fn count(&self, width: usize, height: usize) -> usize {
+        let (across, down) = self.grid(width, height);
+        across * down
+    }
+
+    // the range's pixels, columns and rows from the top left, tile by tile;
+    // the last column and row of tiles are cut short by the image's edges
+    pub fn pixels(&self, width: usize, height: usize) -> Vec<(usize, usize)> {
+        let (across, _) = self.grid(width, height);
+        let mut pixels = Vec::new();
+        for tile in self.start..self.end {
+            let (x0, y0) = ((tile % across) * self.size, (tile / across) * self.size);
+            for y in y0..(y0 + self.size).min(height) {
+                pixels.extend((x0..(x0 + self.size).min(width)).map(|x| (x, y)));
+            }
+        }
+        pixels
+    }
+
+    // renders the range's tiles into a partial buffer
+    pub fn render(&self, scene: &Scene, settings: &RenderSettings) -> Vec<u8> {
+        let pixels = self.pixels(settings.width, settings.height);
+        let estimates = render::render_pixels(scene, settings, &pixels);
+        let mut bytes = MAGIC.to_vec();
+        bytes.extend((settings.width as u32).to_le_bytes());
+        bytes.extend((settings.height as u32).to_le_bytes());
+        for ((x, y), (c, n)) in pixels.iter().zip(estimates) {
+            bytes.extend((*x as u32).to_le_bytes());
+            bytes.extend((*y as u32).to_le_bytes());
+            for c in c.iter() {
+                bytes.extend(float::single(*c).to_le_bytes());
+            }
+            bytes.extend((n as u32).to_le_bytes());
+        }
+        bytes
+    }
+}
+
+fn u32_at(bytes: &[u8], at: usize) -> u32 {
+    u32::from_le_bytes([bytes[at], bytes[at + 1], bytes[at + 2], bytes[at + 3]])
+}
+
+fn f32_at(bytes: &[u8], at: usize) -> Float {
+    f32::from_bits(u32_at(bytes, at)) as Float
+}
+
+// The image the partial buffers cover and the samples each of its pixels took,
+// rows from the top. Every pixel must be in exactly one buffer.
+pub fn merge(parts: &[Vec<u8>]) -> Result<(Image, Vec<usize>), String> {
+    const RECORD: usize = 24;
+    let mut size = None;
+    let mut pixels = Vec::new();
+    let mut counts = Vec::new();
+    let mut filled = Vec::new();
+    for (i, part) in parts.iter().enumerate() {
+        if part.len() < 12 || &part[..4] != MAGIC || (part.len() - 12) % RECORD != 0 {
+            return Err(format!("partial buffer {} is not one", i + 1));
+        }
+        let (width, height) = (u32_at(part, 4) as usize, u32_at(part, 8) as usize);
+        match size {
+            None => {
+                size = Some((width, height));
+                pixels = vec![Vector3::zeros(); width * height];
+                counts = vec![0; width * height];
+                filled = vec![false; width * height];
+            }
+            Some(size) if size != (width, height) => {
+                return Err(format!(
+                    "partial buffer {} is {}x{}, not {}x{} as the first",
+                    i + 1,
+                    width,
+                    height,
+                    size.0,
+                    size.1
+                ));
+            }
+            _ => {}
+        }
+        for record in part[12..].chunks_exact(RECORD) {
+            let (x, y) = (u32_at(record, 0) as usize, u32_at(record, 4) as usize);
+            if x >= width || y >= height {
+                return Err(format!(
+                    "partial buffer {} has pixels outside the image",
+                    i + 1
+                ));
+            }
+            let at = y * width + x;
+            if filled[at] {
+                return Err(format!(
+                    "pixel {},{} is in more than one partial buffer",
+                    x, y
+                ));
+            }
+            filled[at] = true;
+            pixels[at] = Vector3::new(f32_at(record, 8), f32_at(record, 12), f32_at(record, 16));
+            counts[at] = u32_at(record, 20) as usize;
+        }
+    }
+    let (width, height) = size.ok_or("no partial buffers to merge")?;
+    let missing = filled.iter().filter(|f| !**f).count();
+    if missing > 0 {
+        return Err(format!("{} pixels are in no partial buffer", missing));
+    }
+    let image = Image {
+        width,
+        height,
+        pixels,
+    };
+    Ok((image, counts))
+}
+
+// Merges the partial buffers --merge names into the image, in --format, applying
+// the sensor the workers left out, and writes the heatmap when asked to.
+pub fn merge_files(paths: &[String], options: &Options) -> Result<Vec<u8>, String> {
+    let parts = paths
+        .iter()
+        .map(|path| fs::read(path).map_err(|e| format!("cannot read {}: {}", path, e)))
+        .collect::<Result<Vec<Vec<u8>>, String>>()?;
+    let (mut image, counts) = merge(&parts)?;
+    if let Some(sensor) = &options.sensor {
+        sensor.apply(&mut image.pixels);
+    }
+    if let Some(path) = &options.heatmap {
+        let heatmap = adaptive::heatmap(image.width, image.height, &counts, options.spp);
+        fs::write(path, heatmap).map_err(|e| format!("cannot write {}: {}", path, e))?;
+    }
+    Ok(image.encode(
+        options.format,
+        &options.tone,
+        options.spp,
+        &Metadata::default(),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scene;
+
+    #[test]
+    fn tiles_cover_the_image_once() {
+        let tiles = TileRange {
+            size: 4,
+            start: 0,
+            end: 0,
+        };
+        let count = tiles.count(10, 7);
+        assert_eq!(count, 6);
+        let mut pixels = TileRange {
+            end: count,
+            ..tiles
+        }
+        .pixels(10, 7);
+        pixels.sort_unstable();
+        let all: Vec<(usize, usize)> = (0..10).flat_map(|x| (0..7).map(move |y| (x, y))).collect();
+        assert_eq!(pixels, all);
+    }
+
+    #[test]
+    fn merged_tiles_match_a_whole_render() {
+        let settings = RenderSettings {
+            width: 12,
+            height: 9,
+            spp: 4,
+            seed: 7,
+            ..RenderSettings::default()
+        };
+        let scene = scene::by_name("cornell_box", 12.0 / 9.0, &Options::default()).unwrap();
+        let whole = render::render(&scene, &settings);
+        let tiles = TileRange {
+            size: 5,
+            ..TileRange::default()
+        };
+        let count = tiles.count(settings.width, settings.height);
+        let parts: Vec<Vec<u8>> = [(0, 2), (2, count)]
+            .iter()
+            .map(|&(start, end)| {
+                TileRange {
+                    start,
+                    end,
+                    ..tiles
+                }
+                .render(&scene, &settings)
+            })
+            .collect();
+        let (merged, counts) = merge(&parts).unwrap();
+        // the tiles hold f32s, whatever the renderer computes in
+        let single = |pixels: &[Vector3<Float>]| -> Vec<Vector3<f32>> {
+            pixels.iter().map(|p| p.cast()).collect()
+        };
+        assert_eq!(single(&merged.pixels), single(&whole.pixels));
+        assert!(counts.iter().all(|n| *n == settings.spp));
+        assert!(merge(&parts[..1]).is_err());
+    }
+}
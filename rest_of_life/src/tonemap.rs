@@ -0,0 +1,94 @@
+use crate::float::Float;
+use crate::lut::Lut;
+use nalgebra::Vector3;
+use std::str::FromStr;
+use std::sync::Arc;
+
+// Curve that maps linear HDR values into [0, 1] before encoding and quantization.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum ToneMap {
+    #[default]
+    Clamp,
+    Reinhard,
+    ACES,
+}
+
+impl FromStr for ToneMap {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "clamp" => Ok(ToneMap::Clamp),
+            "reinhard" => Ok(ToneMap::Reinhard),
+            "aces" => Ok(ToneMap::ACES),
+            _ => Err(format!("unknown tone map: {}", s)),
+        }
+    }
+}
+
+// How tone mapped values are encoded for display. sRGB is the standard transfer
+// curve; a plain gamma raises values to its inverse, 2.2 matching displays that
+// expect one and 2 the book's sqrt, kept to compare against its images.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum Transfer {
+    #[default]
+    SRGB,
+    Gamma(Float),
+}
+
+impl Transfer {
+    pub fn encode(self, linear: Float) -> Float {
+        match self {
+            Transfer::SRGB if linear <= 0.0031308 => 12.92 * linear,
+            Transfer::SRGB => 1.055 * linear.powf(1.0 / 2.4) - 0.055,
+            Transfer::Gamma(gamma) => linear.powf(1.0 / gamma),
+        }
+    }
+}
+
+// Narkowicz's fit of the ACES filmic curve
+fn aces(c: Float) -> Float {
+    (c * (2.51 * c + 0.03)) / (c * (2.43 * c + 0.59) + 0.14)
+}
+
+// Tone mapping of the 8 bit outputs. Values are scaled by 2^exposure, then mapped
+// by the operator; a white point is the scaled value shown as full white, without
+// one clamp cuts at 1, Reinhard approaches white at infinity and ACES follows the
+// plain curve. The result is then encoded by the transfer function, and the
+// encoded colour looked up in the LUT, if any, as a grade would look up footage.
+#[derive(Clone, Debug, Default)]
+pub struct ToneMapping {
+    pub operator: ToneMap,
+    pub exposure: Float,
+    pub white: Option<Float>,
+    pub transfer: Transfer,
+    pub lut: Option<Arc<Lut>>,
+}
+
+impl ToneMapping {
+    pub fn map(&self, c: Float) -> Float {
+        let c = (c * self.exposure.exp2()).max(0.0);
+        let mapped = match (self.operator, self.white) {
+            (ToneMap::Clamp, None) => c,
+            (ToneMap::Clamp, Some(white)) => c / white,
+            (ToneMap::Reinhard, None) => c / (1.0 + c),
+            (ToneMap::Reinhard, Some(white)) => c * (1.0 + c / (white * white)) / (1.0 + c),
+            (ToneMap::ACES, None) => aces(c),
+            (ToneMap::ACES, Some(white)) => aces(c) / aces(white),
+        };
+        if mapped.is_nan() {
+            0.0
+        } else {
+            mapped.min(1.0)
+        }
+    }
+
+    // tone mapped, encoded and graded display colour, each channel in [0, 1]
+    pub fn display(&self, c: &Vector3<Float>) -> Vector3<Float> {
+        let encoded = c.map(|c| self.transfer.encode(self.map(c)));
+        match &self.lut {
+            Some(lut) => lut.apply(&encoded).map(|c| c.clamp(0.0, 1.0)),
+            None => encoded,
+        }
+    }
+}
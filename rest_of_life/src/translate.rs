@@ -1,33 +1,75 @@
 use crate::aabb::AABB;
+use crate::cone::Cone;
+use crate::float::Float;
 use crate::hittable::{HitRecord, Hittable};
 use crate::ray::Ray;
 use nalgebra::Vector3;
 
 pub struct Translate<H: Hittable> {
     hittable: H,
-    offset: Vector3<f32>,
+    offset: Vector3<Float>,
+    object_space: bool,
 }
 
 impl<H: Hittable> Translate<H> {
-    pub fn new(hittable: H, offset: Vector3<f32>) -> Self {
-        Translate { hittable, offset }
+    pub fn new(hittable: H, offset: Vector3<Float>) -> Self {
+        Translate {
+            hittable,
+            offset,
+            object_space: false,
+        }
+    }
+
+    // the same hittable with its solid textures in its own space, so they move
+    // with it rather than it moving through them
+    pub fn in_object_space(self) -> Self {
+        Translate {
+            object_space: true,
+            ..self
+        }
     }
 }
 
 impl<H: Hittable> Hittable for Translate<H> {
-    fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord> {
+    fn hit(&self, ray: &Ray, t_min: Float, t_max: Float) -> Option<HitRecord<'_>> {
         let moved_ray = Ray::new(ray.origin() - self.offset, ray.direction(), ray.time());
         self.hittable.hit(&moved_ray, t_min, t_max).map(|mut hit| {
             hit.p += self.offset;
+            if !self.object_space {
+                hit.texture_p += self.offset;
+            }
             hit
         })
     }
 
-    fn bounding_box(&self, t0: f32, t1: f32) -> Option<AABB> {
+    fn occluded(&self, ray: &Ray, t_min: Float, t_max: Float) -> bool {
+        let moved_ray = Ray::new(ray.origin() - self.offset, ray.direction(), ray.time());
+        self.hittable.occluded(&moved_ray, t_min, t_max)
+    }
+
+    fn bounding_box(&self, t0: Float, t1: Float) -> Option<AABB> {
         self.hittable.bounding_box(t0, t1).map(|mut b| {
             b.min += self.offset;
             b.max += self.offset;
             b
         })
     }
+
+    fn pdf_value(&self, o: Vector3<Float>, v: Vector3<Float>) -> Float {
+        self.hittable.pdf_value(o - self.offset, v)
+    }
+
+    fn random(&self, o: Vector3<Float>) -> Vector3<Float> {
+        self.hittable.random(o - self.offset)
+    }
+
+    fn occlusion(&self, cone: &Cone, t_min: Float, t_max: Float) -> Float {
+        let moved = cone.transform(|p| p - self.offset, |d| *d);
+        self.hittable.occlusion(&moved, t_min, t_max)
+    }
+
+    fn sample_surface(&self) -> Option<(Vector3<Float>, Vector3<Float>, Float)> {
+        let (p, normal, area) = self.hittable.sample_surface()?;
+        Some((p + self.offset, normal, area))
+    }
 }
@@ -0,0 +1,261 @@
+use crate::aabb::AABB;
+use crate::bvh::BVH;
+use crate::float::Float;
+use crate::hittable::{HitRecord, Hittable, Surfaces};
+use crate::material::Material;
+use crate::ray::{self, Ray};
+use crate::rng;
+use crate::stats::{self, Counter};
+use nalgebra::Vector3;
+
+const PADDING: Float = 0.0001;
+
+// A flat triangle, facing the side its corners wind counter-clockwise around. The
+// hit's u and v are the barycentric weights of b and c, or the texture coordinates
+// given for the corners interpolated between them.
+#[derive(Clone)]
+pub struct Triangle<M: Material> {
+    a: Vector3<Float>,
+    b: Vector3<Float>,
+    c: Vector3<Float>,
+    normals: Option<[Vector3<Float>; 3]>,
+    uvs: Option<[(Float, Float); 3]>,
+    material: M,
+}
+
+impl<M: Material> Triangle<M> {
+    pub fn new(a: Vector3<Float>, b: Vector3<Float>, c: Vector3<Float>, material: M) -> Self {
+        Triangle {
+            a,
+            b,
+            c,
+            normals: None,
+            uvs: None,
+            material,
+        }
+    }
+
+    // Shades with the corners' normals interpolated over the face, as a smooth
+    // surface the triangles approximate. Lights are sampled by the shading normal
+    // too, so keep emitters flat.
+    pub fn with_normals(self, normals: [Vector3<Float>; 3]) -> Self {
+        Triangle {
+            normals: Some(normals.map(|n| n.normalize())),
+            ..self
+        }
+    }
+
+    pub fn with_uvs(self, uvs: [(Float, Float); 3]) -> Self {
+        Triangle {
+            uvs: Some(uvs),
+            ..self
+        }
+    }
+
+    pub fn area(&self) -> Float {
+        0.5 * (self.b - self.a).cross(&(self.c - self.a)).norm()
+    }
+
+    // a point spread uniformly over the triangle
+    fn sample(&self, u1: Float, u2: Float) -> Vector3<Float> {
+        let s = u1.sqrt();
+        (1.0 - s) * self.a + s * (1.0 - u2) * self.b + s * u2 * self.c
+    }
+}
+
+impl<M: Material> Hittable for Triangle<M> {
+    fn hit(&self, ray: &Ray, t_min: Float, t_max: Float) -> Option<HitRecord<'_>> {
+        stats::count(Counter::TriangleTests);
+        let e1 = self.b - self.a;
+        let e2 = self.c - self.a;
+        let p = ray.direction().cross(&e2);
+        let det = e1.dot(&p);
+        if det.abs() < 1e-12 {
+            return None;
+        }
+        let s = ray.origin() - self.a;
+        let u = s.dot(&p) / det;
+        if !(0.0..=1.0).contains(&u) {
+            return None;
+        }
+        let q = s.cross(&e1);
+        let v = ray.direction().dot(&q) / det;
+        if v < 0.0 || u + v > 1.0 {
+            return None;
+        }
+        let t = e2.dot(&q) / det;
+        if t < t_min || t > t_max {
+            return None;
+        }
+        let w = 1.0 - u - v;
+        let normal = match self.normals {
+            Some([na, nb, nc]) => (w * na + u * nb + v * nc).normalize(),
+            None => e1.cross(&e2).normalize(),
+        };
+        let (tex_u, tex_v) = match self.uvs {
+            Some([ta, tb, tc]) => (
+                w * ta.0 + u * tb.0 + v * tc.0,
+                w * ta.1 + u * tb.1 + v * tc.1,
+            ),
+            None => (u, v),
+        };
+        let (dpdu, dpdv) = match self.uvs {
+            Some([ta, tb, tc]) => {
+                // the edges from c solved for the directions of u and v
+                let (du_ac, dv_ac) = (ta.0 - tc.0, ta.1 - tc.1);
+                let (du_bc, dv_bc) = (tb.0 - tc.0, tb.1 - tc.1);
+                let det = du_ac * dv_bc - dv_ac * du_bc;
+                if det.abs() < 1e-12 {
+                    (Vector3::zeros(), Vector3::zeros())
+                } else {
+                    let (ac, bc) = (self.a - self.c, self.b - self.c);
+                    (
+                        (dv_bc * ac - dv_ac * bc) / det,
+                        (du_ac * bc - du_bc * ac) / det,
+                    )
+                }
+            }
+            None => (e1, e2),
+        };
+        // from the corners, so the rounding in t cannot leave it off the plane
+        let point = w * self.a + u * self.b + v * self.c;
+        Some(HitRecord {
+            t,
+            u: tex_u,
+            v: tex_v,
+            p: point,
+            texture_p: point,
+            normal,
+            tangent: (e1 - e1.dot(&normal) * normal).normalize(),
+            dpdu,
+            dpdv,
+            material: &self.material,
+            object_id: 0,
+            class_id: 0,
+        })
+    }
+
+    fn bounding_box(&self, _t0: Float, _t1: Float) -> Option<AABB> {
+        let padding = Vector3::new(PADDING, PADDING, PADDING);
+        let min = self.a.inf(&self.b).inf(&self.c) - padding;
+        let max = self.a.sup(&self.b).sup(&self.c) + padding;
+        Some(AABB { min, max })
+    }
+
+    fn pdf_value(&self, o: Vector3<Float>, v: Vector3<Float>) -> Float {
+        if let Some(hit) = self.hit(&Ray::new(o, v, 0.0), ray::T_MIN, Float::MAX) {
+            let distance_squared = hit.t.powi(2) * v.norm_squared();
+            let cosine = v.dot(&hit.normal).abs() / v.norm();
+            if cosine != 0.0 {
+                distance_squared / (cosine * self.area())
+            } else {
+                0.0
+            }
+        } else {
+            0.0
+        }
+    }
+
+    fn random(&self, o: Vector3<Float>) -> Vector3<Float> {
+        self.sample(rng::uniform(), rng::uniform()) - o
+    }
+
+    fn sample_surface(&self) -> Option<(Vector3<Float>, Vector3<Float>, Float)> {
+        let p = self.sample(rng::uniform(), rng::uniform());
+        let normal = (self.b - self.a).cross(&(self.c - self.a)).normalize();
+        Some((p, normal, self.area()))
+    }
+}
+
+// Triangles sharing one material over indexed vertices, hit through a BVH of
+// their own that holds them. Light sampling picks a triangle in proportion to its
+// area, so points spread uniformly over the whole surface.
+pub struct TriangleMesh<M: Material> {
+    // running total of the triangles' areas in the BVH's order, ending with the
+    // mesh's
+    areas: Vec<Float>,
+    bvh: BVH<Triangle<M>>,
+}
+
+impl<M: Material + Clone + 'static> TriangleMesh<M> {
+    pub fn new(vertices: &[Vector3<Float>], indices: &[[usize; 3]], material: M) -> Self {
+        TriangleMesh::from_triangles(
+            indices
+                .iter()
+                .map(|&[a, b, c]| {
+                    Triangle::new(vertices[a], vertices[b], vertices[c], material.clone())
+                })
+                .collect(),
+        )
+    }
+}
+
+impl<M: Material + 'static> TriangleMesh<M> {
+    // a mesh of triangles built elsewhere, such as with normals and uvs of their own
+    pub fn from_triangles(triangles: Vec<Triangle<M>>) -> Self {
+        if triangles.is_empty() {
+            panic!["no triangles in mesh"]
+        }
+        let bvh = BVH::new(triangles, 0.0, 1.0);
+        let areas = bvh
+            .primitives()
+            .iter()
+            .scan(0.0, |total, triangle| {
+                *total += triangle.area();
+                Some(*total)
+            })
+            .collect();
+        TriangleMesh { areas, bvh }
+    }
+}
+
+impl<M: Material> TriangleMesh<M> {
+    pub fn area(&self) -> Float {
+        *self.areas.last().unwrap()
+    }
+
+    // a triangle picked in proportion to its area
+    fn pick(&self) -> &Triangle<M> {
+        let target = rng::uniform() * self.area();
+        let i = self
+            .areas
+            .partition_point(|&total| total <= target)
+            .min(self.areas.len() - 1);
+        &self.bvh.primitives()[i]
+    }
+}
+
+impl<M: Material> Hittable for TriangleMesh<M> {
+    fn hit(&self, ray: &Ray, t_min: Float, t_max: Float) -> Option<HitRecord<'_>> {
+        self.bvh.hit(ray, t_min, t_max)
+    }
+
+    fn bounding_box(&self, t0: Float, t1: Float) -> Option<AABB> {
+        self.bvh.bounding_box(t0, t1)
+    }
+
+    // every point along v the mesh could have been sampled at counts, not just the
+    // nearest, since random picks points behind others too
+    fn pdf_value(&self, o: Vector3<Float>, v: Vector3<Float>) -> Float {
+        let ray = Ray::new(o, v, 0.0);
+        let mut pdf = 0.0;
+        let mut surfaces = Surfaces::new(&ray);
+        while let Some(hit) = surfaces.next(&self.bvh, ray::T_MIN, Float::MAX) {
+            let distance_squared = hit.t.powi(2) * v.norm_squared();
+            let cosine = v.dot(&hit.normal).abs() / v.norm();
+            if cosine != 0.0 {
+                pdf += distance_squared / (cosine * self.area());
+            }
+        }
+        pdf
+    }
+
+    fn random(&self, o: Vector3<Float>) -> Vector3<Float> {
+        self.pick().random(o)
+    }
+
+    fn sample_surface(&self) -> Option<(Vector3<Float>, Vector3<Float>, Float)> {
+        let (p, normal, _) = self.pick().sample_surface()?;
+        Some((p, normal, self.area()))
+    }
+}
@@ -0,0 +1,49 @@
+use crate::volume::SparseGrid;
+
+// Loads the first grid of an OpenVDB file, such as the density of an exported smoke
+// simulation, with --features vdb. Values stored as half floats are widened. Only
+// leaf voxels are read: constant tiles above the leaves, which simulations use for
+// large uniform interiors, come out empty.
+#[cfg(feature = "vdb")]
+pub fn read(path: &str) -> Result<SparseGrid, String> {
+    use crate::float::Float;
+    use std::fs::File;
+    use std::io::BufReader;
+    use vdb_rs::{VdbLevel, VdbReader};
+
+    let error = |e: vdb_rs::ParseError| format!("cannot read {}: {}", path, e);
+    let file = File::open(path).map_err(|e| format!("cannot read {}: {}", path, e))?;
+    let mut reader = VdbReader::new(BufReader::new(file)).map_err(error)?;
+    let name = reader
+        .available_grids()
+        .into_iter()
+        .next()
+        .ok_or_else(|| format!("{}: no grids", path))?;
+    let index = |p: &vdb_rs::Vec3| [p.x as i32, p.y as i32, p.z as i32];
+    let grid = match reader.read_grid::<f32>(&name) {
+        Ok(grid) => SparseGrid::new(
+            grid.iter()
+                .filter(|(_, _, level)| *level == VdbLevel::Voxel)
+                .map(|(p, value, _)| (index(&p), value as Float)),
+        ),
+        Err(_) => {
+            let grid = reader.read_grid::<half::f16>(&name).map_err(error)?;
+            SparseGrid::new(
+                grid.iter()
+                    .filter(|(_, _, level)| *level == VdbLevel::Voxel)
+                    .map(|(p, value, _)| (index(&p), Float::from(value))),
+            )
+        }
+    };
+    if grid.bounds().is_none() {
+        return Err(format!("{}: grid {} is empty", path, name));
+    }
+    Ok(grid)
+}
+
+#[cfg(not(feature = "vdb"))]
+pub fn read(_path: &str) -> Result<SparseGrid, String> {
+    Err(String::from(
+        "volumes need rest_of_life built with --features vdb",
+    ))
+}
@@ -0,0 +1,337 @@
+use crate::aabb::AABB;
+use crate::float::Float;
+use crate::medium::DensityField;
+use nalgebra::Vector3;
+use std::collections::HashMap;
+
+#[derive(Clone)]
+pub struct DensityGrid {
+    nx: usize,
+    ny: usize,
+    nz: usize,
+    data: Vec<Float>,
+    bounds: AABB,
+}
+
+impl DensityGrid {
+    pub fn new(nx: usize, ny: usize, nz: usize, data: Vec<Float>, bounds: AABB) -> Self {
+        assert_eq!(data.len(), nx * ny * nz, "density grid size mismatch");
+        DensityGrid {
+            nx,
+            ny,
+            nz,
+            data,
+            bounds,
+        }
+    }
+
+    pub fn bounds(&self) -> AABB {
+        self.bounds
+    }
+
+    pub fn max_density(&self) -> Float {
+        self.data.iter().cloned().fold(0.0, Float::max)
+    }
+
+    // edge length of the largest voxel side in world units
+    pub fn voxel_size(&self) -> Float {
+        let extent = self.bounds.max - self.bounds.min;
+        Float::max(
+            extent.x / self.nx as Float,
+            Float::max(extent.y / self.ny as Float, extent.z / self.nz as Float),
+        )
+    }
+
+    fn voxel(&self, x: usize, y: usize, z: usize) -> Float {
+        self.data[(z * self.ny + y) * self.nx + x]
+    }
+
+    pub fn sample(&self, p: &Vector3<Float>) -> Float {
+        let extent = self.bounds.max - self.bounds.min;
+        let dims = [self.nx, self.ny, self.nz];
+        let mut index = [0; 3];
+        let mut frac = [0.0; 3];
+        for a in 0..3 {
+            let g = (p[a] - self.bounds.min[a]) / extent[a] * dims[a] as Float - 0.5;
+            if g < -0.5 || g > dims[a] as Float - 0.5 {
+                return 0.0;
+            }
+            let g = g.max(0.0).min((dims[a] - 1) as Float);
+            index[a] = (g.floor() as usize).min(dims[a].saturating_sub(2));
+            frac[a] = g - index[a] as Float;
+        }
+        let mut accum = 0.0;
+        for i in 0..2 {
+            for j in 0..2 {
+                for k in 0..2 {
+                    let x = (index[0] + i).min(self.nx - 1);
+                    let y = (index[1] + j).min(self.ny - 1);
+                    let z = (index[2] + k).min(self.nz - 1);
+                    let w = (i as Float * frac[0] + (1 - i) as Float * (1.0 - frac[0]))
+                        * (j as Float * frac[1] + (1 - j) as Float * (1.0 - frac[1]))
+                        * (k as Float * frac[2] + (1 - k) as Float * (1.0 - frac[2]));
+                    accum += w * self.voxel(x, y, z);
+                }
+            }
+        }
+        accum
+    }
+
+    // box filters 2x2x2 blocks into the next coarser level
+    fn downsample(&self) -> Self {
+        let nx = self.nx.div_ceil(2);
+        let ny = self.ny.div_ceil(2);
+        let nz = self.nz.div_ceil(2);
+        let mut data = Vec::with_capacity(nx * ny * nz);
+        for z in 0..nz {
+            for y in 0..ny {
+                for x in 0..nx {
+                    let mut sum = 0.0;
+                    let mut count = 0;
+                    for k in 2 * z..(2 * z + 2).min(self.nz) {
+                        for j in 2 * y..(2 * y + 2).min(self.ny) {
+                            for i in 2 * x..(2 * x + 2).min(self.nx) {
+                                sum += self.voxel(i, j, k);
+                                count += 1;
+                            }
+                        }
+                    }
+                    data.push(sum / count as Float);
+                }
+            }
+        }
+        DensityGrid::new(nx, ny, nz, data, self.bounds)
+    }
+}
+
+// voxel densities, trilinearly interpolated
+impl DensityField for DensityGrid {
+    fn density(&self, p: &Vector3<Float>) -> Float {
+        self.sample(p)
+    }
+
+    fn max_density(&self) -> Float {
+        DensityGrid::max_density(self)
+    }
+}
+
+pub struct MipDensityGrid {
+    levels: Vec<DensityGrid>,
+}
+
+impl MipDensityGrid {
+    pub fn new(grid: DensityGrid) -> Self {
+        let mut levels = vec![grid];
+        loop {
+            let last = levels.last().unwrap();
+            if last.nx == 1 && last.ny == 1 && last.nz == 1 {
+                break;
+            }
+            let next = last.downsample();
+            levels.push(next);
+        }
+        MipDensityGrid { levels }
+    }
+
+    pub fn num_levels(&self) -> usize {
+        self.levels.len()
+    }
+
+    pub fn bounds(&self) -> AABB {
+        self.levels[0].bounds
+    }
+
+    pub fn max_density(&self) -> Float {
+        self.levels[0].max_density()
+    }
+
+    // continuous mip level whose voxels match the cone footprint at the lookup point
+    pub fn level(&self, footprint: Float) -> Float {
+        let finest = self.levels[0].voxel_size();
+        if footprint <= finest {
+            0.0
+        } else {
+            (footprint / finest)
+                .log2()
+                .min((self.levels.len() - 1) as Float)
+        }
+    }
+
+    pub fn sample(&self, p: &Vector3<Float>, footprint: Float) -> Float {
+        let level = self.level(footprint);
+        let lo = level.floor() as usize;
+        let hi = (lo + 1).min(self.levels.len() - 1);
+        let t = level - lo as Float;
+        if t == 0.0 || lo == hi {
+            self.levels[lo].sample(p)
+        } else {
+            (1.0 - t) * self.levels[lo].sample(p) + t * self.levels[hi].sample(p)
+        }
+    }
+}
+
+// the finest level's densities, or those of the levels whose voxels match the
+// footprint they are looked up over
+impl DensityField for MipDensityGrid {
+    fn density(&self, p: &Vector3<Float>) -> Float {
+        self.levels[0].sample(p)
+    }
+
+    fn filtered_density(&self, p: &Vector3<Float>, footprint: Float) -> Float {
+        self.sample(p, footprint)
+    }
+
+    fn max_density(&self) -> Float {
+        MipDensityGrid::max_density(self)
+    }
+}
+
+// voxels along each side of a brick
+pub const BRICK: i32 = 8;
+
+struct Brick {
+    values: Box<[Float; 512]>,
+    max: Float,
+}
+
+// A sparse voxel grid, as a VDB file stores a simulation: only the 8x8x8 bricks
+// holding a nonzero voxel are kept, and voxels are addressed by integer index.
+// Samples are trilinear between voxel centres at integer coordinates, and every
+// brick cell [8b, 8b + 8) keeps the largest value its samples can take, so
+// tracking through empty space can skip it whole.
+pub struct SparseGrid {
+    bricks: HashMap<[i32; 3], Brick>,
+    majorants: HashMap<[i32; 3], Float>,
+    bounds: Option<AABB>,
+}
+
+fn brick_of(voxel: [i32; 3]) -> ([i32; 3], usize) {
+    let brick = voxel.map(|v| v.div_euclid(BRICK));
+    let [x, y, z] = voxel.map(|v| v.rem_euclid(BRICK) as usize);
+    (brick, (z * BRICK as usize + y) * BRICK as usize + x)
+}
+
+impl SparseGrid {
+    // the grid of the given voxel values, leaving out zeros
+    pub fn new(voxels: impl IntoIterator<Item = ([i32; 3], Float)>) -> Self {
+        let mut bricks: HashMap<[i32; 3], Brick> = HashMap::new();
+        for (voxel, value) in voxels {
+            if value <= 0.0 {
+                continue;
+            }
+            let (brick, index) = brick_of(voxel);
+            let brick = bricks.entry(brick).or_insert_with(|| Brick {
+                values: Box::new([0.0; 512]),
+                max: 0.0,
+            });
+            brick.values[index] = value;
+            brick.max = brick.max.max(value);
+        }
+        // a cell's samples reach into the bricks above it on each axis
+        let mut majorants: HashMap<[i32; 3], Float> = HashMap::new();
+        let mut min = [i32::MAX; 3];
+        let mut max = [i32::MIN; 3];
+        for (&[x, y, z], brick) in &bricks {
+            for cell in [
+                [x, y, z],
+                [x - 1, y, z],
+                [x, y - 1, z],
+                [x - 1, y - 1, z],
+                [x, y, z - 1],
+                [x - 1, y, z - 1],
+                [x, y - 1, z - 1],
+                [x - 1, y - 1, z - 1],
+            ] {
+                let majorant = majorants.entry(cell).or_insert(0.0);
+                *majorant = majorant.max(brick.max);
+                for a in 0..3 {
+                    min[a] = min[a].min(cell[a]);
+                    max[a] = max[a].max(cell[a]);
+                }
+            }
+        }
+        let bounds = if bricks.is_empty() {
+            None
+        } else {
+            let corner = |c: [i32; 3]| Vector3::new(c[0] as Float, c[1] as Float, c[2] as Float);
+            Some(AABB::new(
+                BRICK as Float * corner(min),
+                BRICK as Float * (corner(max) + Vector3::repeat(1.0)),
+            ))
+        };
+        SparseGrid {
+            bricks,
+            majorants,
+            bounds,
+        }
+    }
+
+    pub fn voxel(&self, voxel: [i32; 3]) -> Float {
+        let (brick, index) = brick_of(voxel);
+        self.bricks
+            .get(&brick)
+            .map_or(0.0, |brick| brick.values[index])
+    }
+
+    // trilinear sample at a point in index space
+    pub fn sample(&self, p: &Vector3<Float>) -> Float {
+        let base = p.map(Float::floor);
+        let f = p - base;
+        let [x, y, z] = [base.x as i32, base.y as i32, base.z as i32];
+        let mut value = 0.0;
+        for corner in 0..8 {
+            let (dx, dy, dz) = (corner & 1, (corner >> 1) & 1, corner >> 2);
+            let weight = (if dx == 1 { f.x } else { 1.0 - f.x })
+                * (if dy == 1 { f.y } else { 1.0 - f.y })
+                * (if dz == 1 { f.z } else { 1.0 - f.z });
+            if weight > 0.0 {
+                value += weight * self.voxel([x + dx, y + dy, z + dz]);
+            }
+        }
+        value
+    }
+
+    // the largest sample in the brick cell [8b, 8b + 8) on each axis
+    pub fn majorant(&self, cell: [i32; 3]) -> Float {
+        self.majorants.get(&cell).copied().unwrap_or(0.0)
+    }
+
+    // the index space box outside which every sample is zero, None when empty
+    pub fn bounds(&self) -> Option<AABB> {
+        self.bounds
+    }
+
+    pub fn max_density(&self) -> Float {
+        self.bricks
+            .values()
+            .map(|brick| brick.max)
+            .fold(0.0, Float::max)
+    }
+
+    pub fn num_bricks(&self) -> usize {
+        self.bricks.len()
+    }
+
+    // The next coarser level, each voxel the mean of a 2x2x2 block: voxel v of it
+    // averages voxels 2v to 2v + 1 of this one, so its centre sits at 2v + 0.5 in
+    // this one's index space.
+    pub fn downsample(&self) -> SparseGrid {
+        let mut sums: HashMap<[i32; 3], Float> = HashMap::new();
+        for (&brick, values) in &self.bricks {
+            for (index, &value) in values.values.iter().enumerate() {
+                if value <= 0.0 {
+                    continue;
+                }
+                let index = index as i32;
+                let offset = [
+                    index % BRICK,
+                    index / BRICK % BRICK,
+                    index / (BRICK * BRICK),
+                ];
+                let voxel = [0, 1, 2].map(|a| (brick[a] * BRICK + offset[a]).div_euclid(2));
+                *sums.entry(voxel).or_insert(0.0) += value;
+            }
+        }
+        SparseGrid::new(sums.into_iter().map(|(voxel, sum)| (voxel, sum / 8.0)))
+    }
+}
@@ -0,0 +1,299 @@
+use crate::alpha;
+use crate::bounce::{BounceKind, Bounces};
+use crate::differential;
+use crate::finite;
+use crate::float::Float;
+use crate::hittable::{HitRecord, Hittable};
+use crate::integrator::{self, Gathered, PathTracer, MAX_DEPTH};
+use crate::interrupt;
+use crate::material::ScatterRecord;
+use crate::parallel::*;
+use crate::ray::{self, Ray};
+use crate::render::{self, RenderSettings};
+use crate::rng;
+use crate::scene::Scene;
+use crate::stats::{self, Counter};
+use nalgebra::Vector3;
+use rand::RngCore;
+
+// the most paths a wave holds, short of one pixel taking more samples than this
+const WAVE_PATHS: usize = 1 << 16;
+
+// The path tracer as a wavefront. Rather than following one sample's path to its
+// end before starting the next, a wave of many pixels' samples advances a bounce
+// at a time in stages, each run over every path still going before the next
+// starts: all the rays are intersected with the world, then all the hits shaded,
+// each drawing the ray its path continues along, and the paths that ended leave
+// the wave. A stage runs one piece of code over a large buffer, which is what a
+// wide machine or a scene of many materials, each with its own shading code, wants;
+// on a small scene the bookkeeping between stages costs more than it saves, and
+// the recursive tracer is the quicker.
+//
+// It estimates what PathTracer does, with the same cutoff, bounce limits, clamp and
+// background; mnee, light links and guiding are left to the recursive tracer.
+// Every sample draws from a generator of its own, see rng::sample_generator, so
+// the image does not depend on how the stages are scheduled, though its noise
+// differs from the recursive tracer's.
+
+// A sample's path between stages, and the hit the intersection stage found for
+// the shading stage.
+struct Path<'s> {
+    rng: Box<dyn RngCore + Send>,
+    hit: Option<HitRecord<'s>>,
+    state: PathState,
+}
+
+struct PathState {
+    // where in the wave the sample's radiance goes, and the pixel and sample it is
+    slot: usize,
+    x: usize,
+    y: usize,
+    sample: usize,
+    wavelength: Option<Float>,
+    ray: Ray,
+    bounces: Bounces,
+    gathered: Gathered,
+    // false once the path has ended, and then the sample's radiance
+    going: bool,
+    radiance: Vector3<Float>,
+}
+
+impl PathState {
+    // the radiance the current vertex sends back itself
+    fn emit(&mut self, radiance: Vector3<Float>) {
+        self.gathered.emit(self.bounces, radiance);
+    }
+
+    // the sample's radiance once its path has ended
+    fn finish(&self, tracer: &PathTracer) -> Vector3<Float> {
+        render::weigh(self.gathered.finish(tracer), self.wavelength)
+    }
+}
+
+// One vertex of the path as color handles it: the emission found there, then the
+// ray the path goes on along, drawn into the path, or false where it ends.
+fn shade(path: &mut PathState, hit: Option<HitRecord>, scene: &Scene, tracer: &PathTracer) -> bool {
+    let Some(hit) = hit else {
+        let background = tracer.background.map_or(Vector3::zeros(), |background| {
+            background.radiance(&path.ray.direction())
+        });
+        path.gathered.at(path.bounces, "the background");
+        path.emit(background);
+        return false;
+    };
+    let hit = hit.material.exit(&path.ray, hit);
+    path.gathered.at(path.bounces, hit.material.name());
+    let emitted = hit.material.emitted(&path.ray, &hit);
+    let weight = match tracer.cutoff.continuation(&path.gathered.throughput()) {
+        Some(weight) if path.bounces.depth < MAX_DEPTH => weight,
+        _ => {
+            path.emit(emitted);
+            return false;
+        }
+    };
+    match hit.material.scatter(&path.ray, &hit) {
+        None => {
+            path.emit(emitted);
+            false
+        }
+        // what a mirror or glass emits itself goes uncounted once the path goes on,
+        // as in color
+        Some(ScatterRecord::Specular {
+            specular_ray,
+            attenuation,
+        }) => {
+            let specular_ray = differential::specular(&path.ray, &hit, specular_ray);
+            let kind = BounceKind::specular(&path.ray, &hit, &specular_ray);
+            let Some(bounces) = path.bounces.after(kind, &tracer.bounces) else {
+                path.emit(emitted);
+                return false;
+            };
+            path.gathered.go_on(weight * attenuation);
+            path.bounces = bounces;
+            path.ray = specular_ray;
+            true
+        }
+        Some(ScatterRecord::Scatter { pdf, attenuation }) => {
+            path.emit(emitted);
+            let Some(bounces) = path.bounces.after(BounceKind::Diffuse, &tracer.bounces) else {
+                return false;
+            };
+            let light_shape = scene.light_shape.as_deref();
+            let (scattered, pdf_val) =
+                integrator::scatter_direction(&path.ray, &hit, pdf, light_shape, tracer);
+            if !(pdf_val > 0.0 && pdf_val.is_finite()) {
+                return false;
+            }
+            let scattering_pdf = hit.material.scattering_pdf(&path.ray, &hit, &scattered);
+            path.gathered
+                .go_on(weight * attenuation * scattering_pdf / pdf_val);
+            path.bounces = bounces;
+            path.ray = scattered;
+            true
+        }
+    }
+}
+
+// The camera path of a sample, the `slot`th of the pixels' in order, each pixel's
+// samples following one another; None outside a fisheye's image circle, which
+// stays black.
+fn camera_path<'s>(
+    scene: &'s Scene,
+    settings: &RenderSettings,
+    pixels: &[(usize, usize)],
+    slot: usize,
+    pass: usize,
+) -> Option<Path<'s>> {
+    // the camera's v, and the sample's seed, count rows from the bottom
+    let (x, row) = pixels[slot / settings.spp];
+    let (y, sample) = (settings.height - 1 - row, slot % settings.spp);
+    let mut rng = rng::sample_generator(settings.rng, settings.seed, pass, x, y, sample);
+    let ray = rng::using(&mut rng, || {
+        alpha::set_pixel(x, y, sample);
+        render::camera_ray(scene, settings, x, y)
+    })?;
+    let state = PathState {
+        slot,
+        x,
+        y,
+        sample,
+        wavelength: ray.wavelength(),
+        ray,
+        bounces: Bounces::default(),
+        gathered: Gathered::default(),
+        going: true,
+        radiance: Vector3::zeros(),
+    };
+    Some(Path {
+        rng,
+        hit: None,
+        state,
+    })
+}
+
+// The samples of the pixels as their paths end, in no particular order, and the
+// estimates of the pixels whose samples are all in.
+struct Film<'a> {
+    settings: &'a RenderSettings,
+    samples: Vec<Vec<Vector3<Float>>>,
+    taken: Vec<usize>,
+    estimates: Vec<(Vector3<Float>, usize)>,
+}
+
+impl<'a> Film<'a> {
+    fn new(settings: &'a RenderSettings, pixels: usize) -> Self {
+        Film {
+            settings,
+            samples: vec![Vec::new(); pixels],
+            taken: vec![0; pixels],
+            estimates: vec![(Vector3::zeros(), 0); pixels],
+        }
+    }
+
+    // keeps a pixel's samples in their order until the last is in, so the
+    // estimator groups them as it would the recursive tracer's
+    fn record(&mut self, slot: usize, radiance: Vector3<Float>) {
+        let spp = self.settings.spp;
+        let (pixel, sample) = (slot / spp, slot % spp);
+        if self.samples[pixel].is_empty() {
+            self.samples[pixel] = vec![Vector3::zeros(); spp];
+        }
+        self.samples[pixel][sample] = radiance;
+        self.taken[pixel] += 1;
+        if self.taken[pixel] == spp {
+            self.develop(pixel);
+        }
+    }
+
+    fn develop(&mut self, pixel: usize) {
+        let mut samples = std::mem::take(&mut self.samples[pixel]);
+        let taken = self.taken[pixel];
+        samples.truncate(taken);
+        let estimate =
+            render::estimate(self.settings, self.settings.estimator, samples.into_iter());
+        self.estimates[pixel] = (estimate, taken);
+    }
+
+    // the estimates, of pixels an interrupt left short of samples too; they were
+    // started in order, so the samples in are the first ones
+    fn finish(mut self) -> Vec<(Vector3<Float>, usize)> {
+        for pixel in 0..self.samples.len() {
+            if !self.samples[pixel].is_empty() {
+                self.develop(pixel);
+            }
+        }
+        self.estimates
+    }
+}
+
+// The estimate of each of the given pixels, columns and rows from the top left,
+// and the samples it took, as the recursive tracer's render pass gives them, with
+// `pass` choosing the samples' generators. The wave is topped up with new camera
+// paths as its paths end, so it stays full until the last samples; an interrupt
+// stops it starting new ones, the pixels left short estimated from the samples
+// they took.
+pub fn render(
+    scene: &Scene,
+    settings: &RenderSettings,
+    tracer: &PathTracer,
+    pixels: &[(usize, usize)],
+    pass: usize,
+) -> Vec<(Vector3<Float>, usize)> {
+    let total = pixels.len() * settings.spp;
+    let mut film = Film::new(settings, pixels.len());
+    let mut started = 0;
+    let mut paths: Vec<Path> = Vec::with_capacity(WAVE_PATHS);
+    loop {
+        if !interrupt::interrupted() {
+            let fresh = (WAVE_PATHS - paths.len()).min(total - started);
+            let camera_paths: Vec<(usize, Option<Path>)> = (started..started + fresh)
+                .into_par_iter()
+                .map(|slot| (slot, camera_path(scene, settings, pixels, slot, pass)))
+                .collect();
+            started += fresh;
+            for (slot, path) in camera_paths {
+                match path {
+                    Some(path) => paths.push(path),
+                    None => film.record(slot, Vector3::zeros()),
+                }
+            }
+        }
+        if paths.is_empty() {
+            break;
+        }
+        paths.par_iter_mut().for_each(|path| {
+            let state = &path.state;
+            if state.bounces.depth > 0 {
+                stats::count(Counter::SecondaryRays);
+            }
+            path.hit = rng::using(&mut path.rng, || {
+                alpha::set_pixel(state.x, state.y, state.sample);
+                scene.world.hit(&state.ray, ray::T_MIN, Float::MAX)
+            });
+        });
+        paths.par_iter_mut().for_each(|path| {
+            let (state, hit) = (&mut path.state, path.hit.take());
+            state.going = rng::using(&mut path.rng, || {
+                alpha::set_pixel(state.x, state.y, state.sample);
+                finite::set_pixel(state.x, settings.height - 1 - state.y, state.sample);
+                // an earlier stage, maybe on another thread, may have logged it
+                if state.gathered.reported() {
+                    finite::set_logged();
+                }
+                shade(state, hit, scene, tracer)
+            });
+            // any not finite counted black, and logged, see finite.rs
+            if !state.going {
+                state.radiance = finite::guard(state.finish(tracer));
+            }
+        });
+        paths.retain(|path| {
+            // the paths log themselves as they are shaded
+            if !path.state.going {
+                film.record(path.state.slot, path.state.radiance);
+            }
+            path.state.going
+        });
+    }
+    film.finish()
+}
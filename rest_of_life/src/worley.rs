@@ -0,0 +1,108 @@
+use crate::float::Float;
+use crate::perlin::Octaves;
+use crate::rng;
+use nalgebra::Vector3;
+use rand::Rng;
+
+// Worley's cellular noise: space is cut into unit cells each holding one feature
+// point at a random place in it, and a point's noise is its distance to the
+// nearest feature point, f1, and to the second nearest, f2. F1 alone gives round
+// cells, pebbles or scales; f2 - f1 is zero along the borders between cells, the
+// cracks between flagstones or the ripples of caustics on water. The cells repeat
+// every 256 units along each axis.
+#[derive(Clone)]
+pub struct Worley {
+    points: Vec<Vector3<Float>>,
+    perm_x: Vec<usize>,
+    perm_y: Vec<usize>,
+    perm_z: Vec<usize>,
+}
+
+fn permutation() -> Vec<usize> {
+    let mut p: Vec<usize> = (0..256).collect();
+    for i in (0..256).rev() {
+        let target = rng::with(|rng| rng.gen_range(0..=i));
+        p.swap(i, target);
+    }
+    p
+}
+
+impl Default for Worley {
+    fn default() -> Self {
+        Worley::new()
+    }
+}
+
+impl Worley {
+    pub fn new() -> Self {
+        Worley {
+            points: (0..256)
+                .map(|_| Vector3::new(rng::uniform(), rng::uniform(), rng::uniform()))
+                .collect(),
+            perm_x: permutation(),
+            perm_y: permutation(),
+            perm_z: permutation(),
+        }
+    }
+
+    // the feature point of the cell whose lowest corner is (i, j, k)
+    fn feature(&self, i: i32, j: i32, k: i32) -> Vector3<Float> {
+        let offset = self.points[self.perm_x[(i & 255) as usize]
+            ^ self.perm_y[(j & 255) as usize]
+            ^ self.perm_z[(k & 255) as usize]];
+        Vector3::new(i as Float, j as Float, k as Float) + offset
+    }
+
+    // the distances from p to its nearest and second nearest feature points, found
+    // among the cell p is in and the 26 around it
+    pub fn distances(&self, p: &Vector3<Float>) -> (Float, Float) {
+        let (i, j, k) = (p.x.floor() as i32, p.y.floor() as i32, p.z.floor() as i32);
+        let (mut f1, mut f2) = (Float::INFINITY, Float::INFINITY);
+        for di in -1..=1 {
+            for dj in -1..=1 {
+                for dk in -1..=1 {
+                    let d = (self.feature(i + di, j + dj, k + dk) - p).norm_squared();
+                    if d < f1 {
+                        f2 = f1;
+                        f1 = d;
+                    } else if d < f2 {
+                        f2 = d;
+                    }
+                }
+            }
+        }
+        (f1.sqrt(), f2.sqrt())
+    }
+
+    // octaves of f1, or of f2 - f1 for `edges`, summed as Octaves::sum does
+    pub fn fractal(&self, p: &Vector3<Float>, octaves: &Octaves, edges: bool) -> Float {
+        octaves.sum(p, |p| {
+            let (f1, f2) = self.distances(p);
+            if edges {
+                f2 - f1
+            } else {
+                f1
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn distances_reach_the_feature_points() {
+        let worley = Worley::new();
+        let feature = worley.feature(3, -2, 7);
+        let (f1, f2) = worley.distances(&feature);
+        assert!(f1 < 1e-6);
+        assert!(f2 > 0.0);
+        for x in 0..20 {
+            let p = Vector3::new(0.37 * x as Float, 0.11 * x as Float, -0.23 * x as Float);
+            let (f1, f2) = worley.distances(&p);
+            // every cell has a point, so the nearest lies within its corners' reach
+            assert!(f1 <= f2 && f1 < Float::sqrt(3.0));
+        }
+    }
+}
@@ -0,0 +1,326 @@
+use nalgebra::Vector3;
+use rest_of_life::background::SolidColor;
+use rest_of_life::camera::{Camera, Optics};
+use rest_of_life::float::{self, Float};
+use rest_of_life::hittable::{FlipNormals, HitRecord, Hittable, HittableList};
+use rest_of_life::integrator::MAX_DEPTH;
+use rest_of_life::material::{DiffuseLight, Lambertian, Material, ScatterRecord};
+use rest_of_life::pdf::PDF;
+use rest_of_life::portal;
+use rest_of_life::ray::Ray;
+use rest_of_life::rect::{AARect, Plane};
+use rest_of_life::rotate::{Axis, Rotate};
+use rest_of_life::sphere::Sphere;
+use rest_of_life::sun::{Sun, SunAndSky, SUN_DIAMETER};
+use rest_of_life::texture::ConstantTexture;
+use rest_of_life::translate::Translate;
+use rest_of_life::{render, Image, RenderSettings, Scene};
+use std::sync::Arc;
+
+// A diffuse surface that also emits, the walls of a furnace.
+struct EmissiveLambertian {
+    albedo: Float,
+    emit: Float,
+}
+
+impl Material for EmissiveLambertian {
+    fn scatter(&self, _ray: &Ray, hit: &HitRecord) -> Option<ScatterRecord<'_>> {
+        Some(ScatterRecord::Scatter {
+            pdf: PDF::cosine(hit.normal),
+            attenuation: Vector3::new(self.albedo, self.albedo, self.albedo),
+        })
+    }
+
+    fn scattering_pdf(&self, _ray: &Ray, hit: &HitRecord, scattered: &Ray) -> Float {
+        let cosine = hit.normal.dot(&scattered.direction().normalize()).max(0.0);
+        cosine / float::consts::PI
+    }
+
+    fn emitted(&self, _ray: &Ray, _hit: &HitRecord) -> Vector3<Float> {
+        Vector3::new(self.emit, self.emit, self.emit)
+    }
+}
+
+fn camera(look_from: Vector3<Float>, look_at: Vector3<Float>, vertical_fov: Float) -> Camera {
+    let view = look_at - look_from;
+    let view_up = if view.x.abs() < 0.9 * view.norm() {
+        Vector3::new(1.0, 0.0, 0.0)
+    } else {
+        Vector3::new(0.0, 1.0, 0.0)
+    };
+    let optics = Optics {
+        vertical_fov,
+        focus_dist: 1.0,
+        ..Optics::default()
+    };
+    Camera::new(look_from, look_at, view_up, 1.0, optics, 0.0, 1.0)
+}
+
+fn settings(spp: usize) -> RenderSettings {
+    RenderSettings {
+        width: 8,
+        height: 8,
+        spp,
+        ..RenderSettings::default()
+    }
+}
+
+// mean over all pixels and channels
+fn mean(image: &Image) -> Float {
+    image.pixels.iter().map(|c| c.x + c.y + c.z).sum::<Float>() / (3 * image.pixels.len()) as Float
+}
+
+fn assert_close(value: Float, expected: Float, tolerance: Float) {
+    assert!(
+        (value - expected).abs() <= tolerance * expected,
+        "rendered {} expected {}",
+        value,
+        expected
+    );
+}
+
+// Form factor from a point to a parallel rectangle of sides a and b at distance c
+// whose corner lies straight above the point.
+fn corner_form_factor(a: Float, b: Float, c: Float) -> Float {
+    let (x, y) = (a / c, b / c);
+    let (sx, sy) = ((1.0 + x * x).sqrt(), (1.0 + y * y).sqrt());
+    (x / sx * (y / sx).atan() + y / sy * (x / sy).atan()) / (2.0 * float::consts::PI)
+}
+
+// A diffuse sphere inside a sphere emitting uniformly inward sees the emission from
+// every direction, so it reflects exactly albedo times the emitted radiance.
+#[test]
+fn diffuse_sphere_inside_uniform_emitter() {
+    let (albedo, emit) = (0.6, 1.5);
+    let mut world = HittableList::default();
+    world.push(Sphere::new(
+        Vector3::zeros(),
+        1.0,
+        Lambertian::new(ConstantTexture::new(albedo, albedo, albedo)),
+    ));
+    world.push(FlipNormals::new(Sphere::new(
+        Vector3::zeros(),
+        10.0,
+        DiffuseLight::new(ConstantTexture::new(emit, emit, emit)),
+    )));
+    let cam = camera(Vector3::new(0.0, 0.0, 5.0), Vector3::zeros(), 5.0);
+    let scene = Scene::new(Arc::new(world), cam);
+    assert_close(mean(&render(&scene, &settings(16))), albedo * emit, 0.01);
+}
+
+// Inside a closed furnace whose walls emit E and reflect with albedo a every bounce
+// adds E scaled by one more power of a, converging to E / (1 - a), as it does when
+// the paths advance as a wavefront.
+#[test]
+fn furnace_converges_to_geometric_series() {
+    let (albedo, emit) = (0.5, 0.25);
+    let mut world = HittableList::default();
+    world.push(FlipNormals::new(Sphere::new(
+        Vector3::zeros(),
+        1.0,
+        EmissiveLambertian { albedo, emit },
+    )));
+    let cam = camera(Vector3::zeros(), Vector3::new(0.0, 0.0, -1.0), 60.0);
+    let scene = Scene::new(Arc::new(world), cam);
+    assert_close(
+        mean(&render(&scene, &settings(4))),
+        emit / (1.0 - albedo),
+        0.01,
+    );
+    let wavefront = RenderSettings {
+        wavefront: true,
+        ..settings(4)
+    };
+    assert_close(
+        mean(&render(&scene, &wavefront)),
+        emit / (1.0 - albedo),
+        0.01,
+    );
+}
+
+// In a furnace whose walls lose nothing every path runs to the depth limit, each of
+// its MAX_DEPTH + 1 vertices adding the walls' emission.
+#[test]
+fn lossless_furnace_runs_paths_to_the_depth_limit() {
+    let emit = 0.01;
+    let mut world = HittableList::default();
+    world.push(FlipNormals::new(Sphere::new(
+        Vector3::zeros(),
+        1.0,
+        EmissiveLambertian { albedo: 1.0, emit },
+    )));
+    let cam = camera(Vector3::zeros(), Vector3::new(0.0, 0.0, -1.0), 60.0);
+    let scene = Scene::new(Arc::new(world), cam);
+    let vertices = (MAX_DEPTH + 1) as Float;
+    assert_close(mean(&render(&scene, &settings(1))), emit * vertices, 0.01);
+}
+
+// A diffuse floor under a square emitter sees the emitter over a form factor known
+// in closed form, and reflects albedo times emission times that form factor. The
+// emitter is placed by transforms, which light sampling has to see through.
+#[test]
+fn parallel_plates_match_form_factor() {
+    let (albedo, emit, half, distance) = (0.5, 2.0, 1.0, 1.0);
+    let emitter = FlipNormals::new(AARect::new(
+        Plane::ZX,
+        -half,
+        half,
+        -half,
+        half,
+        0.0,
+        DiffuseLight::new(ConstantTexture::new(emit, emit, emit)),
+    ));
+    let light: Arc<dyn Hittable> = Arc::new(Translate::new(
+        Rotate::new(Axis::Y, emitter, 30.0),
+        Vector3::new(0.0, distance, 0.0),
+    ));
+    let mut world = HittableList::default();
+    world.push(AARect::new(
+        Plane::ZX,
+        -100.0,
+        100.0,
+        -100.0,
+        100.0,
+        0.0,
+        Lambertian::new(ConstantTexture::new(albedo, albedo, albedo)),
+    ));
+    world.push_shared(light.clone());
+    let cam = camera(
+        Vector3::new(0.0, 0.5 * distance, 0.0),
+        Vector3::zeros(),
+        0.5,
+    );
+    let scene = Scene {
+        light_shape: Some(light),
+        ..Scene::new(Arc::new(world), cam)
+    };
+    let form_factor = 4.0 * corner_form_factor(half, half, distance);
+    assert_close(
+        mean(&render(&scene, &settings(1024))),
+        albedo * emit * form_factor,
+        0.03,
+    );
+}
+
+// A diffuse floor straight under a spherical emitter of radius r at height h is lit
+// by a cone of half angle asin(r / h), a form factor of (r / h)^2.
+#[test]
+fn sphere_light_over_plane_matches_solid_angle() {
+    let (albedo, emit, radius, height) = (0.8, 4.0, 0.5, 2.0);
+    let light: Arc<dyn Hittable> = Arc::new(Sphere::new(
+        Vector3::new(0.0, height, 0.0),
+        radius,
+        DiffuseLight::new(ConstantTexture::new(emit, emit, emit)),
+    ));
+    let mut world = HittableList::default();
+    world.push(AARect::new(
+        Plane::ZX,
+        -100.0,
+        100.0,
+        -100.0,
+        100.0,
+        0.0,
+        Lambertian::new(ConstantTexture::new(albedo, albedo, albedo)),
+    ));
+    world.push_shared(light.clone());
+    let cam = camera(Vector3::new(0.0, 1.0, 0.0), Vector3::zeros(), 0.5);
+    let scene = Scene {
+        light_shape: Some(light),
+        ..Scene::new(Arc::new(world), cam)
+    };
+    assert_close(
+        mean(&render(&scene, &settings(1024))),
+        albedo * emit * (radius / height).powi(2),
+        0.03,
+    );
+}
+
+// A diffuse floor under a black ceiling with a square opening sees a white
+// background through it over the same form factor as the square emitter above;
+// a portal over the opening samples it without changing what it converges to.
+#[test]
+fn portal_lights_floor_through_opening() {
+    let (albedo, half, distance) = (0.5, 0.5, 1.0);
+    let black = Arc::new(Lambertian::new(ConstantTexture::new(0.0, 0.0, 0.0)));
+    let mut world = HittableList::default();
+    world.push(AARect::new(
+        Plane::ZX,
+        -100.0,
+        100.0,
+        -100.0,
+        100.0,
+        0.0,
+        Lambertian::new(ConstantTexture::new(albedo, albedo, albedo)),
+    ));
+    // the ceiling in four pieces around the opening, in z then x
+    for (z0, z1, x0, x1) in [
+        (-100.0, 100.0, -100.0, -half),
+        (-100.0, 100.0, half, 100.0),
+        (-100.0, -half, -half, half),
+        (half, 100.0, -half, half),
+    ] {
+        world.push(AARect::new(
+            Plane::ZX,
+            z0,
+            z1,
+            x0,
+            x1,
+            distance,
+            black.clone(),
+        ));
+    }
+    let opening = portal::portal(Plane::ZX, -half, half, -half, half, distance);
+    let cam = camera(
+        Vector3::new(0.0, 0.5 * distance, 0.0),
+        Vector3::zeros(),
+        0.5,
+    );
+    let scene = Scene {
+        light_shape: Some(Arc::new(opening)),
+        background: Arc::new(SolidColor(Vector3::new(1.0, 1.0, 1.0))),
+        ..Scene::new(Arc::new(world), cam)
+    };
+    let form_factor = 4.0 * corner_form_factor(half, half, distance);
+    assert_close(
+        mean(&render(&scene, &settings(256))),
+        albedo * form_factor,
+        0.03,
+    );
+}
+
+// A diffuse floor under a sun high enough to light it at angle theta from its
+// normal receives irradiance E cos(theta) and reflects albedo E cos(theta) / pi.
+#[test]
+fn sun_lights_floor_by_cosine() {
+    let (albedo, irradiance, elevation) = (0.5, 3.0, 40.0 as Float);
+    let mut world = HittableList::default();
+    world.push(AARect::new(
+        Plane::ZX,
+        -100.0,
+        100.0,
+        -100.0,
+        100.0,
+        0.0,
+        Lambertian::new(ConstantTexture::new(albedo, albedo, albedo)),
+    ));
+    let (sin, cos) = elevation.to_radians().sin_cos();
+    let sun = Sun::new(
+        Vector3::new(cos, sin, 0.0),
+        Vector3::new(irradiance, irradiance, irradiance),
+        SUN_DIAMETER,
+    );
+    let cam = camera(Vector3::new(0.0, 1.0, 0.0), Vector3::zeros(), 0.5);
+    let scene = Scene {
+        light_shape: Some(Arc::new(sun)),
+        background: Arc::new(SunAndSky {
+            sun,
+            sky: Arc::new(SolidColor::default()),
+        }),
+        ..Scene::new(Arc::new(world), cam)
+    };
+    assert_close(
+        mean(&render(&scene, &settings(256))),
+        albedo * irradiance * sin / float::consts::PI,
+        0.03,
+    );
+}
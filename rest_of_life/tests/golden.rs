@@ -0,0 +1,70 @@
+use rest_of_life::cli::Options;
+use rest_of_life::float::Float;
+use rest_of_life::{reference, render, scene, Image, RenderSettings};
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+
+const WIDTH: usize = 40;
+const HEIGHT: usize = 30;
+const SPP: usize = 64;
+
+// Largest differences from a golden image that pass. A render with the golden's
+// seed draws the same samples, so it should match up to floating point noise;
+// anything more is a change to the renders, which should come with new goldens.
+const MAX_RMSE: Float = 1e-3;
+const MAX_FLIP: Float = 1e-3;
+const MAX_MEAN_SHIFT: Float = 1e-3;
+
+fn golden_path(name: &str) -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/golden")
+        .join(format!("{}.pfm", name))
+}
+
+fn render_scene(name: &str, seed: u64) -> Image {
+    let options = Options {
+        seed,
+        ..Options::default()
+    };
+    let settings = RenderSettings {
+        width: WIDTH,
+        height: HEIGHT,
+        spp: SPP,
+        seed,
+        ..RenderSettings::default()
+    };
+    let scene = scene::by_name(name, WIDTH as Float / HEIGHT as Float, &options).unwrap();
+    render(&scene, &settings)
+}
+
+// Every built-in scene against its golden image. After a change that is meant to
+// alter the renders, run with UPDATE_GOLDEN=1 to write the new ones. They are f32
+// renders, which f64 builds don't reproduce sample for sample.
+#[test]
+#[cfg_attr(feature = "f64", ignore)]
+fn scenes_match_golden_images() {
+    let update = env::var_os("UPDATE_GOLDEN").is_some();
+    let mut failures = Vec::new();
+    for name in scene::SCENES {
+        let image = render_scene(name, 0);
+        let path = golden_path(name);
+        if update {
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(&path, image.pfm()).unwrap();
+            continue;
+        }
+        let golden = reference::load(path.to_str().unwrap()).unwrap();
+        let (metrics, _) = reference::compare(&image, &golden).unwrap();
+        if metrics.rmse > MAX_RMSE
+            || metrics.flip > MAX_FLIP
+            || (metrics.mean_ratio - 1.0).abs() > MAX_MEAN_SHIFT
+        {
+            failures.push(format!(
+                "{}: rmse {} flip {} mean ratio {}",
+                name, metrics.rmse, metrics.flip, metrics.mean_ratio
+            ));
+        }
+    }
+    assert!(failures.is_empty(), "{}", failures.join("\n"));
+}
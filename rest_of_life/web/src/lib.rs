@@ -0,0 +1,63 @@
+use nalgebra::Vector3;
+use rest_of_life::cli::Options;
+use rest_of_life::float::Float;
+use rest_of_life::render::{self, RenderSettings};
+use rest_of_life::scene::{self, Scene};
+use rest_of_life::tonemap::ToneMapping;
+use wasm_bindgen::prelude::*;
+
+// The Cornell box refined in the browser. Every step traces one more sample per
+// pixel on the page's thread and the canvas shows the running average. Build with
+//   wasm-pack build --target web
+// in this directory and serve it; index.html drives the loop.
+#[wasm_bindgen]
+pub struct Renderer {
+    scene: Scene,
+    settings: RenderSettings,
+    tone: ToneMapping,
+    sums: Vec<Vector3<Float>>,
+    passes: usize,
+}
+
+#[wasm_bindgen]
+impl Renderer {
+    #[wasm_bindgen(constructor)]
+    pub fn new(width: usize, height: usize) -> Renderer {
+        let options = Options::default();
+        let aspect = width as Float / height as Float;
+        Renderer {
+            scene: scene::by_name("cornell_box", aspect, &options).expect("unknown scene"),
+            settings: RenderSettings {
+                width,
+                height,
+                ..options.settings()
+            },
+            tone: options.tone,
+            sums: vec![Vector3::zeros(); width * height],
+            passes: 0,
+        }
+    }
+
+    // traces one sample per pixel and returns the samples per pixel so far
+    pub fn step(&mut self) -> usize {
+        let pass = render::sample_pass(&self.scene, &self.settings, self.passes);
+        for (sum, c) in self.sums.iter_mut().zip(pass) {
+            *sum += c;
+        }
+        self.passes += 1;
+        self.passes
+    }
+
+    // the average so far as RGBA bytes with rows from the top, as ImageData takes them
+    pub fn pixels(&self) -> Vec<u8> {
+        let passes = self.passes.max(1) as Float;
+        self.sums
+            .iter()
+            .flat_map(|s| {
+                let c = self.tone.display(&(s / passes));
+                let [r, g, b] = [c.x, c.y, c.z].map(|v| (255.99 * v) as u8);
+                [r, g, b, 255]
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,43 @@
+use crate::vec3::{unit_vector, Color, Vec3};
+
+// What a ray that misses everything sees, by its direction, which need not be unit
+// length. The chapters hard-coded the book's gradient; a binary holding one of these
+// can swap it per scene. rest_of_life has the same trait with environment maps and
+// a procedural sky as well.
+pub trait Background: Send + Sync {
+    fn color(&self, direction: Vec3) -> Color;
+}
+
+// one color whichever way the ray goes
+#[derive(Clone, Copy, Debug)]
+pub struct SolidColor(pub Color);
+
+impl Background for SolidColor {
+    fn color(&self, _direction: Vec3) -> Color {
+        self.0
+    }
+}
+
+// The book's sky: `horizon` straight down blending to `zenith` straight up, white
+// to light blue by default.
+#[derive(Clone, Copy, Debug)]
+pub struct Gradient {
+    pub horizon: Color,
+    pub zenith: Color,
+}
+
+impl Default for Gradient {
+    fn default() -> Self {
+        Gradient {
+            horizon: Color::new(1.0, 1.0, 1.0),
+            zenith: Color::new(0.5, 0.7, 1.0),
+        }
+    }
+}
+
+impl Background for Gradient {
+    fn color(&self, direction: Vec3) -> Color {
+        let t = 0.5 * (unit_vector(direction).y() + 1.0);
+        (1.0 - t) * self.horizon + t * self.zenith
+    }
+}
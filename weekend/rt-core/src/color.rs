@@ -0,0 +1,57 @@
+use crate::util::clamp;
+use crate::vec3::{Color, Real};
+
+// How linear color is encoded for display. sRGB is the standard transfer curve;
+// Gamma2 is the book's sqrt approximation, kept to compare against its images.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum Encoding {
+    #[default]
+    SRGB,
+    Gamma2,
+}
+
+impl Encoding {
+    // Gamma2 when args hold --legacy-gamma
+    pub fn parse(args: &[String]) -> Self {
+        if args.iter().any(|arg| arg == "--legacy-gamma") {
+            Encoding::Gamma2
+        } else {
+            Encoding::SRGB
+        }
+    }
+
+    // Encoding::parse on the program's arguments
+    pub fn from_args() -> Self {
+        Encoding::parse(&std::env::args().collect::<Vec<String>>())
+    }
+
+    pub fn encode(self, linear: Real) -> Real {
+        let linear = clamp(linear, 0.0, 1.0);
+        match self {
+            Encoding::SRGB => linear_to_srgb(linear),
+            Encoding::Gamma2 => linear.sqrt(),
+        }
+    }
+}
+
+pub fn linear_to_srgb(linear: Real) -> Real {
+    if linear <= 0.0031308 {
+        12.92 * linear
+    } else {
+        1.055 * linear.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+pub fn write_color(pixel_color: Color, samples_per_pixel: i32, encoding: Encoding) -> String {
+    let scale = 1.0 / samples_per_pixel as Real;
+    let r = encoding.encode(scale * pixel_color.x());
+    let g = encoding.encode(scale * pixel_color.y());
+    let b = encoding.encode(scale * pixel_color.z());
+    const A: Real = 256.0;
+    format!(
+        "{} {} {}",
+        (A * clamp(r, 0.0, 0.999)) as u8,
+        (A * clamp(g, 0.0, 0.999)) as u8,
+        (A * clamp(b, 0.0, 0.999)) as u8,
+    )
+}
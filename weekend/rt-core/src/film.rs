@@ -0,0 +1,114 @@
+use crate::color::Encoding;
+use crate::vec3::{Color, Real};
+
+// Curve that maps linear HDR values into [0, 1] before encoding, as in rest_of_life.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum ToneMap {
+    #[default]
+    Clamp,
+    Reinhard,
+    ACES,
+}
+
+// Narkowicz's fit of the ACES filmic curve
+fn aces(c: Real) -> Real {
+    (c * (2.51 * c + 0.03)) / (c * (2.43 * c + 0.59) + 0.14)
+}
+
+// The production output stack for the chapter binaries: linear pixels are scaled by
+// 2^exposure, tone mapped (a white point is the scaled value shown as full white),
+// encoded and written as a plain or binary PPM. A binary opts in by checking
+// Film::from_args before falling back to the book's write_color, so the same scene
+// can be compared both ways.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Film {
+    pub tone: ToneMap,
+    pub exposure: Real,
+    pub white: Option<Real>,
+    pub encoding: Encoding,
+    pub binary: bool,
+}
+
+// Printed with the error when the film options don't parse
+pub const USAGE: &str = "usage: --film [--tonemap <clamp|reinhard|aces>] [--exposure <stops>] \
+                         [--white-point <value>] [--p6] [--legacy-gamma]";
+
+fn arg_value<T: std::str::FromStr>(args: &[String], name: &str) -> Result<Option<T>, String> {
+    match args.iter().position(|arg| arg == name) {
+        None => Ok(None),
+        Some(i) => args
+            .get(i + 1)
+            .and_then(|value| value.parse().ok())
+            .map(Some)
+            .ok_or_else(|| format!("{} needs a valid value", name)),
+    }
+}
+
+impl Film {
+    // Some when args hold --film, configured by --tonemap <clamp|reinhard|aces>,
+    // --exposure <stops>, --white-point <value>, --p6 and --legacy-gamma
+    pub fn parse(args: &[String]) -> Result<Option<Self>, String> {
+        if !args.iter().any(|arg| arg == "--film") {
+            return Ok(None);
+        }
+        let tone = match arg_value::<String>(args, "--tonemap")?.as_deref() {
+            None | Some("clamp") => ToneMap::Clamp,
+            Some("reinhard") => ToneMap::Reinhard,
+            Some("aces") => ToneMap::ACES,
+            Some(other) => return Err(format!("unknown tone map: {}", other)),
+        };
+        Ok(Some(Film {
+            tone,
+            exposure: arg_value(args, "--exposure")?.unwrap_or(0.0),
+            white: arg_value(args, "--white-point")?,
+            encoding: Encoding::parse(args),
+            binary: args.iter().any(|arg| arg == "--p6"),
+        }))
+    }
+
+    // Film::parse on the program's arguments, exiting with the usage when they
+    // don't parse
+    pub fn from_args() -> Option<Self> {
+        let args = std::env::args().collect::<Vec<String>>();
+        Film::parse(&args).unwrap_or_else(|e| {
+            eprintln!("{}\n{}", e, USAGE);
+            std::process::exit(2)
+        })
+    }
+
+    pub fn map(&self, c: Real) -> Real {
+        let c = (c * self.exposure.exp2()).max(0.0);
+        let mapped = match (self.tone, self.white) {
+            (ToneMap::Clamp, None) => c,
+            (ToneMap::Clamp, Some(white)) => c / white,
+            (ToneMap::Reinhard, None) => c / (1.0 + c),
+            (ToneMap::Reinhard, Some(white)) => c * (1.0 + c / (white * white)) / (1.0 + c),
+            (ToneMap::ACES, None) => aces(c),
+            (ToneMap::ACES, Some(white)) => aces(c) / aces(white),
+        };
+        if mapped.is_nan() {
+            0.0
+        } else {
+            mapped.min(1.0)
+        }
+    }
+
+    // the image as ppm bytes, pixels holding linear averages with rows from the top
+    pub fn develop(&self, width: usize, height: usize, pixels: &[Color]) -> Vec<u8> {
+        let rgb = pixels.iter().flat_map(|c| {
+            [c.x(), c.y(), c.z()].map(|v| (255.99 * self.encoding.encode(self.map(v))) as u8)
+        });
+        if self.binary {
+            let mut bytes = format!("P6\n{} {}\n255\n", width, height).into_bytes();
+            bytes.extend(rgb);
+            bytes
+        } else {
+            let rgb = rgb.collect::<Vec<u8>>();
+            let mut ppm = format!("P3\n{} {}\n255\n", width, height);
+            for rgb in rgb.chunks(3) {
+                ppm.push_str(&format!("{} {} {}\n", rgb[0], rgb[1], rgb[2]));
+            }
+            ppm.into_bytes()
+        }
+    }
+}
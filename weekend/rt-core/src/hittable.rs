@@ -1,14 +1,14 @@
-use std::rc::Rc;
+use std::sync::Arc;
 
 use crate::ray::Ray;
 
-use crate::vec3::{dot, Point3, Vec3};
+use crate::vec3::{dot, Point3, Real, Vec3};
 
 pub struct HitRecord {
     pub p: Point3,
     pub normal: Vec3,
-    pub material: Rc<dyn Material>,
-    pub t: f64,
+    pub material: Arc<dyn Material>,
+    pub t: Real,
     pub front_face: bool,
 }
 
@@ -24,15 +24,15 @@ impl HitRecord {
 }
 
 pub struct Hittable {
-    pub shape: Rc<dyn Shape>,
-    pub material: Rc<dyn Material>,
+    pub shape: Arc<dyn Shape>,
+    pub material: Arc<dyn Material>,
 }
 
-pub trait Shape {
-    fn hit(&self, r: Ray, t_min: f64, t_max: f64, rec: &mut HitRecord) -> bool;
+pub trait Shape: Send + Sync {
+    fn hit(&self, r: Ray, t_min: Real, t_max: Real, rec: &mut HitRecord) -> bool;
 }
 
-pub trait Material {
+pub trait Material: Send + Sync {
     fn scatter(
         &self,
         r_in: Ray,
@@ -45,8 +45,8 @@ pub trait Material {
 impl Hittable {
     pub fn new<T: 'static + Shape, U: 'static + Material>(shape: T, material: U) -> Self {
         Self {
-            shape: Rc::new(shape),
-            material: Rc::new(material),
+            shape: Arc::new(shape),
+            material: Arc::new(material),
         }
     }
 }
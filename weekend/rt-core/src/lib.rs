@@ -0,0 +1,10 @@
+pub mod background;
+pub mod camera;
+pub mod color;
+pub mod film;
+pub mod hittable;
+pub mod material;
+pub mod ray;
+pub mod sphere;
+pub mod util;
+pub mod vec3;
@@ -2,7 +2,7 @@ use crate::{
     hittable::{HitRecord, Material},
     ray::Ray,
     util::random_f64,
-    vec3::{dot, reflect, refract, unit_vector, Color, Vec3},
+    vec3::{dot, reflect, refract, unit_vector, Color, Real, Vec3},
 };
 
 pub struct Lambertian {
@@ -32,11 +32,11 @@ impl Material for Lambertian {
 
 pub struct Metal {
     pub albedo: Color,
-    pub fuzz: f64,
+    pub fuzz: Real,
 }
 
 impl Metal {
-    pub fn new(albedo: Color, fuzz: f64) -> Self {
+    pub fn new(albedo: Color, fuzz: Real) -> Self {
         Self { albedo, fuzz }
     }
 }
@@ -57,11 +57,11 @@ impl Material for Metal {
 }
 
 pub struct Dielectric {
-    pub ir: f64,
+    pub ir: Real,
 }
 
 impl Dielectric {
-    pub fn new(ir: f64) -> Self {
+    pub fn new(ir: Real) -> Self {
         Self { ir }
     }
 }
@@ -98,7 +98,7 @@ impl Material for Dielectric {
     }
 }
 
-fn reflectance(cosine: f64, ref_idx: f64) -> f64 {
+fn reflectance(cosine: Real, ref_idx: Real) -> Real {
     let r0 = ((1.0 - ref_idx) / (1.0 + ref_idx)).powi(2);
     r0 + (1.0 - r0) * (1.0 - cosine).powi(5)
 }
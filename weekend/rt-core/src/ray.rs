@@ -1,4 +1,4 @@
-use crate::vec3::{Point3, Vec3};
+use crate::vec3::{Point3, Real, Vec3};
 
 #[derive(Debug, Clone, Copy)]
 pub struct Ray {
@@ -22,7 +22,7 @@ impl Ray {
         self.dir
     }
 
-    pub fn at(self, t: f64) -> Point3 {
+    pub fn at(self, t: Real) -> Point3 {
         self.orig + t * self.dir
     }
 }
@@ -1,6 +1,7 @@
+use crate::vec3::Real;
 use rand::distributions::{Distribution, Uniform};
 
-pub fn clamp(x: f64, min: f64, max: f64) -> f64 {
+pub fn clamp(x: Real, min: Real, max: Real) -> Real {
     if x < min {
         return min;
     }
@@ -10,13 +11,13 @@ pub fn clamp(x: f64, min: f64, max: f64) -> f64 {
     x
 }
 
-pub fn random_f64() -> f64 {
+pub fn random_f64() -> Real {
     let mut rng = rand::thread_rng();
     let uniform = Uniform::from(0.0..1.0);
     uniform.sample(&mut rng)
 }
 
-pub fn random_f64_range(min: f64, max: f64) -> f64 {
+pub fn random_f64_range(min: Real, max: Real) -> Real {
     let mut rng = rand::thread_rng();
     let uniform = Uniform::from(min..max);
     uniform.sample(&mut rng)
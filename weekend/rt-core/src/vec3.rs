@@ -1,10 +1,25 @@
 use std::ops::{Add, AddAssign, Div, DivAssign, Index, IndexMut, Mul, MulAssign, Neg, Sub};
 
 use crate::util::{random_f64, random_f64_range};
+use rand::Rng;
+
+// Scalar type of the vector math. The f32 feature matches rest_of_life's precision
+// so the two halves of the repo can pass vectors between each other; f64 wins over
+// it, so rest_of_life can ask for f64 of the rt-core it depends on with f32.
+#[cfg(any(not(feature = "f32"), feature = "f64"))]
+pub type Real = f64;
+#[cfg(all(feature = "f32", not(feature = "f64")))]
+pub type Real = f32;
+
+// the constants of Real, as std::f64::consts
+#[cfg(any(not(feature = "f32"), feature = "f64"))]
+pub use std::f64::consts;
+#[cfg(all(feature = "f32", not(feature = "f64")))]
+pub use std::f32::consts;
 
 #[derive(Debug, Clone, Copy)]
 pub struct Vec3 {
-    e: [f64; 4],
+    e: [Real; 4],
 }
 
 impl Default for Vec3 {
@@ -14,29 +29,29 @@ impl Default for Vec3 {
 }
 
 impl Vec3 {
-    pub fn new(e0: f64, e1: f64, e2: f64) -> Self {
+    pub fn new(e0: Real, e1: Real, e2: Real) -> Self {
         Self {
             e: [e0, e1, e2, 0.0],
         }
     }
 
-    pub fn x(&self) -> f64 {
+    pub fn x(&self) -> Real {
         self.e[0]
     }
 
-    pub fn y(&self) -> f64 {
+    pub fn y(&self) -> Real {
         self.e[1]
     }
 
-    pub fn z(&self) -> f64 {
+    pub fn z(&self) -> Real {
         self.e[2]
     }
 
-    pub fn len2(&self) -> f64 {
+    pub fn len2(&self) -> Real {
         self.e[0] * self.e[0] + self.e[1] * self.e[1] + self.e[2] * self.e[2]
     }
 
-    pub fn len(&self) -> f64 {
+    pub fn len(&self) -> Real {
         self.len2().sqrt()
     }
 
@@ -44,7 +59,7 @@ impl Vec3 {
         Vec3::new(random_f64(), random_f64(), random_f64())
     }
 
-    pub fn random_range(min: f64, max: f64) -> Self {
+    pub fn random_range(min: Real, max: Real) -> Self {
         Vec3::new(
             random_f64_range(min, max),
             random_f64_range(min, max),
@@ -53,8 +68,17 @@ impl Vec3 {
     }
 
     pub fn random_in_unit_sphere() -> Self {
+        Vec3::random_in_unit_sphere_with(&mut rand::thread_rng())
+    }
+
+    // as random_in_unit_sphere, drawing from the given generator
+    pub fn random_in_unit_sphere_with<R: Rng + ?Sized>(rng: &mut R) -> Self {
         loop {
-            let p = Vec3::random_range(-1.0, 1.0);
+            let p = Vec3::new(
+                rng.gen_range(-1.0..1.0),
+                rng.gen_range(-1.0..1.0),
+                rng.gen_range(-1.0..1.0),
+            );
             if p.len2() < 1.0 {
                 return p;
             }
@@ -62,7 +86,7 @@ impl Vec3 {
     }
 
     pub fn random_unit_vector() -> Self {
-        let a = random_f64_range(0.0, 2.0 * std::f64::consts::PI);
+        let a = random_f64_range(0.0, 2.0 * std::f64::consts::PI as Real);
         let z = random_f64_range(-1.0, 1.0);
         let r = (1.0 - z * z).sqrt();
         Vec3::new(r * a.cos(), r * a.sin(), z)
@@ -95,16 +119,16 @@ impl AddAssign for Vec3 {
     }
 }
 
-impl MulAssign<f64> for Vec3 {
-    fn mul_assign(&mut self, t: f64) {
+impl MulAssign<Real> for Vec3 {
+    fn mul_assign(&mut self, t: Real) {
         self.e[0] *= t;
         self.e[1] *= t;
         self.e[2] *= t;
     }
 }
 
-impl DivAssign<f64> for Vec3 {
-    fn div_assign(&mut self, t: f64) {
+impl DivAssign<Real> for Vec3 {
+    fn div_assign(&mut self, t: Real) {
         self.e[0] /= t;
         self.e[1] /= t;
         self.e[2] /= t;
@@ -112,7 +136,7 @@ impl DivAssign<f64> for Vec3 {
 }
 
 impl Index<usize> for Vec3 {
-    type Output = f64;
+    type Output = Real;
     fn index(&self, i: usize) -> &Self::Output {
         &self.e[i]
     }
@@ -152,28 +176,28 @@ impl Mul<Vec3> for Vec3 {
     }
 }
 
-impl Mul<f64> for Vec3 {
+impl Mul<Real> for Vec3 {
     type Output = Vec3;
-    fn mul(self, t: f64) -> Vec3 {
+    fn mul(self, t: Real) -> Vec3 {
         Vec3::new(self.e[0] * t, self.e[1] * t, self.e[2] * t)
     }
 }
 
-impl Mul<Vec3> for f64 {
+impl Mul<Vec3> for Real {
     type Output = Vec3;
     fn mul(self, v: Vec3) -> Vec3 {
         Vec3::new(self * v.e[0], self * v.e[1], self * v.e[2])
     }
 }
 
-impl Div<f64> for Vec3 {
+impl Div<Real> for Vec3 {
     type Output = Vec3;
-    fn div(self, t: f64) -> Vec3 {
+    fn div(self, t: Real) -> Vec3 {
         (1.0 / t) * self
     }
 }
 
-pub fn dot(u: Vec3, v: Vec3) -> f64 {
+pub fn dot(u: Vec3, v: Vec3) -> Real {
     u.e[0] * v.e[0] + u[1] * v.e[1] + u.e[2] * v.e[2]
 }
 
@@ -193,7 +217,7 @@ pub fn reflect(v: Vec3, n: Vec3) -> Vec3 {
     v - 2.0 * dot(v, n) * n
 }
 
-pub fn refract(uv: Vec3, n: Vec3, etai_over_etat: f64) -> Vec3 {
+pub fn refract(uv: Vec3, n: Vec3, etai_over_etat: Real) -> Vec3 {
     let cos_theta = dot(-uv, n).min(1.0);
     let r_out_perp = etai_over_etat * (uv + cos_theta * n);
     let r_out_parallel = -(1.0 - r_out_perp.len2()).abs().sqrt() * n;
@@ -201,14 +225,29 @@ pub fn refract(uv: Vec3, n: Vec3, etai_over_etat: f64) -> Vec3 {
 }
 
 pub fn random_in_unit_disk() -> Vec3 {
+    random_in_unit_disk_with(&mut rand::thread_rng())
+}
+
+// as random_in_unit_disk, drawing from the given generator
+pub fn random_in_unit_disk_with<R: Rng + ?Sized>(rng: &mut R) -> Vec3 {
     loop {
-        let p = Vec3::new(
-            random_f64_range(-1.0, 1.0),
-            random_f64_range(-1.0, 1.0),
-            0.0,
-        );
+        let p = Vec3::new(rng.gen_range(-1.0..1.0), rng.gen_range(-1.0..1.0), 0.0);
         if p.len2() < 1.0 {
             return p;
         }
     }
 }
+
+#[cfg(feature = "nalgebra")]
+impl From<nalgebra::Vector3<Real>> for Vec3 {
+    fn from(v: nalgebra::Vector3<Real>) -> Self {
+        Vec3::new(v.x, v.y, v.z)
+    }
+}
+
+#[cfg(feature = "nalgebra")]
+impl From<Vec3> for nalgebra::Vector3<Real> {
+    fn from(v: Vec3) -> Self {
+        nalgebra::Vector3::new(v.x(), v.y(), v.z())
+    }
+}
@@ -1,5 +1,5 @@
 use crate::ray::Ray;
-use crate::vec3::{cross, unit_vector, Point3, Vec3};
+use crate::vec3::{cross, unit_vector, Point3, Real, Vec3};
 
 pub struct Camera {
     origin: Point3,
@@ -9,7 +9,7 @@ pub struct Camera {
 }
 
 impl Camera {
-    pub fn new(lookfrom: Point3, lookat: Point3, vup: Vec3, vfov: f64, aspect_ratio: f64) -> Self {
+    pub fn new(lookfrom: Point3, lookat: Point3, vup: Vec3, vfov: Real, aspect_ratio: Real) -> Self {
         let theta = vfov.to_radians();
         let h = (theta / 2.0).tan();
         let viewport_height = 2.0 * h;
@@ -32,7 +32,7 @@ impl Camera {
         }
     }
 
-    pub fn get_ray(&self, u: f64, v: f64) -> Ray {
+    pub fn get_ray(&self, u: Real, v: Real) -> Ray {
         Ray::new(
             self.origin,
             self.lower_left_corner + u * self.horizontal + v * self.vertical - self.origin,
@@ -1,104 +0,0 @@
-use crate::{
-    hittable::{HitRecord, Material},
-    ray::Ray,
-    util::random_f64,
-    vec3::{dot, reflect, refract, unit_vector, Color, Vec3},
-};
-
-pub struct Lambertian {
-    pub albedo: Color,
-}
-
-impl Lambertian {
-    pub fn new(albedo: Color) -> Self {
-        Self { albedo }
-    }
-}
-
-impl Material for Lambertian {
-    fn scatter(
-        &self,
-        _r_in: Ray,
-        rec: &HitRecord,
-        attenuation: &mut Vec3,
-        scattered: &mut Ray,
-    ) -> bool {
-        let scatter_direction = rec.normal + Vec3::random_unit_vector();
-        *scattered = Ray::new(rec.p, scatter_direction);
-        *attenuation = self.albedo;
-        true
-    }
-}
-
-pub struct Metal {
-    pub albedo: Color,
-    pub fuzz: f64,
-}
-
-impl Metal {
-    pub fn new(albedo: Color, fuzz: f64) -> Self {
-        Self { albedo, fuzz }
-    }
-}
-
-impl Material for Metal {
-    fn scatter(
-        &self,
-        r_in: Ray,
-        rec: &HitRecord,
-        attenuation: &mut Vec3,
-        scattered: &mut Ray,
-    ) -> bool {
-        let reflected = reflect(unit_vector(r_in.direction()), rec.normal);
-        *scattered = Ray::new(rec.p, reflected + self.fuzz * Vec3::random_in_unit_sphere());
-        *attenuation = self.albedo;
-        dot(scattered.direction(), rec.normal) > 0.0
-    }
-}
-
-pub struct Dielectric {
-    pub ir: f64,
-}
-
-impl Dielectric {
-    pub fn new(ir: f64) -> Self {
-        Self { ir }
-    }
-}
-
-impl Material for Dielectric {
-    fn scatter(
-        &self,
-        r_in: Ray,
-        rec: &HitRecord,
-        attenuation: &mut Vec3,
-        scattered: &mut Ray,
-    ) -> bool {
-        *attenuation = Vec3::new(1.0, 1.0, 1.0);
-        let refraction_ratio = if rec.front_face {
-            1.0 / self.ir
-        } else {
-            self.ir
-        };
-
-        let unit_direction = unit_vector(r_in.direction());
-        let cos_theta = dot(-unit_direction, rec.normal).min(1.0);
-        let sin_theta = (1.0 - cos_theta * cos_theta).sqrt();
-
-        let cannot_refract = refraction_ratio * sin_theta > 1.0;
-        let direction = if cannot_refract || reflectance(cos_theta, refraction_ratio) > random_f64()
-        {
-            reflect(unit_direction, rec.normal)
-        } else {
-            refract(unit_direction, rec.normal, refraction_ratio)
-        };
-
-        *scattered = Ray::new(rec.p, direction);
-        true
-    }
-}
-
-fn reflectance(cosine: f64, ref_idx: f64) -> f64 {
-    let r0 = ((1.0 - ref_idx) / (1.0 + ref_idx)).powi(2);
-    r0 + (1.0 - r0) * (1.0 - cosine).powi(5)
-}
@@ -1,19 +0,0 @@
-use crate::util::clamp;
-use crate::vec3::Color;
-
-pub fn write_color(pixel_color: Color, samples_per_pixel: i32) -> String {
-    let mut r = pixel_color.x();
-    let mut g = pixel_color.y();
-    let mut b = pixel_color.z();
-    let scale = 1.0 / samples_per_pixel as f64;
-    r = (scale * r).sqrt();
-    g = (scale * g).sqrt();
-    b = (scale * b).sqrt();
-    const A: f64 = 256.0;
-    format!(
-        "{} {} {}",
-        (A * clamp(r, 0.0, 0.999)) as u8,
-        (A * clamp(g, 0.0, 0.999)) as u8,
-        (A * clamp(b, 0.0, 0.999)) as u8,
-    )
-}
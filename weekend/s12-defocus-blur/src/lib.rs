@@ -1,8 +1 @@
-pub mod camera;
-pub mod color;
-pub mod hittable;
-pub mod material;
-pub mod ray;
-pub mod sphere;
-pub mod util;
-pub mod vec3;
+pub use rt_core::{camera, color, hittable, material, ray, sphere, util, vec3};
@@ -1,214 +0,0 @@
-use std::ops::{Add, AddAssign, Div, DivAssign, Index, IndexMut, Mul, MulAssign, Neg, Sub};
-
-use crate::util::{random_f64, random_f64_range};
-
-#[derive(Debug, Clone, Copy)]
-pub struct Vec3 {
-    e: [f64; 4],
-}
-
-impl Default for Vec3 {
-    fn default() -> Self {
-        Vec3 { e: [0.0; 4] }
-    }
-}
-
-impl Vec3 {
-    pub fn new(e0: f64, e1: f64, e2: f64) -> Self {
-        Self {
-            e: [e0, e1, e2, 0.0],
-        }
-    }
-
-    pub fn x(&self) -> f64 {
-        self.e[0]
-    }
-
-    pub fn y(&self) -> f64 {
-        self.e[1]
-    }
-
-    pub fn z(&self) -> f64 {
-        self.e[2]
-    }
-
-    pub fn len2(&self) -> f64 {
-        self.e[0] * self.e[0] + self.e[1] * self.e[1] + self.e[2] * self.e[2]
-    }
-
-    pub fn len(&self) -> f64 {
-        self.len2().sqrt()
-    }
-
-    pub fn random() -> Self {
-        Vec3::new(random_f64(), random_f64(), random_f64())
-    }
-
-    pub fn random_range(min: f64, max: f64) -> Self {
-        Vec3::new(
-            random_f64_range(min, max),
-            random_f64_range(min, max),
-            random_f64_range(min, max),
-        )
-    }
-
-    pub fn random_in_unit_sphere() -> Self {
-        loop {
-            let p = Vec3::random_range(-1.0, 1.0);
-            if p.len2() < 1.0 {
-                return p;
-            }
-        }
-    }
-
-    pub fn random_unit_vector() -> Self {
-        let a = random_f64_range(0.0, 2.0 * std::f64::consts::PI);
-        let z = random_f64_range(-1.0, 1.0);
-        let r = (1.0 - z * z).sqrt();
-        Vec3::new(r * a.cos(), r * a.sin(), z)
-    }
-
-    pub fn random_in_hemisphere(normal: Vec3) -> Self {
-        let in_unit_sphere = Vec3::random_in_unit_sphere();
-        if dot(in_unit_sphere, normal) > 0.0 {
-            in_unit_sphere
-        } else {
-            -in_unit_sphere
-        }
-    }
-}
-
-impl Neg for Vec3 {
-    type Output = Vec3;
-    fn neg(self) -> Self::Output {
-        Vec3 {
-            e: [-self.e[0], -self.e[1], -self.e[2], self.e[3]],
-        }
-    }
-}
-
-impl AddAssign for Vec3 {
-    fn add_assign(&mut self, v: Vec3) {
-        self.e[0] += v.e[0];
-        self.e[1] += v.e[1];
-        self.e[2] += v.e[2];
-    }
-}
-
-impl MulAssign<f64> for Vec3 {
-    fn mul_assign(&mut self, t: f64) {
-        self.e[0] *= t;
-        self.e[1] *= t;
-        self.e[2] *= t;
-    }
-}
-
-impl DivAssign<f64> for Vec3 {
-    fn div_assign(&mut self, t: f64) {
-        self.e[0] /= t;
-        self.e[1] /= t;
-        self.e[2] /= t;
-    }
-}
-
-impl Index<usize> for Vec3 {
-    type Output = f64;
-    fn index(&self, i: usize) -> &Self::Output {
-        &self.e[i]
-    }
-}
-
-impl IndexMut<usize> for Vec3 {
-    fn index_mut(&mut self, i: usize) -> &mut Self::Output {
-        &mut self.e[i]
-    }
-}
-
-pub type Point3 = Vec3;
-pub type Color = Vec3;
-
-pub fn println_vec(v: Vec3) {
-    println!("{} {} {}", v.e[0], v.e[1], v.e[2]);
-}
-
-impl Add<Vec3> for Vec3 {
-    type Output = Vec3;
-    fn add(self, v: Vec3) -> Vec3 {
-        Vec3::new(self.e[0] + v.e[0], self.e[1] + v.e[1], self.e[2] + v.e[2])
-    }
-}
-
-impl Sub<Vec3> for Vec3 {
-    type Output = Vec3;
-    fn sub(self, v: Vec3) -> Vec3 {
-        Vec3::new(self.e[0] - v.e[0], self.e[1] - v.e[1], self.e[2] - v.e[2])
-    }
-}
-
-impl Mul<Vec3> for Vec3 {
-    type Output = Vec3;
-    fn mul(self, v: Vec3) -> Vec3 {
-        Vec3::new(self.e[0] * v.e[0], self.e[1] * v.e[1], self.e[2] * v.e[2])
-    }
-}
-
-impl Mul<f64> for Vec3 {
-    type Output = Vec3;
-    fn mul(self, t: f64) -> Vec3 {
-        Vec3::new(self.e[0] * t, self.e[1] * t, self.e[2] * t)
-    }
-}
-
-impl Mul<Vec3> for f64 {
-    type Output = Vec3;
-    fn mul(self, v: Vec3) -> Vec3 {
-        Vec3::new(self * v.e[0], self * v.e[1], self * v.e[2])
-    }
-}
-
-impl Div<f64> for Vec3 {
-    type Output = Vec3;
-    fn div(self, t: f64) -> Vec3 {
-        (1.0 / t) * self
-    }
-}
-
-pub fn dot(u: Vec3, v: Vec3) -> f64 {
-    u.e[0] * v.e[0] + u[1] * v.e[1] + u.e[2] * v.e[2]
-}
-
-pub fn cross(u: Vec3, v: Vec3) -> Vec3 {
-    Vec3::new(
-        u.e[1] * v.e[2] - u.e[2] * v.e[1],
-        u.e[2] * v.e[0] - u.e[0] * v.e[2],
-        u.e[0] * v.e[1] - u.e[1] * v.e[0],
-    )
-}
-
-pub fn unit_vector(v: Vec3) -> Vec3 {
-    v / v.len()
-}
-
-pub fn reflect(v: Vec3, n: Vec3) -> Vec3 {
-    v - 2.0 * dot(v, n) * n
-}
-
-pub fn refract(uv: Vec3, n: Vec3, etai_over_etat: f64) -> Vec3 {
-    let cos_theta = dot(-uv, n).min(1.0);
-    let r_out_perp = etai_over_etat * (uv + cos_theta * n);
-    let r_out_parallel = -(1.0 - r_out_perp.len2()).abs().sqrt() * n;
-    r_out_perp + r_out_parallel
-}
-
-pub fn random_in_unit_disk() -> Vec3 {
-    loop {
-        let p = Vec3::new(
-            random_f64_range(-1.0, 1.0),
-            random_f64_range(-1.0, 1.0),
-            0.0,
-        );
-        if p.len2() < 1.0 {
-            return p;
-        }
-    }
-}
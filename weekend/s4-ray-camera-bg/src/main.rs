@@ -1,17 +1,18 @@
 use s4_ray_camera_bg::{
     color::write_color,
+    film::Film,
     ray::Ray,
-    vec3::{unit_vector, Color, Point3, Vec3},
+    vec3::{unit_vector, Color, Point3, Real, Vec3},
 };
 use std::fs::File;
 use std::io::{self, Write};
 
-const ASPECT_RATIO: f64 = 16.0 / 9.0;
+const ASPECT_RATIO: Real = 16.0 / 9.0;
 const IMAGE_WIDTH: i32 = 384;
-const IMAGE_HEIGHT: i32 = (IMAGE_WIDTH as f64 / ASPECT_RATIO) as i32;
-const VIEWPORT_HEIGHT: f64 = 2.0;
-const VIEWPORT_WIDTH: f64 = ASPECT_RATIO * VIEWPORT_HEIGHT;
-const FOCAL_LENGTH: f64 = 1.0;
+const IMAGE_HEIGHT: i32 = (IMAGE_WIDTH as Real / ASPECT_RATIO) as i32;
+const VIEWPORT_HEIGHT: Real = 2.0;
+const VIEWPORT_WIDTH: Real = ASPECT_RATIO * VIEWPORT_HEIGHT;
+const FOCAL_LENGTH: Real = 1.0;
 
 const COUNT_MAX: usize = IMAGE_HEIGHT as usize * IMAGE_WIDTH as usize;
 
@@ -30,26 +31,30 @@ fn main() -> io::Result<()> {
     let lower_left_corner =
         origin - horizontal / 2.0 - vertical / 2.0 - Vec3::new(0.0, 0.0, FOCAL_LENGTH);
 
-    let mut data_vector = vec![String::from(""); COUNT_MAX];
+    let mut pixels = vec![Color::new(0.0, 0.0, 0.0); COUNT_MAX];
     let mut index = 0;
 
     for j in (0..IMAGE_HEIGHT).rev() {
         for i in 0..IMAGE_WIDTH {
-            let u = i as f64 / (IMAGE_WIDTH - 1) as f64;
-            let v = j as f64 / (IMAGE_HEIGHT - 1) as f64;
+            let u = i as Real / (IMAGE_WIDTH - 1) as Real;
+            let v = j as Real / (IMAGE_HEIGHT - 1) as Real;
             let r = Ray::new(
                 origin,
                 lower_left_corner + u * horizontal + v * vertical - origin,
             );
-            let pixel_color = ray_color(r);
-            data_vector[index] = write_color(pixel_color);
+            pixels[index] = ray_color(r);
             index += 1;
         }
     }
 
+    let mut file = File::create("4-ray-camera-bg.ppm").unwrap();
+    if let Some(film) = Film::from_args() {
+        let (width, height) = (IMAGE_WIDTH as usize, IMAGE_HEIGHT as usize);
+        return file.write_all(&film.develop(width, height, &pixels));
+    }
+    let data_vector = pixels.into_iter().map(write_color).collect::<Vec<String>>();
     out_str += &data_vector.join("\n");
 
-    let mut file = File::create("4-ray-camera-bg.ppm").unwrap();
     file.write_fmt(format_args!("{}", out_str))?;
     Ok(())
 }
@@ -1,7 +1,7 @@
-use crate::vec3::Color;
+use crate::vec3::{Color, Real};
 
 pub fn write_color(pixel_color: Color) -> String {
-    const A: f64 = 255.999;
+    const A: Real = 255.999;
     format!(
         "{} {} {}",
         (A * pixel_color.x()) as u8,
@@ -1,3 +1,2 @@
 pub mod color;
-pub mod ray;
-pub mod vec3;
+pub use rt_core::{ray, vec3};
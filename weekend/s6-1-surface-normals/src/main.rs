@@ -1,21 +1,21 @@
 use s6_1_surface_normals::{
     color::write_color,
     ray::Ray,
-    vec3::{dot, unit_vector, Color, Point3, Vec3},
+    vec3::{dot, unit_vector, Color, Point3, Real, Vec3},
 };
 use std::fs::File;
 use std::io::{self, Write};
 
-const ASPECT_RATIO: f64 = 16.0 / 9.0;
+const ASPECT_RATIO: Real = 16.0 / 9.0;
 const IMAGE_WIDTH: i32 = 384;
-const IMAGE_HEIGHT: i32 = (IMAGE_WIDTH as f64 / ASPECT_RATIO) as i32;
-const VIEWPORT_HEIGHT: f64 = 2.0;
-const VIEWPORT_WIDTH: f64 = ASPECT_RATIO * VIEWPORT_HEIGHT;
-const FOCAL_LENGTH: f64 = 1.0;
+const IMAGE_HEIGHT: i32 = (IMAGE_WIDTH as Real / ASPECT_RATIO) as i32;
+const VIEWPORT_HEIGHT: Real = 2.0;
+const VIEWPORT_WIDTH: Real = ASPECT_RATIO * VIEWPORT_HEIGHT;
+const FOCAL_LENGTH: Real = 1.0;
 
 const COUNT_MAX: usize = IMAGE_HEIGHT as usize * IMAGE_WIDTH as usize;
 
-fn hit_sphere(center: Point3, radius: f64, r: Ray) -> f64 {
+fn hit_sphere(center: Point3, radius: Real, r: Ray) -> Real {
     let oc = r.origin() - center;
     let a = dot(r.direction(), r.direction());
     let half_b = dot(oc, r.direction());
@@ -54,8 +54,8 @@ fn main() -> io::Result<()> {
 
     for j in (0..IMAGE_HEIGHT).rev() {
         for i in 0..IMAGE_WIDTH {
-            let u = i as f64 / (IMAGE_WIDTH - 1) as f64;
-            let v = j as f64 / (IMAGE_HEIGHT - 1) as f64;
+            let u = i as Real / (IMAGE_WIDTH - 1) as Real;
+            let v = j as Real / (IMAGE_HEIGHT - 1) as Real;
             let r = Ray::new(
                 origin,
                 lower_left_corner + u * horizontal + v * vertical - origin,
@@ -1,11 +1,11 @@
 use crate::ray::Ray;
 use crate::sphere::Sphere;
-use crate::vec3::{dot, Point3, Vec3};
+use crate::vec3::{dot, Point3, Real, Vec3};
 
 pub struct HitRecord {
     pub p: Point3,
     pub normal: Vec3,
-    pub t: f64,
+    pub t: Real,
     pub front_face: bool
 }
 
@@ -25,7 +25,7 @@ pub struct Hittable<T: Shape> {
 }
 
 pub trait Shape {
-    fn hit(&self, r: Ray, t_min: f64, t_max: f64, rec: &mut HitRecord) -> bool;
+    fn hit(&self, r: Ray, t_min: Real, t_max: Real, rec: &mut HitRecord) -> bool;
 }
 
 impl Hittable<Sphere> {
@@ -1,5 +1,4 @@
 pub mod color;
-pub mod ray;
-pub mod vec3;
 pub mod hittable;
 pub mod sphere;
+pub use rt_core::{ray, vec3};
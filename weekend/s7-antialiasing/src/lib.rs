@@ -1,7 +1,5 @@
+pub mod camera;
 pub mod color;
-pub mod ray;
-pub mod vec3;
 pub mod hittable;
 pub mod sphere;
-pub mod camera;
-pub mod util;
+pub use rt_core::{ray, util, vec3};
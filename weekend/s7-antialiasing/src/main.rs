@@ -3,16 +3,16 @@ use s7_antialiasing::{
     util::random_f64,
     color::write_color,
     ray::Ray,
-    vec3::{dot, unit_vector, Color, Point3, Vec3},
+    vec3::{dot, unit_vector, Color, Point3, Real, Vec3},
     sphere::Sphere,
     hittable::{HitRecord, Hittable, Shape},
 };
 use std::fs::File;
 use std::io::{self, Write};
 
-const ASPECT_RATIO: f64 = 16.0 / 9.0;
+const ASPECT_RATIO: Real = 16.0 / 9.0;
 const IMAGE_WIDTH: i32 = 384;
-const IMAGE_HEIGHT: i32 = (IMAGE_WIDTH as f64 / ASPECT_RATIO) as i32;
+const IMAGE_HEIGHT: i32 = (IMAGE_WIDTH as Real / ASPECT_RATIO) as i32;
 const SAMPLES_PER_PIXEL: i32 = 100;
 
 const COUNT_MAX: usize = IMAGE_HEIGHT as usize * IMAGE_WIDTH as usize;
@@ -25,7 +25,7 @@ fn ray_color(r: Ray, world: &Vec<Hittable<Sphere>>) -> Color {
         front_face: false,
     };
 
-    if world.iter().any(|h| h.shape.hit(r, 0.0, f64::INFINITY, &mut rec)) {
+    if world.iter().any(|h| h.shape.hit(r, 0.0, Real::INFINITY, &mut rec)) {
         return 0.5 * (rec.normal + Color::new(1.0, 1.0, 1.0));
     }
 
@@ -51,8 +51,8 @@ fn main() -> io::Result<()> {
         for i in 0..IMAGE_WIDTH {
             let mut pixel_color = Color::new(0.0, 0.0, 0.0);
             for _ in 0..SAMPLES_PER_PIXEL {
-                let u = (i as f64 + random_f64()) / (IMAGE_WIDTH - 1) as f64;
-                let v = (j as f64 + random_f64()) / (IMAGE_HEIGHT - 1) as f64;
+                let u = (i as Real + random_f64()) / (IMAGE_WIDTH - 1) as Real;
+                let v = (j as Real + random_f64()) / (IMAGE_HEIGHT - 1) as Real;
                 let r = cam.get_ray(u, v);
                 pixel_color += ray_color(r, &world);
             }
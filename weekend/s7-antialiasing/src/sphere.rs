@@ -2,22 +2,22 @@ use crate::{
     hittable::HitRecord,
     hittable::Shape,
     ray::Ray,
-    vec3::{dot, Point3}
+    vec3::{dot, Point3, Real}
 };
 
 pub struct Sphere {
     center: Point3,
-    radius: f64,
+    radius: Real,
 }
 
 impl Sphere {
-    pub fn new(center: Point3, radius: f64) -> Self {
+    pub fn new(center: Point3, radius: Real) -> Self {
         Self { center, radius }
     }
 }
 
 impl Shape for Sphere {
-    fn hit(&self, r: Ray, t_min: f64, t_max: f64, rec: &mut HitRecord) -> bool {
+    fn hit(&self, r: Ray, t_min: Real, t_max: Real, rec: &mut HitRecord) -> bool {
         let oc = r.origin() - self.center;
         let a = r.direction().len2();
         let half_b = dot(oc, r.direction());
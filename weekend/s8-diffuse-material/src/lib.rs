@@ -1,7 +1,4 @@
-pub mod color;
-pub mod ray;
-pub mod vec3;
+pub mod camera;
 pub mod hittable;
 pub mod sphere;
-pub mod camera;
-pub mod util;
+pub use rt_core::{color, ray, util, vec3};
@@ -1,18 +1,18 @@
 use s8_diffuse_material::{
     camera::Camera,
     util::random_f64,
-    color::write_color,
+    color::{write_color, Encoding},
     ray::Ray,
-    vec3::{dot, unit_vector, Color, Point3, Vec3},
+    vec3::{dot, unit_vector, Color, Point3, Real, Vec3},
     sphere::Sphere,
     hittable::{HitRecord, Hittable, Shape},
 };
 use std::fs::File;
 use std::io::{self, Write};
 
-const ASPECT_RATIO: f64 = 16.0 / 9.0;
+const ASPECT_RATIO: Real = 16.0 / 9.0;
 const IMAGE_WIDTH: i32 = 384;
-const IMAGE_HEIGHT: i32 = (IMAGE_WIDTH as f64 / ASPECT_RATIO) as i32;
+const IMAGE_HEIGHT: i32 = (IMAGE_WIDTH as Real / ASPECT_RATIO) as i32;
 const SAMPLES_PER_PIXEL: i32 = 100;
 const MAX_DEPTH: i32 = 50;
 
@@ -30,7 +30,7 @@ fn ray_color(r: Ray, world: &Vec<Hittable<Sphere>>, depth: i32) -> Color {
         return Color::new(0.0, 0.0, 0.0);
     }
 
-    if world.iter().any(|h| h.shape.hit(r, 0.001, f64::INFINITY, &mut rec)) {
+    if world.iter().any(|h| h.shape.hit(r, 0.001, Real::INFINITY, &mut rec)) {
         let target = rec.p + Vec3::random_in_hemisphere(rec.normal);
         return 0.5 * ray_color(Ray::new(rec.p, target - rec.p), world, depth - 1);
     }
@@ -42,6 +42,7 @@ fn ray_color(r: Ray, world: &Vec<Hittable<Sphere>>, depth: i32) -> Color {
 
 fn main() -> io::Result<()> {
     let mut out_str = format!("P3\n{} {}\n255\n", IMAGE_WIDTH, IMAGE_HEIGHT);
+    let encoding = Encoding::from_args();
 
     let world = vec![
         Hittable::new(Sphere::new(Point3::new(0.0, 0.0, -1.0), 0.5)),
@@ -57,12 +58,12 @@ fn main() -> io::Result<()> {
         for i in 0..IMAGE_WIDTH {
             let mut pixel_color = Color::new(0.0, 0.0, 0.0);
             for _ in 0..SAMPLES_PER_PIXEL {
-                let u = (i as f64 + random_f64()) / (IMAGE_WIDTH - 1) as f64;
-                let v = (j as f64 + random_f64()) / (IMAGE_HEIGHT - 1) as f64;
+                let u = (i as Real + random_f64()) / (IMAGE_WIDTH - 1) as Real;
+                let v = (j as Real + random_f64()) / (IMAGE_HEIGHT - 1) as Real;
                 let r = cam.get_ray(u, v);
                 pixel_color += ray_color(r, &world, MAX_DEPTH);
             }
-            data_vector[index] = write_color(pixel_color, SAMPLES_PER_PIXEL);
+            data_vector[index] = write_color(pixel_color, SAMPLES_PER_PIXEL, encoding);
             index += 1;
         }
     }
@@ -1,48 +0,0 @@
-use crate::{
-    hittable::HitRecord,
-    hittable::Shape,
-    ray::Ray,
-    vec3::{dot, Point3},
-};
-
-pub struct Sphere {
-    center: Point3,
-    radius: f64,
-}
-
-impl Sphere {
-    pub fn new(center: Point3, radius: f64) -> Self {
-        Self { center, radius }
-    }
-}
-
-impl Shape for Sphere {
-    fn hit(&self, r: Ray, t_min: f64, t_max: f64, rec: &mut HitRecord) -> bool {
-        let oc = r.origin() - self.center;
-        let a = r.direction().len2();
-        let half_b = dot(oc, r.direction());
-        let c = oc.len2() - self.radius * self.radius;
-        let discriminant = half_b * half_b - a * c;
-
-        if discriminant > 0.0 {
-            let root = discriminant.sqrt();
-            let temp = (-half_b - root) / a;
-            if temp < t_max && temp > t_min {
-                rec.t = temp;
-                rec.p = r.at(rec.t);
-                let outward_normal = (rec.p - self.center) / self.radius;
-                rec.set_face_normal(r, outward_normal);
-                return true;
-            }
-            let temp = (-half_b + root) / a;
-            if temp < t_max && temp > t_min {
-                rec.t = temp;
-                rec.p = r.at(rec.t);
-                let outward_normal = (rec.p - self.center) / self.radius;
-                rec.set_face_normal(r, outward_normal);
-                return true;
-            }
-        }
-        false
-    }
-}